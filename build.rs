@@ -1,5 +1,15 @@
+use std::{env, fmt::Write as _, fs, path::Path};
+
+#[path = "build/fonts.rs"]
+mod fonts;
+
 fn main() {
     linker_be_nice();
+    partition_table();
+    fonts::generate(Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()));
+    if std::env::var_os("CARGO_FEATURE_DEFMT").is_some() {
+        println!("cargo:rustc-link-arg=-Tdefmt.x");
+    }
     // make sure linkall.x is the last linker script (otherwise might cause problems with flip-link)
     println!("cargo:rustc-link-arg=-Tlinkall.x");
 }
@@ -50,3 +60,178 @@ fn linker_be_nice() {
         std::env::current_exe().unwrap().display()
     );
 }
+
+/// Flash size of the MagTag's ESP32-S2 module
+const FLASH_SIZE: u32 = 4 * 1024 * 1024;
+/// The bootloader and the partition table itself come first
+const FIRST_OFFSET: u32 = 0x9000;
+const APP_ALIGN: u32 = 0x10000;
+const DATA_ALIGN: u32 = 0x1000;
+
+struct Partition {
+    name: String,
+    kind: String,
+    subtype: String,
+    offset: u32,
+    size: u32,
+}
+
+/// Validates the partition table for the enabled features and describes it
+/// to the firmware in `$OUT_DIR/partitions.rs`
+fn partition_table() {
+    let ota = env::var_os("CARGO_FEATURE_OTA").is_some();
+    let file = if ota {
+        "partitions_ota.csv"
+    } else {
+        "partitions.csv"
+    };
+    println!("cargo:rerun-if-changed={file}");
+    let path = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join(file);
+    let csv = fs::read_to_string(&path).unwrap_or_else(|err| fail(file, &err.to_string()));
+    let partitions = parse_partitions(&csv).unwrap_or_else(|err| fail(file, &err));
+    if let Err(err) = validate_partitions(&partitions, ota) {
+        fail(file, &err);
+    }
+
+    let mut out = format!("/// The table this build was validated against\npub const TABLE_FILE: &str = {file:?};\n\n");
+    out +=
+        "/// Partitions this build expects, in table order\npub const EXPECTED: &[Expected] = &[\n";
+    for p in &partitions {
+        writeln!(
+            out,
+            "    Expected {{ label: {:?}, offset: {:#x}, len: {:#x} }},",
+            p.name, p.offset, p.size
+        )
+        .unwrap();
+    }
+    out += "];\n";
+    fs::write(
+        Path::new(&env::var("OUT_DIR").unwrap()).join("partitions.rs"),
+        out,
+    )
+    .unwrap();
+}
+
+fn fail(file: &str, message: &str) -> ! {
+    eprintln!();
+    eprintln!("💡 {file}: {message}");
+    eprintln!();
+    panic!("invalid {file}");
+}
+
+/// Parses ESP-IDF partition CSV; a blank offset follows the previous partition
+fn parse_partitions(csv: &str) -> Result<Vec<Partition>, String> {
+    let mut partitions: Vec<Partition> = Vec::new();
+    for (number, line) in csv.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        let [name, kind, subtype, offset, size, ..] = fields[..] else {
+            return Err(format!(
+                "line {}: expected name, type, subtype, offset, size",
+                number + 1
+            ));
+        };
+        let size =
+            parse_size(size).ok_or_else(|| format!("line {}: bad size {size:?}", number + 1))?;
+        let offset = if offset.is_empty() {
+            let end = partitions
+                .last()
+                .map_or(FIRST_OFFSET, |p| p.offset + p.size);
+            let align = if kind == "app" { APP_ALIGN } else { DATA_ALIGN };
+            end.next_multiple_of(align)
+        } else {
+            parse_size(offset)
+                .ok_or_else(|| format!("line {}: bad offset {offset:?}", number + 1))?
+        };
+        partitions.push(Partition {
+            name: name.into(),
+            kind: kind.into(),
+            subtype: subtype.into(),
+            offset,
+            size,
+        });
+    }
+    Ok(partitions)
+}
+
+/// `0x1000`, `4096`, `4K` or `1M`
+fn parse_size(s: &str) -> Option<u32> {
+    if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        return u32::from_str_radix(hex, 16).ok();
+    }
+    let (digits, scale) = match s.as_bytes().last()? {
+        b'K' | b'k' => (&s[..s.len() - 1], 1024),
+        b'M' | b'm' => (&s[..s.len() - 1], 1024 * 1024),
+        _ => (s, 1),
+    };
+    digits.parse::<u32>().ok()?.checked_mul(scale)
+}
+
+fn validate_partitions(partitions: &[Partition], ota: bool) -> Result<(), String> {
+    let find = |kind: &str, subtype: &str| {
+        partitions
+            .iter()
+            .find(|p| p.kind == kind && p.subtype == subtype)
+    };
+    for (i, p) in partitions.iter().enumerate() {
+        let align = if p.kind == "app" {
+            APP_ALIGN
+        } else {
+            DATA_ALIGN
+        };
+        if p.offset % align != 0 {
+            return Err(format!("{} must start on a {align:#x} boundary", p.name));
+        }
+        if p.offset < FIRST_OFFSET {
+            return Err(format!(
+                "{} overlaps the bootloader or partition table",
+                p.name
+            ));
+        }
+        if p.offset + p.size > FLASH_SIZE {
+            return Err(format!(
+                "{} ends past the {} MiB flash",
+                p.name,
+                FLASH_SIZE >> 20
+            ));
+        }
+        if p.name.len() > 15 {
+            return Err(format!("name {} is longer than 15 characters", p.name));
+        }
+        for q in &partitions[..i] {
+            if q.name == p.name {
+                return Err(format!("{} appears twice", p.name));
+            }
+            if p.offset < q.offset + q.size && q.offset < p.offset + p.size {
+                return Err(format!("{} overlaps {}", p.name, q.name));
+            }
+        }
+    }
+
+    for (kind, subtype, what) in [
+        ("data", "nvs", "the config store"),
+        ("data", "spiffs", "the asset store"),
+        ("data", "coredump", "crash records"),
+    ] {
+        if find(kind, subtype).is_none() {
+            return Err(format!("no {kind}/{subtype} partition for {what}"));
+        }
+    }
+    if ota {
+        let (Some(ota_0), Some(ota_1)) = (find("app", "ota_0"), find("app", "ota_1")) else {
+            return Err("the ota feature needs ota_0 and ota_1 app partitions".into());
+        };
+        if ota_0.size != ota_1.size {
+            return Err("ota_0 and ota_1 must be the same size".into());
+        }
+        if find("data", "ota").is_none() {
+            return Err("the ota feature needs a data/ota partition".into());
+        }
+    } else if find("app", "factory").is_none() {
+        return Err("no app/factory partition; enable the ota feature for an A/B table".into());
+    }
+    Ok(())
+}
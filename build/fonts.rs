@@ -0,0 +1,253 @@
+//! The font subsetting behind `src/fonts.rs`, shared by `build.rs` and the
+//! build script of `tools/screenshots` so both draw with the same glyphs.
+
+use std::{collections::BTreeSet, env, fmt::Write as _, fs, path::Path};
+
+/// Glyphs kept besides those of the string tables when `MAGTAG_FONT_CHARS`
+/// isn't set: printable ASCII, for numbers, names and log-like text
+const DEFAULT_EXTRA_CHARS: &str = " !\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~";
+/// Glyphs per row of a generated font image
+const GLYPHS_PER_ROW: usize = 16;
+
+/// A monospaced BDF font
+struct Bdf {
+    width: usize,
+    height: usize,
+    /// Rows above the baseline
+    ascent: i32,
+    /// Left edge of the cell relative to the origin
+    x_offset: i32,
+    /// Each glyph's rows, one bool per pixel of the cell
+    glyphs: Vec<(char, Vec<Vec<bool>>)>,
+}
+
+/// Subsets the BDF fonts in `root/fonts/` to the characters of the string
+/// tables in `root/src/i18n.rs` plus `MAGTAG_FONT_CHARS`, into MonoFonts in
+/// `$OUT_DIR/fonts.rs`
+pub fn generate(root: &Path) {
+    println!("cargo:rerun-if-changed={}", root.join("fonts").display());
+    println!(
+        "cargo:rerun-if-changed={}",
+        root.join("src/i18n.rs").display()
+    );
+    println!("cargo:rerun-if-env-changed=MAGTAG_FONT_CHARS");
+
+    let mut wanted: BTreeSet<char> = env::var("MAGTAG_FONT_CHARS")
+        .unwrap_or_else(|_| DEFAULT_EXTRA_CHARS.into())
+        .chars()
+        .collect();
+    if let Ok(tables) = fs::read_to_string(root.join("src/i18n.rs")) {
+        wanted.extend(string_literals(&tables).chars());
+    }
+    // the stand-in for anything missing
+    wanted.insert('?');
+    // ends a range in a glyph mapping
+    wanted.remove(&'\0');
+    wanted.retain(|c| !c.is_control());
+
+    let mut paths: Vec<_> = fs::read_dir(root.join("fonts"))
+        .map(|dir| {
+            dir.filter_map(|entry| Some(entry.ok()?.path()))
+                .filter(|path| path.extension().is_some_and(|ext| ext == "bdf"))
+                .collect()
+        })
+        .unwrap_or_default();
+    paths.sort();
+
+    let mut out = String::new();
+    if !paths.is_empty() {
+        out += "use embedded_graphics::{\n    geometry::Size,\n    image::ImageRaw,\n    \
+                mono_font::{mapping::StrGlyphMapping, DecorationDimensions, MonoFont},\n};\n";
+    }
+    for path in paths {
+        let file = path.file_name().unwrap().to_string_lossy().into_owned();
+        let source = fs::read_to_string(&path).unwrap_or_else(|err| panic!("fonts/{file}: {err}"));
+        let font = parse_bdf(&source).unwrap_or_else(|err| panic!("fonts/{file}: {err}"));
+        let name: String = path
+            .file_stem()
+            .unwrap()
+            .to_string_lossy()
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() {
+                    c.to_ascii_uppercase()
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+
+        let kept: Vec<_> = font
+            .glyphs
+            .iter()
+            .filter(|(c, _)| wanted.contains(c))
+            .collect();
+        let missing: String = wanted
+            .iter()
+            .filter(|c| !kept.iter().any(|(k, _)| k == *c))
+            .collect();
+        if !missing.is_empty() {
+            println!("cargo:warning={file} has no glyphs for {missing:?}, they show as '?'");
+        }
+        let replacement = kept.iter().position(|(c, _)| *c == '?').unwrap_or(0);
+
+        // the glyphs in rows of GLYPHS_PER_ROW cells, rows padded to bytes
+        let image_width = font.width * GLYPHS_PER_ROW;
+        let stride = image_width.div_ceil(8);
+        let rows = kept.len().div_ceil(GLYPHS_PER_ROW) * font.height;
+        let mut image = vec![0u8; stride * rows];
+        for (i, (_, bitmap)) in kept.iter().enumerate() {
+            let (left, top) = (
+                i % GLYPHS_PER_ROW * font.width,
+                i / GLYPHS_PER_ROW * font.height,
+            );
+            for (y, row) in bitmap.iter().enumerate() {
+                for (x, _) in row.iter().enumerate().filter(|(_, on)| **on) {
+                    let x = left + x;
+                    image[(top + y) * stride + x / 8] |= 0x80 >> (x % 8);
+                }
+            }
+        }
+
+        let glyphs: String = kept.iter().map(|(c, _)| *c).collect();
+        writeln!(
+            out,
+            "\n/// `{file}`, {} of its {} glyphs\npub const {name}: MonoFont<'static> = MonoFont {{\n    \
+             image: ImageRaw::new(&{image:?}, {image_width}),\n    \
+             glyph_mapping: &StrGlyphMapping::new({glyphs:?}, {replacement}),\n    \
+             character_size: Size::new({}, {}),\n    \
+             character_spacing: 0,\n    \
+             baseline: {},\n    \
+             underline: DecorationDimensions::default_underline({}),\n    \
+             strikethrough: DecorationDimensions::default_strikethrough({}),\n}};",
+            kept.len(),
+            font.glyphs.len(),
+            font.width,
+            font.height,
+            font.ascent - 1,
+            font.height,
+            font.height,
+        )
+        .unwrap();
+    }
+    fs::write(
+        Path::new(&env::var("OUT_DIR").unwrap()).join("fonts.rs"),
+        out,
+    )
+    .unwrap();
+}
+
+/// The contents of every `"..."` in `source`, escapes left as they are
+fn string_literals(source: &str) -> String {
+    let mut literals = String::new();
+    let mut chars = source.chars();
+    while let Some(c) = chars.next() {
+        if c != '"' {
+            continue;
+        }
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => break,
+                '\\' => {
+                    chars.next();
+                }
+                c => literals.push(c),
+            }
+        }
+    }
+    literals
+}
+
+fn parse_bdf(source: &str) -> Result<Bdf, String> {
+    let mut lines = source.lines().enumerate();
+    let number = |s: Option<&str>, line: usize| {
+        s.and_then(|s| s.parse::<i32>().ok())
+            .ok_or_else(|| format!("line {}: expected a number", line + 1))
+    };
+    let mut font = Bdf {
+        width: 0,
+        height: 0,
+        ascent: 0,
+        x_offset: 0,
+        glyphs: Vec::new(),
+    };
+    while let Some((n, line)) = lines.next() {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("FONTBOUNDINGBOX") => {
+                let (w, h, x, y) = (
+                    number(words.next(), n)?,
+                    number(words.next(), n)?,
+                    number(words.next(), n)?,
+                    number(words.next(), n)?,
+                );
+                font.width = w.max(0) as usize;
+                font.height = h.max(0) as usize;
+                font.ascent = h + y;
+                font.x_offset = x;
+            }
+            Some("STARTCHAR") => {
+                if font.width == 0 {
+                    return Err(format!("line {}: glyph before FONTBOUNDINGBOX", n + 1));
+                }
+                let mut encoding = None;
+                let mut bbx = (0, 0, 0, 0);
+                let mut bitmap = vec![vec![false; font.width]; font.height];
+                while let Some((n, line)) = lines.next() {
+                    let mut words = line.split_whitespace();
+                    match words.next() {
+                        Some("ENCODING") => encoding = Some(number(words.next(), n)?),
+                        Some("BBX") => {
+                            bbx = (
+                                number(words.next(), n)?,
+                                number(words.next(), n)?,
+                                number(words.next(), n)?,
+                                number(words.next(), n)?,
+                            );
+                        }
+                        Some("BITMAP") => {
+                            let (w, h, x, y) = bbx;
+                            // the glyph's top row within the cell
+                            let top = font.ascent - (y + h);
+                            for row in 0..h {
+                                let (n, hex) = lines.next().ok_or("file ends inside a bitmap")?;
+                                let digits = hex
+                                    .trim()
+                                    .chars()
+                                    .map(|d| d.to_digit(16))
+                                    .collect::<Option<Vec<_>>>()
+                                    .ok_or_else(|| format!("line {}: bad bitmap row", n + 1))?;
+                                for col in 0..w {
+                                    let (cx, cy) = (x - font.x_offset + col, top + row);
+                                    let digit = digits.get(col as usize / 4).copied().unwrap_or(0);
+                                    let on = digit & (8 >> (col % 4)) != 0;
+                                    if on
+                                        && (0..font.width as i32).contains(&cx)
+                                        && (0..font.height as i32).contains(&cy)
+                                    {
+                                        bitmap[cy as usize][cx as usize] = true;
+                                    }
+                                }
+                            }
+                        }
+                        Some("ENDCHAR") => break,
+                        _ => {}
+                    }
+                }
+                // -1 marks glyphs outside the encoding
+                if let Some(c) = encoding
+                    .and_then(|e| u32::try_from(e).ok())
+                    .and_then(char::from_u32)
+                {
+                    font.glyphs.push((c, bitmap));
+                }
+            }
+            _ => {}
+        }
+    }
+    if font.width == 0 || font.height == 0 {
+        return Err("no FONTBOUNDINGBOX".into());
+    }
+    font.glyphs.sort_by_key(|(c, _)| *c);
+    Ok(font)
+}
@@ -0,0 +1,332 @@
+//! The LIS3DH accelerometer on the [I2C bus](crate::i2c).
+//!
+//! It runs at 100 Hz in high-resolution mode with a ±2 g range, where one
+//! count is 1 mg. Before deep sleep it can be armed with a [WakeTrigger]: it
+//! then drops to a low-power rate and raises its INT1 line (GPIO9) on motion
+//! or free fall, or on being turned face up after [crate::power] put it to
+//! sleep face down, which that uses as a wake source. For the step
+//! counter it can also buffer samples in its FIFO, see [start_fifo]. While
+//! awake it can also spot double taps on the case, see [detect_taps].
+
+use core::{cell::RefCell, str::FromStr};
+
+use critical_section::Mutex;
+use esp_hal::peripherals::GPIO9;
+use log::{info, warn};
+
+use crate::{i2c, Error};
+
+/// I2C address with SDO pulled high, as on the MagTag
+pub const ADDRESS: u8 = 0x19;
+
+const WHO_AM_I: u8 = 0x0f;
+const WHO_AM_I_VALUE: u8 = 0x33;
+const CTRL_REG1: u8 = 0x20;
+const CTRL_REG2: u8 = 0x21;
+const CTRL_REG3: u8 = 0x22;
+const CTRL_REG4: u8 = 0x23;
+const CTRL_REG5: u8 = 0x24;
+const REFERENCE: u8 = 0x26;
+const OUT_X_L: u8 = 0x28;
+const FIFO_CTRL_REG: u8 = 0x2e;
+const FIFO_SRC_REG: u8 = 0x2f;
+const INT1_CFG: u8 = 0x30;
+const INT1_SRC: u8 = 0x31;
+const INT1_THS: u8 = 0x32;
+const INT1_DURATION: u8 = 0x33;
+const CLICK_CFG: u8 = 0x38;
+const CLICK_SRC: u8 = 0x39;
+const CLICK_THS: u8 = 0x3a;
+const TIME_LIMIT: u8 = 0x3b;
+const TIME_LATENCY: u8 = 0x3c;
+const TIME_WINDOW: u8 = 0x3d;
+/// 100 Hz, all axes, normal mode
+const RATE_100HZ: u8 = 0x57;
+/// 25 Hz, all axes, normal mode
+const RATE_25HZ: u8 = 0x37;
+/// 50 Hz, all axes, low-power mode
+const RATE_50HZ_LOW_POWER: u8 = 0x5f;
+/// Interrupt threshold steps at ±2 g
+const THRESHOLD_STEP_MG: u16 = 16;
+/// Set in a register address to read several registers in a row
+const AUTO_INCREMENT: u8 = 0x80;
+
+/// Sample rate while buffering in the FIFO, see [start_fifo]
+pub const FIFO_RATE_HZ: u32 = 25;
+/// Samples the FIFO holds, about 1.3 s at [FIFO_RATE_HZ]
+pub const FIFO_LEN: usize = 32;
+
+/// Acceleration in milli-g; at rest, face up, `z` is about 1000
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Acceleration {
+    pub x: i16,
+    pub y: i16,
+    pub z: i16,
+}
+
+impl Acceleration {
+    /// Lying flat with the screen down, e.g. put down on a desk
+    pub fn is_face_down(self) -> bool {
+        self.z < -(FLAT_THRESHOLD_MG as i16)
+    }
+}
+
+/// What wakes the badge from deep sleep, stored under the `motion_wake` key
+/// as `off`, `motion` or `freefall`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WakeTrigger {
+    #[default]
+    Off,
+    /// Any axis changing by more than [MOTION_THRESHOLD_MG], e.g. when picked up
+    Motion,
+    /// All axes near zero g for at least [FREEFALL_MS]
+    FreeFall,
+    /// Gravity pulling on the back of the board for [FACE_UP_MS]; not a
+    /// `motion_wake` setting, as a badge left face up would wake at once
+    FaceUp,
+}
+
+/// Change in acceleration that counts as motion
+pub const MOTION_THRESHOLD_MG: u16 = 160;
+/// Below this on every axis counts as falling
+pub const FREEFALL_THRESHOLD_MG: u16 = 350;
+/// How long a fall has to last, to skip bumps
+pub const FREEFALL_MS: u16 = 100;
+/// Gravity on the z axis that counts as lying flat, face up or face down
+pub const FLAT_THRESHOLD_MG: u16 = 800;
+/// How long the board has to stay face up, to skip it rocking on a desk
+pub const FACE_UP_MS: u16 = 200;
+/// How hard a tap has to be, well above handling the badge
+pub const TAP_THRESHOLD_MG: u16 = 960;
+
+impl WakeTrigger {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WakeTrigger::Off => "off",
+            WakeTrigger::Motion => "motion",
+            WakeTrigger::FreeFall => "freefall",
+            WakeTrigger::FaceUp => "faceup",
+        }
+    }
+}
+
+impl FromStr for WakeTrigger {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(WakeTrigger::Off),
+            "motion" => Ok(WakeTrigger::Motion),
+            "freefall" => Ok(WakeTrigger::FreeFall),
+            _ => Err(()),
+        }
+    }
+}
+
+struct Accel {
+    /// INT1, kept for the deep-sleep wake source
+    interrupt: Option<GPIO9<'static>>,
+}
+
+static ACCEL: Mutex<RefCell<Option<Accel>>> = Mutex::new(RefCell::new(None));
+
+/// Takes the INT1 line (GPIO9), starts the sensor and clears an interrupt
+/// left from deep sleep. Call once at boot, after [i2c::init]; a missing
+/// sensor is logged and left alone.
+pub fn init(interrupt: GPIO9<'static>) {
+    let found = i2c::with(|i2c| {
+        let mut id = [0u8];
+        if i2c.write_read(ADDRESS, &[WHO_AM_I], &mut id).is_err() || id[0] != WHO_AM_I_VALUE {
+            warn!("No LIS3DH at {:#04x}", ADDRESS);
+            return false;
+        }
+        // block data update and high resolution at ±2 g, interrupts off
+        let mut source = [0u8];
+        let started = [
+            [CTRL_REG1, RATE_100HZ],
+            [CTRL_REG2, 0x00],
+            [CTRL_REG3, 0x00],
+            [CTRL_REG4, 0x88],
+            [CTRL_REG5, 0x00],
+            [INT1_CFG, 0x00],
+        ]
+        .iter()
+        .try_for_each(|write| i2c.write(ADDRESS, write))
+        // reading the source releases a latched interrupt
+        .and_then(|()| i2c.write_read(ADDRESS, &[INT1_SRC], &mut source));
+        if let Err(err) = started {
+            warn!("LIS3DH setup failed: {:?}", err);
+            return false;
+        }
+        true
+    });
+    if found != Some(true) {
+        return;
+    }
+    info!("LIS3DH accelerometer ready");
+    critical_section::with(|cs| {
+        ACCEL.borrow_ref_mut(cs).replace(Accel {
+            interrupt: Some(interrupt),
+        })
+    });
+}
+
+/// Runs `f` with the bus if the sensor was found at [init]
+fn with_bus<R>(f: impl FnOnce(&mut Accel, &mut i2c::Bus) -> R) -> Option<R> {
+    critical_section::with(|cs| {
+        let mut accel = ACCEL.borrow_ref_mut(cs);
+        let accel = accel.as_mut()?;
+        i2c::with(|bus| f(accel, bus))
+    })
+}
+
+/// Programs the sensor to raise INT1 on `trigger`, latched until the next
+/// [init], and hands out the INT1 pin for the wake source. Meant to be the
+/// last thing before deep sleep, as readings stop being high resolution.
+pub fn arm(trigger: WakeTrigger) -> Result<GPIO9<'static>, Error> {
+    // CTRL_REG2 (high-pass filter on INT1), INT1_CFG, INT1_THS, INT1_DURATION
+    let (filter, config, threshold_mg, duration) = match trigger {
+        WakeTrigger::Off => return Err(Error::InvalidConfig),
+        // high events on any axis, with gravity filtered out
+        WakeTrigger::Motion => (0x01, 0x2a, MOTION_THRESHOLD_MG, 0),
+        // low events on all axes at once
+        WakeTrigger::FreeFall => (0x00, 0x95, FREEFALL_THRESHOLD_MG, FREEFALL_MS / 20),
+        // 6D position with z up, on gravity itself
+        WakeTrigger::FaceUp => (0x00, 0xe0, FLAT_THRESHOLD_MG, FACE_UP_MS / 20),
+    };
+    let threshold = (threshold_mg / THRESHOLD_STEP_MG) as u8;
+    with_bus(|accel, i2c| {
+        let mut source = [0u8];
+        [
+            [CTRL_REG1, RATE_50HZ_LOW_POWER],
+            // low-power mode rules out high resolution
+            [CTRL_REG4, 0x80],
+            [CTRL_REG2, filter],
+            [INT1_THS, threshold],
+            [INT1_DURATION, duration as u8],
+            [INT1_CFG, config],
+            // latch INT1
+            [CTRL_REG5, 0x08],
+            // IA1 on the INT1 pin
+            [CTRL_REG3, 0x40],
+        ]
+        .iter()
+        .try_for_each(|write| i2c.write(ADDRESS, write))
+        // reading REFERENCE resets the filter to the current attitude, and
+        // INT1_SRC drops anything latched while setting up
+        .and_then(|()| i2c.write_read(ADDRESS, &[REFERENCE], &mut source))
+        .and_then(|()| i2c.write_read(ADDRESS, &[INT1_SRC], &mut source))
+        .map_err(|err| {
+            warn!("arming the LIS3DH failed: {:?}", err);
+            Error::NotFound
+        })?;
+        info!("LIS3DH armed for {}", trigger.as_str());
+        accel.interrupt.take().ok_or(Error::NotFound)
+    })
+    .ok_or(Error::NotFound)?
+}
+
+/// The latest reading; `None` without a sensor or when the bus fails. With
+/// the FIFO running this is the oldest buffered sample instead.
+pub fn read() -> Option<Acceleration> {
+    let mut raw = [0u8; 6];
+    with_bus(|_, i2c| {
+        i2c.write_read(ADDRESS, &[OUT_X_L | AUTO_INCREMENT], &mut raw)
+            .ok()
+    })??;
+    Some(decode(&raw))
+}
+
+/// Latches double taps on any axis for [take_double_tap]. The timings are in
+/// samples, 10 ms at 100 Hz; with the FIFO running at [FIFO_RATE_HZ] taps
+/// have to be slower.
+pub fn detect_taps() -> Result<(), Error> {
+    with_bus(|_, i2c| {
+        [
+            // limit 50 ms a tap, 100 ms quiet after it, 300 ms for the second
+            [TIME_LIMIT, 5],
+            [TIME_LATENCY, 10],
+            [TIME_WINDOW, 30],
+            // latched until CLICK_SRC is read
+            [
+                CLICK_THS,
+                0x80 | (TAP_THRESHOLD_MG / THRESHOLD_STEP_MG) as u8,
+            ],
+            // XD, YD and ZD
+            [CLICK_CFG, 0x2a],
+        ]
+        .iter()
+        .try_for_each(|write| i2c.write(ADDRESS, write))
+        .map_err(|err| {
+            warn!("setting up LIS3DH taps failed: {:?}", err);
+            Error::NotFound
+        })
+    })
+    .ok_or(Error::NotFound)?
+}
+
+/// Whether there was a double tap since the last call
+pub fn take_double_tap() -> bool {
+    let mut source = [0u8];
+    with_bus(|_, i2c| i2c.write_read(ADDRESS, &[CLICK_SRC], &mut source).is_ok()) == Some(true)
+        // IA and DClick
+        && source[0] & 0x60 == 0x60
+}
+
+/// Drops to [FIFO_RATE_HZ] and buffers samples in the sensor's FIFO, so they
+/// can be fetched in batches with [read_fifo] instead of polled. The FIFO
+/// keeps the newest [FIFO_LEN] samples; [init] or [arm] stop it.
+pub fn start_fifo() -> Result<(), Error> {
+    with_bus(|_, i2c| {
+        [
+            [CTRL_REG1, RATE_25HZ],
+            // FIFO_EN
+            [CTRL_REG5, 0x40],
+            // bypass mode first empties the FIFO, then stream mode
+            [FIFO_CTRL_REG, 0x00],
+            [FIFO_CTRL_REG, 0x80],
+        ]
+        .iter()
+        .try_for_each(|write| i2c.write(ADDRESS, write))
+        .map_err(|err| {
+            warn!("starting the LIS3DH FIFO failed: {:?}", err);
+            Error::NotFound
+        })
+    })
+    .ok_or(Error::NotFound)?
+}
+
+/// Takes the samples buffered since the last call, oldest first. If more
+/// than [FIFO_LEN] came in the oldest are lost, which the sensor doesn't
+/// report separately.
+pub fn read_fifo() -> Result<heapless::Vec<Acceleration, FIFO_LEN>, Error> {
+    with_bus(|_, i2c| {
+        let mut samples = heapless::Vec::new();
+        let mut source = [0u8];
+        i2c.write_read(ADDRESS, &[FIFO_SRC_REG], &mut source)
+            .map_err(|_| Error::NotFound)?;
+        // FSS, the number of unread samples; 31 with OVRN_FIFO set means full
+        let count = match source[0] & 0x1f {
+            31 if source[0] & 0x40 != 0 => FIFO_LEN,
+            count => count as usize,
+        };
+        for _ in 0..count {
+            let mut raw = [0u8; 6];
+            i2c.write_read(ADDRESS, &[OUT_X_L | AUTO_INCREMENT], &mut raw)
+                .map_err(|_| Error::NotFound)?;
+            samples.push(decode(&raw)).ok();
+        }
+        Ok(samples)
+    })
+    .ok_or(Error::NotFound)?
+}
+
+fn decode(raw: &[u8; 6]) -> Acceleration {
+    // 12-bit samples, left aligned
+    let axis = |i: usize| i16::from_le_bytes([raw[i], raw[i + 1]]) >> 4;
+    Acceleration {
+        x: axis(0),
+        y: axis(2),
+        z: axis(4),
+    }
+}
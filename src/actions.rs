@@ -0,0 +1,137 @@
+//! Buttons bound to named actions in the `button_actions` config entry, so a
+//! deployment can change what they do without a new build.
+//!
+//! The entry is a comma separated list of bindings such as
+//! `A=refresh,B=next_page,D_long=provision`. A binding is a press of one
+//! button, a long press with `_long` after it (see [crate::input::Timing]),
+//! or a chord such as `A+D`, then the [Action]'s name. The host resolves
+//! them once alerts and an open dialog have had their say, so a bound press
+//! never reaches the app; unbound ones do as before.
+//!
+//! Actions about the whole badge, switching apps and restarting, are the
+//! host's; the rest reach the app as [Event::Action], and apps that have
+//! nothing to do for one ignore it. The host redraws after
+//! [Action::Refresh] either way.
+
+use alloc::vec::Vec;
+use core::str::FromStr;
+
+use log::warn;
+
+use crate::{
+    config::{keys, ConfigStore},
+    input::{Event, InputEvent},
+};
+
+/// What a bound button does
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Fetch what the app shows now rather than when it is due, and redraw
+    Refresh,
+    NextPage,
+    PreviousPage,
+    /// Bring the next installed app to the front, as A+D does
+    NextApp,
+    PreviousApp,
+    /// Restart into WiFi provisioning, as holding D at boot does
+    Provision,
+    /// Restart into safe mode, as holding A at boot does
+    SafeMode,
+    Restart,
+}
+
+impl Action {
+    pub const ALL: [Action; 8] = [
+        Action::Refresh,
+        Action::NextPage,
+        Action::PreviousPage,
+        Action::NextApp,
+        Action::PreviousApp,
+        Action::Provision,
+        Action::SafeMode,
+        Action::Restart,
+    ];
+
+    /// The name bindings use
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Refresh => "refresh",
+            Action::NextPage => "next_page",
+            Action::PreviousPage => "prev_page",
+            Action::NextApp => "next_app",
+            Action::PreviousApp => "prev_app",
+            Action::Provision => "provision",
+            Action::SafeMode => "safe_mode",
+            Action::Restart => "restart",
+        }
+    }
+
+    /// Whether the app handles it rather than the host
+    pub fn is_app_action(self) -> bool {
+        matches!(
+            self,
+            Action::Refresh | Action::NextPage | Action::PreviousPage
+        )
+    }
+}
+
+impl FromStr for Action {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        Action::ALL
+            .into_iter()
+            .find(|action| action.name().eq_ignore_ascii_case(s))
+            .ok_or(())
+    }
+}
+
+/// The bindings from `button_actions`
+#[derive(Debug, Clone, Default)]
+pub struct Bindings {
+    bindings: Vec<(InputEvent, Action)>,
+}
+
+impl Bindings {
+    /// Skips, with a warning, bindings that don't parse
+    pub fn from_config(config: &ConfigStore) -> Self {
+        let mut bindings = Vec::new();
+        for entry in config.get(keys::BUTTON_ACTIONS).unwrap_or("").split(',') {
+            if entry.trim().is_empty() {
+                continue;
+            }
+            match entry.split_once('=').and_then(|(trigger, action)| {
+                Some((parse_trigger(trigger).ok()?, action.parse().ok()?))
+            }) {
+                Some(binding) => bindings.push(binding),
+                None => warn!("Button action {}: not a binding such as A=refresh", entry),
+            }
+        }
+        Self { bindings }
+    }
+
+    /// Whether any binding is for a long press
+    pub fn has_long_press(&self) -> bool {
+        self.bindings
+            .iter()
+            .any(|(trigger, _)| matches!(trigger, InputEvent::LongPress(_)))
+    }
+
+    /// The action `event` is bound to
+    pub fn action(&self, event: Event) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|(trigger, _)| trigger.event() == event)
+            .map(|&(_, action)| action)
+    }
+}
+
+/// `A`, `A_long` or `A+D`
+fn parse_trigger(s: &str) -> Result<InputEvent, ()> {
+    let s = s.trim();
+    match s.strip_suffix("_long") {
+        Some(button) => Ok(InputEvent::LongPress(button.parse()?)),
+        None => s.parse(),
+    }
+}
@@ -0,0 +1,185 @@
+//! Wall-clock schedules such as "every day at 07:00" or "every 30 minutes
+//! between 8 and 20h", evaluated in local time.
+//!
+//! Schedules parse from a cron-like shorthand so they can be kept in the
+//! config store:
+//!
+//! - `07:00`: every day at 07:00
+//! - `*/30`: every 30 minutes, on the hour and half hour
+//! - `*/30 8-20`: the same, from 08:00 up to and including 20:00
+//!
+//! [QuietHours] are a daily window such as `23:00-06:00` in which scheduled
+//! refreshes wait for the window to end.
+
+use core::str::FromStr;
+
+use jiff::civil::DateTime;
+
+use crate::Error;
+
+const MINUTES_PER_DAY: u16 = 24 * 60;
+
+/// When an app wants to be woken up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Schedule {
+    /// Once a day at a local time
+    Daily { hour: u8, minute: u8 },
+    /// Every `minutes`, counted from midnight, within `from_hour..=to_hour`
+    Every {
+        minutes: u16,
+        from_hour: u8,
+        to_hour: u8,
+    },
+}
+
+impl Schedule {
+    pub const fn daily(hour: u8, minute: u8) -> Self {
+        Schedule::Daily { hour, minute }
+    }
+
+    pub const fn every(minutes: u16) -> Self {
+        Schedule::Every {
+            minutes,
+            from_hour: 0,
+            to_hour: 24,
+        }
+    }
+
+    /// Restricts an [Schedule::Every] to a window of local hours
+    pub const fn between(self, from_hour: u8, to_hour: u8) -> Self {
+        match self {
+            Schedule::Every { minutes, .. } => Schedule::Every {
+                minutes,
+                from_hour,
+                to_hour,
+            },
+            daily => daily,
+        }
+    }
+
+    /// The first matching local time strictly after `now`
+    pub fn next_after(&self, now: DateTime) -> DateTime {
+        let now_minute = minute_of_day(now);
+        let (day_offset, minute) = match *self {
+            Schedule::Daily { hour, minute } => {
+                let at = hour as u16 * 60 + minute as u16;
+                (if at > now_minute { 0 } else { 1 }, at)
+            }
+            Schedule::Every {
+                minutes,
+                from_hour,
+                to_hour,
+            } => {
+                let first = from_hour as u16 * 60;
+                let last = (to_hour as u16 * 60).min(MINUTES_PER_DAY - 1);
+                let step = minutes.max(1);
+                let next = (now_minute + 1).max(first).next_multiple_of(step);
+                if next <= last {
+                    (0, next)
+                } else {
+                    (1, first.next_multiple_of(step))
+                }
+            }
+        };
+
+        let mut date = now.date();
+        if day_offset > 0 {
+            date = date.tomorrow().unwrap_or(date);
+        }
+        let minute = minute.min(MINUTES_PER_DAY - 1);
+        date.at((minute / 60) as i8, (minute % 60) as i8, 0, 0)
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(rest) = s.strip_prefix("*/") {
+            let (minutes, window) = match rest.split_once(' ') {
+                Some((minutes, window)) => (minutes, Some(window.trim())),
+                None => (rest, None),
+            };
+            let minutes: u16 = minutes.parse().map_err(|_| Error::InvalidConfig)?;
+            if minutes == 0 || minutes > MINUTES_PER_DAY {
+                return Err(Error::InvalidConfig);
+            }
+            let schedule = Schedule::every(minutes);
+            let Some(window) = window else {
+                return Ok(schedule);
+            };
+            let (from, to) = window.split_once('-').ok_or(Error::InvalidConfig)?;
+            let from: u8 = from.parse().map_err(|_| Error::InvalidConfig)?;
+            let to: u8 = to.parse().map_err(|_| Error::InvalidConfig)?;
+            if from > to || to > 24 {
+                return Err(Error::InvalidConfig);
+            }
+            return Ok(schedule.between(from, to));
+        }
+
+        let (hour, minute) = s.split_once(':').ok_or(Error::InvalidConfig)?;
+        let hour: u8 = hour.parse().map_err(|_| Error::InvalidConfig)?;
+        let minute: u8 = minute.parse().map_err(|_| Error::InvalidConfig)?;
+        if hour > 23 || minute > 59 {
+            return Err(Error::InvalidConfig);
+        }
+        Ok(Schedule::daily(hour, minute))
+    }
+}
+
+/// A daily window of local time, which may span midnight, when the badge
+/// holds off scheduled refreshes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuietHours {
+    /// Minutes after midnight the window starts, inclusive
+    from: u16,
+    /// Minutes after midnight the window ends, exclusive
+    to: u16,
+}
+
+impl QuietHours {
+    pub fn contains(&self, now: DateTime) -> bool {
+        let minute = minute_of_day(now);
+        if self.from < self.to {
+            (self.from..self.to).contains(&minute)
+        } else {
+            minute >= self.from || minute < self.to
+        }
+    }
+
+    /// `wake`, or the end of the window if it falls inside
+    pub fn defer(&self, wake: DateTime) -> DateTime {
+        if !self.contains(wake) {
+            return wake;
+        }
+        let mut date = wake.date();
+        // before midnight a window spanning it ends tomorrow
+        if self.from > self.to && minute_of_day(wake) >= self.from {
+            date = date.tomorrow().unwrap_or(date);
+        }
+        date.at((self.to / 60) as i8, (self.to % 60) as i8, 0, 0)
+    }
+}
+
+impl FromStr for QuietHours {
+    type Err = Error;
+
+    /// `HH:MM-HH:MM`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let minute = |s: &str| match s.trim().parse()? {
+            Schedule::Daily { hour, minute } => Ok(hour as u16 * 60 + minute as u16),
+            Schedule::Every { .. } => Err(Error::InvalidConfig),
+        };
+        let (from, to) = s.split_once('-').ok_or(Error::InvalidConfig)?;
+        let (from, to) = (minute(from)?, minute(to)?);
+        if from == to {
+            return Err(Error::InvalidConfig);
+        }
+        Ok(Self { from, to })
+    }
+}
+
+fn minute_of_day(at: DateTime) -> u16 {
+    at.hour() as u16 * 60 + at.minute() as u16
+}
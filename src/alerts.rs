@@ -0,0 +1,364 @@
+//! Alerts pushed over MQTT, such as a surf report crossing a threshold or a
+//! severe weather warning, that take over the screen, flash the NeoPixels
+//! and sound the speaker until a button is pressed.
+//!
+//! `alert_broker` names the broker, `mqtt://host[:port]`, logged in to with
+//! `mqtt_user`/`mqtt_pass` if set, and `alert_topics` up to [MAX_TOPICS]
+//! topics separated by commas; wildcards are passed on to the broker. A
+//! payload raises an alert when it contains one of the words in
+//! `alert_match`, also separated by commas and compared ignoring case, or
+//! any payload does if that is unset.
+//!
+//! A JSON payload shows its `title` and `message` fields, as Home
+//! Assistant's notify actions send them; anything else shows its first line
+//! as the title and the rest below. A new alert replaces the one on screen.
+//!
+//! The [AppHost](crate::app::AppHost) stays subscribed whichever app is in
+//! front, trying again every [RETRY_INTERVAL] after losing the broker, and
+//! calls [Alerts::poll] every [POLL_INTERVAL]. Quiet hours don't hold alerts
+//! back. Until it is dismissed, the [AlertLayer] covers every screen and the
+//! first press of any button only takes it down.
+
+use alloc::{format, string::String, vec::Vec};
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+use embedded_graphics::{
+    mono_font::{ascii::FONT_10X20, MonoTextStyle},
+    pixelcolor::Gray2,
+    prelude::*,
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
+};
+use esp_hal::{
+    efuse::Efuse,
+    time::{Duration, Instant},
+};
+use log::{debug, info, warn};
+
+use crate::{
+    compositor::{Depth, Layer},
+    config::{keys, ConfigStore},
+    display::{Frame, HEIGHT, WIDTH},
+    fonts::{FIXED_6X10, FIXED_7X14_BOLD},
+    input::Event,
+    json,
+    mqtt::{Login, Subscriber},
+    neopixel::{self, Rgb},
+    speaker, time, tr, wifi,
+};
+
+pub const MAX_TOPICS: usize = 4;
+/// How often the socket is checked, and the pixels and speaker go again
+/// while an alert is up
+pub const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// How long to wait before connecting again after the broker went away
+pub const RETRY_INTERVAL: Duration = Duration::from_secs(30);
+/// The two tones of the siren, each this long, kept short so buttons are
+/// polled between them
+const SIREN_HZ: [u32; 2] = [2400, 1800];
+const SIREN_TONE: Duration = Duration::from_millis(150);
+const MARGIN: i32 = 6;
+/// Lines of message under the title
+const MESSAGE_LINES: usize = 5;
+
+/// An alert on screen
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Raised {
+    title: String,
+    message: String,
+    /// Local `HH:MM` it arrived, if the clock is set
+    at: Option<String>,
+}
+
+struct State {
+    alert: Option<Raised>,
+    changed: bool,
+}
+
+static ALERT: Mutex<RefCell<State>> = Mutex::new(RefCell::new(State {
+    alert: None,
+    changed: false,
+}));
+
+pub fn is_raised() -> bool {
+    critical_section::with(|cs| ALERT.borrow_ref(cs).alert.is_some())
+}
+
+/// Takes the alert down and turns the pixels off, if one is up
+pub fn dismiss() {
+    let dismissed = critical_section::with(|cs| {
+        let mut state = ALERT.borrow_ref_mut(cs);
+        let dismissed = state.alert.take().is_some();
+        state.changed |= dismissed;
+        dismissed
+    });
+    if dismissed {
+        info!("Alert dismissed");
+        neopixel::fill(Rgb::OFF);
+        neopixel::show();
+    }
+}
+
+fn raise(alert: Raised) {
+    info!("Alert: {}", alert.title);
+    critical_section::with(|cs| {
+        let mut state = ALERT.borrow_ref_mut(cs);
+        state.alert = Some(alert);
+        state.changed = true;
+    });
+}
+
+/// What the app gets for `event`: nothing for a press or chord that
+/// dismisses a raised alert
+pub fn route(event: Event) -> Option<Event> {
+    match event {
+        Event::Press(_) | Event::Chord(_) | Event::LongPress(_) if is_raised() => {
+            dismiss();
+            None
+        }
+        _ => Some(event),
+    }
+}
+
+/// The configured subscription
+pub struct Alerts<'a> {
+    subscriber: Subscriber<'a>,
+    broker: String,
+    topics: Vec<String>,
+    /// Lowercase; empty matches every payload
+    words: Vec<String>,
+    attempted_at: Option<Instant>,
+    /// Whether the pixels were lit on the last poll
+    lit: bool,
+}
+
+impl<'a> Alerts<'a> {
+    /// `None` unless the broker and at least one topic are set
+    pub fn from_config(config: &ConfigStore, subscriber: Subscriber<'a>) -> Option<Self> {
+        let broker = config.get(keys::ALERT_BROKER).filter(|s| !s.is_empty())?;
+        let mut topics = split_list(config.get(keys::ALERT_TOPICS)?);
+        if topics.is_empty() {
+            return None;
+        }
+        if topics.len() > MAX_TOPICS {
+            warn!(
+                "{} lists {} topics, subscribing to the first {}",
+                keys::ALERT_TOPICS,
+                topics.len(),
+                MAX_TOPICS
+            );
+            topics.truncate(MAX_TOPICS);
+        }
+        let words = split_list(config.get(keys::ALERT_MATCH).unwrap_or_default())
+            .into_iter()
+            .map(|word| word.to_lowercase())
+            .collect();
+        Some(Self {
+            subscriber,
+            broker: broker.into(),
+            topics,
+            words,
+            attempted_at: None,
+            lit: false,
+        })
+    }
+
+    /// Connects if need be, raises an alert for a matching payload, and
+    /// flashes and sounds one that is up
+    pub fn poll(&mut self, config: &ConfigStore, now: Instant) {
+        self.connect(config, now);
+        if self.subscriber.is_connected() {
+            let words = &self.words;
+            let mut matched = None;
+            // failures are logged and retried on a later poll
+            self.subscriber
+                .poll(|topic, payload| {
+                    if matches(words, payload) {
+                        matched = Some(parse(payload));
+                    } else {
+                        debug!("no alert word in the payload on {}", topic);
+                    }
+                })
+                .ok();
+            if let Some(alert) = matched {
+                raise(alert);
+            }
+        }
+        if is_raised() {
+            self.sound();
+        } else {
+            self.lit = false;
+        }
+    }
+
+    fn connect(&mut self, config: &ConfigStore, now: Instant) {
+        if self.subscriber.is_connected()
+            || !wifi::is_connected()
+            || self
+                .attempted_at
+                .is_some_and(|at| now - at < RETRY_INTERVAL)
+        {
+            return;
+        }
+        self.attempted_at = Some(now);
+        let login = config.get(keys::MQTT_USERNAME).map(|username| Login {
+            username,
+            password: config.get(keys::MQTT_PASSWORD).unwrap_or_default(),
+        });
+        let topics: Vec<&str> = self.topics.iter().map(String::as_str).collect();
+        match self
+            .subscriber
+            .connect(&self.broker, &client_id(), login, &topics)
+        {
+            Ok(()) => info!("Alerts subscribed on {}", self.broker),
+            Err(err) => warn!("Alerts can't reach {}: {}", self.broker, err),
+        }
+    }
+
+    /// One beat of the alarm: the pixels toggle and the siren goes once
+    fn sound(&mut self) {
+        self.lit = !self.lit;
+        neopixel::fill(if self.lit { Rgb::RED } else { Rgb::OFF });
+        neopixel::show();
+        for hz in SIREN_HZ {
+            speaker::tone(hz, SIREN_TONE);
+        }
+    }
+}
+
+/// Draws a raised alert over the whole screen, light on dark
+pub struct AlertLayer;
+
+impl Layer for AlertLayer {
+    fn depth(&self) -> Depth {
+        Depth::Modal
+    }
+
+    fn take_dirty(&mut self) -> bool {
+        critical_section::with(|cs| core::mem::take(&mut ALERT.borrow_ref_mut(cs).changed))
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let Some(alert) = critical_section::with(|cs| ALERT.borrow_ref(cs).alert.clone()) else {
+            return;
+        };
+        frame.clear(Gray2::BLACK).ok();
+        let heading = match &alert.at {
+            Some(at) => format!("{} {}", tr!("alert"), at),
+            None => String::from(tr!("alert")),
+        };
+        Text::with_baseline(
+            &heading,
+            Point::new(MARGIN, MARGIN),
+            MonoTextStyle::new(&FONT_10X20, Gray2::WHITE),
+            Baseline::Top,
+        )
+        .draw(frame)
+        .ok();
+
+        let title_font = &FIXED_7X14_BOLD;
+        let title_columns = columns(title_font.character_size.width);
+        let title = wrap(&alert.title, title_columns).into_iter().next();
+        let mut y = MARGIN + FONT_10X20.character_size.height as i32 + 4;
+        Text::with_baseline(
+            title.unwrap_or_default(),
+            Point::new(MARGIN, y),
+            MonoTextStyle::new(title_font, Gray2::WHITE),
+            Baseline::Top,
+        )
+        .draw(frame)
+        .ok();
+        y += title_font.character_size.height as i32 + 4;
+
+        let small = MonoTextStyle::new(&FIXED_6X10, Gray2::WHITE);
+        let line_height = FIXED_6X10.character_size.height as i32 + 1;
+        for line in wrap(&alert.message, columns(FIXED_6X10.character_size.width))
+            .into_iter()
+            .take(MESSAGE_LINES)
+        {
+            Text::with_baseline(line, Point::new(MARGIN, y), small, Baseline::Top)
+                .draw(frame)
+                .ok();
+            y += line_height;
+        }
+
+        let centered = TextStyleBuilder::new()
+            .alignment(Alignment::Center)
+            .baseline(Baseline::Bottom)
+            .build();
+        Text::with_text_style(
+            tr!("press_any_button"),
+            Point::new(WIDTH as i32 / 2, HEIGHT as i32 - 2),
+            small,
+            centered,
+        )
+        .draw(frame)
+        .ok();
+    }
+}
+
+/// Whether `payload` contains one of `words`, which are lowercase
+fn matches(words: &[String], payload: &[u8]) -> bool {
+    if words.is_empty() {
+        return true;
+    }
+    let text = String::from_utf8_lossy(payload).to_lowercase();
+    words.iter().any(|word| text.contains(word.as_str()))
+}
+
+/// Title and message of a payload, see the [module docs](self)
+fn parse(payload: &[u8]) -> Raised {
+    let at = time::now_local().map(|now| format!("{:02}:{:02}", now.hour(), now.minute()));
+    if let Some(message) = json::string_at(payload, &["message"]) {
+        let title = json::string_at(payload, &["title"])
+            .unwrap_or_else(|| String::from(tr!("alert_title")));
+        return Raised { title, message, at };
+    }
+    let text = String::from_utf8_lossy(payload);
+    let text = text.trim();
+    let (title, message) = text.split_once('\n').unwrap_or((text, ""));
+    Raised {
+        title: title.trim().into(),
+        message: message.trim().into(),
+        at,
+    }
+}
+
+fn split_list(s: &str) -> Vec<String> {
+    s.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Characters of `width` pixels that fit between the margins
+fn columns(width: u32) -> usize {
+    ((WIDTH - 2 * MARGIN as u32) / width) as usize
+}
+
+/// `text` broken into lines of at most `columns` characters, at spaces where
+/// there are any and at line breaks
+fn wrap(text: &str, columns: usize) -> Vec<&str> {
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        let mut rest = paragraph.trim();
+        while !rest.is_empty() {
+            let Some((limit, _)) = rest.char_indices().nth(columns) else {
+                lines.push(rest);
+                break;
+            };
+            let end = rest[..limit].rfind(' ').filter(|&i| i > 0).unwrap_or(limit);
+            lines.push(rest[..end].trim_end());
+            rest = rest[end..].trim_start();
+        }
+    }
+    lines
+}
+
+/// Apart from the ones of [crate::telemetry] and the MQTT display, since a
+/// broker drops the older of two connections with the same ID
+fn client_id() -> String {
+    let mac = Efuse::mac_address();
+    format!("magtag-{:02x}{:02x}{:02x}-alerts", mac[3], mac[4], mac[5])
+}
@@ -0,0 +1,70 @@
+//! Analog inputs on ADC1: the battery voltage divider and the light sensor.
+//!
+//! Readings are converted with the chip's nominal full-scale voltage rather
+//! than a calibration, which the ESP32-S2 doesn't have, so they are only good
+//! to a few percent. That is enough for a battery gauge.
+
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+use esp_hal::{
+    analog::adc::{Adc, AdcChannel, AdcConfig, AdcPin, Attenuation},
+    peripherals::{ADC1, GPIO3, GPIO4},
+    Blocking,
+};
+
+use crate::chip;
+
+/// The battery is measured through a 1:2 divider
+const BATTERY_DIVIDER: u32 = 2;
+/// Readings averaged per measurement
+const SAMPLES: u32 = 8;
+
+struct Inputs {
+    adc: Adc<'static, ADC1<'static>, Blocking>,
+    battery: AdcPin<GPIO4<'static>, ADC1<'static>>,
+    light: AdcPin<GPIO3<'static>, ADC1<'static>>,
+}
+
+static INPUTS: Mutex<RefCell<Option<Inputs>>> = Mutex::new(RefCell::new(None));
+
+/// Takes ADC1, the battery sense pin (GPIO4) and the light sensor (GPIO3).
+/// Call once at boot.
+pub fn init(adc: ADC1<'static>, battery: GPIO4<'static>, light: GPIO3<'static>) {
+    let mut config = AdcConfig::new();
+    let battery = config.enable_pin(battery, Attenuation::_11dB);
+    let light = config.enable_pin(light, Attenuation::_11dB);
+    let adc = Adc::new(adc, config);
+    critical_section::with(|cs| {
+        INPUTS.borrow_ref_mut(cs).replace(Inputs {
+            adc,
+            battery,
+            light,
+        })
+    });
+}
+
+/// Battery voltage in millivolts; `None` before [init]
+pub fn battery_millivolts() -> Option<u32> {
+    with(|inputs| millivolts(&mut inputs.adc, &mut inputs.battery) * BATTERY_DIVIDER)
+}
+
+/// Light sensor output in millivolts, rising with brightness: around 0 in a
+/// dark room, a few hundred indoors and saturating in sunlight. `None` before
+/// [init].
+pub fn light_millivolts() -> Option<u32> {
+    with(|inputs| millivolts(&mut inputs.adc, &mut inputs.light))
+}
+
+fn with<R>(f: impl FnOnce(&mut Inputs) -> R) -> Option<R> {
+    critical_section::with(|cs| INPUTS.borrow_ref_mut(cs).as_mut().map(f))
+}
+
+/// Averages [SAMPLES] readings of `pin`
+fn millivolts<P: AdcChannel>(
+    adc: &mut Adc<'static, ADC1<'static>, Blocking>,
+    pin: &mut AdcPin<P, ADC1<'static>>,
+) -> u32 {
+    let sum: u32 = (0..SAMPLES).map(|_| adc.read_blocking(pin) as u32).sum();
+    sum / SAMPLES * chip::ADC_FULL_SCALE_MV / chip::ADC_MAX_READING
+}
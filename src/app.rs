@@ -0,0 +1,640 @@
+//! Application framework: apps react to events and draw frames, the host owns
+//! the hardware and decides when to redraw and which app is in front.
+
+use alloc::{boxed::Box, vec::Vec};
+
+use embedded_graphics::{
+    pixelcolor::{Gray2, Gray8},
+    prelude::*,
+};
+use esp_hal::time::{Duration, Instant};
+use log::{debug, info, warn};
+
+use crate::{
+    accel::{self, Acceleration},
+    actions::{Action, Bindings},
+    alarm::QuietHours,
+    alerts::{self, AlertLayer, Alerts},
+    bindings::{self, Subscriptions},
+    boot_mode::{self, BootMode},
+    canvas::{Canvas, Dither},
+    compositor::{self, Compositor, Layer},
+    config::{keys, ConfigStore, Settings},
+    console::Console,
+    datalog::{self, DataLog},
+    display::{Display, Frame},
+    hal::{Battery, BatteryAdc, ButtonSource, Panel},
+    home_assistant::HomeAssistant,
+    hooks,
+    host_tasks::{
+        self, ALERTS, AMBIENT_LIGHT, APP_TICK, BATTERY_CHECK, DATALOG, DATALOG_UPLOAD, FACE_DOWN,
+        HOME_ASSISTANT, NET_HEALTH, TELEMETRY, TSDB,
+    },
+    http_server::HttpServer,
+    input::{self, Button, ButtonSet, Buttons, Event},
+    metrics, neopixel,
+    net::NetStack,
+    net_health::{self, Link},
+    notify::{self, Notice},
+    power::{
+        self, cpu,
+        estimator::{self, State},
+    },
+    scheduler::{Scheduler, TaskId},
+    telemetry::Telemetry,
+    time, tr,
+    tsdb::Tsdb,
+    ui::{dialog, toast},
+    wifi, Error,
+};
+
+/// Holding A and D together brings the next installed app to the front
+pub const SWITCH_CHORD: ButtonSet = ButtonSet::of(&[Button::A, Button::D]);
+
+/// How often NeoPixel brightness follows the light sensor
+const AMBIENT_INTERVAL: Duration = Duration::from_secs(10);
+const BATTERY_INTERVAL: Duration = Duration::from_secs(60);
+const NET_HEALTH_INTERVAL: Duration = Duration::from_secs(5);
+/// How often the accelerometer is read for `facedown_s`; with the step
+/// counter's FIFO running, each read takes one of its samples
+const FACE_DOWN_INTERVAL: Duration = Duration::from_secs(2);
+/// Scheduled network work due this soon runs during a refresh instead, see
+/// [App::while_refreshing]
+const REFRESH_OVERLAP: Duration = Duration::from_secs(2);
+/// Longest the network stack gets per pass of the event loop, so heavy
+/// traffic can't hold up buttons and ticks; less when a task is due sooner
+const NET_BUDGET: Duration = Duration::from_millis(5);
+/// [Notice::LowBattery] is raised below this and cleared above
+/// [BATTERY_OK_MV], so a voltage wobbling around one level doesn't repeat it
+const LOW_BATTERY_MV: u32 = 3500;
+const BATTERY_OK_MV: u32 = 3600;
+
+/// What the host should do after an app handled an event
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    /// Nothing visible changed
+    Idle,
+    /// Render and refresh the panel
+    Redraw,
+}
+
+/// Services available to an app while it handles an event
+pub struct Context<'c, 'a> {
+    pub net: &'c NetStack<'a>,
+    pub config: &'c mut ConfigStore,
+    pub now: Instant,
+}
+
+/// An application installed in the [AppHost]
+pub trait App {
+    /// Short name used in logs
+    fn name(&self) -> &'static str;
+
+    /// Handles input or a [Event::Tick]
+    fn on_event(&mut self, event: Event, ctx: &mut Context<'_, '_>) -> Flow;
+
+    /// Draws the whole screen. The frame has been cleared to white.
+    fn render(&mut self, frame: &mut Frame);
+
+    /// `Some` to draw with [App::render_gray8] instead of [App::render],
+    /// brought down to four levels with that dithering
+    fn gray8(&self) -> Option<Dither> {
+        None
+    }
+
+    /// Draws the whole screen in 256 shades. The canvas has been cleared to
+    /// white.
+    fn render_gray8(&mut self, _canvas: &mut Canvas) {}
+
+    /// How long the app can go without a [Event::Tick]; `None` means only input matters
+    fn desired_sleep(&self) -> Option<Duration> {
+        None
+    }
+
+    /// The [bindings] the screen shows; the host redraws when one changes
+    fn subscriptions(&mut self) -> Option<&mut Subscriptions> {
+        None
+    }
+
+    /// Called when the app comes to the front
+    fn on_enter(&mut self, _ctx: &mut Context<'_, '_>) -> Flow {
+        Flow::Redraw
+    }
+
+    /// Called while the panel refreshes with what [App::render] drew, which
+    /// is a couple of seconds of waiting otherwise. Fetching what the next
+    /// screen needs here costs no extra time up to that.
+    fn while_refreshing(&mut self, _ctx: &mut Context<'_, '_>) {}
+}
+
+/// Owns the display, buttons, network, config store, scheduler and the
+/// optional serial console, telemetry, Home Assistant poller, pushed alerts,
+/// data log, time-series writer and HTTP server, and runs the installed apps
+pub struct AppHost<'a, P = Display, B = Buttons> {
+    display: P,
+    buttons: B,
+    battery: &'a dyn Battery,
+    net: &'a NetStack<'a>,
+    config: ConfigStore,
+    scheduler: Scheduler,
+    console: Option<Console<'a>>,
+    telemetry: Option<Telemetry>,
+    home_assistant: Option<HomeAssistant>,
+    alerts: Option<Alerts<'a>>,
+    datalog: Option<DataLog>,
+    tsdb: Option<Tsdb>,
+    server: Option<HttpServer<'a>>,
+    apps: Vec<Box<dyn App + 'a>>,
+    active: usize,
+    /// From `button_actions`
+    actions: Bindings,
+    dirty: bool,
+    low_battery: bool,
+    net_health: net_health::Monitor,
+    /// How long face down before sleeping, from `facedown_s`
+    face_down_after: Option<Duration>,
+    face_down_since: Option<Instant>,
+    /// Made the first time an app renders in Gray8
+    canvas: Option<Canvas>,
+    compositor: Compositor<'a>,
+}
+
+impl<'a, P: Panel, B: ButtonSource> AppHost<'a, P, B> {
+    pub fn new(display: P, buttons: B, net: &'a NetStack<'a>, config: ConfigStore) -> Self {
+        let mut scheduler = Scheduler::new();
+        scheduler.schedule_every(AMBIENT_LIGHT, AMBIENT_INTERVAL);
+        scheduler.schedule_every(BATTERY_CHECK, BATTERY_INTERVAL);
+        scheduler.schedule_every(NET_HEALTH, NET_HEALTH_INTERVAL);
+        let face_down_after = config
+            .get_parsed(keys::FACE_DOWN_SLEEP)
+            .filter(|&secs| secs > 0)
+            .map(Duration::from_secs);
+        if face_down_after.is_some() {
+            scheduler.schedule_every(FACE_DOWN, FACE_DOWN_INTERVAL);
+        }
+        let actions = Bindings::from_config(&config);
+        Self {
+            display,
+            buttons,
+            battery: &BatteryAdc,
+            net,
+            config,
+            scheduler,
+            console: None,
+            telemetry: None,
+            home_assistant: None,
+            alerts: None,
+            datalog: None,
+            tsdb: None,
+            server: None,
+            apps: Vec::new(),
+            active: 0,
+            actions,
+            dirty: false,
+            low_battery: false,
+            net_health: net_health::Monitor::new(),
+            face_down_after,
+            face_down_since: None,
+            canvas: None,
+            compositor: Compositor::new(),
+        }
+    }
+
+    /// Adds an app; the first one installed starts in front
+    pub fn install(&mut self, app: impl App + 'a) {
+        self.apps.push(Box::new(app));
+    }
+
+    /// Serves commands from `console` while the apps run
+    pub fn set_console(&mut self, console: Console<'a>) {
+        self.console = Some(console);
+    }
+
+    /// Serves HTTP requests with `server` while the apps run
+    pub fn set_server(&mut self, server: HttpServer<'a>) {
+        self.server = Some(server);
+    }
+
+    /// Reads the battery from `battery` rather than the ADC
+    pub fn set_battery(&mut self, battery: &'a dyn Battery) {
+        self.battery = battery;
+    }
+
+    /// Draws `layer` on every screen, see [compositor]
+    pub fn add_layer(&mut self, layer: impl Layer + 'a) {
+        self.compositor.add(layer);
+    }
+
+    /// Publishes telemetry now and then every [Telemetry::interval]
+    pub fn set_telemetry(&mut self, telemetry: Telemetry) {
+        self.scheduler.schedule(TELEMETRY, Duration::ZERO);
+        self.telemetry = Some(telemetry);
+    }
+
+    /// Polls `home_assistant` now and then every [HomeAssistant::interval]
+    pub fn set_home_assistant(&mut self, home_assistant: HomeAssistant) {
+        self.scheduler.schedule(HOME_ASSISTANT, Duration::ZERO);
+        self.home_assistant = Some(home_assistant);
+    }
+
+    /// Stays subscribed to `alerts` and shows them over every app, see
+    /// [crate::alerts]
+    pub fn set_alerts(&mut self, alerts: Alerts<'a>) {
+        self.compositor.add(AlertLayer);
+        self.scheduler.schedule_every(ALERTS, alerts::POLL_INTERVAL);
+        self.alerts = Some(alerts);
+    }
+
+    /// Logs readings with `datalog` now and then every [DataLog::interval],
+    /// and uploads the complete files every [datalog::UPLOAD_INTERVAL] if
+    /// it has somewhere to
+    pub fn set_datalog(&mut self, datalog: DataLog) {
+        self.scheduler.schedule(DATALOG, Duration::ZERO);
+        if datalog.uploads() {
+            self.scheduler
+                .schedule_every(DATALOG_UPLOAD, datalog::UPLOAD_INTERVAL);
+        }
+        self.datalog = Some(datalog);
+    }
+
+    /// Writes readings with `tsdb` now and then every [Tsdb::interval]
+    pub fn set_tsdb(&mut self, tsdb: Tsdb) {
+        self.scheduler.schedule(TSDB, Duration::ZERO);
+        self.tsdb = Some(tsdb);
+    }
+
+    /// Runs the event loop forever
+    pub fn run(mut self) -> ! {
+        assert!(!self.apps.is_empty(), "no apps installed");
+        self.check_battery();
+        bindings::publish("wifi.link", link_state(wifi::is_connected()));
+        self.activate(0);
+
+        loop {
+            self.net.work_for(self.net_budget());
+
+            let now = Instant::now();
+            self.buttons.poll(now);
+            while let Some(event) = self.buttons.next_event() {
+                self.dispatch(event);
+            }
+            if let Some(console) = self.console.as_mut() {
+                let mut ctx = Context {
+                    net: self.net,
+                    config: &mut self.config,
+                    now,
+                };
+                if console.poll(&mut ctx) == Flow::Redraw {
+                    self.dirty = true;
+                }
+            }
+            if let Some(server) = self.server.as_mut() {
+                server.poll();
+            }
+            while let Some(task) = self.scheduler.next_due(now) {
+                if let Some(quiet) = self.quiet_for(task) {
+                    self.scheduler.schedule(task, quiet);
+                } else if task == APP_TICK {
+                    self.dispatch(Event::Tick);
+                } else if task == TELEMETRY {
+                    self.publish_telemetry();
+                } else if task == AMBIENT_LIGHT {
+                    neopixel::adapt(Settings::load(&self.config).neopixel_brightness);
+                } else if task == BATTERY_CHECK {
+                    self.check_battery();
+                } else if task == NET_HEALTH {
+                    self.check_network(now);
+                } else if task == HOME_ASSISTANT {
+                    self.poll_home_assistant();
+                } else if task == ALERTS {
+                    if let Some(alerts) = self.alerts.as_mut() {
+                        alerts.poll(&self.config, now);
+                    }
+                } else if task == FACE_DOWN {
+                    self.check_face_down(now);
+                } else if task == DATALOG {
+                    self.log_readings();
+                } else if task == DATALOG_UPLOAD {
+                    self.upload_datalog();
+                } else if task == TSDB {
+                    self.write_tsdb();
+                }
+            }
+            if self.compositor.take_dirty() {
+                self.dirty = true;
+            }
+            let app = &mut self.apps[self.active];
+            if let Some(area) = app.subscriptions().and_then(Subscriptions::dirty) {
+                debug!("{}: bound values changed in {:?}", app.name(), area);
+                self.dirty = true;
+            }
+
+            if self.dirty {
+                self.redraw();
+            }
+        }
+    }
+
+    /// [NET_BUDGET], cut short by the next scheduled task. The stack always
+    /// gets at least one poll.
+    fn net_budget(&self) -> core::time::Duration {
+        let now = Instant::now();
+        let budget = match self.scheduler.next_deadline() {
+            Some(due) if due <= now => Duration::ZERO,
+            Some(due) => (due - now).min(NET_BUDGET),
+            None => NET_BUDGET,
+        };
+        core::time::Duration::from_micros(budget.as_micros())
+    }
+
+    fn activate(&mut self, index: usize) {
+        self.active = index;
+        self.scheduler.cancel(APP_TICK);
+        input::set_repeat(ButtonSet::EMPTY);
+        let mut ctx = Context {
+            net: self.net,
+            config: &mut self.config,
+            now: Instant::now(),
+        };
+        let app = &mut self.apps[index];
+        info!("Switching to app {}", app.name());
+        if app.on_enter(&mut ctx) == Flow::Redraw {
+            self.dirty = true;
+        } else {
+            self.arm_tick();
+        }
+    }
+
+    fn dispatch(&mut self, event: Event) {
+        // a raised alert takes the first press, then an open dialog holds on
+        // to the buttons, the app switch among them
+        let Some(event) = alerts::route(event).and_then(dialog::route) else {
+            return;
+        };
+        let event = match self.actions.action(event) {
+            Some(action) if !action.is_app_action() => return self.run_action(action),
+            Some(action) => {
+                info!("{}: {}", self.apps[self.active].name(), action.name());
+                // the screen shows it was taken even if nothing changed
+                self.dirty |= action == Action::Refresh;
+                Event::Action(action)
+            }
+            None => event,
+        };
+        if event == Event::Chord(SWITCH_CHORD) && self.apps.len() > 1 {
+            self.activate((self.active + 1) % self.apps.len());
+            return;
+        }
+        let refresh = event == Event::Action(Action::Refresh);
+        if refresh {
+            // the fetch blocks, so say so before it
+            compositor::show_banner(tr!("updating"));
+            self.redraw();
+        }
+
+        let mut ctx = Context {
+            net: self.net,
+            config: &mut self.config,
+            now: Instant::now(),
+        };
+        let app = &mut self.apps[self.active];
+        let flow = if matches!(event, Event::Tick | Event::Action(Action::Refresh)) {
+            hooks::fetch(app.name(), || app.on_event(event, &mut ctx))
+        } else {
+            app.on_event(event, &mut ctx)
+        };
+        if refresh {
+            compositor::hide_banner();
+        }
+        if flow == Flow::Redraw {
+            self.dirty = true;
+        } else if event == Event::Tick {
+            self.arm_tick();
+        }
+    }
+
+    /// Carries out one of the host's own [Action]s
+    fn run_action(&mut self, action: Action) {
+        info!("Button action {}", action.name());
+        let count = self.apps.len();
+        match action {
+            Action::NextApp if count > 1 => self.activate((self.active + 1) % count),
+            Action::PreviousApp if count > 1 => self.activate((self.active + count - 1) % count),
+            Action::Provision => boot_mode::restart_into(BootMode::Provisioning),
+            Action::SafeMode => boot_mode::restart_into(BootMode::Safe),
+            Action::Restart => boot_mode::restart_into(BootMode::Normal),
+            _ => {}
+        }
+    }
+
+    fn redraw(&mut self) {
+        self.dirty = false;
+        // telemetry due before the refresh would be over goes out during it
+        let overlap_telemetry = self.scheduler.due_within(TELEMETRY, REFRESH_OVERLAP)
+            && self.quiet_for(TELEMETRY).is_none();
+        let frame = self.display.frame();
+        let app = &mut self.apps[self.active];
+        let canvas = &mut self.canvas;
+        let boost = cpu::boost();
+        self.compositor.compose(frame, |frame| {
+            if let Some(dither) = app.gray8() {
+                let canvas = canvas.get_or_insert_with(Canvas::new);
+                canvas.clear(Gray8::WHITE).ok();
+                app.render_gray8(canvas);
+                canvas.quantize(frame, dither);
+            } else {
+                frame.clear(Gray2::WHITE).ok();
+                app.render(frame);
+            }
+        });
+        drop(boost);
+        // the frame has the current values now
+        if let Some(subscriptions) = app.subscriptions() {
+            subscriptions.dirty();
+        }
+        self.display
+            .set_contrast(self.config.get_parsed(keys::CONTRAST).unwrap_or_default());
+        let started = Instant::now();
+        let previous = estimator::enter(State::Refresh);
+        let telemetry = self.telemetry.as_ref().filter(|_| overlap_telemetry);
+        let (net, config) = (self.net, &mut self.config);
+        let name = app.name();
+        let mut published = None;
+        let flushed = self.display.flush_while(&mut || {
+            let mut ctx = Context {
+                net,
+                config,
+                now: Instant::now(),
+            };
+            hooks::fetch(name, || app.while_refreshing(&mut ctx));
+            if let Some(telemetry) = telemetry {
+                published = Some(telemetry.publish(net));
+            }
+        });
+        estimator::enter(previous);
+        if let Some(result) = published {
+            self.telemetry_published(result);
+        }
+        hooks::after_render(name, flushed.as_ref().map(|_| ()), started.elapsed());
+        match flushed {
+            Ok(()) => metrics::record_refresh(started.elapsed()),
+            Err(err) => {
+                warn!("refresh failed: {}", err);
+                hooks::on_error("refresh", &err);
+            }
+        }
+        self.arm_tick();
+    }
+
+    fn publish_telemetry(&mut self) {
+        let Some(telemetry) = self.telemetry.as_ref() else {
+            return;
+        };
+        let result = telemetry.publish(self.net);
+        self.telemetry_published(result);
+    }
+
+    fn telemetry_published(&mut self, result: Result<(), Error>) {
+        let Some(telemetry) = self.telemetry.as_ref() else {
+            return;
+        };
+        // failures are buffered and logged by the telemetry module
+        match result {
+            Ok(()) => notify::clear(Notice::FetchFailed),
+            Err(err) => {
+                notify::raise(&self.config, Notice::FetchFailed);
+                hooks::on_error("telemetry", &err);
+            }
+        }
+        self.scheduler.schedule(TELEMETRY, telemetry.interval());
+    }
+
+    fn poll_home_assistant(&mut self) {
+        let Some(home_assistant) = self.home_assistant.as_ref() else {
+            return;
+        };
+        // failures are logged and the states kept as they were
+        if let Err(err) = home_assistant.poll(self.net) {
+            hooks::on_error("home assistant", &err);
+        }
+        self.scheduler
+            .schedule(HOME_ASSISTANT, home_assistant.interval());
+    }
+
+    fn log_readings(&mut self) {
+        let Some(datalog) = self.datalog.as_ref() else {
+            return;
+        };
+        if let Err(err) = datalog.sample() {
+            warn!("datalog: {}", err);
+            hooks::on_error("datalog", &err);
+        }
+        self.scheduler.schedule(DATALOG, datalog.interval());
+    }
+
+    fn upload_datalog(&mut self) {
+        let Some(datalog) = self.datalog.as_ref() else {
+            return;
+        };
+        // files that didn't go stay for the next round
+        if let Err(err) = datalog.upload(self.net) {
+            warn!("datalog upload: {}", err);
+            hooks::on_error("datalog upload", &err);
+        }
+    }
+
+    fn write_tsdb(&mut self) {
+        let Some(tsdb) = self.tsdb.as_mut() else {
+            return;
+        };
+        // failures are kept and logged by the tsdb module
+        if let Err(err) = tsdb.write(self.net) {
+            hooks::on_error("tsdb", &err);
+        }
+        self.scheduler.schedule(TSDB, tsdb.interval());
+    }
+
+    fn check_network(&mut self, now: Instant) {
+        let Some(link) = self.net_health.check(self.net, now) else {
+            return;
+        };
+        bindings::publish("wifi.link", link_state(link == Link::Restored));
+        self.dispatch(Event::Link(link));
+        if link == Link::Recovering {
+            // show it before the restart blocks everything
+            compositor::show_banner(tr!("reconnecting_wifi"));
+            self.redraw();
+            self.net_health.recover(self.net);
+            compositor::hide_banner();
+        }
+    }
+
+    /// Sleeps until turned over once the badge has been lying face down for
+    /// `facedown_s`, unless an alert is up
+    fn check_face_down(&mut self, now: Instant) {
+        let Some(after) = self.face_down_after else {
+            return;
+        };
+        if alerts::is_raised() || !accel::read().is_some_and(Acceleration::is_face_down) {
+            self.face_down_since = None;
+            return;
+        }
+        let since = *self.face_down_since.get_or_insert(now);
+        if now - since >= after {
+            info!("Face down for {} s", after.as_secs());
+            power::sleep_until_face_up();
+        }
+    }
+
+    fn check_battery(&mut self) {
+        let Some(mv) = self.battery.millivolts() else {
+            bindings::withdraw("battery.mv");
+            bindings::withdraw("battery.pct");
+            return;
+        };
+        bindings::publish("battery.mv", mv);
+        bindings::publish("battery.pct", estimator::charge_permille(mv) / 10);
+        if !self.low_battery && mv < LOW_BATTERY_MV {
+            self.low_battery = true;
+            notify::raise(&self.config, Notice::LowBattery);
+            toast::show(tr!("battery_low"));
+        } else if self.low_battery && mv > BATTERY_OK_MV {
+            self.low_battery = false;
+            notify::clear(Notice::LowBattery);
+        }
+    }
+
+    /// How long `task` has to wait for the end of the quiet hours, if it
+    /// refreshes and they are on. Buttons still reach the app, so a manual
+    /// refresh goes through.
+    fn quiet_for(&self, task: TaskId) -> Option<Duration> {
+        if !host_tasks::QUIET.contains(&task) {
+            return None;
+        }
+        let quiet: QuietHours = self.config.get_parsed(keys::QUIET_HOURS)?;
+        let now = time::now_local()?;
+        let wait = power::duration_until(host_tasks::deferred(task, &quiet, now)?)?;
+        debug!(
+            "task {:?} waits {} min for the quiet hours to end",
+            task,
+            wait.as_minutes()
+        );
+        Some(wait)
+    }
+
+    fn arm_tick(&mut self) {
+        match self.apps[self.active].desired_sleep() {
+            Some(delay) => self.scheduler.schedule(APP_TICK, delay),
+            None => self.scheduler.cancel(APP_TICK),
+        }
+    }
+}
+
+/// The `wifi.link` binding
+fn link_state(up: bool) -> &'static str {
+    if up {
+        "up"
+    } else {
+        "down"
+    }
+}
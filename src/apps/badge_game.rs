@@ -0,0 +1,506 @@
+//! A conference game for a room full of badges: badges in range find each
+//! other over ESP-NOW, swap contacts at the press of a button and rank
+//! everyone by how many contacts they have collected.
+//!
+//! Each badge broadcasts a beacon with its name and contact count every
+//! [BEACON_INTERVAL] while the app is in front, and lists the badges it
+//! heard from in the last [NEARBY_TIMEOUT]. D offers a contact to the
+//! selected badge, which keeps it and answers with its own if the game is in
+//! front there too; A and C move the selection and B switches between the
+//! badges nearby and the leaderboard.
+//!
+//! The name is `badge_name`, or `Badge` and the end of the MAC address if
+//! unset. Contacts are kept in the asset store as [CONTACTS_FILE], one
+//! `<mac> <count> <name>` line each, so they survive a reset; the count is
+//! the last one heard from that badge. ESP-NOW shares the radio with the
+//! WiFi station, so every badge has to be on the same channel: the same
+//! access point, or none.
+//!
+//! Frames are [MAGIC], a kind byte, the contact count as a little-endian
+//! `u16` and the name in UTF-8, at most [MAX_NAME_LEN] bytes.
+
+use alloc::{format, string::String, vec::Vec};
+use core::{cmp::Reverse, fmt::Write as _};
+
+use esp_hal::{
+    efuse::Efuse,
+    time::{Duration, Instant},
+};
+use esp_radio::esp_now::{EspNow, EspNowWifiInterface, PeerInfo, BROADCAST_ADDRESS};
+use log::{debug, info, warn};
+
+use crate::{
+    app::{App, Context, Flow},
+    assets::AssetStore,
+    config::{keys, ConfigStore},
+    display::Frame,
+    input::{Button, Event},
+    screens::badge_game::{self as screen, Badge},
+    storage::BlobStore,
+    tr,
+    ui::toast,
+    Error,
+};
+
+/// Starts every frame, with a version digit
+pub const MAGIC: [u8; 3] = *b"MG1";
+pub const MAX_NAME_LEN: usize = 24;
+/// Where contacts are kept in the asset store
+pub const CONTACTS_FILE: &str = "CONTACTS.TXT";
+pub const MAX_CONTACTS: usize = 100;
+pub const BEACON_INTERVAL: Duration = Duration::from_secs(5);
+/// How long a badge stays on the nearby list after its last beacon
+pub const NEARBY_TIMEOUT: Duration = Duration::from_secs(30);
+/// How often the radio is checked
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What a frame is for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// "I'm here", broadcast
+    Beacon,
+    /// A contact for the receiver to keep and answer with its own
+    Offer,
+    /// The answer to an [Kind::Offer]
+    Reply,
+}
+
+impl Kind {
+    fn byte(self) -> u8 {
+        match self {
+            Kind::Beacon => b'B',
+            Kind::Offer => b'O',
+            Kind::Reply => b'R',
+        }
+    }
+
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            b'B' => Some(Kind::Beacon),
+            b'O' => Some(Kind::Offer),
+            b'R' => Some(Kind::Reply),
+            _ => None,
+        }
+    }
+}
+
+/// One frame between badges
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub kind: Kind,
+    /// Contacts the sender has collected
+    pub score: u16,
+    pub name: String,
+}
+
+impl Message {
+    pub fn encode(&self) -> Vec<u8> {
+        let name = truncate(&self.name, MAX_NAME_LEN);
+        let mut frame = Vec::with_capacity(MAGIC.len() + 3 + name.len());
+        frame.extend_from_slice(&MAGIC);
+        frame.push(self.kind.byte());
+        frame.extend_from_slice(&self.score.to_le_bytes());
+        frame.extend_from_slice(name.as_bytes());
+        frame
+    }
+
+    /// `None` for anything that isn't a frame of this game
+    pub fn decode(frame: &[u8]) -> Option<Self> {
+        let rest = frame.strip_prefix(&MAGIC)?;
+        let (&kind, rest) = rest.split_first()?;
+        let score = u16::from_le_bytes([*rest.first()?, *rest.get(1)?]);
+        let name = core::str::from_utf8(&rest[2..]).ok()?.trim();
+        if name.is_empty() || name.len() > MAX_NAME_LEN {
+            return None;
+        }
+        Some(Self {
+            kind: Kind::from_byte(kind)?,
+            score,
+            name: name.into(),
+        })
+    }
+}
+
+/// A badge swapped with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Contact {
+    pub mac: [u8; 6],
+    pub name: String,
+    /// Its count when last heard from
+    pub score: u16,
+}
+
+/// A badge heard from lately
+struct Nearby {
+    mac: [u8; 6],
+    name: String,
+    score: u16,
+    rssi: i32,
+    seen: Instant,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum View {
+    Nearby,
+    Leaderboard,
+}
+
+/// The game, over the ESP-NOW interface of the radio
+pub struct BadgeGame<'a> {
+    radio: EspNow<'a>,
+    name: String,
+    contacts: Vec<Contact>,
+    /// Whether [CONTACTS_FILE] was read yet
+    loaded: bool,
+    nearby: Vec<Nearby>,
+    selected: usize,
+    view: View,
+    beacon_at: Option<Instant>,
+}
+
+impl<'a> BadgeGame<'a> {
+    pub fn new(radio: EspNow<'a>) -> Self {
+        Self {
+            radio,
+            name: String::new(),
+            contacts: Vec::new(),
+            loaded: false,
+            nearby: Vec::new(),
+            selected: 0,
+            view: View::Nearby,
+            beacon_at: None,
+        }
+    }
+
+    fn score(&self) -> u16 {
+        self.contacts.len() as u16
+    }
+
+    fn message(&self, kind: Kind) -> Message {
+        Message {
+            kind,
+            score: self.score(),
+            name: self.name.clone(),
+        }
+    }
+
+    fn send(&mut self, to: &[u8; 6], kind: Kind) -> Result<(), Error> {
+        if *to != BROADCAST_ADDRESS && !self.radio.peer_exists(to) {
+            self.radio
+                .add_peer(PeerInfo {
+                    interface: EspNowWifiInterface::Sta,
+                    peer_address: *to,
+                    lmk: None,
+                    // whichever channel the station is on
+                    channel: None,
+                    encrypt: false,
+                })
+                .map_err(|_| Error::Network)?;
+        }
+        let frame = self.message(kind).encode();
+        self.radio
+            .send(to, &frame)
+            .and_then(|waiter| waiter.wait())
+            .map_err(|err| {
+                warn!("ESP-NOW send to {} failed: {:?}", mac_hex(to), err);
+                Error::Network
+            })
+    }
+
+    /// Handles what has arrived; true when the screen should change
+    fn receive(&mut self, now: Instant) -> bool {
+        let mut changed = false;
+        while let Some(received) = self.radio.receive() {
+            let Some(message) = Message::decode(received.data()) else {
+                continue;
+            };
+            let from = received.info.src_address;
+            debug!(
+                "{:?} from {} ({})",
+                message.kind,
+                message.name,
+                mac_hex(&from)
+            );
+            changed |= self.heard(from, &message, received.info.rx_control.rssi, now);
+            match message.kind {
+                Kind::Beacon => {}
+                Kind::Offer => {
+                    self.keep(from, &message);
+                    // failures are logged, the other badge can offer again
+                    self.send(&from, Kind::Reply).ok();
+                    toast::show(&tr!("badge_swapped", message.name));
+                    changed = true;
+                }
+                Kind::Reply => {
+                    self.keep(from, &message);
+                    toast::show(&tr!("badge_got_contact", message.name));
+                    changed = true;
+                }
+            }
+        }
+        changed
+    }
+
+    /// Updates the nearby list and known scores; true if the list changed
+    fn heard(&mut self, mac: [u8; 6], message: &Message, rssi: i32, now: Instant) -> bool {
+        if let Some(contact) = self.contacts.iter_mut().find(|c| c.mac == mac) {
+            contact.score = message.score;
+        }
+        if let Some(badge) = self.nearby.iter_mut().find(|b| b.mac == mac) {
+            let changed = badge.name != message.name || badge.score != message.score;
+            badge.name.clone_from(&message.name);
+            badge.score = message.score;
+            badge.rssi = rssi;
+            badge.seen = now;
+            return changed;
+        }
+        info!("Badge {} is nearby", message.name);
+        self.nearby.push(Nearby {
+            mac,
+            name: message.name.clone(),
+            score: message.score,
+            rssi,
+            seen: now,
+        });
+        true
+    }
+
+    /// Adds or updates the contact of `mac` and stores the list
+    fn keep(&mut self, mac: [u8; 6], message: &Message) {
+        let full = self.contacts.len() >= MAX_CONTACTS;
+        match self.contacts.iter_mut().find(|c| c.mac == mac) {
+            Some(contact) => {
+                contact.name.clone_from(&message.name);
+                contact.score = message.score;
+            }
+            None if full => {
+                warn!(
+                    "{} contacts already, not keeping {}",
+                    MAX_CONTACTS, message.name
+                );
+                return;
+            }
+            None => {
+                info!("New contact {}", message.name);
+                self.contacts.push(Contact {
+                    mac,
+                    name: message.name.clone(),
+                    score: message.score,
+                });
+            }
+        }
+        if let Err(err) = self.save() {
+            warn!("Saving contacts failed: {}", err);
+        }
+    }
+
+    fn load(&mut self) {
+        self.loaded = true;
+        let data = AssetStore::open().and_then(|store| store.read_to_vec(CONTACTS_FILE));
+        match data {
+            Ok(data) => {
+                self.contacts = parse_contacts(&String::from_utf8_lossy(&data));
+                info!("{} contacts collected", self.contacts.len());
+            }
+            Err(Error::NotFound) => {}
+            Err(err) => warn!("Reading {} failed: {}", CONTACTS_FILE, err),
+        }
+    }
+
+    fn save(&self) -> Result<(), Error> {
+        let mut text = String::new();
+        for contact in &self.contacts {
+            writeln!(
+                text,
+                "{} {} {}",
+                mac_hex(&contact.mac),
+                contact.score,
+                contact.name
+            )
+            .ok();
+        }
+        AssetStore::open()?.write(CONTACTS_FILE, text.as_bytes())
+    }
+
+    /// Drops badges not heard from lately; true if any went
+    fn expire(&mut self, now: Instant) -> bool {
+        let before = self.nearby.len();
+        self.nearby
+            .retain(|badge| now - badge.seen < NEARBY_TIMEOUT);
+        if self.selected >= self.nearby.len() {
+            self.selected = self.nearby.len().saturating_sub(1);
+        }
+        self.nearby.len() != before
+    }
+
+    fn offer(&mut self) -> Flow {
+        let Some(badge) = self.nearby.get(self.selected) else {
+            return Flow::Idle;
+        };
+        let (mac, name) = (badge.mac, badge.name.clone());
+        info!("Offering our contact to {}", name);
+        if self.send(&mac, Kind::Offer).is_err() {
+            toast::show(&tr!("badge_not_received", name));
+            return Flow::Redraw;
+        }
+        Flow::Idle
+    }
+
+    fn step(&mut self, forward: bool) -> Flow {
+        let len = self.nearby.len();
+        if self.view != View::Nearby || len == 0 {
+            return Flow::Idle;
+        }
+        self.selected = if forward {
+            (self.selected + 1) % len
+        } else {
+            (self.selected + len - 1) % len
+        };
+        Flow::Redraw
+    }
+
+    /// Everyone known, this badge included, most contacts first
+    fn ranking(&self) -> Vec<(&str, u16, bool)> {
+        let mut ranking: Vec<(&str, u16, bool)> = self
+            .contacts
+            .iter()
+            .map(|c| (c.name.as_str(), c.score, false))
+            .chain(
+                self.nearby
+                    .iter()
+                    .filter(|b| !self.contacts.iter().any(|c| c.mac == b.mac))
+                    .map(|b| (b.name.as_str(), b.score, false)),
+            )
+            .collect();
+        ranking.push((self.name.as_str(), self.score(), true));
+        ranking.sort_by_key(|entry| Reverse(entry.1));
+        ranking
+    }
+}
+
+impl App for BadgeGame<'_> {
+    fn name(&self) -> &'static str {
+        "badge game"
+    }
+
+    fn on_enter(&mut self, ctx: &mut Context<'_, '_>) -> Flow {
+        self.name = badge_name(ctx.config);
+        if !self.loaded {
+            self.load();
+        }
+        // announce right away
+        self.beacon_at = None;
+        self.on_event(Event::Tick, ctx);
+        Flow::Redraw
+    }
+
+    fn on_event(&mut self, event: Event, ctx: &mut Context<'_, '_>) -> Flow {
+        match event {
+            Event::Press(Button::A) => self.step(false),
+            Event::Press(Button::B) => {
+                self.view = match self.view {
+                    View::Nearby => View::Leaderboard,
+                    View::Leaderboard => View::Nearby,
+                };
+                Flow::Redraw
+            }
+            Event::Press(Button::C) => self.step(true),
+            Event::Press(Button::D) if self.view == View::Nearby => self.offer(),
+            Event::Tick => {
+                if self
+                    .beacon_at
+                    .is_none_or(|at| ctx.now - at >= BEACON_INTERVAL)
+                {
+                    self.beacon_at = Some(ctx.now);
+                    // a missed beacon is made up for by the next
+                    self.send(&BROADCAST_ADDRESS, Kind::Beacon).ok();
+                }
+                if self.receive(ctx.now) | self.expire(ctx.now) {
+                    Flow::Redraw
+                } else {
+                    Flow::Idle
+                }
+            }
+            _ => Flow::Idle,
+        }
+    }
+
+    fn render(&mut self, frame: &mut Frame) {
+        match self.view {
+            View::Nearby => {
+                let badges: Vec<Badge> = self
+                    .nearby
+                    .iter()
+                    .map(|badge| Badge {
+                        name: &badge.name,
+                        score: badge.score,
+                        rssi: badge.rssi,
+                        met: self.contacts.iter().any(|c| c.mac == badge.mac),
+                    })
+                    .collect();
+                screen::draw_nearby(frame, &self.name, &badges, self.selected);
+            }
+            View::Leaderboard => screen::draw_leaderboard(frame, self.score(), &self.ranking()),
+        }
+    }
+
+    fn desired_sleep(&self) -> Option<Duration> {
+        Some(POLL_INTERVAL)
+    }
+}
+
+/// `badge_name`, or one made from the MAC address
+fn badge_name(config: &ConfigStore) -> String {
+    match config.get(keys::BADGE_NAME).map(str::trim) {
+        Some(name) if !name.is_empty() => truncate(name, MAX_NAME_LEN).into(),
+        _ => {
+            let mac = Efuse::mac_address();
+            format!("Badge {:02x}{:02x}{:02x}", mac[3], mac[4], mac[5])
+        }
+    }
+}
+
+/// The lines of [CONTACTS_FILE], skipping any that don't parse
+pub fn parse_contacts(text: &str) -> Vec<Contact> {
+    text.lines()
+        .filter_map(|line| {
+            let mut parts = line.splitn(3, ' ');
+            let mac = parse_mac(parts.next()?)?;
+            let score = parts.next()?.parse().ok()?;
+            let name = parts.next()?.trim();
+            (!name.is_empty()).then(|| Contact {
+                mac,
+                name: name.into(),
+                score,
+            })
+        })
+        .take(MAX_CONTACTS)
+        .collect()
+}
+
+fn parse_mac(hex: &str) -> Option<[u8; 6]> {
+    if hex.len() != 12 {
+        return None;
+    }
+    let mut mac = [0u8; 6];
+    for (i, byte) in mac.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(2 * i..2 * i + 2)?, 16).ok()?;
+    }
+    Some(mac)
+}
+
+fn mac_hex(mac: &[u8; 6]) -> String {
+    mac.iter().fold(String::with_capacity(12), |mut hex, byte| {
+        write!(hex, "{:02x}", byte).ok();
+        hex
+    })
+}
+
+/// `s` cut to at most `max` bytes on a character boundary
+fn truncate(s: &str, max: usize) -> &str {
+    let mut end = s.len().min(max);
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
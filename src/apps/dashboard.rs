@@ -0,0 +1,134 @@
+//! A screen laid out by a [Template] rather than in code.
+//!
+//! The template comes from the `dashboard` config key: the name of a file in
+//! the asset store, [DEFAULT_TEMPLATE] if unset, or an `http://` URL. It is
+//! read on entering the app, on B, and again every refresh interval from the
+//! settings. Templates can bind `time` and `date` as well as any of the
+//! [bindings](crate::bindings), and the screen is redrawn when a bound
+//! value changes.
+//!
+//! A template from a URL is kept in the asset store as [CACHE_NAME], so the
+//! dashboard still shows, tagged as stale, while the server can't be reached.
+
+use esp_hal::time::{Duration, Instant};
+use log::{info, warn};
+
+use crate::{
+    actions::Action,
+    app::{App, Context, Flow},
+    bindings::Subscriptions,
+    config::{keys, Settings},
+    data_source::{DataSource, Stale},
+    display::Frame,
+    input::{Button, Event},
+    screens::dashboard as screen,
+    storage,
+    ui::template::{self, Template},
+    Error,
+};
+
+pub const DEFAULT_TEMPLATE: &str = "dashboard.toml";
+/// Where the last template fetched over HTTP is kept
+pub const CACHE_NAME: &str = "dashboard.cache";
+
+/// Draws the configured template
+pub struct Dashboard {
+    template: Option<Result<Template, Error>>,
+    /// The template's URL, when it comes from one
+    remote: Option<DataSource>,
+    stale: Option<Stale>,
+    subscriptions: Subscriptions,
+    loaded_at: Option<Instant>,
+    refresh: Duration,
+}
+
+impl Dashboard {
+    pub fn new() -> Self {
+        Self {
+            template: None,
+            remote: None,
+            stale: None,
+            subscriptions: Subscriptions::new(),
+            loaded_at: None,
+            refresh: Settings::default().refresh_interval(),
+        }
+    }
+
+    fn load(&mut self, ctx: &Context<'_, '_>) -> Flow {
+        let source = ctx.config.get(keys::DASHBOARD).unwrap_or(DEFAULT_TEMPLATE);
+        self.stale = None;
+        let template = if source.starts_with("http://") {
+            if self
+                .remote
+                .as_ref()
+                .is_none_or(|remote| remote.url() != source)
+            {
+                self.remote = Some(DataSource::new(source).persist(CACHE_NAME));
+            }
+            let remote = self.remote.as_mut().unwrap();
+            remote.fetch(ctx.net).and_then(|payload| {
+                self.stale = payload.stale;
+                template::parse(payload.body)
+            })
+        } else {
+            self.remote = None;
+            storage::open(source)
+                .and_then(|(store, name)| store.read_to_vec(name))
+                .and_then(|raw| template::parse(&raw))
+        };
+        match &template {
+            Ok(template) => info!(
+                "Dashboard from {}, {} elements",
+                source,
+                template.elements.len()
+            ),
+            Err(err) => warn!("Dashboard template {}: {}", source, err),
+        }
+        self.subscriptions.clear();
+        if let Ok(template) = &template {
+            template.subscribe(&mut self.subscriptions);
+        }
+        self.template = Some(template);
+        self.loaded_at = Some(ctx.now);
+        self.refresh = Settings::load(ctx.config).refresh_interval();
+        Flow::Redraw
+    }
+}
+
+impl Default for Dashboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl App for Dashboard {
+    fn name(&self) -> &'static str {
+        "dashboard"
+    }
+
+    fn on_enter(&mut self, ctx: &mut Context<'_, '_>) -> Flow {
+        self.load(ctx)
+    }
+
+    fn on_event(&mut self, event: Event, ctx: &mut Context<'_, '_>) -> Flow {
+        match event {
+            Event::Press(Button::B) | Event::Action(Action::Refresh) => self.load(ctx),
+            Event::Tick if self.loaded_at.is_none_or(|at| ctx.now - at >= self.refresh) => {
+                self.load(ctx)
+            }
+            _ => Flow::Idle,
+        }
+    }
+
+    fn render(&mut self, frame: &mut Frame) {
+        screen::draw(frame, self.template.as_ref(), self.stale.as_ref());
+    }
+
+    fn desired_sleep(&self) -> Option<Duration> {
+        Some(self.refresh)
+    }
+
+    fn subscriptions(&mut self) -> Option<&mut Subscriptions> {
+        Some(&mut self.subscriptions)
+    }
+}
@@ -0,0 +1,26 @@
+//! The original hello-world screen: text, a gray square, Ferris and a line.
+
+use crate::{
+    app::{App, Context, Flow},
+    display::Frame,
+    input::Event,
+    screens::demo as screen,
+};
+
+/// Static demo screen
+#[derive(Default)]
+pub struct Demo;
+
+impl App for Demo {
+    fn name(&self) -> &'static str {
+        "demo"
+    }
+
+    fn on_event(&mut self, _event: Event, _ctx: &mut Context<'_, '_>) -> Flow {
+        Flow::Idle
+    }
+
+    fn render(&mut self, frame: &mut Frame) {
+        screen::draw(frame);
+    }
+}
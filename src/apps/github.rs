@@ -0,0 +1,239 @@
+//! Unread GitHub notifications and the CI status of a few repositories.
+//!
+//! `github_repos` lists up to [MAX_REPOS] repositories separated by commas,
+//! `owner/name` for the latest workflow run on any branch or
+//! `owner/name@branch` for one branch. `github_token` is a personal access
+//! token, sent as a bearer token; notifications need a classic token with the
+//! `notifications` scope, or are left out.
+//!
+//! GitHub only serves HTTPS, which [crate::http] doesn't speak, so requests
+//! go to `github_api`: a proxy on the local network that forwards plain HTTP
+//! to `https://api.github.com`.
+//!
+//! Each repository gets a row with a status box: white with a tick for a
+//! passing run, light gray while one runs or after it was cancelled, black
+//! for a failure, and an empty outline when there is nothing to show. The
+//! screen is fetched on entering the app, on B and every refresh interval
+//! from the settings.
+
+use alloc::{format, string::String, vec::Vec};
+
+use esp_hal::time::{Duration, Instant};
+use log::{info, warn};
+
+use crate::{
+    actions::Action,
+    app::{App, Context, Flow},
+    config::{keys, ConfigStore, Settings},
+    display::Frame,
+    http::{Auth, Request, Response},
+    input::{Button, Event},
+    json,
+    net::NetStack,
+    screens::github::{self as screen, Repo},
+    Error,
+};
+
+pub use crate::screens::github::CiState;
+
+/// Rows that fit between the title and the button hints
+pub const MAX_REPOS: usize = 5;
+
+/// The account's notifications and its repositories' CI
+pub struct GitHub {
+    repos: Vec<Repo>,
+    /// `None` until fetched, or when the token may not read them
+    notifications: Option<u32>,
+    /// Whether `github_api` is set
+    configured: bool,
+    loaded_at: Option<Instant>,
+    refresh: Duration,
+}
+
+impl GitHub {
+    pub fn new() -> Self {
+        Self {
+            repos: Vec::new(),
+            notifications: None,
+            configured: false,
+            loaded_at: None,
+            refresh: Settings::default().refresh_interval(),
+        }
+    }
+
+    fn load(&mut self, ctx: &Context<'_, '_>) -> Flow {
+        self.loaded_at = Some(ctx.now);
+        self.refresh = Settings::load(ctx.config).refresh_interval();
+        self.repos = parse_repos(ctx.config.get(keys::GITHUB_REPOS).unwrap_or_default());
+        self.notifications = None;
+        let api = Api::from_config(ctx.config);
+        self.configured = api.is_some();
+        let Some(api) = api else {
+            return Flow::Redraw;
+        };
+        match api.notifications(ctx.net) {
+            Ok(count) => self.notifications = Some(count),
+            Err(err) => warn!("GitHub notifications: {}", err),
+        }
+        for repo in &mut self.repos {
+            if let Err(err) = api.latest_run(ctx.net, repo) {
+                warn!("GitHub {}: {}", repo.name, err);
+                repo.state = CiState::Unknown;
+            }
+        }
+        info!(
+            "GitHub: {:?} notifications, {} repos",
+            self.notifications,
+            self.repos.len()
+        );
+        Flow::Redraw
+    }
+}
+
+impl Default for GitHub {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// `github_api` and the token to send there
+struct Api {
+    base: String,
+    auth: Option<Auth>,
+}
+
+impl Api {
+    fn from_config(config: &ConfigStore) -> Option<Self> {
+        let base = config.get(keys::GITHUB_API).filter(|s| !s.is_empty())?;
+        let auth = config
+            .get(keys::GITHUB_TOKEN)
+            .filter(|s| !s.is_empty())
+            .map(|token| Auth::Bearer(token.into()));
+        Some(Self {
+            base: base.trim_end_matches('/').into(),
+            auth,
+        })
+    }
+
+    fn get(&self, net: &NetStack<'_>, path: &str) -> Result<Response, Error> {
+        let url = format!("{}{}", self.base, path);
+        let mut request = Request::get(&url)
+            .header("Accept", "application/vnd.github+json")
+            .header("X-GitHub-Api-Version", "2022-11-28")
+            // GitHub turns away requests without one
+            .header("User-Agent", "magtag");
+        if let Some(auth) = &self.auth {
+            request = request.auth(auth);
+        }
+        request.send(net)
+    }
+
+    /// Unread notifications, one per page so the `Link` header's last page
+    /// is the count
+    fn notifications(&self, net: &NetStack<'_>) -> Result<u32, Error> {
+        if self.auth.is_none() {
+            return Err(Error::InvalidConfig);
+        }
+        let response = self.get(net, "/notifications?per_page=1")?;
+        if !response.is_success() {
+            warn!("GitHub answered {} for notifications", response.status);
+            return Err(Error::Network);
+        }
+        if let Some(last) = response.header("Link").and_then(last_page) {
+            return Ok(last);
+        }
+        let count = json::array_len(&response.body, &[]).ok_or(Error::Network)?;
+        Ok(count as u32)
+    }
+
+    fn latest_run(&self, net: &NetStack<'_>, repo: &mut Repo) -> Result<(), Error> {
+        let mut path = format!("/repos/{}/actions/runs?per_page=1", repo.name);
+        if let Some(branch) = &repo.branch {
+            path = format!("{}&branch={}", path, branch);
+        }
+        let response = self.get(net, &path)?;
+        if !response.is_success() {
+            warn!("GitHub answered {} for {}", response.status, repo.name);
+            return Err(Error::Network);
+        }
+        let run = |field| json::string_at(&response.body, &["workflow_runs", "0", field]);
+        repo.state = match run("status") {
+            Some(status) => CiState::of_run(&status, run("conclusion").as_deref()),
+            None => CiState::Unknown,
+        };
+        repo.workflow = run("name");
+        Ok(())
+    }
+}
+
+/// Up to [MAX_REPOS] of `owner/name[@branch]`, separated by commas
+fn parse_repos(list: &str) -> Vec<Repo> {
+    let mut repos: Vec<Repo> = list
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| entry.contains('/'))
+        .map(|entry| {
+            let (name, branch) = match entry.split_once('@') {
+                Some((name, branch)) => (name, Some(branch.into())),
+                None => (entry, None),
+            };
+            Repo {
+                name: name.into(),
+                branch,
+                state: CiState::Unknown,
+                workflow: None,
+            }
+        })
+        .collect();
+    if repos.len() > MAX_REPOS {
+        warn!(
+            "{} lists {} repos, showing the first {}",
+            keys::GITHUB_REPOS,
+            repos.len(),
+            MAX_REPOS
+        );
+        repos.truncate(MAX_REPOS);
+    }
+    repos
+}
+
+/// The `page` of the `rel="last"` link, e.g. from
+/// `<https://api.github.com/notifications?per_page=1&page=7>; rel="last"`
+fn last_page(link: &str) -> Option<u32> {
+    let last = link.split(',').find(|part| part.contains("rel=\"last\""))?;
+    let url = last.split(['<', '>']).nth(1)?;
+    let query = url.split_once('?')?.1;
+    query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("page="))?
+        .parse()
+        .ok()
+}
+
+impl App for GitHub {
+    fn name(&self) -> &'static str {
+        "github"
+    }
+
+    fn on_enter(&mut self, ctx: &mut Context<'_, '_>) -> Flow {
+        self.load(ctx)
+    }
+
+    fn on_event(&mut self, event: Event, ctx: &mut Context<'_, '_>) -> Flow {
+        match event {
+            Event::Press(Button::B) | Event::Action(Action::Refresh) => self.load(ctx),
+            Event::Tick if self.loaded_at.is_none_or(|at| ctx.now - at >= self.refresh) => {
+                self.load(ctx)
+            }
+            _ => Flow::Idle,
+        }
+    }
+
+    fn render(&mut self, frame: &mut Frame) {
+        screen::draw(frame, self.notifications, self.configured, &self.repos);
+    }
+
+    fn desired_sleep(&self) -> Option<Duration> {
+        Some(self.refresh)
+    }
+}
@@ -0,0 +1,16 @@
+//! Apps bundled with the firmware.
+
+#[cfg(feature = "badge-game")]
+pub mod badge_game;
+pub mod dashboard;
+pub mod demo;
+pub mod github;
+pub mod mqtt_display;
+pub mod remote_display;
+pub mod safe_mode;
+pub mod selftest;
+pub mod settings;
+pub mod status;
+pub mod steps;
+pub mod tasks;
+pub mod wifi_survey;
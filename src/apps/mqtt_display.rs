@@ -0,0 +1,316 @@
+//! Values pushed over MQTT, each shown in a named slot, so an automation in
+//! Home Assistant or anything else that talks to a broker can put text and
+//! readings on the badge without a firmware change.
+//!
+//! `mqtt_display` names the broker, `mqtt://host[:port]`, logged in to with
+//! `mqtt_user`/`mqtt_pass` if set. `mqtt_slots` lists up to [MAX_SLOTS]
+//! slots separated by `;`, each `name=topic` for text or `name=topic,unit`
+//! for a number shown with its unit:
+//!
+//! ```text
+//! Living room=home/living/temperature,C;Front door=home/door/state
+//! ```
+//!
+//! A slot shows the latest payload on its topic, cut to fit, or `--` until
+//! one arrives and for a number slot whose payload doesn't parse. The value
+//! is also published to the [bindings] as `mqtt.<name>`, for templates to
+//! place. Topics are matched as written; wildcards are refused.
+//!
+//! The app subscribes when it comes to the front and stays connected while
+//! it is there, trying again every [RETRY_INTERVAL] after losing the broker.
+//! Payloads published with the retain flag show up as soon as it subscribes;
+//! others only once they are sent again.
+//!
+//! While connected it also subscribes to `mqtt_press`, if set, and queues
+//! each payload there, such as `A` or `A+D`, as a [remote press](input::push)
+//! for whatever app is in front then.
+
+use alloc::{format, string::String, vec::Vec};
+use core::{fmt::Write as _, str::FromStr};
+
+use esp_hal::{
+    efuse::Efuse,
+    time::{Duration, Instant},
+};
+use log::{info, warn};
+
+use crate::{
+    app::{App, Context, Flow},
+    bindings::{self, Subscriptions},
+    config::{keys, ConfigStore},
+    data_source::Stale,
+    display::Frame,
+    input::{self, Event, Source},
+    mqtt::{Login, Subscriber},
+    net::NetStack,
+    screens::mqtt_display::{self as screen, slot_area, value_area},
+    time, wifi, Error,
+};
+
+/// Slots that fit on the screen, in two columns of three
+pub const MAX_SLOTS: usize = 6;
+/// How often the socket is checked while the app is in front
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// How long to wait before connecting again after the broker went away
+const RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// One `name=topic[,unit]` entry of `mqtt_slots`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Slot {
+    pub name: String,
+    pub topic: String,
+    /// `Some` for a number
+    pub unit: Option<String>,
+}
+
+impl Slot {
+    /// What `payload` shows as, `None` for a number that isn't one
+    fn format(&self, payload: &[u8]) -> Option<String> {
+        let text = core::str::from_utf8(payload).ok()?.trim();
+        let Some(unit) = &self.unit else {
+            return Some(text.into());
+        };
+        let number: f32 = text.parse().ok()?;
+        let mut value = format!("{:.1}", number);
+        if value.ends_with(".0") {
+            value.truncate(value.len() - 2);
+        }
+        if !unit.is_empty() {
+            write!(value, " {}", unit).ok();
+        }
+        Some(value)
+    }
+
+    fn binding(&self) -> String {
+        format!("mqtt.{}", self.name)
+    }
+}
+
+impl FromStr for Slot {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, rest) = s.split_once('=').ok_or(Error::InvalidConfig)?;
+        let (topic, unit) = match rest.split_once(',') {
+            Some((topic, unit)) => (topic, Some(unit.trim().into())),
+            None => (rest, None),
+        };
+        let (name, topic) = (name.trim(), topic.trim());
+        if name.is_empty() || topic.is_empty() || topic.contains(['+', '#']) {
+            return Err(Error::InvalidConfig);
+        }
+        Ok(Self {
+            name: name.into(),
+            topic: topic.into(),
+            unit,
+        })
+    }
+}
+
+/// Parses a whole `mqtt_slots` value
+pub fn parse_slots(s: &str) -> Result<Vec<Slot>, Error> {
+    let slots = s
+        .split(';')
+        .filter(|entry| !entry.trim().is_empty())
+        .map(str::parse)
+        .collect::<Result<Vec<Slot>, _>>()?;
+    if slots.len() > MAX_SLOTS {
+        return Err(Error::InvalidConfig);
+    }
+    Ok(slots)
+}
+
+/// Shows the latest payloads on the configured topics
+pub struct MqttDisplay<'a> {
+    subscriber: Subscriber<'a>,
+    /// `mqtt_display`, `None` while unset
+    broker: Option<String>,
+    slots: Result<Vec<Slot>, Error>,
+    /// `mqtt_press`
+    press_topic: Option<String>,
+    subscriptions: Subscriptions,
+    /// Since when the values on screen haven't been updated, set while the
+    /// broker can't be reached
+    stale: Option<Stale>,
+    attempted_at: Option<Instant>,
+}
+
+impl<'a> MqttDisplay<'a> {
+    /// Subscribes over socket buffers that live as long as the stack
+    pub fn new(stack: &'a NetStack<'a>, rx_buffer: &'a mut [u8], tx_buffer: &'a mut [u8]) -> Self {
+        Self {
+            subscriber: Subscriber::new(stack, rx_buffer, tx_buffer),
+            broker: None,
+            slots: Ok(Vec::new()),
+            press_topic: None,
+            subscriptions: Subscriptions::new(),
+            stale: None,
+            attempted_at: None,
+        }
+    }
+
+    /// Reads the config, reconnecting when it changed
+    fn configure(&mut self, config: &ConfigStore) {
+        let broker = config
+            .get(keys::MQTT_DISPLAY)
+            .filter(|url| !url.is_empty())
+            .map(String::from);
+        let slots = parse_slots(config.get(keys::MQTT_SLOTS).unwrap_or_default());
+        if let Err(err) = &slots {
+            warn!("{}: {}", keys::MQTT_SLOTS, err);
+        }
+        let press_topic = config
+            .get(keys::MQTT_PRESS)
+            .filter(|topic| !topic.is_empty() && !topic.contains(['+', '#']))
+            .map(String::from);
+        if broker == self.broker && slots == self.slots && press_topic == self.press_topic {
+            return;
+        }
+        self.subscriber.disconnect();
+        self.attempted_at = None;
+        self.stale = None;
+        self.subscriptions.clear();
+        for (index, slot) in slots.iter().flatten().enumerate() {
+            self.subscriptions
+                .subscribe(&slot.binding(), value_area(slot_area(index)));
+        }
+        self.broker = broker;
+        self.slots = slots;
+        self.press_topic = press_topic;
+    }
+
+    /// Connects if the app is set up, the network is up and the last attempt
+    /// was long enough ago; true when that changed what the screen says
+    fn connect(&mut self, config: &ConfigStore, now: Instant) -> bool {
+        let (Some(broker), Ok(slots)) = (&self.broker, &self.slots) else {
+            return false;
+        };
+        if slots.is_empty()
+            || self.subscriber.is_connected()
+            || !wifi::is_connected()
+            || self
+                .attempted_at
+                .is_some_and(|at| now - at < RETRY_INTERVAL)
+        {
+            return false;
+        }
+        self.attempted_at = Some(now);
+        let login = config.get(keys::MQTT_USERNAME).map(|username| Login {
+            username,
+            password: config.get(keys::MQTT_PASSWORD).unwrap_or_default(),
+        });
+        let topics: Vec<&str> = slots
+            .iter()
+            .map(|slot| slot.topic.as_str())
+            .chain(self.press_topic.as_deref())
+            .collect();
+        match self
+            .subscriber
+            .connect(broker, &client_id(), login, &topics)
+        {
+            Ok(()) => {
+                info!("MQTT display subscribed on {}", broker);
+                self.stale.take().is_some()
+            }
+            Err(err) => {
+                warn!("MQTT display can't reach {}: {}", broker, err);
+                self.lost(err)
+            }
+        }
+    }
+
+    /// Publishes what has arrived; true when the connection was lost
+    fn poll(&mut self) -> bool {
+        let Ok(slots) = &self.slots else {
+            return false;
+        };
+        if !self.subscriber.is_connected() {
+            return false;
+        }
+        let press_topic = self.press_topic.as_deref();
+        let result = self.subscriber.poll(|topic, payload| {
+            if Some(topic) == press_topic {
+                match core::str::from_utf8(payload).map(|press| press.trim().parse()) {
+                    Ok(Ok(event)) => input::push(event, Source::Remote),
+                    _ => warn!("{}: not a press", topic),
+                }
+            }
+            for slot in slots.iter().filter(|slot| slot.topic == topic) {
+                match slot.format(payload) {
+                    Some(value) => bindings::publish(&slot.binding(), value),
+                    None => bindings::withdraw(&slot.binding()),
+                }
+            }
+        });
+        match result {
+            Ok(()) => false,
+            Err(err) => self.lost(err),
+        }
+    }
+
+    /// Marks the values as stale from now on; true if they weren't already
+    fn lost(&mut self, error: Error) -> bool {
+        if self.stale.is_some() {
+            return false;
+        }
+        self.stale = Some(Stale {
+            since: time::now_utc(),
+            error,
+        });
+        true
+    }
+}
+
+impl App for MqttDisplay<'_> {
+    fn name(&self) -> &'static str {
+        "mqtt display"
+    }
+
+    fn on_enter(&mut self, ctx: &mut Context<'_, '_>) -> Flow {
+        self.configure(ctx.config);
+        // a fresh attempt each time the app comes back
+        self.attempted_at = None;
+        self.connect(ctx.config, ctx.now);
+        Flow::Redraw
+    }
+
+    fn on_event(&mut self, event: Event, ctx: &mut Context<'_, '_>) -> Flow {
+        if event != Event::Tick {
+            return Flow::Idle;
+        }
+        let changed = self.poll() | self.connect(ctx.config, ctx.now);
+        if changed {
+            Flow::Redraw
+        } else {
+            Flow::Idle
+        }
+    }
+
+    fn render(&mut self, frame: &mut Frame) {
+        let slots: Result<Vec<_>, Error> = match (&self.broker, &self.slots) {
+            (_, Err(err)) => Err(*err),
+            (Some(_), Ok(slots)) => Ok(slots
+                .iter()
+                .map(|slot| (slot.name.as_str(), bindings::get(&slot.binding())))
+                .collect()),
+            (None, Ok(_)) => Ok(Vec::new()),
+        };
+        let slots = slots.as_deref().map_err(|err| *err);
+        screen::draw(frame, slots, self.stale.as_ref());
+    }
+
+    fn desired_sleep(&self) -> Option<Duration> {
+        Some(POLL_INTERVAL)
+    }
+
+    fn subscriptions(&mut self) -> Option<&mut Subscriptions> {
+        Some(&mut self.subscriptions)
+    }
+}
+
+/// Apart from the one [crate::telemetry] publishes under, since a broker
+/// drops the older of two connections with the same ID
+fn client_id() -> String {
+    let mac = Efuse::mac_address();
+    format!("magtag-{:02x}{:02x}{:02x}-display", mac[3], mac[4], mac[5])
+}
@@ -0,0 +1,193 @@
+//! Shows frames rendered elsewhere and pushed to the badge over TCP.
+//!
+//! A sender connects to [DEFAULT_PORT] and writes one frame:
+//!
+//! | bytes | content                                       |
+//! |-------|-----------------------------------------------|
+//! | 4     | `MTFB`                                        |
+//! | 1     | encoding: 0 raw, 1 run-length                 |
+//! | 1     | flags: bit 0 signed                           |
+//! | 2     | reserved, zero                                |
+//! | 4     | payload length, little endian                 |
+//! | n     | payload                                       |
+//! | 32    | if signed, HMAC-SHA256 of all of the above    |
+//!
+//! The decoded frame is 296x128 pixels at 2 bits each, rows top to bottom,
+//! four pixels per byte with the leftmost in the high bits; 0 is black and 3
+//! is white. That is [remote_frame::FRAME_LEN] bytes. The run-length encoding is a series of
+//! `(count, byte)` pairs, count 1-255, that expand to exactly those bytes.
+//!
+//! With a `remote_key` config entry only frames signed with that key are
+//! shown, the key being the entry's UTF-8 bytes; others are refused as
+//! `unsigned` or `bad signature`. A signed frame recorded on the network can
+//! still be sent again. Without the entry signatures are not checked.
+//!
+//! The badge answers `ok\n` or `error <reason>\n` and closes the connection.
+//! `tools/magtag_push.py` converts and sends images.
+
+use alloc::{format, string::String, vec::Vec};
+
+use embedded_io::{Read as _, ReadReady as _, Write as _};
+use esp_hal::time::{Duration, Instant};
+use log::{debug, info, warn};
+
+use crate::{
+    app::{App, Context, Flow},
+    config::keys,
+    display::Frame,
+    input::Event,
+    net::{NetStack, TcpSocket},
+    remote_frame::{self, Upload},
+    screens::remote_display as screen,
+};
+
+pub const DEFAULT_PORT: u16 = 7070;
+/// How often the socket is checked while the app is in front
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+/// A sender that hasn't delivered its frame by then is dropped
+const UPLOAD_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Displays the last frame received on its port
+pub struct RemoteDisplay<'a> {
+    socket: TcpSocket<'a, 'a>,
+    port: u16,
+    /// The frame on screen, `None` until one arrives
+    frame: Option<Vec<u8>>,
+    upload: Vec<u8>,
+    started: Option<Instant>,
+    /// Where to send frames, shown until the first one arrives
+    address: String,
+    /// `remote_key`, when frames have to be signed with it
+    key: Option<Vec<u8>>,
+}
+
+impl<'a> RemoteDisplay<'a> {
+    /// Listens on `port` using socket buffers that live as long as the stack
+    pub fn new(
+        stack: &'a NetStack<'a>,
+        port: u16,
+        rx_buffer: &'a mut [u8],
+        tx_buffer: &'a mut [u8],
+    ) -> Self {
+        Self {
+            socket: stack.get_socket(rx_buffer, tx_buffer),
+            port,
+            frame: None,
+            upload: Vec::new(),
+            started: None,
+            address: String::new(),
+            key: None,
+        }
+    }
+
+    /// Receives what's available; true when a new frame is complete
+    fn poll(&mut self) -> bool {
+        if !self.socket.is_open() {
+            self.upload.clear();
+            self.started = None;
+            if let Err(err) = self.socket.listen_unblocking(self.port) {
+                warn!("can't listen on port {}: {:?}", self.port, err);
+            }
+            return false;
+        }
+        if !self.socket.is_connected() {
+            return false;
+        }
+
+        let now = Instant::now();
+        if self
+            .started
+            .is_some_and(|started| now - started > UPLOAD_TIMEOUT)
+        {
+            self.finish(Err("timeout"));
+            return false;
+        }
+        let mut chunk = [0u8; 512];
+        loop {
+            match self.socket.read_ready() {
+                Ok(true) => {}
+                Ok(false) => return false,
+                Err(_) => {
+                    self.socket.disconnect();
+                    return false;
+                }
+            }
+            let Ok(len) = self.socket.read(&mut chunk) else {
+                self.socket.disconnect();
+                return false;
+            };
+            self.started.get_or_insert(now);
+            self.upload.extend_from_slice(&chunk[..len]);
+
+            match remote_frame::parse_upload(&self.upload, self.key.as_deref()) {
+                Upload::Incomplete => {}
+                Upload::Invalid(reason) => {
+                    self.finish(Err(reason));
+                    return false;
+                }
+                Upload::Complete(frame) => {
+                    info!("Received a remote frame");
+                    self.frame = Some(frame);
+                    self.finish(Ok(()));
+                    return true;
+                }
+            }
+        }
+    }
+
+    fn finish(&mut self, result: Result<(), &str>) {
+        let reply = match result {
+            Ok(()) => String::from("ok\n"),
+            Err(reason) => {
+                debug!("rejected a remote frame: {}", reason);
+                format!("error {}\n", reason)
+            }
+        };
+        if self
+            .socket
+            .write_all(reply.as_bytes())
+            .and_then(|()| self.socket.flush())
+            .is_err()
+        {
+            debug!("sender went away before the reply");
+        }
+        self.socket.close();
+        self.upload.clear();
+        self.started = None;
+    }
+}
+
+impl App for RemoteDisplay<'_> {
+    fn name(&self) -> &'static str {
+        "remote display"
+    }
+
+    fn on_enter(&mut self, ctx: &mut Context<'_, '_>) -> Flow {
+        self.key = ctx
+            .config
+            .get(keys::REMOTE_KEY)
+            .filter(|key| !key.is_empty())
+            .map(|key| key.as_bytes().to_vec());
+        self.address = match ctx.net.get_ip_info() {
+            Ok(info) => format!("{}:{}", info.ip, self.port),
+            Err(_) => format!("port {}", self.port),
+        };
+        Flow::Redraw
+    }
+
+    fn on_event(&mut self, event: Event, _ctx: &mut Context<'_, '_>) -> Flow {
+        if event == Event::Tick && self.poll() {
+            Flow::Redraw
+        } else {
+            Flow::Idle
+        }
+    }
+
+    fn render(&mut self, frame: &mut Frame) {
+        screen::draw(frame, self.frame.as_deref(), &self.address);
+    }
+
+    fn desired_sleep(&self) -> Option<Duration> {
+        Some(POLL_INTERVAL)
+    }
+}
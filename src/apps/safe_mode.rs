@@ -0,0 +1,26 @@
+//! Placeholder shown when the badge boots in [crate::boot_mode::BootMode::Safe].
+
+use crate::{
+    app::{App, Context, Flow},
+    display::Frame,
+    input::Event,
+    screens::safe_mode as screen,
+};
+
+/// Says the apps were skipped and the USB console is the way in
+#[derive(Default)]
+pub struct SafeMode;
+
+impl App for SafeMode {
+    fn name(&self) -> &'static str {
+        "safe mode"
+    }
+
+    fn on_event(&mut self, _event: Event, _ctx: &mut Context<'_, '_>) -> Flow {
+        Flow::Idle
+    }
+
+    fn render(&mut self, frame: &mut Frame) {
+        screen::draw(frame);
+    }
+}
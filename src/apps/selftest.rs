@@ -0,0 +1,241 @@
+//! Hardware self-test for newly assembled or returned boards.
+//!
+//! The steps run in order. Sensors and WiFi pass or fail on their own; for
+//! the panel, NeoPixels and speaker the operator judges the result and
+//! answers A (pass) or D (fail), with B to repeat. Every result is logged as
+//! well, so a run can be followed on the serial console. The last screen
+//! lists all results; B starts over.
+
+use alloc::{format, string::String, vec::Vec};
+
+use esp_hal::{
+    delay::Delay,
+    time::{Duration, Instant},
+};
+use log::{info, warn};
+
+use crate::{
+    accel, analog,
+    app::{App, Context, Flow},
+    display::Frame,
+    input::{Button, ButtonSet, Event},
+    neopixel::{self, Rgb},
+    screens::selftest::{self as screen, Outcome, Step},
+    speaker, wifi,
+};
+
+/// How long the operator gets to press all four buttons
+const BUTTON_TIMEOUT: Duration = Duration::from_secs(30);
+/// Pause before a step runs, so its screen is up first
+const STEP_DELAY: Duration = Duration::from_millis(100);
+/// Accepted magnitude of the acceleration at rest, in mg
+const GRAVITY_RANGE: core::ops::RangeInclusive<i32> = 800..=1200;
+/// Accepted battery voltage, in mV
+const BATTERY_RANGE: core::ops::RangeInclusive<u32> = 3000..=4400;
+const NEOPIXEL_COLORS: [Rgb; 4] = [Rgb::RED, Rgb::GREEN, Rgb::BLUE, Rgb::WHITE];
+const NEOPIXEL_BRIGHTNESS: u8 = 30;
+/// C5, E5, G5, C6
+const TONES_HZ: [u32; 4] = [523, 659, 784, 1047];
+
+/// Steps through every peripheral and reports what works
+#[derive(Default)]
+pub struct SelfTest {
+    /// Index into [Step::ALL]; past the end once finished
+    current: usize,
+    /// Whether the current step's stimulus or measurement has run
+    started: bool,
+    /// When the buttons step started waiting
+    since: Option<Instant>,
+    pressed: ButtonSet,
+    results: Vec<Outcome>,
+}
+
+impl SelfTest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn step(&self) -> Option<Step> {
+        Step::ALL.get(self.current).copied()
+    }
+
+    fn restart(&mut self) {
+        info!("Self-test started");
+        *self = Self::default();
+    }
+
+    fn record(&mut self, passed: bool, detail: String) -> Flow {
+        let Some(step) = self.step() else {
+            return Flow::Idle;
+        };
+        if passed {
+            info!("self-test {}: pass {}", step.name(), detail);
+        } else {
+            warn!("self-test {}: FAIL {}", step.name(), detail);
+        }
+        self.results.push(Outcome {
+            step,
+            passed,
+            detail,
+        });
+        self.current += 1;
+        self.started = false;
+        if self.step().is_none() {
+            let failed = self.results.iter().filter(|r| !r.passed).count();
+            info!(
+                "Self-test done, {} of {} passed",
+                self.results.len() - failed,
+                self.results.len()
+            );
+        }
+        Flow::Redraw
+    }
+
+    /// Runs the current step's stimulus, or its measurement if it has no
+    /// operator question
+    fn run(&mut self, step: Step, now: Instant) -> Flow {
+        self.started = true;
+        match step {
+            Step::DisplayBlack | Step::DisplayGrays | Step::DisplayChecker => Flow::Idle,
+            Step::NeoPixels => {
+                show_neopixels();
+                Flow::Idle
+            }
+            Step::Speaker => {
+                for hz in TONES_HZ {
+                    speaker::tone(hz, Duration::from_millis(250));
+                }
+                Flow::Idle
+            }
+            Step::Buttons => {
+                self.since = Some(now);
+                Flow::Idle
+            }
+            Step::Accelerometer => match accel::read() {
+                Some(a) => {
+                    let (x, y, z) = (a.x as i32, a.y as i32, a.z as i32);
+                    let magnitude = (x * x + y * y + z * z).isqrt();
+                    let detail = format!("{} {} {} mg", a.x, a.y, a.z);
+                    self.record(GRAVITY_RANGE.contains(&magnitude), detail)
+                }
+                None => self.record(false, String::from("no sensor")),
+            },
+            Step::Light => match analog::light_millivolts() {
+                Some(mv) => self.record(true, format!("{} mV", mv)),
+                None => self.record(false, String::from("no reading")),
+            },
+            Step::Battery => match analog::battery_millivolts() {
+                Some(mv) => self.record(BATTERY_RANGE.contains(&mv), format!("{} mV", mv)),
+                None => self.record(false, String::from("no reading")),
+            },
+            Step::WifiScan => match wifi::scan() {
+                Ok(networks) => match networks.first() {
+                    Some(best) => self.record(
+                        true,
+                        format!(
+                            "{} seen, {} {} dBm",
+                            networks.len(),
+                            best.ssid,
+                            best.signal_strength
+                        ),
+                    ),
+                    None => self.record(false, String::from("no networks")),
+                },
+                Err(err) => self.record(false, format!("{}", err)),
+            },
+        }
+    }
+
+    fn answer(&mut self, step: Step, button: Button) -> Flow {
+        let flow = match button {
+            Button::A => self.record(true, String::new()),
+            Button::D => self.record(false, String::from("operator")),
+            Button::B => {
+                self.started = false;
+                Flow::Redraw
+            }
+            Button::C => Flow::Idle,
+        };
+        if step == Step::NeoPixels && flow == Flow::Redraw {
+            neopixel::fill(Rgb::OFF);
+            neopixel::show();
+            neopixel::set_policy(neopixel::ambient);
+        }
+        flow
+    }
+}
+
+impl App for SelfTest {
+    fn name(&self) -> &'static str {
+        "self-test"
+    }
+
+    fn on_enter(&mut self, _ctx: &mut Context<'_, '_>) -> Flow {
+        self.restart();
+        Flow::Redraw
+    }
+
+    fn on_event(&mut self, event: Event, ctx: &mut Context<'_, '_>) -> Flow {
+        let Some(step) = self.step() else {
+            if event == Event::Press(Button::B) {
+                self.restart();
+                return Flow::Redraw;
+            }
+            return Flow::Idle;
+        };
+
+        match event {
+            Event::Tick if !self.started => self.run(step, ctx.now),
+            Event::Tick if step == Step::Buttons => self.record(
+                false,
+                format!("only {} pressed in time", self.pressed.len()),
+            ),
+            Event::Press(button) if step == Step::Buttons => {
+                self.pressed = self.pressed.with(button);
+                if self.pressed == ButtonSet::of(&Button::ALL) {
+                    self.record(true, String::new())
+                } else {
+                    Flow::Redraw
+                }
+            }
+            Event::Press(button) if self.started && step.question().is_some() => {
+                self.answer(step, button)
+            }
+            _ => Flow::Idle,
+        }
+    }
+
+    fn render(&mut self, frame: &mut Frame) {
+        match self.step() {
+            Some(step) => screen::draw_step(frame, step, self.pressed),
+            None => screen::draw_summary(frame, &self.results),
+        }
+    }
+
+    fn desired_sleep(&self) -> Option<Duration> {
+        let step = self.step()?;
+        if !self.started {
+            return Some(STEP_DELAY);
+        }
+        match (step, self.since) {
+            (Step::Buttons, Some(since)) => {
+                Some(BUTTON_TIMEOUT.saturating_sub(Instant::now() - since))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Lights the pixels in each test color in turn, ending on the last
+fn show_neopixels() {
+    // a fixed brightness, so a dark room doesn't turn them off
+    neopixel::set_policy(|_, _| NEOPIXEL_BRIGHTNESS);
+    neopixel::adapt(NEOPIXEL_BRIGHTNESS);
+    for (i, color) in NEOPIXEL_COLORS.into_iter().enumerate() {
+        if i > 0 {
+            Delay::new().delay_millis(600);
+        }
+        neopixel::fill(color);
+        neopixel::show();
+    }
+}
@@ -0,0 +1,225 @@
+//! On-device settings screen.
+//!
+//! A selects the next field, or the one before with a long press, B and C
+//! step its value down and up, repeating while held, D saves to the config
+//! store.
+
+use alloc::{format, string::ToString, vec::Vec};
+use log::warn;
+
+use crate::{
+    app::{App, Context, Flow},
+    config::{Settings, Units},
+    display::{Contrast, Frame},
+    i18n::{self, Language},
+    input::{self, Button, ButtonSet, Event},
+    screens::settings::{self as screen, Status},
+    time, tr,
+    tz::Tz,
+};
+
+const REFRESH_CHOICES: [u16; 8] = [5, 10, 15, 30, 60, 120, 240, 480];
+
+/// Timezones offered on the device; any other POSIX TZ string can be stored
+/// under the `tz` key directly
+pub const TIMEZONES: [(&str, &str); 10] = [
+    ("UTC", "UTC0"),
+    ("London", "GMT0BST,M3.5.0/1,M10.5.0"),
+    ("Berlin", "CET-1CEST,M3.5.0,M10.5.0/3"),
+    ("Athens", "EET-2EEST,M3.5.0/3,M10.5.0/4"),
+    ("Kolkata", "IST-5:30"),
+    ("Tokyo", "JST-9"),
+    ("Sydney", "AEST-10AEDT,M10.1.0,M4.1.0/3"),
+    ("New York", "EST5EDT,M3.2.0,M11.1.0"),
+    ("Chicago", "CST6CDT,M3.2.0,M11.1.0"),
+    ("Los Angeles", "PST8PDT,M3.2.0,M11.1.0"),
+];
+
+const BRIGHTNESS_STEP: u8 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Refresh,
+    Timezone,
+    Units,
+    Brightness,
+    Contrast,
+    Language,
+}
+
+impl Field {
+    const ALL: [Field; 6] = [
+        Field::Refresh,
+        Field::Timezone,
+        Field::Units,
+        Field::Brightness,
+        Field::Contrast,
+        Field::Language,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            Field::Refresh => tr!("refresh"),
+            Field::Timezone => tr!("timezone"),
+            Field::Units => tr!("units"),
+            Field::Brightness => tr!("neopixels"),
+            Field::Contrast => tr!("contrast"),
+            Field::Language => tr!("language"),
+        }
+    }
+}
+
+/// Edits [Settings] and writes them to the config store
+pub struct SettingsApp {
+    settings: Settings,
+    selected: usize,
+    status: Status,
+}
+
+impl Default for SettingsApp {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SettingsApp {
+    pub fn new() -> Self {
+        Self {
+            settings: Settings::default(),
+            selected: 0,
+            status: Status::Clean,
+        }
+    }
+
+    fn step(&mut self, up: bool) {
+        let s = &mut self.settings;
+        match Field::ALL[self.selected] {
+            Field::Refresh => {
+                let i = REFRESH_CHOICES
+                    .iter()
+                    .position(|m| *m >= s.refresh_minutes)
+                    .unwrap_or(REFRESH_CHOICES.len() - 1);
+                s.refresh_minutes = REFRESH_CHOICES[step_index(i, REFRESH_CHOICES.len(), up)];
+            }
+            Field::Timezone => {
+                let i = TIMEZONES
+                    .iter()
+                    .position(|(_, tz)| *tz == s.timezone)
+                    .unwrap_or(0);
+                s.timezone = TIMEZONES[step_index(i, TIMEZONES.len(), up)].1.to_string();
+            }
+            Field::Units => {
+                s.units = match s.units {
+                    Units::Celsius => Units::Fahrenheit,
+                    Units::Fahrenheit => Units::Celsius,
+                };
+            }
+            Field::Brightness => {
+                s.neopixel_brightness = if up {
+                    (s.neopixel_brightness + BRIGHTNESS_STEP).min(100)
+                } else {
+                    s.neopixel_brightness.saturating_sub(BRIGHTNESS_STEP)
+                };
+            }
+            Field::Contrast => {
+                let i = Contrast::ALL
+                    .iter()
+                    .position(|c| *c == s.contrast)
+                    .unwrap_or(0);
+                s.contrast = Contrast::ALL[step_index(i, Contrast::ALL.len(), up)];
+            }
+            Field::Language => {
+                let i = Language::ALL
+                    .iter()
+                    .position(|l| *l == s.language)
+                    .unwrap_or(0);
+                s.language = Language::ALL[step_index(i, Language::ALL.len(), up)];
+            }
+        }
+        self.status = Status::Modified;
+    }
+
+    fn save(&mut self, ctx: &mut Context<'_, '_>) {
+        let result = self
+            .settings
+            .store(ctx.config)
+            .and_then(|_| ctx.config.commit());
+        self.status = match result {
+            Ok(()) => {
+                i18n::set_language(self.settings.language);
+                time::set_timezone(self.settings.timezone.parse().unwrap_or_else(|_| Tz::utc()));
+                Status::Saved
+            }
+            Err(err) => {
+                warn!("saving settings failed: {}", err);
+                Status::Failed
+            }
+        };
+    }
+
+    fn value(&self, field: Field) -> alloc::string::String {
+        let s = &self.settings;
+        match field {
+            Field::Refresh => format!("{} {}", s.refresh_minutes, tr!("minutes_short")),
+            Field::Timezone => TIMEZONES
+                .iter()
+                .find(|(_, tz)| *tz == s.timezone)
+                .map(|(name, _)| name.to_string())
+                .unwrap_or_else(|| s.timezone.clone()),
+            Field::Units => match s.units {
+                Units::Celsius => tr!("celsius").to_string(),
+                Units::Fahrenheit => tr!("fahrenheit").to_string(),
+            },
+            Field::Brightness => format!("{}%", s.neopixel_brightness),
+            Field::Contrast => match s.contrast {
+                Contrast::Normal => tr!("contrast_normal").to_string(),
+                Contrast::High => tr!("contrast_high").to_string(),
+                Contrast::Mono => tr!("contrast_mono").to_string(),
+            },
+            Field::Language => s.language.native_name().to_string(),
+        }
+    }
+}
+
+fn step_index(i: usize, len: usize, up: bool) -> usize {
+    if up {
+        (i + 1) % len
+    } else {
+        (i + len - 1) % len
+    }
+}
+
+impl App for SettingsApp {
+    fn name(&self) -> &'static str {
+        "settings"
+    }
+
+    fn on_enter(&mut self, ctx: &mut Context<'_, '_>) -> Flow {
+        self.settings = Settings::load(ctx.config);
+        self.status = Status::Clean;
+        input::set_repeat(ButtonSet::of(&[Button::B, Button::C]));
+        Flow::Redraw
+    }
+
+    fn on_event(&mut self, event: Event, ctx: &mut Context<'_, '_>) -> Flow {
+        match event {
+            Event::Press(Button::A) => self.selected = (self.selected + 1) % Field::ALL.len(),
+            Event::LongPress(Button::A) => {
+                self.selected = (self.selected + Field::ALL.len() - 1) % Field::ALL.len()
+            }
+            Event::Press(Button::B) => self.step(false),
+            Event::Press(Button::C) => self.step(true),
+            Event::Press(Button::D) => self.save(ctx),
+            _ => return Flow::Idle,
+        }
+        Flow::Redraw
+    }
+
+    fn render(&mut self, frame: &mut Frame) {
+        let fields: Vec<_> = Field::ALL
+            .into_iter()
+            .map(|field| (field.label(), self.value(field)))
+            .collect();
+        screen::draw(frame, self.status, &fields, self.selected);
+    }
+}
@@ -0,0 +1,150 @@
+//! Status screen: battery, the estimated draw and run time left, WiFi and
+//! memory. B reads everything again; it also updates on its own every few
+//! minutes and when the network comes or goes.
+
+use alloc::{format, string::String, vec::Vec};
+
+use esp_hal::time::Duration;
+
+use crate::{
+    app::{App, Context, Flow},
+    config::keys,
+    display::Frame,
+    input::{Button, Event},
+    metrics::{self, Snapshot},
+    net_health,
+    power::estimator::{self, Estimate, State},
+    screens::status as screen,
+    tr,
+};
+
+const UPDATE_INTERVAL: Duration = Duration::from_minutes(5);
+
+/// Shows a [Snapshot] and the power [Estimate]
+#[derive(Default)]
+pub struct Status {
+    snapshot: Option<Snapshot>,
+    estimate: Option<Estimate>,
+    capacity_mah: u32,
+}
+
+impl Status {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn update(&mut self, ctx: &Context<'_, '_>) {
+        let snapshot = metrics::snapshot();
+        self.capacity_mah = ctx
+            .config
+            .get_parsed(keys::BATTERY_CAPACITY)
+            .unwrap_or(estimator::DEFAULT_CAPACITY_MAH);
+        self.estimate = estimator::estimate(self.capacity_mah, snapshot.battery_mv);
+        self.snapshot = Some(snapshot);
+    }
+
+    fn lines(&self) -> [String; 6] {
+        let Some(snapshot) = &self.snapshot else {
+            return Default::default();
+        };
+        let battery = match snapshot.battery_mv {
+            Some(mv) => tr!(
+                "status_battery",
+                format!("{}.{:02}", mv / 1000, mv % 1000 / 10),
+                estimator::charge_permille(mv) / 10
+            ),
+            None => String::from(tr!("status_battery_none")),
+        };
+        let (draw, remaining) = match self.estimate {
+            Some(estimate) => (
+                tr!(
+                    "status_draw",
+                    format!(
+                        "{}.{}",
+                        estimate.average_ua / 1000,
+                        estimate.average_ua % 1000 / 100
+                    )
+                ),
+                match estimate.remaining {
+                    Some(left) => tr!("status_left", hours_minutes(left), self.capacity_mah),
+                    None => String::from(tr!("status_left_none")),
+                },
+            ),
+            None => (
+                String::from(tr!("status_draw_none")),
+                String::from(tr!("status_left_none")),
+            ),
+        };
+
+        // share of the time per state, biggest first
+        let totals = estimator::totals();
+        let total: u64 = totals.iter().map(|t| t.as_millis()).sum();
+        let mut states: Vec<(State, u64)> = State::ALL
+            .into_iter()
+            .zip(totals)
+            .map(|(state, time)| (state, time.as_millis() * 100 / total.max(1)))
+            .filter(|(_, percent)| *percent > 0)
+            .collect();
+        states.sort_unstable_by_key(|(_, percent)| core::cmp::Reverse(*percent));
+        let mut split = String::from(tr!("status_time"));
+        for (i, (state, percent)) in states.iter().take(3).enumerate() {
+            let separator = if i == 0 { " " } else { ", " };
+            split += &format!("{}{} {}%", separator, state.name(), percent);
+        }
+
+        let mut wifi = match snapshot.rssi_dbm {
+            Some(rssi) => tr!("status_wifi", rssi),
+            None => String::from(tr!("status_wifi_none")),
+        };
+        match net_health::recoveries() {
+            0 => {}
+            1 => wifi += tr!("status_restarted_once"),
+            n => wifi += &tr!("status_restarted", n),
+        }
+        let system = tr!(
+            "status_up",
+            hours_minutes(snapshot.uptime),
+            snapshot.heap_free / 1024
+        );
+        [battery, draw, remaining, split, wifi, system]
+    }
+}
+
+impl App for Status {
+    fn name(&self) -> &'static str {
+        "status"
+    }
+
+    fn on_enter(&mut self, ctx: &mut Context<'_, '_>) -> Flow {
+        self.update(ctx);
+        Flow::Redraw
+    }
+
+    fn on_event(&mut self, event: Event, ctx: &mut Context<'_, '_>) -> Flow {
+        match event {
+            Event::Tick | Event::Press(Button::B) | Event::Link(_) => {
+                self.update(ctx);
+                Flow::Redraw
+            }
+            _ => Flow::Idle,
+        }
+    }
+
+    fn render(&mut self, frame: &mut Frame) {
+        screen::draw(frame, &self.lines());
+    }
+
+    fn desired_sleep(&self) -> Option<Duration> {
+        Some(UPDATE_INTERVAL)
+    }
+}
+
+/// `3 h 05 min`, or `12 min` under an hour
+fn hours_minutes(duration: Duration) -> String {
+    let minutes = duration.as_minutes();
+    if minutes < 60 {
+        format!("{} min", minutes)
+    } else {
+        format!("{} h {:02} min", minutes / 60, minutes % 60)
+    }
+}
@@ -0,0 +1,112 @@
+//! Step counter: today's steps, counted while this app is in front.
+//!
+//! The accelerometer buffers samples in its FIFO and the app drains it every
+//! second. The panel only refreshes every [REDRAW_INTERVAL] while the count
+//! goes up, as a refresh takes longer than the FIFO lasts; the few samples
+//! lost to it barely change the count.
+
+use esp_hal::time::{Duration, Instant};
+use log::{info, warn};
+
+use crate::{
+    accel,
+    app::{App, Context, Flow},
+    display::Frame,
+    input::{Button, Event},
+    pedometer::{self, Pedometer},
+    screens::steps as screen,
+};
+
+/// Well inside the 1.3 s the FIFO holds
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const REDRAW_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Counts steps into [pedometer]'s daily total and shows it
+pub struct Steps {
+    pedometer: Pedometer,
+    sensor: bool,
+    /// The total on the panel, and when it was drawn
+    shown: u32,
+    drawn_at: Option<Instant>,
+}
+
+impl Steps {
+    pub fn new() -> Self {
+        Self {
+            pedometer: Pedometer::new(accel::FIFO_RATE_HZ),
+            sensor: false,
+            shown: 0,
+            drawn_at: None,
+        }
+    }
+
+    /// Runs the buffered samples through the pedometer
+    fn drain(&mut self) {
+        let samples = match accel::read_fifo() {
+            Ok(samples) => samples,
+            Err(err) => {
+                warn!("reading the accelerometer failed: {}", err);
+                return;
+            }
+        };
+        let steps = samples
+            .into_iter()
+            .filter(|sample| self.pedometer.push(*sample))
+            .count();
+        if steps > 0 {
+            pedometer::add(steps as u32);
+        }
+    }
+
+    fn redraw(&mut self, now: Instant) -> Flow {
+        self.shown = pedometer::today();
+        self.drawn_at = Some(now);
+        Flow::Redraw
+    }
+}
+
+impl Default for Steps {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl App for Steps {
+    fn name(&self) -> &'static str {
+        "steps"
+    }
+
+    fn on_enter(&mut self, ctx: &mut Context<'_, '_>) -> Flow {
+        self.sensor = accel::start_fifo().is_ok();
+        if self.sensor {
+            info!("Counting steps, {} so far today", pedometer::today());
+        }
+        self.redraw(ctx.now)
+    }
+
+    fn on_event(&mut self, event: Event, ctx: &mut Context<'_, '_>) -> Flow {
+        match event {
+            Event::Tick if self.sensor => {
+                self.drain();
+                let due = self
+                    .drawn_at
+                    .is_none_or(|at| ctx.now - at >= REDRAW_INTERVAL);
+                if due && pedometer::today() != self.shown {
+                    self.redraw(ctx.now)
+                } else {
+                    Flow::Idle
+                }
+            }
+            Event::Press(Button::B) => self.redraw(ctx.now),
+            _ => Flow::Idle,
+        }
+    }
+
+    fn render(&mut self, frame: &mut Frame) {
+        screen::draw(frame, self.sensor.then_some(self.shown));
+    }
+
+    fn desired_sleep(&self) -> Option<Duration> {
+        self.sensor.then_some(POLL_INTERVAL)
+    }
+}
@@ -0,0 +1,231 @@
+//! Today's tasks with checkboxes, ticked off from the buttons.
+//!
+//! Tasks come from a [TaskProvider]. The built-in one reads the CalDAV
+//! collection named by `tasks_url` (see [crate::caldav]), logging in with
+//! the HTTP credentials of [Auth::from_config]; others are handed to
+//! [Tasks::with_provider]. The list holds the open tasks due today or
+//! earlier, oldest first, or every open task while the clock isn't set.
+//!
+//! A and C move the selection, B reloads, D marks the selected task done on
+//! the server. A task marked done stays on the list, checked, until the next
+//! reload; the list also reloads every refresh interval from the settings.
+
+use alloc::{boxed::Box, string::String, vec::Vec};
+
+use esp_hal::time::{Duration, Instant};
+use jiff::civil::Date;
+use log::{info, warn};
+
+use crate::{
+    actions::Action,
+    app::{App, Context, Flow},
+    caldav::{self, Todo},
+    config::{keys, ConfigStore, Settings},
+    display::Frame,
+    http::Auth,
+    input::{Button, Event},
+    net::NetStack,
+    screens::tasks as screen,
+    time, tr,
+    ui::toast,
+    Error,
+};
+
+pub use crate::screens::tasks::Task;
+
+/// Where tasks come from and go back to when done
+pub trait TaskProvider {
+    /// The open tasks due by `today`, or all of them if it's `None`
+    fn today(&mut self, net: &NetStack<'_>, today: Option<Date>) -> Result<Vec<Task>, Error>;
+
+    /// Marks the task with `id`, from the last [TaskProvider::today], done
+    fn complete(&mut self, net: &NetStack<'_>, id: &str) -> Result<(), Error>;
+}
+
+/// Tasks from a CalDAV collection
+pub struct CalDavProvider {
+    client: caldav::Client,
+    /// From the last listing, for [caldav::Client::complete]
+    todos: Vec<Todo>,
+}
+
+impl CalDavProvider {
+    pub fn new(client: caldav::Client) -> Self {
+        Self {
+            client,
+            todos: Vec::new(),
+        }
+    }
+}
+
+impl TaskProvider for CalDavProvider {
+    fn today(&mut self, net: &NetStack<'_>, today: Option<Date>) -> Result<Vec<Task>, Error> {
+        self.todos = self.client.todos(net)?;
+        let mut tasks: Vec<Task> = self
+            .todos
+            .iter()
+            .filter(|todo| !todo.completed)
+            .filter(|todo| today.is_none_or(|today| todo.due.is_some_and(|due| due <= today)))
+            .map(|todo| Task {
+                id: todo.href.clone(),
+                title: todo.summary.clone(),
+                due: todo.due,
+                done: false,
+            })
+            .collect();
+        tasks.sort_by_key(|task| task.due);
+        Ok(tasks)
+    }
+
+    fn complete(&mut self, net: &NetStack<'_>, id: &str) -> Result<(), Error> {
+        let todo = self
+            .todos
+            .iter()
+            .find(|todo| todo.href == id)
+            .ok_or(Error::NotFound)?;
+        self.client.complete(net, todo)
+    }
+}
+
+/// The task list
+pub struct Tasks {
+    provider: Option<Box<dyn TaskProvider>>,
+    /// `tasks_url` the provider was made from, `None` for one handed in
+    url: Option<String>,
+    tasks: Option<Result<Vec<Task>, Error>>,
+    selected: usize,
+    loaded_at: Option<Instant>,
+    refresh: Duration,
+}
+
+impl Tasks {
+    /// Tasks from the CalDAV collection in the config
+    pub fn new() -> Self {
+        Self {
+            provider: None,
+            url: None,
+            tasks: None,
+            selected: 0,
+            loaded_at: None,
+            refresh: Settings::default().refresh_interval(),
+        }
+    }
+
+    /// Tasks from `provider` rather than the config
+    pub fn with_provider(provider: impl TaskProvider + 'static) -> Self {
+        Self {
+            provider: Some(Box::new(provider)),
+            ..Self::new()
+        }
+    }
+
+    /// Makes the CalDAV provider again if `tasks_url` changed
+    fn configure(&mut self, config: &ConfigStore) {
+        if self.provider.is_some() && self.url.is_none() {
+            return;
+        }
+        let url = config.get(keys::TASKS_URL).filter(|url| !url.is_empty());
+        if url == self.url.as_deref() && self.provider.is_some() {
+            return;
+        }
+        self.provider = url.map(|url| {
+            let client = caldav::Client::new(url, Auth::from_config(config));
+            Box::new(CalDavProvider::new(client)) as Box<dyn TaskProvider>
+        });
+        self.url = url.map(String::from);
+    }
+
+    fn load(&mut self, ctx: &Context<'_, '_>) -> Flow {
+        self.configure(ctx.config);
+        self.loaded_at = Some(ctx.now);
+        self.refresh = Settings::load(ctx.config).refresh_interval();
+        let Some(provider) = self.provider.as_mut() else {
+            self.tasks = None;
+            return Flow::Redraw;
+        };
+        let today = time::now_local().map(|now| now.date());
+        let tasks = provider.today(ctx.net, today);
+        match &tasks {
+            Ok(tasks) => info!("{} tasks for today", tasks.len()),
+            Err(err) => warn!("Loading tasks failed: {}", err),
+        }
+        self.tasks = Some(tasks);
+        self.selected = 0;
+        Flow::Redraw
+    }
+
+    fn complete_selected(&mut self, ctx: &Context<'_, '_>) -> Flow {
+        let (Some(provider), Some(Ok(tasks))) = (self.provider.as_mut(), self.tasks.as_mut())
+        else {
+            return Flow::Idle;
+        };
+        let Some(task) = tasks.get_mut(self.selected).filter(|task| !task.done) else {
+            return Flow::Idle;
+        };
+        match provider.complete(ctx.net, &task.id) {
+            Ok(()) => {
+                info!("Marked \"{}\" done", task.title);
+                task.done = true;
+                // on to the next one still open
+                if let Some(next) = tasks.iter().position(|task| !task.done) {
+                    self.selected = next;
+                }
+            }
+            Err(err) => {
+                warn!("Marking \"{}\" done failed: {}", task.title, err);
+                toast::show(tr!("task_not_done"));
+            }
+        }
+        Flow::Redraw
+    }
+
+    fn step(&mut self, forward: bool) -> Flow {
+        let len = match &self.tasks {
+            Some(Ok(tasks)) if !tasks.is_empty() => tasks.len(),
+            _ => return Flow::Idle,
+        };
+        self.selected = if forward {
+            (self.selected + 1) % len
+        } else {
+            (self.selected + len - 1) % len
+        };
+        Flow::Redraw
+    }
+}
+
+impl Default for Tasks {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl App for Tasks {
+    fn name(&self) -> &'static str {
+        "tasks"
+    }
+
+    fn on_enter(&mut self, ctx: &mut Context<'_, '_>) -> Flow {
+        self.load(ctx)
+    }
+
+    fn on_event(&mut self, event: Event, ctx: &mut Context<'_, '_>) -> Flow {
+        match event {
+            Event::Press(Button::A) => self.step(false),
+            Event::Press(Button::B) | Event::Action(Action::Refresh) => self.load(ctx),
+            Event::Press(Button::C) => self.step(true),
+            Event::Press(Button::D) => self.complete_selected(ctx),
+            Event::Tick if self.loaded_at.is_none_or(|at| ctx.now - at >= self.refresh) => {
+                self.load(ctx)
+            }
+            _ => Flow::Idle,
+        }
+    }
+
+    fn render(&mut self, frame: &mut Frame) {
+        screen::draw(frame, self.tasks.as_ref(), self.selected);
+    }
+
+    fn desired_sleep(&self) -> Option<Duration> {
+        Some(self.refresh)
+    }
+}
@@ -0,0 +1,196 @@
+//! Pocket WiFi survey: the strongest access points in range with their
+//! channel, signal and security. B scans again.
+//!
+//! A and C pick a network and D joins it, asking for the password on a
+//! [TextEntry] unless it is open, then saves the credentials as provisioning
+//! does. That sets the badge up on a new network with no phone to hand.
+
+use alloc::{string::String, vec::Vec};
+
+use esp_radio::wifi::{AccessPointInfo, AuthMethod};
+use log::{info, warn};
+
+use crate::{
+    app::{App, Context, Flow},
+    display::Frame,
+    input::{Button, Event},
+    screens::wifi_survey::{self as screen, Network},
+    tr,
+    ui::{
+        text_entry::{Outcome, TextEntry},
+        toast,
+    },
+    wifi::{self, Credentials},
+    Error,
+};
+
+pub use crate::screens::wifi_survey::ROWS;
+
+/// The longest WPA2 passphrase
+const MAX_PASSWORD_CHARS: usize = 63;
+
+/// Lists the networks from the last scan
+#[derive(Default)]
+pub struct WifiSurvey {
+    scan: Option<Result<Vec<AccessPointInfo>, Error>>,
+    /// Row of the network D joins
+    selected: usize,
+    /// The network being joined and its password so far
+    joining: Option<(String, TextEntry)>,
+}
+
+impl WifiSurvey {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn rescan(&mut self) {
+        let scan = wifi::scan();
+        match &scan {
+            Ok(networks) => {
+                info!("WiFi survey found {} networks", networks.len());
+                for ap in networks {
+                    info!(
+                        "  {} ch {} {} dBm {}",
+                        ap.ssid,
+                        ap.channel,
+                        ap.signal_strength,
+                        security(ap.auth_method)
+                    );
+                }
+            }
+            Err(err) => info!("WiFi survey failed: {}", err),
+        }
+        self.scan = Some(scan);
+        self.selected = 0;
+    }
+
+    fn networks(&self) -> &[AccessPointInfo] {
+        match &self.scan {
+            Some(Ok(networks)) => &networks[..networks.len().min(ROWS)],
+            _ => &[],
+        }
+    }
+
+    /// Joins the selected network, or asks for its password first
+    fn join_selected(&mut self, ctx: &mut Context<'_, '_>) {
+        let Some(ap) = self.networks().get(self.selected) else {
+            return;
+        };
+        if ap.ssid.is_empty() {
+            toast::show(tr!("hidden_needs_setup"));
+            return;
+        }
+        let ssid = ap.ssid.as_str().into();
+        if matches!(ap.auth_method, Some(AuthMethod::None)) {
+            join(
+                Credentials {
+                    ssid,
+                    password: String::new(),
+                },
+                ctx,
+            );
+            return;
+        }
+        let entry = TextEntry::new(&tr!("password_for", ssid))
+            .with_max_len(MAX_PASSWORD_CHARS)
+            .masked();
+        self.joining = Some((ssid, entry));
+    }
+}
+
+/// Joins with `credentials` and saves them if that worked
+fn join(credentials: Credentials, ctx: &mut Context<'_, '_>) {
+    info!("Joining {} from the survey", credentials.ssid);
+    if let Err(err) = wifi::join(&credentials, ctx.net) {
+        warn!("Joining {} failed: {}", credentials.ssid, err);
+        toast::show(tr!("join_failed"));
+        return;
+    }
+    match credentials
+        .store(ctx.config)
+        .and_then(|_| ctx.config.commit())
+    {
+        Ok(()) => toast::show(tr!("joined_saved")),
+        Err(err) => {
+            warn!("Saving credentials failed: {}", err);
+            toast::show(tr!("joined_not_saved"));
+        }
+    }
+}
+
+impl App for WifiSurvey {
+    fn name(&self) -> &'static str {
+        "wifi survey"
+    }
+
+    fn on_enter(&mut self, _ctx: &mut Context<'_, '_>) -> Flow {
+        self.rescan();
+        Flow::Redraw
+    }
+
+    fn on_event(&mut self, event: Event, ctx: &mut Context<'_, '_>) -> Flow {
+        if let Some((ssid, entry)) = &mut self.joining {
+            match entry.handle(event) {
+                Outcome::Editing(flow) => return flow,
+                Outcome::Done(password) => {
+                    let ssid = core::mem::take(ssid);
+                    join(Credentials { ssid, password }, ctx);
+                }
+                Outcome::Cancelled => {}
+            }
+            self.joining = None;
+            return Flow::Redraw;
+        }
+        let count = self.networks().len();
+        match event {
+            Event::Press(Button::A) if count > 0 => {
+                self.selected = (self.selected + count - 1) % count
+            }
+            Event::Press(Button::C) if count > 0 => self.selected = (self.selected + 1) % count,
+            Event::Press(Button::B) => self.rescan(),
+            Event::Press(Button::D) => self.join_selected(ctx),
+            _ => return Flow::Idle,
+        }
+        Flow::Redraw
+    }
+
+    fn render(&mut self, frame: &mut Frame) {
+        if let Some((_, entry)) = &self.joining {
+            entry.render(frame);
+            return;
+        }
+        let networks: Vec<Network> = match &self.scan {
+            Some(Ok(networks)) => networks
+                .iter()
+                .map(|ap| Network {
+                    ssid: ap.ssid.as_str(),
+                    channel: ap.channel,
+                    rssi: ap.signal_strength,
+                    security: security(ap.auth_method),
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        let scan = self.scan.as_ref().map(|scan| match scan {
+            Ok(_) => Ok(networks.as_slice()),
+            Err(err) => Err(*err),
+        });
+        screen::draw(frame, scan, self.selected);
+    }
+}
+
+fn security(auth: Option<AuthMethod>) -> &'static str {
+    match auth {
+        None => "?",
+        Some(AuthMethod::None) => "open",
+        Some(AuthMethod::Wep) => "WEP",
+        Some(AuthMethod::Wpa) => "WPA",
+        Some(AuthMethod::Wpa2Personal) => "WPA2",
+        Some(AuthMethod::WpaWpa2Personal) => "WPA/WPA2",
+        Some(AuthMethod::Wpa2Enterprise) => "WPA2-Ent",
+        Some(AuthMethod::Wpa3Personal) => "WPA3",
+        Some(AuthMethod::Wpa2Wpa3Personal) => "WPA2/WPA3",
+        Some(_) => "other",
+    }
+}
@@ -0,0 +1,300 @@
+//! Named files (images, fonts, config drops) in the `assets` data partition.
+//!
+//! The first sector holds a directory; each file takes a run of whole sectors
+//! after it. There are no subdirectories and a file is written once, start to
+//! finish, with [AssetStore::create]. Rewriting a name writes the new copy
+//! before dropping the old one, so an interrupted upload leaves the previous
+//! file in place.
+
+use alloc::{string::String, vec::Vec};
+
+use esp_bootloader_esp_idf::partitions::DataPartitionSubType;
+use log::{info, warn};
+
+use crate::{
+    crc::{crc32, crc32_update},
+    flash, psram, Error,
+};
+
+/// Longest file name, in bytes
+pub const MAX_NAME_LEN: usize = 20;
+
+const MAGIC: u32 = u32::from_le_bytes(*b"MTAS");
+const VERSION: u16 = 1;
+const HEADER_LEN: usize = 16;
+const ENTRY_LEN: usize = 32;
+const SECTOR_SIZE: u32 = flash::SECTOR_SIZE;
+const MAX_ENTRIES: usize = (SECTOR_SIZE as usize - HEADER_LEN) / ENTRY_LEN;
+
+/// A stored file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Asset {
+    pub name: String,
+    pub len: u32,
+    pub crc: u32,
+    first_sector: u16,
+    sectors: u16,
+}
+
+impl Asset {
+    fn encode(&self, out: &mut [u8]) {
+        out[..MAX_NAME_LEN].fill(0);
+        out[..self.name.len()].copy_from_slice(self.name.as_bytes());
+        out[20..22].copy_from_slice(&self.first_sector.to_le_bytes());
+        out[22..24].copy_from_slice(&self.sectors.to_le_bytes());
+        out[24..28].copy_from_slice(&self.len.to_le_bytes());
+        out[28..32].copy_from_slice(&self.crc.to_le_bytes());
+    }
+
+    fn decode(raw: &[u8]) -> Option<Self> {
+        let name_len = raw[..MAX_NAME_LEN]
+            .iter()
+            .position(|b| *b == 0)
+            .unwrap_or(MAX_NAME_LEN);
+        let name = core::str::from_utf8(&raw[..name_len]).ok()?;
+        Some(Self {
+            name: name.into(),
+            first_sector: u16::from_le_bytes([raw[20], raw[21]]),
+            sectors: u16::from_le_bytes([raw[22], raw[23]]),
+            len: u32::from_le_bytes(raw[24..28].try_into().unwrap()),
+            crc: u32::from_le_bytes(raw[28..32].try_into().unwrap()),
+        })
+    }
+}
+
+/// The files in the `assets` partition
+pub struct AssetStore {
+    region: flash::Region,
+    assets: Vec<Asset>,
+}
+
+impl AssetStore {
+    /// Locates the partition and reads the directory, formatting a blank partition
+    pub fn open() -> Result<Self, Error> {
+        let region = flash::find_partition(DataPartitionSubType::Spiffs)?;
+        let mut store = Self {
+            region,
+            assets: Vec::new(),
+        };
+
+        let mut directory = alloc::vec![0u8; SECTOR_SIZE as usize];
+        flash::read(region.offset, &mut directory)?;
+        let magic = u32::from_le_bytes(directory[0..4].try_into().unwrap());
+        let version = u16::from_le_bytes([directory[4], directory[5]]);
+        let count = u16::from_le_bytes([directory[6], directory[7]]) as usize;
+        if magic != MAGIC || version != VERSION || count > MAX_ENTRIES {
+            info!("No asset directory, formatting");
+            store.format()?;
+            return Ok(store);
+        }
+
+        store.assets = directory[HEADER_LEN..]
+            .chunks_exact(ENTRY_LEN)
+            .take(count)
+            .filter_map(Asset::decode)
+            .collect();
+        info!("{} assets stored", store.assets.len());
+        Ok(store)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Asset> {
+        self.assets.iter()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Asset> {
+        self.assets.iter().find(|a| a.name == name)
+    }
+
+    /// Total space for file data, in bytes
+    pub fn capacity(&self) -> u32 {
+        self.data_sectors() as u32 * SECTOR_SIZE
+    }
+
+    /// Space left, in bytes
+    pub fn free(&self) -> u32 {
+        let used: u32 = self.assets.iter().map(|a| a.sectors as u32).sum();
+        self.capacity() - used * SECTOR_SIZE
+    }
+
+    /// Reads from `name` at `offset`, returning how many bytes were read
+    pub fn read(&self, name: &str, offset: u32, buf: &mut [u8]) -> Result<usize, Error> {
+        let asset = self.get(name).ok_or(Error::NotFound)?;
+        let len = (asset.len.saturating_sub(offset) as usize).min(buf.len());
+        flash::read(
+            self.sector_offset(asset.first_sector) + offset,
+            &mut buf[..len],
+        )?;
+        Ok(len)
+    }
+
+    /// Reads a whole file and checks its CRC
+    pub fn read_to_vec(&self, name: &str) -> Result<Vec<u8>, Error> {
+        let asset = self.get(name).ok_or(Error::NotFound)?;
+        let mut data = psram::zeroed(asset.len as usize);
+        self.read(name, 0, &mut data)?;
+        if crc32(&data) != asset.crc {
+            warn!("asset {} is corrupt", name);
+            return Err(Error::Storage);
+        }
+        Ok(data)
+    }
+
+    /// Starts writing a file of exactly `len` bytes, replacing any file called `name`
+    pub fn create(&mut self, name: &str, len: u32) -> Result<AssetWriter<'_>, Error> {
+        if name.is_empty() || name.len() > MAX_NAME_LEN || name.contains('\0') {
+            return Err(Error::InvalidConfig);
+        }
+        let replaces_existing = self.get(name).is_some();
+        if !replaces_existing && self.assets.len() >= MAX_ENTRIES {
+            return Err(Error::StorageFull);
+        }
+
+        let sectors = len.div_ceil(SECTOR_SIZE).max(1) as u16;
+        let first_sector = self.find_free(sectors).ok_or(Error::StorageFull)?;
+        let start = self.sector_offset(first_sector);
+        flash::erase(start, start + sectors as u32 * SECTOR_SIZE)?;
+
+        Ok(AssetWriter {
+            asset: Asset {
+                name: name.into(),
+                len,
+                crc: 0,
+                first_sector,
+                sectors,
+            },
+            store: self,
+            written: 0,
+            pending: [0; flash::WORD_SIZE as usize],
+            pending_len: 0,
+        })
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<(), Error> {
+        let before = self.assets.len();
+        self.assets.retain(|a| a.name != name);
+        if self.assets.len() == before {
+            return Err(Error::NotFound);
+        }
+        self.write_directory()
+    }
+
+    /// Deletes every file
+    pub fn format(&mut self) -> Result<(), Error> {
+        self.assets.clear();
+        self.write_directory()
+    }
+
+    fn data_sectors(&self) -> u16 {
+        (self.region.len / SECTOR_SIZE).saturating_sub(1) as u16
+    }
+
+    fn sector_offset(&self, sector: u16) -> u32 {
+        self.region.offset + sector as u32 * SECTOR_SIZE
+    }
+
+    /// First gap of `sectors` unused sectors after the directory
+    fn find_free(&self, sectors: u16) -> Option<u16> {
+        let mut used: Vec<(u16, u16)> = self
+            .assets
+            .iter()
+            .map(|a| (a.first_sector, a.first_sector + a.sectors))
+            .collect();
+        used.sort_unstable();
+
+        let mut candidate = 1;
+        for (start, end) in used {
+            if start >= candidate + sectors {
+                break;
+            }
+            candidate = candidate.max(end);
+        }
+        (candidate + sectors <= self.data_sectors() + 1).then_some(candidate)
+    }
+
+    fn write_directory(&mut self) -> Result<(), Error> {
+        let mut directory = alloc::vec![0u8; HEADER_LEN + self.assets.len() * ENTRY_LEN];
+        directory[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+        directory[4..6].copy_from_slice(&VERSION.to_le_bytes());
+        directory[6..8].copy_from_slice(&(self.assets.len() as u16).to_le_bytes());
+        for (asset, out) in self
+            .assets
+            .iter()
+            .zip(directory[HEADER_LEN..].chunks_exact_mut(ENTRY_LEN))
+        {
+            asset.encode(out);
+        }
+
+        let start = self.region.offset;
+        flash::erase(start, start + SECTOR_SIZE)?;
+        flash::write(start, &directory)
+    }
+}
+
+/// An upload in progress; nothing is visible until [AssetWriter::finish]
+pub struct AssetWriter<'s> {
+    store: &'s mut AssetStore,
+    asset: Asset,
+    written: u32,
+    /// Bytes waiting for a full flash word
+    pending: [u8; flash::WORD_SIZE as usize],
+    pending_len: usize,
+}
+
+impl AssetWriter<'_> {
+    pub fn write(&mut self, mut data: &[u8]) -> Result<(), Error> {
+        if self.written + (self.pending_len + data.len()) as u32 > self.asset.len {
+            return Err(Error::InvalidConfig);
+        }
+        self.asset.crc = crc32_update(self.asset.crc, data);
+
+        if self.pending_len > 0 {
+            let take = (self.pending.len() - self.pending_len).min(data.len());
+            self.pending[self.pending_len..][..take].copy_from_slice(&data[..take]);
+            self.pending_len += take;
+            data = &data[take..];
+            if self.pending_len < self.pending.len() {
+                return Ok(());
+            }
+            self.flush_pending()?;
+        }
+
+        let aligned = data.len() - data.len() % self.pending.len();
+        if aligned > 0 {
+            let offset = self.store.sector_offset(self.asset.first_sector) + self.written;
+            flash::write(offset, &data[..aligned])?;
+            self.written += aligned as u32;
+        }
+        let rest = &data[aligned..];
+        self.pending[..rest.len()].copy_from_slice(rest);
+        self.pending_len = rest.len();
+        Ok(())
+    }
+
+    /// Checks the upload against `expected_crc` and adds the file to the directory
+    pub fn finish(mut self, expected_crc: u32) -> Result<(), Error> {
+        if self.pending_len > 0 {
+            self.pending[self.pending_len..].fill(0xff);
+            self.flush_pending()?;
+        }
+        if self.written < self.asset.len {
+            return Err(Error::InvalidConfig);
+        }
+        if self.asset.crc != expected_crc {
+            warn!("upload of {} failed its CRC check", self.asset.name);
+            return Err(Error::Storage);
+        }
+
+        let store = self.store;
+        store.assets.retain(|a| a.name != self.asset.name);
+        store.assets.push(self.asset);
+        store.write_directory()
+    }
+
+    fn flush_pending(&mut self) -> Result<(), Error> {
+        let offset = self.store.sector_offset(self.asset.first_sector) + self.written;
+        flash::write(offset, &self.pending)?;
+        self.written += self.pending_len as u32;
+        self.pending_len = 0;
+        Ok(())
+    }
+}
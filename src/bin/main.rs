@@ -1,64 +1,162 @@
 #![no_std]
 #![no_main]
 
+extern crate alloc;
+
+use alloc::{boxed::Box, vec::Vec};
 use blocking_network_stack::Stack;
-use core::net::Ipv4Addr;
-use embedded_graphics::{
-    pixelcolor::Gray2,
-    prelude::*,
-    primitives::{Primitive, PrimitiveStyle, Rectangle},
-};
-use embedded_hal_bus::spi::ExclusiveDevice;
-use embedded_io::{Read as _, Write as _};
-use esp_backtrace as _;
 use esp_hal::{
-    delay::Delay,
-    gpio::{Input, InputConfig, Level, Output, OutputConfig},
-    main, ram,
+    clock::CpuClock,
+    gpio::{Input, InputConfig, Level, Output, OutputConfig, Pull},
+    main,
+    otg_fs::Usb,
+    ram,
     rng::Rng,
+    rtc_cntl::Rtc,
     spi::{self, master::Spi},
-    time::{self, Duration, Rate},
+    time::{Duration, Instant},
     timer::timg::TimerGroup,
 };
-use esp_println::logger::init_logger;
-use esp_radio::wifi::{ClientConfig, ModeConfig, ScanConfig};
-use log::info;
+use esp_storage::FlashStorage;
+use log::{info, warn};
+#[cfg(feature = "badge-game")]
+use magtag_esp_hal_epd::apps::badge_game::BadgeGame;
+#[cfg(feature = "sdcard")]
+use magtag_esp_hal_epd::sdcard;
+#[cfg(feature = "magtag-2.9")]
+use magtag_esp_hal_epd::{accel, analog, i2c, speaker};
+use magtag_esp_hal_epd::{
+    alerts::Alerts,
+    app::AppHost,
+    apps::{
+        dashboard::Dashboard,
+        demo::Demo,
+        github::GitHub,
+        mqtt_display::MqttDisplay,
+        remote_display::{self, RemoteDisplay},
+        safe_mode::SafeMode,
+        selftest::SelfTest,
+        settings::SettingsApp,
+        status::Status,
+        steps::Steps,
+        tasks::Tasks,
+        wifi_survey::WifiSurvey,
+    },
+    assets::AssetStore,
+    board, board_pins,
+    boot_mode::{self, BootMode},
+    config::{keys, ConfigStore, Settings},
+    console::{Console, UsbSerial},
+    crash,
+    datalog::DataLog,
+    display::{self, BusyPin, Display},
+    encoder, expansion, file_drop, flash, fleet,
+    home_assistant::HomeAssistant,
+    hooks,
+    http_server::{self, HttpServer},
+    i18n,
+    input::{self, Buttons},
+    lifecycle::{self, Stage, Startup},
+    logging, metrics,
+    mqtt::Subscriber,
+    neopixel, partitions,
+    power::{
+        self,
+        estimator::{self, State},
+    },
+    sntp::SntpBuffers,
+    system,
+    telemetry::Telemetry,
+    time,
+    tsdb::Tsdb,
+    tz::Tz,
+    wifi::{self, Credentials},
+};
 use smoltcp::{
     iface::{SocketSet, SocketStorage},
-    wire::{DhcpOption, IpAddress},
+    wire::DhcpOption,
 };
-use ssd1680::displays::adafruit_thinkink_2in9::{Display2in9Gray2, ThinkInk2in9Gray2};
-use ssd1680::prelude::*;
 
 esp_bootloader_esp_idf::esp_app_desc!();
 
-const SSID: &str = env!("SSID");
-const PASSWORD: &str = env!("PASSWORD");
-
 #[main]
 fn main() -> ! {
-    // Initialize logger for esp-println
-    init_logger(log::LevelFilter::Info);
+    // fast-boot holds back the boot chatter, which costs time on the
+    // serial port, until the apps run
+    #[cfg(not(feature = "fast-boot"))]
+    logging::init(log::LevelFilter::Info);
+    #[cfg(feature = "fast-boot")]
+    logging::init(log::LevelFilter::Warn);
 
     info!("Initialize peripherals");
-    // Setup CPU clock and watchdog, returns the peripherals
-    let peripherals = esp_hal::init(esp_hal::Config::default());
+    // Setup CPU clock and watchdog, returns the peripherals. Boot runs at
+    // full speed, the app host at the configured profile.
+    let peripherals = esp_hal::init(esp_hal::Config::default().with_cpu_clock(CpuClock::max()));
 
     esp_alloc::heap_allocator!(#[ram(reclaimed)] size: 64 * 1024);
     esp_alloc::heap_allocator!(size: 36 * 1024);
+    #[cfg(feature = "psram")]
+    magtag_esp_hal_epd::psram::init(peripherals.PSRAM);
+
+    flash::init(FlashStorage::new(peripherals.FLASH));
+    partitions::check();
+    let crashed = crash::init().map(|report| report.kind);
+    lifecycle::enter(Stage::Boot);
+    #[cfg(feature = "encrypted-secrets")]
+    magtag_esp_hal_epd::secrets::init(peripherals.HMAC);
+    let mut config = ConfigStore::load().unwrap_or_else(|err| {
+        info!("Config store unavailable ({}), settings won't persist", err);
+        ConfigStore::in_memory()
+    });
+    fleet::check_trial(&mut config, crashed);
+    i18n::set_language(Settings::load(&config).language);
+    let pins = board_pins!(peripherals);
+    info!("Board {}", board::NAME);
+    #[cfg(feature = "magtag-2.9")]
+    {
+        analog::init(peripherals.ADC1, pins.battery, pins.light);
+        power::init(pins.neopixel_power, pins.speaker_enable);
+    }
+    neopixel::init(peripherals.RMT, pins.neopixel);
+    #[cfg(all(feature = "magtag-2.9", not(feature = "audio-pcm")))]
+    speaker::init(pins.speaker);
+    #[cfg(all(feature = "magtag-2.9", feature = "audio-pcm"))]
+    speaker::init(pins.speaker, peripherals.DAC1);
+    #[cfg(feature = "magtag-2.9")]
+    {
+        i2c::init(peripherals.I2C0, pins.sda, pins.scl);
+        accel::init(pins.accel_interrupt);
+        power::wake_on(config.get_parsed(keys::MOTION_WAKE).unwrap_or_default());
+    }
+    power::quiet_hours(config.get_parsed(keys::QUIET_HOURS));
+
+    // The clock survives deep sleep, so it is usable before WiFi is up
+    time::init(Rtc::new(peripherals.LPWR), Tz::from_config(&config));
+    match time::now_local() {
+        Some(now) => info!("Local time {}", now),
+        None => info!("Clock not set yet, waiting for SNTP"),
+    }
+    estimator::enter(State::Awake);
 
     let timg0 = TimerGroup::new(peripherals.TIMG0);
     esp_rtos::start(timg0.timer0);
 
-    let esp_radio_ctrl = esp_radio::init().unwrap();
+    // the WiFi controller lives in a static, so the radio has to as well
+    let esp_radio_ctrl = Box::leak(Box::new(esp_radio::init().unwrap()));
 
     let (mut controller, interfaces) =
-        esp_radio::wifi::new(&esp_radio_ctrl, peripherals.WIFI, Default::default()).unwrap();
+        esp_radio::wifi::new(esp_radio_ctrl, peripherals.WIFI, Default::default()).unwrap();
 
+    #[cfg(feature = "badge-game")]
+    let esp_now = interfaces.esp_now;
     let mut device = interfaces.sta;
     let iface = create_interface(&mut device);
 
-    let mut socket_set_entries: [SocketStorage; 3] = Default::default();
+    // held for as long as the badge is up: DHCP, DNS, the HTTP server, the
+    // remote display and the MQTT subscribers of the MQTT display and the
+    // alerts; then room for three short-lived HTTP, MQTT or SNTP sockets at
+    // once, a full set panicking
+    let mut socket_set_entries: [SocketStorage; 6 + 3] = Default::default();
     let mut socket_set = SocketSet::new(&mut socket_set_entries[..]);
     let mut dhcp_socket = smoltcp::socket::dhcpv4::Socket::new();
     // we can set a hostname here (or add other DHCP options)
@@ -67,170 +165,222 @@ fn main() -> ! {
         data: b"esp-radio",
     }]);
     socket_set.add(dhcp_socket);
+    // DHCP fills in the servers once we have a lease
+    let mut dns_queries: [Option<smoltcp::socket::dns::DnsQuery>; 1] = Default::default();
+    socket_set.add(smoltcp::socket::dns::Socket::new(&[], &mut dns_queries[..]));
 
     let rng = Rng::new();
-    let now = || time::Instant::now().duration_since_epoch().as_millis();
+    let now = || Instant::now().duration_since_epoch().as_millis();
     let stack = Stack::new(iface, device, socket_set, now, rng.random());
 
-    controller
-        .set_power_saving(esp_radio::wifi::PowerSaveMode::None)
-        .unwrap();
+    wifi::init(controller);
+    // the radio starts with the first join and stays awake through boot
+    estimator::enter(State::Wifi);
 
-    let client_config = ModeConfig::Client(
-        ClientConfig::default()
-            .with_ssid(SSID.into())
-            .with_password(PASSWORD.into()),
-    );
-    let res = controller.set_config(&client_config);
-    info!("wifi_set_configuration returned {:?}", res);
+    // saved credentials win over the ones baked in at build time
+    let credentials = Credentials::load(&config).or_else(build_credentials);
+    // the radio associates while the panel and SD card start up
+    #[cfg(feature = "fast-boot")]
+    let joining = credentials.as_ref().is_some_and(|credentials| {
+        wifi::begin_join(credentials)
+            .inspect_err(|err| warn!("WiFi unavailable: {}", err))
+            .is_ok()
+    });
 
-    controller.start().unwrap();
-    info!("is wifi started: {:?}", controller.is_started());
+    // SPI display driver setup
+    let panel_pins: board::BoardPins = config.get_parsed(keys::PANEL_PINS).unwrap_or_default();
+    info!("Panel on {}", panel_pins);
+    // SAFETY: taken once, checked when parsed, and not among `pins`
+    let panel = unsafe { panel_pins.take() };
+    let spi = Spi::new(
+        peripherals.SPI2,
+        spi::master::Config::default().with_frequency(display::SPI_RATE),
+    )
+    .unwrap()
+    .with_sck(panel.sclk)
+    .with_miso(panel.miso)
+    .with_mosi(panel.mosi);
+    let panel_bus = board::share_spi(spi);
+    let busy = BusyPin::new(Input::new(panel.busy, InputConfig::default()));
+    let rst = Output::new(panel.rst, Level::Low, OutputConfig::default());
+    let dc = Output::new(panel.dc, Level::High, OutputConfig::default());
+    let spi_device = board::spi_device(panel_bus, panel.cs);
 
-    info!("Start Wifi Scan");
-    let scan_config = ScanConfig::default().with_max(10);
-    let res = controller.scan_with_config(scan_config).unwrap();
-    for ap in res {
-        info!("{:?}", ap);
+    // Create display with SPI interface
+    let epd = board::Driver::new(spi_device, busy, dc, rst).unwrap();
+    let mut display = Display::new(epd);
+    if let Some(secs) = config.get_parsed(keys::DISPLAY_TIMEOUT) {
+        display.set_busy_timeout(Duration::from_secs(secs));
     }
 
-    info!("{:?}", controller.capabilities());
-    info!("wifi_connect {:?}", controller.connect());
-
-    // wait to get connected
-    info!("Wait to get connected");
-    loop {
-        match controller.is_connected() {
-            Ok(true) => break,
-            Ok(false) => {}
-            Err(err) => {
-                info!("{:?}", err);
-                loop {}
-            }
-        }
+    // Initialize the display; a panel that doesn't answer is tried again on
+    // each flush
+    if let Err(err) = display.begin() {
+        warn!("Panel didn't start: {}", err);
     }
-    info!("{:?}", controller.is_connected());
 
-    // wait for getting an ip address
-    info!("Wait to get an ip address");
-    loop {
-        stack.work();
-
-        if stack.is_iface_up() {
-            info!("got ip {:?}", stack.get_ip_info());
-            break;
+    #[cfg_attr(not(feature = "sdcard"), allow(unused_mut))]
+    let mut claimed = Vec::from(panel_pins.gpios());
+    #[cfg(feature = "sdcard")]
+    match config.get_parsed::<board::SdPins>(keys::SD_PINS) {
+        Some(sd_pins) if sd_pins.check(&panel_pins).is_ok() => {
+            claimed.extend(sd_pins.gpios());
+            // SAFETY: taken once, and clear of `pins` and the panel's
+            let bus = unsafe { sd_pins.take() };
+            if let Err(err) = sdcard::init(bus, peripherals.SPI3, panel_bus) {
+                warn!("SD card unavailable: {}", err);
+            }
         }
+        // the check logged why
+        Some(_) => {}
+        None => info!("No usable {} entry, SD card off", keys::SD_PINS),
     }
-
-    info!("Start busy loop on main");
-
-    let mut rx_buffer = [0u8; 1536];
-    let mut tx_buffer = [0u8; 1536];
-    let mut socket = stack.get_socket(&mut rx_buffer, &mut tx_buffer);
-
-    info!("Making HTTP request");
-    socket.work();
-
-    socket
-        .open(IpAddress::Ipv4(Ipv4Addr::new(142, 250, 185, 115)), 80)
-        .unwrap();
-
-    socket
-        .write(b"GET / HTTP/1.0\r\nHost: www.mobile-j.de\r\n\r\n")
-        .unwrap();
-    socket.flush().unwrap();
-
-    let deadline = time::Instant::now() + Duration::from_secs(20);
-    let mut buffer = [0u8; 512];
-    while let Ok(len) = socket.read(&mut buffer) {
-        let to_print = unsafe { core::str::from_utf8_unchecked(&buffer[..len]) };
-        info!("{}", to_print);
-
-        if time::Instant::now() > deadline {
-            info!("Timeout");
-            break;
+    expansion::init(&claimed);
+
+    // Front buttons A-D, active low
+    let button_config = InputConfig::default().with_pull(Pull::Up);
+    let (a, b, c, d) = pins.buttons;
+    let mut buttons = Buttons::new([
+        Input::new(a, button_config),
+        Input::new(b, button_config),
+        Input::new(c, button_config),
+        Input::new(d, button_config),
+    ]);
+    buttons.set_timing(input::Timing::from_config(&config));
+    encoder::attach(&config, &mut buttons);
+    #[cfg(feature = "magtag-2.9")]
+    if let Some(button) = config.get_parsed(keys::TAP_BUTTON) {
+        if accel::detect_taps().is_ok() {
+            buttons.set_tap_button(button);
         }
     }
+    if let Some(token) = config
+        .get(keys::PRESS_TOKEN)
+        .filter(|token| !token.is_empty())
+    {
+        input::allow_remote(token);
+    }
 
-    socket.disconnect();
+    // USB D+ is GPIO20, D- is GPIO19
+    let usb = Usb::new(peripherals.USB0, peripherals.GPIO20, peripherals.GPIO19);
+    let mut serial = UsbSerial::new(usb);
+    let mode = boot_mode::detect(&buttons, &mut display);
+    match mode {
+        BootMode::FileDrop => match AssetStore::open() {
+            Ok(store) => file_drop::run(serial, store, &mut display),
+            Err(err) => warn!("Asset partition unavailable ({}), booting normally", err),
+        },
+        BootMode::FactoryReset => system::factory_reset(&mut display),
+        _ => {}
+    }
 
-    // SPI display driver setup
-    let sclk = peripherals.GPIO36;
-    let mosi = peripherals.GPIO35;
-    let miso = peripherals.GPIO37;
-    let spi = Spi::new(
-        peripherals.SPI2,
-        spi::master::Config::default().with_frequency(Rate::from_mhz(4)),
-    )
-    .unwrap()
-    .with_sck(sclk)
-    .with_miso(miso)
-    .with_mosi(mosi);
-    let busy = Input::new(peripherals.GPIO5, InputConfig::default());
-    let rst = Output::new(peripherals.GPIO6, Level::Low, OutputConfig::default());
-    let dc = Output::new(peripherals.GPIO7, Level::High, OutputConfig::default());
-    let cs = Output::new(peripherals.GPIO8, Level::High, OutputConfig::default());
-    let spi_device = ExclusiveDevice::new(spi, cs, Delay::new()).unwrap();
+    #[cfg(not(feature = "fast-boot"))]
+    let joining = false;
+    let mut sntp_buffers = SntpBuffers::new();
+    lifecycle::bring_up(Startup {
+        net: &stack,
+        display: &mut display,
+        serial: &mut serial,
+        config: &mut config,
+        credentials,
+        mode,
+        joining,
+        sntp: &mut sntp_buffers,
+    });
+
+    #[cfg(feature = "fast-boot")]
+    logging::set_level(log::LevelFilter::Info);
+    power::set_cpu_profile(config.get_parsed(keys::CPU_PROFILE).unwrap_or_default());
+    wifi::set_power_save(config.get_parsed(keys::WIFI_POWER_SAVE).unwrap_or_default());
+    info!("Start app host");
+    hooks::add(lifecycle::FetchWatchdog);
+    let telemetry = Telemetry::from_config(&config);
+    let home_assistant = HomeAssistant::from_config(&config);
+    let datalog = DataLog::from_config(&config);
+    let tsdb = Tsdb::from_config(&config);
+    let alerts = Alerts::from_config(
+        &config,
+        Subscriber::new(
+            &stack,
+            Box::leak(Box::new([0u8; 1024])),
+            Box::leak(Box::new([0u8; 256])),
+        ),
+    );
+    let mut host = AppHost::new(display, buttons, &stack, config);
+    host.set_console(Console::new(serial));
+    if mode == BootMode::Safe {
+        host.install(SafeMode);
+        host.run()
+    }
+    host.install(Demo);
+    host.install(SettingsApp::new());
+    host.install(Status::new());
+    host.install(Dashboard::new());
+    host.install(Steps::new());
+    host.install(Tasks::new());
+    host.install(GitHub::new());
+    host.install(WifiSurvey::new());
+    #[cfg(feature = "badge-game")]
+    host.install(BadgeGame::new(esp_now));
+    host.install(SelfTest::new());
+    host.install(RemoteDisplay::new(
+        &stack,
+        remote_display::DEFAULT_PORT,
+        Box::leak(Box::new([0u8; 2048])),
+        Box::leak(Box::new([0u8; 64])),
+    ));
+    host.install(MqttDisplay::new(
+        &stack,
+        Box::leak(Box::new([0u8; 1024])),
+        Box::leak(Box::new([0u8; 256])),
+    ));
+    if let Some(telemetry) = telemetry {
+        host.set_telemetry(telemetry);
+    }
+    if let Some(home_assistant) = home_assistant {
+        host.set_home_assistant(home_assistant);
+    }
+    if let Some(alerts) = alerts {
+        host.set_alerts(alerts);
+    }
+    if let Some(datalog) = datalog {
+        host.set_datalog(datalog);
+    }
+    if let Some(tsdb) = tsdb {
+        host.set_tsdb(tsdb);
+    }
+    let mut server = HttpServer::new(
+        &stack,
+        http_server::DEFAULT_PORT,
+        Box::leak(Box::new([0u8; 1024])),
+        Box::leak(Box::new([0u8; 2048])),
+    );
+    server.route("/metrics", metrics::serve_prometheus);
+    server.route("/press", input::serve_press);
+    host.set_server(server);
+    host.run()
+}
 
-    // Create display with SPI interface
-    let mut epd = ThinkInk2in9Gray2::new(spi_device, busy, dc, rst).unwrap();
-    let mut display_gray = Display2in9Gray2::new();
+/// Credentials from the `SSID` and `PASSWORD` build environment
+#[cfg(not(feature = "factory"))]
+fn build_credentials() -> Option<Credentials> {
+    Some(Credentials {
+        ssid: option_env!("SSID")?.into(),
+        password: option_env!("PASSWORD").unwrap_or_default().into(),
+    })
+}
 
-    // Initialize the display
-    epd.begin(&mut Delay::new()).unwrap();
+/// Factory images never carry credentials
+#[cfg(feature = "factory")]
+fn build_credentials() -> Option<Credentials> {
+    None
+}
 
-    info!("Draw some black text");
-    let character_style = embedded_graphics::mono_font::MonoTextStyle::new(
-        &embedded_graphics::mono_font::ascii::FONT_7X14_BOLD,
-        Gray2::BLACK,
-    );
-    embedded_graphics::text::Text::new(
-        "Hello from Gray2 Rust!",
-        Point::new(10, 15),
-        character_style,
-    )
-    .draw(&mut display_gray)
-    .unwrap();
-
-    info!("Draw a light gray cube");
-    Rectangle::new(Point::new(50, 50), Size::new(25, 25))
-        .into_styled(PrimitiveStyle::with_fill(Gray2::new(0x01)))
-        .draw(&mut display_gray)
-        .unwrap();
-
-    info!("Draw dark gray bitmap");
-    // Create an ImageRaw from raw bytes (1bpp) and draw it; adjust the width to match the bitmap width
-    let raw = embedded_graphics::image::ImageRaw::<embedded_graphics::pixelcolor::BinaryColor>::new(
-        &include_bytes!("../../assets/ferris.bin")[..],
-        100,
-    );
-    embedded_graphics::image::Image::new(&raw, Point::new(100, 20))
-        .draw(&mut display_gray.as_binary_draw_target())
-        .unwrap();
-
-    info!("Draw a black line");
-    let line = embedded_graphics::primitives::Line::new(Point::new(200, 20), Point::new(240, 107));
-    line.into_styled(PrimitiveStyle::with_stroke(Gray2::BLACK, 2))
-        .draw(&mut display_gray)
-        .unwrap();
-
-    info!("Display frame");
-    // Transfer and display the buffer on the display
-    epd.update_gray2_and_display(
-        display_gray.high_buffer(),
-        display_gray.low_buffer(),
-        &mut Delay::new(),
-    )
-    .unwrap();
-
-    // Done
-    info!("Done");
-    loop {
-        let deadline = time::Instant::now() + Duration::from_secs(5);
-        while time::Instant::now() < deadline {
-            socket.work();
-        }
-    }
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    crash::record_panic(info);
+    // a deployed badge is better off restarting than sitting halted
+    esp_hal::system::software_reset()
 }
 
 // some smoltcp boilerplate
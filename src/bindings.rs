@@ -0,0 +1,139 @@
+//! Named values passed from the code that reads them to the widgets that
+//! show them.
+//!
+//! Data sources [publish] under dotted names such as `battery.mv` or
+//! `weather.temp` whenever they have a new reading, without knowing who
+//! shows it. A widget [Subscriptions::subscribe]s a name together with the
+//! area it draws the value in; the host asks the front app's subscriptions
+//! for [Subscriptions::dirty] areas on every pass and redraws when a value
+//! it shows has changed, and render code reads the current value with [get].
+//!
+//! The host publishes `battery.mv`, `battery.pct` and `wifi.link` (`up` or
+//! `down`), the pedometer `steps`.
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{cell::RefCell, fmt::Display};
+
+use critical_section::Mutex;
+use embedded_graphics::primitives::Rectangle;
+
+struct Entry {
+    name: String,
+    /// `None` once withdrawn
+    value: Option<String>,
+    /// [Store::generation] when the value last changed
+    changed: u32,
+}
+
+struct Store {
+    entries: Vec<Entry>,
+    generation: u32,
+}
+
+static STORE: Mutex<RefCell<Store>> = Mutex::new(RefCell::new(Store {
+    entries: Vec::new(),
+    generation: 0,
+}));
+
+/// Sets `name` to `value`; subscribers only see a change if it differs from
+/// the last one
+pub fn publish(name: &str, value: impl Display) {
+    set(name, Some(value.to_string()));
+}
+
+/// Withdraws `name`, e.g. when a reading fails, so it shows as missing
+pub fn withdraw(name: &str) {
+    set(name, None);
+}
+
+/// The current value of `name`
+pub fn get(name: &str) -> Option<String> {
+    critical_section::with(|cs| {
+        let store = STORE.borrow_ref(cs);
+        store
+            .entries
+            .iter()
+            .find(|entry| entry.name == name)
+            .and_then(|entry| entry.value.clone())
+    })
+}
+
+fn set(name: &str, value: Option<String>) {
+    critical_section::with(|cs| {
+        let mut store = STORE.borrow_ref_mut(cs);
+        let generation = store.generation + 1;
+        match store.entries.iter_mut().find(|entry| entry.name == name) {
+            Some(entry) if entry.value == value => return,
+            Some(entry) => {
+                entry.value = value;
+                entry.changed = generation;
+            }
+            None if value.is_none() => return,
+            None => store.entries.push(Entry {
+                name: name.to_string(),
+                value,
+                changed: generation,
+            }),
+        }
+        store.generation = generation;
+    });
+}
+
+/// The values a screen shows and where
+#[derive(Default)]
+pub struct Subscriptions {
+    bindings: Vec<(String, Rectangle)>,
+    /// The generation checked last
+    seen: u32,
+}
+
+impl Subscriptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Shows `name` in `area`
+    pub fn subscribe(&mut self, name: &str, area: Rectangle) {
+        self.bindings.push((name.to_string(), area));
+    }
+
+    /// Drops every subscription, e.g. before a new layout subscribes its own
+    pub fn clear(&mut self) {
+        self.bindings.clear();
+    }
+
+    /// The smallest area covering every subscribed value that changed since
+    /// the last call, or `None` if none did
+    pub fn dirty(&mut self) -> Option<Rectangle> {
+        critical_section::with(|cs| {
+            let store = STORE.borrow_ref(cs);
+            if store.generation == self.seen {
+                return None;
+            }
+            let seen = core::mem::replace(&mut self.seen, store.generation);
+            self.bindings
+                .iter()
+                .filter(|(name, _)| {
+                    store
+                        .entries
+                        .iter()
+                        .any(|entry| entry.name == *name && entry.changed > seen)
+                })
+                .map(|(_, area)| *area)
+                .reduce(|dirty, area| envelope(&dirty, &area))
+        })
+    }
+}
+
+fn envelope(a: &Rectangle, b: &Rectangle) -> Rectangle {
+    let (Some(a_end), Some(b_end)) = (a.bottom_right(), b.bottom_right()) else {
+        return if a.is_zero_sized() { *b } else { *a };
+    };
+    Rectangle::with_corners(
+        a.top_left.component_min(b.top_left),
+        a_end.component_max(b_end),
+    )
+}
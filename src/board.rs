@@ -0,0 +1,624 @@
+//! What differs between the boards the firmware runs on: the panel, which
+//! pins the panel, buttons and NeoPixels are on, and which of the MagTag's
+//! other parts are fitted. Exactly one board feature picks them:
+//!
+//! - `magtag-2.9` (the default): the Adafruit MagTag with its 2.9" grayscale
+//!   panel, four buttons, four NeoPixels, speaker, light sensor, battery
+//!   divider and accelerometer.
+//! - `ssd1680-generic`: an ESP32-S2 or S3 dev board such as the DevKitC-1
+//!   with a 2.9" SSD1680 panel and four buttons wired as in [Pins], and the
+//!   board's one RGB LED, on GPIO18 of the S2 and GPIO48 of the S3. There is
+//!   no speaker, accelerometer, battery or light sensor, so those modules
+//!   stay uninitialized and report nothing.
+//!
+//! Another SSD1680 board, such as an eInk Feather, gets a module here with
+//! its [Pins], [PANEL] and a `board_pins!` arm, and a feature in
+//! `Cargo.toml`. `main` takes the pins out of the peripherals with
+//! [board_pins!].
+//!
+//! The panel's pins are the exception: they are [BoardPins], looked up by
+//! number at boot so a reworked or breadboarded panel can be moved with a
+//! `panel_pins` config entry such as `cs=10,dc=11` instead of a rebuild.
+//!
+//! The spare GPIOs on the board's pads are its [Pad]s, which
+//! [crate::expansion] hands out to code added to the firmware.
+//!
+//! The panel's SPI bus is a [SpiBus] that other devices, such as an SD card
+//! on the breakout pins, can join with their own CS pin through
+//! [spi_device].
+//!
+//! [board_pins!]: crate::board_pins
+
+#[cfg(not(any(feature = "magtag-2.9", feature = "ssd1680-generic")))]
+compile_error!("select a board with the `magtag-2.9` or `ssd1680-generic` feature");
+#[cfg(all(feature = "magtag-2.9", feature = "ssd1680-generic"))]
+compile_error!("select only one of the `magtag-2.9` and `ssd1680-generic` features");
+
+use alloc::boxed::Box;
+#[cfg(feature = "sdcard")]
+use alloc::vec::Vec;
+use core::{cell::RefCell, fmt, str::FromStr};
+
+use critical_section::Mutex;
+use embedded_hal_bus::spi::CriticalSectionDevice;
+use esp_hal::{
+    delay::Delay,
+    gpio::{AnyPin, Level, Output, OutputConfig},
+    spi::master::Spi,
+    Blocking,
+};
+use log::warn;
+
+use crate::{chip, Error};
+
+#[cfg(feature = "magtag-2.9")]
+pub use magtag::*;
+#[cfg(all(feature = "ssd1680-generic", not(feature = "magtag-2.9")))]
+pub use ssd1680_generic::*;
+
+/// The 2.9" 296x128 four-gray panel, as on the MagTag
+pub mod panel_2in9 {
+    pub use ssd1680::displays::adafruit_thinkink_2in9::{
+        Display2in9Gray2 as Framebuffer, ThinkInk2in9Gray2 as Driver,
+    };
+
+    pub const WIDTH: u32 = 296;
+    pub const HEIGHT: u32 = 128;
+}
+
+/// An SPI bus that devices take turns on, each in a critical section
+pub type SpiBus = Mutex<RefCell<Spi<'static, Blocking>>>;
+
+/// One device on a [SpiBus], selected by its own CS pin
+pub type SpiDevice = CriticalSectionDevice<'static, Spi<'static, Blocking>, Output<'static>, Delay>;
+
+/// Makes `spi` a [SpiBus] for the rest of the run
+pub fn share_spi(spi: Spi<'static, Blocking>) -> &'static SpiBus {
+    Box::leak(Box::new(Mutex::new(RefCell::new(spi))))
+}
+
+/// A device on `bus` selected by driving `cs` low
+pub fn spi_device(bus: &'static SpiBus, cs: AnyPin<'static>) -> SpiDevice {
+    let cs = Output::new(cs, Level::High, OutputConfig::default());
+    match CriticalSectionDevice::new(bus, cs, Delay::new()) {
+        Ok(device) => device,
+        Err(never) => match never {},
+    }
+}
+
+/// Where the panel is wired, by GPIO number
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BoardPins {
+    pub sclk: u8,
+    pub mosi: u8,
+    pub miso: u8,
+    pub cs: u8,
+    pub dc: u8,
+    pub rst: u8,
+    /// Input; every other pin but `miso` is an output
+    pub busy: u8,
+}
+
+/// The panel's pins, taken with [BoardPins::take]
+pub struct PanelPins {
+    pub sclk: AnyPin<'static>,
+    pub mosi: AnyPin<'static>,
+    pub miso: AnyPin<'static>,
+    pub cs: AnyPin<'static>,
+    pub dc: AnyPin<'static>,
+    pub rst: AnyPin<'static>,
+    pub busy: AnyPin<'static>,
+}
+
+impl BoardPins {
+    /// The board's wiring, [PANEL]
+    pub const fn new() -> Self {
+        PANEL
+    }
+
+    fn named(&self) -> [(&'static str, u8); 7] {
+        [
+            ("sclk", self.sclk),
+            ("mosi", self.mosi),
+            ("miso", self.miso),
+            ("cs", self.cs),
+            ("dc", self.dc),
+            ("rst", self.rst),
+            ("busy", self.busy),
+        ]
+    }
+
+    fn pin_mut(&mut self, name: &str) -> Option<&mut u8> {
+        Some(match name {
+            "sclk" => &mut self.sclk,
+            "mosi" => &mut self.mosi,
+            "miso" => &mut self.miso,
+            "cs" => &mut self.cs,
+            "dc" => &mut self.dc,
+            "rst" => &mut self.rst,
+            "busy" => &mut self.busy,
+            _ => return None,
+        })
+    }
+
+    /// Every pin's GPIO number
+    pub fn gpios(&self) -> [u8; 7] {
+        self.named().map(|(_, pin)| pin)
+    }
+
+    /// Whether every pin is a GPIO of the [chip] that can do its job, none
+    /// is used twice and none is in [IN_USE] or wired to the flash or PSRAM
+    pub fn check(&self) -> Result<(), Error> {
+        check_pins("Panel", &self.named(), &["miso", "busy"], IN_USE)
+    }
+
+    /// Takes the pins from the GPIO peripherals
+    ///
+    /// # Safety
+    ///
+    /// Call once, with pins that passed [BoardPins::check], so no other
+    /// driver owns them: [Pins] doesn't have the panel's.
+    pub unsafe fn take(self) -> PanelPins {
+        // SAFETY: up to the caller, see above
+        let pin = |n| unsafe { AnyPin::steal(n) };
+        PanelPins {
+            sclk: pin(self.sclk),
+            mosi: pin(self.mosi),
+            miso: pin(self.miso),
+            cs: pin(self.cs),
+            dc: pin(self.dc),
+            rst: pin(self.rst),
+            busy: pin(self.busy),
+        }
+    }
+}
+
+impl Default for BoardPins {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FromStr for BoardPins {
+    type Err = Error;
+
+    /// `name=gpio` pairs separated by commas, such as `cs=10,dc=11`; pins
+    /// left out keep the board's wiring
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut pins = Self::new();
+        for pair in pin_pairs(s) {
+            let (name, pin) = pair?;
+            *pins.pin_mut(name).ok_or(Error::InvalidConfig)? = pin;
+        }
+        pins.check()?;
+        Ok(pins)
+    }
+}
+
+impl fmt::Display for BoardPins {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (name, pin)) in self.named().iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{}={}", name, pin)?;
+        }
+        Ok(())
+    }
+}
+
+/// Where an SD card is wired, by GPIO number. No board has one, so the
+/// `sd_pins` config entry names either all four pins, such as
+/// `sclk=39,mosi=40,miso=41,cs=42`, for a bus of its own, or just `cs`, such
+/// as `cs=42`, to share the panel's.
+#[cfg(feature = "sdcard")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SdPins {
+    /// The card's own bus, or `None` for the panel's
+    pub spi: Option<SpiPins>,
+    pub cs: u8,
+}
+
+/// The clock and data pins of an SPI bus
+#[cfg(feature = "sdcard")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpiPins {
+    pub sclk: u8,
+    pub mosi: u8,
+    /// Input; the others are outputs
+    pub miso: u8,
+}
+
+/// The card's pins, taken with [SdPins::take]
+#[cfg(feature = "sdcard")]
+pub struct SdBus {
+    /// `sclk`, `mosi` and `miso` of its own bus, if it has one
+    pub spi: Option<(AnyPin<'static>, AnyPin<'static>, AnyPin<'static>)>,
+    pub cs: AnyPin<'static>,
+}
+
+#[cfg(feature = "sdcard")]
+impl SdPins {
+    fn named(&self) -> Vec<(&'static str, u8)> {
+        let mut named = Vec::new();
+        if let Some(spi) = self.spi {
+            named.extend([("sclk", spi.sclk), ("mosi", spi.mosi), ("miso", spi.miso)]);
+        }
+        named.push(("cs", self.cs));
+        named
+    }
+
+    /// Every pin's GPIO number
+    pub fn gpios(&self) -> Vec<u8> {
+        self.named().into_iter().map(|(_, pin)| pin).collect()
+    }
+
+    /// [BoardPins::check] for the card, which can't have the `panel`'s
+    /// pins either
+    pub fn check(&self, panel: &BoardPins) -> Result<(), Error> {
+        let taken: Vec<u8> = IN_USE.iter().copied().chain(panel.gpios()).collect();
+        check_pins("SD card", &self.named(), &["miso"], &taken)
+    }
+
+    /// Takes the pins from the GPIO peripherals
+    ///
+    /// # Safety
+    ///
+    /// As [BoardPins::take], with pins that passed [SdPins::check].
+    pub unsafe fn take(self) -> SdBus {
+        // SAFETY: up to the caller, see above
+        let pin = |n| unsafe { AnyPin::steal(n) };
+        SdBus {
+            spi: self
+                .spi
+                .map(|spi| (pin(spi.sclk), pin(spi.mosi), pin(spi.miso))),
+            cs: pin(self.cs),
+        }
+    }
+}
+
+#[cfg(feature = "sdcard")]
+impl FromStr for SdPins {
+    type Err = Error;
+
+    /// `name=gpio` pairs separated by commas, all four of them or just `cs`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (mut sclk, mut mosi, mut miso, mut cs) = (None, None, None, None);
+        for pair in pin_pairs(s) {
+            let (name, pin) = pair?;
+            *match name {
+                "sclk" => &mut sclk,
+                "mosi" => &mut mosi,
+                "miso" => &mut miso,
+                "cs" => &mut cs,
+                _ => return Err(Error::InvalidConfig),
+            } = Some(pin);
+        }
+        let spi = match (sclk, mosi, miso) {
+            (Some(sclk), Some(mosi), Some(miso)) => Some(SpiPins { sclk, mosi, miso }),
+            (None, None, None) => None,
+            _ => {
+                warn!(
+                    "SD card pins need all of sclk, mosi and miso or none: {}",
+                    s
+                );
+                return Err(Error::InvalidConfig);
+            }
+        };
+        let Some(cs) = cs else {
+            warn!("SD card pins need cs: {}", s);
+            return Err(Error::InvalidConfig);
+        };
+        Ok(Self { spi, cs })
+    }
+}
+
+impl FromStr for Pad {
+    type Err = Error;
+
+    /// The name on the board, such as `A1`, in any case
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        Pad::ALL
+            .into_iter()
+            .find(|pad| pad.name().eq_ignore_ascii_case(s))
+            .ok_or(Error::InvalidConfig)
+    }
+}
+
+/// The `name=gpio` pairs of a pin config entry
+fn pin_pairs(s: &str) -> impl Iterator<Item = Result<(&str, u8), Error>> {
+    s.split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, pin) = pair.split_once('=').ok_or(Error::InvalidConfig)?;
+            let pin = pin.trim().parse().map_err(|_| Error::InvalidConfig)?;
+            Ok((name.trim(), pin))
+        })
+}
+
+/// Checks `pins` of the `what` against the [chip], `taken` and each other;
+/// only the pins called one of `inputs` may be input only
+fn check_pins(what: &str, pins: &[(&str, u8)], inputs: &[&str], taken: &[u8]) -> Result<(), Error> {
+    for (i, &(name, pin)) in pins.iter().enumerate() {
+        let problem = if !chip::is_gpio(pin) {
+            "no such GPIO"
+        } else if chip::is_memory_pin(pin) {
+            "wired to the flash or PSRAM"
+        } else if chip::is_input_only(pin) && !inputs.contains(&name) {
+            "input only"
+        } else if taken.contains(&pin) {
+            "already in use"
+        } else if pins[..i].iter().any(|&(_, other)| other == pin) {
+            "used twice"
+        } else {
+            continue;
+        };
+        warn!("{} pin {}={}: {}", what, name, pin, problem);
+        return Err(Error::InvalidConfig);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "magtag-2.9")]
+mod magtag {
+    use esp_hal::peripherals::*;
+
+    pub use super::panel_2in9::*;
+    use super::BoardPins;
+
+    pub const NAME: &str = "MagTag 2.9\"";
+    pub const NEOPIXEL_COUNT: usize = 4;
+
+    pub type NeoPixelData = GPIO1<'static>;
+
+    pub const PANEL: BoardPins = BoardPins {
+        sclk: 36,
+        mosi: 35,
+        miso: 37,
+        cs: 8,
+        dc: 7,
+        rst: 6,
+        busy: 5,
+    };
+
+    /// Every other pin the firmware drives, which the panel can't have:
+    /// [Pins], and USB on 19 and 20
+    pub const IN_USE: &[u8] = &[15, 14, 12, 11, 1, 21, 16, 17, 4, 3, 33, 34, 9, 19, 20];
+
+    /// The three-pin STEMMA ports on the back, named as on the silkscreen
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Pad {
+        /// GPIO18, which is also DAC2 and ADC2 channel 7
+        A1,
+        /// GPIO10
+        D10,
+    }
+
+    impl Pad {
+        pub const ALL: [Pad; 2] = [Pad::A1, Pad::D10];
+
+        pub const fn gpio(self) -> u8 {
+            match self {
+                Pad::A1 => 18,
+                Pad::D10 => 10,
+            }
+        }
+
+        pub const fn name(self) -> &'static str {
+            match self {
+                Pad::A1 => "A1",
+                Pad::D10 => "D10",
+            }
+        }
+    }
+
+    /// The pins `main` hands to the drivers besides the panel's
+    pub struct Pins {
+        /// A to D, left to right, active low
+        pub buttons: (
+            GPIO15<'static>,
+            GPIO14<'static>,
+            GPIO12<'static>,
+            GPIO11<'static>,
+        ),
+        pub neopixel: NeoPixelData,
+        /// Low powers the pixels
+        pub neopixel_power: GPIO21<'static>,
+        /// High enables the amplifier
+        pub speaker_enable: GPIO16<'static>,
+        pub speaker: GPIO17<'static>,
+        pub battery: GPIO4<'static>,
+        pub light: GPIO3<'static>,
+        pub sda: GPIO33<'static>,
+        pub scl: GPIO34<'static>,
+        pub accel_interrupt: GPIO9<'static>,
+    }
+
+    /// Moves the board's [Pins](crate::board::Pins) out of `peripherals`
+    #[macro_export]
+    macro_rules! board_pins {
+        ($p:ident) => {
+            $crate::board::Pins {
+                buttons: ($p.GPIO15, $p.GPIO14, $p.GPIO12, $p.GPIO11),
+                neopixel: $p.GPIO1,
+                neopixel_power: $p.GPIO21,
+                speaker_enable: $p.GPIO16,
+                speaker: $p.GPIO17,
+                battery: $p.GPIO4,
+                light: $p.GPIO3,
+                sda: $p.GPIO33,
+                scl: $p.GPIO34,
+                accel_interrupt: $p.GPIO9,
+            }
+        };
+    }
+}
+
+#[cfg(all(feature = "ssd1680-generic", not(feature = "magtag-2.9")))]
+mod ssd1680_generic {
+    use esp_hal::peripherals::*;
+
+    pub use super::panel_2in9::*;
+    use super::BoardPins;
+
+    pub const NAME: &str = "SSD1680 2.9\"";
+    pub const NEOPIXEL_COUNT: usize = 1;
+
+    #[cfg(feature = "esp32s2")]
+    pub type NeoPixelData = GPIO18<'static>;
+    #[cfg(not(feature = "esp32s2"))]
+    pub type NeoPixelData = GPIO48<'static>;
+
+    /// The default SPI2 pins, and the control lines on the GPIOs next to
+    /// the buttons
+    #[cfg(feature = "esp32s2")]
+    pub const PANEL: BoardPins = BoardPins {
+        sclk: 36,
+        mosi: 35,
+        miso: 37,
+        cs: 10,
+        dc: 11,
+        rst: 12,
+        busy: 13,
+    };
+    /// The default SPI2 pins, and the control lines next to them; the S2's
+    /// belong to the octal PSRAM on many S3 modules
+    #[cfg(not(feature = "esp32s2"))]
+    pub const PANEL: BoardPins = BoardPins {
+        sclk: 12,
+        mosi: 11,
+        miso: 13,
+        cs: 10,
+        dc: 14,
+        rst: 15,
+        busy: 16,
+    };
+
+    /// Every other pin the firmware drives, which the panel can't have:
+    /// [Pins], and USB on 19 and 20
+    #[cfg(feature = "esp32s2")]
+    pub const IN_USE: &[u8] = &[4, 5, 6, 7, 18, 19, 20];
+    #[cfg(not(feature = "esp32s2"))]
+    pub const IN_USE: &[u8] = &[4, 5, 6, 7, 48, 19, 20];
+
+    /// Header pins clear of [PANEL], [IN_USE], the strapping pins and, on
+    /// the S3, the JTAG and octal PSRAM pins, named by GPIO
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Pad {
+        Io1,
+        Io2,
+        Io8,
+        Io9,
+        Io17,
+        Io21,
+        #[cfg(feature = "esp32s2")]
+        Io14,
+        #[cfg(feature = "esp32s2")]
+        Io15,
+        #[cfg(feature = "esp32s2")]
+        Io16,
+        #[cfg(not(feature = "esp32s2"))]
+        Io18,
+    }
+
+    impl Pad {
+        #[cfg(feature = "esp32s2")]
+        pub const ALL: [Pad; 9] = [
+            Pad::Io1,
+            Pad::Io2,
+            Pad::Io8,
+            Pad::Io9,
+            Pad::Io14,
+            Pad::Io15,
+            Pad::Io16,
+            Pad::Io17,
+            Pad::Io21,
+        ];
+        #[cfg(not(feature = "esp32s2"))]
+        pub const ALL: [Pad; 7] = [
+            Pad::Io1,
+            Pad::Io2,
+            Pad::Io8,
+            Pad::Io9,
+            Pad::Io17,
+            Pad::Io18,
+            Pad::Io21,
+        ];
+
+        pub const fn gpio(self) -> u8 {
+            match self {
+                Pad::Io1 => 1,
+                Pad::Io2 => 2,
+                Pad::Io8 => 8,
+                Pad::Io9 => 9,
+                Pad::Io17 => 17,
+                Pad::Io21 => 21,
+                #[cfg(feature = "esp32s2")]
+                Pad::Io14 => 14,
+                #[cfg(feature = "esp32s2")]
+                Pad::Io15 => 15,
+                #[cfg(feature = "esp32s2")]
+                Pad::Io16 => 16,
+                #[cfg(not(feature = "esp32s2"))]
+                Pad::Io18 => 18,
+            }
+        }
+
+        pub const fn name(self) -> &'static str {
+            match self {
+                Pad::Io1 => "IO1",
+                Pad::Io2 => "IO2",
+                Pad::Io8 => "IO8",
+                Pad::Io9 => "IO9",
+                Pad::Io17 => "IO17",
+                Pad::Io21 => "IO21",
+                #[cfg(feature = "esp32s2")]
+                Pad::Io14 => "IO14",
+                #[cfg(feature = "esp32s2")]
+                Pad::Io15 => "IO15",
+                #[cfg(feature = "esp32s2")]
+                Pad::Io16 => "IO16",
+                #[cfg(not(feature = "esp32s2"))]
+                Pad::Io18 => "IO18",
+            }
+        }
+    }
+
+    /// The pins `main` hands to the drivers besides the panel's
+    pub struct Pins {
+        /// A to D, to ground when pressed
+        pub buttons: (
+            GPIO4<'static>,
+            GPIO5<'static>,
+            GPIO6<'static>,
+            GPIO7<'static>,
+        ),
+        pub neopixel: NeoPixelData,
+    }
+
+    /// Moves the board's [Pins](crate::board::Pins) out of `peripherals`
+    #[cfg(feature = "esp32s2")]
+    #[macro_export]
+    macro_rules! board_pins {
+        ($p:ident) => {
+            $crate::board::Pins {
+                buttons: ($p.GPIO4, $p.GPIO5, $p.GPIO6, $p.GPIO7),
+                neopixel: $p.GPIO18,
+            }
+        };
+    }
+
+    /// Moves the board's [Pins](crate::board::Pins) out of `peripherals`
+    #[cfg(not(feature = "esp32s2"))]
+    #[macro_export]
+    macro_rules! board_pins {
+        ($p:ident) => {
+            $crate::board::Pins {
+                buttons: ($p.GPIO4, $p.GPIO5, $p.GPIO6, $p.GPIO7),
+                neopixel: $p.GPIO48,
+            }
+        };
+    }
+}
@@ -0,0 +1,162 @@
+//! Buttons held while the badge boots pick how it starts.
+//!
+//! | held | mode                                                   |
+//! |------|--------------------------------------------------------|
+//! | A    | safe mode: WiFi and the console, no apps               |
+//! | D    | WiFi provisioning over Improv, even with saved credentials |
+//! | B    | USB file drop, see [crate::file_drop]                  |
+//! | B+C  | [crate::system::factory_reset], after holding for [RESET_HOLD] |
+//!
+//! Anything else boots normally, unless the badge restarted with
+//! [restart_into] to ask for a mode.
+
+use embedded_graphics::{
+    mono_font::{
+        ascii::{FONT_6X10, FONT_7X14_BOLD},
+        MonoTextStyle,
+    },
+    pixelcolor::Gray2,
+    prelude::*,
+    text::{Baseline, Text},
+};
+use esp_hal::{
+    ram,
+    time::{Duration, Instant},
+};
+use log::{info, warn};
+
+use crate::{
+    display::Display,
+    input::{Button, ButtonSet, Buttons},
+};
+
+pub const SAFE_MODE: ButtonSet = ButtonSet::of(&[Button::A]);
+pub const PROVISIONING: ButtonSet = ButtonSet::of(&[Button::D]);
+pub const FILE_DROP: ButtonSet = ButtonSet::of(&[Button::B]);
+pub const FACTORY_RESET: ButtonSet = ButtonSet::of(&[Button::B, Button::C]);
+
+/// How long B+C have to stay held before the badge is reset
+pub const RESET_HOLD: Duration = Duration::from_secs(5);
+
+/// Marks [REQUESTED] as written by [restart_into] rather than left over
+const REQUEST_MAGIC: u32 = 0xb007_0000;
+
+/// The mode asked for before the restart, with [REQUEST_MAGIC]; survives it
+#[ram(unstable(rtc_fast, persistent))]
+static mut REQUESTED: u32 = 0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BootMode {
+    Normal,
+    Safe,
+    Provisioning,
+    FileDrop,
+    FactoryReset,
+}
+
+impl BootMode {
+    /// The mode for exactly the buttons in `held`
+    pub fn from_buttons(held: ButtonSet) -> Self {
+        match held {
+            SAFE_MODE => BootMode::Safe,
+            PROVISIONING => BootMode::Provisioning,
+            FILE_DROP => BootMode::FileDrop,
+            FACTORY_RESET => BootMode::FactoryReset,
+            _ => BootMode::Normal,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            BootMode::Normal => "Normal boot",
+            BootMode::Safe => "Safe mode",
+            BootMode::Provisioning => "WiFi setup",
+            BootMode::FileDrop => "File drop",
+            BootMode::FactoryReset => "Factory reset",
+        }
+    }
+}
+
+/// Reads the held buttons, showing safe mode and factory reset on the display.
+/// A factory reset only counts if the buttons stay held for [RESET_HOLD];
+/// letting go earlier boots normally.
+pub fn detect(buttons: &Buttons, display: &mut Display) -> BootMode {
+    if let Some(mode) = take_request() {
+        info!("Boot mode: {}, asked for before the restart", mode.label());
+        if mode == BootMode::Safe {
+            show(display, mode, "Apps are skipped, the console is up");
+        }
+        return mode;
+    }
+    let mode = BootMode::from_buttons(buttons.sample());
+    if mode == BootMode::Normal {
+        return mode;
+    }
+    info!("Boot mode: {}", mode.label());
+
+    match mode {
+        BootMode::FactoryReset => {
+            show(display, mode, "Keep holding B+C to erase all settings");
+            let deadline = Instant::now() + RESET_HOLD;
+            while Instant::now() < deadline {
+                if buttons.sample() != FACTORY_RESET {
+                    warn!("Factory reset cancelled");
+                    show(display, BootMode::Normal, "Factory reset cancelled");
+                    return BootMode::Normal;
+                }
+            }
+        }
+        BootMode::Safe => show(display, mode, "Apps are skipped, the console is up"),
+        // these draw their own screens
+        BootMode::Provisioning | BootMode::FileDrop | BootMode::Normal => {}
+    }
+    mode
+}
+
+/// Restarts into `mode` as if its buttons were held. A factory reset still
+/// needs them held, so it asks for a normal boot.
+pub fn restart_into(mode: BootMode) -> ! {
+    let code = match mode {
+        BootMode::Safe => 1,
+        BootMode::Provisioning => 2,
+        BootMode::FileDrop => 3,
+        BootMode::Normal | BootMode::FactoryReset => 0,
+    };
+    info!("Restarting into {}", mode.label());
+    // SAFETY: single core, and only touched inside a critical section
+    critical_section::with(|_| unsafe { REQUESTED = REQUEST_MAGIC | code });
+    esp_hal::system::software_reset()
+}
+
+/// The mode [restart_into] asked for, once
+fn take_request() -> Option<BootMode> {
+    // SAFETY: as in restart_into
+    let requested = critical_section::with(|_| unsafe {
+        let requested = REQUESTED;
+        REQUESTED = 0;
+        requested
+    });
+    match requested {
+        r if r == REQUEST_MAGIC | 1 => Some(BootMode::Safe),
+        r if r == REQUEST_MAGIC | 2 => Some(BootMode::Provisioning),
+        r if r == REQUEST_MAGIC | 3 => Some(BootMode::FileDrop),
+        _ => None,
+    }
+}
+
+/// Draws the mode name and a line of detail, then refreshes the panel
+pub fn show(display: &mut Display, mode: BootMode, detail: &str) {
+    let frame = display.frame();
+    frame.clear(Gray2::WHITE).ok();
+    let title_style = MonoTextStyle::new(&FONT_7X14_BOLD, Gray2::BLACK);
+    Text::with_baseline(mode.label(), Point::new(4, 2), title_style, Baseline::Top)
+        .draw(frame)
+        .ok();
+    let style = MonoTextStyle::new(&FONT_6X10, Gray2::BLACK);
+    Text::with_baseline(detail, Point::new(4, 24), style, Baseline::Top)
+        .draw(frame)
+        .ok();
+    if let Err(err) = display.flush() {
+        warn!("refresh failed: {}", err);
+    }
+}
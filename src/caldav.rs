@@ -0,0 +1,156 @@
+//! Just enough CalDAV (RFC 4791) for a to-do list: a `calendar-query`
+//! REPORT for the open VTODOs of one collection, and a PUT of a to-do
+//! marked completed.
+//!
+//! The collection URL is a plain `http://` one, which covers Radicale,
+//! Baïkal or Nextcloud on the local network; see [crate::http] for why.
+//! Responses are read with [crate::caldav_parser], and `DUE` is compared by
+//! its date in the configured timezone.
+
+use alloc::{format, string::String, vec::Vec};
+
+use jiff::tz::TimeZone;
+use log::{debug, warn};
+
+pub use crate::caldav_parser::Todo;
+use crate::{
+    caldav_parser,
+    http::{Auth, Request},
+    net::NetStack,
+    time,
+    url::Url,
+    Error,
+};
+
+/// Asks for every VTODO that isn't completed, with its ETag
+const QUERY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<c:calendar-query xmlns:d="DAV:" xmlns:c="urn:ietf:params:xml:ns:caldav">
+<d:prop><d:getetag/><c:calendar-data/></d:prop>
+<c:filter><c:comp-filter name="VCALENDAR"><c:comp-filter name="VTODO">
+<c:prop-filter name="COMPLETED"><c:is-not-defined/></c:prop-filter>
+</c:comp-filter></c:comp-filter></c:filter>
+</c:calendar-query>"#;
+
+/// A collection of to-dos on a CalDAV server
+pub struct Client {
+    collection: String,
+    auth: Option<Auth>,
+}
+
+impl Client {
+    /// A client for the collection at `url`, e.g.
+    /// `http://nas.local:5232/user/tasks/`
+    pub fn new(url: &str, auth: Option<Auth>) -> Self {
+        Self {
+            collection: url.into(),
+            auth,
+        }
+    }
+
+    /// Every to-do in the collection that isn't completed yet
+    pub fn todos(&self, net: &NetStack<'_>) -> Result<Vec<Todo>, Error> {
+        let mut request = Request::with_method(
+            "REPORT",
+            &self.collection,
+            "application/xml; charset=utf-8",
+            QUERY.as_bytes(),
+        )
+        .header("Depth", "1");
+        if let Some(auth) = &self.auth {
+            request = request.auth(auth);
+        }
+        let response = request.send(net)?;
+        if response.status != 207 {
+            warn!("CalDAV {} answered {}", self.collection, response.status);
+            return Err(Error::Network);
+        }
+        let xml = core::str::from_utf8(&response.body).map_err(|_| Error::Network)?;
+        let todos = caldav_parser::todos(xml, time::to_local);
+        debug!("{} open to-dos in {}", todos.len(), self.collection);
+        Ok(todos)
+    }
+
+    /// Marks `todo` completed on the server. Fails if it changed there since
+    /// it was fetched.
+    pub fn complete(&self, net: &NetStack<'_>, todo: &Todo) -> Result<(), Error> {
+        let url = self.resolve(&todo.href)?;
+        let ics = completed(&todo.ics);
+        let mut request = Request::put(&url, "text/calendar; charset=utf-8", ics.as_bytes());
+        if let Some(etag) = &todo.etag {
+            request = request.header("If-Match", etag);
+        }
+        if let Some(auth) = &self.auth {
+            request = request.auth(auth);
+        }
+        let response = request.send(net)?;
+        match response.status {
+            200..=299 => Ok(()),
+            412 => {
+                warn!(
+                    "\"{}\" changed on the server, not completing it",
+                    todo.summary
+                );
+                Err(Error::Network)
+            }
+            status => {
+                warn!("CalDAV PUT {} answered {}", url, status);
+                Err(Error::Network)
+            }
+        }
+    }
+
+    /// `href` as a full URL, on the collection's server if it is only a path
+    fn resolve(&self, href: &str) -> Result<String, Error> {
+        if href.starts_with("http://") {
+            return Ok(href.into());
+        }
+        let base = Url::parse_with_scheme(&self.collection, "http", 80)?;
+        Ok(if href.starts_with('/') {
+            format!("http://{}:{}{}", base.host, base.port, href)
+        } else {
+            format!("{}{}", self.collection, href)
+        })
+    }
+}
+
+/// `ics` with its VTODO marked completed now
+fn completed(ics: &str) -> String {
+    let stamp = time::now_utc().map(|now| {
+        let utc = TimeZone::UTC.to_datetime(now);
+        format!(
+            "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+            utc.year(),
+            utc.month(),
+            utc.day(),
+            utc.hour(),
+            utc.minute(),
+            utc.second()
+        )
+    });
+    let mut out = String::with_capacity(ics.len() + 64);
+    let mut depth = 0;
+    for line in ics.lines() {
+        let line = line.trim_end_matches('\r');
+        let name = line.split([';', ':']).next().unwrap_or(line);
+        match line {
+            "BEGIN:VTODO" => depth = 1,
+            _ if depth == 0 => {}
+            "END:VTODO" if depth == 1 => {
+                out.push_str("STATUS:COMPLETED\r\nPERCENT-COMPLETE:100\r\n");
+                if let Some(stamp) = &stamp {
+                    out.push_str(&format!("COMPLETED:{}\r\n", stamp));
+                }
+                depth = 0;
+            }
+            _ if name == "BEGIN" => depth += 1,
+            _ if name == "END" => depth -= 1,
+            _ if depth == 1 && ["STATUS", "PERCENT-COMPLETE", "COMPLETED"].contains(&name) => {
+                continue
+            }
+            _ => {}
+        }
+        out.push_str(line);
+        out.push_str("\r\n");
+    }
+    out
+}
@@ -0,0 +1,230 @@
+//! Reads the to-dos out of a CalDAV `calendar-query` REPORT response, the
+//! `207 Multi-Status` XML with a calendar object in each `response`.
+//!
+//! Elements are picked apart by name whatever the namespace prefix, with
+//! entities and CDATA sections resolved, and each calendar object is
+//! unfolded before its VTODO is read. Properties of components inside the
+//! VTODO, such as a VALARM, are left out. `DUE` is read as a date: a UTC
+//! time is converted with the `to_local` passed in, floating and TZID times
+//! are taken as they are.
+//!
+//! Nothing here touches the network or the clock, so `tools/http_conformance`
+//! checks it against server responses on the host and `tools/fuzz` feeds it
+//! arbitrary ones.
+
+use alloc::{format, string::String, vec::Vec};
+
+use jiff::{
+    civil::{Date, DateTime, Time},
+    tz::TimeZone,
+    Timestamp,
+};
+
+/// A UTC time as a local one, `None` if that isn't known
+pub type ToLocal = fn(Timestamp) -> Option<DateTime>;
+
+/// One VTODO and the calendar object it came in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Todo {
+    /// Where the calendar object is, as the server wrote it
+    pub href: String,
+    pub etag: Option<String>,
+    pub summary: String,
+    /// In the configured timezone
+    pub due: Option<Date>,
+    pub completed: bool,
+    /// The calendar object, unfolded, for `caldav::Client::complete` to send
+    /// back
+    pub(crate) ics: String,
+}
+
+/// Every to-do in the REPORT response `xml`; responses without a VTODO are
+/// left out
+pub fn todos(xml: &str, to_local: ToLocal) -> Vec<Todo> {
+    elements(xml, "response")
+        .into_iter()
+        .filter_map(|response| {
+            let href = unescape(elements(response, "href").first()?);
+            let etag = elements(response, "getetag")
+                .first()
+                .map(|etag| unescape(etag))
+                .filter(|etag| !etag.is_empty());
+            let ics = unfold(&unescape(elements(response, "calendar-data").first()?));
+            parse_todo(href, etag, ics, to_local)
+        })
+        .collect()
+}
+
+fn parse_todo(href: String, etag: Option<String>, ics: String, to_local: ToLocal) -> Option<Todo> {
+    if !ics.lines().any(|line| line.trim_end() == "BEGIN:VTODO") {
+        return None;
+    }
+    let mut summary = None;
+    let mut due = None;
+    let mut completed = false;
+    for (name, value) in todo_properties(&ics) {
+        match name {
+            "SUMMARY" => summary = Some(unescape_text(value)),
+            "DUE" => due = parse_date(value, to_local),
+            "STATUS" => completed |= value.eq_ignore_ascii_case("COMPLETED"),
+            "COMPLETED" => completed = true,
+            _ => {}
+        }
+    }
+    Some(Todo {
+        href,
+        etag,
+        summary: summary.unwrap_or_default(),
+        due,
+        completed,
+        ics,
+    })
+}
+
+/// Name and value of each property of the VTODO in `ics`, leaving out those
+/// of its alarms
+pub fn todo_properties(ics: &str) -> impl Iterator<Item = (&str, &str)> {
+    let mut depth = 0;
+    ics.lines().filter_map(move |line| {
+        let line = line.trim_end_matches('\r');
+        let (head, value) = line.split_once(':')?;
+        let name = head.split(';').next().unwrap_or(head);
+        match (name, value) {
+            ("BEGIN", "VTODO") => depth = 1,
+            ("BEGIN", _) if depth > 0 => depth += 1,
+            ("END", _) if depth > 0 => depth -= 1,
+            _ if depth == 1 => return Some((name, value)),
+            _ => {}
+        }
+        None
+    })
+}
+
+/// A `DUE` value, a date or a date and time, as a local date
+pub fn parse_date(value: &str, to_local: ToLocal) -> Option<Date> {
+    let digits = |range: core::ops::Range<usize>| value.get(range)?.parse::<i16>().ok();
+    let date = Date::new(digits(0..4)?, digits(4..6)? as i8, digits(6..8)? as i8).ok()?;
+    if value.len() < 15 || value.as_bytes()[8] != b'T' {
+        return Some(date);
+    }
+    let at = DateTime::from_parts(
+        date,
+        Time::new(
+            digits(9..11)? as i8,
+            digits(11..13)? as i8,
+            digits(13..15)? as i8,
+            0,
+        )
+        .ok()?,
+    );
+    // floating and TZID times are taken as they are, only UTC converted
+    if value.ends_with('Z') {
+        let utc = TimeZone::UTC.to_timestamp(at).ok()?;
+        if let Some(local) = to_local(utc) {
+            return Some(local.date());
+        }
+    }
+    Some(at.date())
+}
+
+/// Joins the continuation lines of iCalendar content
+pub fn unfold(ics: &str) -> String {
+    ics.replace("\r\n ", "")
+        .replace("\r\n\t", "")
+        .replace("\n ", "")
+        .replace("\n\t", "")
+}
+
+/// Undoes iCalendar TEXT escaping
+fn unescape_text(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            // one line is all there is room for
+            Some('n' | 'N') => out.push(' '),
+            Some(other) => out.push(other),
+            None => {}
+        }
+    }
+    out
+}
+
+/// The contents of each element named `name`, whatever its namespace prefix
+pub fn elements<'x>(xml: &'x str, name: &str) -> Vec<&'x str> {
+    let mut found = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        let Some(end) = rest.find('>') else {
+            break;
+        };
+        let tag = &rest[..end];
+        rest = &rest[end + 1..];
+        let qualified = tag
+            .split(|c: char| c.is_ascii_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default();
+        let local = qualified.rsplit(':').next().unwrap_or(qualified);
+        if local != name || tag.starts_with(['/', '?', '!']) {
+            continue;
+        }
+        if tag.ends_with('/') {
+            found.push("");
+            continue;
+        }
+        let close = format!("</{}>", qualified);
+        let Some(inner_end) = rest.find(&close) else {
+            break;
+        };
+        found.push(&rest[..inner_end]);
+        rest = &rest[inner_end + close.len()..];
+    }
+    found
+}
+
+/// Element text with its entities and CDATA sections resolved
+pub fn unescape(raw: &str) -> String {
+    let mut out = String::with_capacity(raw.len());
+    let mut rest = raw.trim();
+    while !rest.is_empty() {
+        if let Some(cdata) = rest.strip_prefix("<![CDATA[") {
+            let end = cdata.find("]]>").unwrap_or(cdata.len());
+            out.push_str(&cdata[..end]);
+            rest = cdata.get(end + 3..).unwrap_or_default();
+        } else if let Some(entity) = rest.strip_prefix('&') {
+            let Some(end) = entity.find(';') else {
+                out.push('&');
+                rest = entity;
+                continue;
+            };
+            let c = match &entity[..end] {
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "amp" => Some('&'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                code => code
+                    .strip_prefix("#x")
+                    .map(|hex| u32::from_str_radix(hex, 16))
+                    .or_else(|| code.strip_prefix('#').map(str::parse))
+                    .and_then(Result::ok)
+                    .and_then(char::from_u32),
+            };
+            match c {
+                Some(c) => out.push(c),
+                None => out.push_str(&rest[..end + 2]),
+            }
+            rest = &entity[end + 1..];
+        } else {
+            let next = rest.find(['&', '<']).unwrap_or(rest.len()).max(1);
+            out.push_str(&rest[..next]);
+            rest = &rest[next..];
+        }
+    }
+    out
+}
@@ -0,0 +1,160 @@
+//! A Gray8 render target that is only brought down to the panel's four
+//! levels when the frame is done.
+//!
+//! Drawing straight into the Gray2 [Frame] rounds every shape, blend and
+//! image pixel to the nearest level as it lands. A [Canvas] keeps 256 shades
+//! until [Canvas::quantize], which spreads the rounding error over the
+//! neighbouring pixels (Floyd-Steinberg), so gradients, photos and
+//! anti-aliased edges keep their in-between tones. Apps opt in with
+//! [crate::app::App::gray8] and draw, or [Canvas::blend] partial coverage, in
+//! [crate::app::App::render_gray8].
+//!
+//! The canvas takes one byte per pixel, 37 KiB, from [psram] where there is
+//! some.
+
+use alloc::vec::Vec;
+use core::convert::Infallible;
+
+use embedded_graphics::{
+    pixelcolor::{Gray2, Gray8},
+    prelude::*,
+    primitives::Rectangle,
+};
+
+use crate::{
+    display::{Frame, HEIGHT, WIDTH},
+    psram,
+};
+
+const W: usize = WIDTH as usize;
+const H: usize = HEIGHT as usize;
+
+/// How [Canvas::quantize] picks levels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dither {
+    /// Nearest level, for flat UI where dither noise would only hurt
+    None,
+    /// Error diffusion, for images and gradients
+    FloydSteinberg,
+}
+
+/// A full-screen Gray8 framebuffer
+pub struct Canvas {
+    pixels: Vec<u8>,
+}
+
+impl Default for Canvas {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Canvas {
+    /// A white canvas
+    pub fn new() -> Self {
+        let mut pixels = psram::zeroed(W * H);
+        pixels.fill(Gray8::WHITE.luma());
+        Self { pixels }
+    }
+
+    pub fn pixel(&self, point: Point) -> Option<Gray8> {
+        self.index(point).map(|i| Gray8::new(self.pixels[i]))
+    }
+
+    /// Mixes `color` into the pixel at `point`; `alpha` 255 covers it
+    pub fn blend(&mut self, point: Point, color: Gray8, alpha: u8) {
+        if let Some(i) = self.index(point) {
+            let (old, new, alpha) = (self.pixels[i] as u32, color.luma() as u32, alpha as u32);
+            self.pixels[i] = ((new * alpha + old * (255 - alpha) + 127) / 255) as u8;
+        }
+    }
+
+    /// Turns light into dark and back inside `area`, as what's behind light
+    /// text
+    pub fn invert(&mut self, area: &Rectangle) {
+        let area = area.intersection(&self.bounding_box());
+        let Some(bottom_right) = area.bottom_right() else {
+            return;
+        };
+        for y in area.top_left.y..=bottom_right.y {
+            let start = y as usize * W + area.top_left.x as usize;
+            for luma in &mut self.pixels[start..start + area.size.width as usize] {
+                *luma = 255 - *luma;
+            }
+        }
+    }
+
+    /// Writes the canvas into `frame` at four levels
+    pub fn quantize(&self, frame: &mut Frame, dither: Dither) {
+        // error carried into this row and the next, one pixel of margin on
+        // each side
+        let mut current = [0i16; W + 2];
+        let mut next = [0i16; W + 2];
+        for y in 0..H {
+            let row = &self.pixels[y * W..][..W];
+            let levels = row.iter().enumerate().map(|(x, &luma)| {
+                let wanted = (luma as i16 + current[x + 1] / 16).clamp(0, 255);
+                let level = (wanted * 3 + 127) / 255;
+                if dither == Dither::FloydSteinberg {
+                    let error = wanted - level * 85;
+                    current[x + 2] += error * 7;
+                    next[x] += error * 3;
+                    next[x + 1] += error * 5;
+                    next[x + 2] += error;
+                }
+                Pixel(Point::new(x as i32, y as i32), Gray2::new(level as u8))
+            });
+            frame.draw_iter(levels).ok();
+            current = next;
+            next = [0; W + 2];
+        }
+    }
+
+    fn index(&self, point: Point) -> Option<usize> {
+        let (x, y) = (
+            usize::try_from(point.x).ok()?,
+            usize::try_from(point.y).ok()?,
+        );
+        (x < W && y < H).then_some(y * W + x)
+    }
+}
+
+impl OriginDimensions for Canvas {
+    fn size(&self) -> Size {
+        Size::new(WIDTH, HEIGHT)
+    }
+}
+
+impl DrawTarget for Canvas {
+    type Color = Gray8;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            if let Some(i) = self.index(point) {
+                self.pixels[i] = color.luma();
+            }
+        }
+        Ok(())
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+        let area = area.intersection(&self.bounding_box());
+        let Some(bottom_right) = area.bottom_right() else {
+            return Ok(());
+        };
+        for y in area.top_left.y..=bottom_right.y {
+            let start = y as usize * W + area.top_left.x as usize;
+            self.pixels[start..start + area.size.width as usize].fill(color.luma());
+        }
+        Ok(())
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        self.pixels.fill(color.luma());
+        Ok(())
+    }
+}
@@ -0,0 +1,79 @@
+//! What differs between the chips the firmware builds for, picked by exactly
+//! one of the `esp32s2` (the default, the MagTag's) and `esp32s3` features.
+//!
+//! Both have ADC1 on the low GPIOs and a USB OTG peripheral on GPIO19/20,
+//! and RMT channel 0, which the NeoPixels use, can transmit on both; on the
+//! S3 only channels 0 to 3 can. Only the S2 has the DAC behind `audio-pcm`,
+//! and only the S3 has Bluetooth LE, which the firmware leaves off.
+//!
+//! Build for the S3 with `--target xtensa-esp32s3-none-elf
+//! --no-default-features --features println,esp32s3,ssd1680-generic`.
+
+#[cfg(not(any(feature = "esp32s2", feature = "esp32s3")))]
+compile_error!("select a chip with the `esp32s2` or `esp32s3` feature");
+#[cfg(all(feature = "esp32s2", feature = "esp32s3"))]
+compile_error!("select only one of the `esp32s2` and `esp32s3` features");
+#[cfg(all(feature = "magtag-2.9", not(feature = "esp32s2")))]
+compile_error!("the MagTag is an ESP32-S2 board");
+#[cfg(all(feature = "audio-pcm", not(feature = "esp32s2")))]
+compile_error!("`audio-pcm` needs the ESP32-S2's DAC");
+
+#[cfg(feature = "esp32s2")]
+pub use s2::*;
+#[cfg(all(feature = "esp32s3", not(feature = "esp32s2")))]
+pub use s3::*;
+
+#[cfg(feature = "esp32s2")]
+mod s2 {
+    pub const NAME: &str = "ESP32-S2";
+
+    /// ADC readings are 13 bits
+    pub const ADC_MAX_READING: u32 = (1 << 13) - 1;
+    /// Nominal input voltage at full scale with 11 dB attenuation
+    pub const ADC_FULL_SCALE_MV: u32 = 2500;
+
+    /// Whether the chip has a GPIO `pin`
+    pub const fn is_gpio(pin: u8) -> bool {
+        matches!(pin, 0..=21 | 26..=46)
+    }
+
+    /// Whether `pin` can't drive an output
+    pub const fn is_input_only(pin: u8) -> bool {
+        pin == 46
+    }
+
+    /// Whether `pin` is wired to the module's flash or PSRAM
+    pub const fn is_memory_pin(pin: u8) -> bool {
+        matches!(pin, 26..=32)
+    }
+}
+
+#[cfg(all(feature = "esp32s3", not(feature = "esp32s2")))]
+mod s3 {
+    pub const NAME: &str = "ESP32-S3";
+
+    /// ADC readings are 12 bits
+    pub const ADC_MAX_READING: u32 = (1 << 12) - 1;
+    /// Nominal input voltage at full scale with 11 dB attenuation
+    pub const ADC_FULL_SCALE_MV: u32 = 3100;
+
+    /// Whether the chip has a GPIO `pin`
+    pub const fn is_gpio(pin: u8) -> bool {
+        matches!(pin, 0..=21 | 26..=48)
+    }
+
+    /// Whether `pin` can't drive an output
+    pub const fn is_input_only(_pin: u8) -> bool {
+        false
+    }
+
+    /// Whether `pin` is wired to the module's flash or PSRAM; 33 to 37 only
+    /// on modules with octal PSRAM, which is what `psram` expects
+    pub const fn is_memory_pin(pin: u8) -> bool {
+        match pin {
+            26..=32 => true,
+            33..=37 => cfg!(feature = "psram"),
+            _ => false,
+        }
+    }
+}
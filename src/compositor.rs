@@ -0,0 +1,241 @@
+//! Builds each frame from layers stacked over and under the front app.
+//!
+//! The host draws the [Depth::Background] layers, then the app, then the
+//! layers above it, so the status bar and banners show on every screen
+//! without apps drawing them. Each [Layer] tracks its own changes, and the
+//! host redraws when any layer, or the app, has changed. The built-in layers
+//! are the [StatusBar], the [Banner], shown with [show_banner] for things
+//! like "Reconnecting..." that block for a while, and the layers of
+//! [crate::ui::dialog] and [crate::ui::toast].
+//!
+//! Gray8 apps replace the whole frame, so background layers don't show
+//! under them.
+
+use alloc::{boxed::Box, string::String, vec::Vec};
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+use embedded_graphics::{
+    mono_font::MonoTextStyle,
+    pixelcolor::Gray2,
+    prelude::*,
+    primitives::{PrimitiveStyleBuilder, Rectangle},
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
+};
+
+use crate::{
+    bindings::{self, Subscriptions},
+    display::{Frame, HEIGHT, WIDTH},
+    fonts::{FIXED_6X10, FIXED_7X14_BOLD},
+    notify,
+    ui::{
+        contrast::draw_knockout,
+        dialog::DialogLayer,
+        icon::{self, Icon},
+        toast::ToastLayer,
+    },
+};
+
+/// Where a [Layer] sits, bottom to top
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Depth {
+    Background,
+    /// The front app; layers here draw right over it
+    App,
+    StatusBar,
+    Modal,
+}
+
+/// Something drawn on every screen
+pub trait Layer {
+    fn depth(&self) -> Depth;
+
+    /// Whether the layer changed since it was last drawn. Called on every
+    /// pass of the event loop, so keep it cheap.
+    fn take_dirty(&mut self) -> bool;
+
+    fn draw(&mut self, frame: &mut Frame);
+}
+
+/// The layers of the frame, kept in [Depth] order
+pub struct Compositor<'a> {
+    layers: Vec<Box<dyn Layer + 'a>>,
+}
+
+impl Default for Compositor<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> Compositor<'a> {
+    /// A compositor with the [StatusBar], [Banner], [DialogLayer] and
+    /// [ToastLayer]
+    pub fn new() -> Self {
+        let mut compositor = Self { layers: Vec::new() };
+        compositor.add(StatusBar::new());
+        compositor.add(Banner);
+        compositor.add(DialogLayer);
+        compositor.add(ToastLayer);
+        compositor
+    }
+
+    /// Adds a layer above the others at its depth
+    pub fn add(&mut self, layer: impl Layer + 'a) {
+        let depth = layer.depth();
+        let at = self.layers.partition_point(|l| l.depth() <= depth);
+        self.layers.insert(at, Box::new(layer));
+    }
+
+    /// Whether any layer changed. Asks all of them, so each one's change is
+    /// taken.
+    pub fn take_dirty(&mut self) -> bool {
+        self.layers
+            .iter_mut()
+            .fold(false, |dirty, layer| layer.take_dirty() | dirty)
+    }
+
+    /// Draws the layers below the app, the app with `app`, and the rest
+    pub fn compose(&mut self, frame: &mut Frame, app: impl FnOnce(&mut Frame)) {
+        let above = self.layers.partition_point(|l| l.depth() < Depth::App);
+        let (below, above) = self.layers.split_at_mut(above);
+        for layer in below {
+            layer.draw(frame);
+        }
+        app(frame);
+        for layer in above {
+            layer.draw(frame);
+        }
+        // whatever changed while the app drew is on the frame already
+        self.take_dirty();
+    }
+}
+
+/// The icons of active notices, a WiFi icon while online and the battery
+/// charge, right to left from the top right corner
+pub struct StatusBar {
+    subscriptions: Subscriptions,
+}
+
+impl Default for StatusBar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StatusBar {
+    pub fn new() -> Self {
+        let mut subscriptions = Subscriptions::new();
+        let area = Rectangle::new(Point::zero(), Size::new(WIDTH, icon::SIZE + 2));
+        subscriptions.subscribe("battery.pct", area);
+        subscriptions.subscribe("wifi.link", area);
+        Self { subscriptions }
+    }
+}
+
+impl Layer for StatusBar {
+    fn depth(&self) -> Depth {
+        Depth::StatusBar
+    }
+
+    fn take_dirty(&mut self) -> bool {
+        notify::take_changed() | self.subscriptions.dirty().is_some()
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        // slots are as wide as the notice icons, next to them
+        let mut x = notify::draw_icons(frame);
+        if bindings::get("wifi.link").as_deref() == Some("up") {
+            Icon::Wifi.draw_boxed(frame, Point::new(x, 1));
+            x -= icon::SIZE as i32 + 2;
+        }
+        if let Some(percent) = bindings::get("battery.pct") {
+            let text = alloc::format!("{}%", percent);
+            let style = TextStyleBuilder::new()
+                .alignment(Alignment::Right)
+                .baseline(Baseline::Top)
+                .build();
+            let text = Text::with_text_style(
+                &text,
+                Point::new(x + icon::SIZE as i32, 2),
+                MonoTextStyle::new(&FIXED_6X10, Gray2::BLACK),
+                style,
+            );
+            draw_knockout(frame, &text, Gray2::WHITE, 1);
+        }
+    }
+}
+
+struct BannerState {
+    text: Option<String>,
+    changed: bool,
+}
+
+static BANNER: Mutex<RefCell<BannerState>> = Mutex::new(RefCell::new(BannerState {
+    text: None,
+    changed: false,
+}));
+
+/// Shows `text` in a box across the middle of every screen until
+/// [hide_banner]
+pub fn show_banner(text: &str) {
+    set_banner(Some(String::from(text)));
+}
+
+pub fn hide_banner() {
+    set_banner(None);
+}
+
+fn set_banner(text: Option<String>) {
+    critical_section::with(|cs| {
+        let mut banner = BANNER.borrow_ref_mut(cs);
+        if banner.text != text {
+            banner.text = text;
+            banner.changed = true;
+        }
+    });
+}
+
+/// The [show_banner] text, if any
+pub struct Banner;
+
+impl Layer for Banner {
+    fn depth(&self) -> Depth {
+        Depth::Modal
+    }
+
+    fn take_dirty(&mut self) -> bool {
+        critical_section::with(|cs| core::mem::take(&mut BANNER.borrow_ref_mut(cs).changed))
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let Some(text) = critical_section::with(|cs| BANNER.borrow_ref(cs).text.clone()) else {
+            return;
+        };
+        let area = Rectangle::new(
+            Point::new(24, HEIGHT as i32 / 2 - 16),
+            Size::new(WIDTH - 48, 32),
+        );
+        area.into_styled(
+            PrimitiveStyleBuilder::new()
+                .fill_color(Gray2::WHITE)
+                .stroke_color(Gray2::BLACK)
+                .stroke_width(2)
+                .build(),
+        )
+        .draw(frame)
+        .ok();
+        let style = TextStyleBuilder::new()
+            .alignment(Alignment::Center)
+            .baseline(Baseline::Middle)
+            .build();
+        Text::with_text_style(
+            &text,
+            area.center(),
+            MonoTextStyle::new(&FIXED_7X14_BOLD, Gray2::BLACK),
+            style,
+        )
+        .draw(frame)
+        .ok();
+    }
+}
@@ -0,0 +1,492 @@
+//! Persistent key/value settings kept in the `nvs` data partition.
+//!
+//! The store is a text record (`key=value` lines) kept in two sectors at the
+//! start of the partition, each write going to the one not holding the
+//! current record. Records carry a sequence number and a CRC, and [load]
+//! takes the newest one that checks out, so a write cut short by a flat
+//! battery leaves the previous settings. It is not the ESP-IDF NVS format;
+//! nothing else in this firmware uses that partition. With
+//! `encrypted-secrets` the passwords and tokens in it are encrypted, see
+//! [crate::secrets].
+//!
+//! [load]: ConfigStore::load
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::str::FromStr;
+
+use esp_bootloader_esp_idf::partitions::DataPartitionSubType;
+use esp_hal::time::Duration;
+use log::{info, warn};
+
+#[cfg(feature = "encrypted-secrets")]
+use crate::secrets;
+use crate::{
+    crc::{crc32, crc32_update},
+    display::Contrast,
+    flash,
+    i18n::Language,
+    Error,
+};
+
+const MAGIC: u32 = u32::from_le_bytes(*b"MTC2");
+/// Magic, payload length, sequence number and CRC of the sequence number and
+/// payload
+const HEADER_LEN: usize = 16;
+/// The single record without sequence number or CRC that older firmware
+/// wrote to the first sector, read once to carry its settings over
+const LEGACY_MAGIC: u32 = u32::from_le_bytes(*b"MTCF");
+const LEGACY_HEADER_LEN: usize = 8;
+const SECTOR_SIZE: usize = flash::SECTOR_SIZE as usize;
+const SLOTS: u32 = 2;
+
+/// Keys used by the built-in modules
+pub mod keys {
+    pub const REFRESH_MINUTES: &str = "refresh_min";
+    pub const TIMEZONE: &str = "tz";
+    /// Whose `Date` header sets the clock when SNTP fails, see
+    /// [crate::lifecycle]
+    pub const TIME_URL: &str = "time_url";
+    pub const UNITS: &str = "units";
+    pub const NEOPIXEL_BRIGHTNESS: &str = "px_bright";
+    /// See [crate::display::Contrast]
+    pub const CONTRAST: &str = "contrast";
+    /// Seconds the panel may stay busy, see [crate::display::Display::set_busy_timeout]
+    pub const DISPLAY_TIMEOUT: &str = "epd_timeout_s";
+    pub const LANGUAGE: &str = "lang";
+    pub const WIFI_SSID: &str = "wifi_ssid";
+    pub const WIFI_PASSWORD: &str = "wifi_pass";
+    pub const TELEMETRY_URL: &str = "telemetry_url";
+    pub const TELEMETRY_MINUTES: &str = "telemetry_min";
+    pub const MQTT_USERNAME: &str = "mqtt_user";
+    pub const MQTT_PASSWORD: &str = "mqtt_pass";
+    /// Credentials for HTTP endpoints, see [crate::http::Auth::from_config]
+    pub const HTTP_USERNAME: &str = "http_user";
+    pub const HTTP_PASSWORD: &str = "http_pass";
+    pub const HTTP_TOKEN: &str = "http_token";
+    pub const BATTERY_CAPACITY: &str = "battery_mah";
+    pub const MOTION_WAKE: &str = "motion_wake";
+    /// Seconds lying face down before the badge goes to sleep until turned
+    /// over, unset or 0 for never; see [crate::power::sleep_until_face_up]
+    pub const FACE_DOWN_SLEEP: &str = "facedown_s";
+    /// Daily window without scheduled refreshes, see [crate::alarm::QuietHours]
+    pub const QUIET_HOURS: &str = "quiet_hours";
+    /// Panel wiring other than the board's, see [crate::board::BoardPins]
+    pub const PANEL_PINS: &str = "panel_pins";
+    /// Where the SD card is wired, see [crate::board::SdPins]
+    pub const SD_PINS: &str = "sd_pins";
+    /// `seesaw` or two pads for a rotary encoder, see [crate::encoder]
+    pub const ENCODER: &str = "encoder";
+    /// The buttons an encoder's turns and push stand for, see [crate::encoder::Keys]
+    pub const ENCODER_KEYS: &str = "encoder_keys";
+    /// Pads with buttons on them, such as `D10=A`, see [crate::encoder]
+    pub const BUTTON_PADS: &str = "button_pads";
+    /// The button a double tap on the case presses, see [crate::accel::detect_taps]
+    pub const TAP_BUTTON: &str = "tap_button";
+    /// What remote presses over HTTP have to carry, see [crate::input::serve_press]
+    pub const PRESS_TOKEN: &str = "press_token";
+    /// MQTT topic whose payloads are presses such as `A` or `A+D`, see
+    /// [crate::apps::mqtt_display]
+    pub const MQTT_PRESS: &str = "mqtt_press";
+    /// Milliseconds a button is held for a long press, unset or 0 for none,
+    /// see [crate::input::Timing]
+    pub const LONG_PRESS: &str = "long_press_ms";
+    /// Key repeat as `delay_ms,every_ms`, or `off`, see [crate::input::Timing]
+    pub const KEY_REPEAT: &str = "key_repeat";
+    /// Buttons bound to actions, such as `A=refresh,D_long=provision`, see
+    /// [crate::actions]
+    pub const BUTTON_ACTIONS: &str = "button_actions";
+    /// RTTTL tune for notifications, see [crate::rtttl]
+    pub const MELODY: &str = "melody";
+    /// Template for the dashboard app, see [crate::apps::dashboard]
+    pub const DASHBOARD: &str = "dashboard";
+    /// Shared key remote display frames have to be signed with, see
+    /// [crate::apps::remote_display]
+    pub const REMOTE_KEY: &str = "remote_key";
+    /// Broker and topics of the MQTT display app, see
+    /// [crate::apps::mqtt_display]
+    pub const MQTT_DISPLAY: &str = "mqtt_display";
+    pub const MQTT_SLOTS: &str = "mqtt_slots";
+    /// Server, token and entities to poll, see [crate::home_assistant]
+    pub const HA_URL: &str = "ha_url";
+    pub const HA_TOKEN: &str = "ha_token";
+    pub const HA_ENTITIES: &str = "ha_entities";
+    pub const HA_MINUTES: &str = "ha_min";
+    /// CalDAV collection of the tasks app, see [crate::apps::tasks]
+    pub const TASKS_URL: &str = "tasks_url";
+    /// Proxy, token and repositories of the GitHub app, see
+    /// [crate::apps::github]
+    pub const GITHUB_API: &str = "github_api";
+    pub const GITHUB_TOKEN: &str = "github_token";
+    pub const GITHUB_REPOS: &str = "github_repos";
+    /// Broker, topics and words of pushed alerts, see [crate::alerts]
+    pub const ALERT_BROKER: &str = "alert_broker";
+    pub const ALERT_TOPICS: &str = "alert_topics";
+    pub const ALERT_MATCH: &str = "alert_match";
+    /// What the badge game calls this badge, see [crate::apps::badge_game]
+    pub const BADGE_NAME: &str = "badge_name";
+    /// Where the centrally kept config is, the key it is signed with and
+    /// the version applied last, see [crate::fleet]
+    pub const FLEET_URL: &str = "fleet_url";
+    pub const FLEET_KEY: &str = "fleet_key";
+    pub const FLEET_VERSION: &str = "fleet_version";
+    /// Runs left in the trial of the applied fleet config, and the last
+    /// version undone after a crash
+    pub const FLEET_TRIAL: &str = "fleet_trial";
+    pub const FLEET_HALTED: &str = "fleet_halted";
+    /// Where, what, how and how often sensor readings are logged, and where
+    /// the files go when complete, see [crate::datalog]
+    pub const DATALOG: &str = "datalog";
+    pub const DATALOG_FIELDS: &str = "datalog_fields";
+    pub const DATALOG_FORMAT: &str = "datalog_format";
+    pub const DATALOG_SECONDS: &str = "datalog_s";
+    pub const DATALOG_MAX_KB: &str = "datalog_kb";
+    pub const DATALOG_URL: &str = "datalog_url";
+    /// Where and how often readings are written to a time-series database,
+    /// and which, see [crate::tsdb]
+    pub const TSDB_URL: &str = "tsdb_url";
+    pub const TSDB_TOKEN: &str = "tsdb_token";
+    pub const TSDB_FIELDS: &str = "tsdb_fields";
+    pub const TSDB_SECONDS: &str = "tsdb_s";
+    /// CPU clock between bursts of work, see [crate::power::cpu]
+    pub const CPU_PROFILE: &str = "cpu_profile";
+    /// How the modem sleeps between transfers, see [crate::wifi::PowerSave]
+    pub const WIFI_POWER_SAVE: &str = "wifi_power_save";
+
+    /// Keys whose values are never shown on the console, and are encrypted
+    /// in flash with `encrypted-secrets`
+    pub const SECRETS: &[&str] = &[
+        WIFI_PASSWORD,
+        MQTT_PASSWORD,
+        HTTP_PASSWORD,
+        HTTP_TOKEN,
+        REMOTE_KEY,
+        HA_TOKEN,
+        GITHUB_TOKEN,
+        FLEET_KEY,
+        TSDB_TOKEN,
+    ];
+}
+
+/// String key/value pairs, written back to flash on [ConfigStore::commit]
+pub struct ConfigStore {
+    entries: Vec<(String, String)>,
+    /// Flash offset of the `nvs` partition, `None` for an in-memory store
+    offset: Option<u32>,
+    /// Sequence number of the stored record; the next goes in slot
+    /// `(seq + 1) % SLOTS`
+    seq: u32,
+    dirty: bool,
+}
+
+impl ConfigStore {
+    /// A store that is never persisted
+    pub fn in_memory() -> Self {
+        Self {
+            entries: Vec::new(),
+            offset: None,
+            seq: 0,
+            dirty: false,
+        }
+    }
+
+    /// Locates the `nvs` partition and reads the stored record, if any
+    pub fn load() -> Result<Self, Error> {
+        let nvs = flash::find_partition(DataPartitionSubType::Nvs)?;
+        let mut store = Self {
+            entries: Vec::new(),
+            offset: Some(nvs.offset),
+            seq: 0,
+            dirty: false,
+        };
+        store.read_record()?;
+        info!("Loaded {} config entries", store.entries.len());
+        Ok(store)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Parses the value of `key`, ignoring values that don't parse
+    pub fn get_parsed<T: FromStr>(&self, key: &str) -> Option<T> {
+        self.get(key)?.parse().ok()
+    }
+
+    /// Sets `key`. Keys may not contain `=` or newlines, values may not contain newlines.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), Error> {
+        if key.is_empty() || key.contains(['=', '\n']) || value.contains('\n') {
+            return Err(Error::InvalidConfig);
+        }
+        match self.entries.iter_mut().find(|(k, _)| k == key) {
+            Some((_, v)) if v == value => return Ok(()),
+            Some((_, v)) => *v = value.to_string(),
+            None => self.entries.push((key.to_string(), value.to_string())),
+        }
+        self.dirty = true;
+        Ok(())
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        let before = self.entries.len();
+        self.entries.retain(|(k, _)| k != key);
+        self.dirty |= self.entries.len() != before;
+    }
+
+    /// Drops every entry; [ConfigStore::commit] erases them from flash
+    pub fn clear(&mut self) {
+        self.dirty |= !self.entries.is_empty();
+        self.entries.clear();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// Writes pending changes to flash
+    pub fn commit(&mut self) -> Result<(), Error> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let Some(offset) = self.offset else {
+            self.dirty = false;
+            return Ok(());
+        };
+
+        let seq = self.seq.wrapping_add(1);
+        let mut record = Vec::with_capacity(SECTOR_SIZE);
+        record.extend_from_slice(&MAGIC.to_le_bytes());
+        record.extend_from_slice(&[0; 4]);
+        record.extend_from_slice(&seq.to_le_bytes());
+        record.extend_from_slice(&[0; 4]);
+        for (k, v) in &self.entries {
+            record.extend_from_slice(k.as_bytes());
+            record.push(b'=');
+            #[cfg(feature = "encrypted-secrets")]
+            if keys::SECRETS.contains(&k.as_str()) {
+                record.extend_from_slice(secrets::seal(k, v)?.as_bytes());
+                record.push(b'\n');
+                continue;
+            }
+            record.extend_from_slice(v.as_bytes());
+            record.push(b'\n');
+        }
+        if record.len() > SECTOR_SIZE {
+            return Err(Error::InvalidConfig);
+        }
+        let payload_len = (record.len() - HEADER_LEN) as u32;
+        record[4..8].copy_from_slice(&payload_len.to_le_bytes());
+        let crc = crc32_update(crc32(&record[8..12]), &record[HEADER_LEN..]);
+        record[12..16].copy_from_slice(&crc.to_le_bytes());
+        // writes have to be word sized
+        record.resize(
+            record.len().next_multiple_of(flash::WORD_SIZE as usize),
+            0xff,
+        );
+
+        // the other slot keeps the current record until this one is whole
+        let slot = offset + seq % SLOTS * SECTOR_SIZE as u32;
+        flash::erase(slot, slot + SECTOR_SIZE as u32)?;
+        flash::write(slot, &record)?;
+
+        self.seq = seq;
+        self.dirty = false;
+        Ok(())
+    }
+
+    fn read_record(&mut self) -> Result<(), Error> {
+        let Some(offset) = self.offset else {
+            return Ok(());
+        };
+
+        let mut newest: Option<(u32, Vec<u8>)> = None;
+        for slot in 0..SLOTS {
+            let Some((seq, payload)) = read_slot(offset + slot * SECTOR_SIZE as u32)? else {
+                continue;
+            };
+            // sequence numbers wrap, so newer is less than half the range ahead
+            match &newest {
+                Some((newest_seq, _)) if (seq.wrapping_sub(*newest_seq) as i32) <= 0 => {
+                    warn!("Config slot {} is older, skipping it", slot)
+                }
+                _ => newest = Some((seq, payload)),
+            }
+        }
+        let payload = match newest {
+            Some((seq, payload)) => {
+                self.seq = seq;
+                payload
+            }
+            None => match read_legacy(offset)? {
+                Some(payload) => {
+                    info!("Carrying over config from the old format");
+                    // rewritten in the new format, to slot 1, on the next commit
+                    self.dirty = true;
+                    payload
+                }
+                None => {
+                    info!("No stored config, using defaults");
+                    return Ok(());
+                }
+            },
+        };
+
+        let Ok(text) = core::str::from_utf8(&payload) else {
+            warn!("Stored config is not valid UTF-8, ignoring it");
+            return Ok(());
+        };
+        for line in text.lines() {
+            if let Some((k, v)) = line.split_once('=') {
+                #[cfg(feature = "encrypted-secrets")]
+                if secrets::is_sealed(v) {
+                    match secrets::open(k, v) {
+                        Ok(v) => self.entries.push((k.to_string(), v)),
+                        Err(err) => warn!("Can't decrypt {}, dropping it: {}", k, err),
+                    }
+                    continue;
+                } else if keys::SECRETS.contains(&k) && secrets::is_available() {
+                    // sealed on the next commit
+                    self.dirty = true;
+                }
+                self.entries.push((k.to_string(), v.to_string()));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The sequence number and payload of the record at `offset`, `None` if
+/// there is none or it is damaged
+fn read_slot(offset: u32) -> Result<Option<(u32, Vec<u8>)>, Error> {
+    let mut header = [0u8; HEADER_LEN];
+    flash::read(offset, &mut header)?;
+    let word = |i: usize| u32::from_le_bytes(header[i..i + 4].try_into().unwrap());
+    let (magic, len, seq, crc) = (word(0), word(4) as usize, word(8), word(12));
+    if magic != MAGIC || len > SECTOR_SIZE - HEADER_LEN {
+        return Ok(None);
+    }
+    let mut payload = alloc::vec![0u8; len];
+    flash::read(offset + HEADER_LEN as u32, &mut payload)?;
+    if crc32_update(crc32(&header[8..12]), &payload) != crc {
+        warn!("Config record {} at {:#x} is damaged", seq, offset);
+        return Ok(None);
+    }
+    Ok(Some((seq, payload)))
+}
+
+/// The payload of an old-format record at `offset`
+fn read_legacy(offset: u32) -> Result<Option<Vec<u8>>, Error> {
+    let mut header = [0u8; LEGACY_HEADER_LEN];
+    flash::read(offset, &mut header)?;
+    let magic = u32::from_le_bytes(header[0..4].try_into().unwrap());
+    let len = u32::from_le_bytes(header[4..8].try_into().unwrap()) as usize;
+    if magic != LEGACY_MAGIC || len > SECTOR_SIZE - LEGACY_HEADER_LEN {
+        return Ok(None);
+    }
+    let mut payload = alloc::vec![0u8; len];
+    flash::read(offset + LEGACY_HEADER_LEN as u32, &mut payload)?;
+    Ok(Some(payload))
+}
+
+/// Temperature units used by apps that show weather or sensor readings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Units {
+    #[default]
+    Celsius,
+    Fahrenheit,
+}
+
+impl Units {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Units::Celsius => "C",
+            Units::Fahrenheit => "F",
+        }
+    }
+}
+
+impl FromStr for Units {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "C" | "c" => Ok(Units::Celsius),
+            "F" | "f" => Ok(Units::Fahrenheit),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The user-facing settings, with defaults for keys that aren't stored
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Settings {
+    pub refresh_minutes: u16,
+    /// POSIX TZ string, e.g. `CET-1CEST,M3.5.0,M10.5.0/3`
+    pub timezone: String,
+    pub units: Units,
+    /// 0-100 percent
+    pub neopixel_brightness: u8,
+    pub contrast: Contrast,
+    pub language: Language,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            refresh_minutes: 30,
+            timezone: "UTC0".to_string(),
+            units: Units::Celsius,
+            neopixel_brightness: 20,
+            contrast: Contrast::Normal,
+            language: Language::build_default(),
+        }
+    }
+}
+
+impl Settings {
+    pub fn load(config: &ConfigStore) -> Self {
+        let defaults = Self::default();
+        Self {
+            refresh_minutes: config
+                .get_parsed(keys::REFRESH_MINUTES)
+                .unwrap_or(defaults.refresh_minutes),
+            timezone: config
+                .get(keys::TIMEZONE)
+                .map(ToString::to_string)
+                .unwrap_or(defaults.timezone),
+            units: config.get_parsed(keys::UNITS).unwrap_or(defaults.units),
+            neopixel_brightness: config
+                .get_parsed::<u8>(keys::NEOPIXEL_BRIGHTNESS)
+                .unwrap_or(defaults.neopixel_brightness)
+                .min(100),
+            contrast: config
+                .get_parsed(keys::CONTRAST)
+                .unwrap_or(defaults.contrast),
+            language: config
+                .get_parsed(keys::LANGUAGE)
+                .unwrap_or(defaults.language),
+        }
+    }
+
+    /// Copies the settings into `config`; call [ConfigStore::commit] to persist them
+    pub fn store(&self, config: &mut ConfigStore) -> Result<(), Error> {
+        config.set(keys::REFRESH_MINUTES, &self.refresh_minutes.to_string())?;
+        config.set(keys::TIMEZONE, &self.timezone)?;
+        config.set(keys::UNITS, self.units.as_str())?;
+        config.set(
+            keys::NEOPIXEL_BRIGHTNESS,
+            &self.neopixel_brightness.to_string(),
+        )?;
+        config.set(keys::CONTRAST, self.contrast.as_str())?;
+        config.set(keys::LANGUAGE, self.language.code())
+    }
+
+    pub fn refresh_interval(&self) -> Duration {
+        Duration::from_minutes(self.refresh_minutes as u64)
+    }
+}
@@ -0,0 +1,428 @@
+//! Line-based command console on the native USB port (CDC-ACM serial).
+//!
+//! Built-in commands:
+//!
+//! - `wifi status`
+//! - `fetch <url>`
+//! - `upload <file> <url>`, a file such as `sd:LOGS/EVENTS.CSV` POSTed as
+//!   the `file` field of a form
+//! - `get config [key]`, `set config <key> <value>`
+//! - `time`
+//! - `refresh`
+//! - `sleep <seconds>`
+//! - `crash [clear]`
+//! - `play [rtttl]`
+//!
+//! `help` lists everything registered, including commands added with
+//! [Console::register]. [Improv](crate::improv) frames on the same port are
+//! answered too, so browser installers can provision WiFi while the apps run.
+
+use alloc::{boxed::Box, string::String, vec::Vec};
+use core::fmt::Write;
+
+use esp_hal::{
+    otg_fs::{Usb, UsbBus},
+    time::Duration,
+};
+use usb_device::{
+    bus::UsbBusAllocator,
+    device::{StringDescriptors, UsbDevice, UsbDeviceBuilder, UsbVidPid},
+};
+use usbd_serial::{SerialPort, USB_CLASS_CDC};
+
+use crate::{
+    app::{Context, Flow},
+    config::keys,
+    crash,
+    http::{self, Request},
+    improv, power, rtttl,
+    storage::{self, Blob},
+    time, wifi,
+};
+
+const MAX_LINE_LEN: usize = 128;
+const PROMPT: &str = "> ";
+/// How much of a fetched body is echoed back
+const FETCH_PREVIEW_LEN: usize = 512;
+
+/// Runs a command with the text after its name
+pub type Handler<'h> = Box<dyn FnMut(&str, &mut Context<'_, '_>, &mut dyn Write) -> Flow + 'h>;
+
+type Bus = UsbBus<Usb<'static>>;
+
+/// USB CDC-ACM serial port
+pub struct UsbSerial {
+    device: UsbDevice<'static, Bus>,
+    port: SerialPort<'static, Bus>,
+}
+
+impl UsbSerial {
+    /// Starts the USB device. Call once; the endpoint memory is never freed.
+    pub fn new(usb: Usb<'static>) -> Self {
+        let ep_memory = Box::leak(alloc::vec![0u32; 1024].into_boxed_slice());
+        let bus: &'static UsbBusAllocator<Bus> = Box::leak(Box::new(UsbBus::new(usb, ep_memory)));
+        let port = SerialPort::new(bus);
+        let device = UsbDeviceBuilder::new(bus, UsbVidPid(0x303a, 0x3001))
+            .strings(&[StringDescriptors::default()
+                .manufacturer("Adafruit")
+                .product("MagTag console")
+                .serial_number("0")])
+            .unwrap()
+            .device_class(USB_CLASS_CDC)
+            .build();
+        Self { device, port }
+    }
+
+    /// Services the USB bus; returns `true` if there may be data to read
+    pub fn poll(&mut self) -> bool {
+        self.device.poll(&mut [&mut self.port])
+    }
+
+    pub fn read(&mut self, buf: &mut [u8]) -> usize {
+        self.port.read(buf).unwrap_or(0)
+    }
+
+    /// Writes `data`, dropping it if no terminal is attached
+    pub fn write(&mut self, mut data: &[u8]) {
+        let mut stalls = 0;
+        while !data.is_empty() && self.port.dtr() && stalls < 1000 {
+            match self.port.write(data) {
+                Ok(len) => {
+                    data = &data[len..];
+                    stalls = 0;
+                }
+                Err(_) => {
+                    self.poll();
+                    stalls += 1;
+                }
+            }
+        }
+    }
+}
+
+impl Write for UsbSerial {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        for (i, line) in s.split('\n').enumerate() {
+            if i > 0 {
+                self.write(b"\r\n");
+            }
+            self.write(line.as_bytes());
+        }
+        Ok(())
+    }
+}
+
+struct Command<'h> {
+    name: &'static str,
+    usage: &'static str,
+    handler: Handler<'h>,
+}
+
+/// Reads lines from the serial port and runs the matching command
+pub struct Console<'h> {
+    serial: UsbSerial,
+    line: String,
+    improv: improv::Decoder,
+    commands: Vec<Command<'h>>,
+}
+
+impl<'h> Console<'h> {
+    /// A console with the built-in commands
+    pub fn new(serial: UsbSerial) -> Self {
+        let mut console = Self {
+            serial,
+            line: String::new(),
+            improv: improv::Decoder::new(),
+            commands: Vec::new(),
+        };
+        console.register("wifi", "wifi status", wifi_status);
+        console.register("fetch", "fetch <url>", fetch);
+        console.register("upload", "upload <file> <url>", upload);
+        console.register("get", "get config [key]", get);
+        console.register("set", "set config <key> <value>", set);
+        console.register("time", "time", show_time);
+        console.register("refresh", "refresh", |_, _, _| Flow::Redraw);
+        console.register("sleep", "sleep <seconds>", sleep);
+        console.register("crash", "crash [clear]", crash);
+        console.register("play", "play [rtttl]", play);
+        console
+    }
+
+    /// Adds a command, replacing any existing one with the same name
+    pub fn register(
+        &mut self,
+        name: &'static str,
+        usage: &'static str,
+        handler: impl FnMut(&str, &mut Context<'_, '_>, &mut dyn Write) -> Flow + 'h,
+    ) {
+        self.commands.retain(|c| c.name != name);
+        self.commands.push(Command {
+            name,
+            usage,
+            handler: Box::new(handler),
+        });
+    }
+
+    /// Reads pending input and runs any complete lines
+    pub fn poll(&mut self, ctx: &mut Context<'_, '_>) -> Flow {
+        if !self.serial.poll() {
+            return Flow::Idle;
+        }
+
+        let mut flow = Flow::Idle;
+        let mut buf = [0u8; 64];
+        loop {
+            let len = self.serial.read(&mut buf);
+            if len == 0 {
+                break;
+            }
+            for &byte in &buf[..len] {
+                let text = match self.improv.push(byte) {
+                    improv::Input::Text(text) => text,
+                    improv::Input::Command(command) => {
+                        improv::handle(command, &mut self.serial, ctx.net, ctx.config);
+                        continue;
+                    }
+                    improv::Input::Invalid => {
+                        improv::send_error(&mut self.serial, improv::ErrorState::InvalidRpc);
+                        continue;
+                    }
+                    improv::Input::Pending => continue,
+                };
+                for byte in text {
+                    if self.edit(byte, ctx) == Flow::Redraw {
+                        flow = Flow::Redraw;
+                    }
+                }
+            }
+        }
+        flow
+    }
+
+    /// Applies one typed byte to the line, running it on enter
+    fn edit(&mut self, byte: u8, ctx: &mut Context<'_, '_>) -> Flow {
+        match byte {
+            b'\r' | b'\n' if !self.line.is_empty() => {
+                self.serial.write(b"\r\n");
+                let line = core::mem::take(&mut self.line);
+                let flow = self.execute(line.trim(), ctx);
+                self.serial.write(PROMPT.as_bytes());
+                return flow;
+            }
+            // backspace or delete
+            0x08 | 0x7f if self.line.pop().is_some() => {
+                self.serial.write(b"\x08 \x08");
+            }
+            0x20..=0x7e if self.line.len() < MAX_LINE_LEN => {
+                self.line.push(byte as char);
+                self.serial.write(&[byte]);
+            }
+            _ => {}
+        }
+        Flow::Idle
+    }
+
+    fn execute(&mut self, line: &str, ctx: &mut Context<'_, '_>) -> Flow {
+        let (name, args) = line.split_once(' ').unwrap_or((line, ""));
+        if name == "help" {
+            for command in &self.commands {
+                writeln!(self.serial, "  {}", command.usage).ok();
+            }
+            return Flow::Idle;
+        }
+        match self.commands.iter_mut().find(|c| c.name == name) {
+            Some(command) => (command.handler)(args.trim(), ctx, &mut self.serial),
+            None => {
+                writeln!(self.serial, "unknown command {:?}, try help", name).ok();
+                Flow::Idle
+            }
+        }
+    }
+}
+
+fn wifi_status(args: &str, ctx: &mut Context<'_, '_>, out: &mut dyn Write) -> Flow {
+    if args != "status" {
+        writeln!(out, "usage: wifi status").ok();
+        return Flow::Idle;
+    }
+    match ctx.net.get_ip_info() {
+        Ok(info) if ctx.net.is_iface_up() => writeln!(
+            out,
+            "up, ip {}/{} gateway {} dns {:?}, rssi {:?}",
+            info.ip,
+            info.subnet.mask.0,
+            info.subnet.gateway,
+            info.dns,
+            wifi::rssi()
+        ),
+        _ => writeln!(out, "down"),
+    }
+    .ok();
+    Flow::Idle
+}
+
+fn fetch(args: &str, ctx: &mut Context<'_, '_>, out: &mut dyn Write) -> Flow {
+    match http::get(ctx.net, args) {
+        Ok(response) => {
+            writeln!(out, "{} ({} bytes)", response.status, response.body.len()).ok();
+            let preview = &response.body[..response.body.len().min(FETCH_PREVIEW_LEN)];
+            match core::str::from_utf8(preview) {
+                Ok(text) => writeln!(out, "{}", text).ok(),
+                Err(_) => writeln!(out, "<binary>").ok(),
+            };
+        }
+        Err(err) => {
+            writeln!(out, "error: {}", err).ok();
+        }
+    }
+    Flow::Idle
+}
+
+fn upload(args: &str, ctx: &mut Context<'_, '_>, out: &mut dyn Write) -> Flow {
+    let Some((location, url)) = args.split_once(' ') else {
+        writeln!(out, "usage: upload <file> <url>").ok();
+        return Flow::Idle;
+    };
+    let sent = storage::open(location).and_then(|(store, name)| {
+        let blob = Blob::open(&*store, name)?;
+        let filename = name.rsplit('/').next().unwrap_or(name);
+        let form = http::multipart().file("file", filename, "application/octet-stream", &blob);
+        let response = Request::multipart(url.trim(), &form).send(ctx.net);
+        response
+    });
+    match sent {
+        Ok(response) => writeln!(out, "{}", response.status).ok(),
+        Err(err) => writeln!(out, "error: {}", err).ok(),
+    };
+    Flow::Idle
+}
+
+fn get(args: &str, ctx: &mut Context<'_, '_>, out: &mut dyn Write) -> Flow {
+    let Some(key) = args.strip_prefix("config") else {
+        writeln!(out, "usage: get config [key]").ok();
+        return Flow::Idle;
+    };
+    match key.trim() {
+        "" => {
+            for (k, v) in ctx.config.iter() {
+                let v = if keys::SECRETS.contains(&k) {
+                    "<hidden>"
+                } else {
+                    v
+                };
+                writeln!(out, "{}={}", k, v).ok();
+            }
+        }
+        key if keys::SECRETS.contains(&key) => {
+            writeln!(out, "<hidden>").ok();
+        }
+        key => {
+            writeln!(out, "{}", ctx.config.get(key).unwrap_or("<unset>")).ok();
+        }
+    }
+    Flow::Idle
+}
+
+fn set(args: &str, ctx: &mut Context<'_, '_>, out: &mut dyn Write) -> Flow {
+    let Some((key, value)) = args
+        .strip_prefix("config ")
+        .and_then(|rest| rest.trim().split_once(' '))
+    else {
+        writeln!(out, "usage: set config <key> <value>").ok();
+        return Flow::Idle;
+    };
+    match ctx
+        .config
+        .set(key, value.trim())
+        .and_then(|_| ctx.config.commit())
+    {
+        Ok(()) => writeln!(out, "ok"),
+        Err(err) => writeln!(out, "error: {}", err),
+    }
+    .ok();
+    Flow::Idle
+}
+
+fn show_time(_args: &str, _ctx: &mut Context<'_, '_>, out: &mut dyn Write) -> Flow {
+    match time::now_local() {
+        Some(now) => writeln!(out, "{}", now),
+        None => writeln!(out, "not set"),
+    }
+    .ok();
+    if let Some(age) = time::since_sync() {
+        let how = if time::is_approximate() {
+            ", from an HTTP Date header"
+        } else {
+            ""
+        };
+        writeln!(out, "last sync {} min ago{}", age.as_minutes(), how).ok();
+    }
+    if let Some(drift) = time::drift_ppb() {
+        writeln!(out, "drift {} ppm", drift / 1000).ok();
+    }
+    Flow::Idle
+}
+
+fn sleep(args: &str, _ctx: &mut Context<'_, '_>, out: &mut dyn Write) -> Flow {
+    match args.parse::<u64>() {
+        Ok(secs) => {
+            writeln!(out, "sleeping {} s", secs).ok();
+            power::sleep_for(Duration::from_secs(secs))
+        }
+        Err(_) => {
+            writeln!(out, "usage: sleep <seconds>").ok();
+            Flow::Idle
+        }
+    }
+}
+
+fn crash(args: &str, _ctx: &mut Context<'_, '_>, out: &mut dyn Write) -> Flow {
+    match args {
+        "" => match crash::last() {
+            Some(report) => {
+                writeln!(out, "{}", report).ok();
+                for address in &report.backtrace {
+                    writeln!(out, "  {:#010x}", address).ok();
+                }
+            }
+            None => {
+                writeln!(out, "no crash recorded").ok();
+            }
+        },
+        "clear" => {
+            match crash::clear() {
+                Ok(()) => writeln!(out, "ok"),
+                Err(err) => writeln!(out, "error: {}", err),
+            }
+            .ok();
+        }
+        _ => {
+            writeln!(out, "usage: crash [clear]").ok();
+        }
+    }
+    Flow::Idle
+}
+
+/// Plays the given tune, or the configured one without arguments
+fn play(args: &str, ctx: &mut Context<'_, '_>, out: &mut dyn Write) -> Flow {
+    let melody = match args {
+        "" => rtttl::configured(ctx.config),
+        tune => match tune.parse::<rtttl::Melody>() {
+            Ok(melody) => melody,
+            Err(err) => {
+                writeln!(out, "error: {}", err).ok();
+                return Flow::Idle;
+            }
+        },
+    };
+    writeln!(
+        out,
+        "playing {}, {} notes, {} ms",
+        melody.name,
+        melody.notes.len(),
+        melody.duration().as_millis()
+    )
+    .ok();
+    melody.play();
+    Flow::Idle
+}
@@ -0,0 +1,268 @@
+//! Post-mortem records of panics, watchdog resets and brownouts.
+//!
+//! A panic can happen in the middle of a flash write with the flash lock held,
+//! so [record_panic] only stages the message and backtrace in RTC fast memory
+//! before the reset. [init] moves the record to the `coredump` partition at the
+//! next boot, where it stays until [clear]. Watchdog and brownout resets leave
+//! no message behind, so those are recorded from the reset reason alone,
+//! with the [lifecycle](crate::lifecycle) stage a watchdog reset cut short.
+//!
+//! Registers aren't captured: a Rust panic is an ordinary call, and the
+//! backtrace addresses are what locate it. Decode them with
+//! `xtensa-esp32s2-elf-addr2line -e <elf> <addresses>`.
+
+use alloc::{string::String, vec::Vec};
+use core::{fmt, panic::PanicInfo};
+
+use esp_bootloader_esp_idf::partitions::DataPartitionSubType;
+use esp_hal::{
+    ram,
+    rtc_cntl::SocResetReason,
+    time::{Duration, Instant},
+};
+use log::{error, warn};
+
+use crate::{crc::crc32, flash, lifecycle, Error};
+
+const MAGIC: u32 = u32::from_le_bytes(*b"MTCR");
+const MAX_FRAMES: usize = 16;
+const MAX_MESSAGE_LEN: usize = 228;
+
+/// How the previous run ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrashKind {
+    Panic,
+    Watchdog,
+    Brownout,
+}
+
+impl CrashKind {
+    fn from_raw(raw: u32) -> Option<Self> {
+        match raw {
+            1 => Some(CrashKind::Panic),
+            2 => Some(CrashKind::Watchdog),
+            3 => Some(CrashKind::Brownout),
+            _ => None,
+        }
+    }
+
+    fn raw(self) -> u32 {
+        match self {
+            CrashKind::Panic => 1,
+            CrashKind::Watchdog => 2,
+            CrashKind::Brownout => 3,
+        }
+    }
+}
+
+impl fmt::Display for CrashKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CrashKind::Panic => "panic",
+            CrashKind::Watchdog => "watchdog reset",
+            CrashKind::Brownout => "brownout",
+        })
+    }
+}
+
+/// A recorded crash
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrashReport {
+    pub kind: CrashKind,
+    pub message: String,
+    /// Return addresses, innermost first
+    pub backtrace: Vec<u32>,
+    /// Time since boot when it happened, if known
+    pub uptime: Option<Duration>,
+}
+
+impl fmt::Display for CrashReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if let Some(uptime) = self.uptime {
+            write!(f, " after {} s", uptime.as_secs())?;
+        }
+        if !self.message.is_empty() {
+            write!(f, ": {}", self.message)?;
+        }
+        Ok(())
+    }
+}
+
+/// The record as staged in RTC memory and stored in flash. Every field is an
+/// integer and there is no padding, so it can be copied as bytes.
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct CrashRecord {
+    magic: u32,
+    /// CRC-32 of everything after this field
+    checksum: u32,
+    kind: u32,
+    backtrace_len: u32,
+    backtrace: [u32; MAX_FRAMES],
+    uptime_ms: u64,
+    message_len: u32,
+    message: [u8; MAX_MESSAGE_LEN],
+}
+
+// SAFETY: only integer fields, and garbage is caught by `magic` and `checksum`
+unsafe impl esp_hal::Persistable for CrashRecord {}
+
+const RECORD_LEN: usize = core::mem::size_of::<CrashRecord>();
+const _: () = assert!(RECORD_LEN.is_multiple_of(flash::WORD_SIZE as usize));
+
+impl CrashRecord {
+    const EMPTY: Self = Self {
+        magic: 0,
+        checksum: 0,
+        kind: 0,
+        backtrace_len: 0,
+        backtrace: [0; MAX_FRAMES],
+        uptime_ms: 0,
+        message_len: 0,
+        message: [0; MAX_MESSAGE_LEN],
+    };
+
+    fn new(kind: CrashKind, uptime_ms: u64) -> Self {
+        Self {
+            magic: MAGIC,
+            kind: kind.raw(),
+            uptime_ms,
+            ..Self::EMPTY
+        }
+    }
+
+    fn seal(&mut self) {
+        self.checksum = crc32(&self.as_bytes()[8..]);
+    }
+
+    fn is_valid(&self) -> bool {
+        self.magic == MAGIC
+            && self.checksum == crc32(&self.as_bytes()[8..])
+            && CrashKind::from_raw(self.kind).is_some()
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        // SAFETY: `repr(C)` with integer fields and no padding
+        unsafe { core::slice::from_raw_parts((self as *const Self).cast::<u8>(), RECORD_LEN) }
+    }
+
+    fn from_bytes(bytes: &[u8; RECORD_LEN]) -> Self {
+        // SAFETY: any bit pattern is a valid `CrashRecord`
+        unsafe { core::ptr::read_unaligned(bytes.as_ptr().cast::<Self>()) }
+    }
+
+    fn report(&self) -> CrashReport {
+        let frames = (self.backtrace_len as usize).min(MAX_FRAMES);
+        let message_len = (self.message_len as usize).min(MAX_MESSAGE_LEN);
+        CrashReport {
+            kind: CrashKind::from_raw(self.kind).unwrap_or(CrashKind::Panic),
+            message: String::from_utf8_lossy(&self.message[..message_len]).into(),
+            backtrace: self.backtrace[..frames].to_vec(),
+            uptime: (self.uptime_ms > 0).then(|| Duration::from_millis(self.uptime_ms)),
+        }
+    }
+}
+
+/// Formats into the fixed message buffer, dropping whatever doesn't fit
+struct MessageWriter<'r>(&'r mut CrashRecord);
+
+impl fmt::Write for MessageWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let start = self.0.message_len as usize;
+        let len = s.len().min(MAX_MESSAGE_LEN - start);
+        self.0.message[start..start + len].copy_from_slice(&s.as_bytes()[..len]);
+        self.0.message_len += len as u32;
+        Ok(())
+    }
+}
+
+#[ram(unstable(rtc_fast, persistent))]
+static mut STAGED: CrashRecord = CrashRecord::EMPTY;
+
+/// Stages a panic for [init] to pick up after the reset; call from the panic handler
+///
+/// Doesn't allocate or take any locks besides a critical section.
+pub fn record_panic(info: &PanicInfo<'_>) {
+    let uptime_ms = Instant::now().duration_since_epoch().as_millis();
+    let mut record = CrashRecord::new(CrashKind::Panic, uptime_ms);
+    fmt::write(&mut MessageWriter(&mut record), format_args!("{}", info)).ok();
+
+    let backtrace = esp_backtrace::Backtrace::capture();
+    for (slot, frame) in record.backtrace.iter_mut().zip(backtrace.frames()) {
+        *slot = frame.program_counter() as u32;
+        record.backtrace_len += 1;
+    }
+    record.seal();
+
+    error!("panic: {}", info);
+    for address in &record.backtrace[..record.backtrace_len as usize] {
+        error!("  {:#010x}", address);
+    }
+    critical_section::with(|_| unsafe { STAGED = record });
+}
+
+/// Stores the crash that ended the previous run, if any, and returns it. Call
+/// at boot after [flash::init].
+pub fn init() -> Option<CrashReport> {
+    let staged = critical_section::with(|_| unsafe {
+        let staged = STAGED;
+        STAGED = CrashRecord::EMPTY;
+        staged
+    });
+
+    let record = if staged.is_valid() {
+        staged
+    } else {
+        let kind = match esp_hal::system::reset_reason()? {
+            SocResetReason::CoreMwdt0
+            | SocResetReason::CoreMwdt1
+            | SocResetReason::CoreRtcWdt
+            | SocResetReason::Cpu0Mwdt0
+            | SocResetReason::Cpu0Mwdt1
+            | SocResetReason::Cpu0RtcWdt
+            | SocResetReason::SysRtcWdt
+            | SocResetReason::SysSuperWdt => CrashKind::Watchdog,
+            SocResetReason::SysBrownOut => CrashKind::Brownout,
+            _ => return None,
+        };
+        let mut record = CrashRecord::new(kind, 0);
+        if let Some(stage) = lifecycle::interrupted().filter(|_| kind == CrashKind::Watchdog) {
+            fmt::write(
+                &mut MessageWriter(&mut record),
+                format_args!("{} stage overran", stage),
+            )
+            .ok();
+        }
+        record.seal();
+        record
+    };
+
+    let report = record.report();
+    warn!("Previous run ended in a {}", report);
+    if let Err(err) = store(&record) {
+        warn!("Couldn't save the crash record: {}", err);
+    }
+    Some(report)
+}
+
+/// The most recent crash stored in flash
+pub fn last() -> Option<CrashReport> {
+    let region = flash::find_partition(DataPartitionSubType::Coredump).ok()?;
+    let mut bytes = [0u8; RECORD_LEN];
+    flash::read(region.offset, &mut bytes).ok()?;
+    let record = CrashRecord::from_bytes(&bytes);
+    record.is_valid().then(|| record.report())
+}
+
+/// Forgets the stored crash
+pub fn clear() -> Result<(), Error> {
+    let region = flash::find_partition(DataPartitionSubType::Coredump)?;
+    flash::erase(region.offset, region.offset + flash::SECTOR_SIZE)
+}
+
+fn store(record: &CrashRecord) -> Result<(), Error> {
+    let region = flash::find_partition(DataPartitionSubType::Coredump)?;
+    flash::erase(region.offset, region.offset + flash::SECTOR_SIZE)?;
+    flash::write(region.offset, record.as_bytes())
+}
@@ -0,0 +1,17 @@
+//! CRC-32 (IEEE 802.3, the zlib/PNG one) for checking stored and transferred data.
+
+/// Continues a CRC over `data`; start with `0`
+pub fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    crc32_update(0, data)
+}
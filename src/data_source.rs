@@ -0,0 +1,211 @@
+//! Data fetched over HTTP that outlives a failed fetch.
+//!
+//! [DataSource::fetch] gets the URL and, when the network or the server lets
+//! it down, hands back the last payload that did come through along with
+//! [Stale] saying since when. Apps keep showing that, with
+//! [draw_stale_banner](crate::ui::stale::draw_stale_banner) on top, instead
+//! of an error or a screen frozen on old data without saying so.
+//!
+//! The last payload is kept in RAM and, with [DataSource::persist], under a
+//! name in the asset store or on the SD card, so a badge that restarts or
+//! wakes from deep sleep without a network still has something to show. The
+//! stored copy is written when the payload changed, or at most every
+//! [PERSIST_INTERVAL] to keep its timestamp current, to spare the flash.
+
+use alloc::{string::String, vec::Vec};
+
+use jiff::Timestamp;
+use log::{info, warn};
+
+use crate::{crc::crc32, http, net::NetStack, storage, time, Error};
+
+/// How often a payload that hasn't changed is stored again
+pub const PERSIST_INTERVAL: core::time::Duration = core::time::Duration::from_secs(60 * 60);
+
+/// In front of a stored payload: when it was fetched, in Unix seconds, or
+/// zero when the clock wasn't set
+const HEADER_LEN: usize = 8;
+
+/// A payload from [DataSource::fetch]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Payload<'d> {
+    pub body: &'d [u8],
+    /// `Some` when this is the last good payload rather than a fresh one
+    pub stale: Option<Stale>,
+}
+
+/// Why and since when a [Payload] is old
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stale {
+    /// When the payload was fetched, if the clock was set then
+    pub since: Option<Timestamp>,
+    /// What went wrong with the fetch just now
+    pub error: Error,
+}
+
+struct Cached {
+    body: Vec<u8>,
+    fetched_at: Option<Timestamp>,
+    crc: u32,
+    /// When the stored copy was last written
+    persisted_at: Option<Timestamp>,
+}
+
+/// A URL and the last payload that came from it
+pub struct DataSource {
+    url: String,
+    /// Where the payload is stored, see [storage::open]
+    location: Option<String>,
+    cached: Option<Cached>,
+    /// Whether `location` has been read yet
+    loaded: bool,
+}
+
+impl DataSource {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.into(),
+            location: None,
+            cached: None,
+            loaded: false,
+        }
+    }
+
+    /// Also keeps the last payload at `location`, such as `forecast.json` in
+    /// the asset store or `sd:CACHE/FORECAST.JSN`
+    pub fn persist(mut self, location: &str) -> Self {
+        self.location = Some(location.into());
+        self
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Gets the URL, or falls back to [DataSource::cached] when that fails.
+    /// Errors only when there is nothing to fall back to.
+    pub fn fetch(&mut self, net: &NetStack<'_>) -> Result<Payload<'_>, Error> {
+        match self.get(net) {
+            Ok(body) => {
+                self.store(body);
+                Ok(self.payload(None))
+            }
+            Err(error) => {
+                let Some(cached) = self.cached() else {
+                    return Err(error);
+                };
+                let since = cached.stale.and_then(|stale| stale.since);
+                warn!("{}: {}, showing data from {:?}", self.url, error, since);
+                Ok(self.payload(Some(error)))
+            }
+        }
+    }
+
+    /// The last payload that came through, from RAM or where it was
+    /// [persist](DataSource::persist)ed; its [Payload::stale] has no error
+    /// of its own, so it reads [Error::Network]
+    pub fn cached(&mut self) -> Option<Payload<'_>> {
+        if !self.loaded {
+            self.loaded = true;
+            if self.cached.is_none() {
+                self.cached = self.load();
+            }
+        }
+        self.cached.as_ref()?;
+        Some(self.payload(Some(Error::Network)))
+    }
+
+    fn payload(&self, error: Option<Error>) -> Payload<'_> {
+        let cached = self.cached.as_ref().expect("payload without a cache");
+        Payload {
+            body: &cached.body,
+            stale: error.map(|error| Stale {
+                since: cached.fetched_at,
+                error,
+            }),
+        }
+    }
+
+    fn get(&self, net: &NetStack<'_>) -> Result<Vec<u8>, Error> {
+        let response = http::get(net, &self.url)?;
+        if !response.is_success() {
+            warn!("{}: HTTP {}", self.url, response.status);
+            return Err(Error::Network);
+        }
+        Ok(response.body)
+    }
+
+    /// Keeps `body` as the last payload, writing it out if it's due
+    fn store(&mut self, body: Vec<u8>) {
+        let now = time::now_utc();
+        let crc = crc32(&body);
+        let persisted_at = self
+            .cached
+            .as_ref()
+            .filter(|cached| cached.crc == crc)
+            .and_then(|cached| cached.persisted_at);
+        let due = match (persisted_at, now) {
+            (Some(at), Some(now)) => now.duration_since(at).unsigned_abs() >= PERSIST_INTERVAL,
+            (Some(_), None) => false,
+            (None, _) => true,
+        };
+        let mut cached = Cached {
+            body,
+            fetched_at: now,
+            crc,
+            persisted_at,
+        };
+        if due {
+            if let Some(location) = &self.location {
+                match write(location, &cached) {
+                    Ok(()) => cached.persisted_at = now,
+                    Err(err) => warn!("Keeping {} at {} failed: {}", self.url, location, err),
+                }
+            }
+        }
+        self.loaded = true;
+        self.cached = Some(cached);
+    }
+
+    fn load(&self) -> Option<Cached> {
+        let location = self.location.as_deref()?;
+        let read = storage::open(location).and_then(|(store, name)| store.read_to_vec(name));
+        let mut data = match read {
+            Ok(data) if data.len() >= HEADER_LEN => data,
+            Ok(_) => return None,
+            Err(Error::NotFound) => return None,
+            Err(err) => {
+                warn!("Reading {} failed: {}", location, err);
+                return None;
+            }
+        };
+        let unix = u64::from_le_bytes(data[..HEADER_LEN].try_into().unwrap());
+        let fetched_at = (unix > 0)
+            .then(|| Timestamp::from_second(unix as i64).ok())
+            .flatten();
+        let body = data.split_off(HEADER_LEN);
+        info!(
+            "{}: {} bytes kept from {:?}",
+            self.url,
+            body.len(),
+            fetched_at
+        );
+        Some(Cached {
+            crc: crc32(&body),
+            body,
+            fetched_at,
+            persisted_at: fetched_at,
+        })
+    }
+}
+
+fn write(location: &str, cached: &Cached) -> Result<(), Error> {
+    let unix = cached
+        .fetched_at
+        .map_or(0, |at| at.as_second().max(0) as u64);
+    let mut data = Vec::with_capacity(HEADER_LEN + cached.body.len());
+    data.extend_from_slice(&unix.to_le_bytes());
+    data.extend_from_slice(&cached.body);
+    let (mut store, name) = storage::open(location)?;
+    store.write(name, &data)
+}
@@ -0,0 +1,331 @@
+//! Sensor readings logged to files, as CSV or InfluxDB line protocol, and
+//! pushed to a server once each file is complete.
+//!
+//! `datalog` is where the files go: `sd:` and a directory that exists on
+//! the SD card, such as `sd:LOGS`, or `assets` for the asset partition.
+//! Every `datalog_s` seconds ([DEFAULT_INTERVAL] if unset) the host reads
+//! the [sensors] `datalog_fields` names, [sensors::DEFAULT_FIELDS] if unset,
+//! and appends one line with the UTC time.
+//!
+//! A reading that isn't there is left empty in CSV and out of the line in
+//! line protocol. Nothing is logged until the clock has been set.
+//!
+//! `datalog_format` is `csv` (the default), whose files start with a header
+//! of the field names, or `line`, for lines such as
+//! `magtag,badge=lobby battery.mv=3950i,light.mv=212i 1760000000` with the
+//! `badge_name` tag if there is one and the time in seconds. Files are
+//! named by the local day and a part number, `26101400.CSV` then
+//! `26101401.CSV` and so on (`.LP` for line protocol): a new part starts
+//! once a file reaches `datalog_kb` KiB ([DEFAULT_MAX_KB] if unset), or
+//! when the fields change.
+//!
+//! With `datalog_url` set, every [UPLOAD_INTERVAL] the files before the one
+//! being written are POSTed there one at a time as the `file` field of a
+//! multipart form, with the HTTP credentials if any are set, oldest first.
+//! Each one a server takes with a 2xx status is deleted; the rest wait for
+//! the next round.
+//!
+//! On the SD card lines are appended in place. The asset partition writes a
+//! file whole, so there each line rewrites the part it goes in; keep
+//! `datalog_kb` small there.
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use esp_hal::time::Duration;
+use log::{debug, info, warn};
+
+use crate::{
+    config::{keys, ConfigStore},
+    http::{self, Auth, Request},
+    net::NetStack,
+    sensors::{self, Readings},
+    storage::{self, Blob, BlobStore},
+    time, wifi, Error,
+};
+
+pub const DEFAULT_INTERVAL: Duration = Duration::from_minutes(5);
+pub const DEFAULT_MAX_KB: u32 = 16;
+pub const UPLOAD_INTERVAL: Duration = Duration::from_minutes(60);
+/// The line protocol measurement
+const MEASUREMENT: &str = "magtag";
+
+/// How lines are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    LineProtocol,
+}
+
+impl Format {
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Csv => "CSV",
+            Format::LineProtocol => "LP",
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            Format::Csv => "text/csv",
+            Format::LineProtocol => "text/plain",
+        }
+    }
+}
+
+/// Where readings are logged, which and how often, from the config store
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataLog {
+    location: String,
+    fields: Vec<String>,
+    format: Format,
+    interval: Duration,
+    max_len: u32,
+    /// The `badge` tag in line protocol
+    badge: Option<String>,
+    upload_url: Option<String>,
+    auth: Option<Auth>,
+}
+
+impl DataLog {
+    /// `None` when no `datalog` location is set
+    pub fn from_config(config: &ConfigStore) -> Option<Self> {
+        let location = config
+            .get(keys::DATALOG)
+            .map(str::trim)
+            .filter(|s| !s.is_empty())?;
+        let fields = sensors::parse_fields(config.get(keys::DATALOG_FIELDS));
+        let format = match config.get(keys::DATALOG_FORMAT).map(str::trim) {
+            None | Some("csv") => Format::Csv,
+            Some("line") => Format::LineProtocol,
+            Some(other) => {
+                warn!(
+                    "{}={}: not csv or line, logging CSV",
+                    keys::DATALOG_FORMAT,
+                    other
+                );
+                Format::Csv
+            }
+        };
+        let interval = config
+            .get_parsed::<u32>(keys::DATALOG_SECONDS)
+            .filter(|&secs| secs > 0)
+            .map_or(DEFAULT_INTERVAL, |secs| Duration::from_secs(secs as u64));
+        let max_kb = config
+            .get_parsed::<u32>(keys::DATALOG_MAX_KB)
+            .filter(|&kb| kb > 0)
+            .unwrap_or(DEFAULT_MAX_KB);
+        Some(Self {
+            location: location.into(),
+            fields,
+            format,
+            interval,
+            max_len: max_kb.saturating_mul(1024),
+            badge: config
+                .get(keys::BADGE_NAME)
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(String::from),
+            upload_url: config
+                .get(keys::DATALOG_URL)
+                .map(str::trim)
+                .filter(|url| !url.is_empty())
+                .map(String::from),
+            auth: Auth::from_config(config),
+        })
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Whether there is a `datalog_url` to upload to
+    pub fn uploads(&self) -> bool {
+        self.upload_url.is_some()
+    }
+
+    /// Reads the fields and appends a line with them
+    pub fn sample(&self) -> Result<(), Error> {
+        let (Some(readings), Some(today)) = (Readings::take(&self.fields), time::now_local())
+        else {
+            debug!("datalog: clock not set yet");
+            return Ok(());
+        };
+        let line = match self.format {
+            Format::Csv => csv_line(&readings),
+            Format::LineProtocol => {
+                match readings.line_protocol(MEASUREMENT, self.badge.as_deref()) {
+                    Some(line) => line,
+                    None => return Ok(()),
+                }
+            }
+        };
+
+        let mut store = storage::open_dir(&self.location)?;
+        let day = format!(
+            "{:02}{:02}{:02}",
+            today.year() % 100,
+            today.month(),
+            today.day()
+        );
+        let header = self.header();
+        let name = self.current(&*store, &day, &header, line.len())?;
+        if self.format == Format::Csv && store.len(&name).is_err() {
+            store.append(&name, format!("{}\n", header).as_bytes())?;
+        }
+        store.append(&name, line.as_bytes())
+    }
+
+    /// The part of `day` the next `len` bytes go in
+    fn current(
+        &self,
+        store: &dyn BlobStore,
+        day: &str,
+        header: &str,
+        len: usize,
+    ) -> Result<String, Error> {
+        let last = self
+            .files(store)?
+            .into_iter()
+            .filter(|name| name.starts_with(day) && name.ends_with(self.format.extension()))
+            .filter_map(|name| part(&name))
+            .max();
+        let Some(last) = last else {
+            return Ok(self.name(day, 0));
+        };
+        let name = self.name(day, last);
+        let full = store.len(&name)? as usize + len > self.max_len as usize;
+        if full || (self.format == Format::Csv && !starts_with(store, &name, header)) {
+            let next = self.name(day, last + 1);
+            info!("datalog: starting {}", next);
+            return Ok(next);
+        }
+        Ok(name)
+    }
+
+    /// `26101400.CSV` for part 0 of 14 October 2026
+    fn name(&self, day: &str, part: u32) -> String {
+        format!("{}{:02}.{}", day, part.min(99), self.format.extension())
+    }
+
+    /// The log files in `store`, oldest first
+    fn files(&self, store: &dyn BlobStore) -> Result<Vec<String>, Error> {
+        let mut names: Vec<String> = store
+            .names()?
+            .into_iter()
+            .filter(|name| is_log_file(name))
+            .collect();
+        names.sort();
+        Ok(names)
+    }
+
+    fn header(&self) -> String {
+        let mut header = String::from("time");
+        for field in &self.fields {
+            header.push(',');
+            header += &csv_value(field);
+        }
+        header
+    }
+
+    /// POSTs the complete files to `datalog_url`, deleting each one taken
+    pub fn upload(&self, net: &NetStack<'_>) -> Result<(), Error> {
+        let Some(url) = &self.upload_url else {
+            return Ok(());
+        };
+        if !wifi::is_connected() {
+            return Err(Error::Network);
+        }
+        let mut store = storage::open_dir(&self.location)?;
+        let mut files = self.files(&*store)?;
+        // the last one in this format is still being written
+        let writing = files
+            .iter()
+            .rposition(|name| name.ends_with(self.format.extension()));
+        if let Some(writing) = writing {
+            files.remove(writing);
+        }
+        for name in files {
+            self.upload_file(net, url, &mut *store, &name)?;
+        }
+        Ok(())
+    }
+
+    fn upload_file(
+        &self,
+        net: &NetStack<'_>,
+        url: &str,
+        store: &mut dyn BlobStore,
+        name: &str,
+    ) -> Result<(), Error> {
+        let content_type = if name.ends_with(Format::Csv.extension()) {
+            Format::Csv.content_type()
+        } else {
+            Format::LineProtocol.content_type()
+        };
+        let status = {
+            let blob = Blob::open(&*store, name)?;
+            let form = http::multipart().file("file", name, content_type, &blob);
+            let mut request = Request::multipart(url, &form);
+            if let Some(auth) = &self.auth {
+                request = request.auth(auth);
+            }
+            request.send(net)?.status
+        };
+        if !(200..300).contains(&status) {
+            warn!("datalog: {} answered {} for {}", url, status, name);
+            return Err(Error::Network);
+        }
+        info!("datalog: uploaded {}", name);
+        store.remove(name)
+    }
+}
+
+/// Eight digits of day and part, then the extension of either format
+fn is_log_file(name: &str) -> bool {
+    let Some((stem, extension)) = name.split_once('.') else {
+        return false;
+    };
+    stem.len() == 8
+        && stem.bytes().all(|b| b.is_ascii_digit())
+        && [Format::Csv, Format::LineProtocol]
+            .iter()
+            .any(|format| extension.eq_ignore_ascii_case(format.extension()))
+}
+
+fn part(name: &str) -> Option<u32> {
+    name.get(6..8)?.parse().ok()
+}
+
+/// Whether `name` starts with the line `header`
+fn starts_with(store: &dyn BlobStore, name: &str, header: &str) -> bool {
+    let mut buf = alloc::vec![0u8; header.len() + 1];
+    match store.read(name, 0, &mut buf) {
+        Ok(len) => buf[..len] == *format!("{}\n", header).as_bytes(),
+        Err(_) => false,
+    }
+}
+
+fn csv_line(readings: &Readings) -> String {
+    let mut line = readings.time.to_string();
+    for (_, value) in &readings.values {
+        line.push(',');
+        if let Some(value) = value {
+            line += &csv_value(value);
+        }
+    }
+    line.push('\n');
+    line
+}
+
+/// Quoted if it has a comma, quote or line break in it
+fn csv_value(value: &str) -> String {
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        String::from(value)
+    }
+}
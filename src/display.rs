@@ -0,0 +1,357 @@
+//! The SSD1680 panel together with the Gray2 framebuffer apps draw into; which
+//! panel that is comes from [board](crate::board).
+//!
+//! The panel has no backlight, so [Contrast] stands in for a brightness
+//! control: it maps the four levels onto fewer on the way to the panel, as
+//! light gray washes out in bright sun. The framebuffer keeps what the apps
+//! drew. Which bits of the driver's two planes make up each level is read off
+//! the framebuffer at [Display::new], so the mapping works on whatever layout
+//! the driver uses; a layout that isn't one bit per plane leaves it off.
+//!
+//! The driver waits on the panel's BUSY line with no way out, so a panel that
+//! never signals ready, from a loose cable or a brownout, would hang the
+//! badge. The waits it asks for go through a delay of ours, and when they add
+//! up to more than the [busy timeout](Display::set_busy_timeout) in one call
+//! the [BusyPin] reads idle, so the driver returns and the call fails with
+//! [Error::DisplayTimeout]. The next flush resets the panel and sets it up
+//! again first; while it keeps timing out, flushes fail at once for twice as
+//! long after each timeout, up to [MAX_BACKOFF], rather than holding up the
+//! apps for the whole timeout every time.
+
+use alloc::vec::Vec;
+use core::{
+    convert::Infallible,
+    str::FromStr,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use embedded_graphics::{pixelcolor::Gray2, prelude::*};
+use embedded_hal::{
+    delay::DelayNs,
+    digital::{ErrorType, InputPin},
+};
+use esp_hal::{
+    delay::Delay,
+    gpio::{Input, Output},
+    time::{Duration, Instant, Rate},
+};
+use log::warn;
+use ssd1680::prelude::*;
+
+use crate::{board, psram, Error};
+
+/// Panel width in pixels (landscape)
+pub const WIDTH: u32 = board::WIDTH;
+/// Panel height in pixels (landscape)
+pub const HEIGHT: u32 = board::HEIGHT;
+/// A gray refresh takes about three seconds, longer in the cold
+pub const DEFAULT_BUSY_TIMEOUT: Duration = Duration::from_secs(30);
+/// Longest a panel that keeps timing out is left alone between tries
+pub const MAX_BACKOFF: Duration = Duration::from_hours(1);
+
+/// The panel's SPI clock, which devices sharing its bus run at too
+pub const SPI_RATE: Rate = Rate::from_mhz(4);
+
+/// SPI device the panel sits on, one of the [board::SpiBus]'s
+pub type SpiDevice = board::SpiDevice;
+
+/// The board's panel driver
+pub type Epd = board::Driver<SpiDevice, BusyPin, Output<'static>, Output<'static>>;
+
+/// Framebuffer apps render into
+pub type Frame = board::Framebuffer;
+
+/// How the four gray levels are shown, stored under the `contrast` key as
+/// `normal`, `high` or `mono`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Contrast {
+    #[default]
+    Normal,
+    /// Light gray shown as white
+    High,
+    /// Only black and white, the grays going to the nearer one
+    Mono,
+}
+
+impl Contrast {
+    pub const ALL: [Contrast; 3] = [Contrast::Normal, Contrast::High, Contrast::Mono];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Contrast::Normal => "normal",
+            Contrast::High => "high",
+            Contrast::Mono => "mono",
+        }
+    }
+
+    /// The level a pixel drawn at `level` is shown at, both 0 (black) to 3
+    pub fn level(self, level: u8) -> u8 {
+        match (self, level) {
+            (Contrast::High, 2) => 3,
+            (Contrast::Mono, 0 | 1) => 0,
+            (Contrast::Mono, _) => 3,
+            _ => level,
+        }
+    }
+}
+
+impl FromStr for Contrast {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Contrast::ALL
+            .into_iter()
+            .find(|contrast| contrast.as_str() == s)
+            .ok_or(())
+    }
+}
+
+/// Owns the panel driver and its framebuffer
+pub struct Display {
+    epd: Epd,
+    frame: Frame,
+    contrast: Contrast,
+    /// Each level's bit in the high plane and the low plane, as `0b_hl`
+    codes: Option<[u8; 4]>,
+    /// Both planes after [Contrast], high then low, made on first use
+    remapped: Vec<u8>,
+    busy_timeout: Duration,
+    /// Set by a timeout: the panel is reset before the next flush, which
+    /// fails at once until then
+    stalled: Option<Stall>,
+}
+
+/// Timeouts in a row and when to try the panel again
+#[derive(Debug, Clone, Copy)]
+struct Stall {
+    timeouts: u32,
+    retry_at: Instant,
+}
+
+impl Display {
+    /// Wraps an already constructed driver. Call [Display::begin] before the first flush.
+    pub fn new(epd: Epd) -> Self {
+        let mut frame = Frame::new();
+        let codes = level_codes(&mut frame);
+        if codes.is_none() {
+            warn!("unknown framebuffer layout, contrast stays normal");
+        }
+        Self {
+            epd,
+            frame,
+            contrast: Contrast::Normal,
+            codes,
+            remapped: Vec::new(),
+            busy_timeout: DEFAULT_BUSY_TIMEOUT,
+            stalled: None,
+        }
+    }
+
+    /// How long one driver call may wait on the panel before it fails with
+    /// [Error::DisplayTimeout]
+    pub fn set_busy_timeout(&mut self, timeout: Duration) {
+        self.busy_timeout = timeout;
+    }
+
+    /// Shows the levels drawn from the next flush on as `contrast` maps them
+    pub fn set_contrast(&mut self, contrast: Contrast) {
+        self.contrast = contrast;
+    }
+
+    /// Resets and initializes the panel
+    pub fn begin(&mut self) -> Result<(), Error> {
+        let mut delay = OverlapDelay::new(None::<fn()>, self.busy_timeout);
+        let result = self.epd.begin(&mut delay).map_err(|_| Error::Display);
+        self.settle(result)
+    }
+
+    /// The framebuffer; drawing here has no visible effect until [Display::flush]
+    pub fn frame(&mut self) -> &mut Frame {
+        &mut self.frame
+    }
+
+    /// Transfers the framebuffer and runs a full refresh
+    pub fn flush(&mut self) -> Result<(), Error> {
+        self.flush_while(|| {})
+    }
+
+    /// [Display::flush], running `work` while the panel refreshes rather than
+    /// only waiting on its busy line. The driver's first wait comes after the
+    /// framebuffer is sent, so `work` can't hold up the refresh; the flush
+    /// takes as long as whichever of the two is slower.
+    pub fn flush_while(&mut self, work: impl FnOnce()) -> Result<(), Error> {
+        if let Some(stall) = self.stalled {
+            let begun = if Instant::now() < stall.retry_at {
+                Err(Error::DisplayTimeout)
+            } else {
+                self.begin()
+            };
+            if let Err(err) = begun {
+                work();
+                return Err(err);
+            }
+        }
+        let mut delay = OverlapDelay::new(Some(work), self.busy_timeout);
+        let (high, low) = if self.remap() {
+            self.remapped.split_at(self.frame.high_buffer().len())
+        } else {
+            (self.frame.high_buffer(), self.frame.low_buffer())
+        };
+        let result = self
+            .epd
+            .update_gray2_and_display(high, low, &mut delay)
+            .map_err(|_| Error::Display);
+        // a refresh that never waited, or failed before it did
+        if let Some(work) = delay.work.take() {
+            work();
+        }
+        self.settle(result)
+    }
+
+    /// `result` of a driver call, or [Error::DisplayTimeout] if the call gave
+    /// up on the panel, which then stalls for a while
+    fn settle(&mut self, result: Result<(), Error>) -> Result<(), Error> {
+        if !GAVE_UP.load(Ordering::Relaxed) {
+            self.stalled = None;
+            return result;
+        }
+        let timeouts = self.stalled.map_or(0, |stall| stall.timeouts) + 1;
+        let backoff = (self.busy_timeout * 2u32.pow(timeouts.min(16) - 1)).min(MAX_BACKOFF);
+        warn!(
+            "panel busy for over {} s, trying it again in {} s",
+            self.busy_timeout.as_secs(),
+            backoff.as_secs()
+        );
+        self.stalled = Some(Stall {
+            timeouts,
+            retry_at: Instant::now() + backoff,
+        });
+        Err(Error::DisplayTimeout)
+    }
+
+    /// Writes the frame with [Contrast] applied to `remapped`; false if it
+    /// goes to the panel as drawn
+    fn remap(&mut self) -> bool {
+        let Some(codes) = self.codes.filter(|_| self.contrast != Contrast::Normal) else {
+            return false;
+        };
+        let (high, low) = (self.frame.high_buffer(), self.frame.low_buffer());
+        if self.remapped.len() != high.len() + low.len() {
+            self.remapped = psram::zeroed(high.len() + low.len());
+        }
+        let (out_high, out_low) = self.remapped.split_at_mut(high.len());
+        for (i, (&h, &l)) in high.iter().zip(low).enumerate() {
+            let (mut new_h, mut new_l) = (0, 0);
+            for (level, &code) in codes.iter().enumerate() {
+                // the pixels of this byte at `level`
+                let at_level = plane_bits(code & 0b10 != 0, h) & plane_bits(code & 0b01 != 0, l);
+                let shown = codes[self.contrast.level(level as u8) as usize];
+                if shown & 0b10 != 0 {
+                    new_h |= at_level;
+                }
+                if shown & 0b01 != 0 {
+                    new_l |= at_level;
+                }
+            }
+            out_high[i] = new_h;
+            out_low[i] = new_l;
+        }
+        true
+    }
+}
+
+/// The bits of each level in the two planes, from clearing `frame` to each
+/// in turn; `None` unless every level sets every bit of each plane alike and
+/// the four come out different
+fn level_codes(frame: &mut Frame) -> Option<[u8; 4]> {
+    let mut codes = [0u8; 4];
+    for (level, code) in codes.iter_mut().enumerate() {
+        frame.clear(Gray2::new(level as u8)).ok();
+        let bit = |plane: &[u8]| match plane.first()? {
+            0x00 if plane.iter().all(|&b| b == 0x00) => Some(0),
+            0xff if plane.iter().all(|&b| b == 0xff) => Some(1),
+            _ => None,
+        };
+        *code = bit(frame.high_buffer())? << 1 | bit(frame.low_buffer())?;
+    }
+    frame.clear(Gray2::WHITE).ok();
+    let distinct = (0..4).all(|i| !codes[i + 1..].contains(&codes[i]));
+    distinct.then_some(codes)
+}
+
+/// `bits` where the plane's bit is set, else the bits that are clear
+fn plane_bits(set: bool, bits: u8) -> u8 {
+    if set {
+        bits
+    } else {
+        !bits
+    }
+}
+
+/// Set once the waits of the driver call in progress add up to the busy
+/// timeout, for [BusyPin]
+static GAVE_UP: AtomicBool = AtomicBool::new(false);
+
+/// The panel's BUSY input, high while the panel is busy, as the driver sees
+/// it: idle once the call in progress has given up on the panel
+pub struct BusyPin(Input<'static>);
+
+impl BusyPin {
+    pub fn new(pin: Input<'static>) -> Self {
+        Self(pin)
+    }
+}
+
+impl ErrorType for BusyPin {
+    type Error = Infallible;
+}
+
+impl InputPin for BusyPin {
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.0.is_high() && !GAVE_UP.load(Ordering::Relaxed))
+    }
+
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        self.is_high().map(|high| !high)
+    }
+}
+
+/// A delay that runs `work` in place of the first wait, and gives up on the
+/// panel once the waits asked for add up to `timeout`, waiting no more from
+/// then on. What `work` takes beyond its wait doesn't count.
+struct OverlapDelay<W: FnOnce()> {
+    work: Option<W>,
+    delay: Delay,
+    waited_ns: u64,
+    timeout: Duration,
+}
+
+impl<W: FnOnce()> OverlapDelay<W> {
+    fn new(work: Option<W>, timeout: Duration) -> Self {
+        GAVE_UP.store(false, Ordering::Relaxed);
+        Self {
+            work,
+            delay: Delay::new(),
+            waited_ns: 0,
+            timeout,
+        }
+    }
+}
+
+impl<W: FnOnce()> DelayNs for OverlapDelay<W> {
+    fn delay_ns(&mut self, ns: u32) {
+        self.waited_ns += ns as u64;
+        if self.waited_ns > self.timeout.as_micros() * 1000 {
+            GAVE_UP.store(true, Ordering::Relaxed);
+            return;
+        }
+        let Some(work) = self.work.take() else {
+            self.delay.delay_ns(ns);
+            return;
+        };
+        let started = Instant::now();
+        work();
+        let spent = started.elapsed().as_micros().min(u32::MAX as u64 / 1000) as u32 * 1000;
+        self.delay.delay_ns(ns.saturating_sub(spent));
+    }
+}
@@ -0,0 +1,278 @@
+//! A rotary encoder, and buttons on the [expansion](crate::expansion) pads,
+//! so menus aren't limited to the four front buttons. Both feed the same
+//! [queue](crate::input::push) as those, from [Buttons::poll].
+//!
+//! A turn by one detent is a press of one front button, and a turn back a
+//! press of another: D and A by default, next and back on most screens. The
+//! knob's push switch is a press of C. `encoder_keys` changes them, see
+//! [Keys]. That way every app works with a knob without knowing about it.
+//!
+//! The `encoder` config entry picks the encoder: `seesaw` for Adafruit's I2C
+//! QT Rotary Encoder on the STEMMA QT connector, at [SEESAW_ADDRESS], or two
+//! pads for the A and B lines of a bare quadrature encoder, such as
+//! `A1,D10`. The pads are polled with the buttons, every few milliseconds,
+//! which keeps up with a knob turned by hand; swap them if it counts
+//! backwards. A bare encoder's push switch is a button pad.
+//!
+//! `button_pads` puts buttons to ground on pads, each standing for a front
+//! button, such as `D10=A` for a big button that does what A does.
+
+use core::str::FromStr;
+
+use esp_hal::{
+    delay::Delay,
+    gpio::{Input, InputConfig, Pull},
+    time::{Duration, Instant},
+};
+use log::{info, warn};
+
+use crate::{
+    config::{keys, ConfigStore},
+    expansion::{self, Pad},
+    i2c,
+    input::{Button, Buttons, InputEvent},
+};
+
+/// The I2C QT Rotary Encoder's address with no jumpers cut
+pub const SEESAW_ADDRESS: u8 = 0x36;
+
+const STATUS_BASE: u8 = 0x00;
+const STATUS_HW_ID: u8 = 0x01;
+const GPIO_BASE: u8 = 0x01;
+const GPIO_DIRCLR_BULK: u8 = 0x03;
+const GPIO_BULK: u8 = 0x04;
+const GPIO_BULK_SET: u8 = 0x05;
+const GPIO_PULLENSET: u8 = 0x0b;
+const ENCODER_BASE: u8 = 0x11;
+const ENCODER_DELTA: u8 = 0x40;
+/// The seesaw GPIO the push switch is on
+const SWITCH_PIN: u32 = 24;
+/// How long the seesaw takes to have a register ready to read
+const SEESAW_DELAY_US: u32 = 250;
+/// Each read costs a bus transaction and [SEESAW_DELAY_US], so not every loop
+const SEESAW_POLL: Duration = Duration::from_millis(20);
+
+/// Detents kept for reporting, so a fast spin doesn't run on long after
+const MAX_PENDING: i32 = 8;
+/// Quarter steps for each pair of previous and current A and B levels
+const QUARTER_STEPS: [i8; 16] = [0, -1, 1, 0, 1, 0, 0, -1, -1, 0, 0, 1, 0, 1, -1, 0];
+/// Quarter steps in one detent of most encoders
+const QUARTERS_PER_DETENT: i8 = 4;
+
+/// The buttons a turn counter-clockwise, a turn clockwise and a push stand
+/// for, stored under `encoder_keys` as `ccw,cw,push` such as `A,D,C`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Keys {
+    pub counter_clockwise: Button,
+    pub clockwise: Button,
+    pub push: Button,
+}
+
+impl Default for Keys {
+    fn default() -> Self {
+        Self {
+            counter_clockwise: Button::A,
+            clockwise: Button::D,
+            push: Button::C,
+        }
+    }
+}
+
+impl FromStr for Keys {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut buttons = s.split(',').map(str::parse);
+        let keys = Self {
+            counter_clockwise: buttons.next().ok_or(())??,
+            clockwise: buttons.next().ok_or(())??,
+            push: buttons.next().ok_or(())??,
+        };
+        match buttons.next() {
+            None => Ok(keys),
+            Some(_) => Err(()),
+        }
+    }
+}
+
+enum Source {
+    Seesaw {
+        next_poll: Instant,
+    },
+    Quadrature {
+        a: Input<'static>,
+        b: Input<'static>,
+        levels: u8,
+    },
+}
+
+/// A rotary encoder, reporting its turns as presses
+pub struct Encoder {
+    source: Source,
+    keys: Keys,
+    /// Quarter steps toward the next detent
+    quarters: i8,
+    /// Detents not reported yet, negative counter-clockwise
+    pending: i32,
+    pushed: bool,
+    clicked: bool,
+}
+
+impl Encoder {
+    /// The I2C QT Rotary Encoder, if it answers
+    pub fn seesaw(keys: Keys) -> Option<Self> {
+        let id = seesaw_read::<1>(STATUS_BASE, STATUS_HW_ID)?;
+        let mask = (1u32 << SWITCH_PIN).to_be_bytes();
+        // the switch is an input pulled up, low when pushed
+        let setup = [GPIO_DIRCLR_BULK, GPIO_PULLENSET, GPIO_BULK_SET]
+            .into_iter()
+            .all(|function| seesaw_write(GPIO_BASE, function, &mask));
+        if !setup {
+            warn!("Seesaw encoder setup failed");
+            return None;
+        }
+        // reading the delta clears turns from before
+        seesaw_read::<4>(ENCODER_BASE, ENCODER_DELTA);
+        info!("Seesaw encoder, hardware {:#04x}", id[0]);
+        Some(Self::new(
+            Source::Seesaw {
+                next_poll: Instant::now(),
+            },
+            keys,
+        ))
+    }
+
+    /// A quadrature encoder with its A and B lines on the two pads, common
+    /// to ground; `None` unless both are free
+    pub fn quadrature(a: Pad, b: Pad, keys: Keys) -> Option<Self> {
+        if !expansion::is_free(a) || !expansion::is_free(b) || a == b {
+            warn!(
+                "Encoder pads {} and {} aren't both free",
+                a.name(),
+                b.name()
+            );
+            return None;
+        }
+        let config = InputConfig::default().with_pull(Pull::Up);
+        let a = Input::new(expansion::take(a)?, config);
+        let b = Input::new(expansion::take(b)?, config);
+        let levels = quadrature_levels(&a, &b);
+        Some(Self::new(Source::Quadrature { a, b, levels }, keys))
+    }
+
+    fn new(source: Source, keys: Keys) -> Self {
+        Self {
+            source,
+            keys,
+            quarters: 0,
+            pending: 0,
+            pushed: false,
+            clicked: false,
+        }
+    }
+
+    /// Samples the encoder and returns the next press it stands for, one
+    /// per call
+    pub fn poll(&mut self, now: Instant) -> Option<InputEvent> {
+        match &mut self.source {
+            Source::Seesaw { next_poll } if now >= *next_poll => {
+                *next_poll = now + SEESAW_POLL;
+                // the seesaw counts clockwise turns down
+                if let Some(delta) = seesaw_read::<4>(ENCODER_BASE, ENCODER_DELTA) {
+                    self.pending = self.pending.saturating_sub(i32::from_be_bytes(delta));
+                }
+                if let Some(levels) = seesaw_read::<4>(GPIO_BASE, GPIO_BULK) {
+                    let pushed = u32::from_be_bytes(levels) & (1 << SWITCH_PIN) == 0;
+                    // a press once released, as for the front buttons
+                    self.clicked |= self.pushed && !pushed;
+                    self.pushed = pushed;
+                }
+            }
+            Source::Seesaw { .. } => {}
+            Source::Quadrature { a, b, levels } => {
+                let now_levels = quadrature_levels(a, b);
+                self.quarters += QUARTER_STEPS[(*levels << 2 | now_levels) as usize];
+                *levels = now_levels;
+                if self.quarters >= QUARTERS_PER_DETENT {
+                    self.quarters = 0;
+                    self.pending += 1;
+                } else if self.quarters <= -QUARTERS_PER_DETENT {
+                    self.quarters = 0;
+                    self.pending -= 1;
+                }
+            }
+        }
+        self.pending = self.pending.clamp(-MAX_PENDING, MAX_PENDING);
+
+        let button = if self.clicked {
+            self.clicked = false;
+            self.keys.push
+        } else if self.pending > 0 {
+            self.pending -= 1;
+            self.keys.clockwise
+        } else if self.pending < 0 {
+            self.pending += 1;
+            self.keys.counter_clockwise
+        } else {
+            return None;
+        };
+        Some(InputEvent::Press(button))
+    }
+}
+
+/// Adds the buttons in `button_pads` and the encoder in `encoder` to
+/// `buttons`. Call after [expansion::init].
+pub fn attach(config: &ConfigStore, buttons: &mut Buttons) {
+    let pull_up = InputConfig::default().with_pull(Pull::Up);
+    for pair in config.get(keys::BUTTON_PADS).unwrap_or("").split(',') {
+        let Some((pad, button)) = pair.split_once('=') else {
+            continue;
+        };
+        let (Ok(pad), Ok(button)) = (pad.parse::<Pad>(), button.parse::<Button>()) else {
+            warn!("Button pad {}: not a pad and a button", pair);
+            continue;
+        };
+        let Some(pin) = expansion::take(pad) else {
+            continue;
+        };
+        if buttons.add_button(Input::new(pin, pull_up), button) {
+            info!("Pad {} is button {:?}", pad.name(), button);
+        }
+    }
+
+    let Some(kind) = config.get(keys::ENCODER) else {
+        return;
+    };
+    let keys = config.get_parsed(keys::ENCODER_KEYS).unwrap_or_default();
+    let encoder = match kind.split_once(',') {
+        _ if kind.trim() == "seesaw" => Encoder::seesaw(keys),
+        Some((a, b)) => match (a.parse(), b.parse()) {
+            (Ok(a), Ok(b)) => Encoder::quadrature(a, b, keys),
+            _ => None,
+        },
+        None => None,
+    };
+    match encoder {
+        Some(encoder) => buttons.set_encoder(encoder),
+        None => warn!("No encoder as {}", kind),
+    }
+}
+
+fn quadrature_levels(a: &Input<'_>, b: &Input<'_>) -> u8 {
+    (a.is_high() as u8) << 1 | b.is_high() as u8
+}
+
+fn seesaw_write(base: u8, function: u8, data: &[u8; 4]) -> bool {
+    let command = [base, function, data[0], data[1], data[2], data[3]];
+    i2c::with(|bus| bus.write(SEESAW_ADDRESS, &command).is_ok()) == Some(true)
+}
+
+/// The `N` bytes of a seesaw register; the bus is free while the chip
+/// gets them ready
+fn seesaw_read<const N: usize>(base: u8, function: u8) -> Option<[u8; N]> {
+    i2c::with(|bus| bus.write(SEESAW_ADDRESS, &[base, function]))?.ok()?;
+    Delay::new().delay_micros(SEESAW_DELAY_US);
+    let mut data = [0u8; N];
+    i2c::with(|bus| bus.read(SEESAW_ADDRESS, &mut data))?.ok()?;
+    Some(data)
+}
@@ -0,0 +1,48 @@
+//! Crate-wide error type.
+
+/// Errors surfaced by the board support modules
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The e-ink driver reported a failure while talking to the panel.
+    Display,
+    /// The panel stayed busy for longer than a refresh can take.
+    DisplayTimeout,
+    /// Reading or writing flash failed, or the partition could not be found.
+    Storage,
+    /// A config key or value was malformed or the record outgrew its sector.
+    InvalidConfig,
+    /// A network request could not be sent or got a malformed reply.
+    Network,
+    /// No reply arrived in time.
+    Timeout,
+    /// A URL was malformed or uses an unsupported scheme.
+    InvalidUrl,
+    /// There is no room left in the partition.
+    StorageFull,
+    /// The named file does not exist.
+    NotFound,
+    /// Too many requests went to the host lately; try again later.
+    RateLimited,
+    /// What had to go into a fixed buffer didn't fit.
+    BufferFull,
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Error::Display => write!(f, "display driver error"),
+            Error::DisplayTimeout => write!(f, "display busy for too long"),
+            Error::Storage => write!(f, "flash storage error"),
+            Error::InvalidConfig => write!(f, "invalid config entry"),
+            Error::Network => write!(f, "network error"),
+            Error::Timeout => write!(f, "timed out"),
+            Error::InvalidUrl => write!(f, "invalid or unsupported URL"),
+            Error::StorageFull => write!(f, "storage full"),
+            Error::NotFound => write!(f, "not found"),
+            Error::RateLimited => write!(f, "rate limited"),
+            Error::BufferFull => write!(f, "buffer too small"),
+        }
+    }
+}
+
+impl core::error::Error for Error {}
@@ -0,0 +1,68 @@
+//! The board's spare GPIOs, its [Pad]s, for code added to the firmware, such
+//! as a sensor on the MagTag's A1 port.
+//!
+//! The pads are left out of [board::Pins](crate::board::Pins), but
+//! `panel_pins` or `sd_pins` may have moved the panel or SD card onto one.
+//! [init] marks those as in use, and [take] hands out each of the rest once,
+//! so added code can't end up driving a pin a driver owns.
+
+use core::cell::Cell;
+
+use critical_section::Mutex;
+use esp_hal::gpio::AnyPin;
+use log::{info, warn};
+
+pub use crate::board::Pad;
+
+/// Bit `i` set while `Pad::ALL[i]` can be taken, `None` before [init]
+static FREE: Mutex<Cell<Option<u32>>> = Mutex::new(Cell::new(None));
+
+fn bit(pad: Pad) -> u32 {
+    let index = Pad::ALL.iter().position(|&other| other == pad);
+    index.map_or(0, |i| 1 << i)
+}
+
+/// Frees every pad that isn't one of the `claimed` GPIOs, the panel's and
+/// SD card's, once
+pub fn init(claimed: &[u8]) {
+    let mut free = 0;
+    for pad in Pad::ALL {
+        if claimed.contains(&pad.gpio()) {
+            warn!("Pad {} (GPIO{}) is in use", pad.name(), pad.gpio());
+        } else {
+            info!("Pad {} (GPIO{}) free", pad.name(), pad.gpio());
+            free |= bit(pad);
+        }
+    }
+    critical_section::with(|cs| {
+        let cell = FREE.borrow(cs);
+        if cell.get().is_none() {
+            cell.set(Some(free));
+        }
+    });
+}
+
+/// Whether [take] would hand out `pad`
+pub fn is_free(pad: Pad) -> bool {
+    critical_section::with(|cs| FREE.borrow(cs).get()).is_some_and(|free| free & bit(pad) != 0)
+}
+
+/// The pin of `pad`, to configure as an input or output as for any other
+/// pin. `None` before [init], for a pad in use and for one taken already.
+pub fn take(pad: Pad) -> Option<AnyPin<'static>> {
+    let taken = critical_section::with(|cs| {
+        let cell = FREE.borrow(cs);
+        let free = cell.get()?;
+        if free & bit(pad) == 0 {
+            return None;
+        }
+        cell.set(Some(free & !bit(pad)));
+        Some(())
+    });
+    if taken.is_none() {
+        warn!("Pad {} is not free", pad.name());
+    }
+    // SAFETY: no driver has the pads, init left out the ones the panel or
+    // SD card were moved onto, and each is handed out once
+    taken.map(|()| unsafe { AnyPin::steal(pad.gpio()) })
+}
@@ -0,0 +1,189 @@
+//! USB file-drop mode: the asset partition served over the serial port.
+//!
+//! Hold B while the badge boots to enter it. `tools/magtag_files.py` is the
+//! host side. Commands are text lines; file contents follow as raw bytes.
+//!
+//! - `ls` lists `<name> <len> <crc>` lines, then `ok`
+//! - `df` replies `<free> <capacity>` in bytes, then `ok`
+//! - `put <name> <len> <crc>` replies `ready`, then takes `len` bytes and
+//!   replies `ok` once the file is stored
+//! - `get <name>` replies `data <len> <crc>`, the bytes, then `ok`
+//! - `rm <name>`, `format`, `reboot`
+//!
+//! CRCs are CRC-32 in hex. Any failure replies `error <reason>`.
+
+use alloc::format;
+use core::fmt::Write;
+
+use embedded_graphics::{
+    mono_font::{
+        ascii::{FONT_6X10, FONT_7X14_BOLD},
+        MonoTextStyle,
+    },
+    pixelcolor::Gray2,
+    prelude::*,
+    text::{Baseline, Text},
+};
+use esp_hal::time::{Duration, Instant};
+use log::{info, warn};
+
+use crate::{assets::AssetStore, console::UsbSerial, display::Display, Error};
+
+const MAX_LINE_LEN: usize = 96;
+/// An upload that stalls this long is abandoned
+const TRANSFER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Serves the asset store until the host sends `reboot`
+pub fn run(mut serial: UsbSerial, mut store: AssetStore, display: &mut Display) -> ! {
+    info!("Entering file-drop mode");
+    show_status(display, &store);
+
+    let mut line = alloc::string::String::new();
+    loop {
+        serial.poll();
+        let mut byte = [0u8];
+        if serial.read(&mut byte) == 0 {
+            continue;
+        }
+        match byte[0] {
+            b'\r' | b'\n' if !line.is_empty() => {
+                let command = core::mem::take(&mut line);
+                let changed = execute(command.trim(), &mut serial, &mut store);
+                if changed {
+                    show_status(display, &store);
+                }
+            }
+            0x20..=0x7e if line.len() < MAX_LINE_LEN => line.push(byte[0] as char),
+            _ => {}
+        }
+    }
+}
+
+/// Runs one command; returns `true` if the store changed
+fn execute(line: &str, serial: &mut UsbSerial, store: &mut AssetStore) -> bool {
+    let mut args = line.split_ascii_whitespace();
+    let result = match (args.next(), args.next(), args.next(), args.next()) {
+        (Some("ls"), None, ..) => {
+            for asset in store.iter() {
+                writeln!(serial, "{} {} {:08x}", asset.name, asset.len, asset.crc).ok();
+            }
+            Ok(false)
+        }
+        (Some("df"), None, ..) => {
+            writeln!(serial, "{} {}", store.free(), store.capacity()).ok();
+            Ok(false)
+        }
+        (Some("put"), Some(name), Some(len), Some(crc)) => {
+            match (len.parse(), u32::from_str_radix(crc, 16)) {
+                (Ok(len), Ok(crc)) => put(serial, store, name, len, crc).map(|_| true),
+                _ => Err(Error::InvalidConfig),
+            }
+        }
+        (Some("get"), Some(name), None, _) => get(serial, store, name).map(|_| false),
+        (Some("rm"), Some(name), None, _) => store.remove(name).map(|_| true),
+        (Some("format"), None, ..) => store.format().map(|_| true),
+        (Some("reboot"), None, ..) => {
+            writeln!(serial, "ok").ok();
+            // let the reply reach the host before the port disappears
+            let start = Instant::now();
+            while start.elapsed() < Duration::from_millis(100) {
+                serial.poll();
+            }
+            esp_hal::system::software_reset()
+        }
+        _ => {
+            writeln!(serial, "error unknown command").ok();
+            return false;
+        }
+    };
+
+    match result {
+        Ok(changed) => {
+            writeln!(serial, "ok").ok();
+            changed
+        }
+        Err(err) => {
+            warn!("file-drop {:?} failed: {}", line, err);
+            writeln!(serial, "error {}", err).ok();
+            false
+        }
+    }
+}
+
+fn put(
+    serial: &mut UsbSerial,
+    store: &mut AssetStore,
+    name: &str,
+    len: u32,
+    crc: u32,
+) -> Result<(), Error> {
+    let mut writer = store.create(name, len)?;
+    writeln!(serial, "ready").ok();
+
+    let mut remaining = len as usize;
+    let mut chunk = [0u8; 256];
+    let mut last_data = Instant::now();
+    while remaining > 0 {
+        if last_data.elapsed() > TRANSFER_TIMEOUT {
+            return Err(Error::Timeout);
+        }
+        serial.poll();
+        let want = remaining.min(chunk.len());
+        let got = serial.read(&mut chunk[..want]);
+        if got > 0 {
+            writer.write(&chunk[..got])?;
+            remaining -= got;
+            last_data = Instant::now();
+        }
+    }
+    writer.finish(crc)
+}
+
+fn get(serial: &mut UsbSerial, store: &AssetStore, name: &str) -> Result<(), Error> {
+    let asset = store.get(name).ok_or(Error::NotFound)?;
+    let (len, crc) = (asset.len, asset.crc);
+    writeln!(serial, "data {} {:08x}", len, crc).ok();
+
+    let mut offset = 0;
+    let mut chunk = [0u8; 256];
+    while offset < len {
+        let read = store.read(name, offset, &mut chunk)?;
+        serial.write(&chunk[..read]);
+        offset += read as u32;
+    }
+    Ok(())
+}
+
+fn show_status(display: &mut Display, store: &AssetStore) {
+    let frame = display.frame();
+    frame.clear(Gray2::WHITE).ok();
+
+    let title_style = MonoTextStyle::new(&FONT_7X14_BOLD, Gray2::BLACK);
+    Text::with_baseline(
+        "USB file drop",
+        Point::new(4, 2),
+        title_style,
+        Baseline::Top,
+    )
+    .draw(frame)
+    .ok();
+
+    let style = MonoTextStyle::new(&FONT_6X10, Gray2::BLACK);
+    let usage = format!(
+        "{} files, {} of {} KiB free",
+        store.iter().count(),
+        store.free() / 1024,
+        store.capacity() / 1024
+    );
+    Text::with_baseline(&usage, Point::new(4, 22), style, Baseline::Top)
+        .draw(frame)
+        .ok();
+    let hint = "Run tools/magtag_files.py on the host";
+    Text::with_baseline(hint, Point::new(4, 36), style, Baseline::Top)
+        .draw(frame)
+        .ok();
+
+    if let Err(err) = display.flush() {
+        warn!("file-drop screen refresh failed: {}", err);
+    }
+}
@@ -0,0 +1,75 @@
+//! The SPI flash, shared by the config store and the asset store.
+
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+use embedded_storage::{nor_flash::NorFlash, ReadStorage};
+use esp_bootloader_esp_idf::partitions::{
+    self, DataPartitionSubType, PartitionTable, PartitionType,
+};
+use esp_storage::FlashStorage;
+
+use crate::Error;
+
+pub const SECTOR_SIZE: u32 = FlashStorage::SECTOR_SIZE;
+pub const WORD_SIZE: u32 = FlashStorage::WORD_SIZE;
+
+static FLASH: Mutex<RefCell<Option<FlashStorage<'static>>>> = Mutex::new(RefCell::new(None));
+
+/// Location of a partition, in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub offset: u32,
+    pub len: u32,
+}
+
+/// Hands the flash to the storage modules. Call once at boot.
+pub fn init(flash: FlashStorage<'static>) {
+    critical_section::with(|cs| FLASH.borrow_ref_mut(cs).replace(flash));
+}
+
+/// Finds the first data partition of the given subtype
+pub fn find_partition(subtype: DataPartitionSubType) -> Result<Region, Error> {
+    with_partition_table(|table| {
+        table
+            .find_partition(PartitionType::Data(subtype))
+            .map_err(|_| Error::Storage)?
+            .map(|entry| Region {
+                offset: entry.offset(),
+                len: entry.len(),
+            })
+            .ok_or(Error::Storage)
+    })?
+}
+
+/// Runs `f` on the partition table as flashed
+pub fn with_partition_table<R>(f: impl FnOnce(&PartitionTable<'_>) -> R) -> Result<R, Error> {
+    with(|flash| {
+        let mut table = [0u8; partitions::PARTITION_TABLE_MAX_LEN];
+        let table =
+            partitions::read_partition_table(flash, &mut table).map_err(|_| Error::Storage)?;
+        Ok(f(&table))
+    })
+}
+
+/// Reads at any offset and length
+pub fn read(offset: u32, buf: &mut [u8]) -> Result<(), Error> {
+    with(|flash| ReadStorage::read(flash, offset, buf).map_err(|_| Error::Storage))
+}
+
+/// Writes to erased flash; `offset` and `data.len()` must be multiples of [WORD_SIZE]
+pub fn write(offset: u32, data: &[u8]) -> Result<(), Error> {
+    with(|flash| NorFlash::write(flash, offset, data).map_err(|_| Error::Storage))
+}
+
+/// Erases whole sectors
+pub fn erase(from: u32, to: u32) -> Result<(), Error> {
+    with(|flash| flash.erase(from, to).map_err(|_| Error::Storage))
+}
+
+fn with<R>(f: impl FnOnce(&mut FlashStorage<'static>) -> Result<R, Error>) -> Result<R, Error> {
+    critical_section::with(|cs| match FLASH.borrow_ref_mut(cs).as_mut() {
+        Some(flash) => f(flash),
+        None => Err(Error::Storage),
+    })
+}
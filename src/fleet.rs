@@ -0,0 +1,451 @@
+//! Config handed out from one place, so a fleet of badges can be set up
+//! again without visiting each one with a USB cable.
+//!
+//! `fleet_url` says where each badge's document is, with `{mac}` standing
+//! for its MAC address in lowercase hex, e.g.
+//! `http://config.local/badges/{mac}.cfg`; without `{mac}` the address is
+//! added as the last path segment. `fleet_key` is the key the documents are
+//! signed with. On every boot the Fetch stage of [crate::lifecycle] asks for
+//! the document and, if its version is newer than `fleet_version`, applies
+//! it to the config store and commits it before the apps start.
+//!
+//! ```text
+//! # badges at the front desk
+//! version 7
+//! rollout 10
+//! set refresh_min 15
+//! a: set dashboard {clock}
+//! b: set dashboard {clock} {weather.temp}
+//! unset ha_url
+//! sig 6d1f...
+//! ```
+//!
+//! Blank lines and `#` comments are skipped. The last line is the
+//! HMAC-SHA256 with `fleet_key` over every byte before it, in hex, as
+//! `tools/fleet_sign.py` appends it. A document that isn't signed with the
+//! key or doesn't parse changes nothing, and so does one that touches a
+//! [PROTECTED] key, so a mistake can't strand a badge off the network.
+//! Settings read earlier in boot, such as the timezone, take effect from the
+//! next one.
+//!
+//! # Staged rollouts and experiments
+//!
+//! Each badge has a [cohort] from 0 to 99 and a [Variant], both from a hash
+//! of its MAC address, so they stay the same from one document to the next.
+//! A document with `rollout <percent>` only goes to the badges whose cohort
+//! is below it; raising the figure in a new copy of the same version takes
+//! it to more of them. Lines starting `a:` or `b:` only apply to badges of
+//! that variant, for trying two layouts or feeds side by side.
+//!
+//! Applying a document starts a trial of [TRIAL_RUNS] runs, each boot or
+//! wake from deep sleep counting as one, with what it replaced kept in the
+//! asset store as [UNDO_FILE], secrets only if they can be sealed. A run
+//! ending in a panic or a watchdog reset during the trial puts the old
+//! values back and records the version in `fleet_halted`, which is never
+//! applied again; a newer one is. Telemetry reports the version in use as
+//! `fleet`, with the cohort and variant, so a rollout can be watched and
+//! held back at the server as well.
+
+use alloc::{format, string::String, vec::Vec};
+use core::fmt::Write as _;
+
+use esp_hal::efuse::Efuse;
+use log::{info, warn};
+
+#[cfg(feature = "encrypted-secrets")]
+use crate::secrets;
+use crate::{
+    assets::AssetStore,
+    config::{keys, ConfigStore},
+    crash::CrashKind,
+    http,
+    net::NetStack,
+    sha256::{ct_eq, hmac_sha256, sha256, DIGEST_LEN},
+    storage::BlobStore,
+    Error,
+};
+
+/// Keys a document may not set or unset
+pub const PROTECTED: &[&str] = &[
+    keys::WIFI_SSID,
+    keys::WIFI_PASSWORD,
+    keys::FLEET_KEY,
+    keys::FLEET_VERSION,
+    keys::FLEET_TRIAL,
+    keys::FLEET_HALTED,
+];
+/// Runs a newly applied document has to get through without a crash
+pub const TRIAL_RUNS: u8 = 5;
+/// What the document on trial replaced, as an unsigned document
+pub const UNDO_FILE: &str = "FLEETUNDO.CFG";
+
+/// One half of an A/B experiment
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variant {
+    A,
+    B,
+}
+
+impl Variant {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Variant::A => "a",
+            Variant::B => "b",
+        }
+    }
+}
+
+/// Where this badge falls in a staged rollout, from 0 to 99
+pub fn cohort() -> u8 {
+    let hash = sha256(&Efuse::mac_address());
+    (u16::from_le_bytes([hash[0], hash[1]]) % 100) as u8
+}
+
+/// Which lines of an experiment this badge follows, apart from its cohort
+pub fn variant() -> Variant {
+    if sha256(&Efuse::mac_address())[2] & 1 == 0 {
+        Variant::A
+    } else {
+        Variant::B
+    }
+}
+
+/// One line of a document
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    Set(String, String),
+    Unset(String),
+}
+
+impl Change {
+    pub fn key(&self) -> &str {
+        match self {
+            Change::Set(key, _) | Change::Unset(key) => key,
+        }
+    }
+
+    fn apply(&self, config: &mut ConfigStore) -> Result<(), Error> {
+        match self {
+            Change::Set(key, value) => config.set(key, value),
+            Change::Unset(key) => {
+                config.remove(key);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A [Change] and the variant it is for, `None` for every badge
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub variant: Option<Variant>,
+    pub change: Change,
+}
+
+/// A document that checked out
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Document {
+    pub version: u16,
+    /// Percent of cohorts it goes to
+    pub rollout: u8,
+    pub entries: Vec<Entry>,
+}
+
+impl Document {
+    /// Checks the signature of `raw` with `key` and parses what it covers
+    pub fn parse(raw: &[u8], key: &[u8]) -> Result<Self, Error> {
+        let text = core::str::from_utf8(raw).map_err(|_| Error::InvalidConfig)?;
+        let text = text.trim_end();
+        let (signed, sig_line) = match text.rfind('\n') {
+            Some(at) => (&text[..at + 1], &text[at + 1..]),
+            None => ("", text),
+        };
+        let signature = sig_line
+            .trim_end_matches('\r')
+            .strip_prefix("sig ")
+            .and_then(|hex| decode_hex(hex.trim()))
+            .ok_or(Error::InvalidConfig)?;
+        if !ct_eq(&hmac_sha256(key, &[signed.as_bytes()]), &signature) {
+            return Err(Error::InvalidConfig);
+        }
+        let document = Self::parse_unsigned(signed)?;
+        match document
+            .entries
+            .iter()
+            .find(|e| PROTECTED.contains(&e.change.key()))
+        {
+            Some(_) => Err(Error::InvalidConfig),
+            None => Ok(document),
+        }
+    }
+
+    /// The lines of a document without checking a signature or the keys
+    fn parse_unsigned(text: &str) -> Result<Self, Error> {
+        let mut version = None;
+        let mut rollout = 100;
+        let mut entries = Vec::new();
+        // only the line ending goes, a value keeps any trailing spaces
+        for line in text.lines().map(str::trim_start) {
+            if line.trim_end().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (variant, line) = match line.split_once(": ") {
+                Some(("a", rest)) => (Some(Variant::A), rest),
+                Some(("b", rest)) => (Some(Variant::B), rest),
+                _ => (None, line),
+            };
+            let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+            let change = match (command, variant) {
+                ("version", None) => {
+                    version = Some(rest.trim().parse().map_err(|_| Error::InvalidConfig)?);
+                    continue;
+                }
+                ("rollout", None) => {
+                    rollout = rest.trim().parse().map_err(|_| Error::InvalidConfig)?;
+                    if rollout > 100 {
+                        return Err(Error::InvalidConfig);
+                    }
+                    continue;
+                }
+                ("set", _) => {
+                    let (key, value) = rest.split_once(' ').unwrap_or((rest, ""));
+                    Change::Set(key.into(), value.into())
+                }
+                ("unset", _) => Change::Unset(rest.trim().into()),
+                _ => return Err(Error::InvalidConfig),
+            };
+            if change.key().is_empty() || change.key().contains('=') {
+                return Err(Error::InvalidConfig);
+            }
+            entries.push(Entry { variant, change });
+        }
+        Ok(Self {
+            version: version.ok_or(Error::InvalidConfig)?,
+            rollout,
+            entries,
+        })
+    }
+
+    /// The changes that apply to a badge of `variant`
+    pub fn changes_for(&self, variant: Variant) -> impl Iterator<Item = &Change> {
+        self.entries
+            .iter()
+            .filter(move |e| e.variant.is_none_or(|v| v == variant))
+            .map(|e| &e.change)
+    }
+}
+
+/// Where this badge's document is and what it is signed with
+pub struct Fleet {
+    url: String,
+    key: String,
+}
+
+impl Fleet {
+    /// `None` unless both `fleet_url` and `fleet_key` are set
+    pub fn from_config(config: &ConfigStore) -> Option<Self> {
+        let url = config.get(keys::FLEET_URL).filter(|s| !s.is_empty())?;
+        let Some(key) = config.get(keys::FLEET_KEY).filter(|s| !s.is_empty()) else {
+            warn!("{} is set but {} isn't", keys::FLEET_URL, keys::FLEET_KEY);
+            return None;
+        };
+        let mac = Efuse::mac_address();
+        let mac = format!(
+            "{:02x}{:02x}{:02x}{:02x}{:02x}{:02x}",
+            mac[0], mac[1], mac[2], mac[3], mac[4], mac[5]
+        );
+        let url = if url.contains("{mac}") {
+            url.replace("{mac}", &mac)
+        } else {
+            format!("{}/{}", url.trim_end_matches('/'), mac)
+        };
+        Some(Self {
+            url,
+            key: key.into(),
+        })
+    }
+
+    /// Fetches the document and applies it if it is newer than the one
+    /// applied last and meant for this badge's cohort. Fails only when it
+    /// couldn't be fetched; one that is rejected is logged and left alone.
+    pub fn sync(&self, net: &NetStack<'_>, config: &mut ConfigStore) -> Result<(), Error> {
+        let response = http::get(net, &self.url)?;
+        match response.status {
+            200 => {}
+            404 => {
+                info!("No fleet config at {}", self.url);
+                return Ok(());
+            }
+            status => {
+                warn!("Fleet server answered {} for {}", status, self.url);
+                return Err(Error::Network);
+            }
+        }
+        let document = match Document::parse(&response.body, self.key.as_bytes()) {
+            Ok(document) => document,
+            Err(err) => {
+                warn!("Fleet config from {} rejected: {}", self.url, err);
+                return Ok(());
+            }
+        };
+        let applied = config.get_parsed::<u16>(keys::FLEET_VERSION).unwrap_or(0);
+        let halted = config.get_parsed::<u16>(keys::FLEET_HALTED).unwrap_or(0);
+        if document.version <= applied {
+            info!("Fleet config {} already applied", applied);
+            return Ok(());
+        }
+        if document.version <= halted {
+            info!("Fleet config {} was halted here", document.version);
+            return Ok(());
+        }
+        let cohort = cohort();
+        if cohort >= document.rollout {
+            info!(
+                "Fleet config {} is out to {}%, not cohort {} yet",
+                document.version, document.rollout, cohort
+            );
+            return Ok(());
+        }
+
+        let variant = variant();
+        // on trial only if it can be undone
+        let trial = match save_undo(config, applied, document.changes_for(variant)) {
+            Ok(()) => true,
+            Err(err) => {
+                warn!("Saving {} failed, no trial: {}", UNDO_FILE, err);
+                false
+            }
+        };
+        let mut changes = 0;
+        for change in document.changes_for(variant) {
+            change.apply(config)?;
+            changes += 1;
+        }
+        config.set(keys::FLEET_VERSION, &format!("{}", document.version))?;
+        if trial {
+            config.set(keys::FLEET_TRIAL, &format!("{}", TRIAL_RUNS))?;
+        } else {
+            config.remove(keys::FLEET_TRIAL);
+        }
+        config.commit()?;
+        info!(
+            "Applied fleet config {} with {} changes for variant {}",
+            document.version,
+            changes,
+            variant.as_str()
+        );
+        Ok(())
+    }
+}
+
+/// Counts down the trial of the applied document, or undoes it if the last
+/// run ended in `crashed`. Call at boot before the config is used.
+pub fn check_trial(config: &mut ConfigStore, crashed: Option<CrashKind>) {
+    let Some(runs) = config.get_parsed::<u8>(keys::FLEET_TRIAL) else {
+        return;
+    };
+    let version = config.get_parsed::<u16>(keys::FLEET_VERSION).unwrap_or(0);
+    // a battery running flat says nothing about the config, and a panel that
+    // stops answering fails its refresh rather than panicking
+    let result = match crashed {
+        Some(CrashKind::Panic | CrashKind::Watchdog) => {
+            warn!("Fleet config {} on trial crashed, undoing it", version);
+            undo(config, version)
+        }
+        _ if runs <= 1 => {
+            info!("Fleet config {} passed its trial", version);
+            config.remove(keys::FLEET_TRIAL);
+            forget_undo();
+            Ok(())
+        }
+        _ => config.set(keys::FLEET_TRIAL, &format!("{}", runs - 1)),
+    };
+    if let Err(err) = result.and_then(|()| config.commit()) {
+        warn!("Fleet trial: {}", err);
+    }
+}
+
+/// Writes what `changes` replace, and `applied`, to [UNDO_FILE]. The store
+/// can be read over USB, so [keys::SECRETS] go in sealed with
+/// `encrypted-secrets` and a key, and are left out otherwise: undoing then
+/// keeps the secret the document set.
+fn save_undo<'c>(
+    config: &ConfigStore,
+    applied: u16,
+    changes: impl Iterator<Item = &'c Change>,
+) -> Result<(), Error> {
+    let mut undo = String::new();
+    writeln!(undo, "version {}", applied).ok();
+    for change in changes {
+        let key = change.key();
+        let value = match config.get(key) {
+            Some(value) if keys::SECRETS.contains(&key) => match sealed(key, value) {
+                Some(sealed) => Some(sealed?),
+                None => {
+                    warn!("Not keeping {} in {} unencrypted", key, UNDO_FILE);
+                    continue;
+                }
+            },
+            value => value.map(String::from),
+        };
+        match value {
+            Some(value) => writeln!(undo, "set {} {}", key, value),
+            None => writeln!(undo, "unset {}", key),
+        }
+        .ok();
+    }
+    AssetStore::open()?.write(UNDO_FILE, undo.as_bytes())
+}
+
+/// Puts back what document `version` replaced and halts it
+fn undo(config: &mut ConfigStore, version: u16) -> Result<(), Error> {
+    let raw = AssetStore::open()?.read_to_vec(UNDO_FILE)?;
+    let text = core::str::from_utf8(&raw).map_err(|_| Error::InvalidConfig)?;
+    let previous = Document::parse_unsigned(text)?;
+    for entry in &previous.entries {
+        match &entry.change {
+            #[cfg(feature = "encrypted-secrets")]
+            Change::Set(key, value) if secrets::is_sealed(value) => {
+                config.set(key, &secrets::open(key, value)?)?
+            }
+            change => change.apply(config)?,
+        }
+    }
+    match previous.version {
+        0 => config.remove(keys::FLEET_VERSION),
+        v => config.set(keys::FLEET_VERSION, &format!("{}", v))?,
+    }
+    config.set(keys::FLEET_HALTED, &format!("{}", version))?;
+    config.remove(keys::FLEET_TRIAL);
+    forget_undo();
+    Ok(())
+}
+
+/// `value` of secret `key` sealed for [UNDO_FILE], `None` with nothing to
+/// seal it with
+#[cfg(feature = "encrypted-secrets")]
+fn sealed(key: &str, value: &str) -> Option<Result<String, Error>> {
+    secrets::is_available().then(|| secrets::seal(key, value))
+}
+
+#[cfg(not(feature = "encrypted-secrets"))]
+fn sealed(_key: &str, _value: &str) -> Option<Result<String, Error>> {
+    None
+}
+
+fn forget_undo() {
+    if let Err(err) = AssetStore::open().and_then(|mut store| store.remove(UNDO_FILE)) {
+        warn!("Removing {} failed: {}", UNDO_FILE, err);
+    }
+}
+
+fn decode_hex(hex: &str) -> Option<[u8; DIGEST_LEN]> {
+    if hex.len() != 2 * DIGEST_LEN {
+        return None;
+    }
+    let mut out = [0u8; DIGEST_LEN];
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(2 * i..2 * i + 2)?, 16).ok()?;
+    }
+    Some(out)
+}
@@ -0,0 +1,17 @@
+//! Fonts subset from BDF files at build time.
+//!
+//! `build.rs` turns every `fonts/<name>.bdf` into a `MonoFont` constant
+//! named after the file, `fonts/fixed-6x10.bdf` into [FIXED_6X10],
+//! keeping only the glyphs the string tables in [crate::i18n] use plus
+//! `MAGTAG_FONT_CHARS`, printable ASCII if unset. Of the 1597 glyphs in
+//! `fixed-6x10.bdf` that leaves about a hundred, 840 bytes of flash instead
+//! of 12 KiB. Characters left out show as `?`, and the build warns about any
+//! the font lacks. The fonts must be monospaced; glyphs are placed in the
+//! cell of `FONTBOUNDINGBOX`.
+//!
+//! The bundled [FIXED_6X10] and [FIXED_7X14_BOLD] are the public domain X11
+//! `fixed` fonts that embedded-graphics' `FONT_6X10` and `FONT_7X14_BOLD` are
+//! made from, so screens draw translated text with them and ASCII looks the
+//! same either way.
+
+include!(concat!(env!("OUT_DIR"), "/fonts.rs"));
@@ -0,0 +1,104 @@
+//! Thin traits over the hardware the host and apps use.
+//!
+//! The [AppHost](crate::app::AppHost) takes any [Panel] and [ButtonSource]
+//! and reads the battery through a [Battery]; [net_health] checks a
+//! [Network]. The real drivers implement them here, next to each other, so a
+//! new driver shows what it has to provide.
+//!
+//! There are no mock implementations: the app host still needs the chip's
+//! clock and radio, so mocks alone wouldn't build it off the chip. Logic
+//! tested on a PC lives in modules of its own instead, such as
+//! [crate::host_tasks] for `tools/host_logic`.
+//!
+//! [net_health]: crate::net_health
+
+use esp_hal::time::Instant;
+
+use crate::{
+    analog,
+    display::{Contrast, Display, Frame},
+    input::{Buttons, Event},
+    net::NetStack,
+    wifi, Error,
+};
+
+/// Something with a [Frame] that can be sent to the screen
+pub trait Panel {
+    fn frame(&mut self) -> &mut Frame;
+
+    /// Shows the frame
+    fn flush(&mut self) -> Result<(), Error>;
+
+    /// Shows the frame, running `work` while the panel is busy with it; by
+    /// default one after the other
+    fn flush_while(&mut self, work: &mut dyn FnMut()) -> Result<(), Error> {
+        let result = self.flush();
+        work();
+        result
+    }
+
+    /// How the levels of the frame are shown from the next flush on; panels
+    /// that can't remap them ignore it
+    fn set_contrast(&mut self, _contrast: Contrast) {}
+}
+
+/// Turns the buttons and the other inputs into [Event]s
+pub trait ButtonSource {
+    fn poll(&mut self, now: Instant);
+
+    /// Next pending event, oldest first
+    fn next_event(&mut self) -> Option<Event>;
+}
+
+pub trait Battery {
+    /// The battery voltage, `None` if it can't be read
+    fn millivolts(&self) -> Option<u32>;
+}
+
+pub trait Network {
+    /// Whether WiFi is joined and the interface has an address
+    fn link_up(&self) -> bool;
+}
+
+impl Panel for Display {
+    fn frame(&mut self) -> &mut Frame {
+        Display::frame(self)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        Display::flush(self)
+    }
+
+    fn flush_while(&mut self, work: &mut dyn FnMut()) -> Result<(), Error> {
+        Display::flush_while(self, work)
+    }
+
+    fn set_contrast(&mut self, contrast: Contrast) {
+        Display::set_contrast(self, contrast)
+    }
+}
+
+impl ButtonSource for Buttons {
+    fn poll(&mut self, now: Instant) {
+        Buttons::poll(self, now);
+    }
+
+    fn next_event(&mut self) -> Option<Event> {
+        Buttons::next_event(self)
+    }
+}
+
+/// The battery divider on the ADC, see [analog]
+pub struct BatteryAdc;
+
+impl Battery for BatteryAdc {
+    fn millivolts(&self) -> Option<u32> {
+        analog::battery_millivolts()
+    }
+}
+
+impl Network for NetStack<'_> {
+    fn link_up(&self) -> bool {
+        wifi::is_connected() && self.is_iface_up()
+    }
+}
@@ -0,0 +1,147 @@
+//! Entity states polled from Home Assistant's REST API, for badges that
+//! would rather ask now and then than hold an MQTT subscription open.
+//!
+//! `ha_url` is the server, e.g. `http://homeassistant.local:8123`, and
+//! `ha_token` a long-lived access token made on the user's profile page.
+//! `ha_entities` lists up to [MAX_ENTITIES] entity IDs separated by commas,
+//! such as `sensor.living_room_temperature,binary_sensor.front_door`. Every
+//! `ha_min` minutes ([DEFAULT_INTERVAL] if unset) each one is fetched from
+//! `/api/states/<entity>` and its state published to the [bindings] as
+//! `ha.<entity>`, with the unit, if it has one, as `ha.<entity>.unit`.
+//!
+//! A state that can't be fetched keeps its last value; an entity the server
+//! doesn't know is withdrawn. States come as Home Assistant writes them,
+//! e.g. `21.53`, `on` or `unavailable`.
+
+use alloc::{format, string::String, vec::Vec};
+
+use esp_hal::time::Duration;
+use log::{debug, warn};
+
+use crate::{
+    bindings,
+    config::{keys, ConfigStore},
+    http::{Auth, Request},
+    json,
+    net::NetStack,
+    rate_limit, wifi, Error,
+};
+
+/// As many as one burst of requests to the server allows
+pub const MAX_ENTITIES: usize = rate_limit::BURST as usize;
+pub const DEFAULT_INTERVAL: Duration = Duration::from_minutes(5);
+
+/// The configured server and entities
+pub struct HomeAssistant {
+    /// `ha_url` without a trailing `/`
+    base: String,
+    auth: Auth,
+    entities: Vec<String>,
+    interval: Duration,
+}
+
+impl HomeAssistant {
+    /// `None` unless the URL, token and at least one entity are set
+    pub fn from_config(config: &ConfigStore) -> Option<Self> {
+        let base = config.get(keys::HA_URL).filter(|s| !s.is_empty())?;
+        let token = config.get(keys::HA_TOKEN).filter(|s| !s.is_empty())?;
+        let mut entities: Vec<String> = config
+            .get(keys::HA_ENTITIES)?
+            .split(',')
+            .map(str::trim)
+            .filter(|entity| !entity.is_empty())
+            .map(String::from)
+            .collect();
+        if entities.is_empty() {
+            return None;
+        }
+        if entities.len() > MAX_ENTITIES {
+            warn!(
+                "{} lists {} entities, polling the first {}",
+                keys::HA_ENTITIES,
+                entities.len(),
+                MAX_ENTITIES
+            );
+            entities.truncate(MAX_ENTITIES);
+        }
+        let interval = config
+            .get_parsed::<u16>(keys::HA_MINUTES)
+            .filter(|&minutes| minutes > 0)
+            .map_or(DEFAULT_INTERVAL, |minutes| {
+                Duration::from_minutes(minutes as u64)
+            });
+        Some(Self {
+            base: base.trim_end_matches('/').into(),
+            auth: Auth::Bearer(token.into()),
+            entities,
+            interval,
+        })
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Fetches and publishes every entity; fails with the last error when
+    /// any of them couldn't be fetched
+    pub fn poll(&self, net: &NetStack<'_>) -> Result<(), Error> {
+        if !wifi::is_connected() {
+            debug!("offline, Home Assistant states left as they are");
+            return Err(Error::Network);
+        }
+        let mut result = Ok(());
+        for entity in &self.entities {
+            let name = format!("ha.{}", entity);
+            let unit = format!("{}.unit", name);
+            match self.fetch(net, entity) {
+                Ok(state) => {
+                    bindings::publish(&name, state.state);
+                    match state.unit {
+                        Some(value) => bindings::publish(&unit, value),
+                        None => bindings::withdraw(&unit),
+                    }
+                }
+                Err(Error::NotFound) => {
+                    warn!("Home Assistant has no entity {}", entity);
+                    bindings::withdraw(&name);
+                    bindings::withdraw(&unit);
+                }
+                Err(err) => {
+                    warn!("Home Assistant {}: {}", entity, err);
+                    result = Err(err);
+                }
+            }
+        }
+        result
+    }
+
+    fn fetch(&self, net: &NetStack<'_>, entity: &str) -> Result<State, Error> {
+        let url = format!("{}/api/states/{}", self.base, entity);
+        let response = Request::get(&url)
+            .auth(&self.auth)
+            .header("Accept", "application/json")
+            .send(net)?;
+        match response.status {
+            200 => {}
+            404 => return Err(Error::NotFound),
+            401 | 403 => {
+                warn!("Home Assistant refused the token in {}", keys::HA_TOKEN);
+                return Err(Error::Network);
+            }
+            status => {
+                warn!("Home Assistant answered {} for {}", status, entity);
+                return Err(Error::Network);
+            }
+        }
+        let state = json::string_at(&response.body, &["state"]).ok_or(Error::Network)?;
+        Ok(State {
+            state,
+            unit: json::string_at(&response.body, &["attributes", "unit_of_measurement"]),
+        })
+    }
+}
+
+struct State {
+    state: String,
+    unit: Option<String>,
+}
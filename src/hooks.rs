@@ -0,0 +1,87 @@
+//! Hooks run at points the app host goes through for every app, so features
+//! that cut across the apps, such as timing fetches or a status light, don't
+//! need each app changed.
+//!
+//! | hook           | runs                                                  |
+//! |----------------|-------------------------------------------------------|
+//! | `before_fetch` | before an app handles a tick or [Action::Refresh], and before [App::while_refreshing] |
+//! | `after_fetch`  | once it has, with how long it took                   |
+//! | `after_render` | after each panel refresh, with how it went            |
+//! | `on_error`     | when the host hits an error it carries on past        |
+//! | `before_sleep` | before deep sleep, from any of [crate::power]'s ways in |
+//!
+//! [add] registers a [Hook] for the rest of the run. Hooks run in the order
+//! they were added, outside a critical section; one added while hooks run
+//! only sees the next point.
+//!
+//! [Action::Refresh]: crate::actions::Action::Refresh
+//! [App::while_refreshing]: crate::app::App::while_refreshing
+
+use alloc::{boxed::Box, vec::Vec};
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+use esp_hal::time::{Duration, Instant};
+
+use crate::Error;
+
+/// Something to do at the host's hook points; every method defaults to
+/// nothing
+pub trait Hook: Send {
+    fn before_fetch(&mut self, _app: &'static str) {}
+
+    fn after_fetch(&mut self, _app: &'static str, _took: Duration) {}
+
+    fn after_render(&mut self, _app: &'static str, _result: Result<(), &Error>, _took: Duration) {}
+
+    /// `what` is the host's name for the step that failed, such as `refresh`
+    fn on_error(&mut self, _what: &'static str, _err: &Error) {}
+
+    /// `wake` is the timer wake-up, `None` for one on motion only
+    fn before_sleep(&mut self, _wake: Option<Duration>) {}
+}
+
+static HOOKS: Mutex<RefCell<Vec<Box<dyn Hook>>>> = Mutex::new(RefCell::new(Vec::new()));
+
+pub fn add(hook: impl Hook + 'static) {
+    critical_section::with(|cs| HOOKS.borrow_ref_mut(cs).push(Box::new(hook)));
+}
+
+/// Runs `f` on every hook, taken out of the mutex so a hook can take its
+/// time and use critical sections of its own
+fn each(mut f: impl FnMut(&mut dyn Hook)) {
+    let mut hooks = critical_section::with(|cs| core::mem::take(&mut *HOOKS.borrow_ref_mut(cs)));
+    if hooks.is_empty() {
+        return;
+    }
+    for hook in &mut hooks {
+        f(hook.as_mut());
+    }
+    critical_section::with(|cs| {
+        let mut global = HOOKS.borrow_ref_mut(cs);
+        hooks.append(&mut global);
+        *global = hooks;
+    });
+}
+
+/// Runs `fetch` for `app` between the fetch hooks
+pub fn fetch<R>(app: &'static str, fetch: impl FnOnce() -> R) -> R {
+    each(|hook| hook.before_fetch(app));
+    let started = Instant::now();
+    let result = fetch();
+    let took = started.elapsed();
+    each(|hook| hook.after_fetch(app, took));
+    result
+}
+
+pub fn after_render(app: &'static str, result: Result<(), &Error>, took: Duration) {
+    each(|hook| hook.after_render(app, result, took));
+}
+
+pub fn on_error(what: &'static str, err: &Error) {
+    each(|hook| hook.on_error(what, err));
+}
+
+pub fn before_sleep(wake: Option<Duration>) {
+    each(|hook| hook.before_sleep(wake));
+}
@@ -0,0 +1,30 @@
+//! The tasks the [AppHost](crate::app::AppHost) schedules, and which of them
+//! wait out the [QuietHours].
+//!
+//! Nothing here needs the chip, so `tools/host_logic` checks on the host
+//! which tasks the quiet hours hold back.
+
+use jiff::civil::DateTime;
+
+use crate::{alarm::QuietHours, scheduler::TaskId};
+
+pub const APP_TICK: TaskId = TaskId(0);
+pub const TELEMETRY: TaskId = TaskId(1);
+pub const AMBIENT_LIGHT: TaskId = TaskId(2);
+pub const BATTERY_CHECK: TaskId = TaskId(3);
+pub const NET_HEALTH: TaskId = TaskId(4);
+pub const HOME_ASSISTANT: TaskId = TaskId(5);
+pub const ALERTS: TaskId = TaskId(6);
+pub const FACE_DOWN: TaskId = TaskId(7);
+pub const DATALOG: TaskId = TaskId(8);
+pub const DATALOG_UPLOAD: TaskId = TaskId(9);
+pub const TSDB: TaskId = TaskId(10);
+
+/// The tasks that refresh the screen or send readings out; the sensors, the
+/// battery, the link and alerts keep going in the quiet hours
+pub const QUIET: [TaskId; 5] = [APP_TICK, TELEMETRY, HOME_ASSISTANT, DATALOG_UPLOAD, TSDB];
+
+/// When `task`, due at `now`, runs instead, if `quiet` holds it back
+pub fn deferred(task: TaskId, quiet: &QuietHours, now: DateTime) -> Option<DateTime> {
+    (QUIET.contains(&task) && quiet.contains(now)).then(|| quiet.defer(now))
+}
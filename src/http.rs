@@ -0,0 +1,443 @@
+//! Minimal HTTP/1.0 client for plain `http://` URLs.
+//!
+//! There is no TLS underneath, so `https://` URLs are rejected with
+//! [Error::InvalidUrl]; use a plain HTTP endpoint on the local network or a
+//! proxy that terminates TLS.
+//!
+//! [get] and [post] cover the simple cases; [Request] adds headers such as
+//! [Auth] credentials. URLs with query parameters are best put together with
+//! [crate::url::Builder]. [Request::multipart] uploads a form, with files
+//! streamed from storage, see [crate::multipart].
+//!
+//! Requests that fail on the network are sent again with
+//! [Policy::NETWORK], so a POST can arrive twice if only its response got
+//! lost. GET requests follow up to [MAX_REDIRECTS] redirects, dropping any
+//! `Authorization` header when one leads to another host; the head and the
+//! redirect rules are in [crate::http_request]. Each host gets only so many
+//! requests a minute, see [crate::rate_limit].
+//!
+//! Responses are read with [crate::http_parser], which stops at the end of
+//! the body rather than waiting for the server to close the connection.
+//!
+//! With the `gzip` feature, responses are requested compressed and expanded
+//! with [crate::inflate] before they are returned.
+//!
+//! [Request::send_with] takes the socket and response buffers from a
+//! [Buffers] the caller owns and leaves the response in it, so a request
+//! needs nothing from the heap. It doesn't follow redirects or ask for
+//! compression, and a response longer than the buffer is cut short.
+
+use alloc::string::String;
+use core::fmt;
+
+use embedded_io::{Read as _, ReadReady as _, Write as _};
+use esp_hal::{
+    rng::Rng,
+    time::{Duration, Instant},
+};
+use heapless::Vec as FixedVec;
+use log::{debug, warn};
+
+use crate::{
+    config::{keys, ConfigStore},
+    http_parser::{Buffer, ResponseParser},
+    http_request::{self, Head},
+    multipart::Multipart,
+    net::{self, BufferSizes, NetStack, TcpSocket},
+    psram, rate_limit,
+    retry::{self, Policy},
+    url::Url,
+    wifi, Error,
+};
+pub use crate::{
+    http_parser::{Response, ResponseRef},
+    http_request::{Auth, MAX_REDIRECTS},
+};
+
+pub const TIMEOUT: Duration = Duration::from_secs(20);
+/// Responses are read into memory; anything past this is dropped
+pub const MAX_RESPONSE_LEN: usize = 32 * 1024;
+/// Response bytes taken from the socket per read
+const READ_CHUNK: usize = 1460;
+/// Headers a [Request] carries besides the ones always sent
+pub const MAX_REQUEST_HEADERS: usize = 8;
+/// Largest body a compressed response may expand to
+#[cfg(feature = "gzip")]
+pub const MAX_DECODED_LEN: usize = 64 * 1024;
+
+/// Fetches `url` with a GET request
+pub fn get(stack: &NetStack<'_>, url: &str) -> Result<Response, Error> {
+    Request::get(url).send(stack)
+}
+
+/// Sends `body` to `url` with a POST request
+pub fn post(
+    stack: &NetStack<'_>,
+    url: &str,
+    content_type: &str,
+    body: &[u8],
+) -> Result<Response, Error> {
+    Request::post(url, content_type, body).send(stack)
+}
+
+/// An empty form for [Request::multipart], with a random boundary
+pub fn multipart<'r>() -> Multipart<'r> {
+    let rng = Rng::new();
+    let boundary = alloc::format!("magtag-{:08x}{:08x}", rng.random(), rng.random());
+    Multipart::with_boundary(&boundary)
+}
+
+/// What a request sends after its head
+#[derive(Debug, Clone, Copy)]
+enum Body<'r> {
+    Bytes(&'r [u8]),
+    Multipart(&'r Multipart<'r>),
+}
+
+impl Body<'_> {
+    fn len(&self) -> usize {
+        match self {
+            Body::Bytes(bytes) => bytes.len(),
+            Body::Multipart(form) => form.len(),
+        }
+    }
+}
+
+impl Auth {
+    /// A bearer token from `http_token`, or else Basic credentials from
+    /// `http_user` and `http_pass`; `None` if neither is set
+    pub fn from_config(config: &ConfigStore) -> Option<Self> {
+        if let Some(token) = config.get(keys::HTTP_TOKEN).filter(|s| !s.is_empty()) {
+            return Some(Auth::Bearer(token.into()));
+        }
+        let username = config.get(keys::HTTP_USERNAME).filter(|s| !s.is_empty())?;
+        Some(Auth::Basic {
+            username: username.into(),
+            password: config.get(keys::HTTP_PASSWORD).unwrap_or_default().into(),
+        })
+    }
+}
+
+/// Socket and response buffers for [Request::send_with]. The defaults match
+/// [BufferSizes::HTTP] and hold an 8 KiB response, head included.
+pub struct Buffers<
+    const RX: usize = { 4 * 1460 },
+    const TX: usize = 1024,
+    const RESPONSE: usize = { 8 * 1024 },
+> {
+    rx: [u8; RX],
+    tx: [u8; TX],
+    /// Holds the request head until it is sent, then the response
+    response: [u8; RESPONSE],
+}
+
+impl<const RX: usize, const TX: usize, const RESPONSE: usize> Buffers<RX, TX, RESPONSE> {
+    pub const fn new() -> Self {
+        Self {
+            rx: [0; RX],
+            tx: [0; TX],
+            response: [0; RESPONSE],
+        }
+    }
+}
+
+impl<const RX: usize, const TX: usize, const RESPONSE: usize> Default
+    for Buffers<RX, TX, RESPONSE>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A request with extra headers, for when [get] and [post] aren't enough:
+///
+/// ```ignore
+/// let response = Request::get("http://api.local/status")
+///     .auth(&Auth::Bearer(token))
+///     .header("Accept", "application/json")
+///     .send(stack)?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct Request<'r> {
+    method: &'static str,
+    url: &'r str,
+    body: Option<(&'r str, Body<'r>)>,
+    headers: FixedVec<(&'r str, &'r str), MAX_REQUEST_HEADERS>,
+    auth: Option<&'r Auth>,
+    buffers: BufferSizes,
+}
+
+impl<'r> Request<'r> {
+    pub fn get(url: &'r str) -> Self {
+        Self::new("GET", url, None)
+    }
+
+    pub fn post(url: &'r str, content_type: &'r str, body: &'r [u8]) -> Self {
+        Self::new("POST", url, Some((content_type, Body::Bytes(body))))
+    }
+
+    pub fn put(url: &'r str, content_type: &'r str, body: &'r [u8]) -> Self {
+        Self::new("PUT", url, Some((content_type, Body::Bytes(body))))
+    }
+
+    /// POSTs `form`, reading its files as it goes
+    pub fn multipart(url: &'r str, form: &'r Multipart<'r>) -> Self {
+        Self::new(
+            "POST",
+            url,
+            Some((form.content_type(), Body::Multipart(form))),
+        )
+    }
+
+    /// Any other method with a body, such as WebDAV's `REPORT`
+    pub fn with_method(
+        method: &'static str,
+        url: &'r str,
+        content_type: &'r str,
+        body: &'r [u8],
+    ) -> Self {
+        Self::new(method, url, Some((content_type, Body::Bytes(body))))
+    }
+
+    fn new(method: &'static str, url: &'r str, body: Option<(&'r str, Body<'r>)>) -> Self {
+        Self {
+            method,
+            url,
+            body,
+            headers: FixedVec::new(),
+            auth: None,
+            buffers: BufferSizes::HTTP,
+        }
+    }
+
+    /// Adds a header; `Host`, `Connection`, `Content-Type`, `Content-Length`
+    /// and with the `gzip` feature `Accept-Encoding` are always sent and
+    /// shouldn't be added again. Past [MAX_REQUEST_HEADERS] they are dropped.
+    pub fn header(mut self, name: &'r str, value: &'r str) -> Self {
+        if self.headers.push((name, value)).is_err() {
+            warn!(
+                "more than {} headers, dropping {}",
+                MAX_REQUEST_HEADERS, name
+            );
+        }
+        self
+    }
+
+    /// Socket buffers to use instead of [BufferSizes::HTTP], e.g. a bigger
+    /// receive buffer for a large download. [Request::send_with] uses the
+    /// ones it is given instead.
+    pub fn buffers(mut self, buffers: BufferSizes) -> Self {
+        self.buffers = buffers;
+        self
+    }
+
+    /// Adds an `Authorization` header for `auth`
+    pub fn auth(mut self, auth: &'r Auth) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    /// Sends the request and reads the whole response, retrying with
+    /// [Policy::NETWORK]
+    pub fn send(&self, stack: &NetStack<'_>) -> Result<Response, Error> {
+        self.check_body()?;
+        let _transfer = wifi::transfer();
+        let head = Head {
+            compressed: cfg!(feature = "gzip"),
+            ..self.head()
+        };
+        http_request::follow(head, self.url, |head, url| {
+            rate_limit::acquire(url.host)?;
+            let response =
+                retry::with_backoff(&Policy::NETWORK, || request_once(stack, self, head, url))?;
+            if response.status == 429 {
+                rate_limit::back_off(url.host);
+            }
+            Ok(response)
+        })
+    }
+
+    /// Sends the request and reads the response into `buffers`, retrying
+    /// with [Policy::NETWORK]. Nothing comes from the heap; a redirect is
+    /// returned as it is.
+    pub fn send_with<'b, const RX: usize, const TX: usize, const RESPONSE: usize>(
+        &self,
+        stack: &NetStack<'_>,
+        buffers: &'b mut Buffers<RX, TX, RESPONSE>,
+    ) -> Result<ResponseRef<'b>, Error> {
+        self.check_body()?;
+        let head = self.head();
+        let url = Url::parse_with_scheme(self.url, "http", 80)?;
+        head.check(&url)?;
+        let _transfer = wifi::transfer();
+        rate_limit::acquire(url.host)?;
+        let Buffers { rx, tx, response } = buffers;
+        let received = retry::with_backoff(&Policy::NETWORK, || {
+            let addr = net::resolve(stack, url.host)?;
+            let mut cursor = Cursor::new(&mut response[..]);
+            head.write(&mut cursor, &url)
+                .map_err(|_| Error::BufferFull)?;
+            let head_len = cursor.len;
+            net::with_tcp_socket(stack, rx, tx, |socket| {
+                send_request(socket, addr, url.port, &response[..head_len], self.body)?;
+                let parser = ResponseParser::new(&mut response[..], RESPONSE);
+                read_response(socket, parser).map(|parser| parser.received())
+            })
+        })?;
+        // framed again from what the last attempt left in the buffer
+        let mut parser = ResponseParser::new(&mut response[..], RESPONSE);
+        parser.commit(received)?;
+        let response = parser.finish_in_place()?;
+        if response.status == 429 {
+            rate_limit::back_off(url.host);
+        }
+        debug!("{} {} -> {}", self.method, url.path, response.status);
+        Ok(response)
+    }
+
+    fn check_body(&self) -> Result<(), Error> {
+        if let Some((_, Body::Multipart(form))) = self.body {
+            if !form.is_valid() {
+                warn!("refusing a form part name with a quote or line break");
+                return Err(Error::InvalidConfig);
+            }
+        }
+        Ok(())
+    }
+
+    fn head(&self) -> Head<'_> {
+        Head {
+            body: self
+                .body
+                .map(|(content_type, body)| (content_type, body.len())),
+            headers: &self.headers,
+            auth: self.auth,
+            ..Head::new(self.method)
+        }
+    }
+}
+
+fn request_once(
+    stack: &NetStack<'_>,
+    request: &Request<'_>,
+    head: &Head<'_>,
+    url: &Url<'_>,
+) -> Result<Response, Error> {
+    let addr = net::resolve(stack, url.host)?;
+    let mut bytes = String::new();
+    head.write(&mut bytes, url).ok();
+
+    let parser = net::with_tcp_socket_sized(stack, request.buffers, |socket| {
+        send_request(socket, addr, url.port, bytes.as_bytes(), request.body)?;
+        // read straight into the response
+        let parser = ResponseParser::new(psram::with_capacity(MAX_RESPONSE_LEN), MAX_RESPONSE_LEN);
+        read_response(socket, parser)
+    })?;
+
+    let response = parser.finish()?;
+    #[cfg(feature = "gzip")]
+    let response = decode(response)?;
+    debug!("{} {} -> {}", request.method, url.path, response.status);
+    Ok(response)
+}
+
+/// Connects and sends `head` and the body
+fn send_request(
+    socket: &mut TcpSocket<'_, '_>,
+    addr: smoltcp::wire::IpAddress,
+    port: u16,
+    head: &[u8],
+    body: Option<(&str, Body<'_>)>,
+) -> Result<(), Error> {
+    socket.open(addr, port).map_err(|_| Error::Network)?;
+    socket.write_all(head).map_err(|_| Error::Network)?;
+    match body {
+        Some((_, Body::Bytes(body))) => socket.write_all(body).map_err(|_| Error::Network)?,
+        Some((_, Body::Multipart(form))) => {
+            form.write_to(&mut |chunk| socket.write_all(chunk).map_err(|_| Error::Network))?
+        }
+        None => {}
+    }
+    socket.flush().map_err(|_| Error::Network)
+}
+
+/// Feeds the response to `parser` until it is complete or [TIMEOUT] passes,
+/// then disconnects
+fn read_response<B: Buffer>(
+    socket: &mut TcpSocket<'_, '_>,
+    mut parser: ResponseParser<B>,
+) -> Result<ResponseParser<B>, Error> {
+    let deadline = Instant::now() + TIMEOUT;
+    loop {
+        if Instant::now() > deadline {
+            socket.disconnect();
+            return Err(Error::Timeout);
+        }
+        match socket.read_ready() {
+            Ok(true) => {}
+            Ok(false) => continue,
+            // the server closed the connection, the response is complete
+            Err(_) => break,
+        }
+        let len = socket
+            .read(parser.spare(READ_CHUNK))
+            .map_err(|_| Error::Network)?;
+        if parser.commit(len)? {
+            break;
+        }
+    }
+    socket.disconnect();
+    Ok(parser)
+}
+
+/// Expands a gzip or deflate body and drops the `Content-Encoding` header, so
+/// callers always see the plain body
+#[cfg(feature = "gzip")]
+fn decode(mut response: Response) -> Result<Response, Error> {
+    let _boost = crate::power::cpu::boost();
+    let decoded = match response.header("content-encoding") {
+        Some(e) if e.eq_ignore_ascii_case("gzip") => {
+            crate::inflate::gunzip(&response.body, MAX_DECODED_LEN)
+        }
+        Some(e) if e.eq_ignore_ascii_case("deflate") => {
+            crate::inflate::inflate(&response.body, MAX_DECODED_LEN)
+        }
+        _ => return Ok(response),
+    };
+    response.body = decoded.inspect_err(|_| {
+        warn!(
+            "undecodable or over {} bytes decoded, {} bytes compressed",
+            MAX_DECODED_LEN,
+            response.body.len()
+        )
+    })?;
+    response.headers.retain(|(name, _)| {
+        !name.eq_ignore_ascii_case("content-encoding")
+            && !name.eq_ignore_ascii_case("content-length")
+    });
+    Ok(response)
+}
+
+/// Writes into a fixed buffer, failing once it is full
+struct Cursor<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(buf: &'a mut [u8]) -> Self {
+        Self { buf, len: 0 }
+    }
+}
+
+impl fmt::Write for Cursor<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        self.buf
+            .get_mut(self.len..end)
+            .ok_or(fmt::Error)?
+            .copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
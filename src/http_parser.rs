@@ -0,0 +1,484 @@
+//! Reads HTTP responses as they arrive off a socket.
+//!
+//! A [ResponseParser] is handed the bytes read from the connection and
+//! says when the response is complete, so a server that keeps the
+//! connection open despite `Connection: close` doesn't hold the client until
+//! it times out. Bodies are framed by `Content-Length`, by chunked transfer
+//! encoding or by the end of the connection; interim `1xx` responses are
+//! skipped. Anything that could frame the body two ways or smuggle a header
+//! is rejected as [Error::Network].
+//!
+//! The parser reads into a `Vec` that grows as the response arrives, or into
+//! a fixed `&mut [u8]` that it never leaves, for callers that keep off the
+//! heap. Either way a chunked body is decoded where it lies.
+//!
+//! Nothing here touches the network, so `tools/http_conformance` runs it
+//! against a local server on the host.
+
+use alloc::{string::String, vec::Vec};
+use core::ops::Range;
+
+use log::warn;
+
+use crate::{url::Url, Error};
+
+/// Longest status line and headers accepted, interim responses included
+pub const MAX_HEAD_LEN: usize = 4096;
+pub const MAX_HEADERS: usize = 32;
+/// Longest chunk size line, extensions included
+const MAX_CHUNK_LINE: usize = 64;
+
+/// A complete response
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// A response with a body of `content_type`, for the [crate::http_server]
+    pub fn new(status: u16, content_type: &str, body: Vec<u8>) -> Self {
+        Self {
+            status,
+            headers: alloc::vec![("Content-Type".into(), content_type.into())],
+            body,
+        }
+    }
+
+    /// Looks up a header, ignoring case
+    pub fn header(&self, name: &str) -> Option<&str> {
+        header(&self.headers, name)
+    }
+
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// Where a redirect sent to `from` points, `None` if it isn't one
+    pub fn redirect(&self, from: &Url<'_>) -> Option<String> {
+        match self.status {
+            301 | 302 | 303 | 307 | 308 => self.header("location").map(|to| from.join(to)),
+            _ => None,
+        }
+    }
+}
+
+fn header<'h>(headers: &'h [(String, String)], name: &str) -> Option<&'h str> {
+    headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.as_str())
+}
+
+/// A complete response left in the buffer it was read into
+#[derive(Debug, Clone, Copy)]
+pub struct ResponseRef<'b> {
+    pub status: u16,
+    /// The header lines, already checked
+    head: &'b str,
+    pub body: &'b [u8],
+}
+
+impl<'b> ResponseRef<'b> {
+    /// Looks up a header, ignoring case
+    pub fn header(&self, name: &str) -> Option<&'b str> {
+        self.headers()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v)
+    }
+
+    pub fn headers(&self) -> impl Iterator<Item = (&'b str, &'b str)> {
+        header_lines(self.head)
+    }
+
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+/// Where a [ResponseParser] keeps what it has read
+pub trait Buffer: AsMut<[u8]> {
+    /// Forgets what the buffer held
+    fn reset(&mut self) {}
+
+    /// Makes the buffer at least `len` bytes long, if it can grow
+    fn grow_to(&mut self, len: usize);
+
+    /// Longest the buffer can get
+    fn max_len(&self) -> usize;
+}
+
+impl Buffer for Vec<u8> {
+    fn reset(&mut self) {
+        self.clear();
+    }
+
+    fn grow_to(&mut self, len: usize) {
+        if self.len() < len {
+            self.resize(len, 0);
+        }
+    }
+
+    fn max_len(&self) -> usize {
+        usize::MAX
+    }
+}
+
+impl Buffer for &mut [u8] {
+    fn grow_to(&mut self, _len: usize) {}
+
+    fn max_len(&self) -> usize {
+        self.len()
+    }
+}
+
+/// How the end of the body is found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Framing {
+    Length(usize),
+    Chunked,
+    UntilClose,
+}
+
+struct Head {
+    status: u16,
+    /// Where the header lines are in the buffer, after the status line
+    lines: Range<usize>,
+    /// Offset of the body in the buffer
+    body_at: usize,
+    framing: Framing,
+}
+
+/// Collects a response read in pieces:
+///
+/// ```ignore
+/// let mut parser = ResponseParser::new(Vec::new(), MAX_RESPONSE_LEN);
+/// loop {
+///     let len = socket.read(parser.spare(READ_CHUNK))?;
+///     if len == 0 || parser.commit(len)? {
+///         break;
+///     }
+/// }
+/// let response = parser.finish()?;
+/// ```
+pub struct ResponseParser<B: Buffer = Vec<u8>> {
+    raw: B,
+    /// Bytes of `raw` read so far
+    filled: usize,
+    limit: usize,
+    /// Where the response after any interim ones starts
+    start: usize,
+    head: Option<Head>,
+    truncated: bool,
+}
+
+impl<B: Buffer> ResponseParser<B> {
+    /// A parser reading into `buffer`, keeping at most `limit` bytes of the
+    /// response, or as many as a fixed buffer holds
+    pub fn new(mut buffer: B, limit: usize) -> Self {
+        buffer.reset();
+        let limit = limit.min(buffer.max_len());
+        Self {
+            raw: buffer,
+            filled: 0,
+            limit,
+            start: 0,
+            head: None,
+            truncated: false,
+        }
+    }
+
+    /// Room for up to `max` more bytes, to read into before [Self::commit]
+    pub fn spare(&mut self, max: usize) -> &mut [u8] {
+        let end = (self.filled + max).min(self.limit);
+        self.raw.grow_to(end);
+        &mut self.raw.as_mut()[self.filled..end]
+    }
+
+    /// Bytes committed so far, interim responses included
+    pub fn received(&self) -> usize {
+        self.filled
+    }
+
+    /// Takes `len` bytes read into [Self::spare]; true once the response is
+    /// complete or the buffer is full, and reading should stop
+    pub fn commit(&mut self, len: usize) -> Result<bool, Error> {
+        self.filled = (self.filled + len).min(self.limit);
+        if self.head.is_none() {
+            self.head = self.parse_head()?;
+        }
+        let complete = match &self.head {
+            Some(head) => {
+                let body = &mut self.raw.as_mut()[head.body_at..self.filled];
+                match head.framing {
+                    Framing::Length(len) => body.len() >= len,
+                    Framing::Chunked => chunks(body, |_, _| {})?,
+                    Framing::UntilClose => false,
+                }
+            }
+            None => false,
+        };
+        if !complete && self.filled == self.limit {
+            warn!("response truncated to {} bytes", self.limit);
+            self.truncated = true;
+            return Ok(true);
+        }
+        Ok(complete)
+    }
+
+    /// Checks how the body ends and decodes it in place, once reading has
+    /// stopped because [Self::commit] said so or the server closed the
+    /// connection. Returns the head and where the body is in the buffer.
+    fn frame(&mut self) -> Result<(Head, Range<usize>), Error> {
+        let head = match self.head.take() {
+            Some(head) => head,
+            None => return Err(malformed("no complete head")),
+        };
+        let body = &mut self.raw.as_mut()[head.body_at..self.filled];
+        let len = match head.framing {
+            Framing::Length(len) if body.len() < len && !self.truncated => {
+                return Err(malformed("body shorter than Content-Length"))
+            }
+            Framing::Length(len) => len.min(body.len()),
+            Framing::Chunked => {
+                let (len, complete) = dechunk(body)?;
+                if !complete && !self.truncated {
+                    return Err(malformed("unterminated chunked body"));
+                }
+                len
+            }
+            Framing::UntilClose => body.len(),
+        };
+        let body_at = head.body_at;
+        Ok((head, body_at..body_at + len))
+    }
+
+    /// The head of the final response, `None` until it is all there
+    fn parse_head(&mut self) -> Result<Option<Head>, Error> {
+        loop {
+            let raw = &self.raw.as_mut()[self.start..self.filled];
+            let Some(len) = raw.windows(4).position(|w| w == b"\r\n\r\n") else {
+                if self.filled - self.start > MAX_HEAD_LEN {
+                    return Err(malformed("head too long"));
+                }
+                return Ok(None);
+            };
+            let body_at = self.start + len + 4;
+            if body_at > MAX_HEAD_LEN {
+                return Err(malformed("head too long"));
+            }
+            let text = core::str::from_utf8(&raw[..len]).map_err(|_| malformed("not UTF-8"))?;
+            let (status, lines_at) = parse_head(text)?;
+            if (100..200).contains(&status) {
+                self.start = body_at;
+                continue;
+            }
+            let framing = framing(status, header_lines(&text[lines_at..]))?;
+            return Ok(Some(Head {
+                status,
+                lines: self.start + lines_at..self.start + len,
+                body_at,
+                framing,
+            }));
+        }
+    }
+}
+
+impl ResponseParser<Vec<u8>> {
+    /// The response, once reading has stopped because [Self::commit] said so
+    /// or the server closed the connection
+    pub fn finish(mut self) -> Result<Response, Error> {
+        let (head, body_range) = self.frame()?;
+        let mut body = self.raw;
+        // checked when the head was parsed
+        let lines = core::str::from_utf8(&body[head.lines]).unwrap_or_default();
+        let headers = header_lines(lines)
+            .map(|(name, value)| (name.into(), value.into()))
+            .collect();
+        body.truncate(body_range.end);
+        body.drain(..body_range.start);
+        Ok(Response {
+            status: head.status,
+            headers,
+            body,
+        })
+    }
+}
+
+impl<'b> ResponseParser<&'b mut [u8]> {
+    /// [ResponseParser::finish] for a fixed buffer, leaving the response in
+    /// it
+    pub fn finish_in_place(mut self) -> Result<ResponseRef<'b>, Error> {
+        let (head, body) = self.frame()?;
+        let raw: &'b [u8] = self.raw;
+        Ok(ResponseRef {
+            status: head.status,
+            // checked when the head was parsed
+            head: core::str::from_utf8(&raw[head.lines]).unwrap_or_default(),
+            body: &raw[body],
+        })
+    }
+}
+
+fn malformed(what: &str) -> Error {
+    warn!("malformed HTTP response: {}", what);
+    Error::Network
+}
+
+/// Checks the lines before the blank one, returning the status and where
+/// the header lines start
+fn parse_head(text: &str) -> Result<(u16, usize), Error> {
+    // HTTP/1.1 200 OK
+    let (line, lines) = text.split_once("\r\n").unwrap_or((text, ""));
+    let (version, rest) = line
+        .split_once(' ')
+        .ok_or_else(|| malformed("status line"))?;
+    let valid = matches!(version, "HTTP/1.0" | "HTTP/1.1")
+        && rest.len() >= 3
+        && rest.as_bytes()[..3].iter().all(u8::is_ascii_digit)
+        && (rest.len() == 3 || rest.as_bytes()[3] == b' ');
+    if !valid {
+        return Err(malformed("status line"));
+    }
+    let status: u16 = rest[..3].parse().map_err(|_| malformed("status"))?;
+    if !(100..600).contains(&status) {
+        return Err(malformed("status"));
+    }
+
+    for (count, line) in lines
+        .split("\r\n")
+        .filter(|_| !lines.is_empty())
+        .enumerate()
+    {
+        // folded continuation lines are obsolete and a smuggling vector
+        if line.starts_with([' ', '\t']) {
+            return Err(malformed("folded header"));
+        }
+        let (name, _) = line
+            .split_once(':')
+            .ok_or_else(|| malformed("header without a colon"))?;
+        if name.is_empty() || !name.bytes().all(|b| b.is_ascii_graphic()) {
+            return Err(malformed("header name"));
+        }
+        if count == MAX_HEADERS {
+            return Err(malformed("too many headers"));
+        }
+    }
+    Ok((status, text.len() - lines.len()))
+}
+
+/// Names and trimmed values of header lines [parse_head] has checked
+fn header_lines(lines: &str) -> impl Iterator<Item = (&str, &str)> + Clone {
+    lines
+        .split("\r\n")
+        .filter(|line| !line.is_empty())
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name, value.trim_matches([' ', '\t'])))
+}
+
+fn framing<'h>(
+    status: u16,
+    headers: impl Iterator<Item = (&'h str, &'h str)> + Clone,
+) -> Result<Framing, Error> {
+    if status == 204 || status == 304 {
+        return Ok(Framing::Length(0));
+    }
+    let mut lengths = headers
+        .clone()
+        .filter(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+        .map(|(_, value)| {
+            value
+                .parse::<usize>()
+                .map_err(|_| malformed("Content-Length"))
+        });
+    let length = lengths.next().transpose()?;
+    for other in lengths {
+        if Some(other?) != length {
+            return Err(malformed("conflicting Content-Length"));
+        }
+    }
+    let coding = headers
+        .filter(|(name, _)| name.eq_ignore_ascii_case("transfer-encoding"))
+        .map(|(_, value)| value)
+        .next();
+    match coding {
+        Some(coding) if !coding.eq_ignore_ascii_case("chunked") => {
+            Err(malformed("unsupported Transfer-Encoding"))
+        }
+        Some(_) if length.is_some() => Err(malformed("both Content-Length and chunked")),
+        Some(_) => Ok(Framing::Chunked),
+        None => Ok(length.map_or(Framing::UntilClose, Framing::Length)),
+    }
+}
+
+/// Moves the data of the chunks in `body` to its start, returning how long
+/// it is and whether the last chunk and trailers were all there
+fn dechunk(body: &mut [u8]) -> Result<(usize, bool), Error> {
+    let mut len = 0;
+    let complete = chunks(body, |body, range| {
+        // the data only ever moves forward, behind where the walk has got to
+        let chunk_len = range.len();
+        body.copy_within(range, len);
+        len += chunk_len;
+    })?;
+    Ok((len, complete))
+}
+
+/// Walks a chunked body, passing where each chunk's data is; true once the
+/// last chunk and trailers are all there. `data` may write to the body
+/// before the chunk it is given.
+fn chunks(body: &mut [u8], mut data: impl FnMut(&mut [u8], Range<usize>)) -> Result<bool, Error> {
+    let mut at = 0;
+    loop {
+        let Some(line_len) = line(&body[at..], MAX_CHUNK_LINE)? else {
+            return Ok(false);
+        };
+        let size = chunk_size(&body[at..at + line_len])?;
+        at += line_len + 2;
+        if size == 0 {
+            // trailers, which are dropped, up to a blank line
+            loop {
+                let Some(len) = line(&body[at..], MAX_HEAD_LEN)? else {
+                    return Ok(false);
+                };
+                at += len + 2;
+                if len == 0 {
+                    return Ok(true);
+                }
+            }
+        }
+        let end = at
+            .checked_add(size)
+            .ok_or_else(|| malformed("chunk size"))?;
+        if body.len() < end + 2 {
+            return Ok(false);
+        }
+        if &body[end..end + 2] != b"\r\n" {
+            return Err(malformed("chunk not followed by CRLF"));
+        }
+        data(body, at..end);
+        at = end + 2;
+    }
+}
+
+/// Length of the line at the start of `data` without its CRLF, `None` if
+/// the CRLF hasn't arrived yet
+fn line(data: &[u8], max: usize) -> Result<Option<usize>, Error> {
+    match data.windows(2).position(|w| w == b"\r\n") {
+        Some(len) if len <= max => Ok(Some(len)),
+        None if data.len() <= max => Ok(None),
+        _ => Err(malformed("line too long")),
+    }
+}
+
+/// The hex size of a chunk line, ignoring extensions
+fn chunk_size(line: &[u8]) -> Result<usize, Error> {
+    let hex = line.split(|&b| b == b';').next().unwrap_or_default();
+    let hex = core::str::from_utf8(hex)
+        .map_err(|_| malformed("chunk size"))?
+        .trim_end_matches([' ', '\t']);
+    if hex.is_empty() || hex.len() > 8 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(malformed("chunk size"));
+    }
+    usize::from_str_radix(hex, 16).map_err(|_| malformed("chunk size"))
+}
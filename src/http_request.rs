@@ -0,0 +1,187 @@
+//! What [crate::http] sends: the request head, and where a redirect leads.
+//!
+//! A [Head] writes the request line and headers, and refuses a URL or header
+//! that would split the request with a line break or space. [follow] sends a request with
+//! whatever sends one and follows up to [MAX_REDIRECTS] GET redirects,
+//! dropping the credentials when one leads to another host.
+//!
+//! Like [crate::http_parser], this needs nothing from the chip, so
+//! `tools/http_conformance` sends the same bytes and follows the same
+//! redirects against a local server on the host.
+
+use alloc::string::String;
+use core::fmt;
+
+use log::{debug, warn};
+
+use crate::{http_parser::Response, url::Url, Error};
+
+/// Redirects followed before the last one is returned as the response
+pub const MAX_REDIRECTS: usize = 3;
+
+/// Credentials sent in the `Authorization` header
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Auth {
+    Basic { username: String, password: String },
+    Bearer(String),
+}
+
+impl Auth {
+    fn write_value(&self, out: &mut impl fmt::Write) -> fmt::Result {
+        match self {
+            Auth::Basic { username, password } => {
+                out.write_str("Basic ")?;
+                write_base64(out, &[username.as_bytes(), b":", password.as_bytes()])
+            }
+            Auth::Bearer(token) => write!(out, "Bearer {}", token),
+        }
+    }
+}
+
+/// A request as far as its head goes
+#[derive(Debug, Clone, Copy)]
+pub struct Head<'r> {
+    pub method: &'r str,
+    /// The body's content type and length
+    pub body: Option<(&'r str, usize)>,
+    pub headers: &'r [(&'r str, &'r str)],
+    pub auth: Option<&'r Auth>,
+    /// Whether to ask for a gzip or deflate body
+    pub compressed: bool,
+    /// Leaves out `auth` and any `Authorization` header, once a redirect has
+    /// led away from the host they were for
+    pub anonymous: bool,
+}
+
+impl<'r> Head<'r> {
+    pub fn new(method: &'r str) -> Self {
+        Self {
+            method,
+            body: None,
+            headers: &[],
+            auth: None,
+            compressed: false,
+            anonymous: false,
+        }
+    }
+
+    /// Refuses a header with a line break, and a URL whose host, path or
+    /// query has a space or control character in it
+    pub fn check(&self, url: &Url<'_>) -> Result<(), Error> {
+        let injected = |s: &str| s.contains(['\r', '\n']);
+        if self
+            .headers
+            .iter()
+            .any(|(name, value)| name.is_empty() || injected(name) || injected(value))
+        {
+            warn!("refusing a header with a line break");
+            return Err(Error::InvalidConfig);
+        }
+        let target = [url.host, url.path, url.query.unwrap_or_default()];
+        if !target.iter().all(|part| is_clean(part)) {
+            warn!("refusing a URL with a space or line break in it");
+            return Err(Error::InvalidUrl);
+        }
+        Ok(())
+    }
+
+    /// The request line and headers for `url`, up to the blank line
+    pub fn write(&self, out: &mut impl fmt::Write, url: &Url<'_>) -> fmt::Result {
+        write!(out, "{} {}", self.method, url.path)?;
+        if let Some(query) = url.query {
+            write!(out, "?{}", query)?;
+        }
+        write!(out, " HTTP/1.0\r\nHost: {}", url.host)?;
+        // the port goes in too when it isn't the scheme's (RFC 7230 5.4)
+        if url.port != 80 {
+            write!(out, ":{}", url.port)?;
+        }
+        out.write_str("\r\nConnection: close\r\n")?;
+        if self.compressed {
+            out.write_str("Accept-Encoding: gzip, deflate\r\n")?;
+        }
+        if let Some((content_type, len)) = self.body {
+            write!(
+                out,
+                "Content-Type: {}\r\nContent-Length: {}\r\n",
+                content_type, len
+            )?;
+        }
+        for (name, value) in self.headers {
+            if self.anonymous && name.eq_ignore_ascii_case("authorization") {
+                continue;
+            }
+            write!(out, "{}: {}\r\n", name, value)?;
+        }
+        if let Some(auth) = self.auth.filter(|_| !self.anonymous) {
+            out.write_str("Authorization: ")?;
+            auth.write_value(out)?;
+            out.write_str("\r\n")?;
+        }
+        out.write_str("\r\n")
+    }
+}
+
+/// Sends `head` to `url` with `send` and then to wherever the responses
+/// redirect, returning the last response. Only GET requests are redirected;
+/// a redirect past [MAX_REDIRECTS], or to a location with a space or control
+/// character in it, is returned as it is.
+pub fn follow(
+    mut head: Head<'_>,
+    url: &str,
+    mut send: impl FnMut(&Head<'_>, &Url<'_>) -> Result<Response, Error>,
+) -> Result<Response, Error> {
+    let mut target = String::from(url);
+    let mut redirects = 0;
+    loop {
+        let url = Url::parse_with_scheme(&target, "http", 80)?;
+        head.check(&url)?;
+        let response = send(&head, &url)?;
+        let Some(to) = response.redirect(&url).filter(|_| head.method == "GET") else {
+            return Ok(response);
+        };
+        if redirects == MAX_REDIRECTS || !is_clean(&to) {
+            warn!("not following the redirect to {}", to);
+            return Ok(response);
+        }
+        redirects += 1;
+        debug!("{} redirects to {}", url, to);
+        let same_host = Url::parse_with_scheme(&to, "http", 80)
+            .is_ok_and(|next| (next.host, next.port) == (url.host, url.port));
+        head.anonymous |= !same_host;
+        target = to;
+    }
+}
+
+/// Whether `s` can go in a request line without ending it early
+fn is_clean(s: &str) -> bool {
+    !s.bytes().any(|b| b <= b' ' || b == 0x7f)
+}
+
+/// Standard base64 with padding of `parts` one after the other
+fn write_base64(out: &mut impl fmt::Write, parts: &[&[u8]]) -> fmt::Result {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut bytes = parts.iter().flat_map(|part| part.iter().copied());
+    loop {
+        let mut chunk = [0u8; 3];
+        let mut len = 0;
+        for (slot, byte) in chunk.iter_mut().zip(&mut bytes) {
+            *slot = byte;
+            len += 1;
+        }
+        if len == 0 {
+            return Ok(());
+        }
+        let bits = u32::from_be_bytes([0, chunk[0], chunk[1], chunk[2]]);
+        for i in 0..4 {
+            if i <= len {
+                out.write_char(ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize] as char)?;
+            } else {
+                out.write_char('=')?;
+            }
+        }
+        if len < 3 {
+            return Ok(());
+        }
+    }
+}
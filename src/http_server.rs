@@ -0,0 +1,215 @@
+//! Minimal HTTP/1.0 server, polled from the app host loop.
+//!
+//! One connection is served at a time and every response closes it, which is
+//! plenty for the occasional scrape or browser visit. Routes are plain
+//! functions matched on the exact path, query string excluded.
+
+use alloc::{format, vec::Vec};
+
+use embedded_io::{Read as _, ReadReady as _, Write as _};
+use esp_hal::time::{Duration, Instant};
+use log::{debug, warn};
+
+use crate::{
+    http::Response,
+    net::{NetStack, TcpSocket},
+};
+
+pub const DEFAULT_PORT: u16 = 80;
+/// Requests larger than this are refused
+pub const MAX_REQUEST_LEN: usize = 2048;
+/// A client that hasn't sent a whole request by then is dropped
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A parsed request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Request<'r> {
+    pub method: &'r str,
+    /// Path without the query
+    pub path: &'r str,
+    pub query: Option<&'r str>,
+    pub body: &'r [u8],
+}
+
+pub type Handler = fn(&Request<'_>) -> Response;
+
+/// Serves registered routes on one listening socket
+pub struct HttpServer<'a> {
+    socket: TcpSocket<'a, 'a>,
+    port: u16,
+    routes: Vec<(&'static str, Handler)>,
+    request: Vec<u8>,
+    /// When the first byte of the current request arrived
+    started: Option<Instant>,
+}
+
+impl<'a> HttpServer<'a> {
+    /// A server on `port` using socket buffers that live as long as the stack
+    pub fn new(
+        stack: &'a NetStack<'a>,
+        port: u16,
+        rx_buffer: &'a mut [u8],
+        tx_buffer: &'a mut [u8],
+    ) -> Self {
+        Self {
+            socket: stack.get_socket(rx_buffer, tx_buffer),
+            port,
+            routes: Vec::new(),
+            request: Vec::new(),
+            started: None,
+        }
+    }
+
+    /// Serves `handler` at `path`, replacing any earlier handler for it
+    pub fn route(&mut self, path: &'static str, handler: Handler) {
+        self.routes.retain(|(p, _)| *p != path);
+        self.routes.push((path, handler));
+    }
+
+    /// Makes progress on the current connection without blocking for long
+    pub fn poll(&mut self) {
+        if !self.socket.is_open() {
+            self.request.clear();
+            self.started = None;
+            if let Err(err) = self.socket.listen_unblocking(self.port) {
+                warn!("can't listen on port {}: {:?}", self.port, err);
+            }
+            return;
+        }
+        if !self.socket.is_connected() {
+            return;
+        }
+
+        let now = Instant::now();
+        if self
+            .started
+            .is_some_and(|started| now - started > REQUEST_TIMEOUT)
+        {
+            debug!("dropping a client that stalled mid-request");
+            self.socket.disconnect();
+            return;
+        }
+        match self.socket.read_ready() {
+            Ok(true) => {}
+            Ok(false) => return,
+            Err(_) => {
+                self.socket.disconnect();
+                return;
+            }
+        }
+        let mut chunk = [0u8; 512];
+        let Ok(len) = self.socket.read(&mut chunk) else {
+            self.socket.disconnect();
+            return;
+        };
+        self.started.get_or_insert(now);
+        self.request.extend_from_slice(&chunk[..len]);
+
+        let response = if self.request.len() > MAX_REQUEST_LEN {
+            error_response(413, "request too large")
+        } else {
+            match parse_request(&self.request) {
+                Parsed::Incomplete => return,
+                Parsed::Invalid => error_response(400, "bad request"),
+                Parsed::Complete(request) => self.dispatch(&request),
+            }
+        };
+        self.respond(&response);
+    }
+
+    fn dispatch(&self, request: &Request<'_>) -> Response {
+        debug!("{} {}", request.method, request.path);
+        match self.routes.iter().find(|(path, _)| *path == request.path) {
+            Some((_, handler)) => handler(request),
+            None => error_response(404, "not found"),
+        }
+    }
+
+    fn respond(&mut self, response: &Response) {
+        let mut head = format!(
+            "HTTP/1.0 {} {}\r\nConnection: close\r\nContent-Length: {}\r\n",
+            response.status,
+            reason(response.status),
+            response.body.len()
+        );
+        for (name, value) in &response.headers {
+            head += &format!("{}: {}\r\n", name, value);
+        }
+        head += "\r\n";
+
+        let sent = self
+            .socket
+            .write_all(head.as_bytes())
+            .and_then(|()| self.socket.write_all(&response.body))
+            .and_then(|()| self.socket.flush());
+        if sent.is_err() {
+            debug!("client went away before the response was sent");
+        }
+        // closing lets the response drain; the socket listens again once it's closed
+        self.socket.close();
+        self.request.clear();
+        self.started = None;
+    }
+}
+
+enum Parsed<'r> {
+    Incomplete,
+    Invalid,
+    Complete(Request<'r>),
+}
+
+fn parse_request(raw: &[u8]) -> Parsed<'_> {
+    let Some(head_len) = raw.windows(4).position(|w| w == b"\r\n\r\n") else {
+        return Parsed::Incomplete;
+    };
+    let Ok(head) = core::str::from_utf8(&raw[..head_len]) else {
+        return Parsed::Invalid;
+    };
+    let mut lines = head.split("\r\n");
+
+    // GET /path?query HTTP/1.1
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+    let (Some(method), Some(target)) = (request_line.next(), request_line.next()) else {
+        return Parsed::Invalid;
+    };
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (target, None),
+    };
+
+    let content_length = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+        .map(|(_, value)| value.trim().parse::<usize>());
+    let body_len = match content_length {
+        Some(Ok(len)) => len,
+        Some(Err(_)) => return Parsed::Invalid,
+        None => 0,
+    };
+    let body = &raw[head_len + 4..];
+    if body.len() < body_len {
+        return Parsed::Incomplete;
+    }
+    Parsed::Complete(Request {
+        method,
+        path,
+        query,
+        body: &body[..body_len],
+    })
+}
+
+fn error_response(status: u16, message: &str) -> Response {
+    Response::new(status, "text/plain", format!("{}\n", message).into_bytes())
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        500 => "Internal Server Error",
+        _ => "",
+    }
+}
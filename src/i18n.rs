@@ -0,0 +1,618 @@
+//! String tables for the bundled apps.
+//!
+//! Look strings up with [`tr!`](crate::tr). The default language is picked at
+//! build time from the `MAGTAG_LANG` environment variable (`en`, `de`, `fr`,
+//! `es`) and can be overridden at runtime from the `lang` config key. Keys
+//! missing from a table fall back to English, then to the key itself.
+
+use alloc::string::String;
+use core::{
+    fmt::{self, Write as _},
+    str::FromStr,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+/// Supported UI languages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Language {
+    En,
+    De,
+    Fr,
+    Es,
+}
+
+impl Language {
+    pub const ALL: [Language; 4] = [Language::En, Language::De, Language::Fr, Language::Es];
+
+    /// The language chosen at build time
+    pub const fn build_default() -> Self {
+        match option_env!("MAGTAG_LANG") {
+            Some(code) => match Self::from_code(code) {
+                Some(lang) => lang,
+                None => Language::En,
+            },
+            None => Language::En,
+        }
+    }
+
+    pub const fn code(self) -> &'static str {
+        match self {
+            Language::En => "en",
+            Language::De => "de",
+            Language::Fr => "fr",
+            Language::Es => "es",
+        }
+    }
+
+    /// Name of the language in that language
+    pub const fn native_name(self) -> &'static str {
+        match self {
+            Language::En => "English",
+            Language::De => "Deutsch",
+            Language::Fr => "Français",
+            Language::Es => "Español",
+        }
+    }
+
+    const fn from_code(code: &str) -> Option<Self> {
+        match code.as_bytes() {
+            b"en" => Some(Language::En),
+            b"de" => Some(Language::De),
+            b"fr" => Some(Language::Fr),
+            b"es" => Some(Language::Es),
+            _ => None,
+        }
+    }
+
+    fn from_index(index: u8) -> Self {
+        Self::ALL
+            .get(index as usize)
+            .copied()
+            .unwrap_or(Language::En)
+    }
+
+    fn table(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Language::En => EN,
+            Language::De => DE,
+            Language::Fr => FR,
+            Language::Es => ES,
+        }
+    }
+}
+
+impl Default for Language {
+    fn default() -> Self {
+        Self::build_default()
+    }
+}
+
+impl FromStr for Language {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_code(s).ok_or(())
+    }
+}
+
+static CURRENT: AtomicU8 = AtomicU8::new(Language::build_default() as u8);
+
+/// The language used by [`tr!`](crate::tr)
+pub fn language() -> Language {
+    Language::from_index(CURRENT.load(Ordering::Relaxed))
+}
+
+pub fn set_language(language: Language) {
+    CURRENT.store(language as u8, Ordering::Relaxed);
+}
+
+/// Looks up `key` in the current language
+pub fn translate(key: &'static str) -> &'static str {
+    lookup(language().table(), key)
+        .or_else(|| lookup(EN, key))
+        .unwrap_or(key)
+}
+
+fn lookup(table: &'static [(&'static str, &'static str)], key: &str) -> Option<&'static str> {
+    table.iter().find(|(k, _)| *k == key).map(|(_, v)| *v)
+}
+
+/// `text` with each `{}` replaced by the next of `args`, so that every
+/// language can put them where its word order wants them
+pub fn fill(text: &str, args: &[&dyn fmt::Display]) -> String {
+    let mut parts = text.split("{}");
+    let mut filled = String::from(parts.next().unwrap_or_default());
+    let mut args = args.iter();
+    for part in parts {
+        if let Some(arg) = args.next() {
+            write!(filled, "{}", arg).ok();
+        }
+        filled += part;
+    }
+    filled
+}
+
+/// Translates a string key, e.g. `tr!("battery_low")`; with arguments, as
+/// in `tr!("tasks_title", open)`, they fill the `{}` of the string
+#[macro_export]
+macro_rules! tr {
+    ($key:literal) => {
+        $crate::i18n::translate($key)
+    };
+    ($key:literal, $($arg:expr),+ $(,)?) => {
+        $crate::i18n::fill($crate::i18n::translate($key), &[$(&$arg),+])
+    };
+}
+
+// Screens draw these with the fonts of crate::fonts, which keep every
+// character used here; the built-in embedded-graphics fonts are ASCII only.
+
+const EN: &[(&str, &str)] = &[
+    ("battery_low", "Battery low"),
+    ("updating", "Updating..."),
+    ("no_network", "No network"),
+    ("settings", "Settings"),
+    ("saved", "saved"),
+    ("save_failed", "save failed"),
+    ("refresh", "Refresh"),
+    ("timezone", "Timezone"),
+    ("units", "Units"),
+    ("neopixels", "NeoPixels"),
+    ("contrast", "Contrast"),
+    ("contrast_normal", "Normal"),
+    ("contrast_high", "High"),
+    ("contrast_mono", "B+W"),
+    ("language", "Language"),
+    ("celsius", "Celsius"),
+    ("fahrenheit", "Fahrenheit"),
+    ("minutes_short", "min"),
+    ("next", "next"),
+    ("save", "save"),
+    ("reconnecting_wifi", "Reconnecting WiFi..."),
+    ("offline_setup", "Offline - hold D at boot to set up WiFi"),
+    ("clock_not_synced", "Clock not synced"),
+    ("alert", "ALERT"),
+    ("alert_title", "Alert"),
+    ("press_any_button", "Press any button"),
+    ("stale", "stale"),
+    ("stale_since", "stale since {}"),
+    ("unknown", "unknown"),
+    ("up", "up"),
+    ("down", "down"),
+    ("update", "update"),
+    ("reload", "reload"),
+    ("done", "done"),
+    ("scan", "scan"),
+    ("join", "join"),
+    ("ranks", "ranks"),
+    ("swap", "swap"),
+    ("nearby", "nearby"),
+    ("pass", "pass"),
+    ("repeat", "repeat"),
+    ("fail", "fail"),
+    ("again", "again"),
+    ("row", "row"),
+    ("type", "type"),
+    ("space", "space"),
+    ("delete", "del"),
+    ("cancel", "cancel"),
+    ("badges_nearby", "{} - {} nearby"),
+    ("no_badges", "No badges nearby yet"),
+    ("leaderboard", "Leaderboard - {} contacts"),
+    ("badge_swapped", "{} swapped contacts"),
+    ("badge_got_contact", "Got {}'s contact"),
+    ("badge_not_received", "{} didn't get it"),
+    ("no_template", "No dashboard template: {}"),
+    ("demo_hello", "Hello from Gray2 Rust!"),
+    ("github_notification", "GitHub - 1 notification"),
+    ("github_notifications", "GitHub - {} notifications"),
+    (
+        "github_unconfigured",
+        "Set {} to a proxy for api.github.com",
+    ),
+    ("ci_passing", "passing"),
+    ("ci_pending", "pending"),
+    ("ci_failing", "failing"),
+    ("bad_key", "Bad {}: {}"),
+    ("set_keys", "Set {} and {}"),
+    ("waiting_for_frame", "Waiting for a frame on"),
+    ("safe_mode", "Safe mode"),
+    ("safe_mode_apps", "Apps are not running."),
+    ("safe_mode_console", "Connect over USB to use the console,"),
+    ("safe_mode_reset", "reset the badge to start normally."),
+    ("selftest", "Self-test"),
+    ("selftest_black", "Evenly black, no light spots?"),
+    ("selftest_grays", "Four distinct shades?"),
+    ("selftest_checker", "Sharp squares, no ghosting?"),
+    ("selftest_neopixels", "All four red, green, blue, white?"),
+    ("selftest_speaker", "Four rising tones?"),
+    ("press_all_buttons", "Press A, B, C and D"),
+    ("measuring", "Measuring..."),
+    ("selftest_passed", "Self-test passed"),
+    ("selftest_failed", "Self-test: {} failed"),
+    ("status", "Status"),
+    ("status_battery", "Battery   {} V, about {}%"),
+    ("status_battery_none", "Battery   no reading"),
+    ("status_draw", "Draw      {} mA average since power-on"),
+    ("status_draw_none", "Draw      not measured yet"),
+    ("status_left", "Left      {} at this rate ({} mAh)"),
+    ("status_left_none", "Left      unknown"),
+    ("status_time", "Time     "),
+    ("status_wifi", "WiFi      {} dBm"),
+    ("status_wifi_none", "WiFi      not connected"),
+    ("status_restarted_once", ", restarted once"),
+    ("status_restarted", ", restarted {} times"),
+    ("status_up", "Up        {}, {} KiB heap free"),
+    ("steps", "Steps"),
+    ("no_accelerometer", "No accelerometer"),
+    ("steps_counted", "Counted while this screen is shown"),
+    ("tasks_title", "Today - {} open"),
+    ("nothing_due", "Nothing due today"),
+    ("tasks_unavailable", "Tasks unavailable: {}"),
+    ("tasks_unconfigured", "Set {} to a CalDAV task list"),
+    ("task_not_done", "Couldn't mark the task done"),
+    ("wifi_survey_title", "WiFi survey, {} found"),
+    ("scan_failed", "Scan failed: {}"),
+    ("security", "security"),
+    ("hidden", "(hidden)"),
+    ("hidden_needs_setup", "Hidden networks need provisioning"),
+    ("password_for", "Password for {}"),
+    ("join_failed", "Joining failed"),
+    ("joined_saved", "Joined and saved"),
+    ("joined_not_saved", "Joined, saving failed"),
+];
+
+const DE: &[(&str, &str)] = &[
+    ("battery_low", "Akku schwach"),
+    ("updating", "Aktualisiere..."),
+    ("no_network", "Kein Netzwerk"),
+    ("settings", "Einstellungen"),
+    ("saved", "gespeichert"),
+    ("save_failed", "Fehler beim Speichern"),
+    ("refresh", "Intervall"),
+    ("timezone", "Zeitzone"),
+    ("units", "Einheiten"),
+    ("neopixels", "NeoPixel"),
+    ("contrast", "Kontrast"),
+    ("contrast_normal", "Normal"),
+    ("contrast_high", "Hoch"),
+    ("contrast_mono", "S+W"),
+    ("language", "Sprache"),
+    ("celsius", "Celsius"),
+    ("fahrenheit", "Fahrenheit"),
+    ("minutes_short", "Min"),
+    ("next", "weiter"),
+    ("save", "sichern"),
+    ("reconnecting_wifi", "WLAN verbindet neu..."),
+    ("offline_setup", "Offline - D beim Start halten für WLAN"),
+    ("clock_not_synced", "Uhr nicht gestellt"),
+    ("alert", "ALARM"),
+    ("alert_title", "Alarm"),
+    ("press_any_button", "Beliebige Taste drücken"),
+    ("stale", "veraltet"),
+    ("stale_since", "veraltet seit {}"),
+    ("unknown", "unbekannt"),
+    ("up", "hoch"),
+    ("down", "runter"),
+    ("update", "laden"),
+    ("reload", "neu laden"),
+    ("done", "erledigt"),
+    ("scan", "suchen"),
+    ("join", "verbinden"),
+    ("ranks", "Rangliste"),
+    ("swap", "tauschen"),
+    ("nearby", "Nähe"),
+    ("pass", "bestanden"),
+    ("repeat", "nochmal"),
+    ("fail", "Fehler"),
+    ("again", "nochmal"),
+    ("row", "Zeile"),
+    ("type", "tippen"),
+    ("space", "Leer"),
+    ("delete", "entf"),
+    ("cancel", "abbrechen"),
+    ("badges_nearby", "{} - {} in der Nähe"),
+    ("no_badges", "Noch keine Badges in der Nähe"),
+    ("leaderboard", "Rangliste - {} Kontakte"),
+    ("badge_swapped", "{} hat Kontakte getauscht"),
+    ("badge_got_contact", "Kontakt von {} erhalten"),
+    ("badge_not_received", "{} hat nichts erhalten"),
+    ("no_template", "Keine Dashboard-Vorlage: {}"),
+    ("demo_hello", "Hallo von Gray2 Rust!"),
+    ("github_notification", "GitHub - 1 Benachrichtigung"),
+    ("github_notifications", "GitHub - {} Benachrichtigungen"),
+    (
+        "github_unconfigured",
+        "{} auf einen Proxy für api.github.com setzen",
+    ),
+    ("ci_passing", "ok"),
+    ("ci_pending", "läuft"),
+    ("ci_failing", "fehlgeschlagen"),
+    ("bad_key", "{} ungültig: {}"),
+    ("set_keys", "{} und {} setzen"),
+    ("waiting_for_frame", "Warte auf ein Bild an"),
+    ("safe_mode", "Abgesicherter Modus"),
+    ("safe_mode_apps", "Die Apps laufen nicht."),
+    ("safe_mode_console", "Über USB verbinden für die Konsole,"),
+    ("safe_mode_reset", "neu starten für den Normalbetrieb."),
+    ("selftest", "Selbsttest"),
+    ("selftest_black", "Gleichmäßig schwarz, ohne helle Stellen?"),
+    ("selftest_grays", "Vier klar getrennte Grautöne?"),
+    ("selftest_checker", "Scharfe Quadrate, kein Nachbild?"),
+    ("selftest_neopixels", "Alle vier rot, grün, blau, weiß?"),
+    ("selftest_speaker", "Vier steigende Töne?"),
+    ("press_all_buttons", "A, B, C und D drücken"),
+    ("measuring", "Messe..."),
+    ("selftest_passed", "Selbsttest bestanden"),
+    ("selftest_failed", "Selbsttest: {} fehlgeschlagen"),
+    ("status", "Status"),
+    ("status_battery", "Akku      {} V, etwa {}%"),
+    ("status_battery_none", "Akku      kein Messwert"),
+    ("status_draw", "Strom     {} mA im Mittel seit Start"),
+    ("status_draw_none", "Strom     noch nicht gemessen"),
+    ("status_left", "Rest      noch {} ({} mAh)"),
+    ("status_left_none", "Rest      unbekannt"),
+    ("status_time", "Zeit     "),
+    ("status_wifi", "WLAN      {} dBm"),
+    ("status_wifi_none", "WLAN      nicht verbunden"),
+    ("status_restarted_once", ", einmal neu gestartet"),
+    ("status_restarted", ", {} mal neu gestartet"),
+    ("status_up", "Laufzeit  {}, {} KiB frei"),
+    ("steps", "Schritte"),
+    ("no_accelerometer", "Kein Beschleunigungssensor"),
+    ("steps_counted", "Gezählt, solange diese Seite offen ist"),
+    ("tasks_title", "Heute - {} offen"),
+    ("nothing_due", "Heute nichts fällig"),
+    ("tasks_unavailable", "Aufgaben nicht verfügbar: {}"),
+    (
+        "tasks_unconfigured",
+        "{} auf eine CalDAV-Aufgabenliste setzen",
+    ),
+    ("task_not_done", "Aufgabe nicht abgehakt"),
+    ("wifi_survey_title", "WLAN-Suche, {} gefunden"),
+    ("scan_failed", "Suche fehlgeschlagen: {}"),
+    ("security", "Sicherheit"),
+    ("hidden", "(versteckt)"),
+    ("hidden_needs_setup", "Versteckte Netze: über WLAN-Setup"),
+    ("password_for", "Passwort für {}"),
+    ("join_failed", "Verbinden fehlgeschlagen"),
+    ("joined_saved", "Verbunden und gespeichert"),
+    ("joined_not_saved", "Verbunden, Speichern fehlgeschlagen"),
+];
+
+const FR: &[(&str, &str)] = &[
+    ("battery_low", "Batterie faible"),
+    ("updating", "Mise à jour..."),
+    ("no_network", "Pas de réseau"),
+    ("settings", "Réglages"),
+    ("saved", "enregistré"),
+    ("save_failed", "échec"),
+    ("refresh", "Intervalle"),
+    ("timezone", "Fuseau"),
+    ("units", "Unités"),
+    ("neopixels", "NeoPixels"),
+    ("contrast", "Contraste"),
+    ("contrast_normal", "Normal"),
+    ("contrast_high", "Élevé"),
+    ("contrast_mono", "N+B"),
+    ("language", "Langue"),
+    ("celsius", "Celsius"),
+    ("fahrenheit", "Fahrenheit"),
+    ("minutes_short", "min"),
+    ("next", "suiv."),
+    ("save", "sauver"),
+    ("reconnecting_wifi", "Reconnexion WiFi..."),
+    ("offline_setup", "Hors ligne - D au démarrage pour le WiFi"),
+    ("clock_not_synced", "Horloge non réglée"),
+    ("alert", "ALERTE"),
+    ("alert_title", "Alerte"),
+    ("press_any_button", "Appuyer sur un bouton"),
+    ("stale", "périmé"),
+    ("stale_since", "périmé depuis {}"),
+    ("unknown", "inconnu"),
+    ("up", "haut"),
+    ("down", "bas"),
+    ("update", "actu."),
+    ("reload", "recharger"),
+    ("done", "fait"),
+    ("scan", "scanner"),
+    ("join", "rejoindre"),
+    ("ranks", "classement"),
+    ("swap", "échanger"),
+    ("nearby", "proximité"),
+    ("pass", "réussi"),
+    ("repeat", "répéter"),
+    ("fail", "échec"),
+    ("again", "encore"),
+    ("row", "ligne"),
+    ("type", "taper"),
+    ("space", "espace"),
+    ("delete", "suppr"),
+    ("cancel", "annuler"),
+    ("badges_nearby", "{} - {} à proximité"),
+    ("no_badges", "Aucun badge à proximité"),
+    ("leaderboard", "Classement - {} contacts"),
+    ("badge_swapped", "{} a échangé les contacts"),
+    ("badge_got_contact", "Contact de {} reçu"),
+    ("badge_not_received", "{} n'a rien reçu"),
+    ("no_template", "Pas de modèle de tableau : {}"),
+    ("demo_hello", "Bonjour de Gray2 Rust !"),
+    ("github_notification", "GitHub - 1 notification"),
+    ("github_notifications", "GitHub - {} notifications"),
+    (
+        "github_unconfigured",
+        "Régler {} sur un proxy d'api.github.com",
+    ),
+    ("ci_passing", "réussi"),
+    ("ci_pending", "en cours"),
+    ("ci_failing", "échoué"),
+    ("bad_key", "{} invalide : {}"),
+    ("set_keys", "Régler {} et {}"),
+    ("waiting_for_frame", "En attente d'une image sur"),
+    ("safe_mode", "Mode sans échec"),
+    ("safe_mode_apps", "Les apps sont arrêtées."),
+    ("safe_mode_console", "Connecter en USB pour la console,"),
+    ("safe_mode_reset", "redémarrer pour revenir à la normale."),
+    ("selftest", "Autotest"),
+    ("selftest_black", "Noir uniforme, sans taches claires ?"),
+    ("selftest_grays", "Quatre nuances distinctes ?"),
+    ("selftest_checker", "Carrés nets, sans image fantôme ?"),
+    (
+        "selftest_neopixels",
+        "Les quatre rouge, vert, bleu, blanc ?",
+    ),
+    ("selftest_speaker", "Quatre notes montantes ?"),
+    ("press_all_buttons", "Appuyer sur A, B, C et D"),
+    ("measuring", "Mesure..."),
+    ("selftest_passed", "Autotest réussi"),
+    ("selftest_failed", "Autotest : {} en échec"),
+    ("status", "État"),
+    ("status_battery", "Batterie  {} V, environ {}%"),
+    ("status_battery_none", "Batterie  pas de mesure"),
+    ("status_draw", "Conso     {} mA en moyenne"),
+    ("status_draw_none", "Conso     pas encore mesurée"),
+    ("status_left", "Reste     {} à ce rythme ({} mAh)"),
+    ("status_left_none", "Reste     inconnu"),
+    ("status_time", "Temps    "),
+    ("status_wifi", "WiFi      {} dBm"),
+    ("status_wifi_none", "WiFi      non connecté"),
+    ("status_restarted_once", ", redémarré une fois"),
+    ("status_restarted", ", redémarré {} fois"),
+    ("status_up", "Allumé    {}, {} Kio libres"),
+    ("steps", "Pas"),
+    ("no_accelerometer", "Pas d'accéléromètre"),
+    ("steps_counted", "Comptés tant que cet écran est affiché"),
+    ("tasks_title", "Aujourd'hui - {} à faire"),
+    ("nothing_due", "Rien à faire aujourd'hui"),
+    ("tasks_unavailable", "Tâches indisponibles : {}"),
+    ("tasks_unconfigured", "Régler {} sur une liste CalDAV"),
+    ("task_not_done", "Tâche non cochée"),
+    ("wifi_survey_title", "Réseaux WiFi, {} trouvés"),
+    ("scan_failed", "Échec du scan : {}"),
+    ("security", "sécurité"),
+    ("hidden", "(masqué)"),
+    ("hidden_needs_setup", "Réseau masqué : passer par l'Improv"),
+    ("password_for", "Mot de passe de {}"),
+    ("join_failed", "Connexion échouée"),
+    ("joined_saved", "Connecté et enregistré"),
+    ("joined_not_saved", "Connecté, échec de l'enregistrement"),
+];
+
+const ES: &[(&str, &str)] = &[
+    ("battery_low", "Batería baja"),
+    ("updating", "Actualizando..."),
+    ("no_network", "Sin red"),
+    ("settings", "Ajustes"),
+    ("saved", "guardado"),
+    ("save_failed", "error al guardar"),
+    ("refresh", "Intervalo"),
+    ("timezone", "Zona horaria"),
+    ("units", "Unidades"),
+    ("neopixels", "NeoPixels"),
+    ("contrast", "Contraste"),
+    ("contrast_normal", "Normal"),
+    ("contrast_high", "Alto"),
+    ("contrast_mono", "B+N"),
+    ("language", "Idioma"),
+    ("celsius", "Celsius"),
+    ("fahrenheit", "Fahrenheit"),
+    ("minutes_short", "min"),
+    ("next", "sig."),
+    ("save", "guardar"),
+    ("reconnecting_wifi", "Reconectando WiFi..."),
+    ("offline_setup", "Sin red - mantén D al arrancar para WiFi"),
+    ("clock_not_synced", "Reloj sin sincronizar"),
+    ("alert", "ALERTA"),
+    ("alert_title", "Alerta"),
+    ("press_any_button", "Pulsa cualquier botón"),
+    ("stale", "antiguo"),
+    ("stale_since", "antiguo desde {}"),
+    ("unknown", "desconocido"),
+    ("up", "arriba"),
+    ("down", "abajo"),
+    ("update", "actualizar"),
+    ("reload", "recargar"),
+    ("done", "hecho"),
+    ("scan", "buscar"),
+    ("join", "unirse"),
+    ("ranks", "ranking"),
+    ("swap", "cambiar"),
+    ("nearby", "cerca"),
+    ("pass", "pasa"),
+    ("repeat", "repetir"),
+    ("fail", "falla"),
+    ("again", "otra vez"),
+    ("row", "fila"),
+    ("type", "teclear"),
+    ("space", "espacio"),
+    ("delete", "borrar"),
+    ("cancel", "cancelar"),
+    ("badges_nearby", "{} - {} cerca"),
+    ("no_badges", "Aún no hay badges cerca"),
+    ("leaderboard", "Clasificación - {} contactos"),
+    ("badge_swapped", "{} intercambió contactos"),
+    ("badge_got_contact", "Contacto de {} recibido"),
+    ("badge_not_received", "{} no lo recibió"),
+    ("no_template", "Sin plantilla de panel: {}"),
+    ("demo_hello", "¡Hola desde Gray2 Rust!"),
+    ("github_notification", "GitHub - 1 notificación"),
+    ("github_notifications", "GitHub - {} notificaciones"),
+    (
+        "github_unconfigured",
+        "Pon en {} un proxy de api.github.com",
+    ),
+    ("ci_passing", "correcto"),
+    ("ci_pending", "en curso"),
+    ("ci_failing", "fallido"),
+    ("bad_key", "{} no válido: {}"),
+    ("set_keys", "Configura {} y {}"),
+    ("waiting_for_frame", "Esperando una imagen en"),
+    ("safe_mode", "Modo seguro"),
+    ("safe_mode_apps", "Las apps no se ejecutan."),
+    ("safe_mode_console", "Conecta por USB para usar la consola,"),
+    ("safe_mode_reset", "reinicia para arrancar como siempre."),
+    ("selftest", "Autoprueba"),
+    ("selftest_black", "¿Negro uniforme, sin manchas claras?"),
+    ("selftest_grays", "¿Cuatro tonos distintos?"),
+    ("selftest_checker", "¿Cuadros nítidos, sin fantasmas?"),
+    (
+        "selftest_neopixels",
+        "¿Los cuatro rojo, verde, azul, blanco?",
+    ),
+    ("selftest_speaker", "¿Cuatro tonos ascendentes?"),
+    ("press_all_buttons", "Pulsa A, B, C y D"),
+    ("measuring", "Midiendo..."),
+    ("selftest_passed", "Autoprueba superada"),
+    ("selftest_failed", "Autoprueba: {} fallidas"),
+    ("status", "Estado"),
+    ("status_battery", "Batería   {} V, aprox. {}%"),
+    ("status_battery_none", "Batería   sin lectura"),
+    ("status_draw", "Consumo   {} mA de media"),
+    ("status_draw_none", "Consumo   aún sin medir"),
+    ("status_left", "Restante  {} a este ritmo ({} mAh)"),
+    ("status_left_none", "Restante  desconocido"),
+    ("status_time", "Tiempo   "),
+    ("status_wifi", "WiFi      {} dBm"),
+    ("status_wifi_none", "WiFi      sin conexión"),
+    ("status_restarted_once", ", reiniciado una vez"),
+    ("status_restarted", ", reiniciado {} veces"),
+    ("status_up", "Activo    {}, {} KiB libres"),
+    ("steps", "Pasos"),
+    ("no_accelerometer", "Sin acelerómetro"),
+    ("steps_counted", "Contados mientras se ve esta pantalla"),
+    ("tasks_title", "Hoy - {} pendientes"),
+    ("nothing_due", "Nada pendiente hoy"),
+    ("tasks_unavailable", "Tareas no disponibles: {}"),
+    ("tasks_unconfigured", "Pon en {} una lista de tareas CalDAV"),
+    ("task_not_done", "No se pudo marcar la tarea"),
+    ("wifi_survey_title", "Redes WiFi, {} encontradas"),
+    ("scan_failed", "Búsqueda fallida: {}"),
+    ("security", "seguridad"),
+    ("hidden", "(oculta)"),
+    ("hidden_needs_setup", "Red oculta: configúrala con Improv"),
+    ("password_for", "Contraseña de {}"),
+    ("join_failed", "Conexión fallida"),
+    ("joined_saved", "Conectado y guardado"),
+    ("joined_not_saved", "Conectado, error al guardar"),
+];
@@ -0,0 +1,35 @@
+//! The MagTag's I2C bus, to the accelerometer on the board and whatever is
+//! plugged into the STEMMA QT connector. [crate::accel] and
+//! [crate::encoder] take turns on it through [with].
+
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+use esp_hal::{
+    i2c::master::{Config, I2c},
+    peripherals::{GPIO33, GPIO34, I2C0},
+    time::Rate,
+    Blocking,
+};
+use log::warn;
+
+/// The bus as the drivers get it
+pub type Bus = I2c<'static, Blocking>;
+
+static BUS: Mutex<RefCell<Option<Bus>>> = Mutex::new(RefCell::new(None));
+
+/// Takes the I2C peripheral, SDA (GPIO33) and SCL (GPIO34). Call once at
+/// boot, before the drivers on the bus.
+pub fn init(i2c: I2C0<'static>, sda: GPIO33<'static>, scl: GPIO34<'static>) {
+    let Ok(i2c) = I2c::new(i2c, Config::default().with_frequency(Rate::from_khz(400))) else {
+        warn!("I2C unavailable");
+        return;
+    };
+    let i2c = i2c.with_sda(sda).with_scl(scl);
+    critical_section::with(|cs| BUS.borrow_ref_mut(cs).replace(i2c));
+}
+
+/// Runs `f` with the bus, in a critical section; `None` before [init]
+pub fn with<R>(f: impl FnOnce(&mut Bus) -> R) -> Option<R> {
+    critical_section::with(|cs| BUS.borrow_ref_mut(cs).as_mut().map(f))
+}
@@ -0,0 +1,319 @@
+//! [Improv WiFi](https://www.improv-wifi.com/serial/) over the USB serial port.
+//!
+//! Browser installers (ESP Web Tools and similar) use it to send WiFi
+//! credentials. Frames are `IMPROV`, version, type, length, data and a
+//! checksum byte, so they can share the port with the text console:
+//! [Decoder] hands back anything that isn't a frame.
+
+use alloc::{string::String, vec::Vec};
+
+use embedded_graphics::{
+    mono_font::{
+        ascii::{FONT_6X10, FONT_7X14_BOLD},
+        MonoTextStyle,
+    },
+    pixelcolor::Gray2,
+    prelude::*,
+    text::{Baseline, Text},
+};
+use heapless::Vec as FixedVec;
+use log::{info, warn};
+
+use crate::{
+    chip,
+    config::ConfigStore,
+    console::UsbSerial,
+    display::Display,
+    net::NetStack,
+    wifi::{self, Credentials},
+};
+
+const HEADER: &[u8; 6] = b"IMPROV";
+const VERSION: u8 = 1;
+/// Header, version, type and length
+const PREFIX_LEN: usize = HEADER.len() + 3;
+const MAX_FRAME_LEN: usize = PREFIX_LEN + u8::MAX as usize + 1;
+
+const TYPE_CURRENT_STATE: u8 = 0x01;
+const TYPE_ERROR_STATE: u8 = 0x02;
+const TYPE_RPC: u8 = 0x03;
+const TYPE_RPC_RESULT: u8 = 0x04;
+
+const RPC_WIFI_SETTINGS: u8 = 0x01;
+const RPC_CURRENT_STATE: u8 = 0x02;
+const RPC_DEVICE_INFO: u8 = 0x03;
+const RPC_SCAN_NETWORKS: u8 = 0x04;
+
+/// Provisioning state reported to the installer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum State {
+    Ready = 0x02,
+    Provisioning = 0x03,
+    Provisioned = 0x04,
+}
+
+/// Error reported to the installer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ErrorState {
+    None = 0x00,
+    InvalidRpc = 0x01,
+    UnknownRpc = 0x02,
+    UnableToConnect = 0x03,
+    Unknown = 0xff,
+}
+
+/// A request from the installer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    WifiSettings(Credentials),
+    CurrentState,
+    DeviceInfo,
+    ScanNetworks,
+    Unknown(u8),
+}
+
+impl Command {
+    fn parse(data: &[u8]) -> Option<Self> {
+        let (&id, rest) = data.split_first()?;
+        let (&len, payload) = rest.split_first()?;
+        let payload = payload.get(..len as usize)?;
+        Some(match id {
+            RPC_WIFI_SETTINGS => {
+                let (ssid, rest) = take_string(payload)?;
+                let (password, _) = take_string(rest)?;
+                Command::WifiSettings(Credentials { ssid, password })
+            }
+            RPC_CURRENT_STATE => Command::CurrentState,
+            RPC_DEVICE_INFO => Command::DeviceInfo,
+            RPC_SCAN_NETWORKS => Command::ScanNetworks,
+            other => Command::Unknown(other),
+        })
+    }
+}
+
+/// A length-prefixed string
+fn take_string(data: &[u8]) -> Option<(String, &[u8])> {
+    let (&len, rest) = data.split_first()?;
+    let (text, rest) = rest.split_at_checked(len as usize)?;
+    Some((core::str::from_utf8(text).ok()?.into(), rest))
+}
+
+/// What [Decoder::push] made of a byte
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Input {
+    /// Part of a frame still arriving
+    Pending,
+    /// Bytes that turned out not to be a frame
+    Text(FixedVec<u8, { HEADER.len() }>),
+    /// A complete RPC frame
+    Command(Command),
+    /// A frame with a bad checksum or malformed RPC
+    Invalid,
+}
+
+/// Splits Improv frames out of the serial byte stream
+#[derive(Default)]
+pub struct Decoder {
+    frame: FixedVec<u8, MAX_FRAME_LEN>,
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, byte: u8) -> Input {
+        let len = self.frame.len();
+        if len < HEADER.len() && byte != HEADER[len] {
+            let mut text: FixedVec<u8, { HEADER.len() }> = self.frame.iter().copied().collect();
+            self.frame.clear();
+            if byte == HEADER[0] {
+                self.frame.push(byte).ok();
+            } else {
+                text.push(byte).ok();
+            }
+            return Input::Text(text);
+        }
+        // the frame can't outgrow the buffer: the length byte is at most 255
+        self.frame.push(byte).ok();
+        if self.frame.len() < PREFIX_LEN {
+            return Input::Pending;
+        }
+
+        let data_len = self.frame[PREFIX_LEN - 1] as usize;
+        if self.frame.len() < PREFIX_LEN + data_len + 1 {
+            return Input::Pending;
+        }
+        let frame = core::mem::take(&mut self.frame);
+        let (body, checksum) = frame.split_at(frame.len() - 1);
+        if checksum[0] != sum(body) {
+            warn!("Improv frame with a bad checksum");
+            return Input::Invalid;
+        }
+        match (body[HEADER.len() + 1], &body[PREFIX_LEN..]) {
+            (TYPE_RPC, data) => Command::parse(data).map_or(Input::Invalid, Input::Command),
+            // nothing else is sent to a device
+            _ => Input::Invalid,
+        }
+    }
+}
+
+fn sum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |acc, b| acc.wrapping_add(*b))
+}
+
+fn send(serial: &mut UsbSerial, kind: u8, data: &[u8]) {
+    let mut frame = Vec::with_capacity(PREFIX_LEN + data.len() + 2);
+    frame.extend_from_slice(HEADER);
+    frame.extend_from_slice(&[VERSION, kind, data.len() as u8]);
+    frame.extend_from_slice(data);
+    frame.push(sum(&frame));
+    frame.push(b'\n');
+    serial.write(&frame);
+}
+
+pub fn send_state(serial: &mut UsbSerial, state: State) {
+    send(serial, TYPE_CURRENT_STATE, &[state as u8]);
+}
+
+pub fn send_error(serial: &mut UsbSerial, error: ErrorState) {
+    send(serial, TYPE_ERROR_STATE, &[error as u8]);
+}
+
+fn send_result(serial: &mut UsbSerial, command: u8, strings: &[&str]) {
+    let mut data = alloc::vec![command, 0];
+    for s in strings {
+        // every string has to fit the one-byte frame length
+        let s = &s.as_bytes()[..s.len().min(64)];
+        data.push(s.len() as u8);
+        data.extend_from_slice(s);
+    }
+    data[1] = (data.len() - 2) as u8;
+    send(serial, TYPE_RPC_RESULT, &data);
+}
+
+fn current_state(net: &NetStack<'_>) -> State {
+    if wifi::is_connected() && net.is_iface_up() {
+        State::Provisioned
+    } else {
+        State::Ready
+    }
+}
+
+/// Answers one command; returns `true` once new credentials have been joined and saved
+pub fn handle(
+    command: Command,
+    serial: &mut UsbSerial,
+    net: &NetStack<'_>,
+    config: &mut ConfigStore,
+) -> bool {
+    match command {
+        Command::CurrentState => send_state(serial, current_state(net)),
+        Command::DeviceInfo => send_result(
+            serial,
+            RPC_DEVICE_INFO,
+            &[
+                env!("CARGO_PKG_NAME"),
+                env!("CARGO_PKG_VERSION"),
+                chip::NAME,
+                "MagTag",
+            ],
+        ),
+        Command::ScanNetworks => {
+            for ap in wifi::scan().unwrap_or_default() {
+                let rssi = alloc::format!("{}", ap.signal_strength);
+                let secured = match ap.auth_method {
+                    None | Some(esp_radio::wifi::AuthMethod::None) => "NO",
+                    Some(_) => "YES",
+                };
+                send_result(serial, RPC_SCAN_NETWORKS, &[&ap.ssid, &rssi, secured]);
+            }
+            send_result(serial, RPC_SCAN_NETWORKS, &[]);
+        }
+        Command::WifiSettings(credentials) => {
+            send_state(serial, State::Provisioning);
+            if let Err(err) = wifi::join(&credentials, net) {
+                warn!("Improv: joining {} failed: {}", credentials.ssid, err);
+                send_error(serial, ErrorState::UnableToConnect);
+                send_state(serial, State::Ready);
+                return false;
+            }
+            if let Err(err) = credentials.store(config).and_then(|_| config.commit()) {
+                warn!("Improv: saving credentials failed: {}", err);
+            }
+            send_state(serial, State::Provisioned);
+            send_result(serial, RPC_WIFI_SETTINGS, &[]);
+            return true;
+        }
+        Command::Unknown(id) => {
+            warn!("Improv: unknown RPC {:#04x}", id);
+            send_error(serial, ErrorState::UnknownRpc);
+        }
+    }
+    false
+}
+
+/// Waits for credentials over Improv, for when there are none or they don't work
+pub fn provision(
+    serial: &mut UsbSerial,
+    net: &NetStack<'_>,
+    config: &mut ConfigStore,
+    display: &mut Display,
+) {
+    info!("Waiting for WiFi credentials over Improv");
+    show_setup_screen(display);
+
+    let mut decoder = Decoder::new();
+    let mut buf = [0u8; 64];
+    loop {
+        net.work();
+        if !serial.poll() {
+            continue;
+        }
+        let len = serial.read(&mut buf);
+        for &byte in &buf[..len] {
+            match decoder.push(byte) {
+                Input::Command(command) => {
+                    if handle(command, serial, net, config) {
+                        return;
+                    }
+                }
+                Input::Invalid => send_error(serial, ErrorState::InvalidRpc),
+                Input::Pending | Input::Text(_) => {}
+            }
+        }
+    }
+}
+
+fn show_setup_screen(display: &mut Display) {
+    let frame = display.frame();
+    frame.clear(Gray2::WHITE).ok();
+
+    let title_style = MonoTextStyle::new(&FONT_7X14_BOLD, Gray2::BLACK);
+    Text::with_baseline("WiFi setup", Point::new(4, 2), title_style, Baseline::Top)
+        .draw(frame)
+        .ok();
+    let style = MonoTextStyle::new(&FONT_6X10, Gray2::BLACK);
+    for (i, line) in [
+        "Connect USB and open an Improv WiFi",
+        "installer in Chrome or Edge.",
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        Text::with_baseline(
+            line,
+            Point::new(4, 22 + i as i32 * 12),
+            style,
+            Baseline::Top,
+        )
+        .draw(frame)
+        .ok();
+    }
+
+    if let Err(err) = display.flush() {
+        warn!("setup screen refresh failed: {}", err);
+    }
+}
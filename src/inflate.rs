@@ -0,0 +1,317 @@
+//! DEFLATE decompression (RFC 1951) with the gzip (RFC 1952) and zlib
+//! (RFC 1950) wrappers, for compressed HTTP responses.
+//!
+//! The HTTP client reads whole responses into memory, so this decodes a
+//! complete buffer rather than a stream. Output past the given limit fails
+//! the whole body rather than truncating it, which also stops a small body
+//! that expands without end. Corrupt input fails with [Error::Network], like
+//! any other malformed reply.
+
+use alloc::vec::Vec;
+
+use crate::{crc::crc32, psram, Error};
+
+const MAX_LITLEN_CODES: usize = 288;
+const MAX_DIST_CODES: usize = 30;
+/// Order of the code length code lengths in a dynamic block header
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// Decompresses a gzip member, checking its CRC and length
+pub fn gunzip(data: &[u8], limit: usize) -> Result<Vec<u8>, Error> {
+    // ID1 ID2 CM FLG MTIME(4) XFL OS
+    if data.len() < 18 || data[..3] != [0x1f, 0x8b, 8] {
+        return Err(Error::Network);
+    }
+    let flags = data[3];
+    let mut pos = 10;
+    if flags & 0x04 != 0 {
+        // FEXTRA
+        let len = u16::from_le_bytes([data[pos], data[pos + 1]]) as usize;
+        pos += 2 + len;
+    }
+    for flag in [0x08, 0x10] {
+        // FNAME, FCOMMENT: zero terminated
+        if flags & flag != 0 {
+            let end = data
+                .get(pos..)
+                .and_then(|rest| rest.iter().position(|&b| b == 0));
+            pos += end.ok_or(Error::Network)? + 1;
+        }
+    }
+    if flags & 0x02 != 0 {
+        // FHCRC
+        pos += 2;
+    }
+    let body = data.get(pos..).ok_or(Error::Network)?;
+    let (out, used) = inflate_raw(body, limit)?;
+
+    let trailer = body.get(used..used + 8).ok_or(Error::Network)?;
+    let crc = u32::from_le_bytes(trailer[..4].try_into().unwrap());
+    let len = u32::from_le_bytes(trailer[4..].try_into().unwrap());
+    if crc != crc32(&out) || len != out.len() as u32 {
+        return Err(Error::Network);
+    }
+    Ok(out)
+}
+
+/// Decompresses a zlib stream, or raw DEFLATE data as some servers send under
+/// `Content-Encoding: deflate`
+pub fn inflate(data: &[u8], limit: usize) -> Result<Vec<u8>, Error> {
+    let zlib = data.len() >= 2
+        && data[0] & 0x0f == 8
+        && u16::from_be_bytes([data[0], data[1]]).is_multiple_of(31)
+        // FDICT, a preset dictionary, isn't used over HTTP
+        && data[1] & 0x20 == 0;
+    let body = if zlib { &data[2..] } else { data };
+    inflate_raw(body, limit).map(|(out, _)| out)
+}
+
+/// Decompresses raw DEFLATE data; also returns how many input bytes it took
+fn inflate_raw(data: &[u8], limit: usize) -> Result<(Vec<u8>, usize), Error> {
+    let mut bits = Bits::new(data);
+    let mut out = psram::with_capacity(limit);
+    loop {
+        let last = bits.take(1)? == 1;
+        match bits.take(2)? {
+            0 => stored(&mut bits, &mut out, limit)?,
+            1 => {
+                let (litlen, dist) = fixed_codes()?;
+                codes(&mut bits, &mut out, limit, &litlen, &dist)?;
+            }
+            2 => {
+                let (litlen, dist) = dynamic_codes(&mut bits)?;
+                codes(&mut bits, &mut out, limit, &litlen, &dist)?;
+            }
+            _ => return Err(Error::Network),
+        }
+        if last {
+            return Ok((out, bits.consumed()));
+        }
+    }
+}
+
+/// Reads the input least significant bit first
+struct Bits<'d> {
+    data: &'d [u8],
+    pos: usize,
+    buffer: u32,
+    count: u32,
+}
+
+impl<'d> Bits<'d> {
+    fn new(data: &'d [u8]) -> Self {
+        Self {
+            data,
+            pos: 0,
+            buffer: 0,
+            count: 0,
+        }
+    }
+
+    fn take(&mut self, n: u32) -> Result<u32, Error> {
+        while self.count < n {
+            let byte = *self.data.get(self.pos).ok_or(Error::Network)?;
+            self.pos += 1;
+            self.buffer |= (byte as u32) << self.count;
+            self.count += 8;
+        }
+        let value = self.buffer & ((1u32 << n) - 1);
+        self.buffer = self.buffer.checked_shr(n).unwrap_or(0);
+        self.count -= n;
+        Ok(value)
+    }
+
+    /// Drops the rest of the current byte
+    fn align(&mut self) {
+        self.buffer = 0;
+        self.count = 0;
+    }
+
+    /// Whole bytes used so far
+    fn consumed(&self) -> usize {
+        self.pos - (self.count / 8) as usize
+    }
+}
+
+/// A canonical Huffman code as symbol counts per length and the symbols in
+/// code order
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Self, Error> {
+        let mut counts = [0u16; 16];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+        // more codes of a length than fit is corrupt; fewer is allowed
+        let mut left = 1i32;
+        for &count in &counts[1..] {
+            left = (left << 1) - count as i32;
+            if left < 0 {
+                return Err(Error::Network);
+            }
+        }
+
+        let mut offsets = [0u16; 16];
+        for len in 1..15 {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = alloc::vec![0u16; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+        Ok(Self { counts, symbols })
+    }
+
+    fn decode(&self, bits: &mut Bits<'_>) -> Result<u16, Error> {
+        // first code and first symbol index of the current length
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for &count in &self.counts[1..] {
+            code |= bits.take(1)? as i32;
+            let count = count as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(Error::Network)
+    }
+}
+
+fn stored(bits: &mut Bits<'_>, out: &mut Vec<u8>, limit: usize) -> Result<(), Error> {
+    bits.align();
+    let len = bits.take(16)? as u16;
+    let nlen = bits.take(16)? as u16;
+    if len != !nlen {
+        return Err(Error::Network);
+    }
+    let start = bits.pos;
+    let block = bits
+        .data
+        .get(start..start + len as usize)
+        .ok_or(Error::Network)?;
+    if out.len() + block.len() > limit {
+        return Err(Error::Network);
+    }
+    out.extend_from_slice(block);
+    bits.pos += len as usize;
+    Ok(())
+}
+
+fn fixed_codes() -> Result<(Huffman, Huffman), Error> {
+    let mut lengths = [0u8; MAX_LITLEN_CODES];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    Ok((Huffman::new(&lengths)?, Huffman::new(&[5; MAX_DIST_CODES])?))
+}
+
+fn dynamic_codes(bits: &mut Bits<'_>) -> Result<(Huffman, Huffman), Error> {
+    let litlen_count = bits.take(5)? as usize + 257;
+    let dist_count = bits.take(5)? as usize + 1;
+    let code_length_count = bits.take(4)? as usize + 4;
+    if litlen_count > MAX_LITLEN_CODES - 2 || dist_count > MAX_DIST_CODES {
+        return Err(Error::Network);
+    }
+
+    let mut code_lengths = [0u8; 19];
+    for &index in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_lengths[index] = bits.take(3)? as u8;
+    }
+    let code_length_code = Huffman::new(&code_lengths)?;
+
+    // literal/length and distance code lengths, run-length coded as one list
+    let mut lengths = [0u8; MAX_LITLEN_CODES + MAX_DIST_CODES];
+    let total = litlen_count + dist_count;
+    let mut i = 0;
+    while i < total {
+        let symbol = code_length_code.decode(bits)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => (
+                *lengths[..i].last().ok_or(Error::Network)?,
+                3 + bits.take(2)? as usize,
+            ),
+            17 => (0, 3 + bits.take(3)? as usize),
+            _ => (0, 11 + bits.take(7)? as usize),
+        };
+        if i + repeat > total {
+            return Err(Error::Network);
+        }
+        lengths[i..i + repeat].fill(value);
+        i += repeat;
+    }
+    if lengths[256] == 0 {
+        // no end of block code
+        return Err(Error::Network);
+    }
+    Ok((
+        Huffman::new(&lengths[..litlen_count])?,
+        Huffman::new(&lengths[litlen_count..total])?,
+    ))
+}
+
+fn codes(
+    bits: &mut Bits<'_>,
+    out: &mut Vec<u8>,
+    limit: usize,
+    litlen: &Huffman,
+    dist: &Huffman,
+) -> Result<(), Error> {
+    loop {
+        let symbol = litlen.decode(bits)? as usize;
+        if out.len() >= limit && symbol != 256 {
+            return Err(Error::Network);
+        }
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let index = symbol - 257;
+                let len = *LENGTH_BASE.get(index).ok_or(Error::Network)? as usize
+                    + bits.take(LENGTH_EXTRA[index] as u32)? as usize;
+                let index = dist.decode(bits)? as usize;
+                let distance = *DIST_BASE.get(index).ok_or(Error::Network)? as usize
+                    + bits.take(DIST_EXTRA[index] as u32)? as usize;
+                if distance > out.len() {
+                    return Err(Error::Network);
+                }
+                if out.len() + len > limit {
+                    return Err(Error::Network);
+                }
+                // the copy may overlap what it produces
+                let start = out.len() - distance;
+                for i in 0..len {
+                    out.push(out[start + i]);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,484 @@
+//! The four front buttons and the events they produce.
+//!
+//! Every input is mapped onto an [InputEvent], a press of a front button or
+//! a chord of them, and goes through one queue, [push] and [next], so apps
+//! don't need to know what produced it. The front buttons and buttons on the
+//! expansion pads, a rotary encoder ([crate::encoder]), a double tap on the
+//! accelerometer and remote presses over HTTP ([serve_press]) or MQTT
+//! ([crate::apps::mqtt_display]) all feed it. [Buttons::next_event] hands
+//! them to the app host as [Event]s.
+//!
+//! Holding a button can also be reported, see [Timing]: as a long press, or
+//! as the same press repeated for the buttons the active app asks for with
+//! [set_repeat], such as stepping a value in the settings. Once a hold has
+//! been reported nothing else is until every button is up, so its release
+//! isn't also a press. A second button going down before then makes the
+//! gesture a chord instead.
+
+use alloc::string::String;
+use core::{
+    cell::{Cell, RefCell},
+    mem,
+    str::FromStr,
+};
+
+use critical_section::Mutex;
+use esp_hal::{
+    gpio::Input,
+    time::{Duration, Instant},
+};
+use heapless::{Deque, Vec};
+use log::debug;
+
+use crate::{
+    accel,
+    actions::{Action, Bindings},
+    config::{keys, ConfigStore},
+    encoder::Encoder,
+    http::Response,
+    http_server::Request,
+    net_health::Link,
+};
+
+/// Extra pins that act as front buttons, see [Buttons::add_button]
+pub const MAX_EXTRA_BUTTONS: usize = 4;
+/// Inputs waiting for the app host; more are dropped
+pub const QUEUE_LEN: usize = 8;
+/// How often the accelerometer is asked for a double tap
+const TAP_POLL: Duration = Duration::from_millis(100);
+
+static QUEUE: Mutex<RefCell<Deque<(InputEvent, Source), QUEUE_LEN>>> =
+    Mutex::new(RefCell::new(Deque::new()));
+/// The `press_token` a remote press has to carry, `None` to refuse them
+static REMOTE_TOKEN: Mutex<RefCell<Option<String>>> = Mutex::new(RefCell::new(None));
+/// The buttons that repeat while held, see [set_repeat]
+static REPEAT: Mutex<Cell<ButtonSet>> = Mutex::new(Cell::new(ButtonSet::EMPTY));
+
+/// How long a raw pin level has to be stable before it is accepted
+const DEBOUNCE: Duration = Duration::from_millis(30);
+/// Long presses when `long_press_ms` isn't set but one is bound, see
+/// [crate::actions]
+const LONG_PRESS: Duration = Duration::from_millis(800);
+/// Key repeat when `key_repeat` isn't set
+const REPEAT_DELAY: Duration = Duration::from_millis(500);
+const REPEAT_EVERY: Duration = Duration::from_millis(150);
+
+/// Front buttons, left to right
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Button {
+    A,
+    B,
+    C,
+    D,
+}
+
+impl Button {
+    pub const ALL: [Button; 4] = [Button::A, Button::B, Button::C, Button::D];
+
+    const fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+impl FromStr for Button {
+    type Err = ();
+
+    /// `A` to `D`, in either case
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "A" | "a" => Ok(Button::A),
+            "B" | "b" => Ok(Button::B),
+            "C" | "c" => Ok(Button::C),
+            "D" | "d" => Ok(Button::D),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A set of buttons, used for chords such as A+D
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ButtonSet(u8);
+
+impl ButtonSet {
+    pub const EMPTY: ButtonSet = ButtonSet(0);
+
+    pub const fn of(buttons: &[Button]) -> Self {
+        let mut bits = 0;
+        let mut i = 0;
+        while i < buttons.len() {
+            bits |= buttons[i].bit();
+            i += 1;
+        }
+        ButtonSet(bits)
+    }
+
+    pub const fn contains(self, button: Button) -> bool {
+        self.0 & button.bit() != 0
+    }
+
+    pub const fn with(self, button: Button) -> Self {
+        ButtonSet(self.0 | button.bit())
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub const fn len(self) -> u32 {
+        self.0.count_ones()
+    }
+
+    /// The single button in this set, if there is exactly one
+    pub fn single(self) -> Option<Button> {
+        if self.len() != 1 {
+            return None;
+        }
+        Button::ALL.into_iter().find(|b| self.contains(*b))
+    }
+}
+
+/// Input delivered to the active app
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// A single button was pressed and released, or it repeats while held
+    Press(Button),
+    /// Two or more buttons were held together, reported once all are released
+    Chord(ButtonSet),
+    /// A single button was held for [Timing::long_press]
+    LongPress(Button),
+    /// What a press is bound to in `button_actions`, see [crate::actions]
+    Action(Action),
+    /// The app's requested sleep interval elapsed
+    Tick,
+    /// The network connection changed, see [crate::net_health]
+    Link(Link),
+    /// The button chosen in the [crate::ui::dialog::Dialog] the app opened
+    Answer(Button),
+}
+
+/// What an input does, whichever one it was
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputEvent {
+    Press(Button),
+    Chord(ButtonSet),
+    LongPress(Button),
+}
+
+impl InputEvent {
+    pub fn event(self) -> Event {
+        match self {
+            InputEvent::Press(button) => Event::Press(button),
+            InputEvent::Chord(buttons) => Event::Chord(buttons),
+            InputEvent::LongPress(button) => Event::LongPress(button),
+        }
+    }
+}
+
+impl FromStr for InputEvent {
+    type Err = ();
+
+    /// A button such as `A`, or a chord such as `A+D`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut buttons = ButtonSet::EMPTY;
+        for button in s.split('+') {
+            buttons = buttons.with(button.parse()?);
+        }
+        match buttons.single() {
+            Some(button) => Ok(InputEvent::Press(button)),
+            None => Ok(InputEvent::Chord(buttons)),
+        }
+    }
+}
+
+/// What produced an [InputEvent], for the log
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    /// The front buttons and those on pads
+    Buttons,
+    Encoder,
+    /// A double tap on the accelerometer
+    Tap,
+    /// HTTP or MQTT
+    Remote,
+}
+
+/// Queues `event` for the active app. Dropping input is better than blocking
+/// when nobody drains the queue, so it is lost when [QUEUE_LEN] are waiting.
+pub fn push(event: InputEvent, source: Source) {
+    critical_section::with(|cs| QUEUE.borrow_ref_mut(cs).push_back((event, source)).ok());
+}
+
+/// The oldest queued input
+pub fn next() -> Option<(InputEvent, Source)> {
+    critical_section::with(|cs| QUEUE.borrow_ref_mut(cs).pop_front())
+}
+
+/// Makes `buttons` repeat their press while held alone, for the active app;
+/// the host clears it when another app comes to the front
+pub fn set_repeat(buttons: ButtonSet) {
+    critical_section::with(|cs| REPEAT.borrow(cs).set(buttons));
+}
+
+/// Accepts remote presses that carry `token`
+pub fn allow_remote(token: &str) {
+    critical_section::with(|cs| REMOTE_TOKEN.borrow_ref_mut(cs).replace(token.into()));
+}
+
+/// Route handler for [crate::http_server::HttpServer] queueing a remote
+/// press, `POST /press?token=<press_token>&press=A+D`; the `+` can also be
+/// sent as `%2B`
+pub fn serve_press(request: &Request<'_>) -> Response {
+    let reply = |status, message: &str| {
+        Response::new(
+            status,
+            "text/plain",
+            alloc::format!("{}\n", message).into_bytes(),
+        )
+    };
+    if request.method != "POST" {
+        return reply(405, "POST only");
+    }
+    let (mut token, mut press) = (None, None);
+    for pair in request.query.unwrap_or("").split('&') {
+        match pair.split_once('=') {
+            Some(("token", value)) => token = Some(value),
+            Some(("press", value)) => press = Some(value.replace("%2B", "+")),
+            _ => {}
+        }
+    }
+    let allowed = critical_section::with(|cs| {
+        let expected = REMOTE_TOKEN.borrow_ref(cs);
+        expected.is_some() && expected.as_deref() == token
+    });
+    if !allowed {
+        return reply(403, "wrong or missing token");
+    }
+    match press.as_deref().map(str::parse) {
+        Some(Ok(event)) => {
+            push(event, Source::Remote);
+            reply(200, "queued")
+        }
+        _ => reply(400, "press needs buttons such as A or A+D"),
+    }
+}
+
+/// How holding a button is reported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timing {
+    /// How long a single button is held for an [Event::LongPress], from
+    /// `long_press_ms`; `None` reports it as a press on release as usual.
+    /// Unset, there are long presses only if `button_actions` binds one.
+    pub long_press: Option<Duration>,
+    /// How long a button is held before it repeats, and how often it then
+    /// does, from `key_repeat`; `None` for no repeat
+    pub repeat: Option<(Duration, Duration)>,
+}
+
+impl Default for Timing {
+    fn default() -> Self {
+        Self {
+            long_press: None,
+            repeat: Some((REPEAT_DELAY, REPEAT_EVERY)),
+        }
+    }
+}
+
+impl Timing {
+    pub fn from_config(config: &ConfigStore) -> Self {
+        let default = Self::default();
+        let long_press = match config.get_parsed(keys::LONG_PRESS) {
+            Some(0) => None,
+            Some(ms) => Some(Duration::from_millis(ms)),
+            None => Bindings::from_config(config)
+                .has_long_press()
+                .then_some(LONG_PRESS),
+        };
+        let repeat = match config.get(keys::KEY_REPEAT).map(str::trim) {
+            None => default.repeat,
+            Some("off" | "0") => None,
+            Some(value) => {
+                let parsed = value.split_once(',').and_then(|(delay, every)| {
+                    Some((delay.trim().parse().ok()?, every.trim().parse().ok()?))
+                });
+                match parsed {
+                    Some((delay, every)) if every > 0 => {
+                        Some((Duration::from_millis(delay), Duration::from_millis(every)))
+                    }
+                    _ => default.repeat,
+                }
+            }
+        };
+        Self { long_press, repeat }
+    }
+}
+
+/// Debounced polling of the front buttons, and of the other inputs that
+/// feed the [queue](push)
+///
+/// Presses are reported on release so a chord never also shows up as the
+/// individual presses it is made of.
+pub struct Buttons {
+    pins: [Input<'static>; 4],
+    extra: Vec<(Input<'static>, Button), MAX_EXTRA_BUTTONS>,
+    encoder: Option<Encoder>,
+    /// The button a double tap presses, and when to ask next
+    tap: Option<(Button, Instant)>,
+    timing: Timing,
+    raw: ButtonSet,
+    raw_since: Instant,
+    stable: ButtonSet,
+    gesture: ButtonSet,
+    /// When the first button of the gesture went down
+    pressed_at: Instant,
+    /// When a held button next repeats
+    next_repeat: Instant,
+    /// A hold was reported, so the release isn't
+    spent: bool,
+}
+
+impl Buttons {
+    /// Takes the pins for A, B, C and D. The buttons pull to ground when pressed.
+    pub fn new(pins: [Input<'static>; 4]) -> Self {
+        Self {
+            pins,
+            extra: Vec::new(),
+            encoder: None,
+            tap: None,
+            timing: Timing::default(),
+            raw: ButtonSet::EMPTY,
+            raw_since: Instant::now(),
+            stable: ButtonSet::EMPTY,
+            gesture: ButtonSet::EMPTY,
+            pressed_at: Instant::now(),
+            next_repeat: Instant::now(),
+            spent: false,
+        }
+    }
+
+    pub fn set_timing(&mut self, timing: Timing) {
+        self.timing = timing;
+    }
+
+    /// Makes `pin`, pulled to ground when pressed, another `button`; false
+    /// if there are [MAX_EXTRA_BUTTONS] already
+    pub fn add_button(&mut self, pin: Input<'static>, button: Button) -> bool {
+        self.extra.push((pin, button)).is_ok()
+    }
+
+    /// Reports the turns of `encoder` among the presses
+    pub fn set_encoder(&mut self, encoder: Encoder) {
+        self.encoder = Some(encoder);
+    }
+
+    /// Makes a double tap on the badge a press of `button`, once
+    /// [accel::detect_taps] is on
+    pub fn set_tap_button(&mut self, button: Button) {
+        self.tap = Some((button, Instant::now()));
+    }
+
+    /// Buttons currently held down (debounced)
+    pub fn held(&self) -> ButtonSet {
+        self.stable
+    }
+
+    /// Samples the pins; call this frequently from the main loop
+    pub fn poll(&mut self, now: Instant) {
+        if let Some(event) = self.encoder.as_mut().and_then(|encoder| encoder.poll(now)) {
+            push(event, Source::Encoder);
+        }
+        if let Some((button, next_check)) = &mut self.tap {
+            if now >= *next_check {
+                *next_check = now + TAP_POLL;
+                if accel::take_double_tap() {
+                    push(InputEvent::Press(*button), Source::Tap);
+                }
+            }
+        }
+        let raw = self.sample();
+        if raw != self.raw {
+            self.raw = raw;
+            self.raw_since = now;
+        } else if raw != self.stable && now - self.raw_since >= DEBOUNCE {
+            self.settle(raw, now);
+        }
+        self.hold(now);
+    }
+
+    /// Takes `raw` as the buttons held, reporting the gesture once all are up
+    fn settle(&mut self, raw: ButtonSet, now: Instant) {
+        if self.stable.is_empty() {
+            self.pressed_at = now;
+            if let Some((delay, _)) = self.timing.repeat {
+                self.next_repeat = now + delay;
+            }
+        }
+        self.stable = raw;
+        for button in Button::ALL {
+            if raw.contains(button) {
+                self.gesture = self.gesture.with(button);
+            }
+        }
+
+        if self.stable.is_empty() && !self.gesture.is_empty() {
+            let gesture = mem::take(&mut self.gesture);
+            if mem::take(&mut self.spent) {
+                return;
+            }
+            let event = match gesture.single() {
+                Some(button) => InputEvent::Press(button),
+                None => InputEvent::Chord(gesture),
+            };
+            push(event, Source::Buttons);
+        }
+    }
+
+    /// Reports a single button held long enough to repeat or long press
+    fn hold(&mut self, now: Instant) {
+        let Some(button) = self.gesture.single() else {
+            return;
+        };
+        let repeats = critical_section::with(|cs| REPEAT.borrow(cs).get()).contains(button);
+        match self.timing.repeat {
+            Some((_, every)) if repeats => {
+                if now >= self.next_repeat {
+                    self.next_repeat = now + every;
+                    self.spent = true;
+                    push(InputEvent::Press(button), Source::Buttons);
+                }
+            }
+            _ => {
+                let long = self
+                    .timing
+                    .long_press
+                    .is_some_and(|long_press| now - self.pressed_at >= long_press);
+                if long && !self.spent {
+                    self.spent = true;
+                    push(InputEvent::LongPress(button), Source::Buttons);
+                }
+            }
+        }
+    }
+
+    /// Next pending input from any source, oldest first
+    pub fn next_event(&mut self) -> Option<Event> {
+        let (event, source) = next()?;
+        debug!("{:?} from {:?}", event, source);
+        Some(event.event())
+    }
+
+    /// Buttons down right now, without debouncing; for checks at boot
+    pub fn sample(&self) -> ButtonSet {
+        let mut set = ButtonSet::EMPTY;
+        for (pin, button) in self.pins.iter().zip(Button::ALL) {
+            if pin.is_low() {
+                set = set.with(button);
+            }
+        }
+        for (pin, button) in &self.extra {
+            if pin.is_low() {
+                set = set.with(*button);
+            }
+        }
+        set
+    }
+}
@@ -0,0 +1,182 @@
+//! Values picked out of JSON without a document model: [string_at] and
+//! [array_len] walk the bytes to the value at a path and skip everything else,
+//! so reading one field of a large response allocates only that field.
+//!
+//! Paths are object keys and, for arrays, indices written as numbers, such as
+//! `["workflow_runs", "0", "conclusion"]`.
+//!
+//! Skipping a value recurses into it, so arrays and objects nested deeper
+//! than [MAX_DEPTH] make the lookup fail rather than run out of stack.
+
+use alloc::{string::String, vec::Vec};
+
+/// Arrays and objects nested inside one another that are skipped over
+pub const MAX_DEPTH: usize = 32;
+
+/// The string at `path` in `json`, e.g. `["attributes", "friendly_name"]`;
+/// `None` if it isn't there or isn't a string
+pub fn string_at(json: &[u8], path: &[&str]) -> Option<String> {
+    let mut parser = Parser { json, pos: 0 };
+    parser.seek(path)?;
+    parser.string()
+}
+
+/// How many elements the array at `path` has
+pub fn array_len(json: &[u8], path: &[&str]) -> Option<usize> {
+    let mut parser = Parser { json, pos: 0 };
+    parser.seek(path)?;
+    parser.expect(b'[')?;
+    if parser.eat(b']') {
+        return Some(0);
+    }
+    let mut len = 0;
+    loop {
+        parser.skip_value()?;
+        len += 1;
+        if parser.eat(b']') {
+            return Some(len);
+        }
+        parser.expect(b',')?;
+    }
+}
+
+struct Parser<'j> {
+    json: &'j [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    /// Moves to the value at `path`
+    fn seek(&mut self, path: &[&str]) -> Option<()> {
+        for segment in path {
+            if let Ok(index) = segment.parse::<usize>() {
+                self.expect(b'[')?;
+                for _ in 0..index {
+                    self.skip_value()?;
+                    self.expect(b',')?;
+                }
+                continue;
+            }
+            self.expect(b'{')?;
+            if self.eat(b'}') {
+                return None;
+            }
+            loop {
+                let name = self.string()?;
+                self.expect(b':')?;
+                if name == *segment {
+                    break;
+                }
+                self.skip_value()?;
+                if !self.eat(b',') {
+                    return None;
+                }
+            }
+        }
+        Some(())
+    }
+
+    fn skip_value(&mut self) -> Option<()> {
+        self.skip_nested(0)
+    }
+
+    /// Skips a value `depth` arrays and objects down
+    fn skip_nested(&mut self, depth: usize) -> Option<()> {
+        self.skip_whitespace();
+        match *self.json.get(self.pos)? {
+            b'"' => self.string().map(drop),
+            open @ (b'{' | b'[') => {
+                if depth == MAX_DEPTH {
+                    return None;
+                }
+                let close = if open == b'{' { b'}' } else { b']' };
+                self.pos += 1;
+                if self.eat(close) {
+                    return Some(());
+                }
+                loop {
+                    if open == b'{' {
+                        self.string()?;
+                        self.expect(b':')?;
+                    }
+                    self.skip_nested(depth + 1)?;
+                    if self.eat(close) {
+                        return Some(());
+                    }
+                    self.expect(b',')?;
+                }
+            }
+            // numbers, true, false and null
+            _ => {
+                let start = self.pos;
+                while self
+                    .json
+                    .get(self.pos)
+                    .is_some_and(|&b| !matches!(b, b',' | b'}' | b']') && !b.is_ascii_whitespace())
+                {
+                    self.pos += 1;
+                }
+                (self.pos > start).then_some(())
+            }
+        }
+    }
+
+    fn string(&mut self) -> Option<String> {
+        self.expect(b'"')?;
+        let mut out = Vec::new();
+        loop {
+            let byte = *self.json.get(self.pos)?;
+            self.pos += 1;
+            match byte {
+                b'"' => return String::from_utf8(out).ok(),
+                b'\\' => {
+                    let escaped = *self.json.get(self.pos)?;
+                    self.pos += 1;
+                    let c = match escaped {
+                        b'n' => '\n',
+                        b't' => '\t',
+                        b'r' => '\r',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'u' => {
+                            let hex = self.json.get(self.pos..self.pos + 4)?;
+                            self.pos += 4;
+                            let code =
+                                u32::from_str_radix(core::str::from_utf8(hex).ok()?, 16).ok()?;
+                            // half of a surrogate pair can't stand alone
+                            char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+                        }
+                        other => other as char,
+                    };
+                    let mut utf8 = [0; 4];
+                    out.extend_from_slice(c.encode_utf8(&mut utf8).as_bytes());
+                }
+                _ => out.push(byte),
+            }
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self
+            .json
+            .get(self.pos)
+            .is_some_and(|b| b.is_ascii_whitespace())
+        {
+            self.pos += 1;
+        }
+    }
+
+    /// Takes `byte` after any whitespace if it comes next
+    fn eat(&mut self, byte: u8) -> bool {
+        self.skip_whitespace();
+        let next = self.json.get(self.pos) == Some(&byte);
+        if next {
+            self.pos += 1;
+        }
+        next
+    }
+
+    fn expect(&mut self, byte: u8) -> Option<()> {
+        self.eat(byte).then_some(())
+    }
+}
@@ -0,0 +1,91 @@
+//! Board support and application runtime for the Adafruit MagTag 2.9" e-ink badge.
+
+#![no_std]
+
+extern crate alloc;
+
+pub mod accel;
+pub mod actions;
+pub mod alarm;
+pub mod alerts;
+pub mod analog;
+pub mod app;
+pub mod apps;
+pub mod assets;
+pub mod bindings;
+pub mod board;
+pub mod boot_mode;
+pub mod caldav;
+pub mod caldav_parser;
+pub mod canvas;
+pub mod chip;
+pub mod compositor;
+pub mod config;
+pub mod console;
+pub mod crash;
+pub mod crc;
+pub mod data_source;
+pub mod datalog;
+pub mod display;
+pub mod encoder;
+pub mod error;
+pub mod expansion;
+pub mod file_drop;
+pub mod flash;
+pub mod fleet;
+pub mod fonts;
+pub mod hal;
+pub mod home_assistant;
+pub mod hooks;
+pub mod host_tasks;
+pub mod http;
+pub mod http_parser;
+pub mod http_request;
+pub mod http_server;
+pub mod i18n;
+pub mod i2c;
+pub mod improv;
+#[cfg(feature = "gzip")]
+pub mod inflate;
+pub mod input;
+pub mod json;
+pub mod lifecycle;
+pub mod logging;
+pub mod metrics;
+pub mod mqtt;
+pub mod mqtt_packet;
+pub mod multipart;
+pub mod neopixel;
+pub mod net;
+pub mod net_health;
+pub mod notify;
+pub mod partitions;
+pub mod pedometer;
+pub mod power;
+pub mod psram;
+pub mod pwm;
+pub mod rate_limit;
+pub mod remote_frame;
+pub mod retry;
+pub mod rtttl;
+pub mod scheduler;
+pub mod screens;
+pub mod sensors;
+#[cfg(feature = "sdcard")]
+pub mod sdcard;
+#[cfg(feature = "encrypted-secrets")]
+pub mod secrets;
+pub mod sha256;
+pub mod sntp;
+pub mod speaker;
+pub mod storage;
+pub mod system;
+pub mod telemetry;
+pub mod time;
+pub mod tsdb;
+pub mod tz;
+pub mod ui;
+pub mod url;
+pub mod wifi;
+
+pub use error::Error;
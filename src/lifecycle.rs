@@ -0,0 +1,325 @@
+//! Boot as a fixed sequence of stages, each with a time budget and a stage to
+//! go on to when it fails, so a network that only half works ends with the
+//! apps up and a notice about it rather than a badge stuck on the last image.
+//!
+//! | stage     | work                                   | budget | on failure |
+//! |-----------|----------------------------------------|--------|------------|
+//! | Boot      | peripherals, config, panel, boot mode  | none   | panic, restart |
+//! | Connect   | joins the saved network                | 90 s   | Render, offline |
+//! | Provision | waits for credentials over Improv      | none   |            |
+//! | Sync      | sets the clock over SNTP or HTTP       | 120 s  | Fetch, clock as it was |
+//! | Fetch     | [crate::fleet] config or a test request| 120 s  | Render     |
+//! | Render    | the [AppHost](crate::app::AppHost)     | none   |            |
+//! | Sleep     | deep sleep, see [crate::power]         | none   |            |
+//!
+//! Networks that block SNTP still answer HTTP, so when SNTP fails and the
+//! clock is unset or a day old, Sync sets it from the `Date` header of the
+//! `time_url` config key, or of the Fetch stage's test page. That is only good
+//! to about a second and nothing vouches for it, so SNTP still gets the next
+//! say; a bad date only moves the clock until then.
+//!
+//! A failed stage leaves a [toast] on the first screen the apps draw. Offline,
+//! [crate::net_health] keeps trying the saved network, so the badge comes
+//! online by itself once the access point is back.
+//!
+//! The steps of each stage give up on their own; the budget is a backstop on
+//! the RTC watchdog, which resets the chip if a stage overstays it. The reset
+//! keeps RTC memory, and [crate::crash] records which stage it cut short.
+//! Stages without a budget stop the watchdog: they wait on the user or run
+//! for as long as the badge is up.
+
+use core::{cell::Cell, fmt};
+
+use critical_section::Mutex;
+use esp_hal::{
+    ram,
+    rtc_cntl::{RwdtStage, RwdtStageAction},
+    time::{Duration, Instant},
+};
+use log::{info, warn};
+
+use crate::{
+    boot_mode::BootMode,
+    config::{keys, ConfigStore},
+    console::UsbSerial,
+    display::Display,
+    fleet::Fleet,
+    hooks::{self, Hook},
+    http, improv,
+    net::NetStack,
+    sntp::{self, SntpBuffers},
+    time, tr,
+    ui::toast,
+    wifi::{self, Credentials},
+    Error,
+};
+
+/// What the Fetch stage asks for
+const TEST_URL: &str = "http://www.mobile-j.de/";
+/// A clock synced longer ago than this is worth setting from HTTP
+const STALE_SYNC: Duration = Duration::from_hours(24);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Boot,
+    Connect,
+    Provision,
+    Sync,
+    Fetch,
+    Render,
+    Sleep,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 7] = [
+        Stage::Boot,
+        Stage::Connect,
+        Stage::Provision,
+        Stage::Sync,
+        Stage::Fetch,
+        Stage::Render,
+        Stage::Sleep,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Boot => "boot",
+            Stage::Connect => "connect",
+            Stage::Provision => "provision",
+            Stage::Sync => "sync",
+            Stage::Fetch => "fetch",
+            Stage::Render => "render",
+            Stage::Sleep => "sleep",
+        }
+    }
+
+    /// How long the stage may take before the watchdog resets the chip
+    pub fn budget(self) -> Option<Duration> {
+        match self {
+            Stage::Connect => Some(Duration::from_secs(90)),
+            Stage::Sync | Stage::Fetch => Some(Duration::from_secs(120)),
+            Stage::Boot | Stage::Provision | Stage::Render | Stage::Sleep => None,
+        }
+    }
+
+    /// Where a failure in this stage leads
+    fn on_failure(self) -> Stage {
+        match self {
+            Stage::Connect | Stage::Fetch => Stage::Render,
+            Stage::Sync => Stage::Fetch,
+            _ => Stage::Render,
+        }
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+static CURRENT: Mutex<Cell<(Stage, Instant)>> =
+    Mutex::new(Cell::new((Stage::Boot, Instant::EPOCH)));
+
+/// One more than the [Stage] in progress, or zero once the apps run.
+/// Survives the watchdog reset, for [interrupted].
+#[ram(unstable(rtc_fast, persistent))]
+static mut UNFINISHED: u32 = 0;
+
+/// The stage the badge is in
+pub fn current() -> Stage {
+    critical_section::with(|cs| CURRENT.borrow(cs).get().0)
+}
+
+/// Moves to `stage`, ending the budget of the one before and starting its own
+pub fn enter(stage: Stage) {
+    let now = Instant::now();
+    let (previous, since) = critical_section::with(|cs| {
+        let current = CURRENT.borrow(cs).replace((stage, now));
+        let raw = if stage == Stage::Render {
+            0
+        } else {
+            stage as u32 + 1
+        };
+        // SAFETY: single core, and only touched inside a critical section
+        unsafe { UNFINISHED = raw };
+        current
+    });
+    info!(
+        "Stage {} done in {} ms, entering {}",
+        previous,
+        (now - since).as_millis(),
+        stage
+    );
+    arm_watchdog(stage.budget());
+}
+
+/// The stage the watchdog cut short, when the previous run ended in a
+/// watchdog reset; the answer is garbage after any other reset
+pub(crate) fn interrupted() -> Option<Stage> {
+    // SAFETY: as in `enter`
+    let raw = critical_section::with(|_| unsafe { UNFINISHED });
+    Stage::ALL.get((raw as usize).checked_sub(1)?).copied()
+}
+
+fn arm_watchdog(budget: Option<Duration>) {
+    time::with_rtc(|rtc| match budget {
+        Some(budget) => {
+            rtc.rwdt.enable();
+            // a core reset leaves the RTC, so the clock and RTC memory, alone
+            rtc.rwdt
+                .set_stage_action(RwdtStage::Stage0, RwdtStageAction::ResetCore);
+            rtc.rwdt.set_timeout(RwdtStage::Stage0, budget);
+            rtc.rwdt.feed();
+        }
+        None => rtc.rwdt.disable(),
+    });
+}
+
+/// Holds each app's fetch, once the apps run, to the Fetch stage's budget on
+/// the same watchdog
+pub struct FetchWatchdog;
+
+impl Hook for FetchWatchdog {
+    fn before_fetch(&mut self, _app: &'static str) {
+        arm_watchdog(Stage::Fetch.budget());
+    }
+
+    fn after_fetch(&mut self, _app: &'static str, _took: Duration) {
+        arm_watchdog(None);
+    }
+}
+
+/// What the network stages work with
+pub struct Startup<'a, 'n> {
+    pub net: &'a NetStack<'n>,
+    pub display: &'a mut Display,
+    pub serial: &'a mut UsbSerial,
+    pub config: &'a mut ConfigStore,
+    pub credentials: Option<Credentials>,
+    pub mode: BootMode,
+    /// The join was started with [wifi::begin_join] during Boot
+    pub joining: bool,
+    /// The SNTP socket lives as long as the stack's socket set
+    pub sntp: &'n mut SntpBuffers,
+}
+
+/// Runs Connect through Fetch and returns on entering Render
+pub fn bring_up(startup: Startup<'_, '_>) {
+    let Startup {
+        net,
+        display,
+        serial,
+        config,
+        credentials,
+        mode,
+        joining,
+        sntp,
+    } = startup;
+    // Sync runs at most once
+    let mut sntp = Some(sntp);
+    let mut stage = Stage::Connect;
+    while stage != Stage::Render {
+        enter(stage);
+        let result = match stage {
+            Stage::Connect => connect(net, credentials.as_ref(), mode, joining),
+            Stage::Provision => {
+                improv::provision(serial, net, config, display);
+                Ok(Stage::Sync)
+            }
+            Stage::Sync => match sntp.take() {
+                Some(buffers) => sync(net, buffers, config),
+                None => Ok(Stage::Fetch),
+            },
+            Stage::Fetch => fetch(net, config, mode),
+            Stage::Boot | Stage::Render | Stage::Sleep => Ok(Stage::Render),
+        };
+        stage = result.unwrap_or_else(|err| {
+            let next = stage.on_failure();
+            warn!("Stage {} failed: {}, going on to {}", stage, err, next);
+            hooks::on_error(stage.name(), &err);
+            match stage {
+                Stage::Connect => toast::show(tr!("offline_setup")),
+                Stage::Sync => toast::show(tr!("clock_not_synced")),
+                _ => toast::show(tr!("no_network")),
+            }
+            next
+        });
+    }
+    enter(Stage::Render);
+}
+
+fn connect(
+    net: &NetStack<'_>,
+    credentials: Option<&Credentials>,
+    mode: BootMode,
+    joining: bool,
+) -> Result<Stage, Error> {
+    let credentials = match credentials {
+        // provisioning mode asks for new credentials even if the saved ones work
+        _ if mode == BootMode::Provisioning => return Ok(Stage::Provision),
+        Some(credentials) => credentials,
+        None => return Ok(Stage::Provision),
+    };
+    let joined = if joining {
+        wifi::finish_join(credentials, net)
+    } else {
+        wifi::join(credentials, net)
+    };
+    // the network health check keeps trying to join
+    joined.inspect_err(|_| wifi::retry_later(credentials))?;
+    Ok(Stage::Sync)
+}
+
+fn sync<'n>(
+    net: &NetStack<'n>,
+    buffers: &'n mut SntpBuffers,
+    config: &ConfigStore,
+) -> Result<Stage, Error> {
+    let err = match sntp::query(net, buffers, sntp::DEFAULT_SERVER) {
+        Ok(unix_us) => {
+            time::sync(unix_us);
+            info!("SNTP sync, local time {:?}", time::now_local());
+            return Ok(Stage::Fetch);
+        }
+        Err(err) => err,
+    };
+    if time::since_sync().is_some_and(|age| age < STALE_SYNC) {
+        return Err(err);
+    }
+    let url = config.get(keys::TIME_URL).unwrap_or(TEST_URL);
+    warn!("SNTP failed: {}, asking {} for the date", err, url);
+    let response = http::get(net, url)?;
+    let date = response
+        .header("date")
+        .and_then(time::parse_http_date)
+        .ok_or(err)?;
+    // the header is truncated to the second
+    time::sync_approximate(date.as_microsecond() as u64 + 500_000);
+    info!("Clock set from HTTP, local time {:?}", time::now_local());
+    Ok(Stage::Fetch)
+}
+
+/// The first request of the run doubles as a check that DNS and HTTP work:
+/// the fleet config if there is one, else a test page that fast boots skip.
+/// Safe mode skips both.
+fn fetch(net: &NetStack<'_>, config: &mut ConfigStore, mode: BootMode) -> Result<Stage, Error> {
+    if mode == BootMode::Safe {
+        return Ok(Stage::Render);
+    }
+    if let Some(fleet) = Fleet::from_config(config) {
+        fleet.sync(net, config)?;
+        return Ok(Stage::Render);
+    }
+    if cfg!(feature = "fast-boot") {
+        return Ok(Stage::Render);
+    }
+    let response = http::get(net, TEST_URL)?;
+    info!(
+        "{} answered {} with {} bytes",
+        TEST_URL,
+        response.status,
+        response.body.len()
+    );
+    Ok(Stage::Render)
+}
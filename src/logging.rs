@@ -0,0 +1,56 @@
+//! Where log records go: text over serial through esp-println by default, or
+//! defmt frames with the `defmt` feature.
+//!
+//! The firmware logs through the `log` macros either way. With `defmt` the esp
+//! crates log natively in defmt, which is where most of the size saving comes
+//! from, and records from this crate are forwarded as formatted strings.
+//! Decode the output with `espflash monitor --log-format defmt`.
+
+use log::LevelFilter;
+
+#[cfg(all(feature = "println", feature = "defmt"))]
+compile_error!("enable only one of the `println` and `defmt` features");
+
+/// Installs the logger. Call once, first thing at boot.
+pub fn init(level: LevelFilter) {
+    #[cfg(feature = "println")]
+    esp_println::logger::init_logger(level);
+
+    #[cfg(feature = "defmt")]
+    // SAFETY: called once at boot, before anything else can log
+    unsafe {
+        log::set_logger_racy(&DefmtLogger).ok();
+        log::set_max_level_racy(level);
+    }
+}
+
+/// Changes which records get through, such as after `fast-boot` has shown
+/// the first screen
+pub fn set_level(level: LevelFilter) {
+    log::set_max_level(level);
+}
+
+#[cfg(feature = "defmt")]
+struct DefmtLogger;
+
+#[cfg(feature = "defmt")]
+impl log::Log for DefmtLogger {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        // filtered by `log` already
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        let args = defmt::Display2Format(record.args());
+        let target = record.target();
+        match record.level() {
+            log::Level::Error => defmt::error!("{=str}: {}", target, args),
+            log::Level::Warn => defmt::warn!("{=str}: {}", target, args),
+            log::Level::Info => defmt::info!("{=str}: {}", target, args),
+            log::Level::Debug => defmt::debug!("{=str}: {}", target, args),
+            log::Level::Trace => defmt::trace!("{=str}: {}", target, args),
+        }
+    }
+
+    fn flush(&self) {}
+}
@@ -0,0 +1,142 @@
+//! Health figures for telemetry: battery, signal, memory, refresh time and
+//! why the badge woke up.
+
+use alloc::string::String;
+use core::{cell::Cell, fmt::Write as _};
+
+use critical_section::Mutex;
+use esp_hal::{
+    system::SleepSource,
+    time::{Duration, Instant},
+};
+
+use crate::{analog, http::Response, http_server::Request, wifi};
+
+/// Content type of the Prometheus text exposition format
+pub const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+static LAST_REFRESH_MS: Mutex<Cell<Option<u64>>> = Mutex::new(Cell::new(None));
+
+/// Why this run started
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WakeCause {
+    /// Power-on or a reset rather than a wake from deep sleep
+    Reset,
+    Timer,
+    Button,
+    Other,
+    /// The accelerometer, see [crate::power::wake_on]
+    Motion,
+}
+
+impl WakeCause {
+    /// In the order of their values, which the telemetry backlog stores
+    pub const ALL: [WakeCause; 5] = [
+        WakeCause::Reset,
+        WakeCause::Timer,
+        WakeCause::Button,
+        WakeCause::Other,
+        WakeCause::Motion,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WakeCause::Reset => "reset",
+            WakeCause::Timer => "timer",
+            WakeCause::Button => "button",
+            WakeCause::Other => "other",
+            WakeCause::Motion => "motion",
+        }
+    }
+}
+
+pub fn wake_cause() -> WakeCause {
+    match esp_hal::system::wakeup_cause() {
+        SleepSource::Undefined => WakeCause::Reset,
+        SleepSource::Timer => WakeCause::Timer,
+        // ext0 is the accelerometer's interrupt line
+        SleepSource::Ext0 => WakeCause::Motion,
+        SleepSource::Ext1 | SleepSource::Gpio => WakeCause::Button,
+        _ => WakeCause::Other,
+    }
+}
+
+/// Records how long the last panel refresh took
+pub fn record_refresh(duration: Duration) {
+    critical_section::with(|cs| LAST_REFRESH_MS.borrow(cs).set(Some(duration.as_millis())));
+}
+
+pub fn last_refresh() -> Option<Duration> {
+    critical_section::with(|cs| LAST_REFRESH_MS.borrow(cs).get()).map(Duration::from_millis)
+}
+
+/// The figures at one point in time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Snapshot {
+    pub uptime: Duration,
+    pub battery_mv: Option<u32>,
+    pub rssi_dbm: Option<i32>,
+    pub heap_free: usize,
+    pub last_refresh: Option<Duration>,
+    pub wake_cause: WakeCause,
+}
+
+/// Reads every figure now
+pub fn snapshot() -> Snapshot {
+    Snapshot {
+        uptime: Instant::now().duration_since_epoch(),
+        battery_mv: analog::battery_millivolts(),
+        rssi_dbm: wifi::rssi(),
+        heap_free: esp_alloc::HEAP.free(),
+        last_refresh: last_refresh(),
+        wake_cause: wake_cause(),
+    }
+}
+
+impl Snapshot {
+    /// The figures in Prometheus text format, leaving out the ones that
+    /// couldn't be read
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        if let Some(mv) = self.battery_mv {
+            gauge(&mut out, "battery_millivolts", "Battery voltage.", mv);
+        }
+        if let Some(rssi) = self.rssi_dbm {
+            gauge(&mut out, "wifi_rssi_dbm", "WiFi signal strength.", rssi);
+        }
+        if let Some(refresh) = self.last_refresh {
+            let ms = refresh.as_millis();
+            gauge(
+                &mut out,
+                "refresh_seconds",
+                "Duration of the last panel refresh.",
+                format_args!("{}.{:03}", ms / 1000, ms % 1000),
+            );
+        }
+        gauge(&mut out, "heap_free_bytes", "Free heap.", self.heap_free);
+        gauge(
+            &mut out,
+            "uptime_seconds",
+            "Time since boot.",
+            self.uptime.as_secs(),
+        );
+        out
+    }
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: impl core::fmt::Display) {
+    write!(
+        out,
+        "# HELP {name} {help}\n# TYPE {name} gauge\n{name} {value}\n"
+    )
+    .ok();
+}
+
+/// Route handler for [http_server::HttpServer] serving the current figures
+pub fn serve_prometheus(_request: &Request<'_>) -> Response {
+    Response::new(
+        200,
+        PROMETHEUS_CONTENT_TYPE,
+        snapshot().to_prometheus().into_bytes(),
+    )
+}
@@ -0,0 +1,359 @@
+//! Minimal MQTT 3.1.1 client: connect, publish at QoS 0, disconnect, or stay
+//! connected as a [Subscriber].
+//!
+//! Targets are written as URLs, `mqtt://host[:port]/topic`; the topic may
+//! contain further `/` levels. A session that fails on the network is
+//! started over with [Policy::NETWORK], so QoS 0 messages may arrive twice.
+//!
+//! Packets are written to the socket piece by piece, so the only buffers
+//! are the socket's: from the heap with [publish], or a caller's [Buffers]
+//! with [publish_with]. Those that arrive are read by [crate::mqtt_packet].
+
+use alloc::vec::Vec;
+
+use embedded_io::{Read as _, ReadReady as _, Write as _};
+use esp_hal::time::{Duration, Instant};
+use log::{debug, warn};
+
+use crate::{
+    mqtt_packet::{self, CONNACK, CONNECT, DISCONNECT, PINGREQ, PUBLISH, SUBSCRIBE},
+    net::{self, BufferSizes, KeepAlive, NetStack, TcpSocket},
+    retry::{self, Policy},
+    url::Url,
+    wifi, Error,
+};
+
+pub const DEFAULT_PORT: u16 = 1883;
+const TIMEOUT: Duration = Duration::from_secs(10);
+const KEEP_ALIVE_SECS: u16 = 60;
+
+/// Username and password for brokers that want them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Login<'l> {
+    pub username: &'l str,
+    pub password: &'l str,
+}
+
+/// Socket buffers for [publish_with], by default the sizes of
+/// [BufferSizes::MQTT]
+pub struct Buffers<const RX: usize = 256, const TX: usize = 1024> {
+    rx: [u8; RX],
+    tx: [u8; TX],
+}
+
+impl<const RX: usize, const TX: usize> Buffers<RX, TX> {
+    pub const fn new() -> Self {
+        Self {
+            rx: [0; RX],
+            tx: [0; TX],
+        }
+    }
+}
+
+impl<const RX: usize, const TX: usize> Default for Buffers<RX, TX> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Publishes each of `payloads` to the topic in `url` over one connection
+pub fn publish(
+    stack: &NetStack<'_>,
+    url: &str,
+    client_id: &str,
+    login: Option<Login<'_>>,
+    payloads: &[&[u8]],
+) -> Result<(), Error> {
+    publish_over(stack, url, client_id, login, payloads, |session| {
+        net::with_tcp_socket_sized(stack, BufferSizes::MQTT, session)
+    })
+}
+
+/// [publish] over a socket on `buffers` instead of the heap
+pub fn publish_with<const RX: usize, const TX: usize>(
+    stack: &NetStack<'_>,
+    buffers: &mut Buffers<RX, TX>,
+    url: &str,
+    client_id: &str,
+    login: Option<Login<'_>>,
+    payloads: &[&[u8]],
+) -> Result<(), Error> {
+    publish_over(stack, url, client_id, login, payloads, |session| {
+        net::with_tcp_socket(stack, &mut buffers.rx, &mut buffers.tx, session)
+    })
+}
+
+/// Runs a session over the sockets `with_socket` hands out, one per attempt
+fn publish_over<'a>(
+    stack: &NetStack<'a>,
+    url: &str,
+    client_id: &str,
+    login: Option<Login<'_>>,
+    payloads: &[&[u8]],
+    mut with_socket: impl FnMut(
+        &mut dyn FnMut(&mut TcpSocket<'_, 'a>) -> Result<(), Error>,
+    ) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let url = Url::parse_with_scheme(url, "mqtt", DEFAULT_PORT)?;
+    let topic = url.path.trim_start_matches('/');
+    if topic.is_empty() {
+        warn!("MQTT URL has no topic");
+        return Err(Error::InvalidUrl);
+    }
+
+    let _transfer = wifi::transfer();
+    retry::with_backoff(&Policy::NETWORK, || {
+        let addr = net::resolve(stack, url.host)?;
+        with_socket(&mut |socket| {
+            socket.open(addr, url.port).map_err(|_| Error::Network)?;
+            let result = session(socket, client_id, login, topic, payloads);
+            socket.disconnect();
+            result
+        })
+    })?;
+    debug!("published {} messages to {}", payloads.len(), topic);
+    Ok(())
+}
+
+fn session(
+    socket: &mut TcpSocket<'_, '_>,
+    client_id: &str,
+    login: Option<Login<'_>>,
+    topic: &str,
+    payloads: &[&[u8]],
+) -> Result<(), Error> {
+    open_session(socket, client_id, login)?;
+    let topic = Str::new(topic);
+    for payload in payloads {
+        send(socket, PUBLISH, &[&topic.len, topic.bytes, payload])?;
+    }
+    send(socket, DISCONNECT, &[])
+}
+
+/// Sends CONNECT and waits for the broker to accept it
+fn open_session(
+    socket: &mut TcpSocket<'_, '_>,
+    client_id: &str,
+    login: Option<Login<'_>>,
+) -> Result<(), Error> {
+    // clean session, plus the username and password flags
+    let mut flags = 0x02;
+    if login.is_some() {
+        flags |= 0x80 | 0x40;
+    }
+    let keep_alive = KEEP_ALIVE_SECS.to_be_bytes();
+    let protocol = Str::new("MQTT");
+    let client_id = Str::new(client_id);
+    let login = login.map(|login| [Str::new(login.username), Str::new(login.password)]);
+    // left out without a login
+    let mut credentials: [&[u8]; 4] = [&[]; 4];
+    if let Some([username, password]) = &login {
+        credentials = [&username.len, username.bytes, &password.len, password.bytes];
+    }
+    let [c0, c1, c2, c3] = credentials;
+    send(
+        socket,
+        CONNECT,
+        &[
+            &protocol.len,
+            protocol.bytes,
+            &[4, flags],
+            &keep_alive,
+            &client_id.len,
+            client_id.bytes,
+            c0,
+            c1,
+            c2,
+            c3,
+        ],
+    )?;
+
+    let mut connack = [0u8; 4];
+    read_exact(socket, &mut connack)?;
+    if connack[0] != CONNACK || connack[3] != 0 {
+        warn!("MQTT broker refused the connection, code {}", connack[3]);
+        return Err(Error::Network);
+    }
+    Ok(())
+}
+
+/// A connection that stays open and receives what is published to its
+/// topics, at QoS 0
+pub struct Subscriber<'a> {
+    stack: &'a NetStack<'a>,
+    socket: TcpSocket<'a, 'a>,
+    /// The start of a packet still coming in
+    pending: Vec<u8>,
+    /// When a packet last went out, for the keep-alive pings
+    sent_at: Instant,
+    connected: bool,
+}
+
+impl<'a> Subscriber<'a> {
+    /// A subscriber on socket buffers that live as long as the stack
+    pub fn new(stack: &'a NetStack<'a>, rx_buffer: &'a mut [u8], tx_buffer: &'a mut [u8]) -> Self {
+        Self {
+            stack,
+            socket: stack.get_socket(rx_buffer, tx_buffer),
+            pending: Vec::new(),
+            sent_at: Instant::EPOCH,
+            connected: false,
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    /// Connects to the broker at `url`, `mqtt://host[:port]`, and subscribes
+    /// to `topics`, dropping the connection there was
+    pub fn connect(
+        &mut self,
+        url: &str,
+        client_id: &str,
+        login: Option<Login<'_>>,
+        topics: &[&str],
+    ) -> Result<(), Error> {
+        self.disconnect();
+        let url = Url::parse_with_scheme(url, "mqtt", DEFAULT_PORT)?;
+        let _transfer = wifi::transfer();
+        let addr = net::resolve(self.stack, url.host)?;
+        self.socket
+            .open(addr, url.port)
+            .map_err(|_| Error::Network)?;
+        KeepAlive::LONG_LIVED.apply(&mut self.socket);
+        let result = open_session(&mut self.socket, client_id, login).and_then(|()| {
+            let topics: Vec<Str<'_>> = topics.iter().map(|topic| Str::new(topic)).collect();
+            let mut parts: Vec<&[u8]> = Vec::with_capacity(1 + 3 * topics.len());
+            // packet identifier
+            parts.push(&[0, 1]);
+            for topic in &topics {
+                // QoS 0
+                parts.extend_from_slice(&[&topic.len, topic.bytes, &[0]]);
+            }
+            send(&mut self.socket, SUBSCRIBE, &parts)
+        });
+        if let Err(err) = result {
+            self.socket.disconnect();
+            return Err(err);
+        }
+        self.connected = true;
+        self.sent_at = Instant::now();
+        debug!("subscribed to {} topics on {}", topics.len(), url.host);
+        Ok(())
+    }
+
+    /// Hands what has arrived to `on_message` as topic and payload, and pings
+    /// the broker when nothing went out for a while. Fails, disconnected,
+    /// when the connection is gone.
+    pub fn poll(&mut self, mut on_message: impl FnMut(&str, &[u8])) -> Result<(), Error> {
+        if !self.connected {
+            return Err(Error::Network);
+        }
+        let result = self.receive(&mut on_message).and_then(|()| {
+            let now = Instant::now();
+            if now - self.sent_at < Duration::from_secs(KEEP_ALIVE_SECS as u64 / 2) {
+                return Ok(());
+            }
+            self.sent_at = now;
+            send(&mut self.socket, PINGREQ, &[])
+        });
+        if result.is_err() {
+            warn!("MQTT subscription lost");
+            self.drop_connection();
+        }
+        result
+    }
+
+    /// Says goodbye to the broker if connected
+    pub fn disconnect(&mut self) {
+        if self.connected {
+            send(&mut self.socket, DISCONNECT, &[]).ok();
+        }
+        self.drop_connection();
+    }
+
+    fn drop_connection(&mut self) {
+        self.socket.disconnect();
+        self.pending.clear();
+        self.connected = false;
+    }
+
+    fn receive(&mut self, on_message: &mut impl FnMut(&str, &[u8])) -> Result<(), Error> {
+        if !self.socket.is_connected() {
+            return Err(Error::Network);
+        }
+        let mut chunk = [0u8; 256];
+        while self.socket.read_ready().map_err(|_| Error::Network)? {
+            let len = self.socket.read(&mut chunk).map_err(|_| Error::Network)?;
+            if len == 0 {
+                return Err(Error::Network);
+            }
+            self.pending.extend_from_slice(&chunk[..len]);
+            mqtt_packet::dispatch(&mut self.pending, on_message)?;
+        }
+        Ok(())
+    }
+}
+
+/// A length-prefixed UTF-8 string
+struct Str<'s> {
+    len: [u8; 2],
+    bytes: &'s [u8],
+}
+
+impl<'s> Str<'s> {
+    fn new(s: &'s str) -> Self {
+        Self {
+            len: (s.len() as u16).to_be_bytes(),
+            bytes: s.as_bytes(),
+        }
+    }
+}
+
+/// Sends a packet whose body is `parts` one after the other
+fn send(socket: &mut TcpSocket<'_, '_>, kind: u8, parts: &[&[u8]]) -> Result<(), Error> {
+    let mut header = [0u8; 5];
+    header[0] = kind;
+    let mut header_len = 1;
+    // remaining length, 7 bits per byte, least significant first
+    let mut len: usize = parts.iter().map(|part| part.len()).sum();
+    loop {
+        let mut byte = (len % 128) as u8;
+        len /= 128;
+        if len > 0 {
+            byte |= 0x80;
+        }
+        header[header_len] = byte;
+        header_len += 1;
+        if len == 0 {
+            break;
+        }
+    }
+    socket
+        .write_all(&header[..header_len])
+        .map_err(|_| Error::Network)?;
+    for part in parts {
+        socket.write_all(part).map_err(|_| Error::Network)?;
+    }
+    socket.flush().map_err(|_| Error::Network)
+}
+
+fn read_exact(socket: &mut TcpSocket<'_, '_>, buf: &mut [u8]) -> Result<(), Error> {
+    let deadline = Instant::now() + TIMEOUT;
+    let mut filled = 0;
+    while filled < buf.len() {
+        if Instant::now() > deadline {
+            return Err(Error::Timeout);
+        }
+        match socket.read_ready() {
+            Ok(true) => {}
+            Ok(false) => continue,
+            Err(_) => return Err(Error::Network),
+        }
+        filled += socket
+            .read(&mut buf[filled..])
+            .map_err(|_| Error::Network)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,93 @@
+//! Reads the MQTT 3.1.1 packets a broker sends a [crate::mqtt::Subscriber]
+//! out of the bytes that have arrived so far.
+//!
+//! Packets longer than [MAX_PACKET_LEN] or with a fifth length byte fail
+//! the connection rather than being buffered. PUBLISH packets at any QoS
+//! are handed on as topic and payload; a refused subscription in a SUBACK
+//! is logged and anything else ignored.
+//!
+//! Nothing here touches the network, so `tools/fuzz` feeds it arbitrary
+//! bytes on the host.
+
+use alloc::vec::Vec;
+
+use log::{debug, warn};
+
+use crate::Error;
+
+pub const CONNECT: u8 = 0x10;
+pub const CONNACK: u8 = 0x20;
+pub const PUBLISH: u8 = 0x30;
+pub const SUBSCRIBE: u8 = 0x82;
+pub const SUBACK: u8 = 0x90;
+pub const PINGREQ: u8 = 0xc0;
+pub const PINGRESP: u8 = 0xd0;
+pub const DISCONNECT: u8 = 0xe0;
+/// Largest packet a subscriber takes in; a bigger one drops the connection
+pub const MAX_PACKET_LEN: usize = 1024;
+
+/// Handles each whole packet at the start of `pending`, removing it, and
+/// leaves the start of one still coming in
+pub fn dispatch(
+    pending: &mut Vec<u8>,
+    on_message: &mut impl FnMut(&str, &[u8]),
+) -> Result<(), Error> {
+    while let Some((header, body_start, len)) = packet_header(pending)? {
+        let end = body_start + len;
+        handle(header, &pending[body_start..end], on_message);
+        pending.drain(..end);
+    }
+    Ok(())
+}
+
+/// The first byte, where the body starts and how long it is of the packet
+/// at the start of `buf`, or `None` until all of it is there
+pub fn packet_header(buf: &[u8]) -> Result<Option<(u8, usize, usize)>, Error> {
+    let mut len = 0;
+    for (i, &byte) in buf.iter().enumerate().skip(1).take(4) {
+        len |= ((byte & 0x7f) as usize) << (7 * (i - 1));
+        if byte & 0x80 == 0 {
+            if len > MAX_PACKET_LEN {
+                warn!("MQTT packet of {} bytes is too large", len);
+                return Err(Error::Network);
+            }
+            return Ok((buf.len() >= i + 1 + len).then_some((buf[0], i + 1, len)));
+        }
+    }
+    if buf.len() > 4 {
+        // a fifth length byte
+        return Err(Error::Network);
+    }
+    Ok(None)
+}
+
+/// Acts on one packet whose first byte is `header`
+pub fn handle(header: u8, body: &[u8], on_message: &mut impl FnMut(&str, &[u8])) {
+    match header & 0xf0 {
+        PUBLISH => {
+            let Some((len, rest)) = body.split_first_chunk::<2>() else {
+                return;
+            };
+            let len = u16::from_be_bytes(*len) as usize;
+            if rest.len() < len {
+                return;
+            }
+            let (topic, mut payload) = rest.split_at(len);
+            // a packet identifier follows the topic above QoS 0
+            if header & 0x06 != 0 {
+                payload = payload.get(2..).unwrap_or_default();
+            }
+            match core::str::from_utf8(topic) {
+                Ok(topic) => on_message(topic, payload),
+                Err(_) => debug!("MQTT topic is not UTF-8"),
+            }
+        }
+        SUBACK => {
+            if body.iter().skip(2).any(|&code| code == 0x80) {
+                warn!("MQTT broker refused a subscription");
+            }
+        }
+        PINGRESP => {}
+        kind => debug!("unexpected MQTT packet {:#x}", kind),
+    }
+}
@@ -0,0 +1,211 @@
+//! `multipart/form-data` bodies, for uploading files such as logs, sensor
+//! CSVs or screenshots with [crate::http::Request::multipart].
+//!
+//! A file part reads its [Source] [CHUNK] bytes at a time while the body is
+//! sent, so an upload only needs one chunk of RAM whatever the file's size.
+//! The client speaks HTTP/1.0 and can't send a body chunked, so the length
+//! of the whole body is worked out beforehand for `Content-Length`; a source
+//! has to be as long as it said. A request sent again after a network error
+//! reads its sources again from the start.
+//!
+//! Like [crate::http_parser], this needs nothing from the chip and is also
+//! built for the host by `tools/http_conformance`.
+
+use alloc::{format, string::String, vec::Vec};
+use core::fmt;
+
+use crate::Error;
+
+/// File bytes read from a [Source] per write to the socket
+pub const CHUNK: usize = 512;
+
+/// Where a file part's bytes come from, such as a blob in a store
+pub trait Source {
+    fn len(&self) -> u32;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Reads from `offset`, returning how many bytes were read
+    fn read(&self, offset: u32, buf: &mut [u8]) -> Result<usize, Error>;
+}
+
+#[derive(Clone, Copy)]
+enum Data<'r> {
+    Bytes(&'r [u8]),
+    Stream(&'r dyn Source),
+}
+
+impl Data<'_> {
+    fn len(&self) -> usize {
+        match self {
+            Data::Bytes(bytes) => bytes.len(),
+            Data::Stream(source) => source.len() as usize,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Part<'r> {
+    name: &'r str,
+    /// Set for a file, with its content type
+    file: Option<(&'r str, &'r str)>,
+    data: Data<'r>,
+}
+
+/// A form body of text fields and files, in the order they are added:
+///
+/// ```ignore
+/// let log = Blob::open(&*store, "EVENTS.CSV")?;
+/// let form = http::multipart()
+///     .text("badge", "lobby")
+///     .file("log", "events.csv", "text/csv", &log);
+/// Request::multipart("http://collector.local/upload", &form).send(stack)?;
+/// ```
+#[derive(Clone)]
+pub struct Multipart<'r> {
+    boundary: String,
+    content_type: String,
+    parts: Vec<Part<'r>>,
+}
+
+impl<'r> Multipart<'r> {
+    /// A form whose parts are separated by `boundary`, which mustn't appear
+    /// in any of them; [crate::http::multipart] picks a random one
+    pub fn with_boundary(boundary: &str) -> Self {
+        Self {
+            boundary: String::from(boundary),
+            content_type: format!("multipart/form-data; boundary={}", boundary),
+            parts: Vec::new(),
+        }
+    }
+
+    pub fn text(mut self, name: &'r str, value: &'r str) -> Self {
+        self.parts.push(Part {
+            name,
+            file: None,
+            data: Data::Bytes(value.as_bytes()),
+        });
+        self
+    }
+
+    /// A file already in memory
+    pub fn bytes(
+        mut self,
+        name: &'r str,
+        filename: &'r str,
+        content_type: &'r str,
+        data: &'r [u8],
+    ) -> Self {
+        self.parts.push(Part {
+            name,
+            file: Some((filename, content_type)),
+            data: Data::Bytes(data),
+        });
+        self
+    }
+
+    /// A file streamed from `source` as the body is sent
+    pub fn file(
+        mut self,
+        name: &'r str,
+        filename: &'r str,
+        content_type: &'r str,
+        source: &'r dyn Source,
+    ) -> Self {
+        self.parts.push(Part {
+            name,
+            file: Some((filename, content_type)),
+            data: Data::Stream(source),
+        });
+        self
+    }
+
+    /// The `Content-Type` the body goes with
+    pub fn content_type(&self) -> &str {
+        &self.content_type
+    }
+
+    /// The length of the whole body
+    pub fn len(&self) -> usize {
+        let parts: usize = self
+            .parts
+            .iter()
+            .map(|part| self.part_head(part).len() + part.data.len() + 2)
+            .sum();
+        parts + self.closing().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.parts.is_empty()
+    }
+
+    /// Whether the names, file names and content types can go in the part
+    /// headers as they are, without a quote or line break
+    pub fn is_valid(&self) -> bool {
+        let plain = |s: &str| !s.contains(['"', '\r', '\n']);
+        self.parts.iter().all(|part| {
+            plain(part.name)
+                && part
+                    .file
+                    .is_none_or(|(filename, content_type)| plain(filename) && plain(content_type))
+        })
+    }
+
+    /// Passes the body to `write` a piece at a time; [Error::Storage] if a
+    /// source ends before its length
+    pub fn write_to(&self, write: &mut dyn FnMut(&[u8]) -> Result<(), Error>) -> Result<(), Error> {
+        let mut chunk = [0u8; CHUNK];
+        for part in &self.parts {
+            write(self.part_head(part).as_bytes())?;
+            match part.data {
+                Data::Bytes(bytes) => write(bytes)?,
+                Data::Stream(source) => {
+                    let len = source.len();
+                    let mut offset = 0;
+                    while offset < len {
+                        let want = CHUNK.min((len - offset) as usize);
+                        let read = source.read(offset, &mut chunk[..want])?;
+                        if read == 0 {
+                            return Err(Error::Storage);
+                        }
+                        write(&chunk[..read])?;
+                        offset += read as u32;
+                    }
+                }
+            }
+            write(b"\r\n")?;
+        }
+        write(self.closing().as_bytes())
+    }
+
+    fn part_head(&self, part: &Part<'_>) -> String {
+        let mut head = format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"{}\"",
+            self.boundary, part.name
+        );
+        if let Some((filename, content_type)) = part.file {
+            head += &format!(
+                "; filename=\"{}\"\r\nContent-Type: {}",
+                filename, content_type
+            );
+        }
+        head += "\r\n\r\n";
+        head
+    }
+
+    fn closing(&self) -> String {
+        format!("--{}--\r\n", self.boundary)
+    }
+}
+
+impl fmt::Debug for Multipart<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Multipart")
+            .field("boundary", &self.boundary)
+            .field("parts", &self.parts.len())
+            .field("len", &self.len())
+            .finish()
+    }
+}
@@ -0,0 +1,207 @@
+//! The NeoPixels, the four along the MagTag's top edge or however many the
+//! [board](crate::board) has, driven through the RMT peripheral.
+//!
+//! What gets shown is the configured brightness passed through a
+//! [BrightnessPolicy], by default [ambient], which dims the pixels along with
+//! the room and turns them off in the dark. The light level is only sampled
+//! in [adapt], which the app host calls every few seconds.
+
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+use esp_hal::{
+    delay::Delay,
+    gpio::Level,
+    peripherals::RMT,
+    rmt::{Channel, PulseCode, Rmt, Tx, TxChannelConfig, TxChannelCreator},
+    time::Rate,
+    Blocking,
+};
+use log::{debug, warn};
+
+use crate::{
+    analog, board,
+    power::{self, Rail},
+};
+
+pub const COUNT: usize = board::NEOPIXEL_COUNT;
+
+/// Light sensor reading below which [ambient] turns the pixels off
+pub const DARK_MV: u32 = 20;
+/// Light sensor reading from which [ambient] uses the full configured brightness
+pub const BRIGHT_MV: u32 = 400;
+
+// WS2812 bit timings in ticks of the 80 MHz RMT clock
+const T0H: u16 = 32;
+const T0L: u16 = 68;
+const T1H: u16 = 64;
+const T1L: u16 = 36;
+
+/// A pixel color at full brightness
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub const OFF: Self = Self::new(0, 0, 0);
+    pub const RED: Self = Self::new(255, 0, 0);
+    pub const GREEN: Self = Self::new(0, 255, 0);
+    pub const BLUE: Self = Self::new(0, 0, 255);
+    pub const WHITE: Self = Self::new(255, 255, 255);
+
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+}
+
+/// Picks the brightness to show, in percent, from the configured brightness
+/// and the light sensor reading in millivolts
+pub type BrightnessPolicy = fn(configured: u8, light_mv: Option<u32>) -> u8;
+
+/// Off in the dark, and from a tenth of `configured` in dim light up to all
+/// of it in a bright room
+pub fn ambient(configured: u8, light_mv: Option<u32>) -> u8 {
+    let Some(light) = light_mv else {
+        return configured;
+    };
+    if light < DARK_MV || configured == 0 {
+        return 0;
+    }
+    let light = light.min(BRIGHT_MV);
+    let scale = 10 + 90 * (light - DARK_MV) / (BRIGHT_MV - DARK_MV);
+    (configured as u32 * scale / 100).max(1) as u8
+}
+
+/// Ignores the light sensor
+pub fn fixed(configured: u8, _light_mv: Option<u32>) -> u8 {
+    configured
+}
+
+struct Pixels {
+    /// `None` while a transmission has it, or after one failed
+    channel: Option<Channel<'static, Blocking, Tx>>,
+    colors: [Rgb; COUNT],
+    brightness: u8,
+    policy: BrightnessPolicy,
+}
+
+static PIXELS: Mutex<RefCell<Option<Pixels>>> = Mutex::new(RefCell::new(None));
+
+/// Takes the RMT peripheral and the data pin (GPIO1 on the MagTag); the pixels are powered
+/// through [Rail::NeoPixel] and start off. Call once at boot, after
+/// [power::init].
+pub fn init(rmt: RMT<'static>, data: board::NeoPixelData) {
+    let config = TxChannelConfig::default()
+        .with_clk_divider(1)
+        .with_idle_output_level(Level::Low)
+        .with_idle_output(true)
+        .with_carrier_modulation(false);
+    let channel = match Rmt::new(rmt, Rate::from_mhz(80))
+        .and_then(|rmt| rmt.channel0.configure_tx(data, config))
+    {
+        Ok(channel) => channel,
+        Err(err) => {
+            warn!("NeoPixels unavailable: {:?}", err);
+            return;
+        }
+    };
+    critical_section::with(|cs| {
+        PIXELS.borrow_ref_mut(cs).replace(Pixels {
+            channel: Some(channel),
+            colors: [Rgb::OFF; COUNT],
+            brightness: 0,
+            policy: ambient,
+        })
+    });
+}
+
+/// Replaces the policy, [ambient] by default
+pub fn set_policy(policy: BrightnessPolicy) {
+    with(|pixels| pixels.policy = policy);
+}
+
+/// Sets one pixel, counted from the left; takes effect on [show]
+pub fn set(index: usize, color: Rgb) {
+    with(|pixels| {
+        if let Some(slot) = pixels.colors.get_mut(index) {
+            *slot = color;
+        }
+    });
+}
+
+/// Sets every pixel; takes effect on [show]
+pub fn fill(color: Rgb) {
+    with(|pixels| pixels.colors = [color; COUNT]);
+}
+
+/// Sends the colors to the pixels at the current brightness
+pub fn show() {
+    with(Pixels::show);
+}
+
+/// Applies the policy to `configured` percent and the current light level,
+/// updating the pixels if the result changed
+pub fn adapt(configured: u8) {
+    let light = analog::light_millivolts();
+    with(|pixels| {
+        let brightness = (pixels.policy)(configured, light).min(100);
+        if brightness != pixels.brightness {
+            debug!("NeoPixel brightness {}% at {:?} mV", brightness, light);
+            pixels.brightness = brightness;
+            pixels.show();
+        }
+    });
+}
+
+impl Pixels {
+    fn show(&mut self) {
+        let rail = power::rail(Rail::NeoPixel);
+        if self.brightness == 0 || self.colors.iter().all(|c| *c == Rgb::OFF) {
+            rail.off();
+            return;
+        }
+        if !rail.is_on() {
+            rail.on();
+            // give the pixels a moment to power up before the data arrives
+            Delay::new().delay_micros(500);
+        }
+
+        let mut codes = [PulseCode::end_marker(); COUNT * 24 + 1];
+        let scale = |v: u8| (v as u32 * self.brightness as u32 / 100) as u8;
+        let bytes = self
+            .colors
+            .iter()
+            .flat_map(|c| [scale(c.g), scale(c.r), scale(c.b)]);
+        for (i, byte) in bytes.enumerate() {
+            for bit in 0..8 {
+                codes[i * 8 + bit] = if byte & (0x80 >> bit) != 0 {
+                    PulseCode::new(Level::High, T1H, Level::Low, T1L)
+                } else {
+                    PulseCode::new(Level::High, T0H, Level::Low, T0L)
+                };
+            }
+        }
+
+        let Some(channel) = self.channel.take() else {
+            return;
+        };
+        self.channel = match channel.transmit(&codes).map(|tx| tx.wait()) {
+            Ok(Ok(channel)) => Some(channel),
+            Ok(Err((err, channel))) => {
+                warn!("NeoPixel update failed: {:?}", err);
+                Some(channel)
+            }
+            Err(err) => {
+                warn!("NeoPixel update failed: {:?}", err);
+                None
+            }
+        };
+    }
+}
+
+fn with<R>(f: impl FnOnce(&mut Pixels) -> R) -> Option<R> {
+    critical_section::with(|cs| PIXELS.borrow_ref_mut(cs).as_mut().map(f))
+}
@@ -0,0 +1,143 @@
+//! Network stack type and helpers for short-lived sockets.
+//!
+//! Sockets from [with_tcp_socket] give up on a peer that leaves a connect or
+//! sent data unanswered for [ACK_TIMEOUT]. Connections that stay open add
+//! [KeepAlive] probes, which keep NAT mappings up and notice a peer that
+//! went away, and can bound blocking reads with
+//! [TcpSocket::set_read_timeout].
+
+use alloc::vec;
+use core::time::Duration;
+
+use blocking_network_stack::{Socket, Stack};
+use esp_radio::wifi::WifiDevice;
+use log::{debug, warn};
+use smoltcp::wire::{DnsQueryType, IpAddress};
+
+use crate::{net_health, Error};
+
+/// Network stack shared by all apps
+pub type NetStack<'a> = Stack<'a, WifiDevice<'a>>;
+
+pub type TcpSocket<'s, 'a> = Socket<'s, 'a, WifiDevice<'a>>;
+
+/// Looks up the first IPv4 address of `host`
+pub fn resolve(stack: &NetStack<'_>, host: &str) -> Result<IpAddress, Error> {
+    let addr = match stack.dns_query(host, DnsQueryType::A) {
+        Ok(addrs) => addrs.first().copied().ok_or(Error::Network),
+        Err(err) => {
+            warn!("resolving {} failed: {:?}", host, err);
+            Err(Error::Network)
+        }
+    };
+    net_health::record_dns(addr.is_ok());
+    addr
+}
+
+/// How long a connect or sent data may go unacknowledged before the
+/// connection is dropped
+pub const ACK_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Keep-alive probing for connections that stay open, such as a subscription
+/// or a WebSocket
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeepAlive {
+    /// Probe after this long without traffic
+    pub interval: Duration,
+    /// Drop the connection when probes go unanswered this long
+    pub timeout: Duration,
+}
+
+impl KeepAlive {
+    /// Well inside the couple of minutes home routers keep an idle TCP
+    /// mapping, and a dead peer noticed within two minutes
+    pub const LONG_LIVED: KeepAlive = KeepAlive {
+        interval: Duration::from_secs(45),
+        timeout: Duration::from_secs(120),
+    };
+
+    pub fn apply(&self, socket: &mut TcpSocket<'_, '_>) {
+        socket.set_keep_alive(Some(self.interval));
+        socket.set_timeout(Some(self.timeout));
+    }
+}
+
+/// Runs `f` with a TCP socket over buffers that only need to outlive the call
+///
+/// [NetStack::get_socket] wants buffers that live as long as the stack, which
+/// code running inside the app host can't provide.
+pub fn with_tcp_socket<'a, R>(
+    stack: &NetStack<'a>,
+    rx_buffer: &mut [u8],
+    tx_buffer: &mut [u8],
+    f: impl FnOnce(&mut TcpSocket<'_, 'a>) -> R,
+) -> R {
+    // SAFETY: the socket removes itself from the stack's socket set when it is
+    // dropped at the end of this function, and `f` only gets a reference so it
+    // can't keep the socket alive past that. The buffers are borrowed for the
+    // whole call.
+    let (rx_buffer, tx_buffer) = unsafe { (extend(rx_buffer), extend(tx_buffer)) };
+    let mut socket = stack.get_socket(rx_buffer, tx_buffer);
+    socket.set_timeout(Some(ACK_TIMEOUT));
+    f(&mut socket)
+}
+
+/// Receive and send buffer sizes of a TCP socket, in bytes
+///
+/// The receive buffer is the TCP window: the server can't send more than
+/// that before hearing back, so a download moves at most one buffer per
+/// round trip. At 20-30 ms to a server on the internet, 1.5 KiB makes
+/// around 60 KiB/s and [BufferSizes::HTTP] a few times that. The send buffer
+/// only needs to hold what goes out in one go.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BufferSizes {
+    pub rx: usize,
+    pub tx: usize,
+}
+
+impl BufferSizes {
+    /// Four full-size segments in flight for downloads
+    pub const HTTP: BufferSizes = BufferSizes {
+        rx: 4 * 1460,
+        tx: 1024,
+    };
+    /// Publishing: next to nothing comes back
+    pub const MQTT: BufferSizes = BufferSizes { rx: 256, tx: 1024 };
+    /// Smallest receive buffer worth shrinking to, one segment
+    pub const MIN_RX: usize = 1460;
+}
+
+/// Heap to leave for everything else when sizing buffers
+const HEAP_RESERVE: usize = 16 * 1024;
+
+/// [with_tcp_socket] over buffers of `sizes` taken from the heap. When the
+/// heap is short the receive buffer is halved down to [BufferSizes::MIN_RX],
+/// which costs speed but not correctness.
+pub fn with_tcp_socket_sized<'a, R>(
+    stack: &NetStack<'a>,
+    sizes: BufferSizes,
+    f: impl FnOnce(&mut TcpSocket<'_, 'a>) -> R,
+) -> R {
+    let free = esp_alloc::HEAP.free();
+    let mut rx = sizes.rx;
+    while rx > BufferSizes::MIN_RX && rx + sizes.tx + HEAP_RESERVE > free {
+        rx = (rx / 2).max(BufferSizes::MIN_RX);
+    }
+    if rx != sizes.rx {
+        debug!(
+            "{} bytes of heap free, socket rx buffer cut to {}",
+            free, rx
+        );
+    }
+    let mut rx_buffer = vec![0u8; rx];
+    let mut tx_buffer = vec![0u8; sizes.tx];
+    with_tcp_socket(stack, &mut rx_buffer, &mut tx_buffer, f)
+}
+
+/// # Safety
+///
+/// The caller has to make sure nothing uses the returned reference after `buf`
+/// is released.
+unsafe fn extend<'a, T>(buf: &mut [T]) -> &'a mut [T] {
+    unsafe { &mut *(buf as *mut [T]) }
+}
@@ -0,0 +1,137 @@
+//! Watchdog on the network connection.
+//!
+//! The [Monitor] looks at the link every few seconds. If the station has
+//! been off the access point or without a DHCP lease for [DOWN_TIMEOUT], or
+//! DNS lookups keep failing while the link looks fine, it restarts WiFi and
+//! the network interface with [wifi::reconnect]. Recoveries that don't help
+//! are spaced out further and further. Apps get a [Link] event whenever the
+//! state changes.
+
+use core::cell::Cell;
+
+use critical_section::Mutex;
+use esp_hal::time::{Duration, Instant};
+use log::{info, warn};
+
+use crate::{hal::Network, net::NetStack, wifi};
+
+/// How long the link may stay down before WiFi is restarted
+pub const DOWN_TIMEOUT: Duration = Duration::from_secs(60);
+/// Failed lookups in a row that count as a broken network
+pub const MAX_DNS_FAILURES: u32 = 6;
+/// Wait after a recovery before the next one, doubling while they don't
+/// help, up to [MAX_RECOVERY_BACKOFF]
+const RECOVERY_BACKOFF: Duration = Duration::from_secs(60);
+const MAX_RECOVERY_BACKOFF: Duration = Duration::from_secs(15 * 60);
+
+/// A change in the network connection, delivered to apps as
+/// [crate::input::Event::Link]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Link {
+    /// The connection dropped or stopped working
+    Lost,
+    /// WiFi is about to be restarted, which blocks for a while
+    Recovering,
+    /// Working again
+    Restored,
+}
+
+static DNS_FAILURES: Mutex<Cell<u32>> = Mutex::new(Cell::new(0));
+static RECOVERIES: Mutex<Cell<u32>> = Mutex::new(Cell::new(0));
+
+/// Counts a lookup towards [MAX_DNS_FAILURES]; a success starts over
+pub(crate) fn record_dns(ok: bool) {
+    critical_section::with(|cs| {
+        let failures = DNS_FAILURES.borrow(cs);
+        failures.set(if ok { 0 } else { failures.get() + 1 });
+    });
+}
+
+fn dns_failures() -> u32 {
+    critical_section::with(|cs| DNS_FAILURES.borrow(cs).get())
+}
+
+/// WiFi restarts since boot
+pub fn recoveries() -> u32 {
+    critical_section::with(|cs| RECOVERIES.borrow(cs).get())
+}
+
+/// Decides when the connection needs a restart, see the [module docs](self)
+pub struct Monitor {
+    healthy: bool,
+    down_since: Option<Instant>,
+    /// No recovery before this
+    next_recovery: Instant,
+    backoff: Duration,
+}
+
+impl Default for Monitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Monitor {
+    pub fn new() -> Self {
+        Self {
+            healthy: true,
+            down_since: None,
+            next_recovery: Instant::now(),
+            backoff: RECOVERY_BACKOFF,
+        }
+    }
+
+    /// Looks at the link; returns an event when its state changed.
+    /// [Link::Recovering] means [Monitor::recover] should be called next.
+    pub fn check(&mut self, net: &impl Network, now: Instant) -> Option<Link> {
+        let up = net.link_up();
+        let dns_broken = dns_failures() >= MAX_DNS_FAILURES;
+
+        if up && !dns_broken {
+            self.down_since = None;
+            self.backoff = RECOVERY_BACKOFF;
+            return (!core::mem::replace(&mut self.healthy, true)).then_some(Link::Restored);
+        }
+
+        let down_since = *self.down_since.get_or_insert(now);
+        let lost = core::mem::replace(&mut self.healthy, false);
+        let due = dns_broken || now - down_since >= DOWN_TIMEOUT;
+        if due && now >= self.next_recovery {
+            Some(Link::Recovering)
+        } else if lost {
+            warn!(
+                "Network lost ({})",
+                if up { "DNS failing" } else { "link down" }
+            );
+            Some(Link::Lost)
+        } else {
+            None
+        }
+    }
+
+    /// Restarts WiFi and the interface. Blocks for up to
+    /// [wifi::JOIN_TIMEOUT].
+    pub fn recover(&mut self, net: &NetStack<'_>) {
+        critical_section::with(|cs| {
+            let recoveries = RECOVERIES.borrow(cs);
+            recoveries.set(recoveries.get() + 1);
+        });
+        record_dns(true);
+        let result = wifi::reconnect(net);
+        // even a restart that worked gets some time before the next one
+        self.next_recovery = Instant::now() + self.backoff;
+        match result {
+            Ok(()) => info!("Network recovered"),
+            Err(err) => {
+                warn!(
+                    "Network recovery failed: {}, next try in {} s",
+                    err,
+                    self.backoff.as_secs()
+                );
+                self.backoff =
+                    Duration::from_secs(self.backoff.as_secs() * 2).min(MAX_RECOVERY_BACKOFF);
+            }
+        }
+        self.down_since = None;
+    }
+}
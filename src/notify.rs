@@ -0,0 +1,294 @@
+//! Feedback for things the user should know about, the same way in every app.
+//!
+//! Each [Notice] has an [Alert]: a NeoPixel pattern, a melody and a status
+//! icon in the top right corner of the panel, any of which can be left out.
+//! The defaults can be replaced per notice under `notify_<notice>`, e.g.
+//!
+//! ```text
+//! set config notify_new_message pixels=blink:green melody=default icon
+//! set config notify_low_battery off
+//! ```
+//!
+//! `pixels` takes `flash`, `blink` or `steady` and a color (`red`, `green`,
+//! `blue`, `white` or `yellow`). `melody` takes an RTTTL tune, or `default`
+//! for the one under the `melody` key. `icon` keeps the icon up until the
+//! notice is cleared, as do `steady` pixels.
+
+use alloc::{format, string::String};
+use core::{cell::RefCell, str::FromStr};
+
+use critical_section::Mutex;
+use embedded_graphics::prelude::*;
+use esp_hal::delay::Delay;
+use log::{info, warn};
+
+use crate::{
+    config::ConfigStore,
+    display::{self, Frame},
+    neopixel::{self, Rgb},
+    rtttl::{self, Melody},
+    ui::icon::Icon,
+    Error,
+};
+
+/// Something worth telling the user about
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Notice {
+    /// A fetch or publish didn't get through
+    FetchFailed,
+    LowBattery,
+    NewMessage,
+}
+
+impl Notice {
+    pub const ALL: [Notice; 3] = [Notice::FetchFailed, Notice::LowBattery, Notice::NewMessage];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Notice::FetchFailed => "fetch_failed",
+            Notice::LowBattery => "low_battery",
+            Notice::NewMessage => "new_message",
+        }
+    }
+
+    /// The config key overriding [Notice::default_alert]
+    pub fn key(self) -> String {
+        format!("notify_{}", self.name())
+    }
+
+    pub fn default_alert(self) -> Alert {
+        let pixels = |pattern, color| Some(Pixels { pattern, color });
+        match self {
+            Notice::FetchFailed => Alert {
+                pixels: pixels(Pattern::Flash, Rgb::RED),
+                melody: None,
+                icon: true,
+            },
+            Notice::LowBattery => Alert {
+                pixels: pixels(Pattern::Blink, YELLOW),
+                melody: None,
+                icon: true,
+            },
+            Notice::NewMessage => Alert {
+                pixels: pixels(Pattern::Blink, Rgb::BLUE),
+                melody: Some(Tune::Default),
+                icon: true,
+            },
+        }
+    }
+
+    fn icon(self) -> Icon {
+        match self {
+            Notice::FetchFailed => Icon::Warning,
+            Notice::LowBattery => Icon::Battery,
+            Notice::NewMessage => Icon::Mail,
+        }
+    }
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+const YELLOW: Rgb = Rgb::new(255, 160, 0);
+const BLINKS: u32 = 3;
+const BLINK_MS: u32 = 150;
+const FLASH_MS: u32 = 400;
+const ICON_SPACING: i32 = 14;
+
+/// How the NeoPixels show an alert
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pattern {
+    /// On once, briefly
+    Flash,
+    /// A few quick blinks
+    Blink,
+    /// On until the notice is cleared
+    Steady,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pixels {
+    pub pattern: Pattern,
+    pub color: Rgb,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Tune {
+    /// The melody under the `melody` key
+    Default,
+    Rtttl(String),
+}
+
+/// What happens when a [Notice] is raised
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Alert {
+    pub pixels: Option<Pixels>,
+    pub melody: Option<Tune>,
+    /// Show the notice's icon until it is cleared
+    pub icon: bool,
+}
+
+impl Alert {
+    /// The alert for `notice` from the config, or its default
+    pub fn load(config: &ConfigStore, notice: Notice) -> Self {
+        let key = notice.key();
+        match config.get(&key).map(str::parse) {
+            Some(Ok(alert)) => alert,
+            Some(Err(err)) => {
+                warn!("{}: {}, using the default", key, err);
+                notice.default_alert()
+            }
+            None => notice.default_alert(),
+        }
+    }
+}
+
+impl FromStr for Alert {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut alert = Alert {
+            pixels: None,
+            melody: None,
+            icon: false,
+        };
+        for part in s.split_whitespace() {
+            match part.split_once('=') {
+                None if part == "off" => {}
+                None if part == "icon" => alert.icon = true,
+                Some(("pixels", value)) => {
+                    let (pattern, color) = value.split_once(':').ok_or(Error::InvalidConfig)?;
+                    let pattern = match pattern {
+                        "flash" => Pattern::Flash,
+                        "blink" => Pattern::Blink,
+                        "steady" => Pattern::Steady,
+                        _ => return Err(Error::InvalidConfig),
+                    };
+                    let color = match color {
+                        "red" => Rgb::RED,
+                        "green" => Rgb::GREEN,
+                        "blue" => Rgb::BLUE,
+                        "white" => Rgb::WHITE,
+                        "yellow" => YELLOW,
+                        _ => return Err(Error::InvalidConfig),
+                    };
+                    alert.pixels = Some(Pixels { pattern, color });
+                }
+                Some(("melody", "default")) => alert.melody = Some(Tune::Default),
+                Some(("melody", tune)) => {
+                    // fail here rather than each time it plays
+                    tune.parse::<Melody>()?;
+                    alert.melody = Some(Tune::Rtttl(String::from(tune)));
+                }
+                _ => return Err(Error::InvalidConfig),
+            }
+        }
+        Ok(alert)
+    }
+}
+
+struct Notices {
+    /// Notices with their icon up, one bit each
+    active: u8,
+    /// Ditto with steady pixels
+    lit: u8,
+    /// Set when the icons changed since [take_changed]
+    changed: bool,
+}
+
+static NOTICES: Mutex<RefCell<Notices>> = Mutex::new(RefCell::new(Notices {
+    active: 0,
+    lit: 0,
+    changed: false,
+}));
+
+/// Plays the alert configured for `notice`, blocking while the pixels blink
+/// and the melody plays
+pub fn raise(config: &ConfigStore, notice: Notice) {
+    let alert = Alert::load(config, notice);
+    info!("Notice: {}", notice.name());
+    let delay = Delay::new();
+    if let Some(Pixels { pattern, color }) = alert.pixels {
+        match pattern {
+            Pattern::Flash => {
+                light(color);
+                delay.delay_millis(FLASH_MS);
+                light(Rgb::OFF);
+            }
+            Pattern::Blink => {
+                for _ in 0..BLINKS {
+                    light(color);
+                    delay.delay_millis(BLINK_MS);
+                    light(Rgb::OFF);
+                    delay.delay_millis(BLINK_MS);
+                }
+            }
+            Pattern::Steady => light(color),
+        }
+    }
+    match alert.melody {
+        Some(Tune::Default) => rtttl::configured(config).play(),
+        Some(Tune::Rtttl(tune)) => match tune.parse::<Melody>() {
+            Ok(melody) => melody.play(),
+            Err(err) => warn!("{}: {}", notice.key(), err),
+        },
+        None => {}
+    }
+
+    let steady = matches!(alert.pixels, Some(p) if p.pattern == Pattern::Steady);
+    critical_section::with(|cs| {
+        let mut notices = NOTICES.borrow_ref_mut(cs);
+        if steady {
+            notices.lit |= notice.bit();
+        }
+        if alert.icon && notices.active & notice.bit() == 0 {
+            notices.active |= notice.bit();
+            notices.changed = true;
+        }
+    });
+}
+
+/// Takes down the icon and steady pixels of `notice`, if it was raised
+pub fn clear(notice: Notice) {
+    let unlit = critical_section::with(|cs| {
+        let mut notices = NOTICES.borrow_ref_mut(cs);
+        if notices.active & notice.bit() != 0 {
+            notices.active &= !notice.bit();
+            notices.changed = true;
+        }
+        let was_lit = notices.lit & notice.bit() != 0;
+        notices.lit &= !notice.bit();
+        was_lit && notices.lit == 0
+    });
+    if unlit {
+        light(Rgb::OFF);
+    }
+}
+
+pub fn is_active(notice: Notice) -> bool {
+    critical_section::with(|cs| NOTICES.borrow_ref(cs).active & notice.bit() != 0)
+}
+
+/// Whether icons came or went since the last call, so the host knows to
+/// redraw
+pub fn take_changed() -> bool {
+    critical_section::with(|cs| core::mem::take(&mut NOTICES.borrow_ref_mut(cs).changed))
+}
+
+/// Draws the icons of the active notices right to left from the top right
+/// corner, on white so they stand out from whatever the app drew there.
+/// Returns the x of the next slot to the left.
+pub fn draw_icons(frame: &mut Frame) -> i32 {
+    let mut x = display::WIDTH as i32 - ICON_SPACING;
+    for notice in Notice::ALL.into_iter().filter(|n| is_active(*n)) {
+        notice.icon().draw_boxed(frame, Point::new(x, 1));
+        x -= ICON_SPACING;
+    }
+    x
+}
+
+fn light(color: Rgb) {
+    neopixel::fill(color);
+    neopixel::show();
+}
@@ -0,0 +1,102 @@
+//! The partition table as flashed, checked against the one this build expects.
+//!
+//! `build.rs` validates `partitions.csv`, or `partitions_ota.csv` with the
+//! `ota` feature, and records it here. Flashing with a different table than
+//! the build was made for shows up as a warning from [check] at boot.
+
+use alloc::{string::String, vec::Vec};
+use core::fmt;
+
+use esp_bootloader_esp_idf::partitions::PartitionType;
+use log::{info, warn};
+
+use crate::{flash, Error};
+
+/// A partition this build expects
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Expected {
+    pub label: &'static str,
+    pub offset: u32,
+    pub len: u32,
+}
+
+include!(concat!(env!("OUT_DIR"), "/partitions.rs"));
+
+/// One entry of the flashed table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartitionInfo {
+    pub label: String,
+    pub kind: PartitionType,
+    pub offset: u32,
+    pub len: u32,
+    /// Whether the running firmware was loaded from it
+    pub booted: bool,
+}
+
+impl fmt::Display for PartitionInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:<10} {:#08x} {:>5} KiB {:?}{}",
+            self.label,
+            self.offset,
+            self.len / 1024,
+            self.kind,
+            if self.booted { " (booted)" } else { "" }
+        )
+    }
+}
+
+/// The partitions on flash, in table order
+pub fn info() -> Result<Vec<PartitionInfo>, Error> {
+    flash::with_partition_table(|table| {
+        let booted = table.booted_partition().ok().flatten().map(|p| p.offset());
+        table
+            .iter()
+            .map(|entry| PartitionInfo {
+                label: entry.label_as_str().into(),
+                kind: entry.partition_type(),
+                offset: entry.offset(),
+                len: entry.len(),
+                booted: booted == Some(entry.offset()),
+            })
+            .collect()
+    })
+}
+
+/// Logs the table and warns about partitions that differ from [EXPECTED].
+/// Returns whether they all match.
+pub fn check() -> bool {
+    let flashed = match info() {
+        Ok(flashed) => flashed,
+        Err(err) => {
+            warn!("Can't read the partition table: {}", err);
+            return false;
+        }
+    };
+    for partition in &flashed {
+        info!("  {}", partition);
+    }
+
+    let mut matches = true;
+    for expected in EXPECTED {
+        match flashed.iter().find(|p| p.label == expected.label) {
+            Some(p) if p.offset == expected.offset && p.len == expected.len => {}
+            Some(p) => {
+                warn!(
+                    "Partition {} is at {:#x}+{:#x}, {} has it at {:#x}+{:#x}",
+                    p.label, p.offset, p.len, TABLE_FILE, expected.offset, expected.len
+                );
+                matches = false;
+            }
+            None => {
+                warn!(
+                    "Partition {} from {} is missing; reflash with --partition-table {}",
+                    expected.label, TABLE_FILE, TABLE_FILE
+                );
+                matches = false;
+            }
+        }
+    }
+    matches
+}
@@ -0,0 +1,194 @@
+//! Step counting from the accelerometer, with a daily total.
+//!
+//! Each step shows up as a peak in the magnitude of the acceleration. The
+//! [Pedometer] smooths the magnitude, follows its range over the last couple
+//! of seconds and counts a step whenever it falls back through the middle of
+//! that range, as long as the swing is big enough to be walking and the
+//! steps come at a human pace.
+//!
+//! The total for today is kept in RTC fast memory, so it carries over deep
+//! sleep but not a power-on. It starts again from zero once the local date
+//! changes; without the wall-clock time it keeps adding to the last day.
+
+use esp_hal::ram;
+use jiff::civil::Date;
+
+use crate::{accel::Acceleration, bindings, crc::crc32, time};
+
+const MAGIC: u32 = u32::from_le_bytes(*b"MTST");
+/// Peak-to-peak swing, in mg, below which the badge counts as still
+const MIN_SWING_MG: i32 = 250;
+/// Fastest pace counted, about five steps a second when running
+const MIN_STEP_MS: u32 = 200;
+/// Range tracking window; also the slowest pace counted
+const WINDOW_MS: u32 = 2000;
+
+/// Counts steps in a stream of samples taken at a fixed rate
+pub struct Pedometer {
+    rate_hz: u32,
+    /// Magnitude, averaged over the last four samples
+    smoothed: i32,
+    history: [i32; 4],
+    next: usize,
+    /// Range within the current window
+    window_min: i32,
+    window_max: i32,
+    window_samples: u32,
+    /// Middle and size of the range over the last full window
+    threshold: i32,
+    swing: i32,
+    above: bool,
+    /// Samples since the last step
+    since_step: u32,
+}
+
+impl Pedometer {
+    pub fn new(rate_hz: u32) -> Self {
+        Self {
+            rate_hz,
+            smoothed: 1000,
+            history: [1000; 4],
+            next: 0,
+            window_min: i32::MAX,
+            window_max: i32::MIN,
+            window_samples: 0,
+            threshold: 1000,
+            swing: 0,
+            above: false,
+            since_step: u32::MAX,
+        }
+    }
+
+    /// Feeds one sample and returns whether it completes a step
+    pub fn push(&mut self, sample: Acceleration) -> bool {
+        let (x, y, z) = (sample.x as i32, sample.y as i32, sample.z as i32);
+        self.history[self.next] = isqrt(x * x + y * y + z * z);
+        self.next = (self.next + 1) % self.history.len();
+        self.smoothed = self.history.iter().sum::<i32>() / self.history.len() as i32;
+        self.since_step = self.since_step.saturating_add(1);
+
+        self.window_min = self.window_min.min(self.smoothed);
+        self.window_max = self.window_max.max(self.smoothed);
+        self.window_samples += 1;
+        if self.window_samples >= self.samples(WINDOW_MS) {
+            self.threshold = (self.window_max + self.window_min) / 2;
+            self.swing = self.window_max - self.window_min;
+            self.window_min = i32::MAX;
+            self.window_max = i32::MIN;
+            self.window_samples = 0;
+        }
+
+        let was_above = self.above;
+        self.above = self.smoothed > self.threshold;
+        let step = was_above
+            && !self.above
+            && self.swing >= MIN_SWING_MG
+            && self.since_step >= self.samples(MIN_STEP_MS);
+        if step {
+            self.since_step = 0;
+        }
+        step
+    }
+
+    fn samples(&self, ms: u32) -> u32 {
+        (ms * self.rate_hz / 1000).max(1)
+    }
+}
+
+/// Integer square root by Newton's method
+fn isqrt(n: i32) -> i32 {
+    if n <= 1 {
+        return n.max(0);
+    }
+    let mut root = n;
+    let mut next = (root + n / root) / 2;
+    while next < root {
+        root = next;
+        next = (root + n / root) / 2;
+    }
+    root
+}
+
+/// Today's steps, kept in RTC fast memory
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct DailySteps {
+    magic: u32,
+    /// CRC-32 of everything after this field
+    checksum: u32,
+    /// Local date as `yyyymmdd`, 0 while the clock wasn't known
+    date: u32,
+    steps: u32,
+}
+
+// SAFETY: only integer fields, and garbage is caught by `magic` and `checksum`
+unsafe impl esp_hal::Persistable for DailySteps {}
+
+impl DailySteps {
+    const EMPTY: Self = Self {
+        magic: 0,
+        checksum: 0,
+        date: 0,
+        steps: 0,
+    };
+
+    fn compute_checksum(&self) -> u32 {
+        // SAFETY: `repr(C)` with integer fields and no padding
+        let bytes = unsafe {
+            core::slice::from_raw_parts(
+                (self as *const Self).cast::<u8>(),
+                core::mem::size_of::<Self>(),
+            )
+        };
+        crc32(&bytes[8..])
+    }
+
+    fn is_valid(&self) -> bool {
+        self.magic == MAGIC && self.checksum == self.compute_checksum()
+    }
+}
+
+#[ram(unstable(rtc_fast, persistent))]
+static mut DAILY: DailySteps = DailySteps::EMPTY;
+
+/// Adds `steps` to today's total and returns the new total
+pub fn add(steps: u32) -> u32 {
+    let mut daily = current();
+    daily.steps = daily.steps.saturating_add(steps);
+    daily.magic = MAGIC;
+    daily.checksum = daily.compute_checksum();
+    // SAFETY: single core, and only touched inside a critical section
+    critical_section::with(|_| unsafe { DAILY = daily });
+    bindings::publish("steps", daily.steps);
+    daily.steps
+}
+
+/// Steps counted today so far
+pub fn today() -> u32 {
+    current().steps
+}
+
+/// The stored record, started over if it is from an earlier day
+fn current() -> DailySteps {
+    // SAFETY: single core, and only touched inside a critical section
+    let stored = critical_section::with(|_| unsafe { DAILY });
+    let stored = if stored.is_valid() {
+        stored
+    } else {
+        DailySteps::EMPTY
+    };
+    match time::now_local().map(|now| date_key(now.date())) {
+        // the first day the clock is known continues the unknown one
+        Some(date) if stored.date == 0 => DailySteps { date, ..stored },
+        Some(date) if date != stored.date => DailySteps {
+            date,
+            steps: 0,
+            ..stored
+        },
+        _ => stored,
+    }
+}
+
+fn date_key(date: Date) -> u32 {
+    date.year() as u32 * 10_000 + date.month() as u32 * 100 + date.day() as u32
+}
@@ -0,0 +1,144 @@
+//! CPU clock scaling. The firmware spends most of its time waiting on the
+//! network, the panel's busy line or the next scheduled task, and the CPU
+//! draws noticeably less at 80 MHz than at 240 MHz while it does. Work bound
+//! by computation takes a [Boost] for full speed and drops back after.
+//!
+//! The [AppHost](crate::app::AppHost) runs at the `cpu_profile` from the
+//! config, [Profile::Low] by default, and boosts while apps render and
+//! dither; expanding compressed responses boosts too. With no TLS in
+//! [crate::http] there's no handshake to speed up. Boot, WiFi join included,
+//! runs at full speed until the host starts. Only the CPU clock changes: the
+//! APB clock the peripherals and the radio run from stays at 80 MHz at every
+//! step.
+
+use core::{cell::Cell, fmt, str::FromStr};
+
+use critical_section::Mutex;
+use esp_hal::peripherals::SYSTEM;
+use log::debug;
+
+use crate::Error;
+
+/// A CPU clock speed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Profile {
+    /// 80 MHz
+    #[default]
+    Low,
+    /// 160 MHz
+    Medium,
+    /// 240 MHz
+    Full,
+}
+
+impl Profile {
+    pub fn mhz(self) -> u32 {
+        match self {
+            Profile::Low => 80,
+            Profile::Medium => 160,
+            Profile::Full => 240,
+        }
+    }
+
+    /// The `CPUPERIOD_SEL` divider from the 480 MHz PLL
+    fn period_sel(self) -> u8 {
+        match self {
+            Profile::Low => 0,
+            Profile::Medium => 1,
+            Profile::Full => 2,
+        }
+    }
+}
+
+/// `low`, `medium` or `full`, or the speed in MHz
+impl FromStr for Profile {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "low" | "80" => Ok(Profile::Low),
+            "medium" | "160" => Ok(Profile::Medium),
+            "full" | "240" => Ok(Profile::Full),
+            _ => Err(Error::InvalidConfig),
+        }
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} MHz", self.mhz())
+    }
+}
+
+#[derive(Clone, Copy)]
+struct State {
+    /// What the clock drops back to when nothing is boosting
+    base: Profile,
+    boosts: u8,
+    current: Profile,
+}
+
+/// `esp_hal::init` sets the clock to full speed in `main`
+static STATE: Mutex<Cell<State>> = Mutex::new(Cell::new(State {
+    base: Profile::Full,
+    boosts: 0,
+    current: Profile::Full,
+}));
+
+unsafe extern "C" {
+    /// The ROM's idea of the clock, which its delay loops count with
+    fn ets_update_cpu_frequency(ticks_per_us: u32);
+}
+
+/// Sets the speed the CPU runs at outside of a [Boost]
+pub fn set_cpu_profile(profile: Profile) {
+    update(|state| state.base = profile);
+}
+
+/// The speed the CPU runs at right now
+pub fn cpu_profile() -> Profile {
+    critical_section::with(|cs| STATE.borrow(cs).get().current)
+}
+
+/// Runs the CPU at full speed until dropped
+#[must_use = "the boost ends when this is dropped"]
+pub struct Boost(());
+
+pub fn boost() -> Boost {
+    update(|state| state.boosts += 1);
+    Boost(())
+}
+
+impl Drop for Boost {
+    fn drop(&mut self) {
+        update(|state| state.boosts -= 1);
+    }
+}
+
+fn update(f: impl FnOnce(&mut State)) {
+    let changed = critical_section::with(|cs| {
+        let cell = STATE.borrow(cs);
+        let mut state = cell.get();
+        f(&mut state);
+        let wanted = if state.boosts > 0 {
+            Profile::Full
+        } else {
+            state.base
+        };
+        let changed = wanted != state.current;
+        if changed {
+            SYSTEM::regs()
+                .cpu_per_conf()
+                // SAFETY: every profile is a divider the PLL supports
+                .modify(|_, w| unsafe { w.cpuperiod_sel().bits(wanted.period_sel()) });
+            // SAFETY: a ROM function that only stores the value
+            unsafe { ets_update_cpu_frequency(wanted.mhz()) };
+            state.current = wanted;
+        }
+        cell.set(state);
+        changed.then_some(wanted)
+    });
+    if let Some(profile) = changed {
+        debug!("CPU at {}", profile);
+    }
+}
@@ -0,0 +1,241 @@
+//! Battery life estimate from the time spent in each power [State].
+//!
+//! The time per state is kept in RTC fast memory, so it carries over deep
+//! sleep and covers everything since power-on. Weighting it with typical
+//! currents for the board gives the average draw, and with the battery
+//! capacity and the charge left, a projected run time. The currents are
+//! ballpark figures from the datasheets, so the projection is too.
+
+use esp_hal::{ram, time::Duration};
+use log::debug;
+
+use crate::{crc::crc32, time};
+
+const MAGIC: u32 = u32::from_le_bytes(*b"MTPW");
+/// Capacity of the LiPo cell usually fitted to the MagTag
+pub const DEFAULT_CAPACITY_MAH: u32 = 420;
+
+/// What the board is doing, as far as the battery is concerned. Only one
+/// state applies at a time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    /// CPU running, radio off
+    Awake,
+    /// CPU running with the WiFi radio on and not power saving
+    Wifi,
+    /// CPU running with the WiFi radio napping between beacons, see
+    /// [crate::wifi::PowerSave]
+    ModemSleep,
+    /// The panel refreshing
+    Refresh,
+    LightSleep,
+    DeepSleep,
+}
+
+impl State {
+    pub const ALL: [State; 6] = [
+        State::Awake,
+        State::Wifi,
+        State::ModemSleep,
+        State::Refresh,
+        State::LightSleep,
+        State::DeepSleep,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            State::Awake => "awake",
+            State::Wifi => "wifi",
+            State::ModemSleep => "modem sleep",
+            State::Refresh => "refresh",
+            State::LightSleep => "light sleep",
+            State::DeepSleep => "deep sleep",
+        }
+    }
+
+    /// Typical draw of the whole board in this state, in microamps
+    pub fn current_ua(self) -> u32 {
+        match self {
+            State::Awake => 25_000,
+            State::Wifi => 80_000,
+            State::ModemSleep => 30_000,
+            // CPU, SPI and the panel's booster
+            State::Refresh => 32_000,
+            State::LightSleep => 1_200,
+            // the chip's 25 µA plus the regulator, sensors and divider
+            State::DeepSleep => 150,
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Time per state since power-on, kept in RTC fast memory
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct Ledger {
+    magic: u32,
+    /// CRC-32 of everything after this field
+    checksum: u32,
+    /// Index of the current state in [State::ALL]
+    state: u32,
+    _reserved: u32,
+    /// RTC time when the current state was entered, in microseconds
+    since_us: u64,
+    /// Completed time per state, in microseconds
+    totals_us: [u64; State::ALL.len()],
+}
+
+// SAFETY: only integer fields, and garbage is caught by `magic` and `checksum`
+unsafe impl esp_hal::Persistable for Ledger {}
+
+impl Ledger {
+    const EMPTY: Self = Self {
+        magic: 0,
+        checksum: 0,
+        state: 0,
+        _reserved: 0,
+        since_us: 0,
+        totals_us: [0; State::ALL.len()],
+    };
+
+    fn compute_checksum(&self) -> u32 {
+        // SAFETY: `repr(C)` with integer fields and no padding
+        let bytes = unsafe {
+            core::slice::from_raw_parts(
+                (self as *const Self).cast::<u8>(),
+                core::mem::size_of::<Self>(),
+            )
+        };
+        crc32(&bytes[8..])
+    }
+
+    fn is_valid(&self) -> bool {
+        self.magic == MAGIC
+            && (self.state as usize) < State::ALL.len()
+            && self.checksum == self.compute_checksum()
+    }
+
+    fn state(&self) -> State {
+        State::ALL[self.state as usize]
+    }
+
+    /// Totals including the time in the current state up to `now_us`
+    fn totals_at(&self, now_us: u64) -> [u64; State::ALL.len()] {
+        let mut totals = self.totals_us;
+        totals[self.state as usize] += now_us.saturating_sub(self.since_us);
+        totals
+    }
+}
+
+#[ram(unstable(rtc_fast, persistent))]
+static mut LEDGER: Ledger = Ledger::EMPTY;
+
+/// Starts timing `state` and returns the one it replaces, so a temporary
+/// state can be left again. Needs [time::init] for the RTC; does nothing
+/// before it.
+pub fn enter(state: State) -> State {
+    let Some(now_us) = rtc_us() else {
+        return state;
+    };
+    let mut ledger = load().unwrap_or(Ledger {
+        magic: MAGIC,
+        since_us: now_us,
+        ..Ledger::EMPTY
+    });
+    let previous = ledger.state();
+    if previous != state {
+        debug!("power state {} -> {}", previous.name(), state.name());
+    }
+    ledger.totals_us = ledger.totals_at(now_us);
+    ledger.state = state.index() as u32;
+    ledger.since_us = now_us;
+    ledger.checksum = ledger.compute_checksum();
+    // SAFETY: single core, and only touched inside a critical section
+    critical_section::with(|_| unsafe { LEDGER = ledger });
+    previous
+}
+
+/// The state being timed; `None` before [time::init]
+pub fn current() -> Option<State> {
+    load().map(|ledger| ledger.state())
+}
+
+/// Time spent in each state of [State::ALL] since power-on
+pub fn totals() -> [Duration; State::ALL.len()] {
+    let totals = match (load(), rtc_us()) {
+        (Some(ledger), Some(now_us)) => ledger.totals_at(now_us),
+        _ => [0; State::ALL.len()],
+    };
+    totals.map(Duration::from_micros)
+}
+
+/// Average draw since power-on and what it means for the battery
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Estimate {
+    pub average_ua: u32,
+    /// Run time left at the average draw; `None` without a battery reading
+    pub remaining: Option<Duration>,
+}
+
+/// Estimates from the time accounted so far, a battery of `capacity_mah` and
+/// its voltage; `None` until some time has been accounted
+pub fn estimate(capacity_mah: u32, battery_mv: Option<u32>) -> Option<Estimate> {
+    let totals = totals();
+    let total_us: u64 = totals.iter().map(|t| t.as_micros()).sum();
+    if total_us == 0 {
+        return None;
+    }
+    let charge_ua_us: u128 = State::ALL
+        .iter()
+        .zip(totals)
+        .map(|(state, time)| state.current_ua() as u128 * time.as_micros() as u128)
+        .sum();
+    let average_ua = (charge_ua_us / total_us as u128).max(1) as u32;
+
+    let remaining = battery_mv.map(|mv| {
+        let left_uah = capacity_mah as u64 * charge_permille(mv) as u64;
+        Duration::from_secs(left_uah * 3600 / average_ua as u64)
+    });
+    Some(Estimate {
+        average_ua,
+        remaining,
+    })
+}
+
+/// Charge left in a LiPo cell at rest from its voltage, in per mille
+pub fn charge_permille(mv: u32) -> u32 {
+    // (mV, ‰), a typical discharge curve at low current
+    const CURVE: [(u32, u32); 8] = [
+        (3300, 0),
+        (3500, 50),
+        (3600, 100),
+        (3700, 300),
+        (3750, 450),
+        (3850, 600),
+        (4000, 800),
+        (4200, 1000),
+    ];
+    if mv <= CURVE[0].0 {
+        return 0;
+    }
+    for pair in CURVE.windows(2) {
+        let ((lo_mv, lo), (hi_mv, hi)) = (pair[0], pair[1]);
+        if mv <= hi_mv {
+            return lo + (hi - lo) * (mv - lo_mv) / (hi_mv - lo_mv);
+        }
+    }
+    1000
+}
+
+fn load() -> Option<Ledger> {
+    // SAFETY: single core, and only touched inside a critical section
+    let ledger = critical_section::with(|_| unsafe { LEDGER });
+    ledger.is_valid().then_some(ledger)
+}
+
+fn rtc_us() -> Option<u64> {
+    time::with_rtc(|rtc| rtc.time_since_boot().as_micros())
+}
@@ -0,0 +1,230 @@
+//! Power-switched rails and deep sleep with an RTC timer wake-up.
+//!
+//! | rail                | switch                                       |
+//! |---------------------|----------------------------------------------|
+//! | [Rail::NeoPixel]    | GPIO21, low powers the pixels                |
+//! | [Rail::Speaker]     | GPIO16, high enables the amplifier           |
+//! | e-ink panel         | none, the SSD1680 stops its booster after each refresh |
+//!
+//! Rails start off, and every rail is switched off before deep sleep. Deep
+//! sleep ends on a timer and, if [wake_on] asked for it, on motion picked up
+//! by the accelerometer; [sleep_until_face_up] ends it only on the badge
+//! being turned over. A wake-up time inside the [quiet_hours] moves to
+//! their end.
+//! Waking from deep sleep restarts the firmware from `main`; the wall clock
+//! in [crate::time] carries over.
+
+use core::cell::{Cell, RefCell};
+
+use critical_section::Mutex;
+use esp_hal::{
+    gpio::{Level, Output, OutputConfig},
+    peripherals::{GPIO16, GPIO21},
+    rtc_cntl::sleep::{Ext0WakeupSource, TimerWakeupSource, WakeSource, WakeupLevel},
+    time::Duration,
+};
+use jiff::civil::DateTime;
+use log::{debug, info, warn};
+
+use crate::{
+    accel::{self, WakeTrigger},
+    alarm::{QuietHours, Schedule},
+    hooks, lifecycle, time,
+};
+
+pub mod cpu;
+pub mod estimator;
+
+pub use cpu::{set_cpu_profile, Profile};
+
+/// How long to sleep when a wake-up time is asked for but the clock isn't set
+pub const UNSYNCED_SLEEP: Duration = Duration::from_minutes(30);
+
+/// A power-switched part of the board
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rail {
+    NeoPixel,
+    Speaker,
+}
+
+impl Rail {
+    pub const ALL: [Rail; 2] = [Rail::NeoPixel, Rail::Speaker];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Rail::NeoPixel => "neopixel",
+            Rail::Speaker => "speaker",
+        }
+    }
+}
+
+struct Rails {
+    neopixel: Output<'static>,
+    speaker: Output<'static>,
+}
+
+impl Rails {
+    /// The pin and the level that turns the rail on
+    fn pin(&mut self, rail: Rail) -> (&mut Output<'static>, Level) {
+        match rail {
+            Rail::NeoPixel => (&mut self.neopixel, Level::Low),
+            Rail::Speaker => (&mut self.speaker, Level::High),
+        }
+    }
+}
+
+static RAILS: Mutex<RefCell<Option<Rails>>> = Mutex::new(RefCell::new(None));
+
+/// Takes the NeoPixel power (GPIO21) and speaker enable (GPIO16) pins, with
+/// both rails off. Call once at boot, before the drivers that use them.
+pub fn init(neopixel: GPIO21<'static>, speaker: GPIO16<'static>) {
+    let rails = Rails {
+        neopixel: Output::new(neopixel, Level::High, OutputConfig::default()),
+        speaker: Output::new(speaker, Level::Low, OutputConfig::default()),
+    };
+    critical_section::with(|cs| RAILS.borrow_ref_mut(cs).replace(rails));
+}
+
+/// The switch for `rail`, e.g. `power::rail(Rail::NeoPixel).off()`
+pub fn rail(rail: Rail) -> Switch {
+    Switch(rail)
+}
+
+/// Turns one rail on and off; does nothing before [init]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Switch(Rail);
+
+impl Switch {
+    pub fn on(self) {
+        self.set(true);
+    }
+
+    pub fn off(self) {
+        self.set(false);
+    }
+
+    pub fn set(self, on: bool) {
+        with_rails(|rails| {
+            let (pin, on_level) = rails.pin(self.0);
+            let level = if on { on_level } else { !on_level };
+            if pin.output_level() != level {
+                debug!("{} rail {}", self.0.name(), if on { "on" } else { "off" });
+                pin.set_level(level);
+            }
+        });
+    }
+
+    pub fn is_on(self) -> bool {
+        with_rails(|rails| {
+            let (pin, on_level) = rails.pin(self.0);
+            pin.output_level() == on_level
+        })
+        .unwrap_or(false)
+    }
+}
+
+/// Switches every rail off
+pub fn all_off() {
+    for r in Rail::ALL {
+        rail(r).off();
+    }
+}
+
+static WAKE_TRIGGER: Mutex<Cell<WakeTrigger>> = Mutex::new(Cell::new(WakeTrigger::Off));
+static QUIET_HOURS: Mutex<Cell<Option<QuietHours>>> = Mutex::new(Cell::new(None));
+
+/// Also ends deep sleep on `trigger`; [WakeTrigger::Off] leaves only the timer
+pub fn wake_on(trigger: WakeTrigger) {
+    critical_section::with(|cs| WAKE_TRIGGER.borrow(cs).set(trigger));
+}
+
+/// Keeps [sleep_until] from waking inside `hours`
+pub fn quiet_hours(hours: Option<QuietHours>) {
+    critical_section::with(|cs| QUIET_HOURS.borrow(cs).set(hours));
+}
+
+fn with_rails<R>(f: impl FnOnce(&mut Rails) -> R) -> Option<R> {
+    critical_section::with(|cs| RAILS.borrow_ref_mut(cs).as_mut().map(f))
+}
+
+/// Deep sleeps for `duration`, with every rail off
+pub fn sleep_for(duration: Duration) -> ! {
+    info!("Deep sleep for {} s", duration.as_secs());
+    let trigger = critical_section::with(|cs| WAKE_TRIGGER.borrow(cs).get());
+    deep_sleep(Some(duration), trigger)
+}
+
+/// Deep sleeps with every rail off until the accelerometer sees the screen
+/// facing up again, for a badge put down face down. Without a sensor to
+/// wake it, sleeps for [UNSYNCED_SLEEP] instead.
+pub fn sleep_until_face_up() -> ! {
+    info!("Deep sleep until turned face up");
+    deep_sleep(None, WakeTrigger::FaceUp)
+}
+
+fn deep_sleep(duration: Option<Duration>, trigger: WakeTrigger) -> ! {
+    hooks::before_sleep(duration);
+    lifecycle::enter(lifecycle::Stage::Sleep);
+    all_off();
+    estimator::enter(estimator::State::DeepSleep);
+    let interrupt = match trigger {
+        WakeTrigger::Off => None,
+        trigger => accel::arm(trigger)
+            .inspect_err(|err| warn!("No {} wake-up: {}", trigger.as_str(), err))
+            .ok(),
+    };
+    let duration = match (duration, &interrupt) {
+        (Some(duration), _) => Some(duration),
+        (None, Some(_)) => None,
+        (None, None) => Some(UNSYNCED_SLEEP),
+    };
+    let timer =
+        duration.map(|d| TimerWakeupSource::new(core::time::Duration::from_micros(d.as_micros())));
+    let accel = interrupt.map(|pin| Ext0WakeupSource::new(pin, WakeupLevel::High));
+    let sources: heapless::Vec<&dyn WakeSource, 2> = [
+        timer.as_ref().map(|t| t as &dyn WakeSource),
+        accel.as_ref().map(|a| a as &dyn WakeSource),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    time::with_rtc(|rtc| rtc.sleep_deep(&sources));
+    panic!("deep sleep needs time::init to have been called");
+}
+
+/// Deep sleeps until `local` in the configured timezone
+///
+/// Falls back to [UNSYNCED_SLEEP] if the clock isn't set, and wakes right away
+/// if `local` has already passed.
+pub fn sleep_until(local: DateTime) -> ! {
+    let local = match critical_section::with(|cs| QUIET_HOURS.borrow(cs).get()) {
+        Some(quiet) if quiet.contains(local) => {
+            info!("Wake-up at {} is in quiet hours", local);
+            quiet.defer(local)
+        }
+        _ => local,
+    };
+    sleep_for(duration_until(local).unwrap_or_else(|| {
+        warn!(
+            "Clock not set, sleeping {} min",
+            UNSYNCED_SLEEP.as_minutes()
+        );
+        UNSYNCED_SLEEP
+    }))
+}
+
+/// Deep sleeps until the next time `schedule` fires
+pub fn sleep_until_next(schedule: &Schedule) -> ! {
+    match time::now_local() {
+        Some(now) => sleep_until(schedule.next_after(now)),
+        None => sleep_for(UNSYNCED_SLEEP),
+    }
+}
+
+/// Time from now until `local`, or `None` if the clock isn't set
+pub fn duration_until(local: DateTime) -> Option<Duration> {
+    let now = time::now_utc()?;
+    let target = time::from_local(local)?;
+    let micros = target.as_microsecond() - now.as_microsecond();
+    Some(Duration::from_micros(micros.max(0) as u64))
+}
@@ -0,0 +1,100 @@
+//! Big buffers in external PSRAM, with the `psram` feature.
+//!
+//! Boards whose module carries PSRAM, 2 MiB on the MagTag's WROVER, can
+//! build with the feature. [init] adds it to the heap behind internal RAM, so
+//! ordinary allocations, the radio's among them, stay in faster internal
+//! memory and only spill over once that is full. Buffers taken from
+//! [with_capacity] or [zeroed] go straight to PSRAM instead: HTTP responses,
+//! decompressed bodies, remote display frames, asset files and the
+//! [crate::canvas::Canvas]. Without the
+//! feature, or when no PSRAM turns up, they come from the heap like any other
+//! allocation.
+//!
+//! A buffer that grows past its capacity is moved wherever the heap finds
+//! room, so size them up front.
+
+use alloc::vec::Vec;
+#[cfg(feature = "psram")]
+use core::{alloc::Layout, cell::Cell};
+
+#[cfg(feature = "psram")]
+use critical_section::Mutex;
+#[cfg(feature = "psram")]
+use esp_alloc::{HeapRegion, MemoryCapability, HEAP};
+#[cfg(feature = "psram")]
+use esp_hal::peripherals::PSRAM;
+#[cfg(feature = "psram")]
+use log::{info, warn};
+
+#[cfg(feature = "psram")]
+static SIZE: Mutex<Cell<usize>> = Mutex::new(Cell::new(0));
+
+/// Adds the PSRAM to the heap; call once, after the internal heap regions
+#[cfg(feature = "psram")]
+pub fn init(psram: PSRAM<'_>) {
+    let (start, size) = esp_hal::psram::psram_raw_parts(&psram);
+    if size == 0 {
+        warn!("No PSRAM found, big buffers stay in internal RAM");
+        return;
+    }
+    // SAFETY: the range is the mapped PSRAM, which nothing else uses, and
+    // this runs once
+    unsafe {
+        HEAP.add_region(HeapRegion::new(
+            start,
+            size,
+            MemoryCapability::External.into(),
+        ));
+    }
+    critical_section::with(|cs| SIZE.borrow(cs).set(size));
+    info!("PSRAM: {} KiB added to the heap", size / 1024);
+}
+
+/// Bytes of PSRAM in the heap; 0 without any
+#[cfg(feature = "psram")]
+pub fn size() -> usize {
+    critical_section::with(|cs| SIZE.borrow(cs).get())
+}
+
+#[cfg(not(feature = "psram"))]
+pub fn size() -> usize {
+    0
+}
+
+/// An empty buffer with room for `capacity` bytes, in PSRAM if possible.
+/// Without PSRAM it starts empty and grows as needed, like [Vec::new].
+pub fn with_capacity(capacity: usize) -> Vec<u8> {
+    #[cfg(feature = "psram")]
+    if let Some(buffer) = external(capacity) {
+        return buffer;
+    }
+    #[cfg(not(feature = "psram"))]
+    let _ = capacity;
+    Vec::new()
+}
+
+/// `len` zero bytes, in PSRAM if possible
+pub fn zeroed(len: usize) -> Vec<u8> {
+    #[cfg(feature = "psram")]
+    if let Some(mut buffer) = external(len) {
+        buffer.resize(len, 0);
+        return buffer;
+    }
+    alloc::vec![0u8; len]
+}
+
+#[cfg(feature = "psram")]
+fn external(capacity: usize) -> Option<Vec<u8>> {
+    if capacity == 0 || size() == 0 {
+        return None;
+    }
+    let layout = Layout::array::<u8>(capacity).ok()?;
+    // SAFETY: the layout isn't zero-sized
+    let ptr = unsafe { HEAP.alloc_caps(MemoryCapability::External.into(), layout) };
+    if ptr.is_null() {
+        return None;
+    }
+    // SAFETY: `ptr` comes from the global heap with the layout of `capacity`
+    // bytes, and the global allocator frees it from whichever region holds it
+    Some(unsafe { Vec::from_raw_parts(ptr, 0, capacity) })
+}
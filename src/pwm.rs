@@ -0,0 +1,274 @@
+//! Hobby servos and analog levels on the [expansion](crate::expansion) pads,
+//! from the LEDC PWM controller.
+//!
+//! `main` doesn't make a [Pwm]; an app that drives outputs takes
+//! `peripherals.LEDC` for one and gets its [Servo]s and [Level]s from it. A
+//! [Servo] sweep moves a step every [Servo::STEP] without blocking: the app
+//! returns [Servo::next_step] from [App::desired_sleep] and calls
+//! [Servo::step] on each [Event::Tick], so the host's scheduler paces it.
+//!
+//! A [Level] is a 20 kHz duty cycle, which an RC filter smooths into a
+//! voltage. The MagTag's A1 port is also the S2's DAC2, for a real voltage
+//! with [Level::dac].
+//!
+//! [App::desired_sleep]: crate::app::App::desired_sleep
+//! [Event::Tick]: crate::app::Event::Tick
+
+use alloc::boxed::Box;
+
+use embedded_hal::pwm::SetDutyCycle;
+#[cfg(feature = "magtag-2.9")]
+use esp_hal::{analog::dac::Dac, peripherals::DAC2};
+use esp_hal::{
+    gpio::DriveMode,
+    ledc::{
+        channel::{self, Channel, ChannelIFace},
+        timer::{self, config::Duty, LSClockSource, Timer, TimerIFace},
+        LSGlobalClkSource, Ledc, LowSpeed,
+    },
+    peripherals::LEDC,
+    time::{Duration, Instant, Rate},
+};
+use log::warn;
+
+use crate::expansion::{self, Pad};
+
+/// Servos expect a pulse every 20 ms
+const SERVO_RATE: Rate = Rate::from_hz(50);
+/// 1.2 µs steps across the 20 ms frame
+const SERVO_DUTY: Duty = Duty::Duty14Bit;
+const SERVO_FRAME_US: u32 = 20_000;
+/// Well above hearing, and easy to filter
+const LEVEL_RATE: Rate = Rate::from_khz(20);
+const LEVEL_DUTY: Duty = Duty::Duty8Bit;
+
+const CHANNELS: [channel::Number; 8] = [
+    channel::Number::Channel0,
+    channel::Number::Channel1,
+    channel::Number::Channel2,
+    channel::Number::Channel3,
+    channel::Number::Channel4,
+    channel::Number::Channel5,
+    channel::Number::Channel6,
+    channel::Number::Channel7,
+];
+
+/// The LEDC controller, with one timer for servos and one for levels, and
+/// eight channels to hand out between them
+pub struct Pwm {
+    ledc: Ledc<'static>,
+    servo_timer: &'static Timer<'static, LowSpeed>,
+    level_timer: &'static Timer<'static, LowSpeed>,
+    next_channel: usize,
+}
+
+impl Pwm {
+    pub fn new(ledc: LEDC<'static>) -> Self {
+        let mut ledc = Ledc::new(ledc);
+        ledc.set_global_slow_clock(LSGlobalClkSource::APBClk);
+        let timer = |number, duty, frequency| {
+            let mut timer = ledc.timer::<LowSpeed>(number);
+            timer
+                .configure(timer::config::Config {
+                    duty,
+                    clock_source: LSClockSource::APBClk,
+                    frequency,
+                })
+                .expect("PWM rate within the divider's range");
+            &*Box::leak(Box::new(timer))
+        };
+        let servo_timer = timer(timer::Number::Timer0, SERVO_DUTY, SERVO_RATE);
+        let level_timer = timer(timer::Number::Timer1, LEVEL_DUTY, LEVEL_RATE);
+        Self {
+            ledc,
+            servo_timer,
+            level_timer,
+            next_channel: 0,
+        }
+    }
+
+    /// A servo on `pad`, centered. `None` if the pad isn't free or all eight
+    /// channels are in use.
+    pub fn servo(&mut self, pad: Pad) -> Option<Servo> {
+        let channel = self.channel(pad, self.servo_timer)?;
+        let mut servo = Servo {
+            channel,
+            min_us: Servo::MIN_US,
+            max_us: Servo::MAX_US,
+            angle: 90,
+            sweep: None,
+        };
+        servo.write();
+        Some(servo)
+    }
+
+    /// A PWM level on `pad`, starting at 0. `None` as for [Pwm::servo].
+    pub fn level(&mut self, pad: Pad) -> Option<Level> {
+        let channel = self.channel(pad, self.level_timer)?;
+        Some(Level {
+            output: Output::Pwm(channel),
+        })
+    }
+
+    fn channel(
+        &mut self,
+        pad: Pad,
+        timer: &'static Timer<'static, LowSpeed>,
+    ) -> Option<Channel<'static, LowSpeed>> {
+        let Some(&number) = CHANNELS.get(self.next_channel) else {
+            warn!("No PWM channel left for pad {}", pad.name());
+            return None;
+        };
+        let pin = expansion::take(pad)?;
+        let mut channel = self.ledc.channel(number, pin);
+        channel
+            .configure(channel::config::Config {
+                timer,
+                duty_pct: 0,
+                drive_mode: DriveMode::PushPull,
+            })
+            .ok()?;
+        self.next_channel += 1;
+        Some(channel)
+    }
+}
+
+/// A hobby servo, positioned in degrees from 0 to 180
+pub struct Servo {
+    channel: Channel<'static, LowSpeed>,
+    min_us: u32,
+    max_us: u32,
+    angle: u8,
+    sweep: Option<Sweep>,
+}
+
+/// A move from `from` to `to` degrees over `over` from `start`
+#[derive(Debug, Clone, Copy)]
+struct Sweep {
+    from: u8,
+    to: u8,
+    start: Instant,
+    over: Duration,
+}
+
+impl Servo {
+    /// The pulse at 0 degrees, which most servos take
+    pub const MIN_US: u32 = 1000;
+    /// The pulse at 180 degrees
+    pub const MAX_US: u32 = 2000;
+    /// How often a sweep moves; the servo only sees one pulse a frame anyway
+    pub const STEP: Duration = Duration::from_millis(20);
+
+    /// The pulses for 0 and 180 degrees, for servos that turn further than
+    /// [Servo::MIN_US] to [Servo::MAX_US] allows. Keep within what the servo
+    /// takes, around 500 to 2500 µs at most, or it strains at the stop.
+    pub fn set_range(&mut self, min_us: u32, max_us: u32) {
+        self.max_us = max_us.min(SERVO_FRAME_US);
+        self.min_us = min_us.min(self.max_us);
+        self.write();
+    }
+
+    pub fn angle(&self) -> u8 {
+        self.angle
+    }
+
+    /// Turns straight to `degrees`, ending a sweep
+    pub fn set_angle(&mut self, degrees: u8) {
+        self.sweep = None;
+        self.angle = degrees.min(180);
+        self.write();
+    }
+
+    /// Starts turning to `degrees` over `over` from `now`; [Servo::step]
+    /// moves it along
+    pub fn sweep_to(&mut self, degrees: u8, over: Duration, now: Instant) {
+        self.sweep = Some(Sweep {
+            from: self.angle,
+            to: degrees.min(180),
+            start: now,
+            over,
+        });
+        self.step(now);
+    }
+
+    pub fn is_sweeping(&self) -> bool {
+        self.sweep.is_some()
+    }
+
+    /// Moves a sweep to where it should be at `now`, and ends it there at
+    /// the end
+    pub fn step(&mut self, now: Instant) {
+        let Some(sweep) = self.sweep else {
+            return;
+        };
+        let elapsed = (now - sweep.start).as_millis();
+        let over = sweep.over.as_millis();
+        if elapsed >= over {
+            self.sweep = None;
+            self.angle = sweep.to;
+        } else {
+            let (from, to) = (sweep.from as i64, sweep.to as i64);
+            self.angle = (from + (to - from) * elapsed as i64 / over as i64) as u8;
+        }
+        self.write();
+    }
+
+    /// [Servo::STEP] while sweeping, for [App::desired_sleep]
+    ///
+    /// [App::desired_sleep]: crate::app::App::desired_sleep
+    pub fn next_step(&self) -> Option<Duration> {
+        self.sweep.map(|_| Self::STEP)
+    }
+
+    /// Stops the pulses, which lets most servos go limp and stop drawing
+    /// current until the next [Servo::set_angle]
+    pub fn release(&mut self) {
+        self.sweep = None;
+        let _ = self.channel.set_duty_cycle(0);
+    }
+
+    fn write(&mut self) {
+        let pulse_us = self.min_us + (self.max_us - self.min_us) * self.angle as u32 / 180;
+        let max = self.channel.max_duty_cycle() as u32;
+        let _ = self
+            .channel
+            .set_duty_cycle((pulse_us * max / SERVO_FRAME_US) as u16);
+    }
+}
+
+/// An analog output from 0 to 255 of 3.3 V
+pub struct Level {
+    output: Output,
+}
+
+enum Output {
+    Pwm(Channel<'static, LowSpeed>),
+    #[cfg(feature = "magtag-2.9")]
+    Dac(Dac<'static, DAC2<'static>>),
+}
+
+impl Level {
+    /// The A1 port driven by DAC2, starting at 0. `None` if the pad isn't
+    /// free.
+    #[cfg(feature = "magtag-2.9")]
+    pub fn dac(dac: DAC2<'static>) -> Option<Self> {
+        expansion::take(Pad::A1)?;
+        // SAFETY: the pad is ours now, and its pin is GPIO18
+        let pin = unsafe { esp_hal::peripherals::GPIO18::steal() };
+        let mut dac = Dac::new(dac, pin);
+        dac.write(0);
+        Some(Self {
+            output: Output::Dac(dac),
+        })
+    }
+
+    pub fn set(&mut self, value: u8) {
+        match &mut self.output {
+            Output::Pwm(channel) => {
+                let _ = channel.set_duty_cycle(value as u16);
+            }
+            #[cfg(feature = "magtag-2.9")]
+            Output::Dac(dac) => dac.write(value),
+        }
+    }
+}
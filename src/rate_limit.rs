@@ -0,0 +1,112 @@
+//! Per-host token buckets for outgoing requests, so a refresh loop gone wrong
+//! or a stuck button can't hammer a free-tier API into banning the user's
+//! key.
+//!
+//! Each host starts with [BURST] requests and gets one back every
+//! [REFILL_INTERVAL]. [crate::http] takes one before each request, redirects
+//! included but not retries, and fails with [Error::RateLimited] when there
+//! is none left. A `429 Too Many Requests` reply empties the host's bucket.
+//!
+//! Buckets are kept by a CRC of the host name rather than the name, so
+//! tracking a host takes nothing from the heap. Two hosts whose names
+//! collide share a bucket.
+
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+use esp_hal::time::{Duration, Instant};
+use heapless::Vec as FixedVec;
+use log::warn;
+
+use crate::{crc::crc32_update, Error};
+
+/// Requests a host can take in a row
+pub const BURST: u32 = 6;
+/// How long a used request takes to come back
+pub const REFILL_INTERVAL: Duration = Duration::from_secs(10);
+/// Hosts tracked at once; the one with the fullest bucket makes way
+const MAX_HOSTS: usize = 8;
+
+struct Bucket {
+    /// CRC-32 of the host name in lower case
+    host: u32,
+    tokens: u32,
+    /// When `tokens` was last brought up to date
+    updated: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, now: Instant) {
+        let interval = REFILL_INTERVAL.as_millis();
+        let earned = (now - self.updated).as_millis() / interval;
+        if earned == 0 {
+            return;
+        }
+        self.tokens = (self.tokens as u64 + earned).min(BURST as u64) as u32;
+        self.updated = if self.tokens == BURST {
+            now
+        } else {
+            self.updated + Duration::from_millis(earned * interval)
+        };
+    }
+}
+
+static BUCKETS: Mutex<RefCell<FixedVec<Bucket, MAX_HOSTS>>> =
+    Mutex::new(RefCell::new(FixedVec::new()));
+
+/// Takes a request for `host`, [Error::RateLimited] if it has none left
+pub fn acquire(host: &str) -> Result<(), Error> {
+    let now = Instant::now();
+    let allowed = with_bucket(host, now, |bucket| {
+        bucket.refill(now);
+        let allowed = bucket.tokens > 0;
+        bucket.tokens = bucket.tokens.saturating_sub(1);
+        allowed
+    });
+    if !allowed {
+        warn!("Too many requests to {}, holding off", host);
+        return Err(Error::RateLimited);
+    }
+    Ok(())
+}
+
+/// Leaves `host` nothing until its bucket refills, after it asked us to slow
+/// down
+pub fn back_off(host: &str) {
+    let now = Instant::now();
+    with_bucket(host, now, |bucket| {
+        bucket.tokens = 0;
+        bucket.updated = now;
+    });
+}
+
+fn with_bucket<R>(host: &str, now: Instant, f: impl FnOnce(&mut Bucket) -> R) -> R {
+    let host = host
+        .bytes()
+        .fold(0, |crc, b| crc32_update(crc, &[b.to_ascii_lowercase()]));
+    critical_section::with(|cs| {
+        let mut buckets = BUCKETS.borrow_ref_mut(cs);
+        let index = match buckets.iter().position(|bucket| bucket.host == host) {
+            Some(index) => index,
+            None => {
+                if buckets.len() == MAX_HOSTS {
+                    buckets.iter_mut().for_each(|bucket| bucket.refill(now));
+                    let fullest = (0..buckets.len())
+                        .max_by_key(|&i| buckets[i].tokens)
+                        .unwrap_or(0);
+                    buckets.swap_remove(fullest);
+                }
+                // room was made above
+                buckets
+                    .push(Bucket {
+                        host,
+                        tokens: BURST,
+                        updated: now,
+                    })
+                    .ok();
+                buckets.len() - 1
+            }
+        };
+        f(&mut buckets[index])
+    })
+}
@@ -0,0 +1,89 @@
+//! Checks and decodes the frames a sender pushes to
+//! [crate::apps::remote_display], in the format described there: the
+//! header, the signature when there has to be one, and the raw or
+//! run-length encoded payload.
+//!
+//! Nothing here touches the network, so `tools/fuzz` feeds it arbitrary
+//! bytes on the host.
+
+use alloc::vec::Vec;
+
+use crate::{
+    display::{HEIGHT, WIDTH},
+    psram,
+    sha256::{ct_eq, hmac_sha256, DIGEST_LEN},
+};
+
+/// Size of a decoded frame in bytes
+pub const FRAME_LEN: usize = (WIDTH * HEIGHT / 4) as usize;
+
+const MAGIC: [u8; 4] = *b"MTFB";
+const HEADER_LEN: usize = 12;
+const ENCODING_RAW: u8 = 0;
+const ENCODING_RLE: u8 = 1;
+const FLAG_SIGNED: u8 = 1 << 0;
+/// Worst case for the run-length encoding, one pair per byte
+const MAX_PAYLOAD_LEN: usize = 2 * FRAME_LEN;
+
+/// How far a frame has arrived
+#[derive(Debug, PartialEq, Eq)]
+pub enum Upload {
+    Incomplete,
+    Invalid(&'static str),
+    Complete(Vec<u8>),
+}
+
+/// Parses `raw`, which has to be signed with `key` if there is one
+pub fn parse_upload(raw: &[u8], key: Option<&[u8]>) -> Upload {
+    if raw.len() < HEADER_LEN {
+        return Upload::Incomplete;
+    }
+    if raw[..4] != MAGIC {
+        return Upload::Invalid("bad magic");
+    }
+    let len = u32::from_le_bytes([raw[8], raw[9], raw[10], raw[11]]) as usize;
+    if len > MAX_PAYLOAD_LEN {
+        return Upload::Invalid("too large");
+    }
+    let signed = raw[5] & FLAG_SIGNED != 0;
+    let signature_len = if signed { DIGEST_LEN } else { 0 };
+    if raw.len() < HEADER_LEN + len + signature_len {
+        return Upload::Incomplete;
+    }
+    let (signed_part, signature) = raw.split_at(HEADER_LEN + len);
+    match key {
+        Some(_) if !signed => return Upload::Invalid("unsigned"),
+        Some(key) if !ct_eq(&hmac_sha256(key, &[signed_part]), &signature[..DIGEST_LEN]) => {
+            return Upload::Invalid("bad signature")
+        }
+        _ => {}
+    }
+    let payload = &signed_part[HEADER_LEN..];
+    match raw[4] {
+        ENCODING_RAW if len == FRAME_LEN => Upload::Complete(payload.to_vec()),
+        ENCODING_RAW => Upload::Invalid("wrong frame size"),
+        ENCODING_RLE => match decode_rle(payload) {
+            Some(frame) => Upload::Complete(frame),
+            None => Upload::Invalid("bad run-length data"),
+        },
+        _ => Upload::Invalid("unknown encoding"),
+    }
+}
+
+/// Expands `(count, byte)` pairs; `None` unless they make exactly one frame
+pub fn decode_rle(payload: &[u8]) -> Option<Vec<u8>> {
+    if !payload.len().is_multiple_of(2) {
+        return None;
+    }
+    let mut frame = psram::zeroed(FRAME_LEN);
+    let mut filled = 0;
+    for pair in payload.chunks_exact(2) {
+        let count = pair[0] as usize;
+        if count == 0 || filled + count > FRAME_LEN {
+            return None;
+        }
+        frame[filled..filled + count].fill(pair[1]);
+        filled += count;
+    }
+    (filled == FRAME_LEN).then_some(frame)
+}
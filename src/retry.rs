@@ -0,0 +1,78 @@
+//! Retrying network operations with jittered exponential backoff.
+//!
+//! A failed attempt waits before the next one, twice as long each time up to
+//! [Policy::max_delay]. Each wait is picked at random between half and all of
+//! that, so a room full of badges that lost the same access point doesn't come
+//! back in lockstep. Only [Error::Network] and [Error::Timeout] are retried;
+//! anything else won't get better by trying again.
+
+use esp_hal::{
+    delay::Delay,
+    rng::Rng,
+    time::{Duration, Instant},
+};
+use log::debug;
+
+use crate::Error;
+
+/// When to try again and when to give up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Policy {
+    /// Attempts in total, including the first
+    pub max_attempts: u32,
+    /// Wait before the second attempt
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    /// No new attempt starts once this much time has gone by since the first
+    pub max_elapsed: Duration,
+}
+
+impl Policy {
+    /// HTTP requests, MQTT sessions and SNTP queries
+    pub const NETWORK: Policy = Policy {
+        max_attempts: 4,
+        initial_delay: Duration::from_millis(500),
+        max_delay: Duration::from_secs(8),
+        max_elapsed: Duration::from_secs(45),
+    };
+}
+
+/// Runs `op` until it succeeds, fails with an error not worth retrying, or
+/// `policy` runs out; returns the last result
+pub fn with_backoff<T>(
+    policy: &Policy,
+    mut op: impl FnMut() -> Result<T, Error>,
+) -> Result<T, Error> {
+    let started = Instant::now();
+    let mut delay = policy.initial_delay;
+    let mut attempt = 1;
+    loop {
+        let err = match op() {
+            Ok(value) => return Ok(value),
+            Err(err) => err,
+        };
+        if !matches!(err, Error::Network | Error::Timeout) || attempt >= policy.max_attempts {
+            return Err(err);
+        }
+        let wait = jittered(delay);
+        if started.elapsed() + wait > policy.max_elapsed {
+            return Err(err);
+        }
+        debug!(
+            "attempt {} failed ({}), retrying in {} ms",
+            attempt,
+            err,
+            wait.as_millis()
+        );
+        Delay::new().delay_millis(wait.as_millis() as u32);
+        delay = Duration::from_millis(delay.as_millis() * 2).min(policy.max_delay);
+        attempt += 1;
+    }
+}
+
+/// A random duration between half of `delay` and all of it
+pub fn jittered(delay: Duration) -> Duration {
+    let half_ms = delay.as_millis() / 2;
+    let extra_ms = Rng::new().random() as u64 % (half_ms + 1);
+    Duration::from_millis(half_ms + extra_ms)
+}
@@ -0,0 +1,189 @@
+//! RTTTL, the Nokia ringtone text format, for melodies kept in the config.
+//!
+//! A tune is a name, defaults and notes, separated by colons:
+//!
+//! ```text
+//! beep:d=8,o=6,b=140:c,e,g,2c7
+//! ```
+//!
+//! `d` is the default length (1 is a whole note, up to 32), `o` the default
+//! octave (4 to 7, `a` in octave 4 being 440 Hz) and `b` the tempo in
+//! quarter notes per minute. Each note is an optional length, a letter
+//! `a`-`g` (or `h` for `b`) or `p` for a pause, an optional `#`, an optional
+//! octave and an optional `.` that makes it half as long again.
+//!
+//! The tune under the `melody` key is read with [configured].
+
+use alloc::{string::String, vec::Vec};
+use core::str::FromStr;
+
+use esp_hal::time::Duration;
+
+use log::warn;
+
+use crate::{
+    config::{keys, ConfigStore},
+    speaker, Error,
+};
+
+/// Plays when the `melody` key is unset
+pub const DEFAULT: &str = "chime:d=8,o=6,b=160:e,c,4g5";
+
+/// Frequencies of C8 to B8 in Hz; lower octaves halve them
+const OCTAVE_8_HZ: [u32; 12] = [
+    4186, 4435, 4699, 4978, 5274, 5588, 5920, 6272, 6645, 7040, 7459, 7902,
+];
+/// Share of each note that sounds, so that repeated notes stay apart
+const LEGATO_PERCENT: u64 = 90;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Note {
+    /// 0 for a pause
+    pub frequency_hz: u32,
+    pub duration: Duration,
+}
+
+/// A parsed tune, see the [module docs](self) for the format
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Melody {
+    pub name: String,
+    pub notes: Vec<Note>,
+}
+
+impl Melody {
+    /// Plays the notes on the speaker, blocking until done
+    pub fn play(&self) {
+        for note in &self.notes {
+            let ms = note.duration.as_millis();
+            let sounding = ms * LEGATO_PERCENT / 100;
+            speaker::tone(note.frequency_hz, Duration::from_millis(sounding));
+            speaker::tone(0, Duration::from_millis(ms - sounding));
+        }
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.notes
+            .iter()
+            .fold(Duration::ZERO, |total, note| total + note.duration)
+    }
+}
+
+/// The tune under the `melody` key, or [DEFAULT] if it is unset or doesn't
+/// parse
+pub fn configured(config: &ConfigStore) -> Melody {
+    if let Some(tune) = config.get(keys::MELODY) {
+        match tune.parse() {
+            Ok(melody) => return melody,
+            Err(err) => warn!("{} `{}`: {}", keys::MELODY, tune, err),
+        }
+    }
+    DEFAULT.parse().expect("valid default melody")
+}
+
+impl FromStr for Melody {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut sections = s.trim().splitn(3, ':');
+        let (Some(name), Some(defaults), Some(notes)) =
+            (sections.next(), sections.next(), sections.next())
+        else {
+            return Err(Error::InvalidConfig);
+        };
+
+        let (mut length, mut octave, mut bpm) = (4, 6, 63);
+        for setting in defaults.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let (key, value) = setting.split_once('=').ok_or(Error::InvalidConfig)?;
+            let value: u32 = value.trim().parse().map_err(|_| Error::InvalidConfig)?;
+            match key.trim() {
+                "d" if valid_length(value) => length = value,
+                "o" if valid_octave(value) => octave = value,
+                "b" if (1..=900).contains(&value) => bpm = value,
+                _ => return Err(Error::InvalidConfig),
+            }
+        }
+
+        // a whole note is four beats
+        let whole_ms = 4 * 60_000 / bpm as u64;
+        let notes = notes
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|note| parse_note(note, length, octave, whole_ms))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            name: String::from(name.trim()),
+            notes,
+        })
+    }
+}
+
+fn parse_note(note: &str, length: u32, octave: u32, whole_ms: u64) -> Result<Note, Error> {
+    let digits = |s: &str| s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+
+    let split = digits(note);
+    let length = match &note[..split] {
+        "" => length,
+        n => n
+            .parse()
+            .ok()
+            .filter(|&n| valid_length(n))
+            .ok_or(Error::InvalidConfig)?,
+    };
+    let mut rest = note[split..].chars().peekable();
+    let semitone = match rest.next().map(|c| c.to_ascii_lowercase()) {
+        Some('c') => Some(0),
+        Some('d') => Some(2),
+        Some('e') => Some(4),
+        Some('f') => Some(5),
+        Some('g') => Some(7),
+        Some('a') => Some(9),
+        Some('b' | 'h') => Some(11),
+        Some('p') => None,
+        _ => return Err(Error::InvalidConfig),
+    };
+    let sharp = rest.next_if_eq(&'#').is_some();
+    // the dot shows up before or after the octave
+    let mut dotted = rest.next_if_eq(&'.').is_some();
+    let rest: String = rest.collect();
+    let split = digits(&rest);
+    let octave = match &rest[..split] {
+        "" => octave,
+        n => n
+            .parse()
+            .ok()
+            .filter(|&n| valid_octave(n))
+            .ok_or(Error::InvalidConfig)?,
+    };
+    match &rest[split..] {
+        "" => {}
+        "." if !dotted => dotted = true,
+        _ => return Err(Error::InvalidConfig),
+    }
+
+    let mut ms = whole_ms / length as u64;
+    if dotted {
+        ms += ms / 2;
+    }
+    let frequency_hz = match semitone {
+        Some(semitone) => {
+            let index = (semitone + sharp as usize) % 12;
+            // b# is the next c up
+            let octave = octave + (semitone + sharp as usize) as u32 / 12;
+            OCTAVE_8_HZ[index] >> (8 - octave.min(8))
+        }
+        None => 0,
+    };
+    Ok(Note {
+        frequency_hz,
+        duration: Duration::from_millis(ms),
+    })
+}
+
+fn valid_length(length: u32) -> bool {
+    matches!(length, 1 | 2 | 4 | 8 | 16 | 32)
+}
+
+fn valid_octave(octave: u32) -> bool {
+    (4..=7).contains(&octave)
+}
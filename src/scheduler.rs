@@ -0,0 +1,87 @@
+//! Minimal timer list for cooperative tasks driven from the host loop.
+
+use esp_hal::time::{Duration, Instant};
+use heapless::Vec;
+
+/// Maximum number of pending timers
+pub const MAX_TIMERS: usize = 12;
+
+/// Identifies what a timer belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TaskId(pub u8);
+
+#[derive(Debug, Clone, Copy)]
+struct Timer {
+    id: TaskId,
+    due: Instant,
+    period: Option<Duration>,
+}
+
+/// One-shot and periodic timers, polled by the owner
+#[derive(Default)]
+pub struct Scheduler {
+    timers: Vec<Timer, MAX_TIMERS>,
+}
+
+impl Scheduler {
+    pub const fn new() -> Self {
+        Self { timers: Vec::new() }
+    }
+
+    /// Fires `id` once after `delay`, replacing any timer already registered for it
+    pub fn schedule(&mut self, id: TaskId, delay: Duration) {
+        self.insert(Timer {
+            id,
+            due: Instant::now() + delay,
+            period: None,
+        });
+    }
+
+    /// Fires `id` every `period`, replacing any timer already registered for it
+    pub fn schedule_every(&mut self, id: TaskId, period: Duration) {
+        self.insert(Timer {
+            id,
+            due: Instant::now() + period,
+            period: Some(period),
+        });
+    }
+
+    pub fn cancel(&mut self, id: TaskId) {
+        self.timers.retain(|t| t.id != id);
+    }
+
+    pub fn is_scheduled(&self, id: TaskId) -> bool {
+        self.timers.iter().any(|t| t.id == id)
+    }
+
+    /// Whether `id` fires within `window` from now
+    pub fn due_within(&self, id: TaskId, window: Duration) -> bool {
+        let by = Instant::now() + window;
+        self.timers.iter().any(|t| t.id == id && t.due <= by)
+    }
+
+    /// The earliest pending deadline
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.timers.iter().map(|t| t.due).min()
+    }
+
+    /// Pops one timer that is due at `now`. Periodic timers are re-armed.
+    pub fn next_due(&mut self, now: Instant) -> Option<TaskId> {
+        let index = self.timers.iter().position(|t| t.due <= now)?;
+        let timer = self.timers[index];
+        match timer.period {
+            Some(period) => self.timers[index].due = now + period,
+            None => {
+                self.timers.swap_remove(index);
+            }
+        }
+        Some(timer.id)
+    }
+
+    fn insert(&mut self, timer: Timer) {
+        self.cancel(timer.id);
+        if self.timers.push(timer).is_err() {
+            log::warn!("scheduler full, dropping timer {:?}", timer.id);
+        }
+    }
+}
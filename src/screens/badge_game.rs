@@ -0,0 +1,128 @@
+//! The badge game's two views, see [crate::apps::badge_game].
+
+use alloc::format;
+
+use embedded_graphics::{
+    mono_font::MonoTextStyle,
+    pixelcolor::Gray2,
+    prelude::*,
+    primitives::{PrimitiveStyle, Rectangle},
+    text::{Baseline, Text},
+};
+
+use crate::{
+    display::{Frame, HEIGHT, WIDTH},
+    fonts::{FIXED_6X10, FIXED_7X14_BOLD},
+    tr,
+    ui::button_bar::{draw_button_hints, BUTTON_BAR_HEIGHT},
+};
+
+const TITLE_HEIGHT: i32 = 18;
+const ROW_HEIGHT: i32 = 14;
+
+/// A badge heard from lately
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Badge<'a> {
+    pub name: &'a str,
+    pub score: u16,
+    pub rssi: i32,
+    /// Whether it is a contact already
+    pub met: bool,
+}
+
+/// The badges nearby with `selected` highlighted, under this badge's name
+pub fn draw_nearby(frame: &mut Frame, own: &str, badges: &[Badge<'_>], selected: usize) {
+    draw_title(frame, &tr!("badges_nearby", own, badges.len()));
+    let small = MonoTextStyle::new(&FIXED_6X10, Gray2::BLACK);
+    if badges.is_empty() {
+        Text::with_baseline(
+            tr!("no_badges"),
+            Point::new(4, TITLE_HEIGHT + 4),
+            small,
+            Baseline::Top,
+        )
+        .draw(frame)
+        .ok();
+    }
+    for (row, (index, badge)) in badges
+        .iter()
+        .enumerate()
+        .skip(first_row(selected))
+        .take(rows())
+        .enumerate()
+    {
+        let top = TITLE_HEIGHT + row as i32 * ROW_HEIGHT;
+        let selected = index == selected;
+        let color = if selected { Gray2::WHITE } else { Gray2::BLACK };
+        if selected {
+            Rectangle::new(Point::new(0, top), Size::new(WIDTH, ROW_HEIGHT as u32))
+                .into_styled(PrimitiveStyle::with_fill(Gray2::BLACK))
+                .draw(frame)
+                .ok();
+        }
+        let line = format!(
+            "{} {:<24} {:>3} {:>4} dBm",
+            if badge.met { '*' } else { ' ' },
+            badge.name,
+            badge.score,
+            badge.rssi
+        );
+        Text::with_baseline(
+            &line,
+            Point::new(4, top + 2),
+            MonoTextStyle::new(&FIXED_6X10, color),
+            Baseline::Top,
+        )
+        .draw(frame)
+        .ok();
+    }
+    draw_button_hints(
+        frame,
+        [
+            Some(tr!("up")),
+            Some(tr!("ranks")),
+            Some(tr!("down")),
+            Some(tr!("swap")),
+        ],
+    );
+}
+
+/// Everyone in `ranking`, name, contacts and whether it is this badge, most
+/// contacts first; `contacts` is this badge's count
+pub fn draw_leaderboard(frame: &mut Frame, contacts: u16, ranking: &[(&str, u16, bool)]) {
+    draw_title(frame, &tr!("leaderboard", contacts));
+    for (place, (name, score, own)) in ranking.iter().take(rows()).enumerate() {
+        let top = TITLE_HEIGHT + place as i32 * ROW_HEIGHT;
+        let font = if *own { &FIXED_7X14_BOLD } else { &FIXED_6X10 };
+        let line = format!("{:>2}. {:<24} {:>3}", place + 1, name, score);
+        Text::with_baseline(
+            &line,
+            Point::new(4, top + 1),
+            MonoTextStyle::new(font, Gray2::BLACK),
+            Baseline::Top,
+        )
+        .draw(frame)
+        .ok();
+    }
+    draw_button_hints(frame, [None, Some(tr!("nearby")), None, None]);
+}
+
+fn draw_title(frame: &mut Frame, title: &str) {
+    Text::with_baseline(
+        title,
+        Point::new(4, 2),
+        MonoTextStyle::new(&FIXED_7X14_BOLD, Gray2::BLACK),
+        Baseline::Top,
+    )
+    .draw(frame)
+    .ok();
+}
+
+fn rows() -> usize {
+    ((HEIGHT as i32 - TITLE_HEIGHT - BUTTON_BAR_HEIGHT as i32) / ROW_HEIGHT).max(1) as usize
+}
+
+/// The first row shown, scrolled so `selected` is on screen
+fn first_row(selected: usize) -> usize {
+    selected.saturating_sub(rows() - 1)
+}
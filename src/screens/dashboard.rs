@@ -0,0 +1,50 @@
+//! The dashboard's template, see [crate::apps::dashboard].
+
+use alloc::{format, string::String};
+
+use embedded_graphics::{
+    mono_font::MonoTextStyle,
+    pixelcolor::Gray2,
+    prelude::*,
+    text::{Baseline, Text},
+};
+
+use crate::{
+    bindings,
+    data_source::Stale,
+    display::Frame,
+    fonts::FIXED_6X10,
+    time, tr,
+    ui::{stale::draw_stale_banner, template::Template},
+    Error,
+};
+
+/// `template` as it was loaded, if it was yet, tagged when it is an old copy
+pub fn draw(frame: &mut Frame, template: Option<&Result<Template, Error>>, stale: Option<&Stale>) {
+    match template {
+        Some(Ok(template)) => {
+            template.render(frame, value);
+            if let Some(stale) = stale {
+                draw_stale_banner(frame, stale);
+            }
+        }
+        Some(Err(err)) => {
+            let style = MonoTextStyle::new(&FIXED_6X10, Gray2::BLACK);
+            let message = tr!("no_template", err);
+            Text::with_baseline(&message, Point::new(4, 4), style, Baseline::Top)
+                .draw(frame)
+                .ok();
+        }
+        None => {}
+    }
+}
+
+/// The clock, or what is published under `name`
+fn value(name: &str) -> Option<String> {
+    match name {
+        "time" => time::now_local().map(|now| format!("{:02}:{:02}", now.hour(), now.minute())),
+        "date" => time::now_local()
+            .map(|now| format!("{}-{:02}-{:02}", now.year(), now.month(), now.day())),
+        _ => bindings::get(name),
+    }
+}
@@ -0,0 +1,37 @@
+//! The demo's hello-world screen, see [crate::apps::demo].
+
+use embedded_graphics::{
+    image::{Image, ImageRaw},
+    mono_font::MonoTextStyle,
+    pixelcolor::{BinaryColor, Gray2},
+    prelude::*,
+    primitives::{Line, PrimitiveStyle, Rectangle},
+    text::Text,
+};
+
+use crate::{display::Frame, fonts::FIXED_7X14_BOLD, tr};
+
+static FERRIS: &[u8] = include_bytes!("../../assets/ferris.bin");
+
+pub fn draw(frame: &mut Frame) {
+    let character_style = MonoTextStyle::new(&FIXED_7X14_BOLD, Gray2::BLACK);
+    Text::new(tr!("demo_hello"), Point::new(10, 15), character_style)
+        .draw(frame)
+        .ok();
+
+    Rectangle::new(Point::new(50, 50), Size::new(25, 25))
+        .into_styled(PrimitiveStyle::with_fill(Gray2::new(0x01)))
+        .draw(frame)
+        .ok();
+
+    // the bitmap is 1bpp, 100 pixels wide, on for white
+    let raw = ImageRaw::<BinaryColor>::new(FERRIS, 100);
+    Image::new(&raw, Point::new(100, 20))
+        .draw(&mut frame.color_converted())
+        .ok();
+
+    Line::new(Point::new(200, 20), Point::new(240, 107))
+        .into_styled(PrimitiveStyle::with_stroke(Gray2::BLACK, 2))
+        .draw(frame)
+        .ok();
+}
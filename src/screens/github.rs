@@ -0,0 +1,164 @@
+//! Notifications and a status box per repository, see [crate::apps::github].
+
+use alloc::{format, string::String};
+
+use embedded_graphics::{
+    mono_font::MonoTextStyle,
+    pixelcolor::Gray2,
+    prelude::*,
+    primitives::{Line, PrimitiveStyle, Rectangle},
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
+};
+
+use crate::{
+    config::keys,
+    display::{Frame, WIDTH},
+    fonts::{FIXED_6X10, FIXED_7X14_BOLD},
+    tr,
+    ui::button_bar::draw_button_hints,
+};
+
+const TITLE_HEIGHT: i32 = 18;
+const ROW_HEIGHT: i32 = 19;
+const BOX_SIZE: u32 = 13;
+
+/// How the latest workflow run of a repository went
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CiState {
+    Passing,
+    /// Queued, running, or stopped without a verdict
+    Pending,
+    Failing,
+    /// No runs, or they couldn't be fetched
+    Unknown,
+}
+
+impl CiState {
+    /// From a run's `status` and `conclusion`
+    pub fn of_run(status: &str, conclusion: Option<&str>) -> Self {
+        if status != "completed" {
+            return CiState::Pending;
+        }
+        match conclusion {
+            Some("success" | "neutral" | "skipped") => CiState::Passing,
+            Some("failure" | "timed_out" | "startup_failure") => CiState::Failing,
+            Some(_) => CiState::Pending,
+            None => CiState::Unknown,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            CiState::Passing => tr!("ci_passing"),
+            CiState::Pending => tr!("ci_pending"),
+            CiState::Failing => tr!("ci_failing"),
+            CiState::Unknown => tr!("unknown"),
+        }
+    }
+}
+
+/// One entry of `github_repos`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Repo {
+    /// `owner/name`
+    pub name: String,
+    pub branch: Option<String>,
+    pub state: CiState,
+    /// The workflow of the latest run
+    pub workflow: Option<String>,
+}
+
+/// `notifications` in the title, if known, then a row for each repository;
+/// without `configured` a line saying what to set instead
+pub fn draw(frame: &mut Frame, notifications: Option<u32>, configured: bool, repos: &[Repo]) {
+    let title_style = MonoTextStyle::new(&FIXED_7X14_BOLD, Gray2::BLACK);
+    let small = MonoTextStyle::new(&FIXED_6X10, Gray2::BLACK);
+    let title = match notifications {
+        Some(1) => String::from(tr!("github_notification")),
+        Some(count) => tr!("github_notifications", count),
+        None => String::from("GitHub"),
+    };
+    Text::with_baseline(&title, Point::new(4, 2), title_style, Baseline::Top)
+        .draw(frame)
+        .ok();
+    if !configured {
+        let message = tr!("github_unconfigured", keys::GITHUB_API);
+        Text::with_baseline(
+            &message,
+            Point::new(4, TITLE_HEIGHT + 4),
+            small,
+            Baseline::Top,
+        )
+        .draw(frame)
+        .ok();
+    }
+
+    let right = TextStyleBuilder::new()
+        .alignment(Alignment::Right)
+        .baseline(Baseline::Top)
+        .build();
+    for (i, repo) in repos.iter().enumerate() {
+        let top = TITLE_HEIGHT + i as i32 * ROW_HEIGHT;
+        let status_box = Rectangle::new(
+            Point::new(4, top + (ROW_HEIGHT - BOX_SIZE as i32) / 2),
+            Size::new_equal(BOX_SIZE),
+        );
+        draw_status_box(frame, status_box, repo.state);
+        let name = match &repo.branch {
+            Some(branch) => format!("{}@{}", repo.name, branch),
+            None => repo.name.clone(),
+        };
+        let label = match &repo.workflow {
+            Some(workflow) => format!("{} - {}", repo.state.label(), workflow),
+            None => String::from(repo.state.label()),
+        };
+        Text::with_baseline(&name, Point::new(24, top + 3), small, Baseline::Top)
+            .draw(frame)
+            .ok();
+        Text::with_text_style(&label, Point::new(WIDTH as i32 - 4, top + 3), small, right)
+            .draw(frame)
+            .ok();
+    }
+
+    draw_button_hints(frame, [None, Some(tr!("update")), None, None]);
+}
+
+/// Red, yellow and green in four grays
+fn draw_status_box(frame: &mut Frame, area: Rectangle, state: CiState) {
+    let fill = match state {
+        CiState::Passing | CiState::Unknown => Gray2::WHITE,
+        CiState::Pending => Gray2::new(2),
+        CiState::Failing => Gray2::BLACK,
+    };
+    let style = PrimitiveStyle::with_fill(fill);
+    area.into_styled(style).draw(frame).ok();
+    area.into_styled(PrimitiveStyle::with_stroke(Gray2::BLACK, 1))
+        .draw(frame)
+        .ok();
+    let at = area.top_left;
+    match state {
+        CiState::Passing => {
+            let stroke = PrimitiveStyle::with_stroke(Gray2::BLACK, 2);
+            Line::new(at + Point::new(3, 6), at + Point::new(5, 9))
+                .into_styled(stroke)
+                .draw(frame)
+                .ok();
+            Line::new(at + Point::new(5, 9), at + Point::new(10, 3))
+                .into_styled(stroke)
+                .draw(frame)
+                .ok();
+        }
+        CiState::Failing => {
+            let stroke = PrimitiveStyle::with_stroke(Gray2::WHITE, 2);
+            Line::new(at + Point::new(3, 3), at + Point::new(9, 9))
+                .into_styled(stroke)
+                .draw(frame)
+                .ok();
+            Line::new(at + Point::new(9, 3), at + Point::new(3, 9))
+                .into_styled(stroke)
+                .draw(frame)
+                .ok();
+        }
+        CiState::Pending | CiState::Unknown => {}
+    }
+}
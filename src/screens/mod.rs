@@ -0,0 +1,19 @@
+//! What each of the [bundled apps](crate::apps) draws, from the app's state
+//! handed in as plain data.
+//!
+//! Nothing here touches the radio, the network or the sensors, so
+//! `tools/screenshots` draws every screen on the host.
+
+pub mod badge_game;
+pub mod dashboard;
+pub mod demo;
+pub mod github;
+pub mod mqtt_display;
+pub mod remote_display;
+pub mod safe_mode;
+pub mod selftest;
+pub mod settings;
+pub mod status;
+pub mod steps;
+pub mod tasks;
+pub mod wifi_survey;
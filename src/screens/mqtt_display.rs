@@ -0,0 +1,102 @@
+//! The MQTT display's slots, see [crate::apps::mqtt_display].
+
+use alloc::string::String;
+
+use embedded_graphics::{
+    mono_font::{ascii::FONT_10X20, MonoTextStyle},
+    pixelcolor::Gray2,
+    prelude::*,
+    primitives::Rectangle,
+    text::{Baseline, Text},
+};
+
+use crate::{
+    config::keys,
+    data_source::Stale,
+    display::{Frame, HEIGHT, WIDTH},
+    fonts::FIXED_6X10,
+    tr,
+    ui::{stale::draw_stale_banner, template::MISSING},
+    Error,
+};
+
+/// Slots in two columns of three
+const COLUMNS: u32 = 2;
+const ROWS: u32 = 3;
+const MARGIN: i32 = 4;
+
+/// Each slot's name and value, `None` until one arrives; no slots says what
+/// to set up and `Err` that `mqtt_slots` doesn't parse
+pub fn draw(
+    frame: &mut Frame,
+    slots: Result<&[(&str, Option<String>)], Error>,
+    stale: Option<&Stale>,
+) {
+    let slots = match slots {
+        Err(err) => {
+            let message = tr!("bad_key", keys::MQTT_SLOTS, err);
+            return draw_message(frame, &message);
+        }
+        Ok(slots) if !slots.is_empty() => slots,
+        Ok(_) => {
+            let message = tr!("set_keys", keys::MQTT_DISPLAY, keys::MQTT_SLOTS);
+            return draw_message(frame, &message);
+        }
+    };
+    let name_style = MonoTextStyle::new(&FIXED_6X10, Gray2::BLACK);
+    let value_style = MonoTextStyle::new(&FONT_10X20, Gray2::BLACK);
+    for (index, (name, value)) in slots.iter().enumerate() {
+        let area = slot_area(index);
+        Text::with_baseline(name, area.top_left, name_style, Baseline::Top)
+            .draw(frame)
+            .ok();
+        let value = value.as_deref().unwrap_or(MISSING);
+        let fits = (area.size.width / FONT_10X20.character_size.width) as usize;
+        let end = value
+            .char_indices()
+            .nth(fits)
+            .map_or(value.len(), |(i, _)| i);
+        let value_area = value_area(area);
+        Text::with_baseline(
+            &value[..end],
+            value_area.top_left,
+            value_style,
+            Baseline::Top,
+        )
+        .draw(frame)
+        .ok();
+    }
+    if let Some(stale) = stale {
+        draw_stale_banner(frame, stale);
+    }
+}
+
+fn draw_message(frame: &mut Frame, message: &str) {
+    let style = MonoTextStyle::new(&FIXED_6X10, Gray2::BLACK);
+    Text::with_baseline(message, Point::new(MARGIN, MARGIN), style, Baseline::Top)
+        .draw(frame)
+        .ok();
+}
+
+/// Where slot `index` goes, filling columns left to right
+pub fn slot_area(index: usize) -> Rectangle {
+    let width = (WIDTH - 2 * MARGIN as u32) / COLUMNS;
+    let height = (HEIGHT - 2 * MARGIN as u32) / ROWS;
+    let (column, row) = (index as u32 / ROWS, index as u32 % ROWS);
+    Rectangle::new(
+        Point::new(
+            MARGIN + (column * width) as i32,
+            MARGIN + (row * height) as i32,
+        ),
+        Size::new(width - MARGIN as u32, height),
+    )
+}
+
+/// The part of a slot under its name
+pub fn value_area(slot: Rectangle) -> Rectangle {
+    let name_height = FIXED_6X10.character_size.height + 1;
+    Rectangle::new(
+        slot.top_left + Point::new(0, name_height as i32),
+        Size::new(slot.size.width, slot.size.height - name_height),
+    )
+}
@@ -0,0 +1,40 @@
+//! The last frame pushed to the remote display, see
+//! [crate::apps::remote_display].
+
+use embedded_graphics::{
+    image::{Image, ImageRaw},
+    mono_font::MonoTextStyle,
+    pixelcolor::Gray2,
+    prelude::*,
+    text::{Baseline, Text},
+};
+
+use crate::{
+    display::{Frame, WIDTH},
+    fonts::FIXED_7X14_BOLD,
+    tr,
+};
+
+/// `pixels`, a decoded frame, or where to send one until the first arrives
+pub fn draw(frame: &mut Frame, pixels: Option<&[u8]>, address: &str) {
+    match pixels {
+        Some(pixels) => {
+            let raw = ImageRaw::<Gray2>::new(pixels, WIDTH);
+            Image::new(&raw, Point::zero()).draw(frame).ok();
+        }
+        None => {
+            let style = MonoTextStyle::new(&FIXED_7X14_BOLD, Gray2::BLACK);
+            Text::with_baseline(
+                tr!("waiting_for_frame"),
+                Point::new(10, 40),
+                style,
+                Baseline::Top,
+            )
+            .draw(frame)
+            .ok();
+            Text::with_baseline(address, Point::new(10, 60), style, Baseline::Top)
+                .draw(frame)
+                .ok();
+        }
+    }
+}
@@ -0,0 +1,39 @@
+//! The safe mode placeholder, see [crate::apps::safe_mode].
+
+use embedded_graphics::{
+    mono_font::MonoTextStyle,
+    pixelcolor::Gray2,
+    prelude::*,
+    text::{Baseline, Text},
+};
+
+use crate::{
+    display::Frame,
+    fonts::{FIXED_6X10, FIXED_7X14_BOLD},
+    tr,
+};
+
+pub fn draw(frame: &mut Frame) {
+    let title = MonoTextStyle::new(&FIXED_7X14_BOLD, Gray2::BLACK);
+    Text::with_baseline(tr!("safe_mode"), Point::new(10, 10), title, Baseline::Top)
+        .draw(frame)
+        .ok();
+    let style = MonoTextStyle::new(&FIXED_6X10, Gray2::BLACK);
+    for (i, line) in [
+        tr!("safe_mode_apps"),
+        tr!("safe_mode_console"),
+        tr!("safe_mode_reset"),
+    ]
+    .iter()
+    .enumerate()
+    {
+        Text::with_baseline(
+            line,
+            Point::new(10, 36 + 14 * i as i32),
+            style,
+            Baseline::Top,
+        )
+        .draw(frame)
+        .ok();
+    }
+}
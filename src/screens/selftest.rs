@@ -0,0 +1,202 @@
+//! The self-test's steps and its summary, see [crate::apps::selftest].
+
+use alloc::{format, string::String};
+
+use embedded_graphics::{
+    mono_font::{MonoFont, MonoTextStyle},
+    pixelcolor::Gray2,
+    prelude::*,
+    primitives::{PrimitiveStyle, Rectangle},
+    text::{Baseline, Text},
+};
+
+use crate::{
+    display::{Frame, HEIGHT, WIDTH},
+    fonts::{FIXED_6X10, FIXED_7X14_BOLD},
+    input::{Button, ButtonSet},
+    tr,
+    ui::button_bar::{draw_button_hints, BUTTON_BAR_HEIGHT},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    DisplayBlack,
+    DisplayGrays,
+    DisplayChecker,
+    NeoPixels,
+    Speaker,
+    Buttons,
+    Accelerometer,
+    Light,
+    Battery,
+    WifiScan,
+}
+
+impl Step {
+    pub const ALL: [Step; 10] = [
+        Step::DisplayBlack,
+        Step::DisplayGrays,
+        Step::DisplayChecker,
+        Step::NeoPixels,
+        Step::Speaker,
+        Step::Buttons,
+        Step::Accelerometer,
+        Step::Light,
+        Step::Battery,
+        Step::WifiScan,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Step::DisplayBlack => "black",
+            Step::DisplayGrays => "grays",
+            Step::DisplayChecker => "checker",
+            Step::NeoPixels => "neopixels",
+            Step::Speaker => "speaker",
+            Step::Buttons => "buttons",
+            Step::Accelerometer => "accel",
+            Step::Light => "light",
+            Step::Battery => "battery",
+            Step::WifiScan => "wifi",
+        }
+    }
+
+    /// The question for steps the operator judges
+    pub fn question(self) -> Option<&'static str> {
+        match self {
+            Step::DisplayBlack => Some(tr!("selftest_black")),
+            Step::DisplayGrays => Some(tr!("selftest_grays")),
+            Step::DisplayChecker => Some(tr!("selftest_checker")),
+            Step::NeoPixels => Some(tr!("selftest_neopixels")),
+            Step::Speaker => Some(tr!("selftest_speaker")),
+            _ => None,
+        }
+    }
+}
+
+/// How a step went
+pub struct Outcome {
+    pub step: Step,
+    pub passed: bool,
+    pub detail: String,
+}
+
+/// `step`'s test pattern, if it has one, and what the operator does; the
+/// buttons step ticks off those `pressed`
+pub fn draw_step(frame: &mut Frame, step: Step, pressed: ButtonSet) {
+    let area = Rectangle::new(Point::zero(), Size::new(WIDTH, HEIGHT));
+    match step {
+        Step::DisplayBlack => {
+            area.into_styled(PrimitiveStyle::with_fill(Gray2::BLACK))
+                .draw(frame)
+                .ok();
+        }
+        Step::DisplayGrays => {
+            for shade in 0..4u8 {
+                let band = WIDTH / 4;
+                Rectangle::new(
+                    Point::new((shade as u32 * band) as i32, 0),
+                    Size::new(band, HEIGHT),
+                )
+                .into_styled(PrimitiveStyle::with_fill(Gray2::new(shade)))
+                .draw(frame)
+                .ok();
+            }
+        }
+        Step::DisplayChecker => {
+            let size = 16;
+            for row in 0..HEIGHT / size {
+                for col in (row % 2..WIDTH / size).step_by(2) {
+                    Rectangle::new(
+                        Point::new((col * size) as i32, (row * size) as i32),
+                        Size::new(size, size),
+                    )
+                    .into_styled(PrimitiveStyle::with_fill(Gray2::BLACK))
+                    .draw(frame)
+                    .ok();
+                }
+            }
+        }
+        _ => {}
+    }
+
+    // title and hints on white strips so they read on any pattern
+    let number = Step::ALL.iter().position(|s| *s == step).unwrap_or(0) + 1;
+    let title = format!(
+        "{} {}/{}: {}",
+        tr!("selftest"),
+        number,
+        Step::ALL.len(),
+        step.name()
+    );
+    strip(frame, 0, 16);
+    text(frame, &title, Point::new(4, 1), &FIXED_7X14_BOLD);
+
+    if let Some(question) = step.question() {
+        strip(frame, 16, 12);
+        text(frame, question, Point::new(4, 17), &FIXED_6X10);
+        strip(
+            frame,
+            (HEIGHT - BUTTON_BAR_HEIGHT) as i32,
+            BUTTON_BAR_HEIGHT,
+        );
+        draw_button_hints(
+            frame,
+            [
+                Some(tr!("pass")),
+                Some(tr!("repeat")),
+                None,
+                Some(tr!("fail")),
+            ],
+        );
+    } else if step == Step::Buttons {
+        text(
+            frame,
+            tr!("press_all_buttons"),
+            Point::new(4, 24),
+            &FIXED_6X10,
+        );
+        let labels = Button::ALL.map(|b| pressed.contains(b).then_some("ok"));
+        draw_button_hints(frame, labels);
+    } else {
+        text(frame, tr!("measuring"), Point::new(4, 24), &FIXED_6X10);
+    }
+}
+
+/// Every step's verdict and detail in two columns
+pub fn draw_summary(frame: &mut Frame, results: &[Outcome]) {
+    let failed = results.iter().filter(|r| !r.passed).count();
+    let title = if failed == 0 {
+        String::from(tr!("selftest_passed"))
+    } else {
+        tr!("selftest_failed", failed)
+    };
+    text(frame, &title, Point::new(4, 1), &FIXED_7X14_BOLD);
+
+    let rows = Step::ALL.len().div_ceil(2);
+    for (i, result) in results.iter().enumerate() {
+        let x = if i < rows { 4 } else { WIDTH as i32 / 2 + 2 };
+        let y = 20 + (i % rows) as i32 * 18;
+        let verdict = if result.passed { "ok  " } else { "FAIL" };
+        let line = format!("{} {}", verdict, result.step.name());
+        text(frame, &line, Point::new(x, y), &FIXED_6X10);
+        // clipped to the column
+        let detail: String = result.detail.chars().take(23).collect();
+        text(frame, &detail, Point::new(x + 6, y + 9), &FIXED_6X10);
+    }
+    draw_button_hints(frame, [None, Some(tr!("again")), None, None]);
+}
+
+fn strip(frame: &mut Frame, top: i32, height: u32) {
+    Rectangle::new(Point::new(0, top), Size::new(WIDTH, height))
+        .into_styled(PrimitiveStyle::with_fill(Gray2::WHITE))
+        .draw(frame)
+        .ok();
+}
+
+fn text(frame: &mut Frame, line: &str, position: Point, font: &'static MonoFont<'static>) {
+    let style = MonoTextStyle::new(font, Gray2::BLACK);
+    Text::with_baseline(line, position, style, Baseline::Top)
+        .draw(frame)
+        .ok();
+}
@@ -0,0 +1,68 @@
+//! The settings fields and their values, see [crate::apps::settings].
+
+use alloc::{format, string::String, string::ToString};
+
+use embedded_graphics::{
+    mono_font::MonoTextStyle,
+    pixelcolor::Gray2,
+    prelude::*,
+    primitives::{PrimitiveStyle, Rectangle},
+    text::{Baseline, Text},
+};
+
+use crate::{
+    display::{Frame, WIDTH},
+    fonts::FIXED_7X14_BOLD,
+    tr,
+    ui::button_bar::draw_button_hints,
+};
+
+const ROW_HEIGHT: i32 = 16;
+
+/// Whether the values on screen are the stored ones
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Clean,
+    Modified,
+    Saved,
+    Failed,
+}
+
+/// A row of label and value per field, `selected` highlighted
+pub fn draw(frame: &mut Frame, status: Status, fields: &[(&str, String)], selected: usize) {
+    let title_style = MonoTextStyle::new(&FIXED_7X14_BOLD, Gray2::BLACK);
+    let title = match status {
+        Status::Clean => tr!("settings").to_string(),
+        Status::Modified => format!("{} *", tr!("settings")),
+        Status::Saved => format!("{} ({})", tr!("settings"), tr!("saved")),
+        Status::Failed => format!("{} ({})", tr!("settings"), tr!("save_failed")),
+    };
+    Text::with_baseline(&title, Point::new(4, 2), title_style, Baseline::Top)
+        .draw(frame)
+        .ok();
+
+    for (i, (label, value)) in fields.iter().enumerate() {
+        let top = 18 + i as i32 * ROW_HEIGHT;
+        let selected = i == selected;
+        let color = if selected { Gray2::WHITE } else { Gray2::BLACK };
+        if selected {
+            Rectangle::new(Point::new(0, top), Size::new(WIDTH, ROW_HEIGHT as u32))
+                .into_styled(PrimitiveStyle::with_fill(Gray2::BLACK))
+                .draw(frame)
+                .ok();
+        }
+
+        let style = MonoTextStyle::new(&FIXED_7X14_BOLD, color);
+        Text::with_baseline(label, Point::new(8, top + 1), style, Baseline::Top)
+            .draw(frame)
+            .ok();
+        Text::with_baseline(value, Point::new(120, top + 1), style, Baseline::Top)
+            .draw(frame)
+            .ok();
+    }
+
+    draw_button_hints(
+        frame,
+        [Some(tr!("next")), Some("-"), Some("+"), Some(tr!("save"))],
+    );
+}
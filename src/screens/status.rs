@@ -0,0 +1,33 @@
+//! The status lines, see [crate::apps::status].
+
+use alloc::string::String;
+
+use embedded_graphics::{
+    mono_font::MonoTextStyle,
+    pixelcolor::Gray2,
+    prelude::*,
+    text::{Baseline, Text},
+};
+
+use crate::{
+    display::Frame,
+    fonts::{FIXED_6X10, FIXED_7X14_BOLD},
+    tr,
+    ui::button_bar::draw_button_hints,
+};
+
+/// `lines` under the title, one per reading
+pub fn draw(frame: &mut Frame, lines: &[String]) {
+    let title_style = MonoTextStyle::new(&FIXED_7X14_BOLD, Gray2::BLACK);
+    Text::with_baseline(tr!("status"), Point::new(4, 1), title_style, Baseline::Top)
+        .draw(frame)
+        .ok();
+    let style = MonoTextStyle::new(&FIXED_6X10, Gray2::BLACK);
+    for (i, line) in lines.iter().enumerate() {
+        let y = 20 + i as i32 * 15;
+        Text::with_baseline(line, Point::new(4, y), style, Baseline::Top)
+            .draw(frame)
+            .ok();
+    }
+    draw_button_hints(frame, [None, Some(tr!("update")), None, None]);
+}
@@ -0,0 +1,62 @@
+//! Today's step count, see [crate::apps::steps].
+
+use alloc::format;
+
+use embedded_graphics::{
+    mono_font::{ascii::FONT_10X20, MonoTextStyle},
+    pixelcolor::Gray2,
+    prelude::*,
+    text::{Baseline, Text},
+};
+
+use crate::{
+    display::Frame,
+    fonts::{FIXED_6X10, FIXED_7X14_BOLD},
+    time, tr,
+    ui::button_bar::draw_button_hints,
+};
+
+/// The count, `None` when there is no accelerometer to count with
+pub fn draw(frame: &mut Frame, steps: Option<u32>) {
+    let title_style = MonoTextStyle::new(&FIXED_7X14_BOLD, Gray2::BLACK);
+    let count_style = MonoTextStyle::new(&FONT_10X20, Gray2::BLACK);
+    let style = MonoTextStyle::new(&FIXED_6X10, Gray2::BLACK);
+    Text::with_baseline(tr!("steps"), Point::new(4, 1), title_style, Baseline::Top)
+        .draw(frame)
+        .ok();
+    let Some(steps) = steps else {
+        Text::with_baseline(
+            tr!("no_accelerometer"),
+            Point::new(4, 24),
+            style,
+            Baseline::Top,
+        )
+        .draw(frame)
+        .ok();
+        return;
+    };
+    Text::with_baseline(
+        &format!("{}", steps),
+        Point::new(4, 30),
+        count_style,
+        Baseline::Top,
+    )
+    .draw(frame)
+    .ok();
+    let since = match time::now_local() {
+        Some(_) => "today, since local midnight",
+        None => "clock not set, counting on",
+    };
+    Text::with_baseline(since, Point::new(4, 56), style, Baseline::Top)
+        .draw(frame)
+        .ok();
+    Text::with_baseline(
+        tr!("steps_counted"),
+        Point::new(4, 70),
+        style,
+        Baseline::Top,
+    )
+    .draw(frame)
+    .ok();
+    draw_button_hints(frame, [None, Some(tr!("update")), None, None]);
+}
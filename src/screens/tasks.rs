@@ -0,0 +1,142 @@
+//! The task list with its checkboxes, see [crate::apps::tasks].
+
+use alloc::{format, string::String, vec::Vec};
+
+use embedded_graphics::{
+    mono_font::MonoTextStyle,
+    pixelcolor::Gray2,
+    prelude::*,
+    primitives::{Line, PrimitiveStyle, Rectangle},
+    text::{Baseline, Text},
+};
+use jiff::civil::Date;
+
+use crate::{
+    config::keys,
+    display::{Frame, HEIGHT, WIDTH},
+    fonts::{FIXED_6X10, FIXED_7X14_BOLD},
+    time, tr,
+    ui::button_bar::{draw_button_hints, BUTTON_BAR_HEIGHT},
+    Error,
+};
+
+const TITLE_HEIGHT: i32 = 18;
+const ROW_HEIGHT: i32 = 20;
+const CHECKBOX_SIZE: u32 = 10;
+
+/// One entry on the list
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Task {
+    /// What the provider knows the task by
+    pub id: String,
+    pub title: String,
+    pub due: Option<Date>,
+    pub done: bool,
+}
+
+/// `tasks` as loaded, scrolled to `selected`; `None` when there is no task
+/// list to load from
+pub fn draw(frame: &mut Frame, tasks: Option<&Result<Vec<Task>, Error>>, selected: usize) {
+    let title_style = MonoTextStyle::new(&FIXED_7X14_BOLD, Gray2::BLACK);
+    let small = MonoTextStyle::new(&FIXED_6X10, Gray2::BLACK);
+    let tasks = match tasks {
+        Some(Ok(tasks)) => tasks,
+        Some(Err(err)) => {
+            let message = tr!("tasks_unavailable", err);
+            Text::with_baseline(&message, Point::new(4, 4), small, Baseline::Top)
+                .draw(frame)
+                .ok();
+            draw_button_hints(frame, [None, Some(tr!("reload")), None, None]);
+            return;
+        }
+        None => {
+            let message = tr!("tasks_unconfigured", keys::TASKS_URL);
+            Text::with_baseline(&message, Point::new(4, 4), small, Baseline::Top)
+                .draw(frame)
+                .ok();
+            return;
+        }
+    };
+
+    let open = tasks.iter().filter(|task| !task.done).count();
+    let title = tr!("tasks_title", open);
+    Text::with_baseline(&title, Point::new(4, 2), title_style, Baseline::Top)
+        .draw(frame)
+        .ok();
+    if tasks.is_empty() {
+        Text::with_baseline(
+            tr!("nothing_due"),
+            Point::new(4, TITLE_HEIGHT + 4),
+            small,
+            Baseline::Top,
+        )
+        .draw(frame)
+        .ok();
+    }
+
+    let rows = (HEIGHT as i32 - TITLE_HEIGHT - BUTTON_BAR_HEIGHT as i32) / ROW_HEIGHT;
+    let rows = rows.max(1) as usize;
+    // scrolled so the selection is on screen
+    let first = selected.saturating_sub(rows - 1);
+    let today = time::now_local().map(|now| now.date());
+    for (row, (index, task)) in tasks.iter().enumerate().skip(first).take(rows).enumerate() {
+        let top = TITLE_HEIGHT + row as i32 * ROW_HEIGHT;
+        let selected = index == selected;
+        let color = if selected { Gray2::WHITE } else { Gray2::BLACK };
+        if selected {
+            Rectangle::new(Point::new(0, top), Size::new(WIDTH, ROW_HEIGHT as u32))
+                .into_styled(PrimitiveStyle::with_fill(Gray2::BLACK))
+                .draw(frame)
+                .ok();
+        }
+        let checkbox = Rectangle::new(
+            Point::new(6, top + (ROW_HEIGHT - CHECKBOX_SIZE as i32) / 2),
+            Size::new_equal(CHECKBOX_SIZE),
+        );
+        checkbox
+            .into_styled(PrimitiveStyle::with_stroke(color, 1))
+            .draw(frame)
+            .ok();
+        if task.done {
+            let (a, b) = (
+                checkbox.top_left,
+                checkbox.bottom_right().unwrap_or(checkbox.top_left),
+            );
+            let stroke = PrimitiveStyle::with_stroke(color, 2);
+            Line::new(a + Point::new(2, 5), a + Point::new(4, 7))
+                .into_styled(stroke)
+                .draw(frame)
+                .ok();
+            Line::new(a + Point::new(4, 7), b - Point::new(1, 7))
+                .into_styled(stroke)
+                .draw(frame)
+                .ok();
+        }
+        // the date only for overdue tasks
+        let overdue = task
+            .due
+            .zip(today)
+            .filter(|(due, today)| due < today)
+            .map(|(due, _)| format!("{:02}-{:02} ", due.month(), due.day()));
+        let label = format!("{}{}", overdue.unwrap_or_default(), task.title);
+        let style = MonoTextStyle::new(&FIXED_7X14_BOLD, color);
+        let fits = ((WIDTH - 24) / FIXED_7X14_BOLD.character_size.width) as usize;
+        let end = label
+            .char_indices()
+            .nth(fits)
+            .map_or(label.len(), |(i, _)| i);
+        Text::with_baseline(&label[..end], Point::new(22, top + 3), style, Baseline::Top)
+            .draw(frame)
+            .ok();
+    }
+
+    draw_button_hints(
+        frame,
+        [
+            Some(tr!("up")),
+            Some(tr!("reload")),
+            Some(tr!("down")),
+            Some(tr!("done")),
+        ],
+    );
+}
@@ -0,0 +1,127 @@
+//! The access points from the last scan, see [crate::apps::wifi_survey].
+
+use alloc::{format, string::String};
+
+use embedded_graphics::{
+    mono_font::MonoTextStyle,
+    pixelcolor::Gray2,
+    prelude::*,
+    primitives::{PrimitiveStyle, Rectangle},
+    text::{Baseline, Text},
+};
+
+use crate::{
+    display::Frame,
+    fonts::{FIXED_6X10, FIXED_7X14_BOLD},
+    tr,
+    ui::button_bar::draw_button_hints,
+    Error,
+};
+
+/// Access points listed, strongest first
+pub const ROWS: usize = 7;
+
+const ROW_HEIGHT: i32 = 12;
+const TOP: i32 = 30;
+const MAX_SSID_CHARS: usize = 20;
+// column positions
+const SSID_X: i32 = 10;
+const CHANNEL_X: i32 = 132;
+const BARS_X: i32 = 156;
+const RSSI_X: i32 = 180;
+const SECURITY_X: i32 = 220;
+
+/// One access point as listed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Network<'a> {
+    /// Empty for a hidden network
+    pub ssid: &'a str,
+    pub channel: u8,
+    pub rssi: i8,
+    pub security: &'static str,
+}
+
+/// The first [ROWS] of `scan`, with `selected` marked; `None` before the
+/// first scan
+pub fn draw(frame: &mut Frame, scan: Option<Result<&[Network<'_>], Error>>, selected: usize) {
+    let title_style = MonoTextStyle::new(&FIXED_7X14_BOLD, Gray2::BLACK);
+    let style = MonoTextStyle::new(&FIXED_6X10, Gray2::BLACK);
+    let text = |frame: &mut Frame, line: &str, x: i32, y: i32| {
+        Text::with_baseline(line, Point::new(x, y), style, Baseline::Top)
+            .draw(frame)
+            .ok();
+    };
+
+    let networks = match scan {
+        Some(Ok(networks)) => networks,
+        Some(Err(err)) => {
+            text(frame, &tr!("scan_failed", err), 4, 20);
+            draw_button_hints(frame, [None, Some(tr!("scan")), None, None]);
+            return;
+        }
+        None => return,
+    };
+    let title = tr!("wifi_survey_title", networks.len());
+    Text::with_baseline(&title, Point::new(4, 1), title_style, Baseline::Top)
+        .draw(frame)
+        .ok();
+
+    text(frame, "SSID", SSID_X, 17);
+    text(frame, "ch", CHANNEL_X, 17);
+    text(frame, "dBm", RSSI_X, 17);
+    text(frame, tr!("security"), SECURITY_X, 17);
+    for (i, ap) in networks.iter().take(ROWS).enumerate() {
+        let y = TOP + i as i32 * ROW_HEIGHT;
+        let ssid: String = if ap.ssid.is_empty() {
+            String::from(tr!("hidden"))
+        } else {
+            ap.ssid.chars().take(MAX_SSID_CHARS).collect()
+        };
+        if i == selected {
+            text(frame, ">", 2, y);
+        }
+        text(frame, &ssid, SSID_X, y);
+        text(frame, &format!("{}", ap.channel), CHANNEL_X, y);
+        draw_bars(frame, Point::new(BARS_X, y), bars(ap.rssi));
+        text(frame, &format!("{}", ap.rssi), RSSI_X, y);
+        text(frame, ap.security, SECURITY_X, y);
+    }
+    draw_button_hints(
+        frame,
+        [
+            Some(tr!("up")),
+            Some(tr!("scan")),
+            Some(tr!("down")),
+            Some(tr!("join")),
+        ],
+    );
+}
+
+/// Signal bars out of four, roughly as phones show them
+fn bars(rssi: i8) -> u8 {
+    match rssi {
+        -55.. => 4,
+        -67.. => 3,
+        -75.. => 2,
+        -85.. => 1,
+        _ => 0,
+    }
+}
+
+/// Four bars of rising height standing on the bottom of a text row starting
+/// at `top_left`; the first `level` are filled
+fn draw_bars(frame: &mut Frame, top_left: Point, level: u8) {
+    for bar in 0..4u8 {
+        let height = 3 + 2 * bar as u32;
+        let origin = top_left + Point::new(bar as i32 * 5, 9 - height as i32);
+        let style = if bar < level {
+            PrimitiveStyle::with_fill(Gray2::BLACK)
+        } else {
+            PrimitiveStyle::with_stroke(Gray2::new(0x01), 1)
+        };
+        Rectangle::new(origin, Size::new(4, height))
+            .into_styled(style)
+            .draw(frame)
+            .ok();
+    }
+}
@@ -0,0 +1,297 @@
+//! A FAT formatted SD card for files too big or too many for the asset
+//! partition, such as slideshows and icon packs. Built with `sdcard`.
+//!
+//! The card is on the free GPIOs named by the `sd_pins` config entry
+//! ([SdPins](crate::board::SdPins)): either SPI3 to itself at up to 20 MHz,
+//! or just a CS pin on the panel's bus at the panel's 4 MHz. Paths are 8.3 names
+//! separated by `/`, such as `SLIDES/CAT01.BMP`, in any case. Long names
+//! aren't looked up, and directories have to be made on a computer.
+//!
+//! [SdStore] is the card as a [BlobStore].
+
+use alloc::{string::String, vec::Vec};
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+use embedded_sdmmc::{Mode, SdCard, SdCardError, TimeSource, Timestamp, VolumeIdx, VolumeManager};
+use esp_hal::{
+    delay::Delay,
+    peripherals::SPI3,
+    spi::{self, master::Spi},
+    time::Rate,
+};
+use log::{info, warn};
+
+use crate::{
+    board::{self, SdBus, SpiBus, SpiDevice},
+    display, psram,
+    storage::BlobStore,
+    time, Error,
+};
+
+/// Cards only answer at up to 400 kHz until they are initialized
+const INIT_RATE: Rate = Rate::from_khz(400);
+/// The most any card takes in SPI mode
+const RATE: Rate = Rate::from_mhz(20);
+
+type Card = SdCard<SpiDevice, Delay>;
+/// One volume, and one directory and file open at a time
+type Volumes = VolumeManager<Card, Clock, 2, 1, 1>;
+type Directory<'a> = embedded_sdmmc::Directory<'a, Card, Clock, 2, 1, 1>;
+type SdError = embedded_sdmmc::Error<SdCardError>;
+
+static CARD: Mutex<RefCell<Option<Volumes>>> = Mutex::new(RefCell::new(None));
+
+/// A file or directory on the card
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// As stored, upper case
+    pub name: String,
+    pub len: u32,
+    pub is_dir: bool,
+}
+
+/// Local time for the FAT driver to stamp files with
+struct Clock;
+
+impl TimeSource for Clock {
+    fn get_timestamp(&self) -> Timestamp {
+        // FAT dates start in 1980
+        let Some(now) = time::now_local().filter(|now| now.year() >= 1980) else {
+            return Timestamp {
+                year_since_1970: 10,
+                zero_indexed_month: 0,
+                zero_indexed_day: 0,
+                hours: 0,
+                minutes: 0,
+                seconds: 0,
+            };
+        };
+        Timestamp {
+            year_since_1970: (now.year() - 1970).min(255) as u8,
+            zero_indexed_month: now.month() as u8 - 1,
+            zero_indexed_day: now.day() as u8 - 1,
+            hours: now.hour() as u8,
+            minutes: now.minute() as u8,
+            seconds: now.second() as u8,
+        }
+    }
+}
+
+/// Mounts the card on SPI3 if `sd` has a bus of its own, or else on the
+/// `panel`'s. A missing or unreadable card is [Error::NotFound]; the other
+/// functions then fail the same way.
+pub fn init(sd: SdBus, spi3: SPI3<'static>, panel: &'static SpiBus) -> Result<(), Error> {
+    let config = spi::master::Config::default().with_frequency(INIT_RATE);
+    let (bus, rate) = match sd.spi {
+        Some((sclk, mosi, miso)) => {
+            let spi = Spi::new(spi3, config)
+                .map_err(|_| Error::Storage)?
+                .with_sck(sclk)
+                .with_mosi(mosi)
+                .with_miso(miso);
+            (board::share_spi(spi), RATE)
+        }
+        None => (panel, display::SPI_RATE),
+    };
+    let set_rate = |rate| {
+        critical_section::with(|cs| {
+            bus.borrow_ref_mut(cs)
+                .apply_config(&config.with_frequency(rate))
+        })
+        .map_err(|_| Error::Storage)
+    };
+    // the panel is idle between refreshes, so it can have its bus slowed
+    set_rate(INIT_RATE)?;
+    let card = SdCard::new(board::spi_device(bus, sd.cs), Delay::new());
+    let bytes = card.num_bytes();
+    set_rate(rate)?;
+    let bytes = bytes.map_err(|err| {
+        warn!("No SD card: {:?}", err);
+        Error::NotFound
+    })?;
+    info!("SD card, {} MiB", bytes >> 20);
+    let volumes = VolumeManager::new_with_limits(card, Clock, 0);
+    critical_section::with(|cs| CARD.borrow_ref_mut(cs).replace(volumes));
+    Ok(())
+}
+
+/// Whether a card was mounted by [init]
+pub fn is_present() -> bool {
+    critical_section::with(|cs| CARD.borrow_ref(cs).is_some())
+}
+
+/// What is in the directory at `path`, `""` for the top
+pub fn list(path: &str) -> Result<Vec<Entry>, Error> {
+    with_dir(path, |dir| {
+        let mut entries = Vec::new();
+        dir.iterate_dir(|entry| {
+            let name = alloc::format!("{}", entry.name);
+            if entry.attributes.is_volume() || name == "." || name == ".." {
+                return;
+            }
+            entries.push(Entry {
+                name,
+                len: entry.size,
+                is_dir: entry.attributes.is_directory(),
+            });
+        })?;
+        Ok(entries)
+    })
+}
+
+/// Reads from the file at `path` from `offset`, returning how many bytes
+/// were read: fewer than `buf` holds only at the end of the file
+pub fn read(path: &str, offset: u32, buf: &mut [u8]) -> Result<usize, Error> {
+    let (dir, name) = split(path);
+    with_dir(dir, |dir| {
+        let file = dir.open_file_in_dir(name, Mode::ReadOnly)?;
+        file.seek_from_start(offset.min(file.length()))?;
+        let mut len = 0;
+        while len < buf.len() && !file.is_eof() {
+            len += file.read(&mut buf[len..])?;
+        }
+        Ok(len)
+    })
+}
+
+/// Reads the whole file at `path`
+pub fn read_to_vec(path: &str) -> Result<Vec<u8>, Error> {
+    let (dir, name) = split(path);
+    with_dir(dir, |dir| {
+        let file = dir.open_file_in_dir(name, Mode::ReadOnly)?;
+        let mut data = psram::zeroed(file.length() as usize);
+        let mut len = 0;
+        while len < data.len() && !file.is_eof() {
+            len += file.read(&mut data[len..])?;
+        }
+        data.truncate(len);
+        Ok(data)
+    })
+}
+
+/// The length of the file at `path`
+pub fn len(path: &str) -> Result<u32, Error> {
+    let (dir, name) = split(path);
+    with_dir(dir, |dir| Ok(dir.find_directory_entry(name)?.size))
+}
+
+/// Stores `data` as the file at `path`, replacing what was there. The
+/// directory has to exist.
+pub fn write(path: &str, data: &[u8]) -> Result<(), Error> {
+    let (dir, name) = split(path);
+    with_dir(dir, |dir| {
+        let file = dir.open_file_in_dir(name, Mode::ReadWriteCreateOrTruncate)?;
+        file.write(data)?;
+        file.close()
+    })
+}
+
+/// Adds `data` to the end of the file at `path`, making the file if it
+/// isn't there. The directory has to exist.
+pub fn append(path: &str, data: &[u8]) -> Result<(), Error> {
+    let (dir, name) = split(path);
+    with_dir(dir, |dir| {
+        let file = dir.open_file_in_dir(name, Mode::ReadWriteCreateOrAppend)?;
+        file.write(data)?;
+        file.close()
+    })
+}
+
+/// Deletes the file at `path`
+pub fn remove(path: &str) -> Result<(), Error> {
+    let (dir, name) = split(path);
+    with_dir(dir, |dir| dir.delete_file_in_dir(name))
+}
+
+/// The files in one directory of the card, by name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SdStore {
+    dir: String,
+}
+
+impl SdStore {
+    /// The files in `dir`, `""` for the top; `dir` has to exist
+    pub fn new(dir: &str) -> Self {
+        Self {
+            dir: String::from(dir.trim_matches('/')),
+        }
+    }
+
+    fn path(&self, name: &str) -> String {
+        if self.dir.is_empty() {
+            String::from(name)
+        } else {
+            alloc::format!("{}/{}", self.dir, name)
+        }
+    }
+}
+
+impl BlobStore for SdStore {
+    fn len(&self, name: &str) -> Result<u32, Error> {
+        len(&self.path(name))
+    }
+
+    fn read(&self, name: &str, offset: u32, buf: &mut [u8]) -> Result<usize, Error> {
+        read(&self.path(name), offset, buf)
+    }
+
+    fn read_to_vec(&self, name: &str) -> Result<Vec<u8>, Error> {
+        read_to_vec(&self.path(name))
+    }
+
+    fn write(&mut self, name: &str, data: &[u8]) -> Result<(), Error> {
+        write(&self.path(name), data)
+    }
+
+    fn append(&mut self, name: &str, data: &[u8]) -> Result<(), Error> {
+        append(&self.path(name), data)
+    }
+
+    fn remove(&mut self, name: &str) -> Result<(), Error> {
+        remove(&self.path(name))
+    }
+
+    fn names(&self) -> Result<Vec<String>, Error> {
+        Ok(list(&self.dir)?
+            .into_iter()
+            .filter(|entry| !entry.is_dir)
+            .map(|entry| entry.name)
+            .collect())
+    }
+}
+
+/// `path` as its directory and the name in it
+fn split(path: &str) -> (&str, &str) {
+    path.rsplit_once('/').unwrap_or(("", path))
+}
+
+/// Runs `f` on the directory at `path`
+fn with_dir<R>(
+    path: &str,
+    f: impl FnOnce(&Directory<'_>) -> Result<R, SdError>,
+) -> Result<R, Error> {
+    // Taken out rather than used in the critical section, which would hold
+    // off the radio for as long as a big file takes to read
+    let volumes =
+        critical_section::with(|cs| CARD.borrow_ref_mut(cs).take()).ok_or(Error::NotFound)?;
+    let result = (|| {
+        let volume = volumes.open_volume(VolumeIdx(0))?;
+        let mut dir = volume.open_root_dir()?;
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            dir.change_dir(name)?;
+        }
+        f(&dir)
+    })();
+    critical_section::with(|cs| CARD.borrow_ref_mut(cs).replace(volumes));
+    result.map_err(|err| match err {
+        embedded_sdmmc::Error::NotFound => Error::NotFound,
+        embedded_sdmmc::Error::DiskFull | embedded_sdmmc::Error::NotEnoughSpace => {
+            Error::StorageFull
+        }
+        err => {
+            warn!("SD card {}: {:?}", path, err);
+            Error::Storage
+        }
+    })
+}
@@ -0,0 +1,170 @@
+//! Encryption of the config values in [keys::SECRETS] before they go to
+//! flash, so reading the `nvs` partition off a lost badge with esptool
+//! doesn't give away the home WiFi password. Built with `encrypted-secrets`.
+//!
+//! The key is a 256-bit eFuse key block burned once with purpose `HMAC_UP`,
+//! after which the chip can still use it through the HMAC peripheral but
+//! nothing can read it out:
+//!
+//! ```text
+//! dd if=/dev/urandom of=key.bin bs=32 count=1
+//! espefuse.py burn_key BLOCK_KEY0 key.bin HMAC_UP
+//! ```
+//!
+//! Burning is permanent and takes a key block that flash encryption or
+//! secure boot could use; the chip's own flash encryption covers the whole
+//! of flash instead and needs none of this. Without a key the values are
+//! stored in the clear as before, with a warning.
+//!
+//! A sealed value is `enc:` and the hex of an 8-byte random nonce, the
+//! value XORed with HMAC-SHA256 of the nonce and a block counter, and the
+//! first 8 bytes of an HMAC over the key name, nonce and ciphertext.
+//!
+//! [keys::SECRETS]: crate::config::keys::SECRETS
+
+use alloc::{string::String, vec::Vec};
+use core::{cell::RefCell, fmt::Write};
+
+use critical_section::Mutex;
+use esp_hal::{
+    hmac::{Hmac, HmacPurpose, KeyId},
+    peripherals::HMAC,
+    rng::Rng,
+};
+use log::{info, warn};
+
+use crate::Error;
+
+/// The eFuse key block holding the key
+const KEY: KeyId = KeyId::Key0;
+const PREFIX: &str = "enc:";
+const NONCE_LEN: usize = 8;
+const TAG_LEN: usize = 8;
+const BLOCK_LEN: usize = 32;
+
+static HMAC_UNIT: Mutex<RefCell<Option<Hmac<'static>>>> = Mutex::new(RefCell::new(None));
+
+/// Takes the HMAC peripheral; call before [ConfigStore::load]. Without a key
+/// burned for it, values are left as they are.
+///
+/// [ConfigStore::load]: crate::config::ConfigStore::load
+pub fn init(hmac: HMAC<'static>) {
+    let mut hmac = Hmac::new(hmac);
+    let mut probe = [0u8; BLOCK_LEN];
+    if mac(&mut hmac, &[b"probe"], &mut probe).is_err() {
+        warn!("No eFuse key for secrets, storing them unencrypted");
+        return;
+    }
+    info!("Secrets are encrypted with eFuse {:?}", KEY);
+    critical_section::with(|cs| HMAC_UNIT.borrow_ref_mut(cs).replace(hmac));
+}
+
+/// Whether there is a key to seal values with
+pub fn is_available() -> bool {
+    critical_section::with(|cs| HMAC_UNIT.borrow_ref(cs).is_some())
+}
+
+/// Whether `value` was stored sealed
+pub fn is_sealed(value: &str) -> bool {
+    value.starts_with(PREFIX)
+}
+
+/// `value` of `key` as it should be stored: sealed, or as it is when there
+/// is no key
+pub fn seal(key: &str, value: &str) -> Result<String, Error> {
+    with_hmac(|hmac| {
+        let mut nonce = [0u8; NONCE_LEN];
+        let rng = Rng::new();
+        for chunk in nonce.chunks_mut(4) {
+            chunk.copy_from_slice(&rng.random().to_le_bytes()[..chunk.len()]);
+        }
+        let mut data = Vec::with_capacity(NONCE_LEN + value.len() + TAG_LEN);
+        data.extend_from_slice(&nonce);
+        data.extend_from_slice(value.as_bytes());
+        apply_keystream(hmac, &nonce, &mut data[NONCE_LEN..])?;
+        let tag = tag(hmac, key, &data)?;
+        data.extend_from_slice(&tag);
+
+        let mut sealed = String::with_capacity(PREFIX.len() + data.len() * 2);
+        sealed.push_str(PREFIX);
+        for byte in data {
+            write!(sealed, "{:02x}", byte).ok();
+        }
+        Ok(sealed)
+    })
+    .unwrap_or_else(|| Ok(String::from(value)))
+}
+
+/// The value of `key` from `sealed`. [Error::InvalidConfig] if it was
+/// sealed by another chip or key, or damaged; [Error::NotFound] with no key.
+pub fn open(key: &str, sealed: &str) -> Result<String, Error> {
+    let hex = sealed.strip_prefix(PREFIX).ok_or(Error::InvalidConfig)?;
+    if hex.len() % 2 != 0 || hex.len() < (NONCE_LEN + TAG_LEN) * 2 {
+        return Err(Error::InvalidConfig);
+    }
+    let mut data = hex
+        .as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            let pair = core::str::from_utf8(pair).ok()?;
+            u8::from_str_radix(pair, 16).ok()
+        })
+        .collect::<Option<Vec<u8>>>()
+        .ok_or(Error::InvalidConfig)?;
+    with_hmac(|hmac| {
+        let tag_at = data.len() - TAG_LEN;
+        let expected = tag(hmac, key, &data[..tag_at])?;
+        // not constant time, but the tag is only checked against what is in
+        // flash, not guessed at over the network
+        if expected[..] != data[tag_at..] {
+            return Err(Error::InvalidConfig);
+        }
+        data.truncate(tag_at);
+        let (nonce, value) = data.split_at_mut(NONCE_LEN);
+        apply_keystream(hmac, nonce, value)?;
+        String::from_utf8(value.to_vec()).map_err(|_| Error::InvalidConfig)
+    })
+    .unwrap_or(Err(Error::NotFound))
+}
+
+fn with_hmac<R>(f: impl FnOnce(&mut Hmac<'static>) -> R) -> Option<R> {
+    critical_section::with(|cs| HMAC_UNIT.borrow_ref_mut(cs).as_mut().map(f))
+}
+
+/// XORs `data` with the keystream for `nonce`
+fn apply_keystream(hmac: &mut Hmac<'_>, nonce: &[u8], data: &mut [u8]) -> Result<(), Error> {
+    let mut block = [0u8; BLOCK_LEN];
+    for (counter, chunk) in data.chunks_mut(BLOCK_LEN).enumerate() {
+        mac(
+            hmac,
+            &[b"stream", nonce, &(counter as u32).to_le_bytes()],
+            &mut block,
+        )?;
+        for (byte, key) in chunk.iter_mut().zip(block) {
+            *byte ^= key;
+        }
+    }
+    Ok(())
+}
+
+/// Ties the nonce and ciphertext in `data` to `key`, so a sealed value can't
+/// be moved to another key either
+fn tag(hmac: &mut Hmac<'_>, key: &str, data: &[u8]) -> Result<[u8; TAG_LEN], Error> {
+    let mut out = [0u8; BLOCK_LEN];
+    mac(hmac, &[b"tag", key.as_bytes(), b"=", data], &mut out)?;
+    Ok(out[..TAG_LEN].try_into().unwrap())
+}
+
+/// HMAC-SHA256 with the eFuse key over `parts` in turn
+fn mac(hmac: &mut Hmac<'_>, parts: &[&[u8]], out: &mut [u8; BLOCK_LEN]) -> Result<(), Error> {
+    hmac.init();
+    nb::block!(hmac.configure(HmacPurpose::ToUser, KEY)).map_err(|_| Error::NotFound)?;
+    for part in parts {
+        let mut rest = *part;
+        while !rest.is_empty() {
+            rest = nb::block!(hmac.update(rest)).unwrap();
+        }
+    }
+    nb::block!(hmac.finalize(out)).unwrap();
+    Ok(())
+}
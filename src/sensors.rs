@@ -0,0 +1,176 @@
+//! Sensor readings by name, for the code that records or sends them, such
+//! as [crate::datalog] and [crate::tsdb], so each of those takes a list of
+//! names from its config entry rather than knowing the hardware.
+//!
+//! | name                            | reading                                |
+//! |---------------------------------|----------------------------------------|
+//! | `battery.mv`                    | battery voltage, see [crate::analog]   |
+//! | `light.mv`                      | light sensor output                    |
+//! | `accel.x`, `accel.y`, `accel.z` | acceleration in mg, see [crate::accel] |
+//! | anything else                   | the value [bindings] has under that name, such as `steps` or `ha.sensor.office_temperature` |
+//!
+//! A build with sensors of its own, such as an environmental sensor on the
+//! I2C bus, [register]s a reader for each name it adds; a registered name
+//! comes before the built-in ones:
+//!
+//! ```ignore
+//! sensors::register("env.temp_c", || bme280::read().map(|r| format!("{:.2}", r.temp_c)));
+//! ```
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{cell::RefCell, fmt::Write as _};
+
+use critical_section::Mutex;
+use jiff::Timestamp;
+
+use crate::{accel, analog, bindings, time};
+
+/// What is read when a list of names is left unset
+pub const DEFAULT_FIELDS: &str = "battery.mv,light.mv";
+
+/// Reads one sensor, `None` when it can't be read
+pub type Reader = fn() -> Option<String>;
+
+static REGISTERED: Mutex<RefCell<Vec<(&'static str, Reader)>>> =
+    Mutex::new(RefCell::new(Vec::new()));
+
+/// Reads `name` with `reader` from now on, replacing any reader registered
+/// for it before
+pub fn register(name: &'static str, reader: Reader) {
+    critical_section::with(|cs| {
+        let mut registered = REGISTERED.borrow_ref_mut(cs);
+        registered.retain(|(registered, _)| *registered != name);
+        registered.push((name, reader));
+    });
+}
+
+/// The reading `name` stands for
+pub fn read(name: &str) -> Option<String> {
+    // run outside the critical section, a reader may take a while on a bus
+    let registered = critical_section::with(|cs| {
+        REGISTERED
+            .borrow_ref(cs)
+            .iter()
+            .find(|(registered, _)| *registered == name)
+            .map(|&(_, reader)| reader)
+    });
+    if let Some(reader) = registered {
+        return reader();
+    }
+    let axis = |f: fn(accel::Acceleration) -> i16| accel::read().map(|a| f(a).to_string());
+    match name {
+        "battery.mv" => analog::battery_millivolts().map(|mv| mv.to_string()),
+        "light.mv" => analog::light_millivolts().map(|mv| mv.to_string()),
+        "accel.x" => axis(|a| a.x),
+        "accel.y" => axis(|a| a.y),
+        "accel.z" => axis(|a| a.z),
+        _ => bindings::get(name),
+    }
+}
+
+/// The names in a comma separated `list`, [DEFAULT_FIELDS] if it is unset
+pub fn parse_fields(list: Option<&str>) -> Vec<String> {
+    list.unwrap_or(DEFAULT_FIELDS)
+        .split(',')
+        .map(str::trim)
+        .filter(|field| !field.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Sensors read at one time
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Readings {
+    /// To the second
+    pub time: Timestamp,
+    /// Each name with its reading, in the order asked for
+    pub values: Vec<(String, Option<String>)>,
+}
+
+impl Readings {
+    /// Reads `fields` now; `None` until the clock has been set
+    pub fn take(fields: &[String]) -> Option<Self> {
+        let now = time::now_utc()?;
+        Some(Self {
+            time: Timestamp::from_second(now.as_second()).unwrap_or(now),
+            values: fields
+                .iter()
+                .map(|field| (field.clone(), read(field)))
+                .collect(),
+        })
+    }
+
+    /// Whether nothing could be read
+    pub fn is_empty(&self) -> bool {
+        self.values.iter().all(|(_, value)| value.is_none())
+    }
+
+    /// One line of InfluxDB line protocol with the readings there are as
+    /// fields, the time in seconds and `badge` as a tag if there is one;
+    /// `None` if nothing could be read
+    pub fn line_protocol(&self, measurement: &str, badge: Option<&str>) -> Option<String> {
+        if self.is_empty() {
+            return None;
+        }
+        let mut line = escape_key(measurement);
+        if let Some(badge) = badge {
+            line += ",badge=";
+            line += &escape_key(badge);
+        }
+        let mut separator = ' ';
+        for (name, value) in &self.values {
+            let Some(value) = value else {
+                continue;
+            };
+            write!(
+                line,
+                "{}{}={}",
+                separator,
+                escape_key(name),
+                line_value(value)
+            )
+            .ok();
+            separator = ',';
+        }
+        writeln!(line, " {}", self.time.as_second()).ok();
+        Some(line)
+    }
+}
+
+/// A measurement, tag or field name with commas, equals signs and spaces
+/// escaped
+fn escape_key(key: &str) -> String {
+    let mut escaped = String::new();
+    for c in key.chars() {
+        if matches!(c, ',' | '=' | ' ') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Integers get the `i` suffix, other numbers go as they are and anything
+/// else as a quoted string
+fn line_value(value: &str) -> String {
+    if let Ok(integer) = value.parse::<i64>() {
+        format!("{}i", integer)
+    } else if let Some(number) = number(value) {
+        number.to_string()
+    } else {
+        let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+        format!("\"{}\"", value.replace(['\r', '\n'], " "))
+    }
+}
+
+/// `value` as a number if it is a finite one, which writers send unquoted
+pub fn number(value: &str) -> Option<f64> {
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|number| number.is_finite())
+}
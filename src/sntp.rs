@@ -0,0 +1,107 @@
+//! Minimal SNTP client (RFC 4330), used to set the wall clock.
+
+use esp_hal::time::{Duration, Instant};
+use log::debug;
+use smoltcp::socket::udp::PacketMetadata;
+
+use crate::{
+    net::{self, NetStack},
+    retry::{self, Policy},
+    wifi, Error,
+};
+
+pub const DEFAULT_SERVER: &str = "pool.ntp.org";
+
+const NTP_PORT: u16 = 123;
+const LOCAL_PORT: u16 = 50123;
+const PACKET_LEN: usize = 48;
+const TIMEOUT: Duration = Duration::from_secs(5);
+/// Seconds from 1900-01-01 (the NTP epoch) to 1970-01-01
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+
+/// Socket buffers for [query]; they have to live as long as the network stack
+pub struct SntpBuffers {
+    rx_meta: [PacketMetadata; 1],
+    rx: [u8; PACKET_LEN],
+    tx_meta: [PacketMetadata; 1],
+    tx: [u8; PACKET_LEN],
+}
+
+impl Default for SntpBuffers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SntpBuffers {
+    pub const fn new() -> Self {
+        Self {
+            rx_meta: [PacketMetadata::EMPTY; 1],
+            rx: [0; PACKET_LEN],
+            tx_meta: [PacketMetadata::EMPTY; 1],
+            tx: [0; PACKET_LEN],
+        }
+    }
+}
+
+/// Asks `server` for the time, returning microseconds since the Unix epoch
+pub fn query<'a>(
+    stack: &NetStack<'a>,
+    buffers: &'a mut SntpBuffers,
+    server: &str,
+) -> Result<u64, Error> {
+    let _transfer = wifi::transfer();
+    let addr = retry::with_backoff(&Policy::NETWORK, || net::resolve(stack, server))?;
+
+    let mut socket = stack.get_udp_socket(
+        &mut buffers.rx_meta,
+        &mut buffers.rx,
+        &mut buffers.tx_meta,
+        &mut buffers.tx,
+    );
+    socket.bind(LOCAL_PORT).map_err(|_| Error::Network)?;
+
+    let (reply, sent) = retry::with_backoff(&Policy::NETWORK, || {
+        // client request, version 4; the transmit timestamp doubles as a nonce
+        // that the server echoes back as the originate timestamp
+        let sent = Instant::now();
+        let nonce = sent.duration_since_epoch().as_micros().to_be_bytes();
+        let mut request = [0u8; PACKET_LEN];
+        request[0] = 0x23;
+        request[40..48].copy_from_slice(&nonce);
+        socket
+            .send(addr, NTP_PORT, &request)
+            .map_err(|_| Error::Network)?;
+
+        let mut reply = [0u8; PACKET_LEN];
+        loop {
+            if sent.elapsed() > TIMEOUT {
+                return Err(Error::Timeout);
+            }
+            match socket.receive(&mut reply) {
+                Ok((len, from, port)) if len == PACKET_LEN && from == addr && port == NTP_PORT => {
+                    if reply[24..32] == nonce {
+                        return Ok((reply, sent));
+                    }
+                    debug!("ignoring stale SNTP reply");
+                }
+                _ => {}
+            }
+        }
+    })?;
+
+    let mode = reply[0] & 0x07;
+    let stratum = reply[1];
+    if mode != 4 || stratum == 0 {
+        // stratum 0 is a kiss-o'-death, e.g. rate limiting, so no retrying
+        return Err(Error::Network);
+    }
+
+    let seconds = u32::from_be_bytes(reply[40..44].try_into().unwrap()) as u64;
+    let fraction = u32::from_be_bytes(reply[44..48].try_into().unwrap()) as u64;
+    let unix_us = seconds.checked_sub(NTP_UNIX_OFFSET).ok_or(Error::Network)? * 1_000_000
+        + ((fraction * 1_000_000) >> 32);
+
+    // the reply left the server about half a round trip ago
+    Ok(unix_us + sent.elapsed().as_micros() / 2)
+}
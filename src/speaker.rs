@@ -0,0 +1,141 @@
+//! The speaker on GPIO17, behind the amplifier on [Rail::Speaker].
+//!
+//! Tones are square waves toggled from the CPU, so [tone] blocks for as long
+//! as it plays. The amplifier is only on while something plays.
+//!
+//! With the `audio-pcm` feature GPIO17 is driven by DAC1 instead, which adds
+//! [play] for short 8-bit [Clip]s such as [CLICK] and [CHIME]. Those are
+//! also timed by the CPU, one sample at a time.
+
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+#[cfg(not(feature = "audio-pcm"))]
+use esp_hal::gpio::{Level, Output, OutputConfig};
+#[cfg(feature = "audio-pcm")]
+use esp_hal::{analog::dac::Dac, peripherals::DAC1};
+use esp_hal::{
+    delay::Delay,
+    peripherals::GPIO17,
+    time::{Duration, Instant},
+};
+
+use crate::power::{self, Rail};
+
+/// The line into the amplifier
+struct Signal {
+    #[cfg(not(feature = "audio-pcm"))]
+    pin: Output<'static>,
+    #[cfg(feature = "audio-pcm")]
+    dac: Dac<'static, DAC1<'static>>,
+}
+
+impl Signal {
+    /// Sets the line to `level`, from 0 (low) to 255 (high); a plain output
+    /// can only go high from 128
+    fn set(&mut self, level: u8) {
+        #[cfg(not(feature = "audio-pcm"))]
+        self.pin.set_level((level >= 0x80).into());
+        #[cfg(feature = "audio-pcm")]
+        self.dac.write(level);
+    }
+}
+
+static SPEAKER: Mutex<RefCell<Option<Signal>>> = Mutex::new(RefCell::new(None));
+
+/// Takes the speaker signal pin (GPIO17). Call once at boot, after
+/// [power::init].
+#[cfg(not(feature = "audio-pcm"))]
+pub fn init(signal: GPIO17<'static>) {
+    let pin = Output::new(signal, Level::Low, OutputConfig::default());
+    critical_section::with(|cs| SPEAKER.borrow_ref_mut(cs).replace(Signal { pin }));
+}
+
+/// Takes the speaker signal pin (GPIO17) and the DAC behind it. Call once at
+/// boot, after [power::init].
+#[cfg(feature = "audio-pcm")]
+pub fn init(signal: GPIO17<'static>, dac: DAC1<'static>) {
+    let mut dac = Dac::new(dac, signal);
+    dac.write(0);
+    critical_section::with(|cs| SPEAKER.borrow_ref_mut(cs).replace(Signal { dac }));
+}
+
+/// Plays `frequency_hz` for `duration`; does nothing before [init]
+pub fn tone(frequency_hz: u32, duration: Duration) {
+    if frequency_hz == 0 {
+        Delay::new().delay_millis(duration.as_millis() as u32);
+        return;
+    }
+    let half_period_us = 500_000 / frequency_hz;
+    with(|signal| {
+        power::rail(Rail::Speaker).on();
+        let delay = Delay::new();
+        let end = Instant::now() + duration;
+        while Instant::now() < end {
+            signal.set(0xff);
+            delay.delay_micros(half_period_us);
+            signal.set(0);
+            delay.delay_micros(half_period_us);
+        }
+        power::rail(Rail::Speaker).off();
+    });
+}
+
+/// Unsigned 8-bit mono samples, 128 being silence, as made by
+/// `tools/pcm_clip.py`
+#[cfg(feature = "audio-pcm")]
+#[derive(Debug, Clone, Copy)]
+pub struct Clip {
+    pub rate_hz: u32,
+    pub samples: &'static [u8],
+}
+
+#[cfg(feature = "audio-pcm")]
+impl Clip {
+    pub fn duration(&self) -> Duration {
+        Duration::from_micros(self.samples.len() as u64 * 1_000_000 / self.rate_hz as u64)
+    }
+}
+
+/// A short tick for button presses
+#[cfg(feature = "audio-pcm")]
+pub const CLICK: Clip = Clip {
+    rate_hz: 8000,
+    samples: include_bytes!("../assets/click.pcm"),
+};
+
+/// Two falling bell notes for notifications
+#[cfg(feature = "audio-pcm")]
+pub const CHIME: Clip = Clip {
+    rate_hz: 8000,
+    samples: include_bytes!("../assets/chime.pcm"),
+};
+
+/// Plays `clip` to the end; does nothing before [init]
+#[cfg(feature = "audio-pcm")]
+pub fn play(clip: &Clip) {
+    let rate_hz = clip.rate_hz as u64;
+    with(|signal| {
+        // settle at the midpoint before the amplifier comes on, or it pops
+        signal.set(0x80);
+        power::rail(Rail::Speaker).on();
+        let start = Instant::now();
+        for (i, &sample) in clip.samples.iter().enumerate() {
+            let due = start + Duration::from_micros(i as u64 * 1_000_000 / rate_hz);
+            while Instant::now() < due {}
+            signal.set(sample);
+        }
+        signal.set(0x80);
+        power::rail(Rail::Speaker).off();
+        signal.set(0);
+    });
+}
+
+/// Runs `f` with the speaker taken out of the mutex, so playing doesn't hold a
+/// critical section
+fn with<R>(f: impl FnOnce(&mut Signal) -> R) -> Option<R> {
+    let mut signal = critical_section::with(|cs| SPEAKER.borrow_ref_mut(cs).take())?;
+    let result = f(&mut signal);
+    critical_section::with(|cs| SPEAKER.borrow_ref_mut(cs).replace(signal));
+    Some(result)
+}
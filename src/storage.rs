@@ -0,0 +1,176 @@
+//! Storage traits, so code that keeps files or settings is written once and
+//! works with whichever backend is there:
+//!
+//! | Backend                                          | Trait       |
+//! | ------------------------------------------------ | ----------- |
+//! | [ConfigStore], the NVS partition                 | [KvStore]   |
+//! | [AssetStore], the assets partition               | [BlobStore] |
+//! | [SdStore](crate::sdcard::SdStore), with `sdcard` | [BlobStore] |
+//!
+//! The asset partition has its own flat layout rather than a file system
+//! such as LittleFS, so names there are at most
+//! [MAX_NAME_LEN](crate::assets::MAX_NAME_LEN) bytes; on the SD card they
+//! are 8.3 names. A name that suits both, such as `FORECAST.JSN`, works
+//! everywhere.
+//!
+//! [open] picks the blob store a location such as `sd:SLIDES/CAT01.BMP`
+//! names, [open_dir] the one for a directory such as `sd:LOGS`.
+
+use alloc::{boxed::Box, string::String, vec::Vec};
+
+use crate::{assets::AssetStore, config::ConfigStore, crc::crc32, multipart::Source, psram, Error};
+
+/// Named blobs of bytes
+pub trait BlobStore {
+    /// The length of `name`, [Error::NotFound] if there is nothing by that
+    /// name
+    fn len(&self, name: &str) -> Result<u32, Error>;
+
+    /// Reads from `name` at `offset`, returning how many bytes were read
+    fn read(&self, name: &str, offset: u32, buf: &mut [u8]) -> Result<usize, Error>;
+
+    fn read_to_vec(&self, name: &str) -> Result<Vec<u8>, Error> {
+        let mut data = psram::zeroed(self.len(name)? as usize);
+        let len = self.read(name, 0, &mut data)?;
+        data.truncate(len);
+        Ok(data)
+    }
+
+    /// Stores `data` as `name`, replacing what was there
+    fn write(&mut self, name: &str, data: &[u8]) -> Result<(), Error>;
+
+    /// Adds `data` to the end of `name`, starting it if there is nothing by
+    /// that name. The default reads the whole blob and writes it back.
+    fn append(&mut self, name: &str, data: &[u8]) -> Result<(), Error> {
+        let mut blob = match self.read_to_vec(name) {
+            Err(Error::NotFound) => Vec::new(),
+            blob => blob?,
+        };
+        blob.extend_from_slice(data);
+        self.write(name, &blob)
+    }
+
+    fn remove(&mut self, name: &str) -> Result<(), Error>;
+
+    /// Everything stored, by name
+    fn names(&self) -> Result<Vec<String>, Error>;
+}
+
+/// String settings by key
+pub trait KvStore {
+    fn get(&self, key: &str) -> Option<&str>;
+
+    /// Sets `key`; takes effect for [KvStore::get] at once and survives a
+    /// reset after [KvStore::commit]
+    fn set(&mut self, key: &str, value: &str) -> Result<(), Error>;
+
+    fn remove(&mut self, key: &str);
+
+    /// Stores the changes made so far
+    fn commit(&mut self) -> Result<(), Error>;
+}
+
+impl BlobStore for AssetStore {
+    fn len(&self, name: &str) -> Result<u32, Error> {
+        self.get(name).map(|asset| asset.len).ok_or(Error::NotFound)
+    }
+
+    fn read(&self, name: &str, offset: u32, buf: &mut [u8]) -> Result<usize, Error> {
+        AssetStore::read(self, name, offset, buf)
+    }
+
+    /// Checks the CRC as well
+    fn read_to_vec(&self, name: &str) -> Result<Vec<u8>, Error> {
+        AssetStore::read_to_vec(self, name)
+    }
+
+    fn write(&mut self, name: &str, data: &[u8]) -> Result<(), Error> {
+        let mut writer = self.create(name, data.len() as u32)?;
+        writer.write(data)?;
+        writer.finish(crc32(data))
+    }
+
+    fn remove(&mut self, name: &str) -> Result<(), Error> {
+        AssetStore::remove(self, name)
+    }
+
+    fn names(&self) -> Result<Vec<String>, Error> {
+        Ok(self.iter().map(|asset| asset.name.clone()).collect())
+    }
+}
+
+impl KvStore for ConfigStore {
+    fn get(&self, key: &str) -> Option<&str> {
+        ConfigStore::get(self, key)
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), Error> {
+        ConfigStore::set(self, key, value)
+    }
+
+    fn remove(&mut self, key: &str) {
+        ConfigStore::remove(self, key)
+    }
+
+    fn commit(&mut self) -> Result<(), Error> {
+        ConfigStore::commit(self)
+    }
+}
+
+/// A blob read a piece at a time as it is needed, such as a file streamed
+/// into an upload
+pub struct Blob<'s> {
+    store: &'s dyn BlobStore,
+    name: &'s str,
+    len: u32,
+}
+
+impl<'s> Blob<'s> {
+    pub fn open(store: &'s dyn BlobStore, name: &'s str) -> Result<Self, Error> {
+        Ok(Self {
+            store,
+            name,
+            len: store.len(name)?,
+        })
+    }
+}
+
+impl Source for Blob<'_> {
+    fn len(&self) -> u32 {
+        self.len
+    }
+
+    fn read(&self, offset: u32, buf: &mut [u8]) -> Result<usize, Error> {
+        self.store.read(self.name, offset, buf)
+    }
+}
+
+/// The store `location` is in and its name there: `sd:` and a path for the
+/// SD card, anything else a name in the asset store
+pub fn open(location: &str) -> Result<(Box<dyn BlobStore>, &str), Error> {
+    if let Some(path) = location.strip_prefix("sd:") {
+        #[cfg(feature = "sdcard")]
+        return Ok((Box::new(crate::sdcard::SdStore::new("")), path));
+        #[cfg(not(feature = "sdcard"))]
+        {
+            log::warn!("{}: built without SD card support", path);
+            return Err(Error::NotFound);
+        }
+    }
+    Ok((Box::new(AssetStore::open()?), location))
+}
+
+/// The store for the directory `location` names: `sd:` and a directory on
+/// the SD card, anything else the asset store
+pub fn open_dir(location: &str) -> Result<Box<dyn BlobStore>, Error> {
+    if let Some(dir) = location.strip_prefix("sd:") {
+        #[cfg(feature = "sdcard")]
+        return Ok(Box::new(crate::sdcard::SdStore::new(dir)));
+        #[cfg(not(feature = "sdcard"))]
+        {
+            log::warn!("{}: built without SD card support", dir);
+            return Err(Error::NotFound);
+        }
+    }
+    Ok(Box::new(AssetStore::open()?))
+}
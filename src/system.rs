@@ -0,0 +1,54 @@
+//! Whole-device operations that cut across the storage modules.
+
+use esp_bootloader_esp_idf::partitions::DataPartitionSubType;
+use log::{info, warn};
+
+use crate::{
+    assets::AssetStore,
+    boot_mode::{self, BootMode},
+    crash,
+    display::Display,
+    flash, telemetry, time, Error,
+};
+
+/// Erases everything the badge has learned since it was flashed and restarts
+///
+/// That is the config store (WiFi credentials included), the files in the
+/// asset partition, the stored crash report, and the clock sync and telemetry
+/// backlog kept in RTC memory. Steps that fail are logged and skipped; the
+/// rest still run.
+pub fn factory_reset(display: &mut Display) -> ! {
+    warn!("Factory reset");
+    let erased = [
+        ("config", erase_config()),
+        (
+            "assets",
+            AssetStore::open().and_then(|mut store| store.format()),
+        ),
+        ("crash report", crash::clear()),
+    ];
+    time::forget_sync();
+    telemetry::clear_backlog();
+
+    let mut failed = false;
+    for (what, result) in erased {
+        if let Err(err) = result {
+            warn!("Erasing the {} failed: {}", what, err);
+            failed = true;
+        }
+    }
+    let detail = if failed {
+        "Partly erased, see the log. Restarting"
+    } else {
+        "Settings and files erased. Restarting"
+    };
+    boot_mode::show(display, BootMode::FactoryReset, detail);
+    info!("Restarting after factory reset");
+    esp_hal::system::software_reset()
+}
+
+/// Erases the whole `nvs` partition, not just the record the config store uses
+fn erase_config() -> Result<(), Error> {
+    let nvs = flash::find_partition(DataPartitionSubType::Nvs)?;
+    flash::erase(nvs.offset, nvs.offset + nvs.len)
+}
@@ -0,0 +1,435 @@
+//! Periodic health reports to an MQTT broker or an HTTP endpoint.
+//!
+//! Each report is one JSON object:
+//!
+//! ```json
+//! {"ts":1760000000,"uptime_s":812,"battery_mv":3950,"rssi_dbm":-61,
+//!  "heap_free":41232,"refresh_ms":1840,"wake":"timer","fleet":7,
+//!  "cohort":42,"variant":"a"}
+//! ```
+//!
+//! `ts` is the Unix time and is `null` until the clock has been synced, and
+//! `fleet` the version of the [crate::fleet] config applied, `null` if none
+//! was, with the badge's rollout `cohort` and experiment `variant`; the other figures are `null` when they couldn't be read. The target
+//! comes from the `telemetry_url` config key: `mqtt://host[:port]/topic`
+//! publishes one message per report, `http://host[:port]/path` POSTs a JSON
+//! array of them, with the credentials from [http::Auth::from_config] if any
+//! are set.
+//!
+//! Reports that couldn't be sent are kept in RTC fast memory, so they survive
+//! deep sleep, and go out with the next successful publish. Only the newest
+//! [BACKLOG_LEN] are kept.
+//!
+//! With the `no-alloc` feature, reports are written into static buffers and
+//! sent with [http::Request::send_with] or [mqtt::publish_with], so a
+//! publish takes nothing from the heap.
+
+use alloc::string::String;
+#[cfg(not(feature = "no-alloc"))]
+use alloc::{format, vec::Vec};
+use core::fmt::{self, Write as _};
+
+use esp_hal::{efuse::Efuse, ram, time::Duration};
+use log::{debug, info, warn};
+
+use crate::{
+    config::{keys, ConfigStore},
+    crc::crc32,
+    fleet, http,
+    metrics::{self, WakeCause},
+    mqtt::{self, Login},
+    net::NetStack,
+    time, wifi, Error,
+};
+
+pub const DEFAULT_INTERVAL: Duration = Duration::from_minutes(15);
+/// Reports kept while offline
+pub const BACKLOG_LEN: usize = 32;
+
+const MAGIC: u32 = u32::from_le_bytes(*b"MTTL");
+
+/// One report as kept in RTC memory. Figures that couldn't be read are stored
+/// as zero, or `u32::MAX` for `refresh_ms`.
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct Sample {
+    unix_s: u64,
+    uptime_s: u32,
+    battery_mv: u32,
+    rssi_dbm: i32,
+    heap_free: u32,
+    refresh_ms: u32,
+    wake: u16,
+    /// Zero for none
+    fleet_version: u16,
+}
+
+impl Sample {
+    const EMPTY: Self = Self {
+        unix_s: 0,
+        uptime_s: 0,
+        battery_mv: 0,
+        rssi_dbm: 0,
+        heap_free: 0,
+        refresh_ms: u32::MAX,
+        wake: 0,
+        fleet_version: 0,
+    };
+
+    fn take(fleet_version: Option<u16>) -> Self {
+        let snapshot = metrics::snapshot();
+        Self {
+            unix_s: time::now_utc().map_or(0, |now| now.as_second() as u64),
+            uptime_s: snapshot.uptime.as_secs() as u32,
+            battery_mv: snapshot.battery_mv.unwrap_or(0),
+            rssi_dbm: snapshot.rssi_dbm.unwrap_or(0),
+            heap_free: snapshot.heap_free as u32,
+            refresh_ms: snapshot
+                .last_refresh
+                .map_or(u32::MAX, |d| d.as_millis() as u32),
+            wake: snapshot.wake_cause as u16,
+            fleet_version: fleet_version.unwrap_or(0),
+        }
+    }
+
+    #[cfg(not(feature = "no-alloc"))]
+    fn to_json(self) -> String {
+        let mut json = String::new();
+        self.write_json(&mut json).ok();
+        json
+    }
+
+    fn write_json(self, json: &mut impl fmt::Write) -> fmt::Result {
+        json.write_str("{\"ts\":")?;
+        push_field(json, (self.unix_s > 0).then_some(self.unix_s))?;
+        write!(json, ",\"uptime_s\":{}", self.uptime_s)?;
+        json.write_str(",\"battery_mv\":")?;
+        push_field(json, (self.battery_mv > 0).then_some(self.battery_mv))?;
+        json.write_str(",\"rssi_dbm\":")?;
+        push_field(json, (self.rssi_dbm != 0).then_some(self.rssi_dbm))?;
+        write!(json, ",\"heap_free\":{}", self.heap_free)?;
+        json.write_str(",\"refresh_ms\":")?;
+        push_field(
+            json,
+            (self.refresh_ms != u32::MAX).then_some(self.refresh_ms),
+        )?;
+        write!(json, ",\"wake\":\"{}\"", wake_cause(self.wake).as_str())?;
+        json.write_str(",\"fleet\":")?;
+        push_field(json, (self.fleet_version > 0).then_some(self.fleet_version))?;
+        write!(
+            json,
+            ",\"cohort\":{},\"variant\":\"{}\"",
+            fleet::cohort(),
+            fleet::variant().as_str()
+        )?;
+        json.write_char('}')
+    }
+}
+
+fn push_field(json: &mut impl fmt::Write, value: Option<impl fmt::Display>) -> fmt::Result {
+    match value {
+        Some(value) => write!(json, "{}", value),
+        None => json.write_str("null"),
+    }
+}
+
+fn wake_cause(raw: u16) -> WakeCause {
+    WakeCause::ALL
+        .into_iter()
+        .find(|cause| *cause as u16 == raw)
+        .unwrap_or(WakeCause::Other)
+}
+
+/// Unsent reports, oldest first starting at `start`
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct Backlog {
+    magic: u32,
+    /// CRC-32 of everything after this field
+    checksum: u32,
+    start: u32,
+    len: u32,
+    samples: [Sample; BACKLOG_LEN],
+}
+
+// SAFETY: only integer fields, and garbage is caught by `magic` and `checksum`
+unsafe impl esp_hal::Persistable for Backlog {}
+
+impl Backlog {
+    const EMPTY: Self = Self {
+        magic: MAGIC,
+        checksum: 0,
+        start: 0,
+        len: 0,
+        samples: [Sample::EMPTY; BACKLOG_LEN],
+    };
+
+    fn compute_checksum(&self) -> u32 {
+        // SAFETY: `repr(C)` with integer fields and no padding
+        let bytes = unsafe {
+            core::slice::from_raw_parts(
+                (self as *const Self).cast::<u8>(),
+                core::mem::size_of::<Self>(),
+            )
+        };
+        crc32(&bytes[8..])
+    }
+
+    fn is_valid(&self) -> bool {
+        self.magic == MAGIC
+            && self.len as usize <= BACKLOG_LEN
+            && (self.start as usize) < BACKLOG_LEN
+            && self.checksum == self.compute_checksum()
+    }
+
+    /// Appends `sample`, dropping the oldest one when full
+    fn push(&mut self, sample: Sample) {
+        let end = (self.start + self.len) as usize % BACKLOG_LEN;
+        self.samples[end] = sample;
+        if self.len as usize == BACKLOG_LEN {
+            self.start = (self.start + 1) % BACKLOG_LEN as u32;
+        } else {
+            self.len += 1;
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = Sample> + '_ {
+        (0..self.len as usize).map(|i| self.samples[(self.start as usize + i) % BACKLOG_LEN])
+    }
+}
+
+#[ram(unstable(rtc_fast, persistent))]
+static mut BACKLOG: Backlog = Backlog::EMPTY;
+
+fn load_backlog() -> Backlog {
+    // SAFETY: single core, and only touched inside a critical section
+    let backlog = critical_section::with(|_| unsafe { BACKLOG });
+    if backlog.is_valid() {
+        backlog
+    } else {
+        Backlog::EMPTY
+    }
+}
+
+fn save_backlog(mut backlog: Backlog) {
+    backlog.checksum = backlog.compute_checksum();
+    // SAFETY: as in `load_backlog`
+    critical_section::with(|_| unsafe { BACKLOG = backlog });
+}
+
+/// Drops the samples still waiting to be sent
+pub(crate) fn clear_backlog() {
+    save_backlog(Backlog::EMPTY);
+}
+
+/// Room for the longest report
+#[cfg(feature = "no-alloc")]
+const REPORT_LEN: usize = 224;
+
+/// What a publish is written into and sent from with `no-alloc`
+#[cfg(feature = "no-alloc")]
+struct Buffers {
+    /// The whole backlog as a JSON array, or one report after another for
+    /// MQTT
+    payload: heapless::String<{ BACKLOG_LEN * (REPORT_LEN + 1) + 1 }>,
+    /// Only the status line of the reply matters
+    http: http::Buffers<{ crate::net::BufferSizes::MIN_RX }, 1024, 1024>,
+    mqtt: mqtt::Buffers,
+}
+
+#[cfg(feature = "no-alloc")]
+static mut BUFFERS: Buffers = Buffers {
+    payload: heapless::String::new(),
+    http: http::Buffers::new(),
+    mqtt: mqtt::Buffers::new(),
+};
+
+/// Whether [BUFFERS] is lent out
+#[cfg(feature = "no-alloc")]
+static BUFFERS_TAKEN: critical_section::Mutex<core::cell::Cell<bool>> =
+    critical_section::Mutex::new(core::cell::Cell::new(false));
+
+/// Lends out [BUFFERS] for the length of `f`; a publish that finds them
+/// lent out fails like one that finds the network down
+#[cfg(feature = "no-alloc")]
+fn with_buffers<R>(f: impl FnOnce(&mut Buffers) -> Result<R, Error>) -> Result<R, Error> {
+    if critical_section::with(|cs| BUFFERS_TAKEN.borrow(cs).replace(true)) {
+        warn!("telemetry buffers in use");
+        return Err(Error::Network);
+    }
+    // SAFETY: the flag above hands out one reference at a time
+    let result = f(unsafe { &mut *core::ptr::addr_of_mut!(BUFFERS) });
+    critical_section::with(|cs| BUFFERS_TAKEN.borrow(cs).set(false));
+    result
+}
+
+/// Where and how often to report, from the config store
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Telemetry {
+    url: String,
+    interval: Duration,
+    login: Option<(String, String)>,
+    auth: Option<http::Auth>,
+    fleet_version: Option<u16>,
+}
+
+impl Telemetry {
+    /// `None` when no `telemetry_url` is set
+    pub fn from_config(config: &ConfigStore) -> Option<Self> {
+        let url = config.get(keys::TELEMETRY_URL).filter(|s| !s.is_empty())?;
+        let interval = config
+            .get_parsed::<u16>(keys::TELEMETRY_MINUTES)
+            .filter(|&minutes| minutes > 0)
+            .map_or(DEFAULT_INTERVAL, |minutes| {
+                Duration::from_minutes(minutes as u64)
+            });
+        let login = config.get(keys::MQTT_USERNAME).map(|username| {
+            (
+                username.into(),
+                config.get(keys::MQTT_PASSWORD).unwrap_or_default().into(),
+            )
+        });
+        Some(Self {
+            url: url.into(),
+            interval,
+            login,
+            auth: http::Auth::from_config(config),
+            fleet_version: config.get_parsed(keys::FLEET_VERSION),
+        })
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Takes a report and sends it along with any backlog. On failure the
+    /// reports are kept for the next attempt.
+    pub fn publish(&self, net: &NetStack<'_>) -> Result<(), Error> {
+        let mut backlog = load_backlog();
+        backlog.push(Sample::take(self.fleet_version));
+        save_backlog(backlog);
+
+        if !wifi::is_connected() {
+            debug!("offline, {} reports buffered", backlog.len);
+            return Err(Error::Network);
+        }
+        let result = if self.url.starts_with("mqtt://") {
+            self.publish_mqtt(net, &backlog)
+        } else {
+            self.publish_http(net, &backlog)
+        };
+        match result {
+            Ok(()) => {
+                info!("Sent {} telemetry reports", backlog.len);
+                save_backlog(Backlog::EMPTY);
+            }
+            Err(err) => warn!(
+                "telemetry failed, {} reports buffered: {}",
+                backlog.len, err
+            ),
+        }
+        result
+    }
+
+    fn login(&self) -> Option<Login<'_>> {
+        self.login
+            .as_ref()
+            .map(|(username, password)| Login { username, password })
+    }
+
+    fn request<'r>(&'r self, body: &'r [u8]) -> http::Request<'r> {
+        let mut request = http::Request::post(&self.url, "application/json", body);
+        if let Some(auth) = &self.auth {
+            request = request.auth(auth);
+        }
+        request
+    }
+
+    #[cfg(not(feature = "no-alloc"))]
+    fn publish_mqtt(&self, net: &NetStack<'_>, backlog: &Backlog) -> Result<(), Error> {
+        let client_id = client_id();
+        let reports: Vec<String> = backlog.iter().map(Sample::to_json).collect();
+        let payloads: Vec<&[u8]> = reports.iter().map(|r| r.as_bytes()).collect();
+        mqtt::publish(net, &self.url, &client_id, self.login(), &payloads)
+    }
+
+    #[cfg(not(feature = "no-alloc"))]
+    fn publish_http(&self, net: &NetStack<'_>, backlog: &Backlog) -> Result<(), Error> {
+        let reports: Vec<String> = backlog.iter().map(Sample::to_json).collect();
+        let body = format!("[{}]", reports.join(","));
+        let response = self.request(body.as_bytes()).send(net)?;
+        check_status(response.status)
+    }
+
+    #[cfg(feature = "no-alloc")]
+    fn publish_mqtt(&self, net: &NetStack<'_>, backlog: &Backlog) -> Result<(), Error> {
+        let client_id = client_id();
+        with_buffers(|buffers| {
+            buffers.payload.clear();
+            let mut ends = heapless::Vec::<usize, BACKLOG_LEN>::new();
+            for sample in backlog.iter() {
+                sample
+                    .write_json(&mut buffers.payload)
+                    .map_err(|_| Error::BufferFull)?;
+                ends.push(buffers.payload.len()).ok();
+            }
+            let payload = buffers.payload.as_bytes();
+            let mut payloads = heapless::Vec::<&[u8], BACKLOG_LEN>::new();
+            let mut start = 0;
+            for end in ends {
+                payloads.push(&payload[start..end]).ok();
+                start = end;
+            }
+            mqtt::publish_with(
+                net,
+                &mut buffers.mqtt,
+                &self.url,
+                &client_id,
+                self.login(),
+                &payloads,
+            )
+        })
+    }
+
+    #[cfg(feature = "no-alloc")]
+    fn publish_http(&self, net: &NetStack<'_>, backlog: &Backlog) -> Result<(), Error> {
+        with_buffers(|buffers| {
+            let body = &mut buffers.payload;
+            body.clear();
+            json_array(body, backlog).map_err(|_| Error::BufferFull)?;
+            let response = self
+                .request(body.as_bytes())
+                .send_with(net, &mut buffers.http)?;
+            check_status(response.status)
+        })
+    }
+}
+
+#[cfg(feature = "no-alloc")]
+fn json_array(out: &mut impl fmt::Write, backlog: &Backlog) -> fmt::Result {
+    out.write_char('[')?;
+    for (i, sample) in backlog.iter().enumerate() {
+        if i > 0 {
+            out.write_char(',')?;
+        }
+        sample.write_json(out)?;
+    }
+    out.write_char(']')
+}
+
+/// `magtag-` and the end of the MAC address
+fn client_id() -> heapless::String<13> {
+    let mac = Efuse::mac_address();
+    let mut id = heapless::String::new();
+    write!(id, "magtag-{:02x}{:02x}{:02x}", mac[3], mac[4], mac[5]).ok();
+    id
+}
+
+fn check_status(status: u16) -> Result<(), Error> {
+    if (200..300).contains(&status) {
+        Ok(())
+    } else {
+        warn!("telemetry endpoint answered {}", status);
+        Err(Error::Network)
+    }
+}
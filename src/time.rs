@@ -0,0 +1,280 @@
+//! Wall-clock time that survives deep sleep.
+//!
+//! The RTC timer keeps counting through deep sleep, so the offset between it
+//! and UTC from the last [sync] is kept in RTC fast memory. After a wake the
+//! time is known straight away, before WiFi is up; only a power-on reset loses
+//! it.
+//!
+//! The RTC slow clock runs noticeably fast or slow, so each sync also measures
+//! how far the clock wandered since the previous one. The resulting drift rate
+//! is applied between syncs.
+//!
+//! A clock set with [sync_approximate], from a source only good to the
+//! second such as an HTTP `Date` header, reads like any other, but the next
+//! sync doesn't measure drift against it.
+
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+use esp_hal::{ram, rtc_cntl::Rtc, time::Duration};
+use jiff::{civil::DateTime, tz::TimeZone, Timestamp};
+use log::{info, warn};
+
+use crate::tz::Tz;
+
+const MAGIC: u32 = u32::from_le_bytes(*b"MTCK");
+/// Syncs closer together than this are too noisy to measure drift from
+const MIN_DRIFT_INTERVAL_US: u64 = 30 * 60 * 1_000_000;
+/// Anything beyond 5% is a bad sync rather than a slow clock
+const MAX_DRIFT_PPB: i64 = 50_000_000;
+
+/// The last sync, kept in RTC fast memory
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct SyncRecord {
+    magic: u32,
+    checksum: u32,
+    /// UTC minus RTC time at the last sync, in microseconds
+    offset_us: i64,
+    /// RTC time at the last sync, in microseconds
+    synced_at_us: u64,
+    /// How fast the RTC runs relative to UTC, in parts per billion
+    drift_ppb: i64,
+    /// Number of measurements averaged into `drift_ppb`
+    drift_samples: u32,
+    /// 1 if the sync was only good to about a second
+    approximate: u32,
+}
+
+// SAFETY: only integer fields, and garbage is caught by `magic` and `checksum`
+unsafe impl esp_hal::Persistable for SyncRecord {}
+
+impl SyncRecord {
+    const EMPTY: Self = Self {
+        magic: 0,
+        checksum: 0,
+        offset_us: 0,
+        synced_at_us: 0,
+        drift_ppb: 0,
+        drift_samples: 0,
+        approximate: 0,
+    };
+
+    fn new(
+        offset_us: i64,
+        synced_at_us: u64,
+        drift_ppb: i64,
+        drift_samples: u32,
+        approximate: bool,
+    ) -> Self {
+        let mut record = Self {
+            magic: MAGIC,
+            checksum: 0,
+            offset_us,
+            synced_at_us,
+            drift_ppb,
+            drift_samples,
+            approximate: approximate as u32,
+        };
+        record.checksum = record.compute_checksum();
+        record
+    }
+
+    /// UTC at RTC time `rtc_us`, with drift correction
+    fn utc_us(&self, rtc_us: u64) -> i64 {
+        let elapsed = rtc_us.saturating_sub(self.synced_at_us) as i128;
+        let correction = elapsed * self.drift_ppb as i128 / 1_000_000_000;
+        rtc_us as i64 + self.offset_us + correction as i64
+    }
+
+    fn compute_checksum(&self) -> u32 {
+        [
+            self.offset_us as u64,
+            self.synced_at_us,
+            self.drift_ppb as u64,
+            self.drift_samples as u64,
+            self.approximate as u64,
+        ]
+        .into_iter()
+        .fold(self.magic, |acc, word| {
+            acc.rotate_left(7) ^ word as u32 ^ (word >> 32) as u32
+        })
+    }
+
+    fn is_valid(&self) -> bool {
+        self.magic == MAGIC && self.checksum == self.compute_checksum()
+    }
+}
+
+#[ram(unstable(rtc_fast, persistent))]
+static mut SYNC: SyncRecord = SyncRecord::EMPTY;
+
+struct Clock {
+    rtc: Rtc<'static>,
+    tz: Tz,
+}
+
+static CLOCK: Mutex<RefCell<Option<Clock>>> = Mutex::new(RefCell::new(None));
+
+/// Hands the RTC to the clock. Call once at boot, before anything reads the time.
+pub fn init(rtc: Rtc<'static>, tz: Tz) {
+    let restored = critical_section::with(|cs| {
+        CLOCK.borrow_ref_mut(cs).replace(Clock { rtc, tz });
+        last_sync().is_some()
+    });
+    if restored {
+        info!("Wall clock restored from RTC memory");
+    }
+}
+
+pub fn set_timezone(tz: Tz) {
+    with_clock(|clock| clock.tz = tz);
+}
+
+/// Sets the clock to `unix_us` microseconds since the Unix epoch, e.g. from SNTP
+pub fn sync(unix_us: u64) {
+    set(unix_us, false);
+}
+
+/// Sets the clock from a source good to about a second, keeping the drift
+/// measured so far
+pub fn sync_approximate(unix_us: u64) {
+    set(unix_us, true);
+}
+
+fn set(unix_us: u64, approximate: bool) {
+    with_clock(|clock| {
+        let rtc_us = clock.rtc.time_since_boot().as_micros();
+        clock.rtc.set_current_time_us(unix_us);
+        let offset_us = unix_us as i64 - rtc_us as i64;
+        let (drift_ppb, drift_samples) = match last_sync() {
+            Some(last) if approximate || last.approximate != 0 => {
+                (last.drift_ppb, last.drift_samples)
+            }
+            Some(last) => update_drift(&last, rtc_us, unix_us as i64),
+            None => (0, 0),
+        };
+        let record = SyncRecord::new(offset_us, rtc_us, drift_ppb, drift_samples, approximate);
+        // SAFETY: single core, and only touched inside a critical section
+        unsafe { SYNC = record };
+    });
+}
+
+/// The current UTC time, or `None` if the clock hasn't been synced since power-on
+pub fn now_utc() -> Option<Timestamp> {
+    let unix_us = with_clock(|clock| {
+        let record = last_sync()?;
+        Some(record.utc_us(clock.rtc.time_since_boot().as_micros()))
+    })??;
+    Timestamp::from_microsecond(unix_us).ok()
+}
+
+/// The current local time in the configured timezone
+pub fn now_local() -> Option<DateTime> {
+    to_local(now_utc()?)
+}
+
+/// `utc` in the configured timezone
+pub fn to_local(utc: Timestamp) -> Option<DateTime> {
+    with_clock(|clock| clock.tz.to_local(utc.as_second()))
+}
+
+/// Converts a local time in the configured timezone to UTC
+pub fn from_local(local: DateTime) -> Option<Timestamp> {
+    let unix = with_clock(|clock| clock.tz.to_unix(local))?;
+    Timestamp::from_second(unix).ok()
+}
+
+/// Time since the last sync
+pub fn since_sync() -> Option<Duration> {
+    with_clock(|clock| {
+        let record = last_sync()?;
+        let rtc_us = clock.rtc.time_since_boot().as_micros();
+        Some(Duration::from_micros(
+            rtc_us.saturating_sub(record.synced_at_us),
+        ))
+    })?
+}
+
+/// Whether the last sync was [sync_approximate]
+pub fn is_approximate() -> bool {
+    last_sync().is_some_and(|record| record.approximate != 0)
+}
+
+/// `Sun, 06 Nov 1994 08:49:37 GMT`, the only format RFC 9110 lets an HTTP
+/// server send a `Date` header in
+pub fn parse_http_date(value: &str) -> Option<Timestamp> {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let mut parts = value.trim().split(' ').filter(|part| !part.is_empty());
+    parts.next()?.strip_suffix(',')?;
+    let day = parts.next()?.parse().ok()?;
+    let month = parts.next()?;
+    let month = MONTHS.iter().position(|m| *m == month)? as i8 + 1;
+    let year = parts.next()?.parse().ok()?;
+    let mut clock = parts.next()?.split(':').map(|n| n.parse::<i8>().ok());
+    let (hour, minute, second) = (clock.next()??, clock.next()??, clock.next()??);
+    if parts.next()? != "GMT" || parts.next().is_some() {
+        return None;
+    }
+    let at = DateTime::new(year, month, day, hour, minute, second, 0).ok()?;
+    TimeZone::UTC.to_timestamp(at).ok()
+}
+
+/// The measured RTC drift in parts per billion, positive when the RTC runs slow
+pub fn drift_ppb() -> Option<i64> {
+    last_sync()
+        .filter(|record| record.drift_samples > 0)
+        .map(|record| record.drift_ppb)
+}
+
+/// Folds the error accumulated since `last` into its drift estimate
+fn update_drift(last: &SyncRecord, rtc_us: u64, unix_us: i64) -> (i64, u32) {
+    let keep = (last.drift_ppb, last.drift_samples);
+    let elapsed = rtc_us.saturating_sub(last.synced_at_us);
+    if elapsed < MIN_DRIFT_INTERVAL_US {
+        return keep;
+    }
+
+    let error_us = unix_us - last.utc_us(rtc_us);
+    info!(
+        "Clock was off by {} ms after {} min",
+        error_us / 1000,
+        elapsed / 60_000_000
+    );
+    // the error is what's left after the old estimate was applied
+    let residual_ppb = error_us as i128 * 1_000_000_000 / elapsed as i128;
+    let measured = last.drift_ppb as i128 + residual_ppb;
+    if measured.unsigned_abs() > MAX_DRIFT_PPB as u128 {
+        warn!("Ignoring implausible clock drift of {} ppb", measured);
+        return keep;
+    }
+
+    // running average over roughly the last four measurements
+    let samples = last.drift_samples.min(3) as i128;
+    let drift = (last.drift_ppb as i128 * samples + measured) / (samples + 1);
+    info!("RTC drift {} ppm", drift / 1000);
+    (drift as i64, last.drift_samples.saturating_add(1))
+}
+
+fn last_sync() -> Option<SyncRecord> {
+    // SAFETY: plain copy, any bit pattern is a valid record
+    let record = critical_section::with(|_| unsafe { SYNC });
+    record.is_valid().then_some(record)
+}
+
+/// Forgets the last sync, so the clock reads as unset after the next reset
+pub(crate) fn forget_sync() {
+    // SAFETY: as in `sync`
+    critical_section::with(|_| unsafe { SYNC = SyncRecord::EMPTY });
+}
+
+/// Runs `f` with the RTC, e.g. to enter sleep
+pub(crate) fn with_rtc<R>(f: impl FnOnce(&mut Rtc<'static>) -> R) -> Option<R> {
+    with_clock(|clock| f(&mut clock.rtc))
+}
+
+fn with_clock<R>(f: impl FnOnce(&mut Clock) -> R) -> Option<R> {
+    critical_section::with(|cs| CLOCK.borrow_ref_mut(cs).as_mut().map(f))
+}
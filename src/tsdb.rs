@@ -0,0 +1,242 @@
+//! Sensor readings written straight to a time-series database as they are
+//! taken, for badges that report to a server at home rather than to files.
+//!
+//! `tsdb_url` says where and how:
+//!
+//! - `influx://host[:port]/org/bucket` writes line protocol to InfluxDB 2's
+//!   `/api/v2/write` over plain HTTP, on port 8086 unless another is given,
+//!   with the API token in `tsdb_token`
+//! - `http://host[:port]/path` POSTs a JSON array of points such as
+//!   `{"ts":1760000000,"badge":"lobby","battery.mv":3950,"light.mv":212}`,
+//!   with the HTTP credentials if any are set
+//!
+//! Every `tsdb_s` seconds ([DEFAULT_INTERVAL] if unset) the [sensors]
+//! `tsdb_fields` names, [sensors::DEFAULT_FIELDS] if unset, are read into
+//! one point with the time in seconds and the `badge_name`, if set, as a
+//! tag. Readings that are numbers go as numbers, others as strings, and
+//! ones that can't be read are left out. Nothing is written until the clock
+//! has been set.
+//!
+//! Points that couldn't be written are kept, the newest [BACKLOG_LEN], and
+//! go with the next write. They are kept in RAM and lost in deep sleep;
+//! [crate::datalog] keeps readings through it.
+
+use alloc::{collections::VecDeque, format, string::String, vec::Vec};
+use core::fmt::Write as _;
+
+use esp_hal::time::Duration;
+use log::{debug, info, warn};
+
+use crate::{
+    config::{keys, ConfigStore},
+    http::{Auth, Request},
+    net::NetStack,
+    sensors::{self, Readings},
+    url::{self, Url},
+    wifi, Error,
+};
+
+pub const DEFAULT_INTERVAL: Duration = Duration::from_minutes(5);
+/// Points kept while writes fail
+pub const BACKLOG_LEN: usize = 32;
+pub const INFLUX_PORT: u16 = 8086;
+/// The line protocol measurement
+const MEASUREMENT: &str = "magtag";
+
+/// Where points are written
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Sink {
+    /// InfluxDB 2's write endpoint for one bucket
+    Influx {
+        /// With the org, bucket and precision in the query
+        url: String,
+        /// `Token` and the API token
+        authorization: Option<String>,
+    },
+    /// Any endpoint taking a JSON array of points
+    Json { url: String, auth: Option<Auth> },
+}
+
+impl Sink {
+    /// The sink for a `tsdb_url`
+    fn parse(url: &str, config: &ConfigStore) -> Result<Self, Error> {
+        if !url.starts_with("influx://") {
+            Url::parse_with_scheme(url, "http", 80)?;
+            return Ok(Sink::Json {
+                url: url.into(),
+                auth: Auth::from_config(config),
+            });
+        }
+        let parsed = Url::parse_with_scheme(url, "influx", INFLUX_PORT)?;
+        let Some((org, bucket)) = parsed.path.trim_matches('/').split_once('/') else {
+            warn!("{}: no org and bucket, as influx://host/org/bucket", url);
+            return Err(Error::InvalidUrl);
+        };
+        let (Some(org), Some(bucket)) = (url::decode(org), url::decode(bucket)) else {
+            return Err(Error::InvalidUrl);
+        };
+        let write = url::Builder::new(&format!(
+            "http://{}:{}/api/v2/write",
+            parsed.host, parsed.port
+        ))
+        .query("org", &org)
+        .query("bucket", &bucket)
+        .query("precision", "s")
+        .build();
+        Ok(Sink::Influx {
+            url: write,
+            authorization: config
+                .get(keys::TSDB_TOKEN)
+                .filter(|token| !token.is_empty())
+                .map(|token| format!("Token {}", token)),
+        })
+    }
+}
+
+/// The configured database, what to read and the points not written yet
+#[derive(Debug, Clone)]
+pub struct Tsdb {
+    sink: Sink,
+    fields: Vec<String>,
+    interval: Duration,
+    badge: Option<String>,
+    backlog: VecDeque<Readings>,
+}
+
+impl Tsdb {
+    /// `None` when no `tsdb_url` is set or it isn't one of the forms above
+    pub fn from_config(config: &ConfigStore) -> Option<Self> {
+        let url = config
+            .get(keys::TSDB_URL)
+            .map(str::trim)
+            .filter(|url| !url.is_empty())?;
+        let sink = Sink::parse(url, config).ok()?;
+        let interval = config
+            .get_parsed::<u32>(keys::TSDB_SECONDS)
+            .filter(|&secs| secs > 0)
+            .map_or(DEFAULT_INTERVAL, |secs| Duration::from_secs(secs as u64));
+        Some(Self {
+            sink,
+            fields: sensors::parse_fields(config.get(keys::TSDB_FIELDS)),
+            interval,
+            badge: config
+                .get(keys::BADGE_NAME)
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(String::from),
+            backlog: VecDeque::new(),
+        })
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Reads the fields and writes them along with any backlog. On failure
+    /// the points are kept for the next attempt.
+    pub fn write(&mut self, net: &NetStack<'_>) -> Result<(), Error> {
+        let Some(readings) = Readings::take(&self.fields) else {
+            debug!("tsdb: clock not set yet");
+            return Ok(());
+        };
+        if !readings.is_empty() {
+            if self.backlog.len() == BACKLOG_LEN {
+                self.backlog.pop_front();
+            }
+            self.backlog.push_back(readings);
+        }
+        if self.backlog.is_empty() {
+            return Ok(());
+        }
+        if !wifi::is_connected() {
+            debug!("tsdb: offline, {} points kept", self.backlog.len());
+            return Err(Error::Network);
+        }
+        let result = self.send(net);
+        match &result {
+            Ok(()) => {
+                info!("tsdb: wrote {} points", self.backlog.len());
+                self.backlog.clear();
+            }
+            Err(err) => warn!("tsdb: {} points kept: {}", self.backlog.len(), err),
+        }
+        result
+    }
+
+    fn send(&self, net: &NetStack<'_>) -> Result<(), Error> {
+        let badge = self.badge.as_deref();
+        let status = match &self.sink {
+            Sink::Influx { url, authorization } => {
+                let body: String = self
+                    .backlog
+                    .iter()
+                    .filter_map(|point| point.line_protocol(MEASUREMENT, badge))
+                    .collect();
+                let mut request = Request::post(url, "text/plain; charset=utf-8", body.as_bytes());
+                if let Some(authorization) = authorization {
+                    request = request.header("Authorization", authorization);
+                }
+                request.send(net)?.status
+            }
+            Sink::Json { url, auth } => {
+                let mut body = String::from("[");
+                for (i, point) in self.backlog.iter().enumerate() {
+                    if i > 0 {
+                        body.push(',');
+                    }
+                    write_json(&mut body, point, badge);
+                }
+                body.push(']');
+                let mut request = Request::post(url, "application/json", body.as_bytes());
+                if let Some(auth) = auth {
+                    request = request.auth(auth);
+                }
+                request.send(net)?.status
+            }
+        };
+        if (200..300).contains(&status) {
+            Ok(())
+        } else {
+            warn!("tsdb: the server answered {}", status);
+            Err(Error::Network)
+        }
+    }
+}
+
+/// One point as a JSON object
+fn write_json(json: &mut String, point: &Readings, badge: Option<&str>) {
+    write!(json, "{{\"ts\":{}", point.time.as_second()).ok();
+    if let Some(badge) = badge {
+        json.push_str(",\"badge\":");
+        write_json_string(json, badge);
+    }
+    for (name, value) in &point.values {
+        let Some(value) = value else {
+            continue;
+        };
+        json.push(',');
+        write_json_string(json, name);
+        json.push(':');
+        if let Some(number) = sensors::number(value) {
+            write!(json, "{}", number).ok();
+        } else {
+            write_json_string(json, value);
+        }
+    }
+    json.push('}');
+}
+
+fn write_json_string(json: &mut String, s: &str) {
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if (c as u32) < 0x20 => {
+                write!(json, "\\u{:04x}", c as u32).ok();
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
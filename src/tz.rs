@@ -0,0 +1,424 @@
+//! POSIX TZ strings (`CET-1CEST,M3.5.0,M10.5.0/3`) for turning UTC into local time.
+//!
+//! Supports quoted names (`<+0530>-5:30`), the `Mm.w.d`, `Jn` and `n` rule
+//! forms and the extended transition times allowed by RFC 8536 (negative or
+//! beyond 24h). A zone with DST but no rules uses the US rules, as glibc does.
+
+use core::str::FromStr;
+
+use heapless::String;
+use jiff::{civil::DateTime, tz::Offset, Timestamp};
+use log::warn;
+
+use crate::config::{keys, ConfigStore};
+
+const SECS_PER_DAY: i64 = 86_400;
+const DEFAULT_TRANSITION_TIME: i32 = 2 * 3600;
+
+/// Zone abbreviation such as `CEST`
+pub type Abbreviation = String<8>;
+
+/// Why a TZ string was rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    Name,
+    Offset,
+    Rule,
+    TrailingInput,
+}
+
+impl core::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ParseError::Name => write!(f, "invalid zone name"),
+            ParseError::Offset => write!(f, "invalid UTC offset"),
+            ParseError::Rule => write!(f, "invalid DST rule"),
+            ParseError::TrailingInput => write!(f, "unexpected trailing characters"),
+        }
+    }
+}
+
+/// The day a transition happens on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Day {
+    /// `Jn`: day 1-365, February 29th is never counted
+    Julian1(u16),
+    /// `n`: day 0-365, counting February 29th in leap years
+    Julian0(u16),
+    /// `Mm.w.d`: weekday `d` (0 = Sunday) of week `w` (5 = last) in month `m`
+    MonthWeekDay { month: u8, week: u8, weekday: u8 },
+}
+
+/// A transition: a day and a local wall-clock time in seconds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rule {
+    pub day: Day,
+    pub time: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dst {
+    pub abbreviation: Abbreviation,
+    /// Seconds east of UTC
+    pub offset: i32,
+    pub start: Rule,
+    pub end: Rule,
+}
+
+/// A parsed POSIX time zone
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tz {
+    pub abbreviation: Abbreviation,
+    /// Seconds east of UTC (the sign is the opposite of what the TZ string says)
+    pub offset: i32,
+    pub dst: Option<Dst>,
+}
+
+/// The offset in effect at some instant
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalOffset {
+    /// Seconds east of UTC
+    pub seconds: i32,
+    pub is_dst: bool,
+    pub abbreviation: Abbreviation,
+}
+
+impl Tz {
+    pub fn utc() -> Self {
+        Self {
+            abbreviation: Abbreviation::try_from("UTC").unwrap(),
+            offset: 0,
+            dst: None,
+        }
+    }
+
+    /// Reads the zone from the `tz` config key, falling back to UTC
+    pub fn from_config(config: &ConfigStore) -> Self {
+        let Some(s) = config.get(keys::TIMEZONE) else {
+            return Self::utc();
+        };
+        s.parse().unwrap_or_else(|err| {
+            warn!("ignoring timezone {:?}: {}", s, err);
+            Self::utc()
+        })
+    }
+
+    /// The offset in effect at `unix` seconds
+    pub fn offset_at(&self, unix: i64) -> LocalOffset {
+        let standard = LocalOffset {
+            seconds: self.offset,
+            is_dst: false,
+            abbreviation: self.abbreviation.clone(),
+        };
+        let Some(dst) = &self.dst else {
+            return standard;
+        };
+
+        let year = civil_from_days((unix + self.offset as i64).div_euclid(SECS_PER_DAY)).0;
+        // start is given in standard time, end in daylight time
+        let start = dst.start.utc_in_year(year, self.offset);
+        let end = dst.end.utc_in_year(year, dst.offset);
+        let in_dst = if start < end {
+            unix >= start && unix < end
+        } else {
+            // southern hemisphere: DST spans the new year
+            unix >= start || unix < end
+        };
+
+        if in_dst {
+            LocalOffset {
+                seconds: dst.offset,
+                is_dst: true,
+                abbreviation: dst.abbreviation.clone(),
+            }
+        } else {
+            standard
+        }
+    }
+
+    /// Local wall-clock time at `unix` seconds
+    pub fn to_local(&self, unix: i64) -> DateTime {
+        let offset = Offset::from_seconds(self.offset_at(unix).seconds).unwrap_or(Offset::UTC);
+        let timestamp = Timestamp::from_second(unix).unwrap_or(Timestamp::UNIX_EPOCH);
+        offset.to_datetime(timestamp)
+    }
+
+    /// Unix seconds for a local wall-clock time. A time repeated when DST ends
+    /// resolves to the earlier instant, a time skipped when DST starts is read
+    /// as standard time.
+    pub fn to_unix(&self, local: DateTime) -> i64 {
+        let days = days_from_civil(local.year() as i32, local.month() as u8, local.day() as u8);
+        let wall = days * SECS_PER_DAY
+            + local.hour() as i64 * 3600
+            + local.minute() as i64 * 60
+            + local.second() as i64;
+        [Some(self.offset), self.dst.as_ref().map(|dst| dst.offset)]
+            .into_iter()
+            .flatten()
+            .map(|offset| wall - offset as i64)
+            .filter(|unix| self.offset_at(*unix).seconds as i64 == wall - unix)
+            .min()
+            .unwrap_or(wall - self.offset as i64)
+    }
+
+    /// The next DST change strictly after `unix`, if the zone has DST
+    pub fn next_transition(&self, unix: i64) -> Option<i64> {
+        let dst = self.dst.as_ref()?;
+        let year = civil_from_days(unix.div_euclid(SECS_PER_DAY)).0;
+        (year..=year + 1)
+            .flat_map(|y| {
+                [
+                    dst.start.utc_in_year(y, self.offset),
+                    dst.end.utc_in_year(y, dst.offset),
+                ]
+            })
+            .filter(|t| *t > unix)
+            .min()
+    }
+}
+
+impl Rule {
+    /// The transition in `year` as unix seconds, given the offset in effect before it
+    fn utc_in_year(&self, year: i32, offset_before: i32) -> i64 {
+        let day = match self.day {
+            Day::Julian1(n) => {
+                // day 60 is always March 1st
+                let n = n as i64;
+                let leap_shift = if is_leap(year) && n >= 60 { 1 } else { 0 };
+                days_from_civil(year, 1, 1) + n - 1 + leap_shift
+            }
+            Day::Julian0(n) => days_from_civil(year, 1, 1) + n as i64,
+            Day::MonthWeekDay {
+                month,
+                week,
+                weekday,
+            } => {
+                let first = days_from_civil(year, month, 1);
+                // 1970-01-01 was a Thursday
+                let first_weekday = (first + 4).rem_euclid(7);
+                let mut day = first + (weekday as i64 - first_weekday).rem_euclid(7);
+                day += 7 * (week as i64 - 1);
+                let month_end = first + days_in_month(year, month) as i64;
+                while day >= month_end {
+                    day -= 7;
+                }
+                day
+            }
+        };
+        day * SECS_PER_DAY + self.time as i64 - offset_before as i64
+    }
+}
+
+impl FromStr for Tz {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut p = Parser { s: s.as_bytes() };
+
+        let abbreviation = p.name()?;
+        let offset = -p.offset(24)?;
+
+        let dst = if p.is_empty() {
+            None
+        } else {
+            let dst_abbreviation = p.name()?;
+            let dst_offset = if p.is_empty() || p.peek() == Some(b',') {
+                offset + 3600
+            } else {
+                -p.offset(24)?
+            };
+            let (start, end) = if p.is_empty() {
+                (
+                    Rule {
+                        day: Day::MonthWeekDay {
+                            month: 3,
+                            week: 2,
+                            weekday: 0,
+                        },
+                        time: DEFAULT_TRANSITION_TIME,
+                    },
+                    Rule {
+                        day: Day::MonthWeekDay {
+                            month: 11,
+                            week: 1,
+                            weekday: 0,
+                        },
+                        time: DEFAULT_TRANSITION_TIME,
+                    },
+                )
+            } else {
+                p.expect(b',').ok_or(ParseError::Rule)?;
+                let start = p.rule()?;
+                p.expect(b',').ok_or(ParseError::Rule)?;
+                let end = p.rule()?;
+                (start, end)
+            };
+            Some(Dst {
+                abbreviation: dst_abbreviation,
+                offset: dst_offset,
+                start,
+                end,
+            })
+        };
+
+        if !p.is_empty() {
+            return Err(ParseError::TrailingInput);
+        }
+        Ok(Tz {
+            abbreviation,
+            offset,
+            dst,
+        })
+    }
+}
+
+struct Parser<'s> {
+    s: &'s [u8],
+}
+
+impl<'s> Parser<'s> {
+    fn is_empty(&self) -> bool {
+        self.s.is_empty()
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.s.first().copied()
+    }
+
+    fn expect(&mut self, c: u8) -> Option<()> {
+        if self.peek() == Some(c) {
+            self.s = &self.s[1..];
+            Some(())
+        } else {
+            None
+        }
+    }
+
+    fn take_while(&mut self, f: impl Fn(u8) -> bool) -> &'s [u8] {
+        let n = self.s.iter().position(|c| !f(*c)).unwrap_or(self.s.len());
+        let (head, tail) = self.s.split_at(n);
+        self.s = tail;
+        head
+    }
+
+    fn name(&mut self) -> Result<Abbreviation, ParseError> {
+        let name = if self.expect(b'<').is_some() {
+            let name = self.take_while(|c| c.is_ascii_alphanumeric() || c == b'+' || c == b'-');
+            self.expect(b'>').ok_or(ParseError::Name)?;
+            name
+        } else {
+            self.take_while(|c| c.is_ascii_alphabetic())
+        };
+        if name.len() < 3 {
+            return Err(ParseError::Name);
+        }
+        let name = core::str::from_utf8(name).map_err(|_| ParseError::Name)?;
+        Abbreviation::try_from(name).map_err(|_| ParseError::Name)
+    }
+
+    fn number(&mut self, max: i32) -> Option<i32> {
+        let digits = self.take_while(|c| c.is_ascii_digit());
+        if digits.is_empty() || digits.len() > 3 {
+            return None;
+        }
+        let n = digits.iter().fold(0i32, |n, d| n * 10 + (d - b'0') as i32);
+        (n <= max).then_some(n)
+    }
+
+    /// `[+-]hh[:mm[:ss]]` in seconds, as written (west-positive for offsets)
+    fn offset(&mut self, max_hours: i32) -> Result<i32, ParseError> {
+        self.hms(max_hours).ok_or(ParseError::Offset)
+    }
+
+    fn hms(&mut self, max_hours: i32) -> Option<i32> {
+        let sign = match self.peek() {
+            Some(b'-') => {
+                self.s = &self.s[1..];
+                -1
+            }
+            Some(b'+') => {
+                self.s = &self.s[1..];
+                1
+            }
+            _ => 1,
+        };
+        let mut secs = self.number(max_hours)? * 3600;
+        if self.expect(b':').is_some() {
+            secs += self.number(59)? * 60;
+            if self.expect(b':').is_some() {
+                secs += self.number(59)?;
+            }
+        }
+        Some(sign * secs)
+    }
+
+    fn rule(&mut self) -> Result<Rule, ParseError> {
+        let day = if self.expect(b'M').is_some() {
+            let month = self.number(12).filter(|m| *m >= 1);
+            self.expect(b'.').ok_or(ParseError::Rule)?;
+            let week = self.number(5).filter(|w| *w >= 1);
+            self.expect(b'.').ok_or(ParseError::Rule)?;
+            let weekday = self.number(6);
+            match (month, week, weekday) {
+                (Some(month), Some(week), Some(weekday)) => Day::MonthWeekDay {
+                    month: month as u8,
+                    week: week as u8,
+                    weekday: weekday as u8,
+                },
+                _ => return Err(ParseError::Rule),
+            }
+        } else if self.expect(b'J').is_some() {
+            let n = self
+                .number(365)
+                .filter(|n| *n >= 1)
+                .ok_or(ParseError::Rule)?;
+            Day::Julian1(n as u16)
+        } else {
+            Day::Julian0(self.number(365).ok_or(ParseError::Rule)? as u16)
+        };
+
+        let time = if self.expect(b'/').is_some() {
+            self.hms(167).ok_or(ParseError::Rule)?
+        } else {
+            DEFAULT_TRANSITION_TIME
+        };
+        Ok(Rule { day, time })
+    }
+}
+
+fn is_leap(year: i32) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i32, month: u8) -> u8 {
+    match month {
+        2 if is_leap(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01 (proleptic Gregorian), after Howard Hinnant's algorithm
+fn days_from_civil(year: i32, month: u8, day: u8) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year } as i64;
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let m = month as i64;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i32, u8, u8) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u8;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+    let year = (yoe + era * 400 + if month <= 2 { 1 } else { 0 }) as i32;
+    (year, month, day)
+}
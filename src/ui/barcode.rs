@@ -0,0 +1,283 @@
+//! One-dimensional barcodes for membership and loyalty cards: Code 128 for
+//! arbitrary ASCII and EAN-13/EAN-8 for retail numbers.
+//!
+//! [Barcode::draw] picks the widest whole-pixel module that fits the area
+//! with the quiet zones each symbology needs, and prints the data underneath.
+//! Scanners want at least a pixel per module, so about 20 characters of
+//! Code 128 fit across the panel.
+
+use alloc::{string::String, vec::Vec};
+
+use embedded_graphics::{
+    mono_font::{ascii::FONT_6X10, MonoTextStyle},
+    pixelcolor::Gray2,
+    prelude::*,
+    primitives::{PrimitiveStyle, Rectangle},
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
+};
+
+use crate::{display::Frame, Error};
+
+/// Bar and space widths of Code 128 values 0 to 105, one bit per module from
+/// the most significant of 11, 1 for a bar
+const CODE128: [u16; 106] = [
+    0x6cc, 0x66c, 0x666, 0x498, 0x48c, 0x44c, 0x4c8, 0x4c4, 0x464, 0x648, 0x644, 0x624, 0x59c,
+    0x4dc, 0x4ce, 0x5cc, 0x4ec, 0x4e6, 0x672, 0x65c, 0x64e, 0x6e4, 0x674, 0x76e, 0x74c, 0x72c,
+    0x726, 0x764, 0x734, 0x732, 0x6d8, 0x6c6, 0x636, 0x518, 0x458, 0x446, 0x588, 0x468, 0x462,
+    0x688, 0x628, 0x622, 0x5b8, 0x58e, 0x46e, 0x5d8, 0x5c6, 0x476, 0x776, 0x68e, 0x62e, 0x6e8,
+    0x6e2, 0x6ee, 0x758, 0x746, 0x716, 0x768, 0x762, 0x71a, 0x77a, 0x642, 0x78a, 0x530, 0x50c,
+    0x4b0, 0x486, 0x42c, 0x426, 0x590, 0x584, 0x4d0, 0x4c2, 0x434, 0x432, 0x612, 0x650, 0x7ba,
+    0x614, 0x47a, 0x53c, 0x4bc, 0x49e, 0x5e4, 0x4f4, 0x4f2, 0x7a4, 0x794, 0x792, 0x6de, 0x6f6,
+    0x7b6, 0x578, 0x51e, 0x45e, 0x5e8, 0x5e2, 0x7a8, 0x7a2, 0x5de, 0x5ee, 0x75e, 0x7ae, 0x684,
+    0x690, 0x69c,
+];
+const CODE128_STOP: u16 = 0x18eb;
+const CODE128_STOP_MODULES: u32 = 13;
+const START_B: u8 = 104;
+const START_C: u8 = 105;
+const SWITCH_TO_B: u8 = 100;
+const SWITCH_TO_C: u8 = 99;
+
+/// EAN digits in the left-hand odd parity set; even parity is these reversed
+/// and the right-hand set their complement
+const EAN_L: [u8; 10] = [
+    0b0001101, 0b0011001, 0b0010011, 0b0111101, 0b0100011, 0b0110001, 0b0101111, 0b0111011,
+    0b0110111, 0b0001011,
+];
+/// Which of the six left-hand EAN-13 digits take even parity, by the first
+/// digit, most significant bit first
+const EAN13_PARITY: [u8; 10] = [
+    0b000000, 0b001011, 0b001101, 0b001110, 0b010011, 0b011001, 0b011100, 0b010101, 0b010110,
+    0b011010,
+];
+
+/// Gap below the bars for the text
+const TEXT_GAP: u32 = 2;
+const TEXT_HEIGHT: u32 = 10;
+
+/// A barcode ready to draw
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Barcode {
+    /// Bars (`true`) and spaces, one per module, without quiet zones
+    modules: Vec<bool>,
+    /// Quiet zones in modules, left and right
+    quiet: (u32, u32),
+    text: String,
+}
+
+impl Barcode {
+    /// Code 128 for printable ASCII, switching to the digit-pair code set
+    /// for runs of digits
+    pub fn code128(data: &str) -> Result<Self, Error> {
+        if data.is_empty() || !data.bytes().all(|b| (b' '..=b'~').contains(&b)) {
+            return Err(Error::InvalidConfig);
+        }
+        let bytes = data.as_bytes();
+        let digit_run = |from: usize| {
+            bytes[from..]
+                .iter()
+                .take_while(|b| b.is_ascii_digit())
+                .count()
+        };
+
+        let mut values = Vec::new();
+        let mut in_c = false;
+        let mut i = 0;
+        while i < bytes.len() {
+            let run = digit_run(i);
+            // a switch costs one symbol and saves one per pair of digits
+            let worth_c = run >= 4 && (i == 0 || i + run == bytes.len()) || run >= 6;
+            if !in_c && worth_c {
+                // an odd digit out goes in set B first, except at the start
+                // where it can go last
+                if run % 2 == 1 && i != 0 {
+                    values.push(bytes[i] - b' ');
+                    i += 1;
+                }
+                values.push(if values.is_empty() {
+                    START_C
+                } else {
+                    SWITCH_TO_C
+                });
+                in_c = true;
+                continue;
+            }
+            if in_c {
+                if run >= 2 {
+                    values.push((bytes[i] - b'0') * 10 + (bytes[i + 1] - b'0'));
+                    i += 2;
+                    continue;
+                }
+                values.push(SWITCH_TO_B);
+                in_c = false;
+            }
+            if values.is_empty() {
+                values.push(START_B);
+            }
+            values.push(bytes[i] - b' ');
+            i += 1;
+        }
+        let checksum = values
+            .iter()
+            .enumerate()
+            .map(|(i, &v)| i.max(1) as u32 * v as u32)
+            .sum::<u32>()
+            % 103;
+        values.push(checksum as u8);
+
+        let mut modules = Vec::new();
+        for value in values {
+            push_bits(&mut modules, CODE128[value as usize] as u32, 11);
+        }
+        push_bits(&mut modules, CODE128_STOP as u32, CODE128_STOP_MODULES);
+        Ok(Self {
+            modules,
+            quiet: (10, 10),
+            text: String::from(data),
+        })
+    }
+
+    /// EAN-13 from 12 digits, or 13 with a check digit that must match
+    pub fn ean13(digits: &str) -> Result<Self, Error> {
+        let digits = ean_digits(digits, 13)?;
+        let mut modules = Vec::new();
+        push_bits(&mut modules, 0b101, 3);
+        let parity = EAN13_PARITY[digits[0] as usize];
+        for (i, &digit) in digits[1..7].iter().enumerate() {
+            let even = parity >> (5 - i) & 1 == 1;
+            push_left(&mut modules, digit, even);
+        }
+        push_bits(&mut modules, 0b01010, 5);
+        for &digit in &digits[7..] {
+            push_right(&mut modules, digit);
+        }
+        push_bits(&mut modules, 0b101, 3);
+        Ok(Self {
+            modules,
+            quiet: (11, 7),
+            text: digits.iter().map(|&d| (b'0' + d) as char).collect(),
+        })
+    }
+
+    /// EAN-8 from 7 digits, or 8 with a check digit that must match
+    pub fn ean8(digits: &str) -> Result<Self, Error> {
+        let digits = ean_digits(digits, 8)?;
+        let mut modules = Vec::new();
+        push_bits(&mut modules, 0b101, 3);
+        for &digit in &digits[..4] {
+            push_left(&mut modules, digit, false);
+        }
+        push_bits(&mut modules, 0b01010, 5);
+        for &digit in &digits[4..] {
+            push_right(&mut modules, digit);
+        }
+        push_bits(&mut modules, 0b101, 3);
+        Ok(Self {
+            modules,
+            quiet: (7, 7),
+            text: digits.iter().map(|&d| (b'0' + d) as char).collect(),
+        })
+    }
+
+    /// Modules across, quiet zones included
+    pub fn width_modules(&self) -> u32 {
+        self.modules.len() as u32 + self.quiet.0 + self.quiet.1
+    }
+
+    /// Draws the barcode centred in `area` with its text underneath;
+    /// [Error::InvalidConfig] if it doesn't fit at one pixel per module
+    pub fn draw(&self, frame: &mut Frame, area: Rectangle) -> Result<(), Error> {
+        let module = area.size.width / self.width_modules();
+        let bar_height = area.size.height.saturating_sub(TEXT_HEIGHT + TEXT_GAP);
+        if module == 0 || bar_height == 0 {
+            return Err(Error::InvalidConfig);
+        }
+        area.into_styled(PrimitiveStyle::with_fill(Gray2::WHITE))
+            .draw(frame)
+            .ok();
+
+        let used = self.width_modules() * module;
+        let left = area.top_left.x + ((area.size.width - used) / 2 + self.quiet.0 * module) as i32;
+        let bar = PrimitiveStyle::with_fill(Gray2::BLACK);
+        let mut x = 0;
+        // runs of bars drawn as one rectangle each
+        for (is_bar, run) in runs(&self.modules) {
+            if is_bar {
+                Rectangle::new(
+                    Point::new(left + (x * module) as i32, area.top_left.y),
+                    Size::new(run * module, bar_height),
+                )
+                .into_styled(bar)
+                .draw(frame)
+                .ok();
+            }
+            x += run;
+        }
+
+        let text_style = TextStyleBuilder::new()
+            .alignment(Alignment::Center)
+            .baseline(Baseline::Top)
+            .build();
+        Text::with_text_style(
+            &self.text,
+            Point::new(
+                area.top_left.x + area.size.width as i32 / 2,
+                area.top_left.y + (bar_height + TEXT_GAP) as i32,
+            ),
+            MonoTextStyle::new(&FONT_6X10, Gray2::BLACK),
+            text_style,
+        )
+        .draw(frame)
+        .ok();
+        Ok(())
+    }
+}
+
+fn push_bits(modules: &mut Vec<bool>, bits: u32, count: u32) {
+    modules.extend((0..count).rev().map(|i| bits >> i & 1 == 1));
+}
+
+fn push_left(modules: &mut Vec<bool>, digit: u8, even: bool) {
+    let code = EAN_L[digit as usize];
+    let code = if even {
+        // the right-hand pattern, mirrored
+        (!code & 0x7f).reverse_bits() >> 1
+    } else {
+        code
+    };
+    push_bits(modules, code as u32, 7);
+}
+
+fn push_right(modules: &mut Vec<bool>, digit: u8) {
+    push_bits(modules, (!EAN_L[digit as usize] & 0x7f) as u32, 7);
+}
+
+/// Parses `len - 1` digits and appends the check digit, or `len` digits
+/// whose check digit is right
+fn ean_digits(s: &str, len: usize) -> Result<Vec<u8>, Error> {
+    if !s.bytes().all(|b| b.is_ascii_digit()) || !(len - 1..=len).contains(&s.len()) {
+        return Err(Error::InvalidConfig);
+    }
+    let mut digits: Vec<u8> = s.bytes().map(|b| b - b'0').collect();
+    // weights 3 and 1 alternate leftwards from the digit next to the check
+    let sum: u32 = digits[..len - 1]
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &d)| d as u32 * if i % 2 == 0 { 3 } else { 1 })
+        .sum();
+    let check = ((10 - sum % 10) % 10) as u8;
+    match digits.get(len - 1) {
+        Some(&given) if given != check => return Err(Error::InvalidConfig),
+        Some(_) => {}
+        None => digits.push(check),
+    }
+    Ok(digits)
+}
+
+/// Lengths of the runs of equal modules
+fn runs(modules: &[bool]) -> impl Iterator<Item = (bool, u32)> + '_ {
+    modules
+        .chunk_by(|a, b| a == b)
+        .map(|run| (run[0], run.len() as u32))
+}
@@ -0,0 +1,42 @@
+//! Labels drawn along the bottom edge, above the physical buttons.
+
+use embedded_graphics::{
+    mono_font::MonoTextStyle,
+    pixelcolor::Gray2,
+    prelude::*,
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
+};
+
+use crate::{
+    display::{Frame, HEIGHT, WIDTH},
+    fonts::FIXED_6X10,
+};
+
+/// Height of the strip taken by the hints
+pub const BUTTON_BAR_HEIGHT: u32 = 12;
+
+/// Draws one label per button (A-D), translated ones too; `None` leaves the
+/// slot empty
+pub fn draw_button_hints(frame: &mut Frame, labels: [Option<&str>; 4]) {
+    let character_style = MonoTextStyle::new(&FIXED_6X10, Gray2::BLACK);
+    let text_style = TextStyleBuilder::new()
+        .alignment(Alignment::Center)
+        .baseline(Baseline::Bottom)
+        .build();
+
+    for (i, label) in labels.into_iter().enumerate() {
+        let Some(label) = label else {
+            continue;
+        };
+        // the buttons sit roughly under the centres of four equal columns
+        let x = (WIDTH * (2 * i as u32 + 1) / 8) as i32;
+        Text::with_text_style(
+            label,
+            Point::new(x, HEIGHT as i32 - 1),
+            character_style,
+            text_style,
+        )
+        .draw(frame)
+        .ok();
+    }
+}
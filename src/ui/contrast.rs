@@ -0,0 +1,121 @@
+//! Keeping text legible over images and mid-gray backgrounds.
+//!
+//! With four levels, light text over a dithered image or gray fill easily
+//! vanishes. [draw_outlined] rings text in a contrasting color, [draw_shadowed]
+//! drops a one pixel shadow, [draw_knockout] clears a box behind it, and
+//! [draw_inverted] draws whatever it is given light-on-dark inside a region.
+
+use embedded_graphics::{
+    draw_target::DrawTargetExt,
+    pixelcolor::Gray2,
+    prelude::*,
+    primitives::{PrimitiveStyle, Rectangle},
+    text::{renderer::TextRenderer, Text},
+};
+
+/// Draws `text` with a one pixel `halo` on every side
+pub fn draw_outlined<S, D>(target: &mut D, text: &Text<'_, S>, halo: S::Color)
+where
+    S: TextRenderer + Clone + Recolor,
+    D: DrawTarget<Color = S::Color>,
+{
+    let ring = recolored(text, halo);
+    for dy in -1..=1 {
+        for dx in -1..=1 {
+            if (dx, dy) != (0, 0) {
+                ring.translate(Point::new(dx, dy)).draw(target).ok();
+            }
+        }
+    }
+    text.draw(target).ok();
+}
+
+/// Draws `text` over a copy in `shadow` one pixel down and to the right
+pub fn draw_shadowed<S, D>(target: &mut D, text: &Text<'_, S>, shadow: S::Color)
+where
+    S: TextRenderer + Clone + Recolor,
+    D: DrawTarget<Color = S::Color>,
+{
+    recolored(text, shadow)
+        .translate(Point::new(1, 1))
+        .draw(target)
+        .ok();
+    text.draw(target).ok();
+}
+
+/// Fills the box around `text`, `padding` pixels larger on each side, with
+/// `background` and draws the text on it
+pub fn draw_knockout<S, D>(target: &mut D, text: &Text<'_, S>, background: S::Color, padding: u32)
+where
+    S: TextRenderer + Clone,
+    D: DrawTarget<Color = S::Color>,
+{
+    text.bounding_box()
+        .offset(padding as i32)
+        .into_styled(PrimitiveStyle::with_fill(background))
+        .draw(target)
+        .ok();
+    text.draw(target).ok();
+}
+
+/// Fills `area` black and runs `draw` with every level flipped, clipped to
+/// `area`: white text drawn as [Gray2::BLACK] shows up white on black
+pub fn draw_inverted<D>(target: &mut D, area: Rectangle, draw: impl FnOnce(&mut Inverted<'_, D>))
+where
+    D: DrawTarget<Color = Gray2>,
+{
+    area.into_styled(PrimitiveStyle::with_fill(Gray2::BLACK))
+        .draw(target)
+        .ok();
+    draw(&mut Inverted { target, area });
+}
+
+/// A draw target that flips levels, see [draw_inverted]
+pub struct Inverted<'t, D> {
+    target: &'t mut D,
+    area: Rectangle,
+}
+
+impl<D> Dimensions for Inverted<'_, D> {
+    fn bounding_box(&self) -> Rectangle {
+        self.area
+    }
+}
+
+impl<D: DrawTarget<Color = Gray2>> DrawTarget for Inverted<'_, D> {
+    type Color = Gray2;
+    type Error = D::Error;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let area = self.area;
+        self.target.clipped(&area).draw_iter(
+            pixels
+                .into_iter()
+                .map(|Pixel(point, color)| Pixel(point, Gray2::new(3 - color.luma()))),
+        )
+    }
+}
+
+/// Text styles whose color can be swapped for a halo or shadow
+pub trait Recolor: TextRenderer {
+    fn with_color(&self, color: Self::Color) -> Self;
+}
+
+impl<C: PixelColor> Recolor for embedded_graphics::mono_font::MonoTextStyle<'_, C> {
+    fn with_color(&self, color: C) -> Self {
+        let mut style = *self;
+        style.text_color = Some(color);
+        // a background would paint over the text next to each copy
+        style.background_color = None;
+        style
+    }
+}
+
+fn recolored<'a, S: Recolor + Clone>(text: &Text<'a, S>, color: S::Color) -> Text<'a, S> {
+    let mut text = text.clone();
+    text.character_style = text.character_style.with_color(color);
+    text
+}
@@ -0,0 +1,185 @@
+//! A modal question with up to two answers on the front buttons.
+//!
+//! An app [open]s a [Dialog] and the compositor draws it over the screen.
+//! While it is up, the host turns a press of one of its buttons into
+//! [Event::Answer] for the app and closes it; other presses, chords and long
+//! presses are ignored.
+//!
+//! ```ignore
+//! dialog::open(
+//!     Dialog::new("Reset steps?", "Today's count starts over")
+//!         .button(Button::A, "Cancel")
+//!         .button(Button::D, "Reset"),
+//! );
+//! ```
+
+use alloc::string::String;
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+use embedded_graphics::{
+    mono_font::MonoTextStyle,
+    pixelcolor::Gray2,
+    prelude::*,
+    primitives::{PrimitiveStyle, PrimitiveStyleBuilder, Rectangle},
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
+};
+
+use crate::{
+    compositor::{Depth, Layer},
+    display::{Frame, HEIGHT, WIDTH},
+    fonts::{FIXED_6X10, FIXED_7X14_BOLD},
+    input::{Button, Event},
+    ui::button_bar::{draw_button_hints, BUTTON_BAR_HEIGHT},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dialog {
+    title: String,
+    message: String,
+    buttons: [Option<(Button, String)>; 2],
+}
+
+impl Dialog {
+    pub fn new(title: &str, message: &str) -> Self {
+        Self {
+            title: String::from(title),
+            message: String::from(message),
+            buttons: [None, None],
+        }
+    }
+
+    /// Answers with `button`, labelled `label` above it; a third button
+    /// replaces the second
+    pub fn button(mut self, button: Button, label: &str) -> Self {
+        let slot = if self.buttons[0].is_none() { 0 } else { 1 };
+        self.buttons[slot] = Some((button, String::from(label)));
+        self
+    }
+
+    fn answers(&self, button: Button) -> bool {
+        self.buttons.iter().flatten().any(|(b, _)| *b == button)
+    }
+}
+
+struct State {
+    dialog: Option<Dialog>,
+    changed: bool,
+}
+
+static DIALOG: Mutex<RefCell<State>> = Mutex::new(RefCell::new(State {
+    dialog: None,
+    changed: false,
+}));
+
+/// Shows `dialog`, replacing any open one
+pub fn open(dialog: Dialog) {
+    set(Some(dialog));
+}
+
+/// Takes the open dialog down without an answer
+pub fn close() {
+    set(None);
+}
+
+pub fn is_open() -> bool {
+    critical_section::with(|cs| DIALOG.borrow_ref(cs).dialog.is_some())
+}
+
+fn set(dialog: Option<Dialog>) {
+    critical_section::with(|cs| {
+        let mut state = DIALOG.borrow_ref_mut(cs);
+        if state.dialog.is_some() || dialog.is_some() {
+            state.dialog = dialog;
+            state.changed = true;
+        }
+    });
+}
+
+/// What the app gets for `event`, see the [module docs](self)
+pub fn route(event: Event) -> Option<Event> {
+    if !is_open() {
+        return Some(event);
+    }
+    match event {
+        Event::Press(button) => {
+            let answered = critical_section::with(|cs| {
+                DIALOG
+                    .borrow_ref(cs)
+                    .dialog
+                    .as_ref()
+                    .is_some_and(|dialog| dialog.answers(button))
+            });
+            answered.then(|| {
+                close();
+                Event::Answer(button)
+            })
+        }
+        Event::Chord(_) | Event::LongPress(_) => None,
+        Event::Tick | Event::Link(_) | Event::Answer(_) | Event::Action(_) => Some(event),
+    }
+}
+
+/// Draws the open [Dialog]
+pub struct DialogLayer;
+
+impl Layer for DialogLayer {
+    fn depth(&self) -> Depth {
+        Depth::Modal
+    }
+
+    fn take_dirty(&mut self) -> bool {
+        critical_section::with(|cs| core::mem::take(&mut DIALOG.borrow_ref_mut(cs).changed))
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let Some(dialog) = critical_section::with(|cs| DIALOG.borrow_ref(cs).dialog.clone()) else {
+            return;
+        };
+        let area = Rectangle::new(Point::new(20, 16), Size::new(WIDTH - 40, 84));
+        area.into_styled(
+            PrimitiveStyleBuilder::new()
+                .fill_color(Gray2::WHITE)
+                .stroke_color(Gray2::BLACK)
+                .stroke_width(2)
+                .build(),
+        )
+        .draw(frame)
+        .ok();
+        let centered = TextStyleBuilder::new()
+            .alignment(Alignment::Center)
+            .baseline(Baseline::Top)
+            .build();
+        let center = area.center().x;
+        Text::with_text_style(
+            &dialog.title,
+            Point::new(center, area.top_left.y + 8),
+            MonoTextStyle::new(&FIXED_7X14_BOLD, Gray2::BLACK),
+            centered,
+        )
+        .draw(frame)
+        .ok();
+        Text::with_text_style(
+            &dialog.message,
+            Point::new(center, area.top_left.y + 32),
+            MonoTextStyle::new(&FIXED_6X10, Gray2::BLACK),
+            centered,
+        )
+        .draw(frame)
+        .ok();
+
+        // the dialog's answers replace the app's hints
+        Rectangle::new(
+            Point::new(0, (HEIGHT - BUTTON_BAR_HEIGHT) as i32),
+            Size::new(WIDTH, BUTTON_BAR_HEIGHT),
+        )
+        .into_styled(PrimitiveStyle::with_fill(Gray2::WHITE))
+        .draw(frame)
+        .ok();
+        let mut labels = [None; 4];
+        for (button, label) in dialog.buttons.iter().flatten() {
+            labels[*button as usize] = Some(label.as_str());
+        }
+        draw_button_hints(frame, labels);
+    }
+}
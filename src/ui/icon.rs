@@ -0,0 +1,115 @@
+//! Small line-art status icons, 12 by 12 pixels.
+
+use core::str::FromStr;
+
+use embedded_graphics::{
+    pixelcolor::Gray2,
+    prelude::*,
+    primitives::{Arc, Circle, Line, PrimitiveStyle, Rectangle, Triangle},
+};
+
+use crate::{display::Frame, Error};
+
+/// Width and height of every icon
+pub const SIZE: u32 = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Icon {
+    /// Triangle with an exclamation mark
+    Warning,
+    /// Battery on its side, nearly empty
+    Battery,
+    /// Envelope
+    Mail,
+    /// Radio waves over a dot
+    Wifi,
+}
+
+impl FromStr for Icon {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "warning" => Ok(Icon::Warning),
+            "battery" => Ok(Icon::Battery),
+            "mail" => Ok(Icon::Mail),
+            "wifi" => Ok(Icon::Wifi),
+            _ => Err(Error::InvalidConfig),
+        }
+    }
+}
+
+impl Icon {
+    /// Draws the icon on a white square one pixel larger on each side, so
+    /// it stands out from whatever is behind it
+    pub fn draw_boxed(self, frame: &mut Frame, origin: Point) {
+        Rectangle::new(origin - Point::new(1, 1), Size::new(SIZE + 2, SIZE + 2))
+            .into_styled(PrimitiveStyle::with_fill(Gray2::WHITE))
+            .draw(frame)
+            .ok();
+        self.draw(frame, origin);
+    }
+
+    /// Draws the icon in black with its top left corner at `origin`
+    pub fn draw(self, frame: &mut Frame, origin: Point) {
+        let stroke = PrimitiveStyle::with_stroke(Gray2::BLACK, 1);
+        let fill = PrimitiveStyle::with_fill(Gray2::BLACK);
+        let at = |x, y| origin + Point::new(x, y);
+        match self {
+            Icon::Warning => {
+                Triangle::new(at(5, 0), at(0, 11), at(11, 11))
+                    .into_styled(stroke)
+                    .draw(frame)
+                    .ok();
+                Line::new(at(5, 4), at(5, 7))
+                    .into_styled(stroke)
+                    .draw(frame)
+                    .ok();
+                Line::new(at(5, 9), at(5, 9))
+                    .into_styled(stroke)
+                    .draw(frame)
+                    .ok();
+            }
+            Icon::Battery => {
+                Rectangle::new(at(0, 3), Size::new(10, 6))
+                    .into_styled(stroke)
+                    .draw(frame)
+                    .ok();
+                Rectangle::new(at(10, 5), Size::new(2, 2))
+                    .into_styled(fill)
+                    .draw(frame)
+                    .ok();
+                Rectangle::new(at(2, 5), Size::new(2, 2))
+                    .into_styled(fill)
+                    .draw(frame)
+                    .ok();
+            }
+            Icon::Mail => {
+                Rectangle::new(at(0, 2), Size::new(12, 8))
+                    .into_styled(stroke)
+                    .draw(frame)
+                    .ok();
+                Line::new(at(0, 2), at(6, 6))
+                    .into_styled(stroke)
+                    .draw(frame)
+                    .ok();
+                Line::new(at(11, 2), at(6, 6))
+                    .into_styled(stroke)
+                    .draw(frame)
+                    .ok();
+            }
+            Icon::Wifi => {
+                for diameter in [7, 13] {
+                    Arc::with_center(at(6, 10), diameter, 225.0.deg(), 90.0.deg())
+                        .into_styled(stroke)
+                        .draw(frame)
+                        .ok();
+                }
+                Circle::with_center(at(6, 10), 3)
+                    .into_styled(fill)
+                    .draw(frame)
+                    .ok();
+            }
+        }
+    }
+}
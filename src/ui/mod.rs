@@ -0,0 +1,12 @@
+//! Reusable UI components for apps.
+
+pub mod barcode;
+pub mod button_bar;
+pub mod contrast;
+pub mod dialog;
+pub mod icon;
+pub mod pager;
+pub mod stale;
+pub mod template;
+pub mod text_entry;
+pub mod toast;
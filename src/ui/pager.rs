@@ -0,0 +1,186 @@
+//! Splits an app into pages flipped with the outer buttons, with dot indicators
+//! along the bottom edge, or the page number when there are too many pages
+//! for a row of dots.
+
+use alloc::{boxed::Box, format, vec::Vec};
+
+use embedded_graphics::{
+    mono_font::{ascii::FONT_6X10, MonoTextStyle},
+    pixelcolor::Gray2,
+    prelude::*,
+    primitives::{Circle, PrimitiveStyle, Rectangle},
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
+};
+
+use crate::{
+    actions::Action,
+    app::Flow,
+    display::{Frame, HEIGHT, WIDTH},
+    input::{Button, Event},
+};
+
+/// Height of the strip reserved for the page indicators
+pub const INDICATOR_HEIGHT: u32 = 10;
+
+const DOT_DIAMETER: u32 = 6;
+const DOT_SPACING: u32 = 12;
+/// Pages whose dots fit across the panel; more show `n/N` instead
+pub const MAX_DOTS: u32 = (WIDTH - DOT_DIAMETER) / DOT_SPACING + 1;
+
+type Page<'p, S> = Box<dyn FnMut(&S, &mut Frame, Rectangle) + 'p>;
+
+/// A list of pages sharing the app state `S`
+///
+/// Each page is drawn into the content area above the indicator strip.
+pub struct Pager<'p, S = ()> {
+    pages: Vec<Page<'p, S>>,
+    current: usize,
+    prev: Button,
+    next: Button,
+    wrap: bool,
+}
+
+impl<'p, S> Default for Pager<'p, S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'p, S> Pager<'p, S> {
+    /// Pages back with A and forward with D, wrapping around at the ends
+    pub fn new() -> Self {
+        Self {
+            pages: Vec::new(),
+            current: 0,
+            prev: Button::A,
+            next: Button::D,
+            wrap: true,
+        }
+    }
+
+    /// Uses different buttons for paging
+    pub fn with_buttons(mut self, prev: Button, next: Button) -> Self {
+        self.prev = prev;
+        self.next = next;
+        self
+    }
+
+    /// Stops at the first and last page instead of wrapping
+    pub fn with_wrap(mut self, wrap: bool) -> Self {
+        self.wrap = wrap;
+        self
+    }
+
+    /// Appends a page
+    pub fn add_page(&mut self, page: impl FnMut(&S, &mut Frame, Rectangle) + 'p) -> &mut Self {
+        self.pages.push(Box::new(page));
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.pages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pages.is_empty()
+    }
+
+    /// Index of the page shown
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    pub fn set_current(&mut self, index: usize) {
+        self.current = index.min(self.pages.len().saturating_sub(1));
+    }
+
+    /// Area pages draw into
+    pub fn content_area() -> Rectangle {
+        Rectangle::new(Point::zero(), Size::new(WIDTH, HEIGHT - INDICATOR_HEIGHT))
+    }
+
+    /// Flips pages on the paging buttons and the page [Action]s; other
+    /// events are left to the app
+    pub fn handle(&mut self, event: Event) -> Flow {
+        let len = self.pages.len();
+        if len < 2 {
+            return Flow::Idle;
+        }
+
+        let forward = match event {
+            Event::Press(b) if b == self.next => true,
+            Event::Press(b) if b == self.prev => false,
+            Event::Action(Action::NextPage) => true,
+            Event::Action(Action::PreviousPage) => false,
+            _ => return Flow::Idle,
+        };
+        let target = if forward {
+            match self.current + 1 {
+                n if n < len => n,
+                _ if self.wrap => 0,
+                _ => return Flow::Idle,
+            }
+        } else {
+            match self.current {
+                0 if self.wrap => len - 1,
+                0 => return Flow::Idle,
+                n => n - 1,
+            }
+        };
+
+        self.current = target;
+        Flow::Redraw
+    }
+
+    /// Draws the current page and the indicators
+    pub fn render(&mut self, state: &S, frame: &mut Frame) {
+        let Some(page) = self.pages.get_mut(self.current) else {
+            return;
+        };
+        page(state, frame, Self::content_area());
+        self.draw_indicators(frame);
+    }
+
+    fn draw_indicators(&self, frame: &mut Frame) {
+        let len = self.pages.len() as u32;
+        if len < 2 {
+            return;
+        }
+
+        if len > MAX_DOTS {
+            let number = format!("{}/{}", self.current + 1, len);
+            let centered = TextStyleBuilder::new()
+                .alignment(Alignment::Center)
+                .baseline(Baseline::Top)
+                .build();
+            Text::with_text_style(
+                &number,
+                Point::new(WIDTH as i32 / 2, (HEIGHT - INDICATOR_HEIGHT) as i32),
+                MonoTextStyle::new(&FONT_6X10, Gray2::BLACK),
+                centered,
+            )
+            .draw(frame)
+            .ok();
+            return;
+        }
+
+        let row_width = (len - 1) * DOT_SPACING + DOT_DIAMETER;
+        let left = WIDTH.saturating_sub(row_width) as i32 / 2;
+        let top = (HEIGHT - INDICATOR_HEIGHT + (INDICATOR_HEIGHT - DOT_DIAMETER) / 2) as i32;
+        let filled = PrimitiveStyle::with_fill(Gray2::BLACK);
+        let outline = PrimitiveStyle::with_stroke(Gray2::new(0x01), 1);
+
+        for i in 0..len {
+            let dot = Circle::new(
+                Point::new(left + (i * DOT_SPACING) as i32, top),
+                DOT_DIAMETER,
+            );
+            let style = if i as usize == self.current {
+                filled
+            } else {
+                outline
+            };
+            dot.into_styled(style).draw(frame).ok();
+        }
+    }
+}
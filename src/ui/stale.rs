@@ -0,0 +1,50 @@
+//! A corner tag for screens showing data that couldn't be refreshed, see
+//! [crate::data_source].
+
+use alloc::format;
+
+use embedded_graphics::{
+    mono_font::MonoTextStyle,
+    pixelcolor::Gray2,
+    prelude::*,
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
+};
+
+use crate::{
+    data_source::Stale,
+    display::{Frame, WIDTH},
+    fonts::FIXED_6X10,
+    time, tr,
+    ui::contrast::draw_knockout,
+};
+
+/// Space between the tag and the edges of the screen, and around its text
+const MARGIN: i32 = 2;
+
+/// Draws "stale since HH:MM" light on dark in the top right corner, with the
+/// date instead once the data is from another day
+pub fn draw_stale_banner(frame: &mut Frame, stale: &Stale) {
+    let since = stale.since.and_then(|since| {
+        let local = time::to_local(since)?;
+        let today = time::now_local().is_some_and(|now| now.date() == local.date());
+        let at = if today {
+            format!("{:02}:{:02}", local.hour(), local.minute())
+        } else {
+            format!("{}-{:02}", local.month(), local.day())
+        };
+        Some(tr!("stale_since", at))
+    });
+    let text = since.as_deref().unwrap_or(tr!("stale"));
+    let style = MonoTextStyle::new(&FIXED_6X10, Gray2::WHITE);
+    let text_style = TextStyleBuilder::new()
+        .alignment(Alignment::Right)
+        .baseline(Baseline::Top)
+        .build();
+    let text = Text::with_text_style(
+        text,
+        Point::new(WIDTH as i32 - 1 - 2 * MARGIN, 2 * MARGIN),
+        style,
+        text_style,
+    );
+    draw_knockout(frame, &text, Gray2::BLACK, MARGIN as u32);
+}
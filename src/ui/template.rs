@@ -0,0 +1,386 @@
+//! Screens described in a small TOML subset, loaded at runtime so a
+//! dashboard can change without a new firmware.
+//!
+//! Each `[[kind]]` table is one element, drawn in order:
+//!
+//! ```toml
+//! [[label]]
+//! at = [4, 2]
+//! text = "Living room"
+//! font = "bold"
+//!
+//! [[value]]
+//! at = [4, 24]
+//! bind = "battery.mv"
+//! format = "{} mV"
+//! font = "large"
+//!
+//! [[icon]]
+//! at = [280, 2]
+//! name = "wifi"
+//!
+//! [[line]]
+//! at = [0, 20]
+//! to = [295, 20]
+//!
+//! [[box]]
+//! at = [200, 40]
+//! size = [90, 40]
+//! fill = "light"
+//! ```
+//!
+//! Positions are the top left corner in pixels; they and sizes are no more
+//! than [MAX_COORDINATE] either way. Fonts are `small` (the default), `bold`
+//! and `large`; colors `black` (the default), `dark`, `light` and `white`;
+//! `align` is `left`, `center` or `right` of `at`.
+//! A value's `{}` is replaced by the named value, or `--` while there is none.
+//! Icons are those of [Icon]. [crate::apps::dashboard] loads templates from
+//! the asset store or over HTTP.
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::str::FromStr;
+
+use embedded_graphics::{
+    mono_font::{
+        ascii::{FONT_10X20, FONT_6X10, FONT_7X14_BOLD},
+        MonoFont, MonoTextStyle,
+    },
+    pixelcolor::Gray2,
+    prelude::*,
+    primitives::{Line, PrimitiveStyle, Rectangle},
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
+};
+use log::warn;
+
+use crate::{
+    bindings::Subscriptions,
+    display::{Frame, WIDTH},
+    ui::icon::Icon,
+    Error,
+};
+
+/// Shown for a value that isn't available
+pub const MISSING: &str = "--";
+/// Far enough off the panel for anything partly on it; lines much longer
+/// than this overflow when drawn
+pub const MAX_COORDINATE: i32 = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Font {
+    Small,
+    Bold,
+    Large,
+}
+
+impl Font {
+    fn mono(self) -> &'static MonoFont<'static> {
+        match self {
+            Font::Small => &FONT_6X10,
+            Font::Bold => &FONT_7X14_BOLD,
+            Font::Large => &FONT_10X20,
+        }
+    }
+}
+
+/// A run of text and how to set it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Style {
+    pub font: Font,
+    pub color: Gray2,
+    pub align: Alignment,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Kind {
+    Label {
+        text: String,
+        style: Style,
+    },
+    /// `format` with `{}` standing for the value named `bind`
+    Value {
+        bind: String,
+        format: String,
+        style: Style,
+    },
+    Icon(Icon),
+    Line {
+        to: Point,
+        color: Gray2,
+    },
+    Box {
+        size: Size,
+        fill: Gray2,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Element {
+    pub at: Point,
+    pub kind: Kind,
+}
+
+/// A parsed screen description, see the [module docs](self)
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Template {
+    pub elements: Vec<Element>,
+}
+
+impl Template {
+    /// Names the values are bound to, in drawing order
+    pub fn bindings(&self) -> impl Iterator<Item = &str> {
+        self.elements
+            .iter()
+            .filter_map(|element| match &element.kind {
+                Kind::Value { bind, .. } => Some(bind.as_str()),
+                _ => None,
+            })
+    }
+
+    /// Subscribes every binding with the row its value is drawn in, as the
+    /// width of the text changes with the value
+    pub fn subscribe(&self, subscriptions: &mut Subscriptions) {
+        for element in &self.elements {
+            if let Kind::Value { bind, style, .. } = &element.kind {
+                let height = style.font.mono().character_size.height;
+                let row = Rectangle::new(Point::new(0, element.at.y), Size::new(WIDTH, height));
+                subscriptions.subscribe(bind, row);
+            }
+        }
+    }
+
+    /// Draws the elements, asking `value` for each binding
+    pub fn render(&self, frame: &mut Frame, value: impl Fn(&str) -> Option<String>) {
+        for element in &self.elements {
+            let at = element.at;
+            match &element.kind {
+                Kind::Label { text, style } => draw_text(frame, text, at, style),
+                Kind::Value {
+                    bind,
+                    format,
+                    style,
+                } => {
+                    let value = value(bind);
+                    let text = format.replacen("{}", value.as_deref().unwrap_or(MISSING), 1);
+                    draw_text(frame, &text, at, style);
+                }
+                Kind::Icon(icon) => icon.draw(frame, at),
+                Kind::Line { to, color } => {
+                    Line::new(at, *to)
+                        .into_styled(PrimitiveStyle::with_stroke(*color, 1))
+                        .draw(frame)
+                        .ok();
+                }
+                Kind::Box { size, fill } => {
+                    Rectangle::new(at, *size)
+                        .into_styled(PrimitiveStyle::with_fill(*fill))
+                        .draw(frame)
+                        .ok();
+                }
+            }
+        }
+    }
+}
+
+fn draw_text(frame: &mut Frame, text: &str, at: Point, style: &Style) {
+    let text_style = TextStyleBuilder::new()
+        .alignment(style.align)
+        .baseline(Baseline::Top)
+        .build();
+    Text::with_text_style(
+        text,
+        at,
+        MonoTextStyle::new(style.font.mono(), style.color),
+        text_style,
+    )
+    .draw(frame)
+    .ok();
+}
+
+/// A template from the bytes of its source, which must be UTF-8
+pub fn parse(raw: &[u8]) -> Result<Template, Error> {
+    core::str::from_utf8(raw)
+        .map_err(|_| Error::InvalidConfig)?
+        .parse()
+}
+
+impl FromStr for Template {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tables: Vec<(&str, Vec<(&str, Value)>)> = Vec::new();
+        for (n, line) in s.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let parsed = if let Some(kind) = line
+                .strip_prefix("[[")
+                .and_then(|rest| rest.strip_suffix("]]"))
+            {
+                tables.push((kind.trim(), Vec::new()));
+                Ok(())
+            } else {
+                match (line.split_once('='), tables.last_mut()) {
+                    (Some((key, value)), Some((_, entries))) => value
+                        .trim()
+                        .parse()
+                        .map(|value| entries.push((key.trim(), value))),
+                    _ => Err(Error::InvalidConfig),
+                }
+            };
+            if let Err(err) = parsed {
+                warn!("template line {}: {}", n + 1, line);
+                return Err(err);
+            }
+        }
+
+        let elements = tables
+            .into_iter()
+            .map(|(kind, entries)| {
+                element(kind, &entries).inspect_err(|_| warn!("template: bad [[{}]]", kind))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { elements })
+    }
+}
+
+fn element(kind: &str, entries: &[(&str, Value)]) -> Result<Element, Error> {
+    let get = |key| entries.iter().find(|(k, _)| *k == key).map(|(_, v)| v);
+    let string = |key| match get(key) {
+        Some(Value::String(s)) => Some(s.as_str()),
+        _ => None,
+    };
+    let pair = |key| match get(key) {
+        Some(&Value::Pair(x, y)) => Some((x, y)),
+        _ => None,
+    };
+    let color = |key, default| string(key).map_or(Ok(default), parse_color);
+    let style = || -> Result<Style, Error> {
+        Ok(Style {
+            font: match string("font") {
+                None | Some("small") => Font::Small,
+                Some("bold") => Font::Bold,
+                Some("large") => Font::Large,
+                Some(_) => return Err(Error::InvalidConfig),
+            },
+            color: color("color", Gray2::BLACK)?,
+            align: match string("align") {
+                None | Some("left") => Alignment::Left,
+                Some("center") => Alignment::Center,
+                Some("right") => Alignment::Right,
+                Some(_) => return Err(Error::InvalidConfig),
+            },
+        })
+    };
+
+    let (x, y) = pair("at").ok_or(Error::InvalidConfig)?;
+    let kind = match kind {
+        "label" => Kind::Label {
+            text: string("text").ok_or(Error::InvalidConfig)?.to_string(),
+            style: style()?,
+        },
+        "value" => Kind::Value {
+            bind: string("bind").ok_or(Error::InvalidConfig)?.to_string(),
+            format: string("format").unwrap_or("{}").to_string(),
+            style: style()?,
+        },
+        "icon" => Kind::Icon(string("name").ok_or(Error::InvalidConfig)?.parse()?),
+        "line" => {
+            let (to_x, to_y) = pair("to").ok_or(Error::InvalidConfig)?;
+            Kind::Line {
+                to: Point::new(to_x, to_y),
+                color: color("color", Gray2::BLACK)?,
+            }
+        }
+        "box" => {
+            let (width, height) = pair("size").ok_or(Error::InvalidConfig)?;
+            Kind::Box {
+                size: Size::new(
+                    u32::try_from(width).map_err(|_| Error::InvalidConfig)?,
+                    u32::try_from(height).map_err(|_| Error::InvalidConfig)?,
+                ),
+                fill: color("fill", Gray2::BLACK)?,
+            }
+        }
+        _ => return Err(Error::InvalidConfig),
+    };
+    Ok(Element {
+        at: Point::new(x, y),
+        kind,
+    })
+}
+
+fn parse_color(s: &str) -> Result<Gray2, Error> {
+    match s {
+        "black" => Ok(Gray2::BLACK),
+        "dark" => Ok(Gray2::new(1)),
+        "light" => Ok(Gray2::new(2)),
+        "white" => Ok(Gray2::WHITE),
+        _ => Err(Error::InvalidConfig),
+    }
+}
+
+/// Drops a `#` comment, leaving any `#` inside a string alone
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// The right-hand side of a `key = value` line
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Value {
+    /// A quoted string, escapes resolved
+    String(String),
+    /// `[x, y]`
+    Pair(i32, i32),
+}
+
+impl FromStr for Value {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(inner) = s.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+            let (x, y) = inner.split_once(',').ok_or(Error::InvalidConfig)?;
+            let number = |n: &str| {
+                n.trim()
+                    .parse()
+                    .ok()
+                    .filter(|n: &i32| n.abs() <= MAX_COORDINATE)
+                    .ok_or(Error::InvalidConfig)
+            };
+            return Ok(Value::Pair(number(x)?, number(y)?));
+        }
+        let inner = s
+            .strip_prefix('"')
+            .and_then(|s| s.strip_suffix('"'))
+            .ok_or(Error::InvalidConfig)?;
+        let mut out = String::with_capacity(inner.len());
+        let mut chars = inner.chars();
+        while let Some(c) = chars.next() {
+            out.push(match c {
+                '\\' => match chars.next() {
+                    Some('"') => '"',
+                    Some('\\') => '\\',
+                    Some('n') => '\n',
+                    _ => return Err(Error::InvalidConfig),
+                },
+                '"' => return Err(Error::InvalidConfig),
+                c => c,
+            });
+        }
+        Ok(Value::String(out))
+    }
+}
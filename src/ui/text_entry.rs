@@ -0,0 +1,320 @@
+//! An on-screen keyboard for short text, such as a WiFi password or a name,
+//! typed with the four front buttons when there's no phone or computer to
+//! send it from.
+//!
+//! A and D move along a row of the character grid and B goes down a row,
+//! all three wrapping round and repeating while held; a long press of B goes
+//! up a row, when long presses are on. C types the key under the cursor. The
+//! bottom row has the keys that aren't characters: the next of lowercase,
+//! uppercase and symbols, space, delete, cancel and OK.
+//!
+//! The app owns the [TextEntry], draws it instead of its own screen and
+//! passes it every event until [TextEntry::handle] says it is done:
+//!
+//! ```ignore
+//! match entry.handle(event) {
+//!     Outcome::Editing(flow) => flow,
+//!     Outcome::Done(password) => self.join(&password, ctx),
+//!     Outcome::Cancelled => Flow::Redraw,
+//! }
+//! ```
+
+use alloc::string::String;
+
+use embedded_graphics::{
+    mono_font::{ascii::FONT_7X14, MonoTextStyle},
+    pixelcolor::Gray2,
+    prelude::*,
+    primitives::{PrimitiveStyle, Rectangle},
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
+};
+
+use crate::{
+    app::Flow,
+    display::{Frame, WIDTH},
+    fonts::{FIXED_6X10, FIXED_7X14_BOLD},
+    input::{self, Button, ButtonSet, Event},
+    tr,
+    ui::button_bar::draw_button_hints,
+};
+
+/// The buttons that repeat while held, for moving across the grid quickly
+pub const REPEAT: ButtonSet = ButtonSet::of(&[Button::A, Button::B, Button::D]);
+
+/// Characters in each row of each layer
+const COLUMNS: usize = 13;
+/// Rows of characters, then one of [Control]s
+const CHARACTER_ROWS: usize = 3;
+const ROWS: usize = CHARACTER_ROWS + 1;
+/// Lowercase, uppercase and symbols, each row [COLUMNS] long
+const LAYERS: [[&str; CHARACTER_ROWS]; 3] = [
+    ["abcdefghijklm", "nopqrstuvwxyz", "0123456789-_."],
+    ["ABCDEFGHIJKLM", "NOPQRSTUVWXYZ", "0123456789-_."],
+    ["!@#$%^&*()+=/", "?<>[]{}|\\:;\"'", "~`,0123456789"],
+];
+/// What the layer key shows, the name of the layer it switches to
+const LAYER_NAMES: [&str; 3] = ["ABC", "#+=", "abc"];
+
+const ENTRY_TOP: i32 = 18;
+const ENTRY_HEIGHT: u32 = 19;
+const GRID_TOP: i32 = 42;
+const ROW_HEIGHT: i32 = 15;
+/// Entry text shown; longer text scrolls to keep the end in view
+const VISIBLE_CHARS: usize = 39;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Control {
+    Layer,
+    Space,
+    Delete,
+    Cancel,
+    Ok,
+}
+
+const CONTROLS: [Control; 5] = [
+    Control::Layer,
+    Control::Space,
+    Control::Delete,
+    Control::Cancel,
+    Control::Ok,
+];
+
+/// What [TextEntry::handle] made of an event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// Still typing
+    Editing(Flow),
+    /// OK, with the text typed
+    Done(String),
+    Cancelled,
+}
+
+/// A text field and the keyboard for it
+#[derive(Debug, Clone)]
+pub struct TextEntry {
+    title: String,
+    text: String,
+    max_len: usize,
+    masked: bool,
+    layer: usize,
+    row: usize,
+    column: usize,
+}
+
+impl TextEntry {
+    /// An empty field of up to 32 characters, titled `title`
+    pub fn new(title: &str) -> Self {
+        input::set_repeat(REPEAT);
+        Self {
+            title: String::from(title),
+            text: String::new(),
+            max_len: 32,
+            masked: false,
+            layer: 0,
+            row: 0,
+            column: 0,
+        }
+    }
+
+    /// Starts with `text` in the field
+    pub fn with_text(mut self, text: &str) -> Self {
+        self.text = text.chars().take(self.max_len).collect();
+        self
+    }
+
+    /// Takes up to `max_len` characters, 63 for a WPA2 passphrase
+    pub fn with_max_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len;
+        self
+    }
+
+    /// Shows all but the last character typed as `*`
+    pub fn masked(mut self) -> Self {
+        self.masked = true;
+        self
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Moves the cursor or types on the front buttons
+    pub fn handle(&mut self, event: Event) -> Outcome {
+        // again for each event, the host clears it when another app comes up
+        input::set_repeat(REPEAT);
+        match event {
+            Event::Press(Button::A) => self.step(false),
+            Event::Press(Button::D) => self.step(true),
+            Event::Press(Button::B) => self.move_row(true),
+            Event::LongPress(Button::B) => self.move_row(false),
+            Event::Press(Button::C) => return self.type_key(),
+            _ => return Outcome::Editing(Flow::Idle),
+        }
+        Outcome::Editing(Flow::Redraw)
+    }
+
+    fn row_len(row: usize) -> usize {
+        if row < CHARACTER_ROWS {
+            COLUMNS
+        } else {
+            CONTROLS.len()
+        }
+    }
+
+    fn step(&mut self, forward: bool) {
+        let len = Self::row_len(self.row);
+        self.column = if forward {
+            (self.column + 1) % len
+        } else {
+            (self.column + len - 1) % len
+        };
+    }
+
+    /// Keeps the cursor about as far across as it was
+    fn move_row(&mut self, down: bool) {
+        let from = Self::row_len(self.row);
+        self.row = if down {
+            (self.row + 1) % ROWS
+        } else {
+            (self.row + ROWS - 1) % ROWS
+        };
+        let to = Self::row_len(self.row);
+        self.column = (self.column * 2 + 1) * to / (from * 2);
+    }
+
+    fn type_key(&mut self) -> Outcome {
+        if let Some(row) = LAYERS[self.layer].get(self.row) {
+            if let Some(c) = row.chars().nth(self.column) {
+                self.push(c);
+            }
+            return Outcome::Editing(Flow::Redraw);
+        }
+        match CONTROLS[self.column] {
+            Control::Layer => self.layer = (self.layer + 1) % LAYERS.len(),
+            Control::Space => self.push(' '),
+            Control::Delete => {
+                self.text.pop();
+            }
+            Control::Cancel => return self.finish(Outcome::Cancelled),
+            Control::Ok => {
+                let text = core::mem::take(&mut self.text);
+                return self.finish(Outcome::Done(text));
+            }
+        }
+        Outcome::Editing(Flow::Redraw)
+    }
+
+    fn push(&mut self, c: char) {
+        if self.text.chars().count() < self.max_len {
+            self.text.push(c);
+        }
+    }
+
+    fn finish(&mut self, outcome: Outcome) -> Outcome {
+        input::set_repeat(ButtonSet::EMPTY);
+        outcome
+    }
+
+    /// Draws the whole screen; the frame should be cleared to white
+    pub fn render(&self, frame: &mut Frame) {
+        Text::with_baseline(
+            &self.title,
+            Point::new(4, 1),
+            MonoTextStyle::new(&FIXED_7X14_BOLD, Gray2::BLACK),
+            Baseline::Top,
+        )
+        .draw(frame)
+        .ok();
+
+        Rectangle::new(Point::new(2, ENTRY_TOP), Size::new(WIDTH - 4, ENTRY_HEIGHT))
+            .into_styled(PrimitiveStyle::with_stroke(Gray2::BLACK, 1))
+            .draw(frame)
+            .ok();
+        Text::with_baseline(
+            &self.shown_text(),
+            Point::new(6, ENTRY_TOP + 2),
+            MonoTextStyle::new(&FONT_7X14, Gray2::BLACK),
+            Baseline::Top,
+        )
+        .draw(frame)
+        .ok();
+
+        for (row, chars) in LAYERS[self.layer].iter().enumerate() {
+            for (column, c) in chars.chars().enumerate() {
+                let mut buf = [0u8; 4];
+                self.draw_key(frame, row, column, COLUMNS, c.encode_utf8(&mut buf));
+            }
+        }
+        for (column, &control) in CONTROLS.iter().enumerate() {
+            let label = self.control_label(control);
+            self.draw_key(frame, CHARACTER_ROWS, column, CONTROLS.len(), label);
+        }
+        draw_button_hints(
+            frame,
+            [Some("<"), Some(tr!("row")), Some(tr!("type")), Some(">")],
+        );
+    }
+
+    /// The text with masking, the start cut off if it is too long, and a
+    /// cursor after it
+    fn shown_text(&self) -> String {
+        let count = self.text.chars().count();
+        let mut shown = String::new();
+        // room for the cursor, and for the `<` when cut
+        let skip = match count + 1 {
+            len if len > VISIBLE_CHARS => len + 1 - VISIBLE_CHARS,
+            _ => 0,
+        };
+        if skip > 0 {
+            shown.push('<');
+        }
+        for (i, c) in self.text.chars().enumerate().skip(skip) {
+            shown.push(if self.masked && i + 1 < count { '*' } else { c });
+        }
+        shown.push('_');
+        shown
+    }
+
+    fn control_label(&self, control: Control) -> &'static str {
+        match control {
+            Control::Layer => LAYER_NAMES[self.layer],
+            Control::Space => tr!("space"),
+            Control::Delete => tr!("delete"),
+            Control::Cancel => tr!("cancel"),
+            Control::Ok => "OK",
+        }
+    }
+
+    /// Draws one key of a row `len` keys wide, dark when the cursor is on it
+    fn draw_key(&self, frame: &mut Frame, row: usize, column: usize, len: usize, label: &str) {
+        let width = WIDTH as i32 / len as i32;
+        let left = (WIDTH as i32 - width * len as i32) / 2 + width * column as i32;
+        let top = GRID_TOP + ROW_HEIGHT * row as i32;
+        let selected = row == self.row && column == self.column;
+        let (background, foreground) = if selected {
+            (Gray2::BLACK, Gray2::WHITE)
+        } else {
+            (Gray2::WHITE, Gray2::BLACK)
+        };
+        if selected {
+            Rectangle::new(
+                Point::new(left + 1, top),
+                Size::new(width as u32 - 2, ROW_HEIGHT as u32 - 1),
+            )
+            .into_styled(PrimitiveStyle::with_fill(background))
+            .draw(frame)
+            .ok();
+        }
+        Text::with_text_style(
+            label,
+            Point::new(left + width / 2, top + ROW_HEIGHT / 2),
+            MonoTextStyle::new(&FIXED_6X10, foreground),
+            TextStyleBuilder::new()
+                .alignment(Alignment::Center)
+                .baseline(Baseline::Middle)
+                .build(),
+        )
+        .draw(frame)
+        .ok();
+    }
+}
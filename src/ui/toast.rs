@@ -0,0 +1,94 @@
+//! A short message that shows with the next refresh and is gone by the one
+//! after, for things like "Saved" that need no answer.
+//!
+//! [show] redraws right away; the toast is then dropped without a refresh of
+//! its own, so it stays up until something else changes the screen.
+
+use alloc::string::String;
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+use embedded_graphics::{
+    mono_font::MonoTextStyle,
+    pixelcolor::Gray2,
+    prelude::*,
+    primitives::{PrimitiveStyle, Rectangle},
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
+};
+
+use crate::{
+    compositor::{Depth, Layer},
+    display::{Frame, HEIGHT, WIDTH},
+    fonts::FIXED_6X10,
+    ui::button_bar::BUTTON_BAR_HEIGHT,
+};
+
+/// White space around the text
+const PADDING: u32 = 4;
+
+#[derive(Clone, PartialEq, Eq)]
+enum State {
+    Hidden,
+    /// Waiting for a refresh
+    Pending(String),
+    /// On the panel until the next refresh
+    Shown,
+}
+
+static TOAST: Mutex<RefCell<State>> = Mutex::new(RefCell::new(State::Hidden));
+
+/// Shows `text` above the button hints for one refresh
+pub fn show(text: &str) {
+    critical_section::with(|cs| *TOAST.borrow_ref_mut(cs) = State::Pending(String::from(text)));
+}
+
+/// Draws a [show]n toast, light on dark
+pub struct ToastLayer;
+
+impl Layer for ToastLayer {
+    fn depth(&self) -> Depth {
+        Depth::Modal
+    }
+
+    fn take_dirty(&mut self) -> bool {
+        critical_section::with(|cs| matches!(*TOAST.borrow_ref(cs), State::Pending(_)))
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let text = critical_section::with(|cs| {
+            let mut toast = TOAST.borrow_ref_mut(cs);
+            match core::mem::replace(&mut *toast, State::Hidden) {
+                State::Pending(text) => {
+                    *toast = State::Shown;
+                    Some(text)
+                }
+                State::Shown | State::Hidden => None,
+            }
+        });
+        let Some(text) = text else {
+            return;
+        };
+
+        let character_style = MonoTextStyle::new(&FIXED_6X10, Gray2::WHITE);
+        let width = (text.len() as u32 * character_style.font.character_size.width)
+            .min(WIDTH - 2 * PADDING);
+        let height = character_style.font.character_size.height;
+        let area = Rectangle::new(
+            Point::new(
+                (WIDTH - width) as i32 / 2 - PADDING as i32,
+                (HEIGHT - BUTTON_BAR_HEIGHT - height) as i32 - 2 * PADDING as i32,
+            ),
+            Size::new(width + 2 * PADDING, height + 2 * PADDING),
+        );
+        area.into_styled(PrimitiveStyle::with_fill(Gray2::BLACK))
+            .draw(frame)
+            .ok();
+        let text_style = TextStyleBuilder::new()
+            .alignment(Alignment::Center)
+            .baseline(Baseline::Middle)
+            .build();
+        Text::with_text_style(&text, area.center(), character_style, text_style)
+            .draw(frame)
+            .ok();
+    }
+}
@@ -0,0 +1,258 @@
+//! URLs: parsing `scheme://host[:port][/path][?query]` and building them with
+//! percent-encoded path segments and query parameters.
+//!
+//! ```ignore
+//! let url = url::Builder::new("http://api.example.com/v1")
+//!     .segment("forecast")
+//!     .query("q", "Ann Arbor, MI")
+//!     .query("units", "metric")
+//!     .build();
+//! // http://api.example.com/v1/forecast?q=Ann%20Arbor%2C%20MI&units=metric
+//! let response = http::get(stack, &url)?;
+//! ```
+//!
+//! Only the unreserved characters (letters, digits, `-._~`) are left as they
+//! are; everything else is encoded as UTF-8 bytes.
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt;
+
+use log::warn;
+
+use crate::Error;
+
+/// A borrowed, parsed URL
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Url<'u> {
+    pub scheme: &'u str,
+    pub host: &'u str,
+    /// The one given, or the scheme's default
+    pub port: u16,
+    /// Always starts with `/`, still encoded
+    pub path: &'u str,
+    /// Without the `?`, still encoded
+    pub query: Option<&'u str>,
+}
+
+impl<'u> Url<'u> {
+    /// Parses a URL; the port may only be left out for `http`, `https` and
+    /// `mqtt`
+    pub fn parse(url: &'u str) -> Result<Self, Error> {
+        Self::parse_inner(url, default_port)
+    }
+
+    /// Parses a URL that must be of `scheme`, using `default_port` when it
+    /// has none
+    pub fn parse_with_scheme(url: &'u str, scheme: &str, default_port: u16) -> Result<Self, Error> {
+        match Self::parse_inner(url, |_| Some(default_port)) {
+            Ok(parsed) if parsed.scheme == scheme => Ok(parsed),
+            _ => {
+                warn!("only {}:// URLs are supported: {}", scheme, url);
+                Err(Error::InvalidUrl)
+            }
+        }
+    }
+
+    fn parse_inner(
+        url: &'u str,
+        default_port: impl Fn(&str) -> Option<u16>,
+    ) -> Result<Self, Error> {
+        let (scheme, rest) = url.split_once("://").ok_or(Error::InvalidUrl)?;
+        let (rest, query) = match rest.split_once('?') {
+            Some((rest, query)) => (rest, Some(query)),
+            None => (rest, None),
+        };
+        let (authority, path) = match rest.find('/') {
+            Some(i) => rest.split_at(i),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().map_err(|_| Error::InvalidUrl)?),
+            None => (authority, default_port(scheme).ok_or(Error::InvalidUrl)?),
+        };
+        if scheme.is_empty() || host.is_empty() {
+            return Err(Error::InvalidUrl);
+        }
+        Ok(Self {
+            scheme,
+            host,
+            port,
+            path,
+            query,
+        })
+    }
+
+    /// Path and query as sent in an HTTP request line
+    pub fn target(&self) -> String {
+        match self.query {
+            Some(query) => format!("{}?{}", self.path, query),
+            None => String::from(self.path),
+        }
+    }
+
+    /// `reference`, such as a redirect's `Location`, resolved against this
+    /// URL. Dot segments are left as they are.
+    pub fn join(&self, reference: &str) -> String {
+        let absolute = reference.split_once("://").is_some_and(|(scheme, _)| {
+            !scheme.is_empty()
+                && scheme
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b"+-.".contains(&b))
+        });
+        if absolute {
+            return String::from(reference);
+        }
+        if let Some(rest) = reference.strip_prefix("//") {
+            return format!("{}://{}", self.scheme, rest);
+        }
+        let mut origin = Self {
+            path: "",
+            query: None,
+            ..*self
+        }
+        .to_string();
+        if reference.starts_with('/') {
+            origin += reference;
+        } else if reference.starts_with('?') {
+            origin += self.path;
+            origin += reference;
+        } else {
+            // relative to the last directory of the path
+            origin += &self.path[..self.path.rfind('/').map_or(0, |i| i + 1)];
+            if !origin.ends_with('/') {
+                origin.push('/');
+            }
+            origin += reference;
+        }
+        origin
+    }
+
+    /// The decoded value of the first query parameter called `name`
+    pub fn query_param(&self, name: &str) -> Option<String> {
+        query_pairs(self.query.unwrap_or(""))
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value)
+    }
+}
+
+impl fmt::Display for Url<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}://{}", self.scheme, self.host)?;
+        if default_port(self.scheme) != Some(self.port) {
+            write!(f, ":{}", self.port)?;
+        }
+        f.write_str(self.path)?;
+        if let Some(query) = self.query {
+            write!(f, "?{}", query)?;
+        }
+        Ok(())
+    }
+}
+
+fn default_port(scheme: &str) -> Option<u16> {
+    match scheme {
+        "http" => Some(80),
+        "https" => Some(443),
+        "mqtt" => Some(crate::mqtt::DEFAULT_PORT),
+        _ => None,
+    }
+}
+
+/// Builds a URL onto a base, encoding each piece added
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Builder {
+    url: String,
+    has_query: bool,
+}
+
+impl Builder {
+    /// Starts from `base`, used as given; it may already have a query
+    pub fn new(base: &str) -> Self {
+        Self {
+            url: String::from(base),
+            has_query: base.contains('?'),
+        }
+    }
+
+    /// Appends `/` and `segment`; a `/` inside `segment` is encoded too
+    pub fn segment(mut self, segment: &str) -> Self {
+        debug_assert!(!self.has_query, "path segment after the query");
+        if !self.url.ends_with('/') {
+            self.url.push('/');
+        }
+        encode_into(&mut self.url, segment);
+        self
+    }
+
+    /// Appends a `name=value` query parameter
+    pub fn query(mut self, name: &str, value: &str) -> Self {
+        self.url.push(if self.has_query { '&' } else { '?' });
+        self.has_query = true;
+        encode_into(&mut self.url, name);
+        self.url.push('=');
+        encode_into(&mut self.url, value);
+        self
+    }
+
+    pub fn build(self) -> String {
+        self.url
+    }
+}
+
+/// Percent-encodes everything but the unreserved characters
+pub fn encode(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    encode_into(&mut out, s);
+    out
+}
+
+fn encode_into(out: &mut String, s: &str) {
+    const HEX: &[u8; 16] = b"0123456789ABCDEF";
+    for &b in s.as_bytes() {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~') {
+            out.push(b as char);
+        } else {
+            out.push('%');
+            out.push(HEX[(b >> 4) as usize] as char);
+            out.push(HEX[(b & 0x0f) as usize] as char);
+        }
+    }
+}
+
+/// Undoes percent-encoding; `None` for a broken escape or bytes that aren't
+/// UTF-8
+pub fn decode(s: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut rest = s.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        if b == b'%' {
+            let hex = tail
+                .get(..2)
+                .filter(|h| h.iter().all(u8::is_ascii_hexdigit))?;
+            let hex = core::str::from_utf8(hex).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(b);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// The decoded `name=value` pairs of a query or form body; `+` counts as a
+/// space and pairs that don't decode are skipped
+pub fn query_pairs(query: &str) -> impl Iterator<Item = (String, String)> + '_ {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .filter_map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let decode_form = |s: &str| decode(&s.replace('+', " "));
+            Some((decode_form(name)?, decode_form(value)?))
+        })
+}
@@ -0,0 +1,427 @@
+//! The WiFi station: joining a network and scanning.
+//!
+//! A join goes straight to connecting, which finds the access point itself.
+//! Only when it fails is there a scan, to log whether the network is in
+//! range at all.
+//!
+//! With `fast-boot` the access point and channel of the last join are kept in
+//! RTC memory, and a join after waking goes straight to them instead of
+//! scanning every channel. The first join after power-on scans once to find
+//! them; one that fails falls back to the full search.
+//!
+//! Between transfers the modem sleeps as [PowerSave] says, waking for
+//! beacons to hear whether the access point holds anything for it. Work that
+//! talks to the network takes a [Transfer] to keep it awake, so requests the
+//! badge makes don't pay for it. What does:
+//!
+//! | mode                 | between transfers   | unsolicited packets wait |
+//! |----------------------|---------------------|--------------------------|
+//! | [PowerSave::Off]     | ~80 mA, listening   | not at all               |
+//! | [PowerSave::Managed] | ~30 mA, every DTIM  | up to a beacon, ~100 ms  |
+//! | [PowerSave::Max]     | ~15 mA, every 3rd   | up to ~300 ms            |
+//!
+//! Unsolicited packets are the HTTP server's and the remote display's
+//! connections, and pings. The currents are ballpark figures for the whole
+//! board from Espressif's power-saving notes with a DTIM of one, as home
+//! access points use; a longer DTIM saves more and delays more. Turning
+//! the modem's sleep on or off takes a few milliseconds each way.
+
+use alloc::{string::String, vec::Vec};
+use core::{
+    cell::{Cell, RefCell},
+    str::FromStr,
+};
+
+use critical_section::Mutex;
+use esp_hal::time::{Duration, Instant};
+use esp_radio::wifi::{
+    AccessPointInfo, ClientConfig, ModeConfig, PowerSaveMode, ScanConfig, WifiController,
+};
+use log::{debug, info, warn};
+
+use crate::{
+    config::{keys, ConfigStore},
+    net::NetStack,
+    power::estimator::{self, State},
+    Error,
+};
+
+/// How long joining may take, association and DHCP together
+pub const JOIN_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_SCAN_RESULTS: usize = 16;
+
+static CONTROLLER: Mutex<RefCell<Option<WifiController<'static>>>> = Mutex::new(RefCell::new(None));
+/// The network last joined or meant to be, for [reconnect]
+static JOINED: Mutex<RefCell<Option<Credentials>>> = Mutex::new(RefCell::new(None));
+static MODEM: Mutex<Cell<Modem>> = Mutex::new(Cell::new(Modem {
+    mode: PowerSave::Off,
+    transfers: 0,
+    applied: PowerSave::Off,
+}));
+
+/// The network to join
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Credentials {
+    pub ssid: String,
+    pub password: String,
+}
+
+impl Credentials {
+    /// Credentials saved with [Credentials::store], if any
+    pub fn load(config: &ConfigStore) -> Option<Self> {
+        Some(Self {
+            ssid: config
+                .get(keys::WIFI_SSID)
+                .filter(|s| !s.is_empty())?
+                .into(),
+            password: config.get(keys::WIFI_PASSWORD).unwrap_or_default().into(),
+        })
+    }
+
+    /// Copies the credentials into `config`; call [ConfigStore::commit] to persist them
+    pub fn store(&self, config: &mut ConfigStore) -> Result<(), Error> {
+        config.set(keys::WIFI_SSID, &self.ssid)?;
+        config.set(keys::WIFI_PASSWORD, &self.password)
+    }
+}
+
+/// Hands the controller to this module. Call once at boot. The modem stays
+/// awake until [set_power_save].
+pub fn init(mut controller: WifiController<'static>) {
+    if let Err(err) = controller.set_power_saving(PowerSaveMode::None) {
+        warn!("WiFi power save unavailable: {:?}", err);
+    }
+    critical_section::with(|cs| CONTROLLER.borrow_ref_mut(cs).replace(controller));
+}
+
+/// Joins `credentials` and waits for a DHCP lease, dropping any current network
+pub fn join(credentials: &Credentials, net: &NetStack<'_>) -> Result<(), Error> {
+    begin_join(credentials)?;
+    finish_join(credentials, net)
+}
+
+/// Starts joining `credentials`, dropping any current network. The radio
+/// associates in the background until [finish_join].
+pub fn begin_join(credentials: &Credentials) -> Result<(), Error> {
+    info!("Joining {}", credentials.ssid);
+    let client = ClientConfig::default()
+        .with_ssid(credentials.ssid.clone())
+        .with_password(credentials.password.clone());
+    #[cfg(feature = "fast-boot")]
+    let client = match last_ap::get(&credentials.ssid).or_else(|| last_ap::find(&credentials.ssid))
+    {
+        // only that channel is scanned, and only for that access point
+        Some(ap) => client.with_bssid(ap.bssid).with_channel(ap.channel),
+        None => client,
+    };
+    let config = ModeConfig::Client(client);
+    with(|controller| {
+        if controller.is_connected().unwrap_or(false) {
+            controller.disconnect().ok();
+        }
+        controller.set_config(&config)?;
+        if !controller.is_started()? {
+            controller.start()?;
+        }
+        controller.connect()
+    })?
+    .map_err(|err| {
+        warn!("WiFi connect failed: {:?}", err);
+        Error::Network
+    })
+}
+
+/// Waits for the join [begin_join] started to get a DHCP lease
+pub fn finish_join(credentials: &Credentials, net: &NetStack<'_>) -> Result<(), Error> {
+    #[cfg(feature = "fast-boot")]
+    if last_ap::get(&credentials.ssid).is_some() {
+        if wait_joined(credentials, net, last_ap::JOIN_TIMEOUT).is_ok() {
+            return Ok(());
+        }
+        // the access point moved or went away, look again
+        warn!("Cached access point didn't answer, scanning");
+        last_ap::forget();
+        begin_join(credentials)?;
+    }
+    wait_joined(credentials, net, JOIN_TIMEOUT).inspect_err(|_| diagnose(&credentials.ssid))
+}
+
+/// Logs whether `ssid` can be seen at all, after joining it failed. This is
+/// the only scan a plain join does; connecting finds the access point itself.
+fn diagnose(ssid: &str) {
+    match scan_for(ssid) {
+        Some(ap) => warn!(
+            "{} is in range on channel {} at {} dBm, check the password",
+            ssid, ap.channel, ap.signal_strength
+        ),
+        None => warn!("{} is not in range, or hidden", ssid),
+    }
+}
+
+fn wait_joined(
+    credentials: &Credentials,
+    net: &NetStack<'_>,
+    timeout: Duration,
+) -> Result<(), Error> {
+    let deadline = Instant::now() + timeout;
+    while !(is_connected() && net.is_iface_up()) {
+        if Instant::now() > deadline {
+            warn!("No connection to {} after {:?}", credentials.ssid, timeout);
+            with(|controller| controller.disconnect().ok()).ok();
+            return Err(Error::Timeout);
+        }
+        net.work();
+    }
+    info!("Joined {}, ip {:?}", credentials.ssid, net.get_ip_info());
+    critical_section::with(|cs| JOINED.borrow_ref_mut(cs).replace(credentials.clone()));
+    Ok(())
+}
+
+/// Starts over from scratch: stops the radio, resets the network interface
+/// including its DHCP lease, and joins the last network again
+pub fn reconnect(net: &NetStack<'_>) -> Result<(), Error> {
+    let credentials =
+        critical_section::with(|cs| JOINED.borrow_ref(cs).clone()).ok_or(Error::NotFound)?;
+    info!("Restarting WiFi");
+    with(|controller| {
+        controller.disconnect().ok();
+        controller.stop().ok();
+    })?;
+    net.reset();
+    join(&credentials, net)
+}
+
+/// Has [reconnect] join `credentials`, which didn't work at boot
+pub fn retry_later(credentials: &Credentials) {
+    critical_section::with(|cs| JOINED.borrow_ref_mut(cs).replace(credentials.clone()));
+}
+
+/// Whether the station is associated with an access point
+pub fn is_connected() -> bool {
+    with(|controller| controller.is_connected().unwrap_or(false)).unwrap_or(false)
+}
+
+/// Signal strength of the current network in dBm
+pub fn rssi() -> Option<i32> {
+    with(|controller| controller.rssi().ok()).ok().flatten()
+}
+
+/// How the modem sleeps between transfers, see the [module docs](self)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PowerSave {
+    /// Never, the radio listens all the time
+    Off,
+    /// Wakes for every DTIM beacon
+    #[default]
+    Managed,
+    /// Wakes for every third beacon
+    Max,
+}
+
+impl PowerSave {
+    fn driver_mode(self) -> PowerSaveMode {
+        match self {
+            PowerSave::Off => PowerSaveMode::None,
+            PowerSave::Managed => PowerSaveMode::Minimum,
+            PowerSave::Max => PowerSaveMode::Maximum,
+        }
+    }
+}
+
+/// `off`, `managed` or `max`
+impl FromStr for PowerSave {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "off" => Ok(PowerSave::Off),
+            "managed" => Ok(PowerSave::Managed),
+            "max" => Ok(PowerSave::Max),
+            _ => Err(Error::InvalidConfig),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Modem {
+    /// What the modem does between transfers
+    mode: PowerSave,
+    transfers: u8,
+    /// What the driver was last told
+    applied: PowerSave,
+}
+
+/// Sets how the modem sleeps between transfers
+pub fn set_power_save(mode: PowerSave) {
+    update_modem(|modem| modem.mode = mode);
+}
+
+/// Keeps the modem awake until dropped
+#[must_use = "the modem may sleep again when this is dropped"]
+pub struct Transfer(());
+
+/// Takes a [Transfer] for work that talks to the network
+pub fn transfer() -> Transfer {
+    update_modem(|modem| modem.transfers += 1);
+    Transfer(())
+}
+
+impl Drop for Transfer {
+    fn drop(&mut self) {
+        update_modem(|modem| modem.transfers -= 1);
+    }
+}
+
+fn update_modem(f: impl FnOnce(&mut Modem)) {
+    let change = critical_section::with(|cs| {
+        let cell = MODEM.borrow(cs);
+        let mut modem = cell.get();
+        f(&mut modem);
+        let wanted = if modem.transfers > 0 {
+            PowerSave::Off
+        } else {
+            modem.mode
+        };
+        let previous = modem.applied;
+        modem.applied = wanted;
+        cell.set(modem);
+        (wanted != previous).then_some(wanted)
+    });
+    let Some(wanted) = change else {
+        return;
+    };
+    match with(|controller| controller.set_power_saving(wanted.driver_mode())) {
+        Ok(Ok(())) => debug!("WiFi power save {:?}", wanted),
+        Ok(Err(err)) => warn!("WiFi power save {:?} failed: {:?}", wanted, err),
+        // before init, or taken by a join or scan that is awake anyway
+        Err(_) => {}
+    }
+    // the refresh state is left to whoever entered it
+    if matches!(estimator::current(), Some(State::Wifi | State::ModemSleep)) {
+        estimator::enter(if wanted == PowerSave::Off {
+            State::Wifi
+        } else {
+            State::ModemSleep
+        });
+    }
+}
+
+/// The strongest access point of `ssid` in range
+fn scan_for(ssid: &str) -> Option<AccessPointInfo> {
+    with(|controller| {
+        if !controller.is_started()? {
+            controller.start()?;
+        }
+        controller.scan_with_config(ScanConfig::default().with_ssid(ssid))
+    })
+    .ok()?
+    .inspect_err(|err| warn!("WiFi scan failed: {:?}", err))
+    .ok()?
+    .into_iter()
+    .filter(|ap| ap.ssid == ssid)
+    .max_by_key(|ap| ap.signal_strength)
+}
+
+/// Networks in range, strongest first
+pub fn scan() -> Result<Vec<AccessPointInfo>, Error> {
+    let mut networks = with(|controller| {
+        if !controller.is_started()? {
+            controller.start()?;
+        }
+        controller.scan_with_config(ScanConfig::default().with_max(MAX_SCAN_RESULTS))
+    })?
+    .map_err(|err| {
+        warn!("WiFi scan failed: {:?}", err);
+        Error::Network
+    })?;
+    networks.sort_unstable_by_key(|ap| core::cmp::Reverse(ap.signal_strength));
+    Ok(networks)
+}
+
+/// Runs `f` with the controller taken out of the mutex, so blocking driver
+/// calls don't run inside a critical section
+fn with<R>(f: impl FnOnce(&mut WifiController<'static>) -> R) -> Result<R, Error> {
+    let mut controller =
+        critical_section::with(|cs| CONTROLLER.borrow_ref_mut(cs).take()).ok_or(Error::Network)?;
+    let result = f(&mut controller);
+    critical_section::with(|cs| CONTROLLER.borrow_ref_mut(cs).replace(controller));
+    Ok(result)
+}
+
+/// The access point last joined and its channel, kept in RTC memory across
+/// deep sleep so a `fast-boot` wake skips the scan over every channel
+#[cfg(feature = "fast-boot")]
+mod last_ap {
+    use esp_hal::{ram, time::Duration};
+    use log::info;
+
+    use super::scan_for;
+    use crate::crc::crc32;
+
+    /// Joining a known access point on a known channel takes well under this
+    pub const JOIN_TIMEOUT: Duration = Duration::from_secs(8);
+    const MAGIC: u32 = u32::from_le_bytes(*b"MTAP");
+
+    #[derive(Clone, Copy)]
+    pub struct AccessPoint {
+        magic: u32,
+        ssid_crc: u32,
+        pub bssid: [u8; 6],
+        pub channel: u8,
+        checksum: u32,
+    }
+
+    impl AccessPoint {
+        const EMPTY: AccessPoint = AccessPoint {
+            magic: 0,
+            ssid_crc: 0,
+            bssid: [0; 6],
+            channel: 0,
+            checksum: 0,
+        };
+
+        fn compute_checksum(&self) -> u32 {
+            let mut bytes = [0u8; 15];
+            bytes[..4].copy_from_slice(&self.magic.to_le_bytes());
+            bytes[4..8].copy_from_slice(&self.ssid_crc.to_le_bytes());
+            bytes[8..14].copy_from_slice(&self.bssid);
+            bytes[14] = self.channel;
+            crc32(&bytes)
+        }
+    }
+
+    #[ram(unstable(rtc_fast, persistent))]
+    static mut LAST_AP: AccessPoint = AccessPoint::EMPTY;
+
+    /// The cached access point, if it was for `ssid`
+    pub fn get(ssid: &str) -> Option<AccessPoint> {
+        // SAFETY: single core, and only touched inside a critical section
+        let ap = critical_section::with(|_| unsafe { LAST_AP });
+        (ap.magic == MAGIC
+            && ap.checksum == ap.compute_checksum()
+            && ap.ssid_crc == crc32(ssid.as_bytes()))
+        .then_some(ap)
+    }
+
+    /// Scans for the strongest access point of `ssid` and caches it
+    pub fn find(ssid: &str) -> Option<AccessPoint> {
+        let found = scan_for(ssid)?;
+        let mut ap = AccessPoint {
+            magic: MAGIC,
+            ssid_crc: crc32(ssid.as_bytes()),
+            bssid: found.bssid,
+            channel: found.channel,
+            checksum: 0,
+        };
+        ap.checksum = ap.compute_checksum();
+        info!("Caching {} on channel {}", ssid, ap.channel);
+        // SAFETY: as in `get`
+        critical_section::with(|_| unsafe { LAST_AP = ap });
+        Some(ap)
+    }
+
+    pub fn forget() {
+        // SAFETY: as in `get`
+        critical_section::with(|_| unsafe { LAST_AP = AccessPoint::EMPTY });
+    }
+}
@@ -0,0 +1,36 @@
+#!/bin/sh
+# Builds a merged factory image (bootloader, partition table and app at
+# offset 0) plus an ESP Web Tools manifest in target/factory/.
+#
+#     tools/build_factory.sh
+#
+# Serve that directory over HTTPS and point an <esp-web-install-button> at
+# manifest.json; the web flasher sets up WiFi over Improv once the badge
+# restarts. Needs espflash on PATH.
+set -eu
+
+cd "$(dirname "$0")/.."
+name=magtag_esp_hal_epd
+version=$(sed -n 's/^version = "\(.*\)"/\1/p' Cargo.toml | head -n 1)
+out=target/factory
+
+cargo build --release --features factory
+mkdir -p "$out"
+espflash save-image --chip esp32s2 --merge --partition-table partitions.csv \
+    "target/xtensa-esp32s2-none-elf/release/$name" "$out/magtag-factory.bin"
+
+cat > "$out/manifest.json" <<JSON
+{
+  "name": "MagTag",
+  "version": "$version",
+  "new_install_prompt_erase": true,
+  "builds": [
+    {
+      "chipFamily": "ESP32-S2",
+      "improv": true,
+      "parts": [{ "path": "magtag-factory.bin", "offset": 0 }]
+    }
+  ]
+}
+JSON
+echo "$out/magtag-factory.bin and $out/manifest.json ready"
@@ -0,0 +1,48 @@
+#!/usr/bin/env python3
+"""Sign a fleet config document for badges with that `fleet_key`.
+
+    fleet_sign.py front-desk.cfg [--key KEY] [-o OUT]
+
+Appends the `sig` line, replacing one that is there already, and writes the
+result to OUT, or back to the input file. MAGTAG_FLEET_KEY works instead
+of --key. See src/fleet.rs for the format.
+"""
+
+import argparse
+import hashlib
+import hmac
+import os
+import sys
+
+
+def sign(text, key):
+    lines = text.rstrip("\n").split("\n")
+    if lines and lines[-1].startswith("sig "):
+        lines.pop()
+    signed = "\n".join(lines) + "\n"
+    if not any(line.startswith("version ") for line in lines):
+        raise ValueError("no version line")
+    mac = hmac.new(key.encode(), signed.encode(), hashlib.sha256).hexdigest()
+    return f"{signed}sig {mac}\n"
+
+
+def main():
+    parser = argparse.ArgumentParser(description=__doc__.splitlines()[0])
+    parser.add_argument("document")
+    parser.add_argument("--key", default=os.environ.get("MAGTAG_FLEET_KEY"))
+    parser.add_argument("-o", "--output")
+    args = parser.parse_args()
+    if not args.key:
+        sys.exit("give --key or set MAGTAG_FLEET_KEY")
+    with open(args.document, encoding="utf-8") as f:
+        text = f.read()
+    try:
+        signed = sign(text, args.key)
+    except ValueError as err:
+        sys.exit(f"{args.document}: {err}")
+    with open(args.output or args.document, "w", encoding="utf-8", newline="\n") as f:
+        f.write(signed)
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,24 @@
+//! CalDAV REPORT responses: the XML elements, entities, unfolding and the
+//! VTODO properties read from them
+#![no_main]
+
+use jiff::tz::TimeZone;
+use libfuzzer_sys::fuzz_target;
+use magtag_fuzz::caldav_parser;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(xml) = core::str::from_utf8(data) else {
+        return;
+    };
+    for todo in caldav_parser::todos(xml, |utc| Some(TimeZone::UTC.to_datetime(utc))) {
+        assert!(todo.summary.len() <= xml.len());
+    }
+    for element in caldav_parser::elements(xml, "calendar-data") {
+        // no entity or CDATA section is shorter than what it stands for
+        assert!(caldav_parser::unescape(element).len() <= element.len());
+    }
+    let ics = caldav_parser::unfold(xml);
+    for (_, value) in caldav_parser::todo_properties(&ics) {
+        caldav_parser::parse_date(value, |_| None);
+    }
+});
@@ -0,0 +1,13 @@
+//! Chunked bodies after a valid head
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use magtag_fuzz::read_after;
+
+fuzz_target!(|data: &[u8]| {
+    read_after(
+        "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n",
+        data,
+    )
+    .ok();
+});
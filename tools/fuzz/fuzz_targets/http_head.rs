@@ -0,0 +1,10 @@
+//! Header lines after a valid status line, so inputs get past it
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use magtag_fuzz::read;
+
+fuzz_target!(|data: &[u8]| {
+    let raw = [b"HTTP/1.1 200 OK\r\n", data, b"\r\n\r\n"].concat();
+    read(&raw, &[]).ok();
+});
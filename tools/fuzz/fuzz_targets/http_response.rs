@@ -0,0 +1,18 @@
+//! Whole responses, read in pieces whose sizes come from the input
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use magtag_fuzz::{read, url::Url};
+
+fuzz_target!(|data: &[u8]| {
+    // the first bytes pick how the rest is split up
+    let (splits, raw) = data.split_at(data.len().min(4));
+    if let Ok(response) = read(raw, splits) {
+        let from = Url::parse("http://badge.local:8080/a/b?c=d").unwrap();
+        if let Some(to) = response.redirect(&from) {
+            Url::parse(&to).ok();
+        }
+        response.header("content-type");
+        response.is_success();
+    }
+});
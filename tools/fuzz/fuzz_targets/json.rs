@@ -0,0 +1,29 @@
+//! JSON from APIs and MQTT payloads: reading one field or counting an array
+//! at the paths the apps use, and at a path taken from the input
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use magtag_fuzz::json;
+
+const PATHS: &[&[&str]] = &[
+    &[],
+    &["state"],
+    &["attributes", "unit_of_measurement"],
+    &["workflow_runs", "0", "conclusion"],
+    &["0"],
+    &["2", "a"],
+];
+
+fuzz_target!(|data: &[u8]| {
+    // a path of `/` separated segments on the first line, then the JSON
+    let (path, json) = match data.iter().position(|&b| b == b'\n') {
+        Some(i) => (&data[..i], &data[i + 1..]),
+        None => (&b""[..], data),
+    };
+    let path = core::str::from_utf8(path).unwrap_or("");
+    let path: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    for path in PATHS.iter().copied().chain([&path[..]]) {
+        json::string_at(json, path);
+        json::array_len(json, path);
+    }
+});
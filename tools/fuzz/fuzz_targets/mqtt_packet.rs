@@ -0,0 +1,40 @@
+//! What a broker sends a subscriber, read in pieces whose sizes come from
+//! the input
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use magtag_fuzz::mqtt_packet::{self, MAX_PACKET_LEN};
+
+/// Feeds `raw` to `dispatch` `splits` bytes at a time, as `Subscriber::poll`
+/// does with what the socket has
+fn messages(raw: &[u8], splits: &[u8]) -> (Vec<(String, Vec<u8>)>, bool) {
+    let mut received = Vec::new();
+    let mut pending = Vec::new();
+    let mut splits = splits.iter().cycle();
+    let mut rest = raw;
+    while !rest.is_empty() {
+        let len = splits
+            .next()
+            .map_or(rest.len(), |&n| n as usize + 1)
+            .min(rest.len());
+        pending.extend_from_slice(&rest[..len]);
+        rest = &rest[len..];
+        let mut on_message = |topic: &str, payload: &[u8]| {
+            assert!(topic.len() + payload.len() <= MAX_PACKET_LEN);
+            received.push((topic.to_string(), payload.to_vec()));
+        };
+        if mqtt_packet::dispatch(&mut pending, &mut on_message).is_err() {
+            return (received, false);
+        }
+        // only the start of one packet is left waiting
+        assert!(pending.len() <= 5 + MAX_PACKET_LEN);
+    }
+    (received, true)
+}
+
+fuzz_target!(|data: &[u8]| {
+    // the first bytes pick how the rest is split up
+    let (splits, raw) = data.split_at(data.len().min(4));
+    let whole = messages(raw, &[]);
+    assert_eq!(messages(raw, splits), whole);
+});
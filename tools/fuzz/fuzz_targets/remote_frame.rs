@@ -0,0 +1,17 @@
+//! Frames pushed to the remote display, signed or not, raw or run-length
+//! encoded
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use magtag_fuzz::remote_frame::{self, Upload, FRAME_LEN};
+
+fuzz_target!(|data: &[u8]| {
+    for key in [None, Some(&b"remote key"[..])] {
+        if let Upload::Complete(frame) = remote_frame::parse_upload(data, key) {
+            assert_eq!(frame.len(), FRAME_LEN);
+        }
+    }
+    if let Some(frame) = remote_frame::decode_rle(data) {
+        assert_eq!(frame.len(), FRAME_LEN);
+    }
+});
@@ -0,0 +1,22 @@
+//! Screen templates: the TOML subset, then drawing what parsed
+#![no_main]
+
+use embedded_graphics::prelude::*;
+use libfuzzer_sys::fuzz_target;
+use magtag_fuzz::{
+    bindings::Subscriptions,
+    display::{Frame, HEIGHT, WIDTH},
+    ui::template,
+};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(template) = template::parse(data) else {
+        return;
+    };
+    let mut subscriptions = Subscriptions::new();
+    template.subscribe(&mut subscriptions);
+    let mut frame = Frame::new(Size::new(WIDTH, HEIGHT));
+    // with and without a value for each binding
+    template.render(&mut frame, |name| Some(name.repeat(3)));
+    template.render(&mut frame, |_| None);
+});
@@ -0,0 +1,22 @@
+//! URLs from config and redirects: parsing, joining and percent-decoding
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use magtag_fuzz::url::{self, Url};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(s) = core::str::from_utf8(data) else {
+        return;
+    };
+    // a URL and a reference to resolve against it, split at the first space
+    let (base, reference) = s.split_once(' ').unwrap_or((s, ""));
+    if let Ok(parsed) = Url::parse(base) {
+        parsed.target();
+        parsed.query_param("q");
+        parsed.to_string();
+        Url::parse(&parsed.join(reference)).ok();
+    }
+    Url::parse_with_scheme(base, "http", 80).ok();
+    url::decode(s);
+    url::query_pairs(s).count();
+});
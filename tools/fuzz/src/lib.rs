@@ -0,0 +1,116 @@
+//! The firmware's parsers for what servers, brokers and senders send built
+//! for the host, and the read loop the fuzz targets drive them with.
+//!
+//! A panic in any of these on the badge means a watchdog reset and a lost
+//! refresh, possibly on every wake if the server keeps sending the same
+//! thing, so the targets feed them arbitrary bytes and check the limits they
+//! promise.
+
+extern crate alloc;
+
+#[path = "../../../src/bindings.rs"]
+pub mod bindings;
+#[path = "../../../src/caldav_parser.rs"]
+pub mod caldav_parser;
+#[path = "../../../src/error.rs"]
+pub mod error;
+#[path = "../../../src/http_parser.rs"]
+pub mod http_parser;
+#[path = "../../../src/json.rs"]
+pub mod json;
+#[path = "../../../src/mqtt_packet.rs"]
+pub mod mqtt_packet;
+#[path = "../../../src/remote_frame.rs"]
+pub mod remote_frame;
+#[path = "../../../src/sha256.rs"]
+pub mod sha256;
+pub mod ui;
+#[path = "../../../src/url.rs"]
+pub mod url;
+
+pub use error::Error;
+use http_parser::{Buffer, Response, ResponseParser};
+
+/// What `url.rs` needs from the MQTT client
+pub mod mqtt {
+    pub const DEFAULT_PORT: u16 = 1883;
+}
+
+/// The panel's size and a framebuffer of the same shape, as in
+/// `tools/screenshots`
+pub mod display {
+    use embedded_graphics::pixelcolor::Gray2;
+    use embedded_graphics_simulator::SimulatorDisplay;
+
+    pub const WIDTH: u32 = 296;
+    pub const HEIGHT: u32 = 128;
+
+    pub type Frame = SimulatorDisplay<Gray2>;
+}
+
+/// Plain heap buffers, as without the `psram` feature
+pub mod psram {
+    use alloc::vec::Vec;
+
+    pub fn zeroed(len: usize) -> Vec<u8> {
+        alloc::vec![0u8; len]
+    }
+}
+
+/// Small enough that inputs reach it, unlike the firmware's 32 KiB
+pub const LIMIT: usize = 4096;
+
+/// Reads `raw` the way `http::request_once` reads a socket, in pieces as
+/// long as the bytes of `splits` say, until the parser has the whole
+/// response or `raw` runs out as a closed connection would. It is read into
+/// a fixed buffer as well, which has to give the same response.
+pub fn read(raw: &[u8], splits: &[u8]) -> Result<Response, Error> {
+    let response =
+        feed(ResponseParser::new(Vec::new(), LIMIT), raw, splits).and_then(ResponseParser::finish);
+    let mut buffer = [0u8; LIMIT];
+    let in_place = feed(ResponseParser::new(&mut buffer[..], LIMIT), raw, splits)
+        .and_then(ResponseParser::finish_in_place);
+    match (&response, in_place) {
+        (Ok(response), Ok(in_place)) => {
+            assert_eq!(response.status, in_place.status);
+            assert_eq!(response.body, in_place.body);
+            assert!(response
+                .headers
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_str()))
+                .eq(in_place.headers()));
+        }
+        (Err(err), Err(in_place)) => assert_eq!(*err, in_place),
+        (_, in_place) => panic!("fixed buffer disagrees: {:?}", in_place.map(|r| r.status)),
+    }
+    let response = response?;
+    assert!(response.body.len() <= LIMIT);
+    assert!(response.headers.len() <= http_parser::MAX_HEADERS);
+    Ok(response)
+}
+
+fn feed<B: Buffer>(
+    mut parser: ResponseParser<B>,
+    raw: &[u8],
+    splits: &[u8],
+) -> Result<ResponseParser<B>, Error> {
+    let mut rest = raw;
+    let mut splits = splits.iter().cycle();
+    while !rest.is_empty() {
+        let want = splits.next().map_or(rest.len(), |&n| n as usize + 1);
+        let spare = parser.spare(want);
+        let len = spare.len().min(rest.len());
+        spare[..len].copy_from_slice(&rest[..len]);
+        rest = &rest[len..];
+        if parser.commit(len)? {
+            break;
+        }
+    }
+    Ok(parser)
+}
+
+/// [read] with a head before `body`, in one piece
+pub fn read_after(head: &str, body: &[u8]) -> Result<Response, Error> {
+    let raw = [head.as_bytes(), body].concat();
+    read(&raw, &[])
+}
@@ -0,0 +1,4 @@
+#[path = "../../../../src/ui/icon.rs"]
+pub mod icon;
+#[path = "../../../../src/ui/template.rs"]
+pub mod template;
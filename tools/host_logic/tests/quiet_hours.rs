@@ -0,0 +1,57 @@
+//! Which of the host's tasks wait out `quiet_hours`, and until when.
+
+use jiff::civil::{date, DateTime};
+use magtag_host_logic::{
+    alarm::QuietHours,
+    host_tasks::{
+        deferred, ALERTS, AMBIENT_LIGHT, APP_TICK, BATTERY_CHECK, DATALOG, DATALOG_UPLOAD,
+        FACE_DOWN, HOME_ASSISTANT, NET_HEALTH, TELEMETRY, TSDB,
+    },
+    scheduler::TaskId,
+};
+
+fn night() -> QuietHours {
+    "23:00-06:00".parse().unwrap()
+}
+
+fn at(hour: i8, minute: i8) -> DateTime {
+    date(2026, 10, 14).at(hour, minute, 0, 0)
+}
+
+#[test]
+fn tsdb_writes_wait_for_the_end_of_the_quiet_hours() {
+    let morning = date(2026, 10, 15).at(6, 0, 0, 0);
+    assert_eq!(deferred(TSDB, &night(), at(23, 30)), Some(morning));
+    assert_eq!(deferred(TSDB, &night(), at(2, 15)), Some(at(6, 0)));
+}
+
+#[test]
+fn refreshes_and_uploads_wait_too() {
+    for task in [APP_TICK, TELEMETRY, HOME_ASSISTANT, DATALOG_UPLOAD] {
+        assert_eq!(deferred(task, &night(), at(2, 15)), Some(at(6, 0)), "{:?}", task);
+    }
+}
+
+#[test]
+fn local_work_and_alerts_keep_going() {
+    let tasks = [
+        AMBIENT_LIGHT,
+        BATTERY_CHECK,
+        NET_HEALTH,
+        ALERTS,
+        FACE_DOWN,
+        DATALOG,
+    ];
+    for task in tasks {
+        assert_eq!(deferred(task, &night(), at(2, 15)), None, "{:?}", task);
+    }
+}
+
+#[test]
+fn nothing_waits_outside_the_quiet_hours() {
+    for id in 0..=TSDB.0 {
+        let task = TaskId(id);
+        assert_eq!(deferred(task, &night(), at(6, 0)), None, "{:?}", task);
+        assert_eq!(deferred(task, &night(), at(22, 59)), None, "{:?}", task);
+    }
+}
@@ -0,0 +1,117 @@
+//! The firmware's HTTP response parser, request heads, redirect rules and
+//! URL handling built for the host, with a client over `std::net` that sends
+//! and reads requests the way `http::Request::send` does on the badge.
+//!
+//! [fetch_in_place] reads into a fixed buffer instead, without following
+//! redirects.
+//!
+//! The firmware's `multipart` form bodies are built here too, for
+//! `tests/multipart.rs` to check byte for byte, and its CalDAV REPORT
+//! parser for `tests/caldav.rs` to read the responses servers send.
+//!
+//! Only the socket calls differ from the firmware: [fetch] polls a blocking
+//! stream with a short read timeout where the firmware polls `read_ready`,
+//! and doesn't retry or hold back for the rate limit.
+
+extern crate alloc;
+
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::TcpStream,
+    time::{Duration, Instant},
+};
+
+#[path = "../../../src/caldav_parser.rs"]
+pub mod caldav_parser;
+#[path = "../../../src/error.rs"]
+pub mod error;
+#[path = "../../../src/http_parser.rs"]
+pub mod http_parser;
+#[path = "../../../src/http_request.rs"]
+pub mod http_request;
+#[path = "../../../src/multipart.rs"]
+pub mod multipart;
+#[path = "../../../src/url.rs"]
+pub mod url;
+
+pub use error::Error;
+use http_parser::{Buffer, Response, ResponseParser, ResponseRef};
+use http_request::Head;
+pub use http_request::MAX_REDIRECTS;
+use url::Url;
+
+/// What `url.rs` needs from the MQTT client
+pub mod mqtt {
+    pub const DEFAULT_PORT: u16 = 1883;
+}
+
+/// As in `http.rs`
+pub const MAX_RESPONSE_LEN: usize = 32 * 1024;
+const READ_CHUNK: usize = 1460;
+
+/// How long [fetch] waits for a whole response; the firmware's is 20 s,
+/// far too long for a test
+pub const TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Sends a GET for `url` with `headers` and follows redirects
+pub fn fetch(url: &str, headers: &[(&str, &str)]) -> Result<Response, Error> {
+    let head = Head {
+        headers,
+        ..Head::new("GET")
+    };
+    http_request::follow(head, url, |head, url| {
+        let parser = ResponseParser::new(Vec::new(), MAX_RESPONSE_LEN);
+        read(connect(head, url)?, parser)?.finish()
+    })
+}
+
+/// Sends one GET for `url` and reads the response into a fixed `buffer`,
+/// as `http::Request::send_with` does
+pub fn fetch_in_place<'b>(url: &str, buffer: &'b mut [u8]) -> Result<ResponseRef<'b>, Error> {
+    let url = Url::parse_with_scheme(url, "http", 80)?;
+    let head = Head::new("GET");
+    head.check(&url)?;
+    let parser = ResponseParser::new(buffer, MAX_RESPONSE_LEN);
+    read(connect(&head, &url)?, parser)?.finish_in_place()
+}
+
+/// Opens a connection to `url` and sends `head`
+fn connect(head: &Head<'_>, url: &Url<'_>) -> Result<TcpStream, Error> {
+    let mut request = String::new();
+    head.write(&mut request, url).ok();
+
+    let mut socket = TcpStream::connect((url.host, url.port)).map_err(|_| Error::Network)?;
+    socket
+        .set_read_timeout(Some(Duration::from_millis(20)))
+        .map_err(|_| Error::Network)?;
+    socket
+        .write_all(request.as_bytes())
+        .map_err(|_| Error::Network)?;
+    Ok(socket)
+}
+
+/// Feeds the response off `socket` to `parser` until it is complete
+fn read<B: Buffer>(
+    mut socket: TcpStream,
+    mut parser: ResponseParser<B>,
+) -> Result<ResponseParser<B>, Error> {
+    let deadline = Instant::now() + TIMEOUT;
+    loop {
+        if Instant::now() > deadline {
+            return Err(Error::Timeout);
+        }
+        let len = match socket.read(parser.spare(READ_CHUNK)) {
+            // the server closed the connection
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                continue
+            }
+            Err(_) => return Err(Error::Network),
+        };
+        if parser.commit(len)? {
+            break;
+        }
+    }
+    Ok(parser)
+}
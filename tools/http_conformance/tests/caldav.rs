@@ -0,0 +1,251 @@
+//! `calendar-query` REPORT responses as Radicale, Nextcloud and Baïkal
+//! write them, read by the firmware's `caldav_parser` the way
+//! `caldav::Client::todos` does.
+
+use jiff::{
+    civil::{date, Date, DateTime},
+    tz::{Offset, TimeZone},
+    Timestamp,
+};
+use magtag_http_conformance::caldav_parser::{self, Todo};
+
+/// The badge two hours ahead of UTC
+fn utc_plus_2(utc: Timestamp) -> Option<DateTime> {
+    Some(TimeZone::fixed(Offset::constant(2)).to_datetime(utc))
+}
+
+/// The badge five hours behind UTC
+fn utc_minus_5(utc: Timestamp) -> Option<DateTime> {
+    Some(TimeZone::fixed(Offset::constant(-5)).to_datetime(utc))
+}
+
+/// The clock hasn't been set
+fn unknown(_: Timestamp) -> Option<DateTime> {
+    None
+}
+
+/// A Nextcloud style response for each `(href, etag, calendar-data)`, with
+/// the CRs of the calendar data written as `&#13;`
+fn nextcloud(objects: &[(&str, &str, &str)]) -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\"?>\n<d:multistatus xmlns:d=\"DAV:\" \
+         xmlns:cal=\"urn:ietf:params:xml:ns:caldav\" xmlns:oc=\"http://owncloud.org/ns\">",
+    );
+    for (href, etag, ics) in objects {
+        let ics = ics
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('\r', "&#13;");
+        xml += &format!(
+            "<d:response><d:href>{}</d:href><d:propstat><d:prop>\
+             <d:getetag>&quot;{}&quot;</d:getetag>\
+             <cal:calendar-data>{}</cal:calendar-data>\
+             </d:prop><d:status>HTTP/1.1 200 OK</d:status></d:propstat></d:response>",
+            href, etag, ics
+        );
+    }
+    xml + "</d:multistatus>"
+}
+
+/// A calendar object with one VTODO made of `properties`
+fn vtodo(properties: &str) -> String {
+    format!(
+        "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nPRODID:-//test//EN\r\nBEGIN:VTODO\r\n\
+         UID:1@test\r\n{}END:VTODO\r\nEND:VCALENDAR\r\n",
+        properties
+    )
+}
+
+fn only(xml: &str, to_local: caldav_parser::ToLocal) -> Todo {
+    let mut todos = caldav_parser::todos(xml, to_local);
+    assert_eq!(todos.len(), 1, "{:?}", todos);
+    todos.remove(0)
+}
+
+fn due(properties: &str, to_local: caldav_parser::ToLocal) -> Option<Date> {
+    let ics = vtodo(properties);
+    only(&nextcloud(&[("/t/1.ics", "1", &ics)]), to_local).due
+}
+
+#[test]
+fn radicale_response_without_prefixes() {
+    let xml = "<?xml version='1.0' encoding='utf-8'?>\n\
+        <multistatus xmlns=\"DAV:\" xmlns:C=\"urn:ietf:params:xml:ns:caldav\">\
+        <response><href>/user/tasks/</href><propstat><prop><getetag/></prop>\
+        <status>HTTP/1.1 404 Not Found</status></propstat></response>\
+        <response><href>/user/tasks/milk.ics</href><propstat><prop>\
+        <getetag>\"4f1c\"</getetag><C:calendar-data>BEGIN:VCALENDAR\n\
+        BEGIN:VTODO\nUID:milk\nSUMMARY:Buy milk\nDUE;VALUE=DATE:20261014\n\
+        END:VTODO\nEND:VCALENDAR\n</C:calendar-data></prop>\
+        <status>HTTP/1.1 200 OK</status></propstat></response></multistatus>";
+    let todo = only(xml, utc_plus_2);
+    assert_eq!(todo.href, "/user/tasks/milk.ics");
+    assert_eq!(todo.etag.as_deref(), Some("\"4f1c\""));
+    assert_eq!(todo.summary, "Buy milk");
+    assert_eq!(todo.due, Some(date(2026, 10, 14)));
+    assert!(!todo.completed);
+}
+
+#[test]
+fn entities_in_href_etag_and_calendar_data() {
+    let ics = vtodo("SUMMARY:Fish & chips <for two>\r\n");
+    let todo = only(
+        &nextcloud(&[("/remote.php/dav/calendars/a%20b/t/x&amp;y.ics", "7e", &ics)]),
+        utc_plus_2,
+    );
+    assert_eq!(todo.href, "/remote.php/dav/calendars/a%20b/t/x&y.ics");
+    assert_eq!(todo.etag.as_deref(), Some("\"7e\""));
+    assert_eq!(todo.summary, "Fish & chips <for two>");
+}
+
+#[test]
+fn numeric_entities() {
+    let xml = "<d:multistatus xmlns:d=\"DAV:\"><d:response><d:href>/t/1.ics</d:href>\
+        <d:propstat><d:prop><c:calendar-data xmlns:c=\"urn:ietf:params:xml:ns:caldav\">\
+        BEGIN:VTODO&#x0D;&#10;SUMMARY:Caf&#233; &#x2615;&#13;&#10;END:VTODO&#13;&#10;\
+        </c:calendar-data></d:prop></d:propstat></d:response></d:multistatus>";
+    let todo = only(xml, utc_plus_2);
+    assert_eq!(todo.summary, "Café ☕");
+    assert_eq!(todo.etag, None);
+}
+
+#[test]
+fn unknown_entities_are_kept() {
+    let ics = "BEGIN:VTODO\nSUMMARY:A &nbsp; B &#xZZ; C & D\nEND:VTODO\n";
+    let xml = format!(
+        "<multistatus><response><href>/1</href><calendar-data>{}</calendar-data>\
+         </response></multistatus>",
+        ics
+    );
+    assert_eq!(only(&xml, utc_plus_2).summary, "A &nbsp; B &#xZZ; C & D");
+}
+
+#[test]
+fn cdata_calendar_data() {
+    let xml = "<D:multistatus xmlns:D=\"DAV:\" xmlns:C=\"urn:ietf:params:xml:ns:caldav\">\
+        <D:response><D:href>/dav/cal/2.ics</D:href><D:propstat><D:prop>\
+        <D:getetag>&quot;2&quot;</D:getetag><C:calendar-data><![CDATA[BEGIN:VCALENDAR\r\n\
+        BEGIN:VTODO\r\nSUMMARY:Tea & <biscuits>\r\nEND:VTODO\r\nEND:VCALENDAR\r\n]]>\
+        </C:calendar-data></D:prop></D:propstat></D:response></D:multistatus>";
+    let todo = only(xml, utc_plus_2);
+    assert_eq!(todo.summary, "Tea & <biscuits>");
+    assert_eq!(todo.etag.as_deref(), Some("\"2\""));
+}
+
+#[test]
+fn folded_lines_are_joined() {
+    let ics = vtodo(
+        "SUMMARY:Renew the passport before the trip to Lisbon in\r\n  November, \
+         photos\r\n\tfirst\r\nDUE;VALUE=DATE:2026\r\n 1031\r\n",
+    );
+    let todo = only(&nextcloud(&[("/t/1.ics", "1", &ics)]), utc_plus_2);
+    assert_eq!(
+        todo.summary,
+        "Renew the passport before the trip to Lisbon in November, photosfirst"
+    );
+    assert_eq!(todo.due, Some(date(2026, 10, 31)));
+}
+
+#[test]
+fn text_escapes() {
+    let ics = vtodo("SUMMARY:Eggs\\, flour\\; milk\\nand \\\\ butter\r\n");
+    let todo = only(&nextcloud(&[("/t/1.ics", "1", &ics)]), utc_plus_2);
+    assert_eq!(todo.summary, "Eggs, flour; milk and \\ butter");
+}
+
+#[test]
+fn due_date() {
+    assert_eq!(
+        due("DUE;VALUE=DATE:20261014\r\n", utc_plus_2),
+        Some(date(2026, 10, 14))
+    );
+    assert_eq!(
+        due("DUE;VALUE=DATE:20261014\r\n", utc_minus_5),
+        Some(date(2026, 10, 14))
+    );
+}
+
+#[test]
+fn due_utc_time_is_converted() {
+    let late = "DUE:20261014T230000Z\r\n";
+    assert_eq!(due(late, utc_plus_2), Some(date(2026, 10, 15)));
+    assert_eq!(due(late, utc_minus_5), Some(date(2026, 10, 14)));
+    let early = "DUE:20261014T030000Z\r\n";
+    assert_eq!(due(early, utc_minus_5), Some(date(2026, 10, 13)));
+    // no clock yet, so the UTC date
+    assert_eq!(due(late, unknown), Some(date(2026, 10, 14)));
+}
+
+#[test]
+fn due_floating_and_tzid_times_are_taken_as_they_are() {
+    assert_eq!(
+        due("DUE:20261014T233000\r\n", utc_plus_2),
+        Some(date(2026, 10, 14))
+    );
+    assert_eq!(
+        due("DUE;TZID=America/New_York:20261014T233000\r\n", utc_plus_2),
+        Some(date(2026, 10, 14))
+    );
+}
+
+#[test]
+fn bad_due_is_no_due() {
+    assert_eq!(due("DUE:20261340\r\n", utc_plus_2), None);
+    assert_eq!(due("DUE:tomorrow\r\n", utc_plus_2), None);
+    assert_eq!(due("DUE:20261014T250000Z\r\n", utc_plus_2), None);
+}
+
+#[test]
+fn valarm_properties_are_left_out() {
+    let ics = vtodo(
+        "SUMMARY:Call the dentist\r\nBEGIN:VALARM\r\nACTION:DISPLAY\r\n\
+         SUMMARY:Reminder\r\nDESCRIPTION:Call\r\nTRIGGER;RELATED=END:-PT15M\r\n\
+         DUE:20200101\r\nSTATUS:COMPLETED\r\nEND:VALARM\r\nDUE;VALUE=DATE:20261020\r\n",
+    );
+    let todo = only(&nextcloud(&[("/t/1.ics", "1", &ics)]), utc_plus_2);
+    assert_eq!(todo.summary, "Call the dentist");
+    assert_eq!(todo.due, Some(date(2026, 10, 20)));
+    assert!(!todo.completed);
+}
+
+#[test]
+fn completed_to_dos_are_marked() {
+    let by_status = vtodo("SUMMARY:a\r\nSTATUS:COMPLETED\r\n");
+    let by_time = vtodo("SUMMARY:b\r\nCOMPLETED:20261001T120000Z\r\n");
+    let open = vtodo("SUMMARY:c\r\nSTATUS:NEEDS-ACTION\r\n");
+    let todos = caldav_parser::todos(
+        &nextcloud(&[
+            ("/t/a.ics", "a", &by_status),
+            ("/t/b.ics", "b", &by_time),
+            ("/t/c.ics", "c", &open),
+        ]),
+        utc_plus_2,
+    );
+    let completed: Vec<_> = todos.iter().map(|todo| todo.completed).collect();
+    assert_eq!(completed, [true, true, false]);
+}
+
+#[test]
+fn responses_without_a_vtodo_are_left_out() {
+    let event = "BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nSUMMARY:Party\r\nEND:VEVENT\r\n\
+                 END:VCALENDAR\r\n";
+    let todo = vtodo("SUMMARY:Tidy up\r\n");
+    let todos = caldav_parser::todos(
+        &nextcloud(&[("/t/e.ics", "e", event), ("/t/t.ics", "t", &todo)]),
+        utc_plus_2,
+    );
+    assert_eq!(todos.len(), 1);
+    assert_eq!(todos[0].summary, "Tidy up");
+}
+
+#[test]
+fn empty_and_truncated_responses() {
+    assert!(caldav_parser::todos("", utc_plus_2).is_empty());
+    assert!(caldav_parser::todos("<d:multistatus xmlns:d=\"DAV:\"/>", utc_plus_2).is_empty());
+    let ics = vtodo("SUMMARY:x\r\n");
+    let xml = nextcloud(&[("/t/1.ics", "1", &ics)]);
+    for len in 0..xml.len() {
+        caldav_parser::todos(&xml[..len], utc_plus_2);
+    }
+}
@@ -0,0 +1,413 @@
+//! Each test starts a server on a free local port that answers with bytes
+//! written by hand, then fetches from it with the firmware's parser.
+//!
+//! The server writes a reply in parts with pauses in between, so responses
+//! arrive split at awkward places, and leaves the connection open unless the
+//! test says otherwise, as a keep-alive server would.
+
+use std::{
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::mpsc::{self, Receiver},
+    thread,
+    time::{Duration, Instant},
+};
+
+use magtag_http_conformance::{
+    fetch, fetch_in_place, http_parser::MAX_HEAD_LEN, Error, MAX_REDIRECTS, MAX_RESPONSE_LEN,
+    TIMEOUT,
+};
+
+/// A reply: its parts, the pause before each, and whether to close after
+#[derive(Clone)]
+struct Reply {
+    parts: Vec<Vec<u8>>,
+    pause: Duration,
+    close: bool,
+}
+
+impl Reply {
+    fn new(raw: &str) -> Self {
+        Self::bytes(raw.as_bytes())
+    }
+
+    fn bytes(raw: &[u8]) -> Self {
+        Self {
+            parts: vec![raw.to_vec()],
+            pause: Duration::ZERO,
+            close: false,
+        }
+    }
+
+    /// Sends the reply `size` bytes at a time, waiting `pause` before each
+    fn trickle(mut self, size: usize, pause: Duration) -> Self {
+        self.parts = self
+            .parts
+            .concat()
+            .chunks(size)
+            .map(<[u8]>::to_vec)
+            .collect();
+        self.pause = pause;
+        self
+    }
+
+    fn then_close(mut self) -> Self {
+        self.close = true;
+        self
+    }
+}
+
+/// Serves `replies` to one connection each, in order, and passes on every
+/// request head it read
+fn serve(replies: Vec<Reply>) -> (u16, Receiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let (requests, received) = mpsc::channel();
+    thread::spawn(move || {
+        let mut open = Vec::new();
+        for reply in replies {
+            let (mut stream, _) = listener.accept().unwrap();
+            requests.send(read_head(&mut stream)).ok();
+            for part in &reply.parts {
+                thread::sleep(reply.pause);
+                if stream.write_all(part).is_err() {
+                    break;
+                }
+            }
+            if !reply.close {
+                // held until the test is over
+                open.push(stream);
+            }
+        }
+        thread::sleep(TIMEOUT * 2);
+    });
+    (port, received)
+}
+
+fn read_head(stream: &mut TcpStream) -> String {
+    let mut head = Vec::new();
+    let mut byte = [0u8];
+    while !head.ends_with(b"\r\n\r\n") && stream.read(&mut byte).unwrap_or(0) == 1 {
+        head.push(byte[0]);
+    }
+    String::from_utf8(head).unwrap()
+}
+
+fn url(port: u16, path: &str) -> String {
+    format!("http://127.0.0.1:{}{}", port, path)
+}
+
+/// Fetches `reply` into a growing buffer and again into a fixed one, which
+/// have to agree
+fn get(reply: Reply) -> Result<(u16, Vec<u8>), Error> {
+    let (port, _) = serve(vec![reply.clone(), reply]);
+    let grown = fetch(&url(port, "/"), &[]).map(|response| (response.status, response.body));
+    let mut buffer = vec![0; MAX_RESPONSE_LEN];
+    let fixed = fetch_in_place(&url(port, "/"), &mut buffer)
+        .map(|response| (response.status, response.body.to_vec()));
+    assert_eq!(grown, fixed, "fixed buffer read differently");
+    grown
+}
+
+#[test]
+fn content_length_ends_the_body_on_an_open_connection() {
+    let started = Instant::now();
+    let reply = Reply::new("HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello");
+    assert_eq!(get(reply), Ok((200, b"hello".to_vec())));
+    assert!(started.elapsed() < TIMEOUT / 2, "waited for the close");
+}
+
+#[test]
+fn content_length_drops_anything_after_the_body() {
+    let reply = Reply::new("HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nokHTTP/1.1 200 OK\r\n");
+    assert_eq!(get(reply), Ok((200, b"ok".to_vec())));
+}
+
+#[test]
+fn body_without_length_runs_to_the_close() {
+    let reply =
+        Reply::new("HTTP/1.0 200 OK\r\nContent-Type: text/plain\r\n\r\nall of it").then_close();
+    assert_eq!(get(reply), Ok((200, b"all of it".to_vec())));
+}
+
+#[test]
+fn body_without_length_on_an_open_connection_times_out() {
+    let reply = Reply::new("HTTP/1.1 200 OK\r\n\r\nnever ends");
+    assert_eq!(get(reply), Err(Error::Timeout));
+}
+
+#[test]
+fn no_content_needs_no_body() {
+    let reply = Reply::new("HTTP/1.1 204 No Content\r\nContent-Length: 10\r\n\r\n");
+    assert_eq!(get(reply), Ok((204, Vec::new())));
+}
+
+#[test]
+fn chunked_body_is_decoded() {
+    let reply = Reply::new(
+        "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+         5\r\nhello\r\n7;name=value\r\n, world\r\n0\r\nExpires: never\r\n\r\n",
+    );
+    assert_eq!(get(reply), Ok((200, b"hello, world".to_vec())));
+}
+
+#[test]
+fn chunked_body_with_upper_case_sizes_and_coding() {
+    let body = "x".repeat(0x1A);
+    let reply = Reply::new(&format!(
+        "HTTP/1.1 200 OK\r\nTransfer-Encoding: CHUNKED\r\n\r\n1A\r\n{}\r\n0\r\n\r\n",
+        body
+    ));
+    assert_eq!(get(reply), Ok((200, body.into_bytes())));
+}
+
+#[test]
+fn chunked_body_arriving_a_byte_at_a_time() {
+    let reply = Reply::new(
+        "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n2\r\nde\r\n0\r\n\r\n",
+    )
+    .trickle(1, Duration::from_millis(2));
+    assert_eq!(get(reply), Ok((200, b"abcde".to_vec())));
+}
+
+#[test]
+fn chunked_body_cut_short_is_an_error() {
+    let reply =
+        Reply::new("HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhel").then_close();
+    assert_eq!(get(reply), Err(Error::Network));
+}
+
+#[test]
+fn chunk_without_its_crlf_is_an_error() {
+    let reply =
+        Reply::new("HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nabc\r\n0\r\n\r\n");
+    assert_eq!(get(reply), Err(Error::Network));
+}
+
+#[test]
+fn bad_chunk_sizes_are_errors() {
+    for size in ["", "g", "-1", "0x10", "123456789", &"0".repeat(80)] {
+        let reply = Reply::new(&format!(
+            "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n{}\r\nabc\r\n0\r\n\r\n",
+            size
+        ));
+        assert_eq!(get(reply), Err(Error::Network), "size {:?}", size);
+    }
+}
+
+#[test]
+fn slow_head_split_mid_line() {
+    let reply = Reply::new("HTTP/1.1 200 OK\r\nContent-Length: 4\r\nX-Slow: yes\r\n\r\nslow")
+        .trickle(3, Duration::from_millis(10));
+    assert_eq!(get(reply), Ok((200, b"slow".to_vec())));
+}
+
+#[test]
+fn response_slower_than_the_timeout() {
+    let reply =
+        Reply::new("HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\nlate").trickle(8, TIMEOUT / 2);
+    assert_eq!(get(reply), Err(Error::Timeout));
+}
+
+#[test]
+fn interim_responses_are_skipped() {
+    let reply = Reply::new(
+        "HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 102 Processing\r\n\r\n\
+         HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\ndone",
+    );
+    assert_eq!(get(reply), Ok((200, b"done".to_vec())));
+}
+
+#[test]
+fn body_shorter_than_its_length_is_an_error() {
+    let reply = Reply::new("HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nshort").then_close();
+    assert_eq!(get(reply), Err(Error::Network));
+}
+
+#[test]
+fn oversized_body_is_truncated() {
+    let len = MAX_RESPONSE_LEN;
+    let reply = Reply::new(&format!(
+        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+        len,
+        "y".repeat(len)
+    ));
+    let (status, body) = get(reply).unwrap();
+    assert_eq!(status, 200);
+    assert!(body.len() < len && body.iter().all(|&b| b == b'y'));
+}
+
+#[test]
+fn malformed_heads_are_errors() {
+    let heads = [
+        "HTTP/1.1 OK\r\n",
+        "HTTP/1.1 20 OK\r\n",
+        "HTTP/1.1 2000 OK\r\n",
+        "HTTP/2 200 OK\r\n",
+        "ICY 200 OK\r\n",
+        "HTTP/1.1 200 OK\r\nNo colon here\r\n",
+        "HTTP/1.1 200 OK\r\nContent-Length : 2\r\n",
+        "HTTP/1.1 200 OK\r\n: empty name\r\n",
+        "HTTP/1.1 200 OK\r\nX-Folded: one\r\n two\r\n",
+        "HTTP/1.1 200 OK\r\nContent-Length: two\r\n",
+        "HTTP/1.1 200 OK\r\nContent-Length: -2\r\n",
+        "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nContent-Length: 3\r\n",
+        "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nTransfer-Encoding: chunked\r\n",
+        "HTTP/1.1 200 OK\r\nTransfer-Encoding: gzip, chunked\r\n",
+        "HTTP/1.1 700 Beyond\r\n",
+    ];
+    for head in heads {
+        let reply = Reply::new(&format!("{}\r\nok", head)).then_close();
+        assert_eq!(get(reply), Err(Error::Network), "{:?}", head);
+    }
+}
+
+#[test]
+fn repeated_equal_lengths_are_accepted() {
+    let reply = Reply::new("HTTP/1.1 200 OK\r\nContent-Length: 2\r\ncontent-length: 2\r\n\r\nok");
+    assert_eq!(get(reply), Ok((200, b"ok".to_vec())));
+}
+
+#[test]
+fn header_values_are_trimmed_and_looked_up_ignoring_case() {
+    let (port, _) = serve(vec![Reply::new(
+        "HTTP/1.1 200 OK\r\ncontent-type:\t text/plain \r\nContent-Length: 0\r\n\r\n",
+    )]);
+    let response = fetch(&url(port, "/"), &[]).unwrap();
+    assert_eq!(response.header("Content-Type"), Some("text/plain"));
+}
+
+#[test]
+fn non_utf8_head_is_an_error() {
+    let reply = Reply::bytes(b"HTTP/1.1 200 OK\r\nX-Bad: \xff\r\n\r\n").then_close();
+    assert_eq!(get(reply), Err(Error::Network));
+}
+
+#[test]
+fn endless_head_is_an_error() {
+    let reply = Reply::new(&format!(
+        "HTTP/1.1 200 OK\r\nX-Long: {}",
+        "z".repeat(MAX_HEAD_LEN)
+    ));
+    assert_eq!(get(reply), Err(Error::Network));
+}
+
+#[test]
+fn too_many_headers_is_an_error() {
+    let headers: String = (0..40).map(|i| format!("X-{}: {}\r\n", i, i)).collect();
+    let reply = Reply::new(&format!("HTTP/1.1 200 OK\r\n{}\r\n", headers)).then_close();
+    assert_eq!(get(reply), Err(Error::Network));
+}
+
+#[test]
+fn redirects_are_followed() {
+    let (port, requests) = serve(vec![
+        Reply::new("HTTP/1.1 301 Moved\r\nLocation: /b/c\r\nContent-Length: 0\r\n\r\n"),
+        Reply::new("HTTP/1.1 302 Found\r\nLocation: d?x=1\r\nContent-Length: 0\r\n\r\n"),
+        Reply::new("HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\narrive"),
+    ]);
+    let response = fetch(&url(port, "/a"), &[]).unwrap();
+    assert_eq!((response.status, response.body), (200, b"arrive".to_vec()));
+    let targets: Vec<String> = requests
+        .iter()
+        .take(3)
+        .map(|head| head.split(' ').nth(1).unwrap().to_string())
+        .collect();
+    assert_eq!(targets, ["/a", "/b/c", "/b/d?x=1"]);
+}
+
+#[test]
+fn host_header_has_a_port_other_than_80() {
+    let (port, requests) = serve(vec![Reply::new(
+        "HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n",
+    )]);
+    assert_eq!(fetch(&url(port, "/"), &[]).unwrap().status, 200);
+    let head = requests.recv().unwrap();
+    assert!(
+        head.contains(&format!("\r\nHost: 127.0.0.1:{}\r\n", port)),
+        "{}",
+        head
+    );
+}
+
+#[test]
+fn redirect_loops_stop() {
+    let hop = || Reply::new("HTTP/1.1 307 Again\r\nLocation: /\r\nContent-Length: 0\r\n\r\n");
+    let (port, requests) = serve((0..=MAX_REDIRECTS + 1).map(|_| hop()).collect());
+    let response = fetch(&url(port, "/"), &[]).unwrap();
+    assert_eq!(response.status, 307);
+    assert_eq!(requests.try_iter().count(), MAX_REDIRECTS + 1);
+}
+
+#[test]
+fn redirect_with_a_space_in_the_location_is_not_followed() {
+    let reply =
+        Reply::new("HTTP/1.1 302 Found\r\nLocation: /a HTTP/1.0\r\nContent-Length: 0\r\n\r\n");
+    assert_eq!(get(reply), Ok((302, Vec::new())));
+}
+
+#[test]
+fn urls_with_a_space_or_line_break_are_refused() {
+    let (port, requests) = serve(vec![Reply::new(
+        "HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n",
+    )]);
+    for path in [
+        "/a b",
+        "/a\r\nX-Injected: 1",
+        "/?q=a b",
+        "/?q=1\r\n\r\nGET /",
+    ] {
+        let target = url(port, path);
+        let fetched = fetch(&target, &[]).map(|response| response.status);
+        assert_eq!(fetched, Err(Error::InvalidUrl), "{:?}", path);
+        let mut buffer = [0u8; 256];
+        let fetched = fetch_in_place(&target, &mut buffer).map(|response| response.status);
+        assert_eq!(fetched, Err(Error::InvalidUrl), "{:?}", path);
+    }
+    assert!(requests.try_recv().is_err(), "a request was sent");
+}
+
+#[test]
+fn redirect_to_another_host_drops_credentials() {
+    let (second, to_second) = serve(vec![Reply::new(
+        "HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n",
+    )]);
+    let location = format!("http://localhost:{}/elsewhere", second);
+    let (first, to_first) = serve(vec![Reply::new(&format!(
+        "HTTP/1.1 302 Found\r\nLocation: {}\r\nContent-Length: 0\r\n\r\n",
+        location
+    ))]);
+    let auth = [("Authorization", "Bearer secret")];
+    assert_eq!(fetch(&url(first, "/"), &auth).unwrap().status, 200);
+    assert!(to_first.recv().unwrap().contains("Bearer secret"));
+    let redirected = to_second.recv().unwrap();
+    assert!(redirected.starts_with("GET /elsewhere HTTP/1.0\r\n"));
+    assert!(!redirected.contains("Authorization"), "{}", redirected);
+}
+
+#[test]
+fn redirect_on_the_same_host_keeps_credentials() {
+    let (port, requests) = serve(vec![
+        Reply::new("HTTP/1.1 303 See Other\r\nLocation: /next\r\nContent-Length: 0\r\n\r\n"),
+        Reply::new("HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n"),
+    ]);
+    let auth = [("Authorization", "Bearer secret")];
+    assert_eq!(fetch(&url(port, "/"), &auth).unwrap().status, 200);
+    assert!(requests
+        .iter()
+        .take(2)
+        .all(|head| head.contains("Bearer secret")));
+}
+
+#[test]
+fn fixed_buffer_truncates_to_its_size() {
+    let (port, _) = serve(vec![Reply::new(&format!(
+        "HTTP/1.1 200 OK\r\nContent-Length: 1000\r\n\r\n{}",
+        "z".repeat(1000)
+    ))]);
+    let mut buffer = [0u8; 256];
+    let response = fetch_in_place(&url(port, "/"), &mut buffer).unwrap();
+    assert_eq!(response.status, 200);
+    assert_eq!(response.header("content-length"), Some("1000"));
+    assert!(response.body.len() < 256 && response.body.iter().all(|&b| b == b'z'));
+}
@@ -0,0 +1,139 @@
+//! Form bodies from the firmware's `multipart` module, checked against bytes
+//! written by hand, with files from memory and streamed from a source.
+
+use std::cell::Cell;
+
+use magtag_http_conformance::{
+    multipart::{Multipart, Source, CHUNK},
+    Error,
+};
+
+/// A file read from `data`, counting its reads; claims `len` bytes, which may
+/// be more than it has
+struct Stream {
+    data: Vec<u8>,
+    len: u32,
+    reads: Cell<usize>,
+}
+
+impl Stream {
+    fn new(data: Vec<u8>) -> Self {
+        let len = data.len() as u32;
+        Self {
+            data,
+            len,
+            reads: Cell::new(0),
+        }
+    }
+}
+
+impl Source for Stream {
+    fn len(&self) -> u32 {
+        self.len
+    }
+
+    fn read(&self, offset: u32, buf: &mut [u8]) -> Result<usize, Error> {
+        self.reads.set(self.reads.get() + 1);
+        let rest = self.data.get(offset as usize..).unwrap_or(&[]);
+        let len = rest.len().min(buf.len());
+        buf[..len].copy_from_slice(&rest[..len]);
+        Ok(len)
+    }
+}
+
+fn encode(form: &Multipart<'_>) -> Result<Vec<u8>, Error> {
+    let mut body = Vec::new();
+    form.write_to(&mut |bytes| {
+        body.extend_from_slice(bytes);
+        Ok(())
+    })?;
+    Ok(body)
+}
+
+#[test]
+fn text_and_file_parts() {
+    let form = Multipart::with_boundary("XyZ")
+        .text("badge", "lobby")
+        .bytes("log", "events.csv", "text/csv", b"at,what\n1,boot\n");
+    let body = encode(&form).unwrap();
+    assert_eq!(
+        String::from_utf8(body.clone()).unwrap(),
+        "--XyZ\r\n\
+         Content-Disposition: form-data; name=\"badge\"\r\n\
+         \r\n\
+         lobby\r\n\
+         --XyZ\r\n\
+         Content-Disposition: form-data; name=\"log\"; filename=\"events.csv\"\r\n\
+         Content-Type: text/csv\r\n\
+         \r\n\
+         at,what\n1,boot\n\r\n\
+         --XyZ--\r\n"
+    );
+    assert_eq!(form.len(), body.len());
+    assert_eq!(form.content_type(), "multipart/form-data; boundary=XyZ");
+}
+
+#[test]
+fn empty_form_is_just_the_closing_boundary() {
+    let form = Multipart::with_boundary("b");
+    assert!(form.is_empty());
+    assert_eq!(encode(&form).unwrap(), b"--b--\r\n");
+    assert_eq!(form.len(), 7);
+}
+
+#[test]
+fn streamed_file_is_read_a_chunk_at_a_time() {
+    let data: Vec<u8> = (0..CHUNK * 3 + 17).map(|i| i as u8).collect();
+    let stream = Stream::new(data.clone());
+    let form = Multipart::with_boundary("b").file("shot", "screen.bmp", "image/bmp", &stream);
+    let body = encode(&form).unwrap();
+
+    assert_eq!(form.len(), body.len());
+    assert_eq!(stream.reads.get(), 4);
+    let head = b"--b\r\nContent-Disposition: form-data; name=\"shot\"; filename=\"screen.bmp\"\r\n\
+                 Content-Type: image/bmp\r\n\r\n";
+    assert_eq!(&body[..head.len()], head);
+    assert_eq!(&body[head.len()..head.len() + data.len()], &data[..]);
+    assert_eq!(&body[head.len() + data.len()..], b"\r\n--b--\r\n");
+}
+
+#[test]
+fn streamed_file_can_be_sent_again() {
+    let stream = Stream::new(b"hello".to_vec());
+    let form = Multipart::with_boundary("b").file("f", "f.txt", "text/plain", &stream);
+    assert_eq!(encode(&form).unwrap(), encode(&form).unwrap());
+}
+
+#[test]
+fn short_source_is_a_storage_error() {
+    let mut stream = Stream::new(b"only five".to_vec());
+    stream.len = 20;
+    let form = Multipart::with_boundary("b").file("f", "f.txt", "text/plain", &stream);
+    assert_eq!(encode(&form), Err(Error::Storage));
+}
+
+#[test]
+fn write_error_stops_the_body() {
+    let form = Multipart::with_boundary("b").text("a", "1").text("b", "2");
+    let mut writes = 0;
+    let result = form.write_to(&mut |_| {
+        writes += 1;
+        Err(Error::Network)
+    });
+    assert_eq!(result, Err(Error::Network));
+    assert_eq!(writes, 1);
+}
+
+#[test]
+fn quotes_and_line_breaks_in_headers_are_invalid() {
+    assert!(Multipart::with_boundary("b")
+        .text("name", "any \"value\"\r\n")
+        .is_valid());
+    assert!(!Multipart::with_boundary("b").text("na\"me", "v").is_valid());
+    assert!(!Multipart::with_boundary("b")
+        .bytes("f", "a\r\nb.txt", "text/plain", b"")
+        .is_valid());
+    assert!(!Multipart::with_boundary("b")
+        .bytes("f", "a.txt", "text/plain\nX: y", b"")
+        .is_valid());
+}
@@ -0,0 +1,104 @@
+#!/usr/bin/env python3
+"""Copy files to and from a MagTag in USB file-drop mode.
+
+Hold B while the badge boots, then for example:
+
+    magtag_files.py /dev/ttyACM0 ls
+    magtag_files.py /dev/ttyACM0 put ferris.bin [name]
+    magtag_files.py /dev/ttyACM0 get ferris.bin [dest]
+    magtag_files.py /dev/ttyACM0 rm ferris.bin
+    magtag_files.py /dev/ttyACM0 df | format | reboot
+
+Needs pyserial.
+"""
+
+import argparse
+import pathlib
+import sys
+import zlib
+
+import serial
+
+MAX_NAME_LEN = 20
+
+
+class Badge:
+    def __init__(self, port):
+        self.port = serial.Serial(port, timeout=10)
+        self.port.reset_input_buffer()
+
+    def command(self, line):
+        self.port.write(line.encode() + b"\n")
+
+    def reply(self):
+        line = self.port.readline()
+        if not line:
+            sys.exit("no reply from the badge; is it in file-drop mode?")
+        line = line.decode().strip()
+        if line.startswith("error"):
+            sys.exit(line)
+        return line
+
+    def lines_until_ok(self):
+        lines = []
+        while (line := self.reply()) != "ok":
+            lines.append(line)
+        return lines
+
+    def put(self, path, name):
+        data = path.read_bytes()
+        self.command(f"put {name} {len(data)} {zlib.crc32(data):08x}")
+        if self.reply() != "ready":
+            sys.exit("unexpected reply to put")
+        self.port.write(data)
+        self.lines_until_ok()
+        print(f"{name}: {len(data)} bytes")
+
+    def get(self, name, dest):
+        self.command(f"get {name}")
+        _, length, crc = self.reply().split()
+        data = self.port.read(int(length))
+        if len(data) != int(length) or zlib.crc32(data) != int(crc, 16):
+            sys.exit("transfer failed its CRC check")
+        self.lines_until_ok()
+        dest.write_bytes(data)
+        print(f"{name}: {len(data)} bytes")
+
+
+def main():
+    parser = argparse.ArgumentParser(description=__doc__.splitlines()[0])
+    parser.add_argument("port")
+    parser.add_argument("command", choices=["ls", "df", "put", "get", "rm", "format", "reboot"])
+    parser.add_argument("args", nargs="*")
+    args = parser.parse_args()
+    badge = Badge(args.port)
+
+    if args.command == "put":
+        path = pathlib.Path(args.args[0])
+        name = args.args[1] if len(args.args) > 1 else path.name
+        if len(name.encode()) > MAX_NAME_LEN or " " in name:
+            sys.exit(f"names are at most {MAX_NAME_LEN} bytes without spaces")
+        badge.put(path, name)
+    elif args.command == "get":
+        name = args.args[0]
+        dest = pathlib.Path(args.args[1] if len(args.args) > 1 else name)
+        badge.get(name, dest)
+    elif args.command == "ls":
+        badge.command("ls")
+        for line in badge.lines_until_ok():
+            name, length, crc = line.split()
+            print(f"{int(length):>8}  {crc}  {name}")
+    elif args.command == "df":
+        badge.command("df")
+        free, capacity = map(int, badge.lines_until_ok()[0].split())
+        print(f"{free // 1024} KiB free of {capacity // 1024} KiB")
+    elif args.command == "rm":
+        badge.command(f"rm {args.args[0]}")
+        badge.lines_until_ok()
+    else:
+        badge.command(args.command)
+        badge.lines_until_ok()
+
+
+if __name__ == "__main__":
+    main()
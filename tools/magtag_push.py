@@ -0,0 +1,82 @@
+#!/usr/bin/env python3
+"""Send an image to a MagTag running the remote display app.
+
+    magtag_push.py 192.168.1.50 dashboard.png [--port 7070] [--raw] [--key KEY]
+
+The image is scaled to 296x128, converted to four gray levels and sent
+run-length encoded unless --raw is given. --key signs the frame for a badge
+with that `remote_key`; MAGTAG_REMOTE_KEY works too. Needs Pillow.
+"""
+
+import argparse
+import hashlib
+import hmac
+import os
+import socket
+import struct
+import sys
+
+from PIL import Image
+
+WIDTH, HEIGHT = 296, 128
+MAGIC = b"MTFB"
+ENCODING_RAW, ENCODING_RLE = 0, 1
+FLAG_SIGNED = 1
+
+
+def pack(image):
+    """2 bits per pixel, leftmost pixel in the high bits, 0 black to 3 white"""
+    gray = image.convert("L").resize((WIDTH, HEIGHT))
+    levels = [round(v * 3 / 255) for v in gray.getdata()]
+    out = bytearray()
+    for i in range(0, len(levels), 4):
+        a, b, c, d = levels[i : i + 4]
+        out.append(a << 6 | b << 4 | c << 2 | d)
+    return bytes(out)
+
+
+def rle(data):
+    out = bytearray()
+    i = 0
+    while i < len(data):
+        run = 1
+        while i + run < len(data) and run < 255 and data[i + run] == data[i]:
+            run += 1
+        out += bytes((run, data[i]))
+        i += run
+    return bytes(out)
+
+
+def main():
+    parser = argparse.ArgumentParser(description=__doc__.splitlines()[0])
+    parser.add_argument("host")
+    parser.add_argument("image")
+    parser.add_argument("--port", type=int, default=7070)
+    parser.add_argument("--raw", action="store_true", help="skip compression")
+    parser.add_argument(
+        "--key",
+        default=os.environ.get("MAGTAG_REMOTE_KEY"),
+        help="the badge's remote_key, to sign the frame with",
+    )
+    args = parser.parse_args()
+
+    frame = pack(Image.open(args.image))
+    if args.raw:
+        encoding, payload = ENCODING_RAW, frame
+    else:
+        encoding, payload = ENCODING_RLE, rle(frame)
+    flags = FLAG_SIGNED if args.key else 0
+    message = MAGIC + struct.pack("<BB2xI", encoding, flags, len(payload)) + payload
+    if args.key:
+        message += hmac.new(args.key.encode(), message, hashlib.sha256).digest()
+
+    with socket.create_connection((args.host, args.port), timeout=15) as conn:
+        conn.sendall(message)
+        reply = conn.makefile().readline().strip()
+    print(f"{len(payload)} bytes sent: {reply}")
+    if reply != "ok":
+        sys.exit(1)
+
+
+if __name__ == "__main__":
+    main()
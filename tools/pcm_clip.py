@@ -0,0 +1,62 @@
+#!/usr/bin/env python3
+"""Convert a WAV file to a clip for the speaker's PCM playback.
+
+    pcm_clip.py chime.wav assets/chime.pcm [--rate 8000]
+
+Clips are raw unsigned 8-bit mono samples at the given rate, which has to
+match the `rate_hz` of the `speaker::Clip` that includes the file. Channels
+are mixed down and the rate is converted by picking the nearest sample.
+Only uses the standard library.
+"""
+
+import argparse
+import array
+import sys
+import wave
+
+
+def convert(path, rate):
+    with wave.open(path, "rb") as wav:
+        channels = wav.getnchannels()
+        width = wav.getsampwidth()
+        source_rate = wav.getframerate()
+        frames = wav.readframes(wav.getnframes())
+
+    if width == 1:
+        values = [v - 128 for v in frames]
+        full_scale = 128
+    elif width == 2:
+        values = array.array("h", frames)
+        if sys.byteorder == "big":
+            values.byteswap()
+        full_scale = 32768
+    else:
+        sys.exit(f"{width * 8}-bit samples aren't supported")
+
+    mono = [
+        sum(values[i : i + channels]) / channels
+        for i in range(0, len(values), channels)
+    ]
+    count = len(mono) * rate // source_rate
+    out = bytearray()
+    for i in range(count):
+        value = mono[i * source_rate // rate] / full_scale
+        out.append(max(0, min(255, round(128 + value * 127))))
+    return bytes(out)
+
+
+def main():
+    parser = argparse.ArgumentParser(description=__doc__.splitlines()[0])
+    parser.add_argument("wav")
+    parser.add_argument("out")
+    parser.add_argument("--rate", type=int, default=8000)
+    args = parser.parse_args()
+
+    clip = convert(args.wav, args.rate)
+    with open(args.out, "wb") as f:
+        f.write(clip)
+    print(f"{len(clip)} samples, {len(clip) / args.rate:.2f} s at {args.rate} Hz")
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,12 @@
+//! Subsets the firmware's `fonts/` for `crate::fonts` the way its own
+//! `build.rs` does, so screens come out with the same glyphs.
+
+use std::{env, path::Path};
+
+#[path = "../../build/fonts.rs"]
+mod fonts;
+
+fn main() {
+    let manifest = env::var("CARGO_MANIFEST_DIR").unwrap();
+    fonts::generate(&Path::new(&manifest).join("../.."));
+}
@@ -0,0 +1,207 @@
+//! The firmware's widget modules and the bundled apps' screens built for
+//! the host, drawing into a simulator display instead of the panel's
+//! framebuffer.
+//!
+//! Only modules that need nothing from the chip can come along. What they
+//! take from the rest of the firmware, the panel's frame, the app host's
+//! [app::Flow], input events, config key names and the clock among them, is
+//! stood in for here with the same shape; the clock stands still at
+//! [time::NOW] so screens that show times come out the same on every run.
+
+extern crate alloc;
+
+#[path = "../../../src/bindings.rs"]
+pub mod bindings;
+#[path = "../../../src/canvas.rs"]
+pub mod canvas;
+#[path = "../../../src/error.rs"]
+pub mod error;
+#[path = "../../../src/fonts.rs"]
+pub mod fonts;
+#[path = "../../../src/i18n.rs"]
+pub mod i18n;
+#[path = "../../../src/screens/mod.rs"]
+pub mod screens;
+pub mod ui;
+
+pub use error::Error;
+
+/// The panel's size and a framebuffer of the same shape
+pub mod display {
+    use embedded_graphics::pixelcolor::Gray2;
+    use embedded_graphics_simulator::SimulatorDisplay;
+
+    pub const WIDTH: u32 = 296;
+    pub const HEIGHT: u32 = 128;
+
+    pub type Frame = SimulatorDisplay<Gray2>;
+}
+
+/// The names of the config keys screens mention, as in `config.rs`
+pub mod config {
+    pub mod keys {
+        pub const GITHUB_API: &str = "github_api";
+        pub const MQTT_DISPLAY: &str = "mqtt_display";
+        pub const MQTT_SLOTS: &str = "mqtt_slots";
+        pub const TASKS_URL: &str = "tasks_url";
+    }
+}
+
+/// Plain heap buffers, as without the `psram` feature
+pub mod psram {
+    use alloc::vec::Vec;
+
+    pub fn zeroed(len: usize) -> Vec<u8> {
+        alloc::vec![0u8; len]
+    }
+}
+
+/// The result of handling an event, as in `app.rs`
+pub mod app {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Flow {
+        Idle,
+        Redraw,
+    }
+}
+
+/// What a bound button does, as in `actions.rs`
+pub mod actions {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Action {
+        Refresh,
+        NextPage,
+        PreviousPage,
+        NextApp,
+        PreviousApp,
+        Provision,
+        SafeMode,
+        Restart,
+    }
+}
+
+/// Buttons and the events apps get, as in `input.rs`, without the drivers
+pub mod input {
+    use crate::{actions::Action, net_health::Link};
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Button {
+        A,
+        B,
+        C,
+        D,
+    }
+
+    impl Button {
+        pub const ALL: [Button; 4] = [Button::A, Button::B, Button::C, Button::D];
+
+        const fn bit(self) -> u8 {
+            1 << self as u8
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub struct ButtonSet(u8);
+
+    impl ButtonSet {
+        pub const EMPTY: ButtonSet = ButtonSet(0);
+
+        pub const fn of(buttons: &[Button]) -> Self {
+            let mut bits = 0;
+            let mut i = 0;
+            while i < buttons.len() {
+                bits |= buttons[i].bit();
+                i += 1;
+            }
+            ButtonSet(bits)
+        }
+
+        pub const fn contains(self, button: Button) -> bool {
+            self.0 & button.bit() != 0
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Event {
+        Press(Button),
+        Chord(ButtonSet),
+        LongPress(Button),
+        Action(Action),
+        Tick,
+        Link(Link),
+        Answer(Button),
+    }
+
+    /// There are no buttons to repeat
+    pub fn set_repeat(_buttons: ButtonSet) {}
+}
+
+/// The network events, as in `net_health.rs`
+pub mod net_health {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Link {
+        Lost,
+        Recovering,
+        Restored,
+    }
+}
+
+/// Layers over the app, as in `compositor.rs`
+pub mod compositor {
+    use crate::display::Frame;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    pub enum Depth {
+        Background,
+        App,
+        StatusBar,
+        Modal,
+    }
+
+    pub trait Layer {
+        fn depth(&self) -> Depth;
+
+        fn take_dirty(&mut self) -> bool;
+
+        fn draw(&mut self, frame: &mut Frame);
+    }
+}
+
+/// A payload kept from an earlier fetch, as in `data_source.rs`
+pub mod data_source {
+    use jiff::Timestamp;
+
+    use crate::Error;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Stale {
+        pub since: Option<Timestamp>,
+        pub error: Error,
+    }
+}
+
+/// A clock set to [NOW] in UTC+2
+pub mod time {
+    use jiff::{
+        civil::DateTime,
+        tz::{Offset, TimeZone},
+        Timestamp,
+    };
+
+    /// 14 October 2026, 12:30 local time
+    pub const NOW: &str = "2026-10-14T10:30:00Z";
+
+    const ZONE: TimeZone = TimeZone::fixed(Offset::constant(2));
+
+    pub fn now_utc() -> Option<Timestamp> {
+        NOW.parse().ok()
+    }
+
+    pub fn now_local() -> Option<DateTime> {
+        to_local(now_utc()?)
+    }
+
+    pub fn to_local(utc: Timestamp) -> Option<DateTime> {
+        Some(ZONE.to_datetime(utc))
+    }
+}
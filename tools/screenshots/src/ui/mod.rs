@@ -0,0 +1,20 @@
+#[path = "../../../../src/ui/barcode.rs"]
+pub mod barcode;
+#[path = "../../../../src/ui/button_bar.rs"]
+pub mod button_bar;
+#[path = "../../../../src/ui/contrast.rs"]
+pub mod contrast;
+#[path = "../../../../src/ui/dialog.rs"]
+pub mod dialog;
+#[path = "../../../../src/ui/icon.rs"]
+pub mod icon;
+#[path = "../../../../src/ui/pager.rs"]
+pub mod pager;
+#[path = "../../../../src/ui/stale.rs"]
+pub mod stale;
+#[path = "../../../../src/ui/template.rs"]
+pub mod template;
+#[path = "../../../../src/ui/text_entry.rs"]
+pub mod text_entry;
+#[path = "../../../../src/ui/toast.rs"]
+pub mod toast;
@@ -0,0 +1,795 @@
+//! Each test draws a screen the way the host would, on a white frame, and
+//! compares it with `golden/<name>.png`.
+//!
+//! After a change that is meant to alter a screen, `UPDATE_GOLDEN=1 cargo
+//! test` rewrites the images; review them like any other diff. A failing
+//! test leaves what it drew and a map of the differing pixels in
+//! `target/screenshots/`.
+
+use std::{
+    env, fs,
+    path::PathBuf,
+    sync::{Mutex, PoisonError},
+};
+
+use embedded_graphics::{
+    mono_font::{
+        ascii::{FONT_6X10, FONT_7X14_BOLD},
+        MonoTextStyle,
+    },
+    pixelcolor::{Gray2, Gray8},
+    prelude::*,
+    primitives::{PrimitiveStyle, Rectangle},
+    text::{Baseline, Text},
+};
+use embedded_graphics_simulator::{OutputSettings, SimulatorDisplay};
+use jiff::{civil::date, Timestamp};
+use magtag_screenshots::{
+    bindings,
+    canvas::{Canvas, Dither},
+    compositor::Layer,
+    data_source::Stale,
+    display::{Frame, HEIGHT, WIDTH},
+    fonts::{FIXED_6X10, FIXED_7X14_BOLD},
+    i18n::{self, Language},
+    input::{Button, ButtonSet, Event},
+    screens::{
+        badge_game, dashboard, demo,
+        github::{self, CiState, Repo},
+        mqtt_display, remote_display, safe_mode,
+        selftest::{self, Outcome, Step},
+        settings::{self, Status},
+        status, steps,
+        tasks::{self, Task},
+        wifi_survey::{self, Network},
+    },
+    ui::{
+        barcode::Barcode,
+        button_bar::draw_button_hints,
+        contrast::{draw_inverted, draw_knockout, draw_outlined, draw_shadowed},
+        dialog::{self, Dialog, DialogLayer},
+        icon::{self, Icon},
+        pager::{Pager, MAX_DOTS},
+        stale::draw_stale_banner,
+        template,
+        text_entry::TextEntry,
+        toast::{self, ToastLayer},
+    },
+    Error,
+};
+
+/// Pixels that may differ before a screen counts as changed, for small
+/// differences in how a primitive is rasterized between library versions
+const TOLERANCE: usize = 8;
+
+/// Held while drawing: screens look strings up in the one current language
+static LANGUAGE: Mutex<()> = Mutex::new(());
+
+fn check(name: &str, draw: impl FnOnce(&mut Frame)) {
+    let mut frame = Frame::new(Size::new(WIDTH, HEIGHT));
+    frame.clear(Gray2::WHITE).unwrap();
+    {
+        let _language = LANGUAGE.lock().unwrap_or_else(PoisonError::into_inner);
+        draw(&mut frame);
+    }
+
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let golden = root.join("golden").join(format!("{name}.png"));
+    let settings = OutputSettings::default();
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::create_dir_all(golden.parent().unwrap()).unwrap();
+        frame
+            .to_grayscale_output_image(&settings)
+            .save_png(&golden)
+            .unwrap();
+        return;
+    }
+
+    let expected = SimulatorDisplay::<Gray2>::load_png(&golden)
+        .unwrap_or_else(|err| panic!("{}: {err}; UPDATE_GOLDEN=1 creates it", golden.display()));
+    let differing = match frame.diff(&expected) {
+        Some(diff) => {
+            let count = diff
+                .bounding_box()
+                .points()
+                .filter(|p| diff.get_pixel(*p).is_on())
+                .count();
+            if count > TOLERANCE {
+                let out = root.join("target").join("screenshots");
+                fs::create_dir_all(&out).unwrap();
+                frame
+                    .to_grayscale_output_image(&settings)
+                    .save_png(out.join(format!("{name}.png")))
+                    .unwrap();
+                diff.to_grayscale_output_image(&settings)
+                    .save_png(out.join(format!("{name}-diff.png")))
+                    .unwrap();
+            }
+            count
+        }
+        None => 0,
+    };
+    assert!(
+        differing <= TOLERANCE,
+        "{name}: {differing} pixels differ from {}",
+        golden.display()
+    );
+}
+
+/// [check] with the strings of `language`
+fn check_in(language: Language, name: &str, draw: impl FnOnce(&mut Frame)) {
+    check(name, |frame| {
+        i18n::set_language(language);
+        draw(frame);
+        i18n::set_language(Language::En);
+    });
+}
+
+#[test]
+fn icons() {
+    check("icons", |frame| {
+        // the boxed row over gray, as over an image
+        Rectangle::new(Point::new(0, 28), Size::new(WIDTH, 20))
+            .into_styled(PrimitiveStyle::with_fill(Gray2::new(1)))
+            .draw(frame)
+            .unwrap();
+        let icons = [Icon::Warning, Icon::Battery, Icon::Mail, Icon::Wifi];
+        for (i, icon) in icons.into_iter().enumerate() {
+            let x = 8 + i as i32 * (icon::SIZE as i32 + 8);
+            icon.draw(frame, Point::new(x, 8));
+            icon.draw_boxed(frame, Point::new(x, 32));
+        }
+    });
+}
+
+#[test]
+fn button_hints() {
+    check("button_hints", |frame| {
+        draw_button_hints(frame, [Some("next"), Some("-"), Some("+"), Some("save")]);
+    });
+}
+
+#[test]
+fn barcodes() {
+    check("barcodes", |frame| {
+        Barcode::code128("MAGTAG-2024")
+            .unwrap()
+            .draw(frame, Rectangle::new(Point::new(4, 4), Size::new(180, 56)))
+            .unwrap();
+        Barcode::ean13("400638133393")
+            .unwrap()
+            .draw(frame, Rectangle::new(Point::new(4, 66), Size::new(120, 56)))
+            .unwrap();
+        Barcode::ean8("9638507")
+            .unwrap()
+            .draw(
+                frame,
+                Rectangle::new(Point::new(190, 66), Size::new(100, 56)),
+            )
+            .unwrap();
+    });
+}
+
+#[test]
+fn contrast() {
+    check("contrast", |frame| {
+        Rectangle::new(Point::zero(), Size::new(WIDTH, HEIGHT / 2))
+            .into_styled(PrimitiveStyle::with_fill(Gray2::new(1)))
+            .draw(frame)
+            .unwrap();
+        let style = MonoTextStyle::new(&FONT_7X14_BOLD, Gray2::WHITE);
+        let at = |x, y| Point::new(x, y);
+        draw_outlined(
+            frame,
+            &Text::with_baseline("outlined", at(8, 8), style, Baseline::Top),
+            Gray2::BLACK,
+        );
+        draw_shadowed(
+            frame,
+            &Text::with_baseline("shadowed", at(104, 8), style, Baseline::Top),
+            Gray2::BLACK,
+        );
+        draw_knockout(
+            frame,
+            &Text::with_baseline(
+                "knockout",
+                at(200, 8),
+                MonoTextStyle::new(&FONT_7X14_BOLD, Gray2::BLACK),
+                Baseline::Top,
+            ),
+            Gray2::WHITE,
+            2,
+        );
+        draw_inverted(
+            frame,
+            Rectangle::new(at(8, 80), Size::new(140, 24)),
+            |inverted| {
+                Text::with_baseline(
+                    "inverted",
+                    at(16, 86),
+                    MonoTextStyle::new(&FONT_6X10, Gray2::BLACK),
+                    Baseline::Top,
+                )
+                .draw(inverted)
+                .unwrap();
+            },
+        );
+    });
+}
+
+#[test]
+fn dithered_gradient() {
+    check("dithered_gradient", |frame| {
+        let mut canvas = Canvas::new();
+        for x in 0..WIDTH as i32 {
+            let luma = (x * 255 / (WIDTH as i32 - 1)) as u8;
+            Rectangle::new(Point::new(x, 0), Size::new(1, HEIGHT))
+                .into_styled(PrimitiveStyle::with_fill(Gray8::new(luma)))
+                .draw(&mut canvas)
+                .unwrap();
+        }
+        // the lower half runs dark to light
+        canvas.invert(&Rectangle::new(
+            Point::new(0, HEIGHT as i32 / 2),
+            Size::new(WIDTH, HEIGHT / 2),
+        ));
+        canvas.quantize(frame, Dither::FloydSteinberg);
+    });
+}
+
+/// A pager of `len` pages on page `current`, each saying which it is
+fn pager(len: usize, current: usize) -> Pager<'static> {
+    let mut pager = Pager::new();
+    for i in 0..len {
+        pager.add_page(move |_, frame, area| {
+            Text::with_baseline(
+                &format!("page {}", i + 1),
+                area.top_left + Point::new(4, 4),
+                MonoTextStyle::new(&FONT_7X14_BOLD, Gray2::BLACK),
+                Baseline::Top,
+            )
+            .draw(frame)
+            .unwrap();
+        });
+    }
+    pager.set_current(current);
+    pager
+}
+
+#[test]
+fn pager_dots() {
+    check("pager_dots", |frame| pager(4, 1).render(&(), frame));
+}
+
+#[test]
+fn pager_full_row_of_dots() {
+    check("pager_full_row_of_dots", |frame| {
+        pager(MAX_DOTS as usize, MAX_DOTS as usize - 1).render(&(), frame)
+    });
+}
+
+#[test]
+fn pager_too_many_for_dots() {
+    check("pager_too_many_for_dots", |frame| {
+        pager(40, 6).render(&(), frame)
+    });
+}
+
+#[test]
+fn dialog_over_a_screen() {
+    check("dialog", |frame| {
+        steps::draw(frame, Some(4711));
+        dialog::open(
+            Dialog::new("Reset steps?", "Today's count starts over")
+                .button(Button::A, "Cancel")
+                .button(Button::D, "Reset"),
+        );
+        DialogLayer.draw(frame);
+    });
+}
+
+#[test]
+fn toast_over_a_screen() {
+    check("toast", |frame| {
+        steps::draw(frame, Some(4711));
+        toast::show("Joined and saved");
+        ToastLayer.draw(frame);
+    });
+}
+
+/// Readings kept since `hh:mm` UTC on 14 October, 12:30 local being now
+fn stale_since(utc: &str) -> Stale {
+    Stale {
+        since: Some(format!("2026-10-{utc}:00Z").parse::<Timestamp>().unwrap()),
+        error: Error::Network,
+    }
+}
+
+#[test]
+fn stale_banner() {
+    check("stale_banner", |frame| {
+        draw_stale_banner(frame, &stale_since("14T07:45"))
+    });
+}
+
+#[test]
+fn stale_banner_yesterday() {
+    check("stale_banner_yesterday", |frame| {
+        draw_stale_banner(frame, &stale_since("13T18:00"))
+    });
+}
+
+#[test]
+fn stale_banner_never_fetched() {
+    check("stale_banner_never_fetched", |frame| {
+        let stale = Stale {
+            since: None,
+            error: Error::Timeout,
+        };
+        draw_stale_banner(frame, &stale);
+    });
+}
+
+#[test]
+fn text_entry() {
+    check("text_entry", |frame| {
+        let mut entry = TextEntry::new("Badge name").with_text("Lobby");
+        // down a row and two along, onto a key in the middle
+        for event in [
+            Event::Press(Button::B),
+            Event::Press(Button::D),
+            Event::Press(Button::D),
+        ] {
+            entry.handle(event);
+        }
+        entry.render(frame);
+    });
+}
+
+#[test]
+fn text_entry_masked() {
+    check("text_entry_masked", |frame| {
+        TextEntry::new("Password for HomeNet")
+            .with_text("hunter2")
+            .masked()
+            .render(frame);
+    });
+}
+
+/// The example in `ui/template.rs`
+const TEMPLATE: &str = r#"
+[[label]]
+at = [4, 2]
+text = "Living room"
+font = "bold"
+
+[[value]]
+at = [4, 24]
+bind = "battery.mv"
+format = "{} mV"
+font = "large"
+
+[[icon]]
+at = [280, 2]
+name = "wifi"
+
+[[line]]
+at = [0, 20]
+to = [295, 20]
+
+[[box]]
+at = [200, 40]
+size = [90, 40]
+fill = "light"
+
+[[value]]
+at = [245, 54]
+bind = "missing"
+font = "bold"
+align = "center"
+"#;
+
+#[test]
+fn template() {
+    check("template", |frame| {
+        let template = template::parse(TEMPLATE.as_bytes()).unwrap();
+        template.render(frame, |name| (name == "battery.mv").then(|| "3950".into()));
+    });
+}
+
+// The bundled apps' screens, from the state each app would be in
+
+#[test]
+fn badge_game_nearby() {
+    check("badge_game_nearby", |frame| {
+        let badges = [
+            ("Ana", 12, -48, true),
+            ("Badge 4f21c0", 3, -61, false),
+            ("Lobby", 27, -70, true),
+            ("Keynote speaker", 0, -83, false),
+            ("Booth 12", 5, -90, false),
+            ("Hallway", 1, -92, false),
+        ]
+        .map(|(name, score, rssi, met)| badge_game::Badge {
+            name,
+            score,
+            rssi,
+            met,
+        });
+        badge_game::draw_nearby(frame, "Sam", &badges, 2);
+    });
+}
+
+#[test]
+fn badge_game_nearby_empty() {
+    check("badge_game_nearby_empty", |frame| {
+        badge_game::draw_nearby(frame, "Sam", &[], 0)
+    });
+}
+
+#[test]
+fn badge_game_leaderboard() {
+    check("badge_game_leaderboard", |frame| {
+        let ranking = [
+            ("Lobby", 27, false),
+            ("Ana", 12, false),
+            ("Sam", 7, true),
+            ("Booth 12", 5, false),
+            ("Badge 4f21c0", 3, false),
+            ("Hallway", 1, false),
+        ];
+        badge_game::draw_leaderboard(frame, 7, &ranking);
+    });
+}
+
+const DASHBOARD: &str = r#"
+[[value]]
+at = [4, 2]
+bind = "time"
+font = "large"
+
+[[value]]
+at = [291, 4]
+bind = "date"
+align = "right"
+
+[[line]]
+at = [0, 24]
+to = [295, 24]
+
+[[label]]
+at = [4, 32]
+text = "Battery"
+
+[[value]]
+at = [4, 44]
+bind = "battery.mv"
+format = "{} mV"
+font = "bold"
+
+[[label]]
+at = [150, 32]
+text = "Outside"
+
+[[value]]
+at = [150, 44]
+bind = "mqtt.Outside"
+font = "bold"
+"#;
+
+#[test]
+fn dashboard() {
+    check("dashboard", |frame| {
+        bindings::publish("battery.mv", 3950);
+        let template = template::parse(DASHBOARD.as_bytes());
+        dashboard::draw(frame, Some(&template), None);
+    });
+}
+
+#[test]
+fn dashboard_stale() {
+    check("dashboard_stale", |frame| {
+        bindings::publish("battery.mv", 3950);
+        let template = template::parse(DASHBOARD.as_bytes());
+        dashboard::draw(frame, Some(&template), Some(&stale_since("13T18:00")));
+    });
+}
+
+#[test]
+fn dashboard_without_template() {
+    check("dashboard_without_template", |frame| {
+        dashboard::draw(frame, Some(&Err(Error::NotFound)), None)
+    });
+}
+
+#[test]
+fn demo() {
+    check("demo", demo::draw);
+}
+
+fn repo(name: &str, branch: Option<&str>, state: CiState, workflow: Option<&str>) -> Repo {
+    Repo {
+        name: name.into(),
+        branch: branch.map(String::from),
+        state,
+        workflow: workflow.map(String::from),
+    }
+}
+
+#[test]
+fn github() {
+    check("github", |frame| {
+        let repos = [
+            repo("rust-lang/rust", None, CiState::Passing, Some("CI")),
+            repo(
+                "esp-rs/esp-hal",
+                Some("main"),
+                CiState::Pending,
+                Some("HIL"),
+            ),
+            repo("ScottCUSA/magtag", None, CiState::Failing, Some("build")),
+            repo("someone/gone", None, CiState::Unknown, None),
+        ];
+        github::draw(frame, Some(3), true, &repos);
+    });
+}
+
+#[test]
+fn github_unconfigured() {
+    check("github_unconfigured", |frame| {
+        github::draw(frame, None, false, &[])
+    });
+}
+
+#[test]
+fn mqtt_display() {
+    check("mqtt_display", |frame| {
+        let slots = [
+            ("Living room", Some(String::from("21.5 C"))),
+            ("Front door", Some(String::from("closed"))),
+            ("Washer", None),
+            ("Power", Some(String::from("1240 W"))),
+            (
+                "Now playing",
+                Some(String::from("A very long title indeed")),
+            ),
+        ];
+        mqtt_display::draw(frame, Ok(&slots), Some(&stale_since("14T09:58")));
+    });
+}
+
+#[test]
+fn mqtt_display_unset() {
+    check("mqtt_display_unset", |frame| {
+        mqtt_display::draw(frame, Ok(&[]), None)
+    });
+}
+
+#[test]
+fn remote_display_waiting() {
+    check("remote_display_waiting", |frame| {
+        remote_display::draw(frame, None, "192.168.1.42:7070")
+    });
+}
+
+#[test]
+fn remote_display_frame() {
+    check("remote_display_frame", |frame| {
+        // four bands, black to white, in 2 bit pixels
+        let row_bytes = WIDTH as usize / 4;
+        let pixels: Vec<u8> = (0..HEIGHT as usize * row_bytes)
+            .map(|i| {
+                let shade = (i % row_bytes * 4 / row_bytes) as u8;
+                shade * 0b0101_0101
+            })
+            .collect();
+        remote_display::draw(frame, Some(&pixels), "192.168.1.42:7070");
+    });
+}
+
+#[test]
+fn safe_mode() {
+    check("safe_mode", safe_mode::draw);
+}
+
+#[test]
+fn selftest_grays() {
+    check("selftest_grays", |frame| {
+        selftest::draw_step(frame, Step::DisplayGrays, ButtonSet::EMPTY)
+    });
+}
+
+#[test]
+fn selftest_buttons() {
+    check("selftest_buttons", |frame| {
+        let pressed = ButtonSet::of(&[Button::A, Button::C]);
+        selftest::draw_step(frame, Step::Buttons, pressed);
+    });
+}
+
+#[test]
+fn selftest_summary() {
+    check("selftest_summary", |frame| {
+        let results: Vec<Outcome> = Step::ALL
+            .into_iter()
+            .map(|step| {
+                let (passed, detail) = match step {
+                    Step::Speaker => (false, "operator"),
+                    Step::Accelerometer => (true, "-12 40 1002 mg"),
+                    Step::Light => (true, "212 mV"),
+                    Step::Battery => (true, "3950 mV"),
+                    Step::WifiScan => (true, "6 seen, HomeNet -52 dBm"),
+                    _ => (true, ""),
+                };
+                Outcome {
+                    step,
+                    passed,
+                    detail: detail.into(),
+                }
+            })
+            .collect();
+        selftest::draw_summary(frame, &results);
+    });
+}
+
+#[test]
+fn settings() {
+    check("settings", |frame| {
+        let fields = [
+            ("Refresh", "15 min"),
+            ("Timezone", "Berlin"),
+            ("Units", "Celsius"),
+            ("NeoPixels", "30%"),
+            ("Contrast", "normal"),
+            ("Language", "English"),
+        ]
+        .map(|(label, value)| (label, String::from(value)));
+        settings::draw(frame, Status::Modified, &fields, 1);
+    });
+}
+
+#[test]
+fn settings_in_french() {
+    check_in(Language::Fr, "settings_fr", |frame| {
+        let fields = [
+            ("refresh", String::from("15 min")),
+            ("timezone", String::from("Berlin")),
+            ("units", i18n::translate("celsius").into()),
+            ("neopixels", String::from("30%")),
+            ("contrast", i18n::translate("contrast_high").into()),
+            ("language", Language::Fr.native_name().into()),
+        ]
+        .map(|(key, value)| (i18n::translate(key), value));
+        settings::draw(frame, Status::Failed, &fields, 4);
+    });
+}
+
+#[test]
+fn fonts_keep_the_tables_characters() {
+    for font in [FIXED_6X10, FIXED_7X14_BOLD] {
+        let missing = font.glyph_mapping.index('?');
+        // in the French and Spanish tables, and in none
+        assert_ne!(font.glyph_mapping.index('é'), missing);
+        assert_ne!(font.glyph_mapping.index('ñ'), missing);
+        assert_eq!(font.glyph_mapping.index('Ж'), missing);
+    }
+}
+
+#[test]
+fn status() {
+    check("status", |frame| {
+        let lines = [
+            "Battery   3.95 V, about 78%",
+            "Draw      4.2 mA average since power-on",
+            "Left      2 h 10 min at this rate (420 mAh)",
+            "Time      sleep 91%, wifi 6%, refresh 2%",
+            "WiFi      -52 dBm, restarted once",
+            "Up        5 h 47 min, 143 KiB heap free",
+        ]
+        .map(String::from);
+        status::draw(frame, &lines);
+    });
+}
+
+#[test]
+fn steps() {
+    check("steps", |frame| steps::draw(frame, Some(4711)));
+}
+
+#[test]
+fn steps_without_sensor() {
+    check("steps_without_sensor", |frame| steps::draw(frame, None));
+}
+
+fn task(title: &str, due: Option<(i16, i8, i8)>, done: bool) -> Task {
+    Task {
+        id: format!("/tasks/{}.ics", title.len()),
+        title: title.into(),
+        due: due.map(|(y, m, d)| date(y, m, d)),
+        done,
+    }
+}
+
+#[test]
+fn tasks() {
+    check("tasks", |frame| {
+        let tasks = Ok(vec![
+            task(
+                "Renew the passport before the trip",
+                Some((2026, 10, 2)),
+                false,
+            ),
+            task("Buy milk", Some((2026, 10, 14)), true),
+            task("Call the dentist", Some((2026, 10, 14)), false),
+            task("Water the plants", None, false),
+        ]);
+        tasks::draw(frame, Some(&tasks), 2);
+    });
+}
+
+#[test]
+fn tasks_in_german() {
+    check_in(Language::De, "tasks_de", |frame| {
+        let tasks = Ok(vec![
+            task("Pass verlängern", Some((2026, 10, 2)), false),
+            task("Milch kaufen", Some((2026, 10, 14)), true),
+        ]);
+        tasks::draw(frame, Some(&tasks), 0);
+    });
+}
+
+#[test]
+fn tasks_none_due_in_spanish() {
+    check_in(Language::Es, "tasks_none_due_es", |frame| {
+        tasks::draw(frame, Some(&Ok(Vec::new())), 0)
+    });
+}
+
+#[test]
+fn tasks_none_due() {
+    check("tasks_none_due", |frame| {
+        tasks::draw(frame, Some(&Ok(Vec::new())), 0)
+    });
+}
+
+#[test]
+fn tasks_unavailable() {
+    check("tasks_unavailable", |frame| {
+        tasks::draw(frame, Some(&Err(Error::Timeout)), 0)
+    });
+}
+
+#[test]
+fn tasks_unconfigured() {
+    check("tasks_unconfigured", |frame| tasks::draw(frame, None, 0));
+}
+
+#[test]
+fn wifi_survey() {
+    check("wifi_survey", |frame| {
+        let networks = [
+            ("HomeNet", 6, -48, "WPA2"),
+            ("HomeNet-Guest", 6, -52, "open"),
+            ("", 11, -63, "WPA2"),
+            ("FRITZ!Box 7590 XY with a long name", 1, -71, "WPA2/WPA3"),
+            ("Printer", 11, -80, "WPA"),
+            ("Neighbours", 36, -88, "WPA3"),
+            ("Cafe", 1, -90, "WEP"),
+            ("Eighth, not listed", 6, -95, "open"),
+        ]
+        .map(|(ssid, channel, rssi, security)| Network {
+            ssid,
+            channel,
+            rssi,
+            security,
+        });
+        wifi_survey::draw(frame, Some(Ok(&networks)), 1);
+    });
+}
+
+#[test]
+fn wifi_survey_failed() {
+    check("wifi_survey_failed", |frame| {
+        wifi_survey::draw(frame, Some(Err(Error::Timeout)), 0)
+    });
+}